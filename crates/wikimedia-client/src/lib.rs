@@ -0,0 +1,196 @@
+//! A client for the JSON API of a `wikimedia-web` server, such as one run by `wmd web`.
+//!
+//! Requests and responses use the types in [`wikimedia::web_api`], which the server uses
+//! too, so an application reads the same structures from a remote server as the server reads
+//! from its store.
+//!
+//! ```no_run
+//! # async fn example() -> wikimedia::Result<()> {
+//! use futures::TryStreamExt;
+//!
+//! let client = wikimedia_client::Client::new("http://localhost:8089")?;
+//! let since = chrono::Utc::now() - chrono::Duration::days(1);
+//! let mut changes = std::pin::pin!(client.changes_since(Some("simplewiki"), since));
+//! while let Some(page) = changes.try_next().await? {
+//!     println!("{id} {slug}", id = page.mediawiki_id, slug = page.slug);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{Context, format_err};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use serde::de::DeserializeOwned;
+use std::time::Duration as StdDuration;
+use wikimedia::{
+    bail_kind,
+    Result,
+    web_api::{self, Changes, ChangesQuery, PageChange, PageSections, SavedSearchResults,
+              SavedSearches},
+};
+
+/// A client for one server's JSON API. Clones share the same connection pool.
+#[derive(Clone, Debug)]
+pub struct Client {
+    base_url: reqwest::Url,
+    http: reqwest::Client,
+}
+
+/// The timeout for each request.
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+impl Client {
+    /// A client for the server at `base_url`, e.g. `http://localhost:8089`.
+    pub fn new(base_url: &str) -> Result<Client> {
+        let http = reqwest::Client::builder()
+            .user_agent(format!("wikimedia-client/{version}",
+                                version = env!("CARGO_PKG_VERSION")))
+            .timeout(REQUEST_TIMEOUT)
+            .gzip(true)
+            .build()?;
+        Client::with_http_client(base_url, http)
+    }
+
+    /// A client for the server at `base_url` that sends requests with `http`, e.g. to set
+    /// its own timeouts or proxy.
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Result<Client> {
+        let base_url = reqwest::Url::parse(base_url)
+            .with_context(|| format!("parsing server URL url='{base_url}'"))?;
+        if base_url.cannot_be_a_base() {
+            bail_kind!(User, "The server URL must be an http: or https: URL url='{base_url}'");
+        }
+
+        Ok(Client {
+            base_url,
+            http,
+        })
+    }
+
+    /// Get up to `query.limit` pages whose latest revision was saved after `query.since`
+    /// from the store for `dump_name`, or the server's default store if `None`. See
+    /// [`Store::pages_modified_since`][pages_modified_since].
+    ///
+    /// [pages_modified_since]: https://docs.rs/wikimedia-store/latest/wikimedia_store/struct.Store.html#method.pages_modified_since
+    #[tracing::instrument(level = "debug", skip(self), fields(base_url = %self.base_url))]
+    pub async fn changes(&self, dump_name: Option<&str>, query: &ChangesQuery
+    ) -> Result<Changes> {
+        let mut url = self.v1_url(dump_name, "changes")?;
+        url.query_pairs_mut()
+           .append_pair("since", &query.since.to_rfc3339());
+        if let Some(cursor) = query.cursor.as_deref() {
+            url.query_pairs_mut().append_pair("cursor", cursor);
+        }
+        if let Some(limit) = query.limit {
+            url.query_pairs_mut().append_pair("limit", &limit.to_string());
+        }
+
+        self.get_json(url).await
+    }
+
+    /// Every page whose latest revision was saved after `since` in the store for
+    /// `dump_name`, or the server's default store if `None`, requesting the next pages from
+    /// the server as the stream is read.
+    pub fn changes_since<'a>(&'a self, dump_name: Option<&'a str>, since: DateTime<Utc>
+    ) -> impl Stream<Item = Result<PageChange>> + 'a {
+        // The state is the next query, or `None` after the last response.
+        let pages = stream::try_unfold(
+            Some(ChangesQuery {
+                since,
+                cursor: None,
+                limit: None,
+            }),
+            move |query| async move {
+                let Some(mut query) = query else {
+                    return Ok::<_, anyhow::Error>(None);
+                };
+                let changes = self.changes(dump_name, &query).await?;
+                let next = changes.next_cursor.map(|cursor| {
+                    query.cursor = Some(cursor);
+                    query
+                });
+                Ok(Some((stream::iter(changes.pages.into_iter().map(Ok)), next)))
+            });
+        futures::TryStreamExt::try_flatten(pages)
+    }
+
+    /// Get the sections of the page with MediaWiki ID `mediawiki_id` from the store for
+    /// `dump_name`, or the server's default store if `None`, nested by heading level.
+    #[tracing::instrument(level = "debug", skip(self), fields(base_url = %self.base_url))]
+    pub async fn page_sections(&self, dump_name: Option<&str>, mediawiki_id: u64
+    ) -> Result<PageSections> {
+        let url = self.v1_url(dump_name, &format!("page/{mediawiki_id}/sections"))?;
+        self.get_json(url).await
+    }
+
+    /// List the searches saved in the store for `dump_name`, or the server's default store
+    /// if `None`.
+    #[tracing::instrument(level = "debug", skip(self), fields(base_url = %self.base_url))]
+    pub async fn saved_searches(&self, dump_name: Option<&str>) -> Result<SavedSearches> {
+        let url = self.v1_url(dump_name, "saved-searches")?;
+        self.get_json(url).await
+    }
+
+    /// Run the search saved as `name` in the store for `dump_name`, or the server's default
+    /// store if `None`.
+    #[tracing::instrument(level = "debug", skip(self), fields(base_url = %self.base_url))]
+    pub async fn saved_search_results(&self, dump_name: Option<&str>, name: &str
+    ) -> Result<SavedSearchResults> {
+        let url = self.v1_url(dump_name, &format!("saved-searches/{name}/results"))?;
+        self.get_json(url).await
+    }
+
+    /// The URL of the version 1 API route `route` for `dump_name`, e.g. `changes` or
+    /// `page/1/sections`.
+    fn v1_url(&self, dump_name: Option<&str>, route: &str) -> Result<reqwest::Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+           .map_err(|()| format_err!("Server URL cannot be a base url='{}'", self.base_url))?
+           .pop_if_empty()
+           .extend(web_api::v1_path(dump_name).split('/').filter(|s| !s.is_empty()))
+           .extend(route.split('/'));
+        Ok(url)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: reqwest::Url) -> Result<T> {
+        let response = self.http.get(url.clone()).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status.is_client_error() {
+            bail_kind!(User, "HTTP client error response from server url='{url}' \
+                              response_code={status} body={body:?}");
+        }
+        if !status.is_success() {
+            bail_kind!(Network, "HTTP error response from server url='{url}' \
+                                 response_code={status} body={body:?}");
+        }
+
+        serde_json::from_str::<T>(&body)
+            .with_context(|| format!("parsing server response url='{url}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v1_url() {
+        let client = Client::new("http://localhost:8089").unwrap();
+        assert_eq!(client.v1_url(None, "changes").unwrap().as_str(),
+                   "http://localhost:8089/api/v1/changes");
+        assert_eq!(client.v1_url(Some("simplewiki"), "changes").unwrap().as_str(),
+                   "http://localhost:8089/simplewiki/api/v1/changes");
+        assert_eq!(client.v1_url(None, "page/12/sections").unwrap().as_str(),
+                   "http://localhost:8089/api/v1/page/12/sections");
+        assert_eq!(client.v1_url(None, "saved-searches/New pages?/results").unwrap().as_str(),
+                   "http://localhost:8089/api/v1/saved-searches/New%20pages%3F/results");
+
+        // A server under a path prefix, with a trailing slash.
+        let client = Client::new("https://example.com/wmd/").unwrap();
+        assert_eq!(client.v1_url(Some("simplewiki"), "changes").unwrap().as_str(),
+                   "https://example.com/wmd/simplewiki/api/v1/changes");
+
+        assert!(Client::new("mailto:someone@example.com").is_err());
+    }
+}
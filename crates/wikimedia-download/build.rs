@@ -0,0 +1,32 @@
+//! Record build metadata for `wmd get-version --build`.
+
+use std::{env, process::Command};
+
+fn main() {
+    // Paths are relative to this crate. Changes to the checked out commit update these.
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs/heads");
+
+    // Not set when building from a source package without git.
+    if let Some(commit) = command_output("git", &["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=WMD_GIT_COMMIT={commit}");
+    }
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    if let Some(rustc_version) = command_output(&rustc, &["--version"]) {
+        println!("cargo:rustc-env=WMD_RUSTC_VERSION={rustc_version}");
+    }
+
+    for var in ["TARGET", "PROFILE"] {
+        println!("cargo:rustc-env=WMD_{var}={}", env::var(var).unwrap_or_default());
+    }
+}
+
+/// The trimmed stdout of a successful command, or `None` if it failed.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok().map(|stdout| stdout.trim().to_string())
+}
@@ -1,20 +1,34 @@
+#[cfg(feature = "download")]
 mod http_cache_mode;
+#[cfg(feature = "download")]
 use http_cache_mode::HttpCacheModeParser;
 
-use anyhow::bail;
+use wikimedia::bail_kind;
 use clap::CommandFactory;
+#[cfg(feature = "download")]
 use http_cache_reqwest::CacheMode as HttpCacheMode;
 use std::path::{Path, PathBuf};
 use wikimedia::{
     dump::{
         self,
         DumpName, JobName, Version, VersionSpec,
-        local::Compression,
+        local::{Compression, ParseLimits},
     },
-    http,
     Result,
     UserRegex,
 };
+
+#[cfg(feature = "download")]
+use std::sync::Arc;
+#[cfg(feature = "download")]
+use wikimedia::{
+    dump::source::{DumpSource, UrlTemplateSource, WikimediaSource},
+    http,
+};
+
+#[cfg(feature = "store")]
+use std::time::Duration as StdDuration;
+#[cfg(feature = "store")]
 use wikimedia_store as store;
 
 #[derive(clap::Args, Clone, Debug)]
@@ -55,12 +69,99 @@ pub struct CommonArgs {
     #[arg(long, env = "WMD_OUT_DIR")]
     out_dir: Option<PathBuf>,
 
+    #[cfg(feature = "store")]
+    /// The directory to keep the store's chunk files in, e.g. on a large disk.
+    ///
+    /// Defaults to the subdirectory `chunks` under the store directory.
+    ///
+    /// Only used when a store is created, after which the location is read from the
+    /// store config file. If set for an existing store it must match the config file.
+    #[arg(long, env = "WMD_STORE_CHUNKS_DIR")]
+    store_chunks_dir: Option<PathBuf>,
+
+    #[cfg(feature = "store")]
+    /// The directory to keep the store's index in, e.g. on a fast disk.
+    ///
+    /// Defaults to the subdirectory `index` under the store directory.
+    ///
+    /// Only used when a store is created, after which the location is read from the
+    /// store config file. If set for an existing store it must match the config file.
+    #[arg(long, env = "WMD_STORE_INDEX_DIR")]
+    store_index_dir: Option<PathBuf>,
+
+    #[cfg(feature = "store")]
+    /// The base URL of a remote store to read from, e.g. `https://example.com/stores/enwiki`.
+    ///
+    /// The remote store's index is downloaded once, and its chunks are fetched on demand
+    /// with HTTP range requests and cached locally. A store with a remote URL is read-only.
+    ///
+    /// Only used when a store is created, after which the URL is read from the
+    /// store config file. If set for an existing store it must match the config file.
+    #[arg(long, env = "WMD_STORE_REMOTE_URL")]
+    store_remote_url: Option<String>,
+
+    #[cfg(feature = "store")]
+    /// The URL of the site the store's pages are published on, e.g.
+    /// `https://en.wiktionary.org`. Used for links to the live site and interwiki links.
+    ///
+    /// Defaults to the Wikimedia site for the store's dump name. When set, the site is saved
+    /// in the store config file and used from then on.
+    #[arg(long, env = "WMD_STORE_SITE_URL")]
+    store_site_url: Option<String>,
+
+    #[cfg(feature = "store")]
+    /// The language code of the site set with `--store-site-url`, e.g. `en`.
+    #[arg(long, env = "WMD_STORE_SITE_LANGUAGE", requires = "store_site_url")]
+    store_site_language: Option<String>,
+
+    #[cfg(feature = "store")]
+    /// The path of an article on the site set with `--store-site-url`, with `$1` in place of
+    /// the page slug.
+    #[arg(long, env = "WMD_STORE_SITE_ARTICLE_PATH", default_value = "/wiki/$1")]
+    store_site_article_path: String,
+
+    #[cfg(feature = "store")]
+    /// Log store index queries that take at least this many milliseconds, with their SQL
+    /// and query plan. Query parameters are logged without their text.
+    ///
+    /// Use `wmd index-explain` to explain a single query.
+    #[arg(long, env = "WMD_SLOW_QUERY_MS")]
+    slow_query_ms: Option<u64>,
+
+    #[cfg(feature = "store")]
+    /// How the store's chunk files are read.
+    ///
+    /// `mmap` is the fastest. Use `pread` for a store on a network filesystem such as NFS or
+    /// SMB, where memory mapped files can silently return stale or torn data: chunk files are
+    /// read into memory and checked against their checksum files, at some performance cost.
+    #[arg(long, value_enum, default_value = "mmap", env = "WMD_STORE_CHUNK_READ_MODE")]
+    store_chunk_read_mode: ChunkReadModeArg,
+
+    #[cfg(feature = "store")]
+    /// Which pending store index migrations to apply when the store is opened.
+    ///
+    /// `safe` applies migrations that only add tables or columns. A store whose index needs
+    /// slower migrations can't be opened until they are applied with `wmd migrate-index`, or
+    /// with `all` here. `off` applies none.
+    #[arg(long, value_enum, default_value = "safe", env = "WMD_STORE_AUTO_MIGRATE")]
+    store_auto_migrate: AutoMigrateArg,
+
+    #[cfg(feature = "download")]
     /// HTTP cache mode to use when making requests.
     ///
     /// See the `http-cache` crate documentation for an explanation of each of the options:
     /// <https://docs.rs/http-cache/0.10.1/http_cache/enum.CacheMode.html>
     #[arg(long, default_value = "Default", value_parser = HttpCacheModeParser)]
     pub http_cache_mode: HttpCacheMode,
+
+    #[cfg(feature = "download")]
+    /// Fetch fresh dump metadata (the list of dumps, dump versions, and job statuses),
+    /// instead of using metadata cached by an earlier run.
+    ///
+    /// By default cached metadata is used for up to an hour, and older cached metadata is
+    /// used if fresh metadata can't be fetched, e.g. when offline.
+    #[arg(long, default_value_t = false)]
+    refresh_metadata: bool,
 }
 
 #[derive(clap::Args, Clone, Debug)]
@@ -126,14 +227,53 @@ pub struct OpenSpecArgs {
     #[arg(long)]
     pub job_dir: Option<PathBuf>,
 
+    /// A directory of page files to use, each holding a single page.
+    ///
+    /// Files named `*.xml` (optionally compressed) hold one exported page. Files named
+    /// `*.wikitext` or `*.wiki` hold wikitext after a front matter header, for example:
+    ///
+    /// ---
+    /// title: Talk:Onboarding
+    /// id: 7
+    /// ns: 1
+    /// ---
+    /// Welcome!
+    ///
+    /// The header must have `title` and `id`, and may have `ns`, `revision_id`,
+    /// `parent_id`, `timestamp` (RFC 3339), and `restrictions`.
+    #[arg(long)]
+    pub page_files_dir: Option<PathBuf>,
+
     /// The compression format to use when reading files.
-    #[arg(long, value_enum, default_value_t = Compression::Bzip2)]
-    pub compression: Compression,
+    ///
+    /// By default this is detected from the first bytes of each file, so a job directory
+    /// can hold files with different compressions.
+    #[arg(long, value_enum)]
+    pub compression: Option<Compression>,
 
     /// Maximum count of pages operate on. No limit if omitted.
     #[arg(long)]
     pub limit: Option<u64>,
 
+    /// Maximum length in bytes of the text of one XML element, e.g. a page's wikitext.
+    ///
+    /// Pages over this limit are skipped when importing.
+    #[arg(long)]
+    pub max_text_bytes: Option<u64>,
+
+    /// Maximum length in bytes of the XML for one page, including all its revisions.
+    ///
+    /// Pages over this limit are skipped when importing.
+    #[arg(long)]
+    pub max_page_bytes: Option<u64>,
+
+    /// Maximum bytes buffered to read one XML element or text node.
+    ///
+    /// Reading a file stops with an error when this is exceeded, which protects against
+    /// running out of memory reading malformed files.
+    #[arg(long)]
+    pub max_buf_bytes: Option<u64>,
+
     #[clap(flatten)]
     pub file_name_regex: FileNameRegexArg,
 }
@@ -147,6 +287,207 @@ pub struct FileNameRegexArg {
     pub value: Option<UserRegex>,
 }
 
+#[cfg(feature = "store")]
+/// Arguments to choose how a new store index's tables are split between files.
+#[derive(clap::Args, Clone, Debug)]
+pub struct IndexLayoutArgs {
+    /// How a new store index's tables are split between files. An existing index keeps its
+    /// layout.
+    ///
+    /// `single` keeps every table in `index.db`. `split-search` keeps the page title search
+    /// and spelling tables in `search.db`, splitting a very large index such as enwiki's in
+    /// two.
+    #[arg(long, value_enum)]
+    pub index_layout: Option<IndexLayoutArg>,
+}
+
+#[cfg(feature = "store")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum IndexLayoutArg {
+    Single,
+    SplitSearch,
+}
+
+#[cfg(feature = "store")]
+/// Arguments to choose how page titles are tokenised for full text search.
+#[derive(clap::Args, Clone, Debug)]
+pub struct FtsTokenizerArgs {
+    /// The full text search tokenizer for page titles.
+    ///
+    /// `unicode61` splits titles into words at spaces and punctuation. `trigram` indexes
+    /// every sequence of 3 characters, so search terms match any part of a title; use it for
+    /// languages written without spaces, such as Japanese and Chinese. With `trigram`,
+    /// search terms must be at least 3 characters long.
+    #[arg(long, value_enum)]
+    pub fts_tokenizer: Option<FtsTokenizerName>,
+
+    /// With `unicode61`, how to remove diacritics: 0 keeps them, 1 removes them from
+    /// characters that are a single code point, and 2 removes them from all characters.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub fts_remove_diacritics: u8,
+
+    /// With `unicode61`, extra characters to split tokens at.
+    #[arg(long)]
+    pub fts_separators: Option<String>,
+
+    /// With `unicode61`, extra characters to keep in tokens, e.g. '-' to keep hyphenated
+    /// words together.
+    #[arg(long)]
+    pub fts_token_chars: Option<String>,
+
+    /// With `trigram`, match search terms case sensitively.
+    #[arg(long, default_value_t = false)]
+    pub fts_case_sensitive: bool,
+}
+
+#[cfg(feature = "store")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum FtsTokenizerName {
+    Unicode61,
+    Trigram,
+}
+
+#[cfg(feature = "store")]
+/// Arguments to limit the threads and disk reads used by imports and chunk verification, e.g.
+/// to run them on a machine that is also serving requests.
+#[derive(clap::Args, Clone, Debug)]
+pub struct ScheduleArgs {
+    /// The count of threads to use. Defaults to the count of CPUs.
+    #[arg(long)]
+    pub threads: Option<u16>,
+
+    /// Use at most this percentage of the CPUs, e.g. 25 for a quarter of them, rounded up to
+    /// a whole thread. An alternative to `--threads` for a background import.
+    #[arg(long, value_name = "PERCENT", conflicts_with = "threads",
+          value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub cpu_limit: Option<u8>,
+
+    /// The maximum count of files to read at once. Unlimited by default. Set it to 1 or 2
+    /// when the files are on a hard disk.
+    #[arg(long)]
+    pub io_concurrency: Option<u16>,
+
+    /// Write chunks at most this many MiB per second, so a background import leaves disk
+    /// bandwidth for other programs. Unlimited by default.
+    #[arg(long, value_name = "MIB_PER_SEC",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub io_nice: Option<u64>,
+}
+
+#[cfg(feature = "store")]
+/// Arguments to limit a store's size during imports, e.g. so an import on a small device
+/// doesn't fill its disk.
+#[derive(clap::Args, Clone, Debug)]
+pub struct QuotaArgs {
+    /// Stop importing when the store's chunk files total this many MiB. An import may exceed
+    /// it by up to one chunk per thread. Unlimited by default.
+    #[arg(long, value_name = "MIB")]
+    pub max_store_mib: Option<u64>,
+
+    /// Stop importing when the store has this many pages. An import may exceed it by up to
+    /// one chunk per thread. Unlimited by default.
+    #[arg(long)]
+    pub max_store_pages: Option<u64>,
+}
+
+#[cfg(feature = "store")]
+/// Arguments to choose which namespaces' pages an import writes, e.g. to leave out talk
+/// pages and make the store much smaller.
+#[derive(clap::Args, Clone, Debug)]
+pub struct NamespaceFilterArgs {
+    /// Only import pages in this namespace, given by key or name, e.g. `0` or `Main` for
+    /// articles. Repeat it to import several namespaces. Every namespace is imported by
+    /// default.
+    #[arg(long = "include-namespace", value_name = "NAMESPACE")]
+    pub include_namespaces: Vec<dump::Namespace>,
+
+    /// Don't import pages in this namespace, given by key or name, e.g. `User`. Repeat it to
+    /// leave out several namespaces.
+    #[arg(long = "exclude-namespace", value_name = "NAMESPACE")]
+    pub exclude_namespaces: Vec<dump::Namespace>,
+
+    /// Don't import pages in any talk namespace, e.g. `Talk` or `User talk`.
+    #[arg(long, default_value_t = false)]
+    pub exclude_talk: bool,
+}
+
+#[cfg(feature = "download")]
+/// Arguments to choose where job files are downloaded from.
+#[derive(clap::Args, Clone, Debug)]
+pub struct DumpSourceArgs {
+    /// Specify the URL of a mirror to download job files from. Only supports http: and https: URLs.
+    ///
+    /// If not present tries to read the environment variable `WMD_MIRROR_URL`.
+    ///
+    /// Examples:
+    ///   * <https://dumps.wikimedia.org>
+    ///   * <https://ftp.acc.umu.se/mirror/wikimedia.org/dumps>
+    ///
+    /// Note that only job files are downloaded from this mirror, metadata files are downloaded from <https://dumps.wikimedia.org> to ensure we get the freshest data.
+    ///
+    /// To find a mirror, see <https://meta.wikimedia.org/wiki/Mirroring_Wikimedia_project_XML_dumps#Current_mirrors>
+    #[arg(long, env = "WMD_MIRROR_URL", required_unless_present = "source_url_template")]
+    mirror_url: Option<String>,
+
+    /// Download job files from a server that isn't a Wikimedia dumps server or mirror, e.g. a
+    /// private MediaWiki's XML exports.
+    ///
+    /// The URL of each file in `--source-file`, with placeholders `{dump}`, `{version}`,
+    /// `{job}` and `{file}`. Only supports http: and https: URLs.
+    ///
+    /// There is no job metadata on such a server, so downloaded files are only checked against
+    /// the size in the server's `Content-Length` header, and the latest version is today's date.
+    ///
+    /// Example: `https://wiki.example.com/dumps/{file}`
+    #[arg(long, conflicts_with = "mirror_url", requires = "source_file")]
+    source_url_template: Option<String>,
+
+    /// The name of a job file to download with `--source-url-template`. May be repeated.
+    #[arg(long, requires = "source_url_template")]
+    source_file: Vec<String>,
+}
+
+#[cfg(feature = "download")]
+/// Arguments to use job files already downloaded to other directories.
+#[derive(clap::Args, Clone, Debug)]
+pub struct SharedDirArgs {
+    /// A directory to look in for a copy of each job file before downloading it, e.g. a
+    /// team's shared cache on a file server. It's laid out like the `dumps` directory under
+    /// `--out-dir`. May be repeated.
+    ///
+    /// A copy is only used if its size and SHA1 hash match the job's metadata.
+    #[arg(long)]
+    shared_dir: Vec<PathBuf>,
+
+    /// How to put a copy from `--shared-dir` into the out dir. `hard-link` and `reflink`
+    /// fall back to copying when they aren't supported.
+    #[arg(long, value_enum, default_value_t = LinkModeArg::HardLink)]
+    link_mode: LinkModeArg,
+}
+
+#[cfg(feature = "store")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChunkReadModeArg {
+    Mmap,
+    Pread,
+}
+
+#[cfg(feature = "store")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum AutoMigrateArg {
+    Safe,
+    All,
+    Off,
+}
+
+#[cfg(feature = "download")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinkModeArg {
+    HardLink,
+    Reflink,
+    Copy,
+}
+
 #[derive(clap::Args, Clone, Debug)]
 pub struct JsonOutputArg {
     /// Print results to stdout as JSON. By default the data will be printed as text.
@@ -186,14 +527,28 @@ impl CommonArgs {
         self.out_dir().join("dumps")
     }
 
+    #[cfg(feature = "download")]
     pub fn http_cache_path(&self) -> PathBuf {
         self.out_dir().join("http_cache")
     }
 
+    #[cfg(feature = "download")]
+    pub fn metadata_cache_path(&self) -> PathBuf {
+        self.out_dir().join("metadata_cache")
+    }
+
+    #[cfg(feature = "store")]
     pub fn store_path(&self) -> PathBuf {
-        self.out_dir().join("stores").join(&*self.store_dump_name.0)
+        self.store_path_for(&self.store_dump_name)
     }
 
+    #[cfg(feature = "store")]
+    /// The default root directory of the store for `dump_name`.
+    pub fn store_path_for(&self, dump_name: &DumpName) -> PathBuf {
+        self.out_dir().join("stores").join(&*dump_name.0)
+    }
+
+    #[cfg(feature = "download")]
     pub fn http_options(&self) -> Result<http::OptionsBuilder> {
         Ok(http::OptionsBuilder::default()
                .cache_path(self.http_cache_path())
@@ -201,60 +556,261 @@ impl CommonArgs {
                .to_owned())
     }
 
+    #[cfg(feature = "download")]
+    pub fn metadata_cache_options(&self) -> Result<dump::metadata_cache::Options> {
+        Ok(dump::metadata_cache::Options::builder()
+               .path(self.metadata_cache_path())
+               .refresh(self.refresh_metadata)
+               .build()?)
+    }
+
+    #[cfg(feature = "download")]
+    pub fn metadata_client(&self) -> Result<dump::download::MetadataClient> {
+        dump::download::MetadataClient::new(&self.http_options()?.build()?,
+                                            self.metadata_cache_options()?)
+    }
+
+    #[cfg(all(feature = "store", feature = "wikitext-render"))]
     pub fn store_dump_name(&self) -> DumpName {
         self.store_dump_name.clone()
     }
 
+    #[cfg(feature = "web")]
+    /// Whether the store's chunks directory, index directory, or remote URL was set.
+    pub fn has_store_location_args(&self) -> bool {
+        self.store_chunks_dir.is_some()
+            || self.store_index_dir.is_some()
+            || self.store_remote_url.is_some()
+    }
+
+    #[cfg(feature = "store")]
     pub fn store_options(&self) -> Result<store::Options> {
-        Ok(store::Options::default()
-               .dump_name(self.store_dump_name.clone())
-               .path(self.store_path())
-               .to_owned())
+        let mut opts = self.store_options_at(self.store_dump_name.clone(), self.store_path())?;
+
+        if let Some(dir) = self.store_chunks_dir.as_ref() {
+            opts.chunks_path(dir.clone());
+        }
+        if let Some(dir) = self.store_index_dir.as_ref() {
+            opts.index_path(dir.clone());
+        }
+        if let Some(url) = self.store_remote_url.as_ref() {
+            opts.remote_url(url.clone());
+        }
+        if let Some(url) = self.store_site_url.as_ref() {
+            opts.site(dump::SiteInfo {
+                site_url: url.trim_end_matches('/').to_string(),
+                language: self.store_site_language.clone(),
+                article_path: self.store_site_article_path.clone(),
+            });
+        }
+
+        Ok(opts)
+    }
+
+    #[cfg(feature = "store")]
+    /// Options for the store for `dump_name` with root directory `path`, ignoring the
+    /// store location arguments.
+    pub fn store_options_at(&self, dump_name: DumpName, path: PathBuf
+    ) -> Result<store::Options>
+    {
+        let mut opts = store::Options::default();
+        opts.dump_name(dump_name)
+            .path(path)
+            .chunk_read_mode(match self.store_chunk_read_mode {
+                ChunkReadModeArg::Mmap => store::ChunkReadMode::Mmap,
+                ChunkReadModeArg::Pread => store::ChunkReadMode::Pread,
+            })
+            .auto_migrate(match self.store_auto_migrate {
+                AutoMigrateArg::Safe => store::index::AutoMigrate::Safe,
+                AutoMigrateArg::All => store::index::AutoMigrate::All,
+                AutoMigrateArg::Off => store::index::AutoMigrate::Off,
+            });
+
+        if let Some(ms) = self.slow_query_ms {
+            opts.slow_query_threshold(StdDuration::from_millis(ms));
+        }
+
+        Ok(opts)
+    }
+}
+
+#[cfg(feature = "download")]
+impl SharedDirArgs {
+    pub fn apply(&self, download_options: &mut dump::download::OptionsBuilder) {
+        download_options
+            .shared_dirs(self.shared_dir.clone())
+            .link_mode(match self.link_mode {
+                LinkModeArg::HardLink => dump::download::LinkMode::HardLink,
+                LinkModeArg::Reflink => dump::download::LinkMode::Reflink,
+                LinkModeArg::Copy => dump::download::LinkMode::Copy,
+            });
+    }
+}
+
+#[cfg(feature = "store")]
+impl ScheduleArgs {
+    pub fn apply(&self, store_options: &mut store::Options) {
+        if let Some(threads) = self.threads {
+            store_options.threads(threads.into());
+        }
+        if let Some(cpu_limit) = self.cpu_limit {
+            let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+            store_options.threads((cpus * usize::from(cpu_limit)).div_ceil(100).max(1));
+        }
+        if let Some(io_concurrency) = self.io_concurrency {
+            store_options.io_concurrency(io_concurrency.into());
+        }
+        if let Some(io_nice) = self.io_nice {
+            store_options.write_rate_limit(io_nice.saturating_mul(1024 * 1024));
+        }
+    }
+}
+
+#[cfg(feature = "store")]
+impl IndexLayoutArgs {
+    pub fn apply(&self, store_options: &mut store::Options) {
+        if let Some(layout) = self.index_layout {
+            store_options.index_layout(match layout {
+                IndexLayoutArg::Single => store::index::Layout::Single,
+                IndexLayoutArg::SplitSearch => store::index::Layout::SplitSearch,
+            });
+        }
+    }
+}
+
+#[cfg(feature = "store")]
+impl NamespaceFilterArgs {
+    pub fn apply(&self, store_options: &mut store::Options) {
+        store_options.import_namespaces(store::NamespaceFilter {
+            include: self.include_namespaces.iter().map(dump::Namespace::key).collect(),
+            exclude: self.exclude_namespaces.iter().map(dump::Namespace::key).collect(),
+            exclude_talk: self.exclude_talk,
+        });
+    }
+}
+
+#[cfg(feature = "store")]
+impl QuotaArgs {
+    pub fn apply(&self, store_options: &mut store::Options) {
+        store_options.quota(store::Quota {
+            max_chunk_bytes: self.max_store_mib.map(|mib| mib.saturating_mul(1024 * 1024)),
+            max_pages: self.max_store_pages,
+        });
+    }
+}
+
+#[cfg(feature = "store")]
+impl FtsTokenizerArgs {
+    /// The tokenizer chosen, or `None` if `--fts-tokenizer` wasn't passed.
+    pub fn tokenizer(&self) -> Result<Option<store::index::FtsTokenizer>> {
+        let unicode61_set = self.fts_remove_diacritics != 1
+                            || self.fts_separators.is_some()
+                            || self.fts_token_chars.is_some();
+        let tokenizer = match self.fts_tokenizer {
+            None if unicode61_set || self.fts_case_sensitive =>
+                bail_kind!(User, "The --fts-* tokenizer options require --fts-tokenizer"),
+            None => return Ok(None),
+            Some(FtsTokenizerName::Unicode61) => {
+                if self.fts_case_sensitive {
+                    bail_kind!(User, "--fts-case-sensitive is only supported with \
+                                      --fts-tokenizer trigram");
+                }
+                store::index::FtsTokenizer::Unicode61 {
+                    remove_diacritics: self.fts_remove_diacritics,
+                    separators: self.fts_separators.clone().unwrap_or_default(),
+                    token_chars: self.fts_token_chars.clone().unwrap_or_default(),
+                }
+            },
+            Some(FtsTokenizerName::Trigram) => {
+                if unicode61_set {
+                    bail_kind!(User, "--fts-remove-diacritics, --fts-separators and \
+                                      --fts-token-chars are only supported with \
+                                      --fts-tokenizer unicode61");
+                }
+                store::index::FtsTokenizer::Trigram {
+                    case_sensitive: self.fts_case_sensitive,
+                }
+            },
+        };
+        Ok(Some(tokenizer))
+    }
+}
+
+#[cfg(feature = "download")]
+impl DumpSourceArgs {
+    pub fn source(&self) -> Arc<dyn DumpSource> {
+        match (&self.source_url_template, &self.mirror_url) {
+            (Some(url_template), _) => Arc::new(UrlTemplateSource {
+                url_template: url_template.clone(),
+                file_names: self.source_file.clone(),
+            }),
+            (None, Some(mirror_url)) => Arc::new(WikimediaSource {
+                mirror_url: mirror_url.clone(),
+            }),
+            (None, None) => unreachable!("clap requires --mirror-url or --source-url-template"),
+        }
     }
 }
 
 impl OpenSpecArgs {
     pub fn try_into_open_spec(self, dumps_dir: &Path) -> Result<dump::local::OpenSpec> {
-        let source: dump::local::SourceSpec = match (self.job_file, self.job_dir) {
-            (Some(_), Some(_)) => bail!("You supplied both --job-file and --job-dir, \
-                                         but should only supply one of these"),
-            (Some(file), None) => {
-                dump::local::SourceSpec::File(dump::local::FileSpec {
-                    compression: self.compression,
-                    path: file,
-                    seek: self.seek,
-                })
+        let mut builder = dump::local::OpenSpec::builder();
+
+        match (self.job_file, self.job_dir, self.page_files_dir) {
+            (Some(file), None, None) => {
+                builder.file(file);
             },
-            (None, Some(dir)) => {
-                dump::local::SourceSpec::Dir(dump::local::DirSpec {
-                    path: dir,
-                    file_name_regex: self.file_name_regex.value,
-                })
+            (None, Some(dir), None) => {
+                builder.dir(dir);
+            }
+            (None, None, Some(dir)) => {
+                builder.page_files(dir);
             }
-            (None, None) => {
-                match (self.dump_name.as_ref(),
-                       self.version.as_ref(),
-                       self.job_name.as_ref()) {
-                    (Some(dump), Some(version), Some(job)) =>
-                        dump::local::SourceSpec::Job(dump::local::JobSpec {
-                            out_dir: dumps_dir.to_owned(),
-                            dump: dump.value.clone(),
-                            version: version.clone(),
-                            job: job.value.clone(),
-                            file_name_regex: self.file_name_regex.value,
-                        }),
-                    _ => bail!("You must supply one of these 3 valid argument sets:\n\
-                                1. `--dump-file`\n\
-                                2. `--job-dir'\n\
-                                3. `--dump`, `--version`, and `--job`"),
+            (None, None, None) => {
+                match (self.dump_name, self.version, self.job_name) {
+                    (Some(dump), Some(version), Some(job)) => {
+                        builder.dumps_dir(dumps_dir)
+                               .job(dump.value, version, job.value);
+                    },
+                    _ => bail_kind!(User,
+                                    "You must supply one of these 3 valid argument sets:\n\
+                                     1. `--dump-file`\n\
+                                     2. `--job-dir'\n\
+                                     3. `--page-files-dir'\n\
+                                     4. `--dump`, `--version`, and `--job`"),
                 }
             },
+            _ => bail_kind!(User,
+                            "You supplied more than one of --job-file, --job-dir, and \
+                             --page-files-dir, but should only supply one of these"),
         }; // end of match on arg choices.
 
-        Ok(dump::local::OpenSpec {
-            compression: self.compression,
-            source,
-            limit: self.limit,
-        })
+        match self.compression {
+            Some(compression) => builder.compression(compression),
+            None => builder.compression_auto(),
+        };
+        if let Some(seek) = self.seek {
+            builder.seek(seek);
+        }
+        if let Some(file_name_regex) = self.file_name_regex.value {
+            builder.file_name_regex(file_name_regex);
+        }
+        if let Some(limit) = self.limit {
+            builder.limit(limit);
+        }
+
+        let mut parse_limits = ParseLimits::default();
+        if let Some(max) = self.max_text_bytes {
+            parse_limits.max_text_len = max;
+        }
+        if let Some(max) = self.max_page_bytes {
+            parse_limits.max_page_len = max;
+        }
+        if let Some(max) = self.max_buf_bytes {
+            parse_limits.max_buf_len = max;
+        }
+        builder.parse_limits(parse_limits);
+
+        builder.build()
     }
 }
@@ -0,0 +1,96 @@
+use anyhow::Context;
+use crate::args::{CommonArgs, JsonOutputArg};
+use std::{
+    fs,
+    io::BufReader,
+    path::PathBuf,
+    time::Duration as StdDuration,
+};
+use wikimedia::Result;
+use wikimedia_store::deletion;
+
+/// Delete the pages in a deletion list from the store, e.g. for takedowns or Wikimedia page
+/// suppressions, recording each in an audit log.
+///
+/// The list has one page per line: a MediaWiki page ID, or a title, optionally followed by
+/// a tab and the reason it's deleted. Prefix a title made only of digits with `title:`.
+/// Blank lines and lines starting with `#` are ignored.
+///
+/// Pages are deleted in batches with a pause between them, so the web server can keep
+/// reading the store. Each entry is appended to the audit log as JSON when its batch is
+/// committed. Then each chunk file that held deleted pages is written again without them,
+/// so the deleted pages' text doesn't remain on disk.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// The path of the deletion list to read.
+    #[arg(long)]
+    list: PathBuf,
+
+    /// The count of pages to delete in each index transaction.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    batch_size: u32,
+
+    /// How long to pause between batches, in milliseconds.
+    #[arg(long, default_value_t = 1000)]
+    batch_interval_ms: u64,
+
+    /// Only record the pages that would be deleted in the audit log.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// The path of the audit log to append to. Defaults to `deletions.jsonl` in the store's
+    /// directory.
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Rebuild the index file afterwards, so no deleted rows remain in its free space. This
+    /// needs as much free disk space as the index takes.
+    #[arg(long, default_value_t = false)]
+    vacuum: bool,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let list = fs::File::open(&args.list)
+        .with_context(|| format!("opening deletion list path='{}'", args.list.display()))?;
+    let entries = deletion::parse_list(BufReader::new(list))?;
+
+    let mut store = args.common.store_options()?
+                               .cancellation(crate::cancellation()?)
+                               .build()?;
+
+    let mut options = deletion::Options::default();
+    options.batch_size(args.batch_size as usize)
+           .batch_interval(StdDuration::from_millis(args.batch_interval_ms))
+           .dry_run(args.dry_run)
+           .vacuum(args.vacuum);
+    if let Some(audit_log) = args.audit_log {
+        options.audit_log(audit_log);
+    }
+
+    let report = store.apply_deletion_list(&entries, &options)?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+        println!();
+    } else {
+        let deleted = match report.dry_run {
+            true => "would delete:",
+            false => "deleted:",
+        };
+        println!("entries:        {}", report.entries_len);
+        println!("{deleted:<16}{}", report.pages_deleted);
+        println!("not found:      {}", report.not_found);
+        println!("chunks removed: {}", report.chunks_removed);
+        println!("audit log:      {}", report.audit_log.display());
+        println!("duration:       {}", report.duration);
+    }
+
+    Ok(())
+}
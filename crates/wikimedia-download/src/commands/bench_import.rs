@@ -0,0 +1,82 @@
+use anyhow::Context;
+use crate::args::CommonArgs;
+use std::path::PathBuf;
+use wikimedia::{bail_kind, dump::synthetic, Result};
+use wikimedia_store::bench::{self, ImportReport};
+
+/// Benchmark importing a generated synthetic dump, and print the rates as JSON.
+///
+/// The dump and the store imported into are written to a temporary directory under the
+/// output directory, so choose the output directory on the disk to benchmark. Compare a run
+/// with a saved previous run with `--baseline` to check for performance regressions.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The number of pages to generate.
+    #[arg(long, default_value_t = 10_000)]
+    pages: u64,
+
+    /// The approximate length in bytes of each article's wikitext.
+    #[arg(long, default_value_t = 4_000)]
+    text_len: usize,
+
+    /// The random number generator seed for the dump's contents.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// The maximum length in bytes of each chunk file written. Defaults to 10 MB.
+    #[arg(long)]
+    max_chunk_len: Option<u64>,
+
+    /// Keep the generated dump and store after the benchmark.
+    #[arg(long, default_value_t = false)]
+    keep: bool,
+
+    /// The path to JSON output from a previous run. Exits with an error if any rate is
+    /// more than `--max-regression-pct` slower than in this run.
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// The slowdown in percent compared to `--baseline` reported as a regression.
+    #[arg(long, default_value_t = 20.0)]
+    max_regression_pct: f64,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let baseline = args.baseline.as_ref().map(|path| -> Result<ImportReport> {
+        let json = std::fs::read(path)
+            .with_context(|| format!("reading baseline path='{}'", path.display()))?;
+        Ok(serde_json::from_slice(&json)?)
+    }).transpose()?;
+
+    let options = bench::Options {
+        dump: synthetic::Options {
+            pages: args.pages,
+            text_len: args.text_len,
+            seed: args.seed,
+            ..synthetic::Options::default()
+        },
+        dir: args.common.out_dir(),
+        keep: args.keep,
+        max_chunk_len: args.max_chunk_len,
+    };
+    // Importing blocks for a long time, so run it on a thread for blocking work.
+    let report = tokio::task::spawn_blocking(move || bench::run_import(&options)).await??;
+
+    serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+    println!();
+
+    if let Some(baseline) = baseline {
+        let regressions = report.regressions(&baseline, args.max_regression_pct / 100.0);
+        if !regressions.is_empty() {
+            bail_kind!(Verification,
+                       "Import performance regressed compared to the baseline:\n{}",
+                       regressions.join("\n"));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use crate::args::{CommonArgs, FtsTokenizerArgs};
+use wikimedia::Result;
+
+/// Rebuild the full text search index of page titles in the store.
+///
+/// With `--fts-tokenizer`, the index is recreated with that tokenizer, for example to search
+/// a store of a Japanese or Chinese dump imported with the default tokenizer.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    fts: FtsTokenizerArgs,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store = args.common.store_options()?.build()?;
+
+    match args.fts.tokenizer()? {
+        Some(tokenizer) => store.retokenize_fts(&tokenizer)?,
+        None => store.build_fts()?,
+    }
+
+    Ok(())
+}
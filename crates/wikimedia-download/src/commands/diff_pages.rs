@@ -0,0 +1,59 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::Result;
+use wikimedia_store::{diff, StorePageId};
+
+/// Diff the revision texts of two pages in the store.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The store page ID of the old page.
+    #[arg(long)]
+    old_store_page_id: StorePageId,
+
+    /// The store page ID of the new page.
+    #[arg(long)]
+    new_store_page_id: StorePageId,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?.build()?;
+
+    let diff = store.diff(args.old_store_page_id, args.new_store_page_id)?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &diff)?;
+        println!();
+        return Ok(());
+    }
+
+    // json == false, so print the diff as text, with changed words marked like `git diff
+    // --word-diff`: `[-deleted-]` and `{+inserted+}`.
+    for line in diff.lines.iter() {
+        let (sign, open, close) = match line.tag {
+            diff::ChangeTag::Delete => ('-', "[-", "-]"),
+            diff::ChangeTag::Insert => ('+', "{+", "+}"),
+            diff::ChangeTag::Equal => (' ', "", ""),
+        };
+
+        let text = line.spans.iter()
+            .map(|span| if span.emphasized {
+                format!("{open}{text}{close}", text = span.text)
+            } else {
+                span.text.clone()
+            })
+            .collect::<String>();
+
+        println!("{sign} {text}");
+    }
+
+    let (deleted, inserted) = diff.line_counts();
+    eprintln!("\n{deleted} lines deleted, {inserted} lines inserted");
+
+    Ok(())
+}
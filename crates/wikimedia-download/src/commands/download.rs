@@ -1,5 +1,6 @@
 use crate::{
-    args::{CommonArgs, DumpNameArg, FileNameRegexArg, JobNameArg, VersionSpecArg},
+    args::{CommonArgs, DumpNameArg, DumpSourceArgs, FileNameRegexArg, JobNameArg,
+           SharedDirArgs, VersionSpecArg},
 };
 use wikimedia::{
     dump,
@@ -24,23 +25,21 @@ pub struct Args {
     #[clap(flatten)]
     file_name_regex: FileNameRegexArg,
 
+    /// Download the files that are done from a job that is not done yet.
+    ///
+    /// By default only jobs with status `done` are downloaded.
+    #[arg(long, default_value_t = false)]
+    allow_incomplete_job: bool,
+
     /// Keep the temporary directory where files are initially downloaded. By default this is deleted after use.
     #[arg(long, default_value_t = false)]
     keep_temp_dir: bool,
 
-    /// Specify the URL of a mirror to download job files from. Only supports http: and https: URLs.
-    ///
-    /// If not present tries to read the environment variable `WMD_MIRROR_URL`.
-    ///
-    /// Examples:
-    ///   * <https://dumps.wikimedia.org>
-    ///   * <https://ftp.acc.umu.se/mirror/wikimedia.org/dumps>
-    ///
-    /// Note that only job files are downloaded from this mirror, metadata files are downloaded from <https://dumps.wikimedia.org> to ensure we get the freshest data.
-    ///
-    /// To find a mirror, see <https://meta.wikimedia.org/wiki/Mirroring_Wikimedia_project_XML_dumps#Current_mirrors>
-    #[arg(long, env = "WMD_MIRROR_URL")]
-    mirror_url: String,
+    #[clap(flatten)]
+    source: DumpSourceArgs,
+
+    #[clap(flatten)]
+    shared_dirs: SharedDirArgs,
 }
 
 #[tracing::instrument(level = "trace")]
@@ -49,13 +48,17 @@ pub async fn main(args: Args) -> Result<()> {
     let version_spec = &args.version_spec.value;
     let job_name = &args.job_name.value;
 
-    let download_options =
-        dump::download::OptionsBuilder::default()
-            .http_options(args.common.http_options()?.build()?)
-            .keep_temp_dir(args.keep_temp_dir)
-            .dump_mirror_url(args.mirror_url.clone())
-            .out_dir(args.common.dumps_path())
-            .build()?;
+    let mut download_options = dump::download::OptionsBuilder::default();
+    download_options
+        .http_options(args.common.http_options()?.build()?)
+        .allow_incomplete_job(args.allow_incomplete_job)
+        .metadata_cache_options(args.common.metadata_cache_options()?)
+        .keep_temp_dir(args.keep_temp_dir)
+        .source(args.source.source())
+        .out_dir(args.common.dumps_path());
+    args.shared_dirs.apply(&mut download_options);
+    download_options.cancellation(crate::cancellation()?);
+    let download_options = download_options.build()?;
 
     let _ = dump::download::download_job(
         dump_name,
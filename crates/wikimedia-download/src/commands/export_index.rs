@@ -0,0 +1,34 @@
+use crate::args::CommonArgs;
+use std::path::PathBuf;
+use wikimedia::Result;
+
+/// Write a compact copy of the store's index to a file, to clone the store elsewhere.
+///
+/// To clone a store, copy its chunk files (e.g. with rsync) and the exported index file to
+/// the new location, then run `wmd import-index` there. This is much faster than importing
+/// the dump again or rebuilding the full text search index.
+///
+/// The file is a sqlite database, so it can also be served as a remote store's index at
+/// `{remote_url}/index/index.db`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The path of the file to write. It must not already exist.
+    #[arg(long)]
+    out_file: PathBuf,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?
+                           .cancellation(crate::cancellation()?)
+                           .build()?;
+
+    store.export_index(&args.out_file)?;
+
+    tracing::info!(path = %args.out_file.display(), "Exported store index");
+
+    Ok(())
+}
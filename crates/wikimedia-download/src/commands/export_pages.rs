@@ -0,0 +1,138 @@
+use anyhow::Context;
+use crate::args::CommonArgs;
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use wikimedia::{dump::Namespace, Result, util::fmt::Sha1Hash};
+use wikimedia_store::export;
+
+/// Export every page in the store to a file, or to stdout.
+///
+/// Pages are read and formatted in parallel. By default they are written in the order their
+/// chunks finish; pass `--ordered` for output in MediaWiki ID order that is the same each
+/// time the same store is exported.
+///
+/// With `--out-file` a manifest describing the export is also written, with the store's
+/// dump name and version, the imports that wrote its pages, the export options, and the
+/// count of pages and SHA1 hash of the file written, so a published dataset made from it can
+/// be reproduced and attributed.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The format to export pages in.
+    #[arg(long, value_enum, default_value_t = Format::Jsonl)]
+    format: Format,
+
+    /// The path of the file to write. It must not already exist. When not set, pages are
+    /// written to stdout.
+    #[arg(long)]
+    out_file: Option<PathBuf>,
+
+    /// The path of the export manifest to write, as JSON. Defaults to the path of
+    /// `--out-file` with `.manifest.json` appended.
+    #[arg(long, requires = "out_file")]
+    manifest_file: Option<PathBuf>,
+
+    /// Write pages in ascending MediaWiki ID order.
+    #[arg(long, default_value_t = false)]
+    ordered: bool,
+
+    /// Only export pages in this namespace, given by key or name, e.g. `0` or `Main` for
+    /// articles.
+    #[arg(long)]
+    namespace: Option<Namespace>,
+
+    /// The count of threads to read and format pages with. Defaults to the count of CPUs.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+
+    /// Don't print progress to stderr.
+    #[arg(long, default_value_t = false)]
+    quiet: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// One JSON object per line for each page.
+    Jsonl,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?
+                           .cancellation(crate::cancellation()?)
+                           .build()?;
+
+    let mut pipeline = export::pipeline::Options::default();
+    pipeline.ordered(args.ordered)
+            .progress(!args.quiet);
+    if let Some(threads) = args.threads {
+        pipeline.threads(threads.into());
+    }
+
+    let mut options = export::Options::new(match args.format {
+        Format::Jsonl => export::Format::Jsonl,
+    });
+    options.pipeline(pipeline);
+    if let Some(namespace) = args.namespace.as_ref() {
+        options.namespace(namespace.key());
+    }
+
+    let mut out: Box<dyn Write> = match args.out_file.as_ref() {
+        Some(path) => Box::new(BufWriter::new(
+            fs::File::create_new(path)
+                .with_context(|| format!("creating export file path='{}'", path.display()))?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+
+    let res = match store.export_pages(&options, &mut *out) {
+        Ok(res) => res,
+        Err(e) => {
+            drop(out);
+            if let Some(path) = args.out_file.as_ref() {
+                // Don't leave a partial export that looks complete.
+                fs::remove_file(path)
+                    .with_context(|| format!("removing partial export file path='{}'",
+                                             path.display()))?;
+            }
+            return Err(e);
+        },
+    };
+
+    out.flush()?;
+    drop(out);
+
+    if let Some(path) = args.out_file.as_ref() {
+        let manifest_path = args.manifest_file.clone()
+                                .unwrap_or_else(|| default_manifest_path(path));
+        let sha1 = Sha1Hash::calculate_from_reader(
+            fs::File::open(path)
+                .with_context(|| format!("opening export file path='{}'", path.display()))?)?;
+        let manifest = store.export_manifest(&options, &res, sha1)?;
+        let mut manifest_file = BufWriter::new(
+            fs::File::create(&manifest_path)
+                .with_context(|| format!("creating export manifest file path='{}'",
+                                         manifest_path.display()))?);
+        serde_json::to_writer_pretty(&mut manifest_file, &manifest)?;
+        manifest_file.write_all(b"\n")?;
+        manifest_file.flush()?;
+    }
+
+    tracing::info!(pages_len = res.pages_len,
+                   bytes_len = %res.bytes_len,
+                   duration = %res.duration,
+                   path = ?args.out_file,
+                   "Exported pages");
+
+    Ok(())
+}
+
+fn default_manifest_path(out_file: &Path) -> PathBuf {
+    let mut path = out_file.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
@@ -0,0 +1,132 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::{
+    dump::{CategorySlug, Namespace},
+    format_err_kind,
+    Result,
+};
+use wikimedia_store::{self as store, index};
+
+/// Get categories from the store, or the pages in a category.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The slug or name of a single category to get, e.g. `Living_people` or
+    /// `Category:Living people`.
+    ///
+    /// If not set, a list of categories is returned.
+    #[arg(long)]
+    slug: Option<CategorySlug>,
+
+    /// Get the pages in the category given by `--slug`, instead of the category itself.
+    #[arg(long, default_value_t = false, requires = "slug")]
+    members: bool,
+
+    /// When listing categories, only return categories with a slug greater than this value.
+    ///
+    /// Use the last slug from a previous run to get the next page of results.
+    #[arg(long, conflicts_with = "slug")]
+    slug_lower_bound: Option<CategorySlug>,
+
+    /// When listing category members, only return pages after the page with this MediaWiki
+    /// ID. Members are listed in order of their sort keys in the category.
+    ///
+    /// Use the last MediaWiki ID from a previous run to get the next page of results.
+    #[arg(long, requires = "members")]
+    page_mediawiki_id_lower_bound: Option<u64>,
+
+    /// When listing category members, only return pages in this namespace, given by key or
+    /// name, e.g. `0` or `Main` for articles without their talk pages, or `Category` for
+    /// subcategories.
+    #[arg(long, requires = "members")]
+    namespace: Option<Namespace>,
+
+    /// The maximum number of results to return.
+    ///
+    /// Defaults to and is capped at the store's maximum query limit.
+    #[arg(long)]
+    limit: Option<u64>,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?.build()?;
+
+    let limit = store::QueryLimit::from(args.limit);
+
+    match (args.slug.as_ref(), args.members) {
+        (None, _) => {
+            let categories = store.get_category(args.slug_lower_bound.as_ref(), limit)?;
+
+            output_categories(&args, &categories)?;
+
+            if u64::try_from(categories.len()).expect("u64 from usize") == limit.get() {
+                let last = categories.last().expect("categories not empty");
+                eprintln!("\nMore categories may be available, \
+                           to get them pass `--slug-lower-bound '{last}'`",
+                          last = last.slug.0);
+            }
+        },
+        (Some(slug), false) => {
+            let category = store.get_category_by_slug(slug)?
+                                .ok_or_else(
+                                    || format_err_kind!(User, "Category not found by slug."))?;
+            output_categories(&args, &[category])?;
+        },
+        (Some(slug), true) => {
+            let pages = store.get_category_pages(
+                slug,
+                args.page_mediawiki_id_lower_bound,
+                args.namespace.as_ref().map(Namespace::key),
+                limit)?;
+
+            output_pages(&args, &pages)?;
+
+            if u64::try_from(pages.len()).expect("u64 from usize") == limit.get() {
+                let last = pages.last().expect("pages not empty");
+                eprintln!("\nMore pages may be available, \
+                           to get them pass `--page-mediawiki-id-lower-bound {last}`",
+                          last = last.mediawiki_id);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn output_categories(args: &Args, categories: &[index::Category]) -> Result<()> {
+    for category in categories.iter() {
+        if args.json.value {
+            serde_json::to_writer_pretty(&std::io::stdout(), category)?;
+            println!();
+        } else {
+            println!("{}", category.name.0);
+        }
+    }
+
+    Ok(())
+}
+
+fn output_pages(args: &Args, pages: &[index::Page]) -> Result<()> {
+    if args.json.value {
+        for page in pages.iter() {
+            serde_json::to_writer_pretty(&std::io::stdout(), page)?;
+            println!();
+        }
+    } else {
+        // json == false, so print a table of IDs and titles.
+        println!("{:>12}  {:>16}  title", "mediawiki_id", "store_page_id");
+        for page in pages.iter() {
+            println!("{mediawiki_id:>12}  {store_page_id:>16}  {title}",
+                     mediawiki_id = page.mediawiki_id,
+                     store_page_id = page.store_id().to_string(),
+                     title = page.title());
+        }
+    }
+
+    Ok(())
+}
@@ -1,36 +1,65 @@
-use anyhow::format_err;
 use crate::args::CommonArgs;
-use wikimedia::Result;
+use wikimedia::{format_err_kind, Result};
 use wikimedia_store as store;
 
-/// Get information about a page store chunk.
+/// Get information about a page store chunk as JSON, in a stable schema for scripts.
+///
+/// See `wikimedia_store::metadata::ChunkInfo` for the fields.
 #[derive(clap::Args, Clone, Debug)]
 pub struct Args {
     #[clap(flatten)]
     common: CommonArgs,
 
-    /// The chunk ID to examine. If not set, data about all chunks will be returned.
+    /// The chunk ID to examine. If not set, data about chunks in ascending ID order will be
+    /// returned, up to `--limit`.
     #[arg(long)]
     chunk_id: Option<store::ChunkId>,
+
+    /// When listing chunks, only return chunks with IDs greater than this.
+    ///
+    /// Use this to get the next chunks after a previous listing.
+    #[arg(long, conflicts_with = "chunk_id")]
+    chunk_id_lower_bound: Option<store::ChunkId>,
+
+    /// The maximum number of chunks to return when listing chunks.
+    ///
+    /// Defaults to and is capped at the store's maximum query limit.
+    #[arg(long, conflicts_with = "chunk_id")]
+    limit: Option<u64>,
+
+    /// Don't calculate the SHA1 hash of each chunk file, which reads every chunk in full.
+    #[arg(long, default_value_t = false)]
+    no_hash: bool,
 }
 
 #[tracing::instrument(level = "trace")]
 pub async fn main(args: Args) -> Result<()> {
     let store = args.common.store_options()?.build()?;
 
+    let limit = store::QueryLimit::from(args.limit);
+
     let chunk_ids: Vec<store::ChunkId> =
         match args.chunk_id {
             Some(chunk_id) => vec![chunk_id],
-            None => store.chunk_id_vec()?,
+            None => store.chunk_ids(args.chunk_id_lower_bound, limit)?,
         };
 
-    for chunk_id in chunk_ids.into_iter() {
-        let chunk_meta = store.get_chunk_meta_by_chunk_id(chunk_id)?
-                              .ok_or_else(|| format_err!("ChunkMeta not found by ChunkId"))?;
+    for chunk_id in chunk_ids.iter() {
+        let chunk_info = store.chunk_info(*chunk_id, !args.no_hash)?
+                              .ok_or_else(
+                                  || format_err_kind!(User, "Chunk not found by ChunkId"))?;
 
-        serde_json::to_writer_pretty(&std::io::stdout(), &chunk_meta)?;
+        serde_json::to_writer_pretty(&std::io::stdout(), &chunk_info)?;
         println!();
     }
 
+    if args.chunk_id.is_none()
+        && u64::try_from(chunk_ids.len()).expect("u64 from usize") == limit.get()
+    {
+        let last = chunk_ids.last().expect("chunk_ids not empty");
+        eprintln!("\nMore chunks may be available, \
+                   to get them pass `--chunk-id-lower-bound {last}`");
+    }
+
     Ok(())
 }
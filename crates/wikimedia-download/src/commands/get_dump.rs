@@ -1,7 +1,6 @@
 use crate::args::{CommonArgs, JsonOutputArg};
 use wikimedia::{
     dump,
-    http,
     Result,
 };
 
@@ -17,17 +16,19 @@ pub struct Args {
 
 #[tracing::instrument(level = "trace")]
 pub async fn main(args: Args) -> Result<()> {
-    let client = http::metadata_client(&args.common.http_options()?.build()?)?;
+    let client = args.common.metadata_client()?;
 
     let dumps = dump::download::get_dumps(&client).await?;
 
     if args.json.value {
-        for dump in dumps {
-            println!(r#""{}""#, dump.0);
+        for dump in dumps.iter() {
+            serde_json::to_writer_pretty(&std::io::stdout(), dump)?;
+            println!();
         }
     } else {
-        for dump in dumps {
-            println!("{}", dump.0);
+        // json == false, so print dump names only
+        for dump in dumps.iter() {
+            println!("{}", dump.name.0);
         }
     }
 
@@ -3,7 +3,6 @@ use crate::{
 };
 use wikimedia::{
     dump::{self, FileInfoOutput},
-    http,
     Result,
 };
 
@@ -25,35 +24,48 @@ pub struct Args {
     #[clap(flatten)]
     file_name_regex: FileNameRegexArg,
 
+    /// Get files from a job that is not done yet. Each file's status is returned.
+    ///
+    /// By default only jobs with status `done` are accepted.
+    #[arg(long, default_value_t = false)]
+    allow_incomplete_job: bool,
+
     #[clap(flatten)]
     json: JsonOutputArg,
 }
 
 #[tracing::instrument(level = "trace")]
 pub async fn main(args: Args) -> Result<()> {
-    let client = http::metadata_client(&args.common.http_options()?.build()?)?;
+    let client = args.common.metadata_client()?;
 
-    let (_version, files) = dump::download::get_file_infos(
+    let dump::download::FileInfos { job_status, files, .. } = dump::download::get_file_infos(
         &client,
         &args.dump_name.value,
         &args.version_spec.value,
         &args.job_name.value,
-        args.file_name_regex.value.as_ref()).await?;
+        args.file_name_regex.value.as_ref(),
+        args.allow_incomplete_job).await?;
 
     if args.json.value {
         for (file_name, file_meta) in files.iter() {
             let file = FileInfoOutput {
                 name: file_name.clone(),
+                status: file_meta.status(job_status),
                 metadata: file_meta.clone(),
             };
             serde_json::to_writer_pretty(&std::io::stdout(), &file)?;
             println!();
         }
-    } else {
+    } else if job_status == dump::Status::Done {
         // json == false, so print file names only
         for (file_name, _file_meta) in files.iter() {
             println!("{}", file_name);
         }
+    } else {
+        // json == false and the job is not done, so print file names and statuses.
+        for (file_name, file_meta) in files.iter() {
+            println!("{file_name} {status}", status = file_meta.status(job_status));
+        }
     }
 
     Ok(())
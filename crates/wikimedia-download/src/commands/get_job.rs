@@ -1,8 +1,7 @@
-use anyhow::bail;
 use crate::args::{CommonArgs, DumpNameArg, JsonOutputArg, VersionSpecArg};
 use wikimedia::{
+    bail_kind,
     dump::{self, JobName, JobOutput, JobStatus},
-    http,
     Result,
 };
 
@@ -31,7 +30,7 @@ pub async fn main(args: Args) -> Result<()> {
     let dump_name = &args.dump_name.value;
     let version_spec = &args.version.value;
 
-    let client = http::metadata_client(&args.common.http_options()?.build()?)?;
+    let client = args.common.metadata_client()?;
 
     let(version, version_status) = dump::download::get_dump_version_status(&client, dump_name,
                                                                            version_spec).await?;
@@ -39,11 +38,12 @@ pub async fn main(args: Args) -> Result<()> {
     let mut jobs: Vec<(String, JobStatus)> = match args.job_name.as_ref() {
         Some(job_name) => {
             let Some(job_status) = version_status.jobs.get(&*job_name.0) else {
-                bail!("No status found for job job_name='{job_name}' version='{version}' \
-                       dump_name='{dump_name}'",
-                      dump_name = dump_name.0,
-                      job_name = job_name.0,
-                      version = version.0);
+                bail_kind!(User,
+                           "No status found for job job_name='{job_name}' version='{version}' \
+                            dump_name='{dump_name}'",
+                           dump_name = dump_name.0,
+                           job_name = job_name.0,
+                           version = version.0);
             };
             vec![(job_name.0.clone(), job_status.clone())]
         },
@@ -63,6 +63,12 @@ pub async fn main(args: Args) -> Result<()> {
                                             .map(|file_info| file_info.size.unwrap_or(0))
                                             .sum(),
                 files_count: job_status.files.len(),
+                files_done_count: job_status.done_files().count(),
+                file_statuses: job_status.files.iter()
+                                               .map(|(name, file_info)|
+                                                    (name.clone(),
+                                                     file_info.status(job_status.status)))
+                                               .collect(),
                 status: job_status.clone(),
             };
             serde_json::to_writer_pretty(&std::io::stdout(), &job)?;
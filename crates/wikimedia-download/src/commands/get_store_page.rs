@@ -1,15 +1,9 @@
-use anyhow::{bail, Context, format_err};
 use crate::args::CommonArgs;
-use std::{
-    fs,
-    io::Write,
-};
 use wikimedia::{
+    bail_kind,
     dump,
+    format_err_kind,
     Result,
-    slug,
-    util::rand::rand_hex,
-    wikitext,
 };
 use wikimedia_store::{
     self as store,
@@ -80,7 +74,8 @@ enum OutputType {
 #[tracing::instrument(level = "trace")]
 pub async fn main(args: Args) -> Result<()> {
     if args.open && args.out != OutputType::Html {
-        bail!("If argument `--open` is passed then argument `--out` must equal `html`.");
+        bail_kind!(User,
+                   "If argument `--open` is passed then argument `--out` must equal `html`.");
     }
 
     let arg_groups_given: Vec<&'static str> = [
@@ -91,40 +86,43 @@ pub async fn main(args: Args) -> Result<()> {
         ].into_iter().flatten().collect();
 
     if arg_groups_given.len() > 1{
-        bail!("You passed multiple arguments specifying which pages to get: {opts}.\n\
-               You must pass only one of these arguments.",
-              opts = arg_groups_given.join(", "));
+        bail_kind!(User,
+                   "You passed multiple arguments specifying which pages to get: {opts}.\n\
+                    You must pass only one of these arguments.",
+                   opts = arg_groups_given.join(", "));
     }
 
     let store = args.common.store_options()?.build()?;
+    let site = store.site();
 
     let mut count: u64 = 0;
 
     match (args.store_page_id, args.mediawiki_id, args.slug.as_ref(), args.chunk_id) {
         (Some(store_page_id), None, None, None) => {
             let page = store.get_page_by_store_id(store_page_id)?
-                            .ok_or_else(|| format_err!("page not found by id."))?;
-            output_page(&args, page.borrow()?).await?;
+                            .ok_or_else(|| format_err_kind!(User, "page not found by id."))?;
+            output_page(&args, site.as_ref(), page.borrow()?).await?;
             count += 1;
         },
         (None, Some(mediawiki_id), None, None) => {
             let page = store.get_page_by_mediawiki_id(mediawiki_id)?
-                            .ok_or_else(|| format_err!("page not found by mediawiki-id."))?;
-            output_page(&args, page.borrow()?).await?;
+                            .ok_or_else(
+                                || format_err_kind!(User, "page not found by mediawiki-id."))?;
+            output_page(&args, site.as_ref(), page.borrow()?).await?;
             count += 1;
         },
         (None, None, Some(slug), None) => {
             let page = store.get_page_by_slug(slug)?
-                            .ok_or_else(|| format_err!("page not found by slug."))?;
-            output_page(&args, page.borrow()?).await?;
+                            .ok_or_else(|| format_err_kind!(User, "page not found by slug."))?;
+            output_page(&args, site.as_ref(), page.borrow()?).await?;
             count += 1;
         },
         (None, None, None, Some(chunk_id)) => {
             check_output_type_not_html(args.out)?;
             let chunk = store.map_chunk(chunk_id)?
-                             .ok_or_else(|| format_err!("chunk not found by id."))?;
+                             .ok_or_else(|| format_err_kind!(User, "chunk not found by id."))?;
             for (_store_id, page) in chunk.pages_iter()? {
-                output_page(&args, page).await?;
+                output_page(&args, site.as_ref(), page).await?;
                 count += 1;
 
                 if args.limit.is_some() && count >= args.limit.unwrap() {
@@ -142,10 +140,11 @@ pub async fn main(args: Args) -> Result<()> {
             for chunk_id in chunk_ids.into_iter() {
                 tracing::debug!(?chunk_id, "Outputting pages from new chunk");
                 let chunk = store.map_chunk(chunk_id)?
-                                 .ok_or_else(|| format_err!("chunk not found by id."))?;
+                                 .ok_or_else(
+                                     || format_err_kind!(User, "chunk not found by id."))?;
                 '_by_page:
                 for (_store_id, page) in chunk.pages_iter()? {
-                    output_page(&args, page).await?;
+                    output_page(&args, site.as_ref(), page).await?;
                     count += 1;
 
                     if args.limit.is_some() && count >= args.limit.unwrap() {
@@ -164,13 +163,14 @@ pub async fn main(args: Args) -> Result<()> {
 
 fn check_output_type_not_html(output_type: OutputType) -> Result<()> {
     match output_type {
-        OutputType::Html => bail!(
-            "Cannot use --out Html if more than one page might be returned."),
+        OutputType::Html => bail_kind!(
+            User, "Cannot use --out Html if more than one page might be returned."),
         _ => Ok(())
     }
 }
 
-async fn output_page(args: &Args, page: wmc::page::Reader<'_>) -> Result<()>
+async fn output_page(args: &Args, site: Option<&dump::SiteInfo>, page: wmc::page::Reader<'_>
+) -> Result<()>
 {
     match args.out {
         OutputType::None => {},
@@ -198,43 +198,69 @@ async fn output_page(args: &Args, page: wmc::page::Reader<'_>) -> Result<()>
         },
         OutputType::Html => {
             let page = dump::Page::try_from(&page)?;
-            let html = wikitext::convert_page_to_html(&page, &args.common.store_dump_name(),
-                                                      &*args.common.out_dir()).await?;
-
-            if args.open {
-                // Write page HTML to a temp file.
-                let slug = slug::title_to_slug(&*page.title);
-
-                // Add rand a random value to output file names to
-                // avoid overwriting data from previous runs.
-                let rand = rand_hex(8);
-
-                let path = args.common.out_dir().join(
-                    format!("temp/pages/{slug}_{rand}.html"));
-                let parent = path.parent().expect("path has parent by construction");
-
-                // Closure to add error context.
-                (|| -> Result<()> {
-                    println!("\nWrite output HTML to {path} . . .\n", path = path.display());
-
-                    fs::create_dir_all(parent)?;
-                    fs::write(&*path, html.as_bytes())?;
-
-                    // Open the html file using the operating system's default method,
-                    // should use a web browser.
-                    open::that(&*path)
-                        .with_context(|| "opening the HTML file in your browser")?;
-
-                    Ok(())
-                })().with_context(|| format!("saving HTML to file and opening it in a browser \
-                                              file_path={path}",
-                                             path = (&*path).display()))?;
-            } else {
-                // args.open == false
-                std::io::stdout().write_all(html.as_bytes())?;
-            }
+            output_html(args, site, &page).await?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(feature = "wikitext-render")]
+async fn output_html(args: &Args, site: Option<&dump::SiteInfo>, page: &dump::Page
+) -> Result<()>
+{
+    use anyhow::Context;
+    use std::{fs, io::Write};
+    use wikimedia::{slug, util::rand::rand_hex, wikitext};
+
+    let rendered = wikitext::convert_page_to_html(page, &args.common.store_dump_name(),
+                                                  site, &*args.common.out_dir(),
+                                                  &wikitext::RenderLimits::default())
+                            .await?;
+    if let Some(limit_exceeded) = rendered.limit_exceeded {
+        tracing::warn!(%limit_exceeded, "Page HTML truncated");
+    }
+    let html = rendered.html;
+
+    if args.open {
+        // Write page HTML to a temp file.
+        let slug = slug::title_to_slug(&*page.title);
+
+        // Add rand a random value to output file names to
+        // avoid overwriting data from previous runs.
+        let rand = rand_hex(8);
+
+        let path = args.common.out_dir().join(
+            format!("temp/pages/{slug}_{rand}.html"));
+        let parent = path.parent().expect("path has parent by construction");
+
+        // Closure to add error context.
+        (|| -> Result<()> {
+            println!("\nWrite output HTML to {path} . . .\n", path = path.display());
+
+            fs::create_dir_all(parent)?;
+            fs::write(&*path, html.as_bytes())?;
+
+            // Open the html file using the operating system's default method,
+            // should use a web browser.
+            open::that(&*path)
+                .with_context(|| "opening the HTML file in your browser")?;
+
+            Ok(())
+        })().with_context(|| format!("saving HTML to file and opening it in a browser \
+                                      file_path={path}",
+                                     path = (&*path).display()))?;
+    } else {
+        // args.open == false
+        std::io::stdout().write_all(html.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "wikitext-render"))]
+async fn output_html(_args: &Args, _site: Option<&dump::SiteInfo>, _page: &dump::Page
+) -> Result<()>
+{
+    bail_kind!(User, "`--out html` requires wmd built with the `wikitext-render` feature")
+}
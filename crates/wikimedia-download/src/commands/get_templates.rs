@@ -0,0 +1,101 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::{
+    format_err_kind,
+    Result,
+    slug,
+    wikitext,
+};
+use wikimedia_store as store;
+
+/// Get the pages that transclude a template or module, or the templates and modules a page
+/// transcludes.
+///
+/// Templates are indexed when pages are imported, so pages imported by older versions of
+/// `wmd` have none until they are imported again.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The title or slug of a template or module to get the pages of, e.g.
+    /// `Infobox person`, `Template:Infobox_person`, or `Module:Citation/CS1`.
+    ///
+    /// Names without a namespace are templates. Prefix a name with `:` for a page in the
+    /// main namespace, e.g. `:Main Page`.
+    #[arg(long, required_unless_present = "page_mediawiki_id")]
+    template: Option<String>,
+
+    /// Get the templates and modules the page with this MediaWiki ID transcludes, instead of
+    /// the pages of a template.
+    #[arg(long, conflicts_with = "template")]
+    page_mediawiki_id: Option<u64>,
+
+    /// Also get pages that transclude the template through other templates in the store.
+    #[arg(long, default_value_t = false, requires = "template")]
+    indirect: bool,
+
+    /// Only return pages after the page with this MediaWiki ID. Pages are listed in order of
+    /// MediaWiki ID.
+    ///
+    /// Use the last MediaWiki ID from a previous run to get the next page of results.
+    #[arg(long, requires = "template")]
+    page_mediawiki_id_lower_bound: Option<u64>,
+
+    /// The maximum number of pages to return.
+    ///
+    /// Defaults to and is capped at the store's maximum query limit.
+    #[arg(long, requires = "template")]
+    limit: Option<u64>,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?.build()?;
+
+    let Some(template) = args.template.as_ref() else {
+        let page_mediawiki_id = args.page_mediawiki_id.expect("template or page ID required");
+        let templates = store.get_page_templates(page_mediawiki_id)?;
+        if args.json.value {
+            serde_json::to_writer_pretty(&std::io::stdout(), &templates)?;
+            println!();
+        } else {
+            for template_slug in templates.iter() {
+                println!("{}", slug::slug_to_title(template_slug));
+            }
+        }
+        return Ok(());
+    };
+
+    let limit = store::QueryLimit::from(args.limit);
+    let template_title = wikitext::template_title(template)
+        .ok_or_else(|| format_err_kind!(User, "`--template` is not a template name."))?;
+    let pages = store.get_template_pages(&slug::title_to_slug(&template_title), args.indirect,
+                                         args.page_mediawiki_id_lower_bound, limit)?;
+
+    if args.json.value {
+        for page in pages.iter() {
+            serde_json::to_writer_pretty(&std::io::stdout(), page)?;
+            println!();
+        }
+    } else {
+        println!("{:>12}  {:>16}  title", "mediawiki_id", "store_page_id");
+        for page in pages.iter() {
+            println!("{mediawiki_id:>12}  {store_page_id:>16}  {title}",
+                     mediawiki_id = page.mediawiki_id,
+                     store_page_id = page.store_id().to_string(),
+                     title = page.title());
+        }
+    }
+
+    if u64::try_from(pages.len()).expect("u64 from usize") == limit.get() {
+        let last = pages.last().expect("pages not empty");
+        eprintln!("\nMore pages may be available, \
+                   to get them pass `--page-mediawiki-id-lower-bound {last}`",
+                  last = last.mediawiki_id);
+    }
+
+    Ok(())
+}
@@ -1,11 +1,13 @@
 use crate::args::{CommonArgs, DumpNameArg, JsonOutputArg};
-use wikimedia::{
-    dump,
-    http,
-    Result,
-};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use wikimedia::Result;
+#[cfg(feature = "store")]
+use wikimedia_store::{self as store, metadata::StoreCompatibility};
 
 /// Get data about what versions are available for a dump.
+///
+/// With `--build`, get this program's build metadata and supported store formats instead.
 #[derive(clap::Args, Clone, Debug)]
 pub struct Args {
     #[clap(flatten)]
@@ -16,13 +18,80 @@ pub struct Args {
 
     #[clap(flatten)]
     json: JsonOutputArg,
+
+    /// Print this program's crate versions, git commit, enabled features, and supported store
+    /// format versions instead of the dump's versions.
+    #[arg(long, default_value_t = false)]
+    build: bool,
+
+    /// With `--build`, also check whether this program can open the store chosen by the
+    /// store arguments, without changing it.
+    #[cfg(feature = "store")]
+    #[arg(long, default_value_t = false, requires = "build")]
+    check_store: bool,
+}
+
+/// The output of `wmd get-version --build`.
+#[derive(Clone, Debug, Serialize)]
+struct BuildInfo {
+    /// The versions of wmd and the workspace crates it was built with, by crate name.
+    crates: BTreeMap<&'static str, &'static str>,
+
+    /// The git commit wmd was built from, if it was built from a git checkout.
+    git_commit: Option<&'static str>,
+
+    rustc: Option<&'static str>,
+    target: &'static str,
+    profile: &'static str,
+
+    /// Enabled cargo features, as `crate/feature`.
+    features: Vec<String>,
+
+    /// The chunk file format version, see `wikimedia_store::capnp::SCHEMA_VERSION`.
+    #[cfg(feature = "store")]
+    chunk_schema_version: u32,
+
+    /// The newest store index schema version supported. Older indexes are migrated.
+    #[cfg(feature = "store")]
+    index_schema_version: i64,
+
+    /// The JSON schema version of `store-info` and other metadata output.
+    #[cfg(feature = "store")]
+    metadata_schema_version: u32,
+
+    /// Set with `--check-store`.
+    #[cfg(feature = "store")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<StoreCompatibility>,
 }
 
+/// The cargo features `wmd` was built with.
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "download")]
+    "download",
+    #[cfg(feature = "store")]
+    "store",
+    #[cfg(feature = "web")]
+    "web",
+    #[cfg(feature = "wikitext-render")]
+    "wikitext-render",
+];
+
 #[tracing::instrument(level = "trace")]
 pub async fn main(args: Args) -> Result<()> {
-    let client = http::metadata_client(&args.common.http_options()?.build()?)?;
+    if args.build {
+        return print_build_info(&args);
+    }
 
-    let versions = dump::download::get_dump_versions(&client, &args.dump_name.value).await?;
+    print_dump_versions(&args).await
+}
+
+#[cfg(feature = "download")]
+async fn print_dump_versions(args: &Args) -> Result<()> {
+    let client = args.common.metadata_client()?;
+
+    let versions = wikimedia::dump::download::get_dump_versions(&client, &args.dump_name.value)
+                       .await?;
 
     if args.json.value {
         for version in versions {
@@ -36,3 +105,94 @@ pub async fn main(args: Args) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(not(feature = "download"))]
+async fn print_dump_versions(_args: &Args) -> Result<()> {
+    wikimedia::bail_kind!(User, "Getting a dump's versions requires wmd built with the \
+                                 `download` feature. Pass `--build` to get this program's \
+                                 build metadata.")
+}
+
+fn print_build_info(args: &Args) -> Result<()> {
+    #[cfg(feature = "store")]
+    let store = if args.check_store {
+        Some(args.common.store_options()?.compatibility()?)
+    } else {
+        None
+    };
+
+    let info = BuildInfo {
+        crates: BTreeMap::from([
+            ("wikimedia", wikimedia::VERSION),
+            ("wikimedia-download", env!("CARGO_PKG_VERSION")),
+            #[cfg(feature = "store")]
+            ("wikimedia-store", store::VERSION),
+            #[cfg(feature = "web")]
+            ("wikimedia-web", wikimedia_web::VERSION),
+        ]),
+        git_commit: option_env!("WMD_GIT_COMMIT"),
+        rustc: option_env!("WMD_RUSTC_VERSION"),
+        target: env!("WMD_TARGET"),
+        profile: env!("WMD_PROFILE"),
+        features: FEATURES.iter()
+                          .map(|feature| format!("wikimedia-download/{feature}"))
+                          .chain(wikimedia::FEATURES.iter()
+                                     .map(|feature| format!("wikimedia/{feature}")))
+                          .collect(),
+        #[cfg(feature = "store")]
+        chunk_schema_version: store::capnp::SCHEMA_VERSION,
+        #[cfg(feature = "store")]
+        index_schema_version: store::index::SCHEMA_VERSION,
+        #[cfg(feature = "store")]
+        metadata_schema_version: store::metadata::SCHEMA_VERSION,
+        #[cfg(feature = "store")]
+        store,
+    };
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &info)?;
+        println!();
+        return Ok(());
+    }
+
+    for (name, version) in info.crates.iter() {
+        println!("{name} {version}");
+    }
+    println!("git commit: {}", info.git_commit.unwrap_or("unknown"));
+    println!("rustc: {}", info.rustc.unwrap_or("unknown"));
+    println!("target: {} ({})", info.target, info.profile);
+    println!("features: {}",
+             if info.features.is_empty() { "none".to_string() } else { info.features.join(", ") });
+
+    #[cfg(feature = "store")]
+    print_store_build_info(&info);
+
+    Ok(())
+}
+
+#[cfg(feature = "store")]
+fn print_store_build_info(info: &BuildInfo) {
+    println!("chunk schema version: {}", info.chunk_schema_version);
+    println!("index schema version: {}", info.index_schema_version);
+    println!("metadata schema version: {}", info.metadata_schema_version);
+
+    if let Some(store) = info.store.as_ref() {
+        println!();
+        println!("store path: {}", store.path);
+        if !store.exists {
+            println!("store does not exist, it will be created when opened.");
+            return;
+        }
+        println!("store index schema version: {}",
+                 store.index_schema_version.map(|v| v.to_string())
+                                           .unwrap_or_else(|| "no index".to_string()));
+        match (store.can_open, store.problem.as_ref()) {
+            (false, Some(problem)) => println!("can open store: no. {problem}"),
+            (false, None) => println!("can open store: no"),
+            (true, _) if store.migrates_index =>
+                println!("can open store: yes, after migrating its index to schema version {}",
+                         store.supported_index_schema_version),
+            (true, _) => println!("can open store: yes"),
+        }
+    }
+}
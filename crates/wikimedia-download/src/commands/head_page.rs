@@ -0,0 +1,88 @@
+use crate::args::{CommonArgs, JsonOutputArg, OpenSpecArgs};
+use serde::Serialize;
+use wikimedia::{dump, Result};
+
+/// Print a summary of the first pages of a dump file or job without importing them, e.g. to
+/// check a file before starting a long import.
+///
+/// Prints each page's ID, namespace, title, text size, and count of categories. Prints the
+/// first 10 pages unless `--limit` is set. To check pages deep into a file, skip pages with
+/// `--skip`, or with `--job-file` jump to a stream of a multistream file with `--seek`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    open_spec: OpenSpecArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// The count of pages to read and skip before printing pages.
+    #[arg(long, default_value_t = 0)]
+    skip: u64,
+}
+
+/// The count of pages printed if `--limit` is not set.
+const DEFAULT_LIMIT: u64 = 10;
+
+#[derive(Serialize)]
+struct PageSummary {
+    mediawiki_id: u64,
+    ns_id: i64,
+    title: String,
+
+    /// The length of the page's revision text in bytes, `None` if it has no text.
+    text_bytes: Option<u64>,
+    categories_len: u64,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(mut args: Args) -> Result<()> {
+    let limit = args.open_spec.limit.take().unwrap_or(DEFAULT_LIMIT);
+    let job_files = args.open_spec.try_into_open_spec(&args.common.dumps_path())?
+                        .open()?;
+
+    let mut pages = Vec::<PageSummary>::new();
+    for page in job_files.open_pages_iter()?
+                         .skip(usize::try_from(args.skip).expect("usize from u64"))
+                         .take(usize::try_from(limit).expect("usize from u64")) {
+        pages.push(PageSummary::from(&page?));
+    }
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &pages)?;
+        println!();
+        return Ok(());
+    }
+
+    println!("{id:>10}  {ns:<4}  {bytes:>10}  {categories:>10}  title",
+             id = "id", ns = "ns", bytes = "text bytes", categories = "categories");
+    for page in pages.iter() {
+        println!("{id:>10}  {ns:<4}  {bytes:>10}  {categories:>10}  {title}",
+                 id = page.mediawiki_id,
+                 ns = page.ns_id,
+                 bytes = page.text_bytes.map(|bytes| bytes.to_string()).unwrap_or_default(),
+                 categories = page.categories_len,
+                 title = page.title);
+    }
+
+    Ok(())
+}
+
+impl From<&dump::Page> for PageSummary {
+    fn from(page: &dump::Page) -> PageSummary {
+        PageSummary {
+            mediawiki_id: page.id,
+            ns_id: page.ns_id,
+            title: page.title.clone(),
+            text_bytes: page.revision_text()
+                            .map(|text| u64::try_from(text.len()).expect("u64 from usize")),
+            categories_len: page.revision.as_ref()
+                                .map(|rev| u64::try_from(rev.categories.len())
+                                               .expect("u64 from usize"))
+                                .unwrap_or(0),
+        }
+    }
+}
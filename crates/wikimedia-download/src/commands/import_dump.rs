@@ -1,5 +1,8 @@
-use crate::args::{CommonArgs, OpenSpecArgs};
-use wikimedia::Result;
+use crate::args::{CommonArgs, FtsTokenizerArgs, IndexLayoutArgs, NamespaceFilterArgs,
+                  OpenSpecArgs, QuotaArgs, ScheduleArgs};
+use std::time::Duration as StdDuration;
+use wikimedia::{bail_kind, Result};
+use wikimedia_store::{ChunkSizeStrategy, index};
 
 /// Import pages from an article dump into our store.
 #[derive(clap::Args, Clone, Debug)]
@@ -11,8 +14,89 @@ pub struct Args {
     #[arg(long, default_value_t = false)]
     clear: bool,
 
+    /// Continue a previous import: skip files that were completely imported, and continue
+    /// partially imported files after the last page committed to the store.
+    #[arg(id = "continue", long = "continue", default_value_t = false, conflicts_with = "clear")]
+    continue_import: bool,
+
     #[clap(flatten)]
     open_spec: OpenSpecArgs,
+
+    /// The full text search tokenizer options, used when the store's index is created or
+    /// cleared. Use `wmd build-fts` to change the tokenizer of an existing store.
+    #[clap(flatten)]
+    fts: FtsTokenizerArgs,
+
+    #[clap(flatten)]
+    index_layout: IndexLayoutArgs,
+
+    #[clap(flatten)]
+    schedule: ScheduleArgs,
+
+    #[clap(flatten)]
+    quota: QuotaArgs,
+
+    #[clap(flatten)]
+    namespaces: NamespaceFilterArgs,
+
+    /// The maximum length in bytes of each chunk file written. Each chunk's pages are
+    /// committed to the index in one transaction, so this also sets the index transaction
+    /// size. Defaults to 10 MB.
+    #[arg(long)]
+    max_chunk_len: Option<u64>,
+
+    /// The maximum number of pages in each chunk file written. Useful for stores of many
+    /// small pages, e.g. without page text. Defaults to 100,000.
+    #[arg(long)]
+    max_pages_per_chunk: Option<u64>,
+
+    /// Which limits make a chunk full. Defaults to `first` if `--max-pages-per-chunk` is
+    /// set, otherwise `bytes`.
+    #[arg(long, value_enum)]
+    chunk_size_strategy: Option<ChunkSizeStrategyArg>,
+
+    /// The number of rows inserted into the index by each insert statement, or the initial
+    /// number with `--index-batch-adaptive`.
+    #[arg(long, default_value_t = index::VALUES_PER_BATCH_DEFAULT)]
+    index_batch_values: usize,
+
+    /// Adjust the rows per index insert statement after each chunk, based on how long
+    /// the index commit took and whether the database was busy.
+    ///
+    /// The values chosen are logged for each chunk at debug level, for tuning
+    /// `--index-batch-values`.
+    #[arg(long, default_value_t = false)]
+    index_batch_adaptive: bool,
+
+    /// The index commit latency in milliseconds to aim for with `--index-batch-adaptive`.
+    #[arg(long, default_value_t = 500)]
+    index_commit_target_ms: u64,
+
+    /// Choose an output type for the import's result.
+    #[arg(long, value_enum, default_value_t = OutputType::None)]
+    out: OutputType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum ChunkSizeStrategyArg {
+    /// Full at `--max-chunk-len` bytes.
+    Bytes,
+
+    /// Full at `--max-pages-per-chunk` pages.
+    Pages,
+
+    /// Full at whichever limit is reached first.
+    First,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum OutputType {
+    /// Output the import's result as a JSON object, with totals and a breakdown for each
+    /// source file.
+    Json,
+
+    /// Output nothing. The import's result is logged when it is done.
+    None,
 }
 
 #[tracing::instrument(level = "trace")]
@@ -20,13 +104,55 @@ pub async fn main(args: Args) -> Result<()> {
     let job_files = args.open_spec.try_into_open_spec(&*args.common.dumps_path())?
                         .open()?;
 
-    let mut store = args.common.store_options()?.build()?;
+    let mut store_options = args.common.store_options()?;
+    if let Some(max_chunk_len) = args.max_chunk_len {
+        store_options.max_chunk_len(max_chunk_len);
+    }
+    if let Some(max_pages_per_chunk) = args.max_pages_per_chunk {
+        store_options.max_pages_per_chunk(max_pages_per_chunk);
+    }
+    if let Some(strategy) = args.chunk_size_strategy {
+        store_options.chunk_size_strategy(match strategy {
+            ChunkSizeStrategyArg::Bytes => ChunkSizeStrategy::Bytes,
+            ChunkSizeStrategyArg::Pages => ChunkSizeStrategy::Pages,
+            ChunkSizeStrategyArg::First => ChunkSizeStrategy::First,
+        });
+    }
+    if let Some(tokenizer) = args.fts.tokenizer()? {
+        store_options.fts_tokenizer(tokenizer);
+    }
+    args.index_layout.apply(&mut store_options);
+    store_options.index_batch(index::BatchOptions {
+        max_values_per_batch: args.index_batch_values,
+        adaptive: args.index_batch_adaptive,
+        target_commit_latency: StdDuration::from_millis(args.index_commit_target_ms),
+        ..index::BatchOptions::default()
+    });
+    args.schedule.apply(&mut store_options);
+    args.quota.apply(&mut store_options);
+    args.namespaces.apply(&mut store_options);
+    store_options.cancellation(crate::cancellation()?);
+    let mut store = store_options.build()?;
 
     if args.clear {
         store.clear()?;
     }
 
-    store.import(job_files)?;
+    let res = store.import(job_files, args.continue_import)?;
+
+    match args.out {
+        OutputType::Json => {
+            serde_json::to_writer_pretty(&std::io::stdout(), &res)?;
+            println!();
+        },
+        OutputType::None => (),
+    }
+
+    if res.cancelled {
+        bail_kind!(Cancelled, "Import cancelled after {chunks_len} chunks, run it again with \
+                               `--continue` to import the rest",
+                   chunks_len = res.chunks_len);
+    }
 
     Ok(())
 }
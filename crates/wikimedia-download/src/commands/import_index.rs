@@ -0,0 +1,35 @@
+use crate::args::CommonArgs;
+use std::path::PathBuf;
+use wikimedia::{bail_kind, Result};
+
+/// Replace the store's index with an index file written by `wmd export-index`.
+///
+/// Copy the exported store's chunk files into this store's chunks directory first. The
+/// store must not be in use by another process, e.g. `wmd web`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The index file to import.
+    #[arg(long)]
+    file: PathBuf,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store = args.common.store_options()?.build()?;
+
+    let missing_chunks = store.import_index(&args.file)?;
+    if let Some(first) = missing_chunks.first() {
+        bail_kind!(Verification,
+                   "The imported index refers to {len} chunks that are not in the store, \
+                    starting with chunk ID {first}. Copy the chunk files from the exported \
+                    store.",
+                   len = missing_chunks.len());
+    }
+
+    tracing::info!(path = %args.file.display(), "Imported store index");
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::{dump::{CategorySlug, Namespace}, format_err_kind, Result};
+use wikimedia_store::index::{IndexQuery, PageSearchOptions};
+
+/// Show how the store's index runs a query, for diagnosing slow queries.
+///
+/// Prints the query's SQL and parameters, sqlite's query plan, and how long the query took
+/// to run. To log slow queries made by other commands, e.g. the web server, use
+/// `--slow-query-ms`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// The query to explain.
+    #[arg(value_enum)]
+    query: QueryType,
+
+    /// The query's argument: the category slug for `category-pages`, the namespace key or
+    /// name for `namespace-pages`, the page slug for `page-by-slug`, the search query for
+    /// `page-search`, or the optional slug lower bound for `categories`.
+    value: Option<String>,
+
+    /// The maximum number of results the query returns.
+    ///
+    /// Defaults to and is capped at the store's maximum query limit.
+    #[arg(long)]
+    limit: Option<u64>,
+
+    /// For `page-search`, only search pages in the category with this slug.
+    #[arg(long)]
+    category: Option<CategorySlug>,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum QueryType {
+    /// List categories, as `wmd get-category`.
+    Categories,
+
+    /// List the pages in a category, as `wmd get-category --members`.
+    CategoryPages,
+
+    /// List the pages in a namespace.
+    NamespacePages,
+
+    /// Get a page by slug, as `wmd get-store-page --slug`.
+    PageBySlug,
+
+    /// Full text search of page titles, as the web interface's search.
+    PageSearch,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?.build()?;
+
+    let value = || args.value.clone().ok_or_else(
+        || format_err_kind!(User, "This query requires a value argument."));
+
+    let query = match args.query {
+        QueryType::Categories => IndexQuery::Categories {
+            slug_lower_bound: args.value.as_deref().map(str::parse).transpose()?,
+            limit: args.limit.into(),
+        },
+        QueryType::CategoryPages => IndexQuery::CategoryPages {
+            slug: value()?.parse()?,
+            page_mediawiki_id_lower_bound: None,
+            namespace: None,
+            limit: args.limit.into(),
+        },
+        QueryType::NamespacePages => IndexQuery::NamespacePages {
+            ns_id: value()?.parse::<Namespace>()?.key(),
+            slug_lower_bound: None,
+            limit: args.limit.into(),
+        },
+        QueryType::PageBySlug => IndexQuery::PageBySlug {
+            slug: value()?,
+        },
+        QueryType::PageSearch => IndexQuery::PageSearch {
+            query: value()?,
+            options: PageSearchOptions {
+                limit: args.limit.into(),
+                category: args.category.clone(),
+                ..PageSearchOptions::default()
+            },
+        },
+    };
+
+    let explanation = store.explain_index_query(&query)?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &explanation)?;
+        println!();
+    } else {
+        println!("query:    {}", explanation.name);
+        println!("sql:      {}", explanation.sql);
+        println!("params:   {}", explanation.params.join(", "));
+        println!("rows:     {}", explanation.rows_len);
+        println!("duration: {:.3} ms", explanation.duration_ms);
+        println!("plan:");
+        for line in explanation.plan.iter() {
+            println!("  {line}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use crate::args::{CommonArgs, DumpNameArg, JsonOutputArg, VersionSpecArg};
+use serde::Serialize;
+use wikimedia::{
+    dump::{self, JobName, Status, WELL_KNOWN_JOBS},
+    Result,
+};
+
+/// List the jobs in a dump version with their status and a description of the files each
+/// writes.
+///
+/// Jobs not known to this program are listed without a description.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    dump_name: DumpNameArg,
+
+    #[clap(flatten)]
+    version: VersionSpecArg,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// List the jobs known to this program instead of the jobs in a dump version. Does not
+    /// make any requests.
+    #[arg(long, default_value_t = false)]
+    well_known: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct JobListing {
+    name: String,
+
+    /// `None` when listing well-known jobs.
+    status: Option<Status>,
+
+    description: Option<&'static str>,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let jobs: Vec<JobListing> = if args.well_known {
+        WELL_KNOWN_JOBS.iter()
+                       .map(|(name, description)| JobListing {
+                           name: name.to_string(),
+                           status: None,
+                           description: Some(description),
+                       })
+                       .collect()
+    } else {
+        let client = args.common.metadata_client()?;
+        let (_version, version_status) =
+            dump::download::get_dump_version_status(&client, &args.dump_name.value,
+                                                    &args.version.value).await?;
+        version_status.jobs.iter()
+                      .map(|(name, job_status)| JobListing {
+                          name: name.clone(),
+                          status: Some(job_status.status),
+                          description: JobName(name.clone()).description(),
+                      })
+                      .collect()
+    };
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &jobs)?;
+        println!();
+        return Ok(());
+    }
+
+    let name_width = jobs.iter().map(|job| job.name.len()).max().unwrap_or(0);
+    for job in jobs.iter() {
+        let status = job.status.map(|s| format!("{s:<12}")).unwrap_or_default();
+        println!("{name:<name_width$}  {status}{description}",
+                 name = job.name,
+                 description = job.description.unwrap_or(""));
+    }
+
+    Ok(())
+}
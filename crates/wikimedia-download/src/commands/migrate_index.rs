@@ -0,0 +1,53 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::Result;
+
+/// Apply the pending schema migrations to the store's index, including slow ones that
+/// aren't applied when the store is opened.
+///
+/// Migrations are applied in one transaction, so a web server serving the store keeps
+/// working from the old schema until they finish. Prints the migrations applied and the
+/// index's migration history.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// List the pending migrations without applying them.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let report = args.common.store_options()?.migrate_index(args.dry_run)?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+        println!();
+    } else {
+        println!("schema version: {from} (current {to})",
+                 from = report.from_version, to = report.to_version);
+        let applied = match report.dry_run {
+            true => "would apply",
+            false => "applied",
+        };
+        println!("{applied}: {}", report.migrations.len());
+        for migration in report.migrations.iter() {
+            println!("    {version:>3} {name}{slow}",
+                     version = migration.version, name = migration.name,
+                     slow = if migration.safe { "" } else { " (slow)" });
+        }
+        println!("history: {}", report.history.len());
+        for migration in report.history.iter() {
+            println!("    {version:>3} {name:<40} {applied} from version {from}",
+                     version = migration.version, name = migration.name,
+                     applied = migration.applied.to_rfc3339(), from = migration.from_version);
+        }
+        println!("duration: {}", report.duration);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,66 @@
+#[cfg(feature = "store")]
+pub mod apply_deletions;
+#[cfg(feature = "store")]
+pub mod bench_import;
+#[cfg(feature = "store")]
+pub mod build_fts;
+#[cfg(feature = "store")]
 pub mod clear_store;
 pub mod completion;
+#[cfg(feature = "store")]
+pub mod diff_pages;
+#[cfg(feature = "download")]
 pub mod download;
+#[cfg(feature = "store")]
+pub mod export_index;
+#[cfg(feature = "store")]
+pub mod export_pages;
+#[cfg(feature = "store")]
+pub mod get_category;
+#[cfg(feature = "store")]
 pub mod get_chunk;
+#[cfg(feature = "download")]
 pub mod get_dump;
 pub mod get_dump_page;
+#[cfg(feature = "download")]
 pub mod get_file_info;
+#[cfg(feature = "download")]
 pub mod get_job;
+#[cfg(feature = "store")]
 pub mod get_store_page;
+#[cfg(feature = "store")]
+pub mod get_templates;
 pub mod get_version;
+pub mod head_page;
+#[cfg(feature = "store")]
 pub mod import_dump;
+#[cfg(feature = "store")]
+pub mod import_index;
+#[cfg(feature = "store")]
+pub mod index_explain;
+#[cfg(feature = "download")]
+pub mod list_jobs;
+#[cfg(feature = "store")]
+pub mod migrate_index;
+#[cfg(feature = "store")]
+pub mod optimize_store;
+#[cfg(all(feature = "store", feature = "wikitext-render"))]
+pub mod prerender_store;
+#[cfg(feature = "web")]
+pub mod proxy;
+#[cfg(feature = "store")]
+pub mod rebuild_index;
+#[cfg(feature = "store")]
+pub mod report_redlinks;
+#[cfg(feature = "store")]
+pub mod report_slug_collisions;
+#[cfg(feature = "web")]
+pub mod serve_managed;
+#[cfg(feature = "store")]
+pub mod store_info;
+#[cfg(all(feature = "download", feature = "store"))]
+pub mod sync;
+#[cfg(feature = "store")]
+pub mod verify_store;
+#[cfg(feature = "web")]
 pub mod web;
@@ -0,0 +1,68 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::{bail_kind, Result};
+use wikimedia_store::MaintenanceOptions;
+
+/// Check and optimise the store's index, e.g. on a schedule separate from imports.
+///
+/// Runs SQLite's `integrity_check` on the index, checks every chunk in the index has a chunk
+/// file, merges the full text search index, and updates the query planner's statistics with
+/// `ANALYZE` and `PRAGMA optimize`, printing how long each step took. Add `--vacuum` to also
+/// rebuild the index file to reclaim free space. Exits with the verification error code if
+/// any problems are found.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// Rebuild the index file to reclaim free space. This needs as much free disk space as
+    /// the index takes, and blocks the web server while it runs.
+    #[arg(long, default_value_t = false)]
+    vacuum: bool,
+
+    /// Skip `integrity_check`, which reads the whole index.
+    #[arg(long, default_value_t = false)]
+    no_integrity_check: bool,
+
+    /// Skip checking every chunk in the index has a chunk file.
+    #[arg(long, default_value_t = false)]
+    no_check_chunks: bool,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store = args.common.store_options()?.build()?;
+
+    let report = store.maintain(&MaintenanceOptions {
+        integrity_check: !args.no_integrity_check,
+        check_chunks: !args.no_check_chunks,
+        vacuum: args.vacuum,
+        ..MaintenanceOptions::default()
+    })?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+        println!();
+    } else {
+        for step in report.steps.iter() {
+            println!("{name:<16} {duration}", name = step.name, duration = step.duration);
+        }
+        println!("integrity errors: {}", report.integrity_errors.len());
+        for error in report.integrity_errors.iter() {
+            println!("    {error}");
+        }
+        println!("missing chunks: {}", report.missing_chunks.len());
+        for chunk_id in report.missing_chunks.iter() {
+            println!("    {chunk_id}");
+        }
+    }
+
+    if !report.is_ok() {
+        bail_kind!(Verification, "Store maintenance found problems. Run `wmd verify-store` \
+                                  for details of missing chunks.");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::{
+    dump,
+    Result,
+    wikitext::{self, RenderLimits},
+};
+
+/// Render the store's pages to HTML and store the HTML with them, so `wmd web` serves it
+/// without rendering each page's wikitext on every request.
+///
+/// Pages that already have HTML are skipped unless `--rerender` is given, so run this again
+/// after each import to render the pages imported. Pages that fail to render or exceed the
+/// render limits are left without HTML and rendered by `wmd web` when viewed.
+///
+/// Press Ctrl-C to stop; run the command again to render the rest.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    /// Render pages that already have HTML again, e.g. after upgrading pandoc.
+    #[arg(long, default_value_t = false)]
+    rerender: bool,
+
+    /// Only render pages in this namespace, given by key or name, e.g. `0` or `Main` for
+    /// articles. Repeat it to render several namespaces. Every namespace is rendered by
+    /// default.
+    #[arg(long = "namespace", value_name = "NAMESPACE")]
+    namespaces: Vec<dump::Namespace>,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store = args.common.store_options()?
+                               .cancellation(crate::cancellation()?)
+                               .build()?;
+
+    let dump_name = args.common.store_dump_name();
+    let out_dir = args.common.out_dir();
+    let site = store.site();
+    let ns_ids = args.namespaces.iter().map(dump::Namespace::key).collect::<Vec<i64>>();
+    let limits = RenderLimits::default();
+    let runtime = tokio::runtime::Handle::current();
+
+    let render = move |page: &dump::Page| -> Result<Option<String>> {
+        if !ns_ids.is_empty() && !ns_ids.contains(&page.ns_id) {
+            return Ok(None);
+        }
+
+        let rendered = runtime.block_on(
+            wikitext::convert_page_to_html(page, &dump_name, site.as_ref(), &out_dir, &limits));
+        match rendered {
+            Ok(rendered) => match rendered.limit_exceeded {
+                Some(limit_exceeded) => {
+                    tracing::warn!(page.id, page.title, %limit_exceeded,
+                                   "Page exceeded render limits, not pre-rendered");
+                    Ok(None)
+                },
+                None => Ok(Some(rendered.html)),
+            },
+            Err(e) => {
+                tracing::warn!(page.id, page.title, err = %e,
+                               "Error rendering page, not pre-rendered");
+                Ok(None)
+            },
+        }
+    };
+
+    let rerender = args.rerender;
+    let report = tokio::task::spawn_blocking(move || store.prerender(rerender, render))
+                     .await??;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+        println!();
+    } else {
+        println!("chunks read:      {}", report.chunks_read);
+        println!("chunks written:   {}", report.chunks_written);
+        println!("chunks removed:   {}", report.chunks_removed);
+        println!("pages rendered:   {}", report.pages_rendered);
+        println!("already rendered: {}", report.pages_already_rendered);
+        println!("not rendered:     {}", report.pages_not_rendered);
+        println!("duration:         {}", report.duration);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use crate::commands::web;
+use std::{
+    sync::Arc,
+    time::Duration as StdDuration,
+};
+use wikimedia::{
+    cancel,
+    format_err_kind,
+    live,
+    Result,
+};
+use wikimedia_web::{proxy::{self, LiveProxy}, WebState};
+
+/// Run the web server, serving pages missing from the store from the live wiki.
+///
+/// Pages in the store are served from it. Other pages are fetched from the live wiki's REST
+/// API, at most `--live-fetches-per-sec` a second, and kept in memory for `--live-ttl-mins`.
+/// With `--save-pages` fetched pages are also written to the store in batches, so the store
+/// fills up as pages are read, even before a dump is imported. Saved pages are served from
+/// the store from then on, and not fetched again after `--live-ttl-mins`.
+///
+/// With `--save-pages`, press Ctrl-C to save the pages still queued and stop.
+#[derive(clap::Args, Clone, Debug)]
+// The flattened `web::Args` already has the argument group ID `Args`.
+#[group(skip)]
+pub struct Args {
+    #[clap(flatten)]
+    web: web::Args,
+
+    /// The URL of the live wiki's REST API, e.g. `https://en.wikipedia.org/w/rest.php`.
+    ///
+    /// If not present the REST API of the store's site is used.
+    #[arg(long)]
+    live_api_url: Option<String>,
+
+    /// Minutes to keep a page fetched from the live wiki before fetching it again.
+    #[arg(long, default_value_t = 24 * 60)]
+    live_ttl_mins: u64,
+
+    /// The most pages fetched from the live wiki to keep in memory.
+    #[arg(long, default_value_t = proxy::CACHE_LEN_DEFAULT)]
+    live_cache_len: usize,
+
+    /// The most pages to fetch from the live wiki per second. Requests over the limit wait.
+    #[arg(long, default_value_t = proxy::FETCHES_PER_SEC_DEFAULT)]
+    live_fetches_per_sec: u32,
+
+    /// Write pages fetched from the live wiki to the store, in batches of up to 100 pages at
+    /// least once a minute.
+    #[arg(long, default_value_t = false)]
+    save_pages: bool,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut state = args.web.web_state()?;
+    let dump_name = state.default_dump_name();
+    let http_options = args.web.common.http_options()?.build()?;
+
+    let client = match args.live_api_url.as_ref() {
+        Some(api_url) => live::Client::new(api_url, &http_options)?,
+        None => {
+            let site = state.site(&dump_name.0)?.ok_or_else(|| format_err_kind!(User,
+                "The store's site is not known, set the live wiki's REST API URL with \
+                 `--live-api-url`."))?;
+            live::Client::for_site(&site, &http_options)?
+        },
+    };
+
+    let mut live_proxy = LiveProxy::new(client, StdDuration::from_secs(args.live_ttl_mins * 60));
+    live_proxy.set_cache_len(args.live_cache_len)
+              .set_save_pages(args.save_pages)
+              .set_fetches_per_sec(args.live_fetches_per_sec)?;
+    state.set_live_proxy(&dump_name.0, live_proxy)?;
+    let state = Arc::new(state);
+
+    if !args.save_pages {
+        return web::serve(&args.web, state).await;
+    }
+
+    let cancellation = crate::cancellation()?;
+    tokio::select! {
+        res = web::serve(&args.web, state.clone()) => res,
+        res = save_loop(&state, &dump_name.0) => res,
+        () = cancellation.cancelled() => {
+            save_live_pages(&state, &dump_name.0).await;
+            cancel::check(&cancellation, "Serving pages")
+        },
+    }
+}
+
+/// Save the pages the live proxy queued every `proxy::SAVE_INTERVAL`.
+async fn save_loop(state: &Arc<WebState>, dump_name: &str) -> Result<()> {
+    loop {
+        tokio::time::sleep(proxy::SAVE_INTERVAL).await;
+        save_live_pages(state, dump_name).await;
+    }
+}
+
+async fn save_live_pages(state: &Arc<WebState>, dump_name: &str) {
+    let state = state.clone();
+    let dump_name = dump_name.to_string();
+    let res = tokio::task::spawn_blocking(move || state.save_live_pages(&dump_name)).await;
+    match res {
+        Ok(Ok(_pages_len)) => (),
+        Ok(Err(e)) => tracing::warn!(err = format!("{e:#}"), "Error saving live pages to store"),
+        Err(e) => tracing::warn!(err = %e, "Error saving live pages to store"),
+    }
+}
@@ -0,0 +1,43 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::Result;
+
+/// Rebuild the store's index from its chunk files, e.g. after the index file is lost or
+/// corrupted, without importing the dump again.
+///
+/// Clears the index, then indexes the newest copy of every page in the chunk files, skipping
+/// pages recorded as deleted in the store's default deletion audit log. Saved searches and
+/// import history are kept if the index still has them. If the index is too corrupted to
+/// open, delete `index.db` in the store's index directory first.
+///
+/// Press Ctrl-C to stop; run the command again to finish the index.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store = args.common.store_options()?
+                               .cancellation(crate::cancellation()?)
+                               .build()?;
+
+    let report = store.rebuild_index()?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+        println!();
+    } else {
+        println!("chunks:           {}", report.chunks_len);
+        println!("pages indexed:    {}", report.pages_indexed);
+        println!("superseded:       {}", report.pages_superseded);
+        println!("deleted:          {}", report.pages_deleted);
+        println!("chunks removed:   {}", report.chunks_removed);
+        println!("duration:         {}", report.duration);
+    }
+
+    Ok(())
+}
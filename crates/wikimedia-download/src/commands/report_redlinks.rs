@@ -0,0 +1,55 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::Result;
+
+/// List the titles most linked to by articles in the store that aren't in the store (red
+/// links), e.g. to decide which dumps to import next.
+///
+/// Titles are compared ignoring case, as in page lookups by title. Only links between
+/// articles are indexed, so links to other namespaces are not reported.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// Print the red links as CSV with a header row.
+    #[arg(long, default_value_t = false, conflicts_with = "json")]
+    csv: bool,
+
+    /// The maximum count of titles to list.
+    #[arg(long, default_value_t = 100)]
+    limit: u64,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?.build()?;
+
+    let red_links = store.red_links(args.limit)?;
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &red_links)?;
+        println!();
+        return Ok(());
+    }
+
+    if args.csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        for red_link in red_links.iter() {
+            writer.serialize(red_link)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    println!("red links: {}", red_links.len());
+    for red_link in red_links.iter() {
+        println!("  {links:>8}  {slug}",
+                 links = red_link.links_len,
+                 slug = red_link.slug);
+    }
+
+    Ok(())
+}
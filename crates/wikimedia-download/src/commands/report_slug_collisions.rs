@@ -0,0 +1,58 @@
+use crate::args::{CommonArgs, JsonOutputArg};
+use wikimedia::Result;
+
+/// List the groups of pages in the store whose slugs are equal ignoring case.
+///
+/// Looking up a slug in a group finds the page with exactly that slug if there is one, but
+/// otherwise finds nothing, e.g. for a slug typed in a different case, unless a
+/// disambiguation was written for the group with `--write-disambiguations`. The page chosen
+/// for each group is marked with `*`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// Choose a page for lookups of each group's slugs, replacing any chosen before. An
+    /// article is chosen over a redirect, then the page with the most text. Takes the
+    /// store's write lock.
+    #[arg(long, default_value_t = false)]
+    write_disambiguations: bool,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store = args.common.store_options()?.build()?;
+
+    let collisions = if args.write_disambiguations {
+        store.write_slug_disambiguations()?
+    } else {
+        store.slug_collisions()?
+    };
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &collisions)?;
+        println!();
+        return Ok(());
+    }
+
+    println!("slug collisions: {}", collisions.len());
+    for collision in collisions.iter() {
+        println!();
+        for page in collision.pages.iter() {
+            let chosen = if collision.disambiguation == Some(page.mediawiki_id) { "*" } else { " " };
+            let redirect = match page.redirect_slug.as_ref() {
+                Some(target) => format!(" -> {target}"),
+                None => String::new(),
+            };
+            println!("  {chosen} {id:>10}  ns {ns:<4} {slug}{redirect}",
+                     id = page.mediawiki_id,
+                     ns = page.ns_id,
+                     slug = page.slug);
+        }
+    }
+
+    Ok(())
+}
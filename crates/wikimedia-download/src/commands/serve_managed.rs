@@ -0,0 +1,156 @@
+use crate::{
+    args::{FileNameRegexArg, JobNameArg},
+    commands::web,
+};
+use std::{
+    sync::Arc,
+    time::Duration as StdDuration,
+};
+use wikimedia::{
+    bail_kind,
+    dump::{self, download::Downloader, source::WikimediaSource, Status, Version, VersionSpec},
+    Result,
+};
+use wikimedia_store::{self as store, generation::Generations};
+use wikimedia_web::WebState;
+
+/// Run the web server and keep its store up to date with the latest dump version.
+///
+/// A background task periodically checks for a new version of the store's dump. When the
+/// job is done, its files are downloaded and imported into a new generation of the store,
+/// then the web server switches to the new generation. The previous generation is removed
+/// after a grace period. An import interrupted by a restart is continued on the next check.
+///
+/// Downloaded dump files are kept under the output directory.
+#[derive(clap::Args, Clone, Debug)]
+// The flattened `web::Args` already has the argument group ID `Args`.
+#[group(skip)]
+pub struct Args {
+    #[clap(flatten)]
+    web: web::Args,
+
+    #[clap(flatten)]
+    job_name: JobNameArg,
+
+    #[clap(flatten)]
+    file_name_regex: FileNameRegexArg,
+
+    /// Specify the URL of a mirror to download job files from. Only supports http: and https: URLs.
+    ///
+    /// If not present tries to read the environment variable `WMD_MIRROR_URL`.
+    ///
+    /// Example: <https://dumps.wikimedia.org>
+    #[arg(long, env = "WMD_MIRROR_URL")]
+    mirror_url: String,
+
+    /// Minutes between checks for a new dump version.
+    #[arg(long, default_value_t = 60)]
+    check_interval_mins: u64,
+
+    /// Minutes to keep a previous store generation after switching to a new one, for other
+    /// processes still reading it. It is removed at the first check after this.
+    #[arg(long, default_value_t = 10)]
+    generation_grace_mins: u64,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let common = &args.web.common;
+    if common.has_store_location_args() || args.web.serves_store_list() {
+        bail_kind!(User,
+                   "The store's chunks, index, and remote URL are chosen by each store \
+                    generation, so they and `--store` can't be set for serve-managed.");
+    }
+
+    let state = Arc::new(args.web.web_state()?);
+
+    // One downloader for all checks, so its HTTP connections are reused.
+    let source = Arc::new(WikimediaSource {
+        mirror_url: args.mirror_url.clone(),
+    });
+    let downloader = Downloader::new(
+        dump::download::OptionsBuilder::default()
+            .http_options(common.http_options()?.build()?)
+            .metadata_cache_options(common.metadata_cache_options()?)
+            .source(source)
+            .out_dir(common.dumps_path())
+            .events(state.events().clone())
+            .build()?)?;
+
+    tokio::select! {
+        res = web::serve(&args.web, state.clone()) => res,
+        res = update_loop(&args, &state, &downloader) => res,
+    }
+}
+
+async fn update_loop(args: &Args, state: &WebState, downloader: &Downloader) -> Result<()> {
+    let interval = StdDuration::from_secs(args.check_interval_mins * 60);
+
+    loop {
+        match update(args, state, downloader).await {
+            Ok(Some(version)) => tracing::info!(version = version.0, "Store updated"),
+            Ok(None) => tracing::debug!("Store is up to date"),
+            Err(e) => tracing::error!(err = format!("{e:#}"),
+                                      "Error updating store, will try again at the next check"),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Download and import the latest version of the store's dump if it is newer than the
+/// current generation, then switch the web server to it.
+///
+/// Returns the new version, or `None` if the store was already up to date.
+async fn update(args: &Args, state: &WebState, downloader: &Downloader
+) -> Result<Option<Version>> {
+    let common = &args.web.common;
+    let dump_name = common.store_dump_name();
+    let job_name = &args.job_name.value;
+    let file_name_regex = args.file_name_regex.value.as_ref();
+    let generations = Generations::new(&common.store_path());
+    generations.remove_retired(StdDuration::from_secs(args.generation_grace_mins * 60))?;
+
+    let file_infos = downloader.get_file_infos(&dump_name, &VersionSpec::Latest, job_name,
+                                               file_name_regex,
+                                               /* allow_incomplete_job: */ true).await?;
+    let version = file_infos.version;
+
+    if generations.current()?.as_deref() == Some(&*version.0) {
+        return Ok(None);
+    }
+    if file_infos.job_status != Status::Done {
+        tracing::info!(version = version.0, job_status = %file_infos.job_status,
+                       "Latest dump version's job is not done yet");
+        return Ok(None);
+    }
+
+    tracing::info!(version = version.0, "Downloading new dump version");
+    downloader.download_job(&dump_name, &VersionSpec::Version(version.clone()), job_name,
+                            file_name_regex).await?;
+
+    let mut open_spec = dump::local::OpenSpec::builder();
+    open_spec.dumps_dir(common.dumps_path())
+             .job(dump_name.clone(), version.clone(), job_name.clone())
+             .compression_auto();
+    if let Some(file_name_regex) = file_name_regex {
+        open_spec.file_name_regex(file_name_regex.clone());
+    }
+    let job_files = open_spec.build()?.open()?;
+
+    tracing::info!(version = version.0, "Importing new dump version");
+    let generation_path = generations.path(&version.0)?;
+    let mut store_options = store::Options::default();
+    store_options.dump_name(dump_name)
+                 .path(generation_path.clone())
+                 .events(state.events().clone());
+    // Importing blocks for a long time, so run it on a thread for blocking work.
+    tokio::task::spawn_blocking(move || {
+        let mut store = store_options.build()?;
+        store.import(job_files, /* continue_import: */ true)
+    }).await??;
+
+    state.publish_store_generation(&generation_path)?;
+
+    Ok(Some(version))
+}
@@ -0,0 +1,23 @@
+use crate::args::CommonArgs;
+use wikimedia::Result;
+
+/// Get a summary of the pages store as JSON, in a stable schema for scripts.
+///
+/// See `wikimedia_store::metadata::StoreInfo` for the fields.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let store = args.common.store_options()?.build()?;
+
+    let store_info = store.store_info()?;
+
+    serde_json::to_writer_pretty(&std::io::stdout(), &store_info)?;
+    println!();
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use crate::args::{CommonArgs, DumpNameArg, DumpSourceArgs, FileNameRegexArg, FtsTokenizerArgs,
+                  IndexLayoutArgs, JobNameArg, NamespaceFilterArgs, QuotaArgs, ScheduleArgs,
+                  SharedDirArgs, VersionSpecArg};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+use wikimedia::{
+    bail_kind,
+    dump::{self, local::OpenSpec},
+    format_err_kind,
+    Result,
+};
+use valuable::Valuable;
+use wikimedia_store::ImportResult;
+
+/// Download a dump job's files and import them into our store, importing each file while the
+/// next one downloads.
+///
+/// Files are imported as with `wmd import-dump --continue`, so running this again after it
+/// stops skips the files that were completely imported. Only XML files are imported, e.g. a
+/// multistream job's index files are downloaded but not imported.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    dump_name: DumpNameArg,
+
+    #[clap(flatten)]
+    version_spec: VersionSpecArg,
+
+    #[clap(flatten)]
+    job_name: JobNameArg,
+
+    #[clap(flatten)]
+    file_name_regex: FileNameRegexArg,
+
+    #[clap(flatten)]
+    source: DumpSourceArgs,
+
+    #[clap(flatten)]
+    shared_dirs: SharedDirArgs,
+
+    /// Download and import the files that are done from a job that is not done yet.
+    ///
+    /// By default only jobs with status `done` are downloaded.
+    #[arg(long, default_value_t = false)]
+    allow_incomplete_job: bool,
+
+    /// Keep the temporary directory where files are initially downloaded. By default this is deleted after use.
+    #[arg(long, default_value_t = false)]
+    keep_temp_dir: bool,
+
+    /// The full text search tokenizer options, used when the store's index is created.
+    #[clap(flatten)]
+    fts: FtsTokenizerArgs,
+
+    #[clap(flatten)]
+    index_layout: IndexLayoutArgs,
+
+    #[clap(flatten)]
+    schedule: ScheduleArgs,
+
+    #[clap(flatten)]
+    quota: QuotaArgs,
+
+    #[clap(flatten)]
+    namespaces: NamespaceFilterArgs,
+
+    /// Print the result of importing each file to stdout as JSON.
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut download_options = dump::download::OptionsBuilder::default();
+    download_options
+        .http_options(args.common.http_options()?.build()?)
+        .allow_incomplete_job(args.allow_incomplete_job)
+        .metadata_cache_options(args.common.metadata_cache_options()?)
+        .keep_temp_dir(args.keep_temp_dir)
+        .source(args.source.source())
+        .out_dir(args.common.dumps_path());
+    args.shared_dirs.apply(&mut download_options);
+    download_options.cancellation(crate::cancellation()?);
+    let download_options = download_options.build()?;
+
+    let mut store_options = args.common.store_options()?;
+    if let Some(tokenizer) = args.fts.tokenizer()? {
+        store_options.fts_tokenizer(tokenizer);
+    }
+    args.index_layout.apply(&mut store_options);
+    args.schedule.apply(&mut store_options);
+    args.quota.apply(&mut store_options);
+    args.namespaces.apply(&mut store_options);
+    store_options.cancellation(crate::cancellation()?);
+    let mut store = store_options.build()?;
+
+    // Import on a blocking thread, fed downloaded files over a channel.
+    let (file_tx, file_rx) = mpsc::channel::<PathBuf>();
+    let importer = tokio::task::spawn_blocking(move || -> Result<Vec<ImportResult>> {
+        let mut results = Vec::new();
+        for path in file_rx {
+            let job_files = OpenSpec::builder().file(path)
+                                               .compression_auto()
+                                               .build()?
+                                               .open()?;
+            let res = store.import(job_files, /* continue_import: */ true)?;
+            if res.cancelled {
+                bail_kind!(Cancelled, "Sync cancelled, run it again to continue");
+            }
+            results.push(res);
+        }
+        Ok(results)
+    });
+
+    let download_res = dump::download::download_job_with(
+        &args.dump_name.value,
+        &args.version_spec.value,
+        &args.job_name.value,
+        args.file_name_regex.value.as_ref(),
+        &download_options,
+        |path: &Path| -> Result<()> {
+            if !is_xml_file(path) {
+                tracing::debug!(path = %path.display(), "Not importing non-XML job file");
+                return Ok(());
+            }
+            file_tx.send(path.to_path_buf())
+                   .map_err(|_| format_err_kind!(Internal, "The import thread has stopped"))
+        }).await;
+    drop(file_tx);
+
+    // The importer's error explains why a send to it failed, so return that first.
+    let import_results = importer.await??;
+    let download_res = download_res?;
+
+    tracing::info!(files_imported = import_results.len(),
+                   pages_total = import_results.iter().map(|res| res.pages_total).sum::<u64>(),
+                   download_ok = download_res.download_ok,
+                   existing_ok = download_res.existing_ok,
+                   duration = download_res.duration.as_value(),
+                   "Sync complete");
+
+    if args.json {
+        serde_json::to_writer_pretty(&std::io::stdout(), &import_results)?;
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Whether `path` names an XML file, optionally compressed, e.g. `pages-articles1.xml.bz2`.
+fn is_xml_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    name.ends_with(".xml") || name.rsplit_once('.').is_some_and(|(stem, _)| stem.ends_with(".xml"))
+}
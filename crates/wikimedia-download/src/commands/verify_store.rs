@@ -0,0 +1,109 @@
+use crate::args::{CommonArgs, JsonOutputArg, ScheduleArgs};
+use serde::Serialize;
+use wikimedia::{bail_kind, Result};
+use wikimedia_store::{ChunkVerifyReport, FsckReport, index::IndexOrphans};
+
+/// Check that the store's index is consistent with itself and the chunk files.
+///
+/// Reports chunks in the index without chunk files, and index rows that refer to missing
+/// pages or chunks, which can be left when an import fails part way through. Exits with the
+/// verification error code if any are found, unless they are deleted with
+/// `--delete-orphans`.
+///
+/// With `--chunks`, also reads every page of every chunk in parallel, limited by
+/// `--threads` and `--io-concurrency`.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(flatten)]
+    json: JsonOutputArg,
+
+    /// Delete the pages in missing chunks and all orphan rows from the index. Takes the
+    /// store's write lock.
+    #[arg(long, default_value_t = false)]
+    delete_orphans: bool,
+
+    /// Read every page in every chunk file to check the chunks can be read and hold the
+    /// pages the index refers to. This reads the whole store.
+    #[arg(long, default_value_t = false)]
+    chunks: bool,
+
+    #[clap(flatten)]
+    schedule: ScheduleArgs,
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(flatten)]
+    index: FsckReport,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunks: Option<ChunkVerifyReport>,
+}
+
+#[tracing::instrument(level = "trace")]
+pub async fn main(args: Args) -> Result<()> {
+    let mut store_options = args.common.store_options()?;
+    args.schedule.apply(&mut store_options);
+    let mut store = store_options.build()?;
+
+    let index = store.fsck_index(args.delete_orphans)?;
+    let chunks = if args.chunks {
+        Some(store.verify_chunks()?)
+    } else {
+        None
+    };
+    let report = Report {
+        index,
+        chunks,
+    };
+
+    if args.json.value {
+        serde_json::to_writer_pretty(&std::io::stdout(), &report)?;
+        println!();
+    } else {
+        let index = &report.index;
+        println!("missing chunks: {}", index.missing_chunks.len());
+        for chunk_id in index.missing_chunks.iter() {
+            println!("    {chunk_id}");
+        }
+        println!("pages in missing chunks: {}", index.pages_in_missing_chunks);
+        print_orphans("orphan rows", &index.orphans);
+        if let Some(deleted) = index.deleted.as_ref() {
+            print_orphans("deleted rows", deleted);
+        }
+        if let Some(chunks) = report.chunks.as_ref() {
+            println!("chunks verified: {} with {} pages, {} on {} threads in {}",
+                     chunks.chunks_len, chunks.pages_len, chunks.bytes_len, chunks.threads,
+                     chunks.duration);
+            println!("chunk errors: {}", chunks.errors.len());
+            for error in chunks.errors.iter() {
+                println!("    {}: {}", error.chunk_id, error.error);
+            }
+        }
+    }
+
+    if !report.index.is_ok() && report.index.deleted.is_none() {
+        bail_kind!(Verification,
+                   "Store index is inconsistent. Run with --delete-orphans to repair it.");
+    }
+    if report.chunks.as_ref().is_some_and(|chunks| !chunks.is_ok()) {
+        bail_kind!(Verification, "Some chunks failed verification.");
+    }
+
+    Ok(())
+}
+
+fn print_orphans(heading: &str, orphans: &IndexOrphans) {
+    println!("{heading}: {}", orphans.total());
+    println!("    pages without chunk: {}", orphans.pages_without_chunk);
+    println!("    page categories:     {}", orphans.page_categories);
+    println!("    page properties:     {}", orphans.page_props);
+    println!("    page links:          {}", orphans.page_links);
+    println!("    page templates:      {}", orphans.page_templates);
+    println!("    slug disambiguations: {}", orphans.slug_disambiguations);
+    println!("    page full text:      {}", orphans.page_fts);
+    println!("    categories:          {}", orphans.categories);
+}
@@ -1,130 +1,170 @@
-use askama::Template;
 use axum::{
-    extract::{Path, Query, State},
-    headers::ContentType,
-    http::{header, status::StatusCode, uri},
-    response::{IntoResponse, Response},
-    Router,
-    routing,
+    http::{header, uri},
     Server,
-    TypedHeader,
 };
 use crate::args::CommonArgs;
-use futures::future::{self, Either};
-use serde::Deserialize;
 use std::{
-    any::Any,
-    fmt::{self, Display},
-    future::Future,
     net::SocketAddr,
-    result::Result as StdResult,
-    sync::{Arc, MutexGuard},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::Duration as StdDuration,
 };
 use tower_http::{
-    catch_panic::CatchPanicLayer,
     sensitive_headers::SetSensitiveHeadersLayer,
     trace::TraceLayer,
 };
 use wikimedia::{
-    dump::{self, CategorySlug},
-    slug,
+    bail_kind,
+    dump::DumpName,
+    format_err_kind,
     Result,
-    util::fmt::Sha1Hash,
-    wikitext,
+    wikitext::{HighlightStyle, RenderLimits},
 };
-use wikimedia_store::{self as store, index, StorePageId};
-
+use wikimedia_web::{auth, WebState};
 
 /// Run a web server that returns Wikimedia content.
 #[derive(clap::Args, Clone, Debug)]
 pub struct Args {
     #[clap(flatten)]
-    common: CommonArgs,
+    pub(crate) common: CommonArgs,
 
     /// Open the index of the web server in your browser.
     #[arg(long, default_value_t = false)]
     open: bool,
-}
-
-type WebResult<T> = StdResult<T, WebError>;
 
-mod state {
-    use anyhow::{ensure, format_err};
-    use std::sync::{Mutex, MutexGuard};
-    use super::Args;
-    use wikimedia::{dump::DumpName, Result};
-    use wikimedia_store::Store;
-
-    pub struct WebState {
-        args: Args,
-        store: Mutex<Store>,
-        store_dump_name: DumpName,
+    /// A store to serve, as `NAME` or `NAME=PATH`: the store's dump name, e.g. `simplewiki`,
+    /// and optionally the root directory of the store. Repeat this to serve several stores.
+    ///
+    /// Without a path a store is in the `stores` directory of the output directory, as with
+    /// `--store-dump`. Each store is opened on the first request for it. The first store is
+    /// the default, e.g. for page search.
+    ///
+    /// Each store's chunks, index, and site are read from its config file, so the other
+    /// store arguments can't be used with this. If not present serves only the store given
+    /// by `--store-dump`.
+    #[arg(long, value_name = "NAME[=PATH]")]
+    store: Vec<StoreRoot>,
+
+    /// Allow admin requests, i.e. those that may change state and those under `/admin`, with
+    /// this token in the header `Authorization: Bearer TOKEN`.
+    ///
+    /// Without this or `--admin-user` admin requests are rejected. Admin requests are logged
+    /// with the target `wikimedia_web::audit`.
+    #[arg(long, env = "WMD_ADMIN_TOKEN", conflicts_with = "admin_user")]
+    admin_token: Option<String>,
+
+    /// Allow admin requests from this user with HTTP basic authentication, as
+    /// `USER:PASSWORD`. Repeat this to allow several users.
+    #[arg(long, env = "WMD_ADMIN_USER", value_name = "USER:PASSWORD")]
+    admin_user: Vec<String>,
+
+    /// The theme for highlighted code in `<syntaxhighlight>` and `<source>` tags.
+    #[arg(long, value_enum, default_value_t = HighlightStyle::default())]
+    highlight_style: HighlightStyle,
+
+    /// The most wikitext to render for each page, in bytes. Longer pages are truncated, with
+    /// a notice.
+    #[arg(long, default_value_t = RenderLimits::default().max_input_bytes)]
+    render_max_input_bytes: usize,
+
+    /// The most HTML to return for each page, in bytes. Longer pages are truncated, with a
+    /// notice.
+    #[arg(long, default_value_t = RenderLimits::default().max_output_bytes)]
+    render_max_output_bytes: usize,
+
+    /// How long rendering each page may take, in milliseconds. Pages that take longer are
+    /// shown as wikitext, with a notice.
+    #[arg(long, default_value_t = RenderLimits::default().timeout.as_millis() as u64)]
+    render_timeout_ms: u64,
+}
+
+/// A store to serve, see `Args::store`.
+#[derive(Clone, Debug)]
+struct StoreRoot {
+    dump_name: DumpName,
+    path: Option<PathBuf>,
+}
+
+impl FromStr for StoreRoot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<StoreRoot> {
+        let (dump_name, path) = match s.split_once('=') {
+            Some((dump_name, path)) => (dump_name, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        Ok(StoreRoot {
+            dump_name: dump_name.parse::<DumpName>()?,
+            path,
+        })
     }
+}
 
-    impl WebState {
-        pub fn new(args: Args) -> Result<WebState> {
-            let store = args.common.store_options()?.build()?;
-
-            Ok(WebState {
-                store: Mutex::new(store),
-                store_dump_name: args.common.store_dump_name().clone(),
-
-                // This moves `args`, so do it last.
-                args,
-            })
-        }
-
-        pub fn args(&self) -> &Args {
-            &self.args
-        }
-
-        pub fn store<'state>(&'state self, dump_name: &str
-        ) -> Result<MutexGuard<'state, Store>>
-        {
-            ensure!(dump_name == &*self.store_dump_name.0,
-                    "WebState::store() error: Dump name requested ({dump_name}) \
-                     is not the same as the loaded store's dump name ({store_dump_name})",
-                    store_dump_name = &*self.store_dump_name.0);
+impl Args {
+    /// Whether the stores to serve were listed with `--store`.
+    pub(crate) fn serves_store_list(&self) -> bool {
+        !self.store.is_empty()
+    }
 
-            Ok(self.store.lock()
-                   .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))?)
+    /// The state for the web server: the stores given by the arguments.
+    pub(crate) fn web_state(&self) -> Result<WebState> {
+        let common = &self.common;
+        let store_options = if self.store.is_empty() {
+            vec![(common.store_dump_name(), common.store_options()?)]
+        } else {
+            if common.has_store_location_args() {
+                bail_kind!(User,
+                           "The store's chunks, index, and remote URL can't be set with \
+                            `--store`, set them in each store's config file instead.");
+            }
+            self.store.iter()
+                .map(|root| -> Result<_> {
+                    let path = root.path.clone()
+                                   .unwrap_or_else(|| common.store_path_for(&root.dump_name));
+                    Ok((root.dump_name.clone(),
+                        common.store_options_at(root.dump_name.clone(), path)?))
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let mut state = WebState::new(store_options, common.out_dir())?;
+        state.set_highlight_style(self.highlight_style);
+        state.set_render_limits(RenderLimits {
+            max_input_bytes: self.render_max_input_bytes,
+            max_output_bytes: self.render_max_output_bytes,
+            timeout: StdDuration::from_millis(self.render_timeout_ms),
+        });
+
+        if let Some(token) = self.admin_token.as_ref() {
+            state.set_authenticator(Arc::new(auth::ApiToken::new(token.clone())));
+        } else if !self.admin_user.is_empty() {
+            let users = self.admin_user.iter()
+                .map(|user| user.split_once(':')
+                                .map(|(user, password)| (user.to_string(), password.to_string()))
+                                .ok_or_else(|| format_err_kind!(User, 
+                                    "`--admin-user` must be formatted as `USER:PASSWORD`")))
+                .collect::<Result<Vec<_>>>()?;
+            state.set_authenticator(Arc::new(auth::BasicAuth::new(users)));
         }
 
-        pub fn store_dump_name(&self) -> DumpName {
-            self.store_dump_name.clone()
-        }
+        Ok(state)
     }
 }
 
-use state::WebState;
-
 #[tracing::instrument(level = "trace")]
 pub async fn main(args: Args) -> Result<()> {
-    let state = Arc::new(WebState::new(args.clone())?);
-
-    let app = Router::new()
-        .route("/", routing::get(get_index))
-        .route("/:dump_name/category", routing::get(get_categories))
-        .route("/:dump_name/category/by-name/:category_slug",
-               routing::get(get_category_by_slug))
-
-        .route("/:dump_name/page/by-id/:page_id", routing::get(get_page_by_id))
-        .route("/:dump_name/page/by-store-id/:page_store_id", routing::get(get_page_by_store_id))
-        .route("/:dump_name/page/by-title/:page_slug", routing::get(get_page_by_slug))
-
-        .route("/page/search", routing::get(get_page_search))
-
-        .route("/test_panic", routing::get(|| async { panic!("Test panic") }))
-
-        .fallback(router_fallback)
-
-        .with_state(state)
+    let state = Arc::new(args.web_state()?);
+    serve(&args, state).await
+}
 
+/// Serve requests with `state` until the server stops.
+pub(crate) async fn serve(args: &Args, state: Arc<WebState>) -> Result<()> {
+    let app = wikimedia_web::router(state)
         // Lower layers run first.
         .layer(tower::ServiceBuilder::new()
                    .layer(SetSensitiveHeadersLayer::new(vec![header::AUTHORIZATION]))
                    .layer(TraceLayer::new_for_http())
-                   .layer(CatchPanicLayer::custom(handle_panic))
                 );
 
     let port: u16 = 8089;
@@ -150,452 +190,3 @@ pub async fn main(args: Args) -> Result<()> {
 
     Ok(())
 }
-
-struct WebError(Response);
-
-impl WebError {
-    fn from_std_error<E>(e: E) -> WebError
-        where E: std::error::Error + Send + Sync + 'static
-    {
-        let anyhow_err = anyhow::Error::new(e);
-        WebError::from(anyhow_err)
-    }
-}
-
-impl IntoResponse for WebError {
-    fn into_response(self) -> Response {
-        self.0
-    }
-}
-
-impl From<anyhow::Error> for WebError {
-    fn from(e: anyhow::Error) -> WebError {
-        WebError(_500_response(&&*format!("Error: {e:#}")))
-    }
-}
-
-impl From<fmt::Error> for WebError {
-    fn from(e: fmt::Error) -> WebError {
-        WebError::from_std_error(e)
-    }
-}
-
-impl<T> From<std::sync::PoisonError<MutexGuard<'_, T>>> for WebError {
-    fn from(_e: std::sync::PoisonError<MutexGuard<'_, T>>) -> WebError {
-        // PoisonError is from trying to unlock a poisoned mutex. It
-        // contains the MutexGuard in case you want to clear the poison and continue.
-        // However MutexGuard is not Send, and axum wants errors from handlers to be Send.
-        // So we special case this conversion from sync::PoisonError<MutexGuard>
-        // to ignore the inner value and make sure we are Send.
-        WebError(_500_response(&"PoisonError unlocking Mutex in web module"))
-    }
-}
-
-#[cfg(any())]
-impl<E> From<E> for WebError
-    where E: std::error::Error + Send + Sync + 'static
-{
-    fn from(e: E) -> WebError {
-        WebError(_500_response(&*format!("Error: {e}")))
-    }
-}
-
-#[derive(askama::Template)]
-#[template(path = "error.html")]
-struct ErrorHtml<'a> {
-    title: &'static str,
-    message: &'a str,
-}
-
-fn _500_response(msg: &dyn Display) -> Response {
-    error_response("Error", msg, StatusCode::INTERNAL_SERVER_ERROR)
-}
-
-fn _404_response(msg: &dyn Display) -> Response {
-    error_response("Not found", msg, StatusCode::NOT_FOUND)
-}
-
-fn error_response(title: &'static str, msg: &dyn Display, status: StatusCode) -> Response {
-    let msg = msg.to_string();
-
-    tracing::error!(%title, %msg,
-                    status_u16 = status.as_u16(),
-                    status_str = status.as_str(),
-                    "web::error_response");
-
-    let template = ErrorHtml {
-        title: title,
-        message: &*msg,
-    };
-
-    let html = match template.render() {
-        Ok(html) => html,
-        Err(e) => format!(
-            "<html>\
-             <head>\
-             <title>{title}</title>\
-             </head>\
-             <body>\
-             <h1>{title}</h1>\
-             <pre>{msg}</pre>\
-             <p>Additional error rendering the error:</p>\
-             <pre>{e}</pre>\
-             </body>\
-             </html>"),
-    };
-
-    (
-        status,
-        TypedHeader(ContentType::html()),
-        html,
-    ).into_response()
-}
-
-fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response {
-    let s = if let Some(s) = err.downcast_ref::<String>() {
-        s.clone()
-    } else if let Some(s) = err.downcast_ref::<&str>() {
-        s.to_string()
-    } else {
-        "Unknown panic message".to_string()
-    };
-
-    tracing::error!("panic: {s}");
-
-    _500_response(&format!("panic: {s}"))
-}
-
-async fn router_fallback() -> impl IntoResponse {
-    _404_response(&"Route not found")
-}
-
-#[derive(askama::Template)]
-#[template(path = "index.html")]
-struct IndexHtml {
-    title: String,
-    dump_name: String,
-}
-
-async fn get_index(
-    State(state): State<Arc<WebState>>,
-) -> impl IntoResponse {
-    let dump_name = state.store_dump_name().0;
-    IndexHtml {
-        title: format!("Index for {dump_name}"),
-        dump_name,
-    }
-}
-
-#[derive(Deserialize)]
-struct GetCategoryQuery {
-    limit: Option<u64>,
-    slug_lower_bound: Option<String>,
-}
-
-#[derive(askama::Template)]
-#[template(path = "categories.html")]
-struct CategoriesHtml<'a> {
-    title: &'a str,
-    dump_name: String,
-
-    categories: Vec<CategorySlug>,
-    show_more_href: Option<String>,
-}
-
-async fn get_categories(
-    State(state): State<Arc<WebState>>,
-    Path(dump_name): Path<String>,
-    Query(query): Query<GetCategoryQuery>
-) -> WebResult<impl IntoResponse> {
-
-    let limit = query.limit.unwrap_or(store::MAX_QUERY_LIMIT).min(store::MAX_QUERY_LIMIT);
-
-    let categories = state.store(&*dump_name)?
-        .get_category(
-            query.slug_lower_bound.as_ref().map(|s| CategorySlug(s.clone())).as_ref(),
-            Some(limit))?;
-
-    let last_slug = categories.last().cloned();
-    let len = u64::try_from(categories.len()).expect("u64 from usize");
-
-    let show_more_href =
-        if let Some(CategorySlug(slug_lower_bound)) = last_slug {
-            if limit == len {
-                let limit_pair = match query.limit {
-                    Some(limit) => format!("&limit={}", limit),
-                    None => "".to_string(),
-                };
-
-                Some(format!(
-                    "/{dump_name}/category?slug_lower_bound={slug_lower_bound}{limit_pair}"))
-            } else { None }
-        } else { None };
-
-    Ok(CategoriesHtml {
-        title: "Categories",
-        dump_name,
-
-        categories,
-        show_more_href,
-    })
-}
-
-#[derive(Deserialize)]
-struct GetCategoryBySlugQuery {
-    limit: Option<u64>,
-    page_mediawiki_id_lower_bound: Option<u64>,
-}
-
-#[derive(askama::Template)]
-#[template(path = "category.html")]
-struct CategoryHtml {
-    title: String,
-    dump_name: String,
-
-    pages: Vec<index::Page>,
-    show_more_href: Option<String>,
-}
-
-async fn get_category_by_slug(
-    State(state): State<Arc<WebState>>,
-    Path((dump_name, category_slug)): Path<(String, String)>,
-    Query(query): Query<GetCategoryBySlugQuery>,
-) -> WebResult<impl IntoResponse> {
-
-    let limit = query.limit.unwrap_or(store::MAX_QUERY_LIMIT).min(store::MAX_QUERY_LIMIT);
-
-    let store = state.store(&*dump_name)?;
-    let pages: Vec<index::Page> = store.get_category_pages(
-        &CategorySlug(category_slug.clone()),
-        query.page_mediawiki_id_lower_bound,
-        Some(limit),
-    )?;
-
-    // Drop the MutexGuard.
-    drop(store);
-
-    let page_mediawiki_id_lower_bound = pages.last().map(|page| page.mediawiki_id);
-    let len = u64::try_from(pages.len()).expect("u64 from usize");
-
-    let show_more_href =
-        if let Some(page_mediawiki_id_lower_bound) = page_mediawiki_id_lower_bound {
-            if len == limit {
-                let limit_pair = match query.limit {
-                    Some(limit) => format!("&limit={}", limit),
-                    None => "".to_string(),
-                };
-
-                Some(format!("/{dump_name}/category/by-name/{category_slug}\
-                              ?page_mediawiki_id_lower_bound={page_mediawiki_id_lower_bound}\
-                              {limit_pair}"))
-            } else { None }
-        } else { None };
-
-    Ok(CategoryHtml {
-        title: format!("Category:{category_slug}"),
-        dump_name,
-
-        pages,
-        show_more_href,
-    })
-}
-
-#[derive(Deserialize)]
-struct SinglePageQuery {
-    debug: Option<bool>,
-}
-
-async fn get_page_by_id(
-    State(state): State<Arc<WebState>>,
-    Path((dump_name, page_id)): Path<(String, u64)>,
-    Query(query): Query<SinglePageQuery>,
-) -> WebResult<impl IntoResponse> {
-
-    let page = state.store(&*dump_name)?.get_page_by_mediawiki_id(page_id)?;
-
-    response_from_mapped_page(page, &*state, query).await
-}
-
-async fn get_page_by_store_id(
-    State(state): State<Arc<WebState>>,
-    Path((dump_name, page_store_id)): Path<(String, String)>,
-    Query(query): Query<SinglePageQuery>,
-) -> WebResult<impl IntoResponse> {
-
-    let page_store_id = page_store_id.parse::<store::StorePageId>()?;
-
-    let page = state.store(&*dump_name)?.get_page_by_store_id(page_store_id)?;
-
-    response_from_mapped_page(page, &*state, query).await
-}
-
-async fn get_page_by_slug(
-    State(state): State<Arc<WebState>>,
-    Path((dump_name, page_slug)): Path<(String, String)>,
-    Query(query): Query<SinglePageQuery>,
-) -> WebResult<impl IntoResponse> {
-
-    let page = state.store(&*dump_name)?.get_page_by_slug(&*page_slug)?;
-
-    response_from_mapped_page(page, &*state, query).await
-}
-
-#[derive(askama::Template)]
-#[template(path = "page.html")]
-struct PageHtml {
-    title: String,
-
-    slug: String,
-    wikitext_html: String,
-
-    dump_name: String,
-    wikimedia_url_base: Option<String>,
-}
-
-#[derive(askama::Template)]
-#[template(path = "page_debug.html")]
-struct PageDebugHtml {
-    title: String,
-
-    namespace: dump::Namespace,
-    mediawiki_id: u64,
-    slug: String,
-    store_page_id: StorePageId,
-
-    revision_id: Option<u64>,
-    revision_parent_id: Option<u64>,
-    revision_timestamp_string: Option<String>,
-    revision_text_sha1: Option<Sha1Hash>,
-
-    wikitext: String,
-
-    dump_name: String,
-    wikimedia_url_base: Option<String>,
-}
-
-fn response_from_mapped_page(
-    page: Option<store::MappedPage>,
-    state: &WebState,
-    query: SinglePageQuery,
-) -> impl Future<Output = WebResult<Response>> + Send {
-    let Some(page) = page else {
-        return Either::Left(Either::Left(future::ok(_404_response(&"Page not found"))));
-    };
-
-    let store_page_id = page.store_id();
-    let page_cap = match page.borrow() {
-        Ok(p) => p,
-        Err(e) => return Either::Left(Either::Right(future::err(e.into()))),
-    };
-    let page_dump = match dump::Page::try_from(&page_cap) {
-        Ok(p) => p,
-        Err(e) => return Either::Left(Either::Right(future::err(e.into()))),
-    };
-
-    let common_args = state.args().common.clone();
-    let dump_name = page.dump_name();
-    let wikimedia_url_base = dump::dump_name_to_wikimedia_url_base(&dump_name);
-
-    if query.debug.unwrap_or(false) {
-        let wikitext = page_dump.revision_text().unwrap_or("").to_string();
-        let slug = slug::title_to_slug(&*page_dump.title);
-
-        Either::Right(Either::Left({
-            let html = PageDebugHtml {
-                title: format!("{title} - debug info", title = page_dump.title),
-
-                namespace:
-                    match page_dump.namespace() {
-                        Ok(ns) => ns,
-                        Err(e) => return Either::Left(Either::Right(future::err(e.into()))),
-                    },
-                mediawiki_id: page_dump.id,
-                slug,
-                store_page_id,
-                wikitext,
-
-                revision_id: page_dump.revision.as_ref().map(|r| r.id),
-                revision_parent_id: page_dump.revision.as_ref().and_then(|r| r.parent_id),
-                revision_timestamp_string:
-                    page_dump.revision.as_ref().and_then(|r| r.timestamp)
-                             .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs,
-                                                          /* use_z: */ true)),
-                revision_text_sha1:
-                    page_dump.revision.as_ref().and_then(|r| r.sha1),
-
-                wikimedia_url_base,
-
-                // This moves dump_name, do it last.
-                dump_name: dump_name.0,
-            };
-            future::ok(html.into_response())
-        }))
-    } else {
-        Either::Right(Either::Right(async move {
-            let wikitext_html = wikitext::convert_page_to_html(&page_dump,
-                                                               &dump_name,
-                                                               &*common_args.out_dir()).await?;
-            let slug = slug::title_to_slug(&*page_dump.title);
-            let html = PageHtml {
-                title: page_dump.title,
-
-                slug,
-                wikitext_html,
-
-                wikimedia_url_base,
-
-                // This moves dump_name, do it last.
-                dump_name: dump_name.0,
-            };
-            Ok(html.into_response())
-        }))
-    }
-}
-
-
-
-#[derive(Deserialize)]
-struct PageSearchQuery {
-    query: Option<String>,
-}
-
-#[derive(askama::Template)]
-#[template(path = "page_search.html")]
-struct PageSearchHtml {
-    title: String,
-    dump_name: String,
-
-    query: Option<String>,
-
-    pages: Vec<index::Page>,
-    show_more_href: Option<String>,
-}
-
-async fn get_page_search(
-    State(state): State<Arc<WebState>>,
-    Query(query): Query<PageSearchQuery>,
-) -> WebResult<impl IntoResponse> {
-
-    let dump_name = state.store_dump_name();
-    let Some(query_string) = query.query else {
-        return Ok(PageSearchHtml {
-                title: "Page search".to_string(),
-                dump_name: dump_name.0,
-                query: None,
-                pages: Vec::with_capacity(0),
-                show_more_href: None,
-            });
-    };
-
-    let store = state.store(&*dump_name.0)?;
-
-    let pages = store.page_search(&*query_string, None /* limit, TODO */)?;
-
-    Ok(PageSearchHtml {
-        title: "Page search".to_string(),
-        dump_name: dump_name.0,
-        query: Some(query_string),
-        pages,
-        show_more_href: None, // TODO
-    })
-}
@@ -8,9 +8,12 @@ mod args;
 mod commands;
 
 use clap::Parser;
+use std::process::ExitCode;
 use tracing::Level;
 use valuable::Valuable;
 use wikimedia::{
+    cancel,
+    ErrorKind,
     Result,
     util,
 };
@@ -28,17 +31,71 @@ struct Args {
 
 #[derive(clap::Subcommand, Clone, Debug)]
 enum Command {
+    #[cfg(feature = "store")]
+    ApplyDeletions(commands::apply_deletions::Args),
+    #[cfg(feature = "store")]
+    BenchImport(commands::bench_import::Args),
+    #[cfg(feature = "store")]
+    BuildFts(commands::build_fts::Args),
+    #[cfg(feature = "store")]
     ClearStore(commands::clear_store::Args),
     Completion(commands::completion::Args),
+    #[cfg(feature = "store")]
+    DiffPages(commands::diff_pages::Args),
+    #[cfg(feature = "download")]
     Download(commands::download::Args),
+    #[cfg(feature = "store")]
+    ExportIndex(commands::export_index::Args),
+    #[cfg(feature = "store")]
+    ExportPages(commands::export_pages::Args),
+    #[cfg(feature = "store")]
+    GetCategory(commands::get_category::Args),
+    #[cfg(feature = "store")]
     GetChunk(commands::get_chunk::Args),
+    #[cfg(feature = "download")]
     GetDump(commands::get_dump::Args),
     GetDumpPage(commands::get_dump_page::Args),
+    #[cfg(feature = "download")]
     GetFileInfo(commands::get_file_info::Args),
+    #[cfg(feature = "download")]
     GetJob(commands::get_job::Args),
+    #[cfg(feature = "store")]
     GetStorePage(commands::get_store_page::Args),
+    #[cfg(feature = "store")]
+    GetTemplates(commands::get_templates::Args),
     GetVersion(commands::get_version::Args),
+    HeadPage(commands::head_page::Args),
+    #[cfg(feature = "store")]
     ImportDump(commands::import_dump::Args),
+    #[cfg(feature = "store")]
+    ImportIndex(commands::import_index::Args),
+    #[cfg(feature = "store")]
+    IndexExplain(commands::index_explain::Args),
+    #[cfg(feature = "download")]
+    ListJobs(commands::list_jobs::Args),
+    #[cfg(feature = "store")]
+    MigrateIndex(commands::migrate_index::Args),
+    #[cfg(feature = "store")]
+    OptimizeStore(commands::optimize_store::Args),
+    #[cfg(all(feature = "store", feature = "wikitext-render"))]
+    PrerenderStore(commands::prerender_store::Args),
+    #[cfg(feature = "web")]
+    Proxy(commands::proxy::Args),
+    #[cfg(feature = "store")]
+    RebuildIndex(commands::rebuild_index::Args),
+    #[cfg(feature = "store")]
+    ReportRedlinks(commands::report_redlinks::Args),
+    #[cfg(feature = "store")]
+    ReportSlugCollisions(commands::report_slug_collisions::Args),
+    #[cfg(feature = "web")]
+    ServeManaged(commands::serve_managed::Args),
+    #[cfg(feature = "store")]
+    StoreInfo(commands::store_info::Args),
+    #[cfg(all(feature = "download", feature = "store"))]
+    Sync(commands::sync::Args),
+    #[cfg(feature = "store")]
+    VerifyStore(commands::verify_store::Args),
+    #[cfg(feature = "web")]
     Web(commands::web::Args),
 }
 
@@ -49,12 +106,15 @@ enum LogMode {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let start_time = std::time::Instant::now();
 
     let args = Args::parse();
 
-    init_logging(args.log_json)?;
+    if let Err(err) = init_logging(args.log_json) {
+        eprintln!("Error: {err:?}");
+        return exit_code(ErrorKind::of(&err));
+    }
 
     if tracing::enabled!(Level::DEBUG) {
         tracing::debug!(args = ?args.clone(), "parsed CLI args");
@@ -63,17 +123,77 @@ async fn main() -> Result<()> {
     // Wrap command dispatch in a closure to log errors.
     let res = (|| async {
         match args.command {
+            #[cfg(feature = "store")]
+            Command::ApplyDeletions(cmd_args) =>
+                commands::apply_deletions::main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::BenchImport(cmd_args)  => commands::bench_import::  main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::BuildFts(cmd_args)     => commands::build_fts::     main(cmd_args).await?,
+            #[cfg(feature = "store")]
             Command::ClearStore(cmd_args)   => commands::clear_store::   main(cmd_args).await?,
             Command::Completion(cmd_args)   => commands::completion::    main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::DiffPages(cmd_args)    => commands::diff_pages::    main(cmd_args).await?,
+            #[cfg(feature = "download")]
             Command::Download(cmd_args)     => commands::download::      main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::ExportIndex(cmd_args)  => commands::export_index::  main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::ExportPages(cmd_args)  => commands::export_pages::  main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::GetCategory(cmd_args)  => commands::get_category::  main(cmd_args).await?,
+            #[cfg(feature = "store")]
             Command::GetChunk(cmd_args)     => commands::get_chunk::     main(cmd_args).await?,
+            #[cfg(feature = "download")]
             Command::GetDump(cmd_args)      => commands::get_dump::      main(cmd_args).await?,
             Command::GetDumpPage(cmd_args)  => commands::get_dump_page:: main(cmd_args).await?,
+            #[cfg(feature = "download")]
             Command::GetFileInfo(cmd_args)  => commands::get_file_info:: main(cmd_args).await?,
+            #[cfg(feature = "download")]
             Command::GetJob(cmd_args)       => commands::get_job::       main(cmd_args).await?,
+            #[cfg(feature = "store")]
             Command::GetStorePage(cmd_args) => commands::get_store_page::main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::GetTemplates(cmd_args) => commands::get_templates:: main(cmd_args).await?,
             Command::GetVersion(cmd_args)   => commands::get_version::   main(cmd_args).await?,
+            Command::HeadPage(cmd_args)     => commands::head_page::     main(cmd_args).await?,
+            #[cfg(feature = "store")]
             Command::ImportDump(cmd_args)   => commands::import_dump::   main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::ImportIndex(cmd_args)  => commands::import_index::  main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::IndexExplain(cmd_args) => commands::index_explain:: main(cmd_args).await?,
+            #[cfg(feature = "download")]
+            Command::ListJobs(cmd_args)     => commands::list_jobs::     main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::MigrateIndex(cmd_args) => commands::migrate_index:: main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::OptimizeStore(cmd_args) =>
+                commands::optimize_store::main(cmd_args).await?,
+            #[cfg(all(feature = "store", feature = "wikitext-render"))]
+            Command::PrerenderStore(cmd_args) =>
+                commands::prerender_store::main(cmd_args).await?,
+            #[cfg(feature = "web")]
+            Command::Proxy(cmd_args)        => commands::proxy::         main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::RebuildIndex(cmd_args) =>
+                commands::rebuild_index::main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::ReportRedlinks(cmd_args) =>
+                commands::report_redlinks::main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::ReportSlugCollisions(cmd_args) =>
+                commands::report_slug_collisions::main(cmd_args).await?,
+            #[cfg(feature = "web")]
+            Command::ServeManaged(cmd_args) => commands::serve_managed:: main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::StoreInfo(cmd_args)    => commands::store_info::    main(cmd_args).await?,
+            #[cfg(all(feature = "download", feature = "store"))]
+            Command::Sync(cmd_args)         => commands::sync::          main(cmd_args).await?,
+            #[cfg(feature = "store")]
+            Command::VerifyStore(cmd_args)  => commands::verify_store::  main(cmd_args).await?,
+            #[cfg(feature = "web")]
             Command::Web(cmd_args)          => commands::web::           main(cmd_args).await?,
         }
 
@@ -85,15 +205,48 @@ async fn main() -> Result<()> {
     tracing::info!(duration = duration.as_value(), "wmd::main() returning");
 
     if let Err(err) = res {
+        let kind = ErrorKind::of(&err);
+
         // Record an error with tracing as this will output properly formatted JSON (if enabled).
+        tracing::error!(%err, %kind, "Command returned with an error.");
 
-        tracing::error!(%err, "Command returned with an error.");
+        // Print the error too for a pretty display of its causes and backtrace.
+        eprintln!("Error: {err:?}");
 
-        // Return the error too so Rust can print a pretty stack trace display.
-        return Err(err)
+        return exit_code(kind);
     }
 
-    Ok(())
+    ExitCode::SUCCESS
+}
+
+/// The token cancelled when `wmd` receives ctrl-c or SIGTERM.
+///
+/// Commands that can stop cleanly part way through call this, which installs the signal
+/// handler. Other commands are killed by those signals as usual.
+#[cfg(any(feature = "download", feature = "store"))]
+pub(crate) fn cancellation() -> Result<cancel::CancellationToken> {
+    use std::sync::OnceLock;
+
+    static CANCELLATION: OnceLock<cancel::CancellationToken> = OnceLock::new();
+
+    if let Some(token) = CANCELLATION.get() {
+        return Ok(token.clone());
+    }
+    let token = cancel::cancel_on_signal()?;
+    Ok(CANCELLATION.get_or_init(|| token).clone())
+}
+
+/// The process exit code for an error of kind `kind`, so scripts can tell failures apart.
+///
+/// 2 is also the exit code clap uses for invalid arguments.
+fn exit_code(kind: ErrorKind) -> ExitCode {
+    ExitCode::from(match kind {
+        ErrorKind::Internal     => 1,
+        ErrorKind::User         => 2,
+        ErrorKind::Network      => 3,
+        ErrorKind::Verification => 4,
+        ErrorKind::Cancelled    => cancel::EXIT_CODE,
+    })
 }
 
 fn init_logging(log_json: bool) -> Result<()> {
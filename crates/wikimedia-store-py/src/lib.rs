@@ -0,0 +1,266 @@
+//! Python bindings to read pages from a store, built as the `wikimedia_store` Python module.
+//!
+//! Build and install the module into the current Python environment with
+//! [maturin](https://www.maturin.rs/), e.g. `maturin develop --release` in this crate's
+//! directory. Then:
+//!
+//! ```python
+//! import wikimedia_store
+//!
+//! store = wikimedia_store.open_store("enwiki", out_dir="/home/me/wmd/out")
+//!
+//! page = store.get_page(title="Rust (programming language)")
+//! print(page.mediawiki_id, page.text[:100])
+//!
+//! for page in store.search("rust", limit=10):
+//!     print(page.title)
+//!
+//! for page in store.scan_pages():
+//!     ...
+//! ```
+//!
+//! Errors are raised as `ValueError` for invalid arguments or a store that doesn't exist, and
+//! as `RuntimeError` otherwise.
+
+// pyo3's generated wrappers convert `PyErr` into itself.
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+};
+use wikimedia::{
+    dump::{self, DumpName},
+    ErrorKind,
+    slug,
+};
+use wikimedia_store::{self as store, ChunkId, index, StorePageId};
+
+#[pymodule]
+#[pyo3(name = "wikimedia_store")]
+fn py_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("VERSION", store::VERSION)?;
+    m.add_function(wrap_pyfunction!(open_store, m)?)?;
+    m.add_class::<Store>()?;
+    m.add_class::<Page>()?;
+    m.add_class::<PageIter>()?;
+    Ok(())
+}
+
+/// Open the existing store for `dump`, e.g. `enwiki`.
+///
+/// The store is found in `out_dir` as with `wmd --out-dir`, or at `path` if that is given,
+/// as with `wmd --store-path`.
+#[pyfunction]
+#[pyo3(signature = (dump, out_dir = None, path = None))]
+fn open_store(dump: String, out_dir: Option<PathBuf>, path: Option<PathBuf>
+) -> PyResult<Store> {
+    let path = match (path, out_dir) {
+        (Some(path), _) => path,
+        (None, Some(out_dir)) => out_dir.join("stores").join(&dump),
+        (None, None) => return Err(PyValueError::new_err(
+            "open_store() requires `out_dir` or `path`")),
+    };
+
+    let mut options = store::Options::default();
+    options.dump_name(DumpName(dump))
+           .path(path.clone());
+
+    // Check first, because building the options creates a store that doesn't exist.
+    let compatibility = options.compatibility().map_err(to_py_err)?;
+    if !compatibility.exists {
+        return Err(PyValueError::new_err(
+            format!("No store found at path='{}'", path.display())));
+    }
+    if let Some(problem) = compatibility.problem {
+        return Err(PyValueError::new_err(problem));
+    }
+
+    Ok(Store {
+        inner: options.build().map_err(to_py_err)?,
+    })
+}
+
+/// A store opened by `open_store()`.
+#[pyclass(module = "wikimedia_store", frozen)]
+struct Store {
+    inner: store::Store,
+}
+
+/// A page read from a store.
+#[pyclass(module = "wikimedia_store", frozen, get_all)]
+struct Page {
+    mediawiki_id: u64,
+    ns_id: i64,
+    title: String,
+
+    /// The page's ID in the store, e.g. `12.34` for the page at index 34 in chunk 12.
+    store_page_id: String,
+
+    revision_id: Option<u64>,
+
+    /// The revision's timestamp in RFC 3339 format.
+    revision_timestamp: Option<String>,
+
+    /// The revision's wikitext.
+    text: Option<String>,
+
+    /// The names of the page's categories, without the `Category:` prefix.
+    categories: Vec<String>,
+}
+
+/// An iterator of [`Page`]s returned by [`Store::search`] and [`Store::scan_pages`].
+///
+/// Pages are read from the store as the iterator advances.
+#[pyclass(module = "wikimedia_store")]
+struct PageIter {
+    store: Py<Store>,
+    source: PageSource,
+
+    /// Pages read but not yet returned, e.g. the rest of the current chunk.
+    pages: VecDeque<Page>,
+}
+
+enum PageSource {
+    /// Pages to read by ID.
+    Pages(VecDeque<StorePageId>),
+
+    /// Chunks to read all the pages of.
+    Chunks(VecDeque<ChunkId>),
+}
+
+#[pymethods]
+impl Store {
+    /// Get a page by MediaWiki ID or by title, or `None` if the store doesn't have it.
+    #[pyo3(signature = (mediawiki_id = None, title = None))]
+    fn get_page(&self, mediawiki_id: Option<u64>, title: Option<&str>
+    ) -> PyResult<Option<Page>> {
+        let mapped_page = match (mediawiki_id, title) {
+            (Some(mediawiki_id), None) => self.inner.get_page_by_mediawiki_id(mediawiki_id),
+            (None, Some(title)) => self.inner.get_page_by_slug(&slug::title_to_slug(title)),
+            _ => return Err(PyValueError::new_err(
+                "get_page() requires one of `mediawiki_id` or `title`")),
+        }.map_err(to_py_err)?;
+
+        mapped_page.map(|mapped_page| {
+            let page_cap = mapped_page.borrow().map_err(to_py_err)?;
+            Page::new(mapped_page.store_id(), &page_cap)
+        }).transpose()
+    }
+
+    /// Full text search of page titles. Returns an iterator of the best `limit` matching
+    /// pages, best first. `limit` defaults to and is capped at 100.
+    #[pyo3(signature = (query, limit = None))]
+    fn search(slf: &Bound<'_, Self>, query: &str, limit: Option<u64>) -> PyResult<PageIter> {
+        let options = index::PageSearchOptions {
+            limit: limit.into(),
+            ..index::PageSearchOptions::default()
+        };
+        let results = slf.get().inner.page_search(query, &options).map_err(to_py_err)?;
+
+        Ok(PageIter {
+            store: slf.clone().unbind(),
+            source: PageSource::Pages(results.iter().map(index::Page::store_id).collect()),
+            pages: VecDeque::new(),
+        })
+    }
+
+    /// Returns an iterator of every page in the store, in chunk order.
+    fn scan_pages(slf: &Bound<'_, Self>) -> PyResult<PageIter> {
+        let chunk_ids = slf.get().inner.chunk_id_vec().map_err(to_py_err)?;
+
+        Ok(PageIter {
+            store: slf.clone().unbind(),
+            source: PageSource::Chunks(chunk_ids.into()),
+            pages: VecDeque::new(),
+        })
+    }
+}
+
+#[pymethods]
+impl Page {
+    fn __repr__(&self) -> String {
+        format!("Page(mediawiki_id={mediawiki_id}, title={title:?})",
+                mediawiki_id = self.mediawiki_id, title = self.title)
+    }
+}
+
+#[pymethods]
+impl PageIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Page>> {
+        let store = &self.store.bind(py).get().inner;
+
+        loop {
+            if let Some(page) = self.pages.pop_front() {
+                return Ok(Some(page));
+            }
+
+            match &mut self.source {
+                PageSource::Pages(store_page_ids) => {
+                    let Some(store_page_id) = store_page_ids.pop_front() else {
+                        return Ok(None);
+                    };
+                    // A page can be missing if the store changed since the search.
+                    if let Some(mapped_page) = store.get_page_by_store_id(store_page_id)
+                                                    .map_err(to_py_err)? {
+                        let page_cap = mapped_page.borrow().map_err(to_py_err)?;
+                        self.pages.push_back(Page::new(store_page_id, &page_cap)?);
+                    }
+                },
+                PageSource::Chunks(chunk_ids) => {
+                    let Some(chunk_id) = chunk_ids.pop_front() else {
+                        return Ok(None);
+                    };
+                    let Some(chunk) = store.map_chunk(chunk_id).map_err(to_py_err)? else {
+                        continue;
+                    };
+                    for (store_page_id, page_cap) in chunk.pages_iter().map_err(to_py_err)? {
+                        self.pages.push_back(Page::new(store_page_id, &page_cap)?);
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl Page {
+    fn new<C>(store_page_id: StorePageId, page_cap: &C) -> PyResult<Page>
+        where for<'a> dump::Page: TryFrom<&'a C, Error = wikimedia::Error>
+    {
+        let page = dump::Page::try_from(page_cap).map_err(to_py_err)?;
+        let revision = page.revision.as_ref();
+
+        Ok(Page {
+            mediawiki_id: page.id,
+            ns_id: page.ns_id,
+            store_page_id: store_page_id.to_string(),
+            revision_id: revision.map(|rev| rev.id),
+            revision_timestamp: revision.and_then(|rev| rev.timestamp)
+                                        .map(|ts| ts.to_rfc3339()),
+            categories: revision.map(|rev| rev.categories.iter()
+                                                         .map(|cat| cat.name.0.clone())
+                                                         .collect())
+                                .unwrap_or_default(),
+            text: page.revision.and_then(|rev| rev.text),
+            title: page.title,
+        })
+    }
+}
+
+/// Convert an error to a Python exception: `ValueError` for user errors, otherwise
+/// `RuntimeError`.
+fn to_py_err(err: wikimedia::Error) -> PyErr {
+    let message = format!("{err:#}");
+    match ErrorKind::of(&err) {
+        ErrorKind::User => PyValueError::new_err(message),
+        _ => PyRuntimeError::new_err(message),
+    }
+}
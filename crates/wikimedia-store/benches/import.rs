@@ -0,0 +1,39 @@
+//! Import a synthetic dump and print the rates as JSON. Run with `cargo bench`.
+//!
+//! Configure with the environment variables `WMD_BENCH_PAGES` and `WMD_BENCH_TEXT_LEN`, and
+//! set `WMD_BENCH_BASELINE` to the path of a previous run's JSON to fail if a rate regressed
+//! by more than 20%.
+
+use std::{env, fs, path::PathBuf};
+use wikimedia::{dump::synthetic, Result};
+use wikimedia_store::bench::{self, ImportReport};
+
+const REGRESSION_TOLERANCE: f64 = 0.2;
+
+fn main() -> Result<()> {
+    let mut dump = synthetic::Options::default();
+    if let Ok(pages) = env::var("WMD_BENCH_PAGES") {
+        dump.pages = pages.parse()?;
+    }
+    if let Ok(text_len) = env::var("WMD_BENCH_TEXT_LEN") {
+        dump.text_len = text_len.parse()?;
+    }
+
+    let report = bench::run_import(&bench::Options {
+        dump,
+        dir: env::temp_dir().join("wmd-bench"),
+        keep: false,
+        max_chunk_len: None,
+    })?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    if let Some(baseline_path) = env::var_os("WMD_BENCH_BASELINE").map(PathBuf::from) {
+        let baseline: ImportReport = serde_json::from_slice(&fs::read(baseline_path)?)?;
+        let regressions = report.regressions(&baseline, REGRESSION_TOLERANCE);
+        if !regressions.is_empty() {
+            anyhow::bail!("Import benchmark regressed:\n{}", regressions.join("\n"));
+        }
+    }
+
+    Ok(())
+}
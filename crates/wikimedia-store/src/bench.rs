@@ -0,0 +1,202 @@
+//! Benchmark importing a synthetic dump, to catch performance regressions in the dump parser,
+//! chunk writer, and index, and to size hardware for an import.
+//!
+//! Used by the `wmd bench-import` command and the `import` benchmark in `benches/`.
+
+use anyhow::Context;
+use crate::{Options as StoreOptions, Store};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+use wikimedia::{
+    dump::{local::{Compression, OpenSpec}, synthetic, DumpName},
+    Result,
+    TempDir,
+};
+
+/// What to benchmark, see [`run_import`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The synthetic dump to generate and import.
+    pub dump: synthetic::Options,
+
+    /// The directory for the benchmark's temporary directory, which holds the generated dump
+    /// and the store imported into. Use a directory on the disk to be benchmarked.
+    pub dir: PathBuf,
+
+    /// Keep the temporary directory after the benchmark, e.g. to inspect the store.
+    pub keep: bool,
+
+    /// The store's maximum chunk length, see [`StoreOptions::max_chunk_len`].
+    pub max_chunk_len: Option<u64>,
+}
+
+/// The results of [`run_import`]. Rates are per second of wall clock time, except
+/// `index_rows_per_commit_sec`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ImportReport {
+    pub pages: u64,
+
+    /// The approximate length in bytes of each article's wikitext.
+    pub text_len: usize,
+
+    /// The length of the generated uncompressed dump file in bytes.
+    pub dump_bytes: u64,
+
+    /// The threads used to import, from the rayon thread pool.
+    pub threads: usize,
+
+    /// Reading all pages from the dump file without importing them.
+    pub parse_secs: f64,
+    pub parse_pages_per_sec: f64,
+    pub parse_mb_per_sec: f64,
+
+    /// Importing all pages into a new store.
+    pub import_secs: f64,
+    pub import_pages_per_sec: f64,
+
+    pub chunks: u64,
+    pub chunk_bytes: u64,
+    pub chunk_write_mb_per_sec: f64,
+
+    /// Rows inserted into the index, over all tables.
+    pub index_rows: u64,
+    pub index_rows_per_sec: f64,
+
+    /// Total time spent committing to the index, over all import threads.
+    pub index_commit_secs: f64,
+    pub index_rows_per_commit_sec: f64,
+}
+
+const MB: f64 = 1_000_000.0;
+
+/// Generate a synthetic dump, read its pages, then import it into a new store, timing each.
+pub fn run_import(options: &Options) -> Result<ImportReport> {
+    let temp_dir = TempDir::create(&options.dir, options.keep)?;
+    let temp_path = temp_dir.path()?;
+
+    let dump_path = temp_path.join("synthetic.xml");
+    let mut dump_file = BufWriter::new(
+        fs::File::create(&dump_path)
+            .with_context(|| format!("creating dump file path='{}'", dump_path.display()))?);
+    synthetic::write_dump(&mut dump_file, &options.dump)?;
+    dump_file.flush()?;
+    drop(dump_file);
+    let dump_bytes = fs::metadata(&dump_path)?.len();
+
+    let mut open_spec = OpenSpec::builder();
+    open_spec.file(dump_path)
+             .compression(Compression::None);
+    let open_spec = open_spec.build()?;
+
+    let parse_start = Instant::now();
+    let mut parse_pages = 0_u64;
+    for page in open_spec.clone().open()?.open_pages_iter()? {
+        page?;
+        parse_pages += 1;
+    }
+    let parse_secs = parse_start.elapsed().as_secs_f64();
+
+    let mut store_options = StoreOptions::default();
+    store_options.dump_name(DumpName("syntheticwiki".to_string()))
+                 .path(temp_path.join("store"));
+    if let Some(max_chunk_len) = options.max_chunk_len {
+        store_options.max_chunk_len(max_chunk_len);
+    }
+    let mut store: Store = store_options.build()?;
+    let import = store.import(open_spec.open()?, /* continue_import: */ false)?;
+    drop(store);
+
+    let import_secs = import.duration.0.as_secs_f64();
+    let index_commit_secs = import.index_commit_duration.0.as_secs_f64();
+
+    Ok(ImportReport {
+        pages: parse_pages,
+        text_len: options.dump.text_len,
+        dump_bytes,
+        threads: rayon::current_num_threads(),
+
+        parse_secs,
+        parse_pages_per_sec: rate(parse_pages as f64, parse_secs),
+        parse_mb_per_sec: rate(dump_bytes as f64 / MB, parse_secs),
+
+        import_secs,
+        import_pages_per_sec: rate(import.pages_total as f64, import_secs),
+
+        chunks: import.chunks_len,
+        chunk_bytes: import.chunk_bytes_total.0,
+        chunk_write_mb_per_sec: rate(import.chunk_bytes_total.0 as f64 / MB, import_secs),
+
+        index_rows: import.index_values_total,
+        index_rows_per_sec: rate(import.index_values_total as f64, import_secs),
+
+        index_commit_secs,
+        index_rows_per_commit_sec: rate(import.index_values_total as f64, index_commit_secs),
+    })
+}
+
+fn rate(count: f64, secs: f64) -> f64 {
+    if secs > 0.0 { count / secs } else { 0.0 }
+}
+
+impl ImportReport {
+    /// The rates in this report more than `tolerance` (a fraction, e.g. 0.2 for 20%) slower
+    /// than in `baseline`, as descriptions for the user.
+    pub fn regressions(&self, baseline: &ImportReport, tolerance: f64) -> Vec<String> {
+        let rates = [
+            ("parse_pages_per_sec", self.parse_pages_per_sec, baseline.parse_pages_per_sec),
+            ("import_pages_per_sec", self.import_pages_per_sec, baseline.import_pages_per_sec),
+            ("chunk_write_mb_per_sec",
+             self.chunk_write_mb_per_sec, baseline.chunk_write_mb_per_sec),
+            ("index_rows_per_sec", self.index_rows_per_sec, baseline.index_rows_per_sec),
+        ];
+
+        rates.into_iter()
+             .filter(|(_name, curr, base)| *curr < *base * (1.0 - tolerance))
+             .map(|(name, curr, base)|
+                  format!("{name} regressed {pct:.1}%: {curr:.1} vs baseline {base:.1}",
+                          pct = (1.0 - curr / base) * 100.0))
+             .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regressions() {
+        let baseline = ImportReport {
+            pages: 1000,
+            text_len: 1000,
+            dump_bytes: 1_000_000,
+            threads: 4,
+            parse_secs: 1.0,
+            parse_pages_per_sec: 1000.0,
+            parse_mb_per_sec: 1.0,
+            import_secs: 2.0,
+            import_pages_per_sec: 500.0,
+            chunks: 1,
+            chunk_bytes: 1_000_000,
+            chunk_write_mb_per_sec: 0.5,
+            index_rows: 4000,
+            index_rows_per_sec: 2000.0,
+            index_commit_secs: 0.5,
+            index_rows_per_commit_sec: 8000.0,
+        };
+        assert!(baseline.regressions(&baseline, 0.1).is_empty());
+
+        let slower = ImportReport {
+            import_pages_per_sec: 400.0,
+            index_rows_per_sec: 1900.0,
+            ..baseline.clone()
+        };
+        let regressions = slower.regressions(&baseline, 0.1);
+        assert_eq!(regressions.len(), 1);
+        assert!(regressions[0].starts_with("import_pages_per_sec regressed 20.0%"));
+    }
+}
@@ -1,3 +1,7 @@
+/// The version of the chunk file format defined in `capnp/wikimedia.capnp`. Incremented when
+/// a schema change means chunk files written before it can't be read.
+pub const SCHEMA_VERSION: u32 = 1;
+
 #[path = "../capnp/generated/wikimedia_capnp.rs"]
 #[allow(dead_code)] // Allow unused generated code.
 pub mod wikimedia_capnp;
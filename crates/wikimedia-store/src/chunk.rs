@@ -5,21 +5,24 @@
 use anyhow::{bail, Context, format_err};
 use crate::{
     capnp::wikimedia_capnp as wmc,
+    config,
+    pread::{self, ChecksumWriter, PreadSegments},
+    remote::RemoteSegments,
 };
 use capnp::{
-    message::{HeapAllocator, Reader, ReaderOptions, TypedBuilder,
+    message::{Allocator, HeapAllocator, Reader, ReaderOptions, ReaderSegments, TypedBuilder,
               TypedReader},
     serialize::BufferSegments,
 };
 use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use crossbeam_utils::CachePadded;
-use memmap2::Mmap;
+use memmap2::{Mmap, MmapMut};
 use serde::Serialize;
 use std::{
     cmp,
     fmt::{self, Debug, Display},
     fs,
-    io::{BufWriter, Seek, Write},
+    io::{self, BufReader, BufWriter, Write},
     marker::PhantomData,
     path::{Path, PathBuf},
     result::Result as StdResult,
@@ -50,40 +53,153 @@ pub(crate) struct Store {
 pub(crate) struct Options {
     pub dump_name: DumpName,
     pub max_chunk_len: u64,
+    pub max_pages_per_chunk: u64,
     pub path: PathBuf,
+    pub read_mode: ChunkReadMode,
+    pub size_strategy: ChunkSizeStrategy,
+
+    /// The store's root directory, whose config file records the next chunk ID, see
+    /// `config::Config::next_chunk_id`.
+    pub store_path: PathBuf,
+}
+
+/// Which limits make a chunk full during an import, see [`Builder::is_full`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ChunkSizeStrategy {
+    /// Full when the chunk's estimated length passes the maximum chunk length in bytes.
+    #[default]
+    Bytes,
+
+    /// Full when the chunk has the maximum pages per chunk.
+    Pages,
+
+    /// Full when either limit is reached.
+    First,
+}
+
+/// How chunk files are read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ChunkReadMode {
+    /// Memory map each chunk file. The fastest, but on a network filesystem such as NFS or
+    /// SMB a mapping can silently return stale or torn data.
+    #[default]
+    Mmap,
+
+    /// Read each chunk file with positional reads, checking the blocks read against the
+    /// chunk's checksum file and reading them again on a mismatch. Slower and uses more
+    /// memory, but correct for stores on network filesystems. See the `pread` module.
+    Pread,
 }
 
 pub(crate) struct WriteLockGuard<'lock> {
     _inner: fd_lock::RwLockWriteGuard<'lock, fs::File>,
+    dump_name: DumpName,
     max_chunk_len: u64,
+    max_pages_per_chunk: u64,
+    size_strategy: ChunkSizeStrategy,
     next_chunk_id: CachePadded<AtomicU64>,
     out_dir: PathBuf,
+    read_mode: ChunkReadMode,
+    store_path: PathBuf,
     temp_dir: PathBuf,
 }
 
+/// Writes pages to a new chunk file.
+///
+/// The page count of a chunk isn't known until it is full, and the capnp list of pages must
+/// be sized when it is created, so each pushed page is serialised straight away to a spill
+/// file of single page messages. `write_pending()` then copies them one at a time into the
+/// chunk's message, whose one segment is a memory map of the pending chunk file, see
+/// [`FileSegmentAllocator`]. This means neither the pushed `dump::Page` values nor the
+/// chunk's message are kept in memory.
+///
+/// The spill file is removed when the builder is written or dropped. Spill files left by a
+/// process that stopped are removed when the chunk store's write lock is next taken.
 pub(crate) struct Builder<'lock> {
-    capb: TypedBuilder<wmc::chunk::Owned, HeapAllocator>,
+    checksums_path: PathBuf,
     chunk_id: ChunkId,
-    curr_bytes_len_estimate: u64,
     max_chunk_len: u64,
+    max_pages_per_chunk: u64,
+    size_strategy: ChunkSizeStrategy,
     out_path: PathBuf,
-    pages: Vec<dump::Page>,
-    temp_path: PathBuf,
+    pages_len: u64,
+    spill: BufWriter<fs::File>,
+    spill_file: RemoveOnDrop,
+
+    /// Total size of the page messages in `spill`, in capnp words.
+    spill_words: u64,
+    pending_path: PathBuf,
 
     phantom_lock: PhantomData<&'lock WriteLockGuard<'lock>>,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A file removed when this is dropped, unless [`RemoveOnDrop::keep`] is called first, e.g. so
+/// a partly written file isn't left behind when writing it returns an error.
+struct RemoveOnDrop {
+    path: Option<PathBuf>,
+}
+
+/// Allocates the first segment of a chunk message in a memory map of the chunk file, so the
+/// message is written to the file as it's built, and the kernel can write its pages back
+/// and drop them from memory. Later segments, only needed if the first is too short, are
+/// allocated on the heap.
+struct FileSegmentAllocator<'map> {
+    first: Option<&'map mut [u8]>,
+    first_ptr: *const u8,
+    heap: HeapAllocator,
+}
+
+/// The length of the segment table of a message with one segment.
+const ONE_SEGMENT_TABLE_LEN: u64 = 8;
+
+/// The extension of a [`Builder`]'s spill file, in the chunk store's temporary directory.
+const SPILL_FILE_EXTENSION: &str = "pages";
+
+/// The extension of the file a [`Builder`] writes a chunk message to when it doesn't fit in
+/// the pending file's one segment, in the chunk store's temporary directory.
+const FULL_MESSAGE_FILE_EXTENSION: &str = "cap";
+
+/// A chunk file written to its pending path by [`Builder::write_pending`], not yet visible
+/// to readers.
+///
+/// Imports commit a chunk in phases: first the index records the chunk as pending, without
+/// its pages, then [`PendingChunk::commit`] renames the file to its chunk path, then the
+/// chunk's pages are committed to the index with the chunk, so readers never find pages
+/// whose chunk file isn't there yet. If the import stops before the pages are committed,
+/// [`WriteLockGuard::recover_pending`] rolls the chunk back when the store is next opened,
+/// and the pages are imported again when the import continues.
+pub(crate) struct PendingChunk {
+    meta: ChunkMeta,
+    pending_path: PathBuf,
+}
+
+/// The result of [`WriteLockGuard::recover_pending`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RecoveredChunks {
+    /// Pending chunks whose files are now at their chunk paths.
+    pub committed: Vec<ChunkId>,
+
+    /// Pending chunks whose files are now removed.
+    pub rolled_back: Vec<ChunkId>,
+
+    /// Pending chunks with no chunk file or pending file.
+    pub lost: Vec<ChunkId>,
+
+    /// Pending files of chunks that were never committed to the index, now removed.
+    pub stray_files_removed: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct StorePageId {
     pub(crate) chunk_id: ChunkId,
     pub(crate) page_chunk_index: PageChunkIndex,
 }
 
-#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Serialize, Valuable)]
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Valuable)]
 #[serde(transparent)]
 pub struct ChunkId(pub(crate) u64);
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct PageChunkIndex(pub(crate) u64);
 
 pub struct MappedChunk {
@@ -91,7 +207,15 @@ pub struct MappedChunk {
     id: ChunkId,
     len: u64,
     path: PathBuf,
-    reader: TypedReader<BufferSegments<Mmap>, wmc::chunk::Owned>,
+    reader: TypedReader<ChunkSegments, wmc::chunk::Owned>,
+}
+
+/// The capnp segments of a chunk, either memory mapped or read on demand from a local
+/// file, or fetched on demand from a remote store.
+pub(crate) enum ChunkSegments {
+    Mapped(BufferSegments<Mmap>),
+    Pread(PreadSegments),
+    Remote(RemoteSegments),
 }
 
 pub struct MappedPage {
@@ -103,6 +227,11 @@ pub struct MappedPage {
 #[derive(Clone, Debug, Serialize, Valuable)]
 pub struct ChunkMeta {
     pub bytes_len: Bytes,
+
+    /// The length the chunk's builder estimated while pages were pushed, which decided
+    /// when the chunk was full. `None` except for chunks just written by an import.
+    pub bytes_len_estimate: Option<Bytes>,
+
     pub id: ChunkId,
     pub pages_len: u64,
     pub path: PathBuf,
@@ -115,6 +244,20 @@ struct ChunksStats {
 
 pub const MAX_LEN_DEFAULT: u64 = 10_000_000; // 10 MB.
 
+pub const MAX_PAGES_DEFAULT: u64 = 100_000;
+
+/// The capnp words in a chunk message that are not in its pages: the segment table for one
+/// segment, the root pointer, the chunk struct's pointer to its pages, and the pages list's
+/// tag.
+const CHUNK_OVERHEAD_WORDS: u64 = 4;
+
+/// The capnp words in each spilled page message that are not copied into the chunk: the
+/// segment table for one segment and the root pointer.
+const SPILL_PAGE_OVERHEAD_WORDS: u64 = 2;
+
+/// A chunk's pages are a capnp list, which has a 32-bit length.
+pub const MAX_PAGES_MAX: u64 = u32::MAX as u64;
+
 impl FromStr for ChunkId {
     type Err = anyhow::Error;
 
@@ -175,6 +318,12 @@ impl Display for StorePageId {
     }
 }
 
+impl Serialize for StorePageId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl Options {
     pub fn build(self) -> Result<Store> {
         Store::new(self)
@@ -198,8 +347,10 @@ impl Store {
 
         let chunks_path = &*self.opts.path;
         if chunks_path.try_exists()? {
-            for chunk_id in Self::chunk_id_iter_from_opts(opts) {
+            record_next_chunk_id(&opts.store_path, Self::next_chunk_id(opts)?)?;
+            for chunk_id in chunk_id_iter_in(&opts.path) {
                 let chunk_path = chunk_path(&*opts.path, chunk_id?);
+                remove_file_if_exists(&pread::checksums_path(&chunk_path))?;
                 fs::remove_file(chunk_path)?;
             }
         }
@@ -212,25 +363,43 @@ impl Store {
     {
         let inner_guard = self.lock.try_write()?;
 
-        let chunks_stats = Self::get_chunk_stats(&self.opts)?;
+        // No builder holds the lock now, so any spill files are left by a writer that stopped.
+        remove_stale_spill_files(&self.opts.path)?;
 
-        let next_chunk_id = match chunks_stats.max_id {
-            Some(ChunkId(id)) => ChunkId(id + 1),
-            None => ChunkId(0),
-        };
+        let next_chunk_id = Self::next_chunk_id(&self.opts)?;
 
         tracing::debug!(%next_chunk_id,
                         "store::chunk::Store::try_write_lock() succeeded");
 
         Ok(WriteLockGuard {
             _inner: inner_guard,
+            dump_name: self.opts.dump_name.clone(),
             max_chunk_len: self.opts.max_chunk_len,
+            max_pages_per_chunk: self.opts.max_pages_per_chunk,
+            size_strategy: self.opts.size_strategy,
             next_chunk_id: CachePadded::new(AtomicU64::new(next_chunk_id.0)),
             out_dir: self.opts.path.to_owned(),
+            read_mode: self.opts.read_mode,
+            store_path: self.opts.store_path.to_owned(),
             temp_dir: self.temp_dir.path()?.to_owned(),
         })
     }
 
+    /// The ID for the next chunk written: after the highest chunk file, and not less than the
+    /// next chunk ID recorded in the store config file, so the IDs of removed chunks aren't
+    /// used again.
+    fn next_chunk_id(opts: &Options) -> Result<ChunkId> {
+        let chunks_stats = Self::get_chunk_stats(opts)?;
+        let after_files = match chunks_stats.max_id {
+            Some(ChunkId(id)) => id + 1,
+            None => 0,
+        };
+        let recorded = config::Config::read(&opts.store_path)?
+                           .and_then(|config| config.next_chunk_id)
+                           .unwrap_or(0);
+        Ok(ChunkId(after_files.max(recorded)))
+    }
+
     fn init_lock(opts: &Options) -> Result<fd_lock::RwLock<fs::File>> {
         let lock_path = opts.path.join("lock");
 
@@ -254,52 +423,6 @@ impl Store {
         Ok(Some(page))
     }
 
-    pub fn chunk_id_vec(&self) -> Result<Vec<ChunkId>> {
-        let mut vec: Vec<ChunkId> = self.chunk_id_iter().try_collect()?;
-        vec.sort();
-        Ok(vec)
-    }
-
-    pub fn chunk_id_iter(&self) -> impl Iterator<Item = Result<ChunkId>> {
-        Self::chunk_id_iter_from_opts(&self.opts)
-    }
-
-    fn chunk_id_iter_from_opts(opts: &Options) -> impl Iterator<Item = Result<ChunkId>> + Send {
-        // This closure is to specify the return type explicitly.
-        // Without this the return type is inferred from the first return
-        // and doesn't include the `dyn`, so the subsequent ones fail to type check.
-        (|| -> Box<dyn Iterator<Item = Result<ChunkId>> + Send> {
-            let read_dir = match fs::read_dir(&*opts.path) {
-                Err(e) if e.kind() == std::io::ErrorKind::NotFound =>
-                    return std::iter::empty().boxed_send(),
-                Err(e) => return std::iter::once(Err(e.into())).boxed_send(),
-                Ok(d) => d,
-            };
-            read_dir.flat_map(|item: StdResult<fs::DirEntry, _>| -> Option<Result<ChunkId>>{
-                let item = match item {
-                    Ok(item) => item,
-                    Err(e) => return Some(Err(e.into())),
-                };
-                let name = match item.file_name().into_string() {
-                    Ok(name) => name,
-                    Err(oss) => return Some(Err(
-                        format_err!("Cannot convert item name into String: '{oss}'",
-                                    oss = oss.to_string_lossy().to_string()))),
-                };
-
-                let Some(captures) = lazy_regex!("^articles-([0-9a-f]{16}).cap$").captures(&*name)
-                else {
-                    return None;
-                };
-
-                let id_hex = captures.get(1).expect("regex capture 1 is None").as_str();
-                let id = u64::from_str_radix(id_hex, 16)
-                             .expect("parse u64 from prevalidated hex String");
-                Some(Ok(ChunkId(id)))
-            }).boxed_send()
-        })()
-    }
-
     fn get_chunk_stats(opts: &Options) -> Result<ChunksStats> {
         let chunk_iter_span = tracing::trace_span!("ChunkStore enumerating existing chunks.",
                                                    chunk_count = tracing::field::Empty,
@@ -307,7 +430,7 @@ impl Store {
                                       .entered();
 
         let chunk_stats: ChunksStats =
-            Store::chunk_id_iter_from_opts(&opts)
+            chunk_id_iter_in(&opts.path)
                 .try_fold(ChunksStats { count: 0, max_id: None }, // inital state
                           |s: ChunksStats, next: Result<ChunkId>|
                           -> Result<ChunksStats> {
@@ -333,38 +456,227 @@ impl Store {
         Ok(Some(chunk.meta()?))
     }
 
+    pub fn chunk_exists(&self, chunk_id: ChunkId) -> Result<bool> {
+        Ok(chunk_path(&self.opts.path, chunk_id).try_exists()?)
+    }
+
     pub fn map_chunk(&self, id: ChunkId) -> Result<Option<MappedChunk>> {
-        let path = chunk_path(&*self.opts.path, id);
+        map_chunk(&self.opts.dump_name, &self.opts.path, self.opts.read_mode, id)
+    }
+}
 
-        let file = match fs::File::open(&*path) {
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
-            Err(e) => return Err(e.into()),
-            Ok(f) => f,
-        };
-        let mmap = unsafe {
-            memmap2::MmapOptions::new()
-                .map(&file)?
-        };
-        let len = mmap.len().try_into().expect("usize as u64");
+/// Map the file of chunk `id` in `dir`, or return `None` if it doesn't exist.
+fn map_chunk(dump_name: &DumpName, dir: &Path, read_mode: ChunkReadMode, id: ChunkId
+) -> Result<Option<MappedChunk>> {
+    let path = chunk_path(dir, id);
 
-        let segments = BufferSegments::new(mmap, ReaderOptions::default())?;
-        let reader = Reader::new(segments, ReaderOptions::default());
-        let typed_reader = reader.into_typed::<wmc::chunk::Owned>();
+    if read_mode == ChunkReadMode::Pread {
+        // Closure to add context to errors.
+        return (|| -> Result<Option<MappedChunk>> {
+            let segments = try2!(PreadSegments::open(&path));
+            // The root struct is in the first segment, so any error reading it is
+            // returned here.
+            segments.load(0)?;
+            Ok(Some(MappedChunk::new(dump_name.clone(), id, segments.len(),
+                                     path.clone(), ChunkSegments::Pread(segments))))
+        })().with_context(|| format!("reading chunk file path='{}'", path.display()));
+    }
 
-        let chunk = MappedChunk {
-            dump_name: self.opts.dump_name.clone(),
-            id,
-            len,
-            path: path.clone(),
-            reader: typed_reader,
+    let file = match fs::File::open(&*path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+        Ok(f) => f,
+    };
+    let mmap = unsafe {
+        memmap2::MmapOptions::new()
+            .map(&file)?
+    };
+    let len = mmap.len().try_into().expect("usize as u64");
+
+    // BufferSegments::new() panics if the segment table claims more words than the
+    // file has, e.g. for a truncated chunk file, so check that first.
+    capnp::serialize::read_message_from_flat_slice(&mut &*mmap, ReaderOptions::default())
+        .with_context(|| format!("reading chunk file path='{}'", path.display()))?;
+    let segments = BufferSegments::new(mmap, ReaderOptions::default())?;
+
+    Ok(Some(MappedChunk::new(dump_name.clone(), id, len, path,
+                             ChunkSegments::Mapped(segments))))
+}
+
+/// The IDs of the chunk files in `dir`, in no particular order.
+fn chunk_id_iter_in(dir: &Path) -> impl Iterator<Item = Result<ChunkId>> + Send {
+    // This closure is to specify the return type explicitly.
+    // Without this the return type is inferred from the first return
+    // and doesn't include the `dyn`, so the subsequent ones fail to type check.
+    (|| -> Box<dyn Iterator<Item = Result<ChunkId>> + Send> {
+        let read_dir = match fs::read_dir(dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound =>
+                return std::iter::empty().boxed_send(),
+            Err(e) => return std::iter::once(Err(e.into())).boxed_send(),
+            Ok(d) => d,
         };
+        read_dir.flat_map(|item: StdResult<fs::DirEntry, _>| -> Option<Result<ChunkId>>{
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let name = match item.file_name().into_string() {
+                Ok(name) => name,
+                Err(oss) => return Some(Err(
+                    format_err!("Cannot convert item name into String: '{oss}'",
+                                oss = oss.to_string_lossy().to_string()))),
+            };
 
-        Ok(Some(chunk))
-    }
+            let captures = lazy_regex!("^articles-([0-9a-f]{16}).cap$").captures(&*name)?;
+
+            let id_hex = captures.get(1).expect("regex capture 1 is None").as_str();
+            let id = u64::from_str_radix(id_hex, 16)
+                         .expect("parse u64 from prevalidated hex String");
+            Some(Ok(ChunkId(id)))
+        }).boxed_send()
+    })()
 }
 
 fn chunk_path(dir: &Path, chunk_id: ChunkId) -> PathBuf {
-    dir.join(format!("articles-{id:016x}.cap", id = chunk_id.0))
+    dir.join(chunk_file_name(chunk_id))
+}
+
+pub(crate) fn chunk_file_name(chunk_id: ChunkId) -> String {
+    format!("articles-{id:016x}.cap", id = chunk_id.0)
+}
+
+/// The path a chunk file is written to before it's committed, see [`PendingChunk`].
+fn pending_chunk_path(dir: &Path, chunk_id: ChunkId) -> PathBuf {
+    dir.join(format!("{name}.pending", name = chunk_file_name(chunk_id)))
+}
+
+fn remove_file_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Record `next_chunk_id` in the config file of the store at `store_path`, unless it
+/// already has a higher one. Stores without a config file, e.g. in tests, record nothing.
+fn record_next_chunk_id(store_path: &Path, next_chunk_id: ChunkId) -> Result<()> {
+    let Some(mut config) = config::Config::read(store_path)? else {
+        return Ok(());
+    };
+    if config.next_chunk_id.is_some_and(|recorded| recorded >= next_chunk_id.0) {
+        return Ok(());
+    }
+    config.next_chunk_id = Some(next_chunk_id.0);
+    config.write(store_path)
+}
+
+/// Remove the files [`Builder`]s write in the temporary directories of chunk stores at
+/// `dir`, see [`TempDir`]. Only call this with the write lock held.
+fn remove_stale_spill_files(dir: &Path) -> Result<()> {
+    let temp_root = dir.join("temp");
+    if !temp_root.try_exists()? {
+        return Ok(());
+    }
+
+    let mut removed = 0_u64;
+    for temp_dir in fs::read_dir(&temp_root)? {
+        let temp_dir = temp_dir?;
+        if !temp_dir.file_type()?.is_dir() {
+            continue;
+        }
+        for item in fs::read_dir(temp_dir.path())? {
+            let path = item?.path();
+            if path.extension().is_some_and(|ext| ext == SPILL_FILE_EXTENSION
+                                                   || ext == FULL_MESSAGE_FILE_EXTENSION) {
+                remove_file_if_exists(&path)?;
+                removed += 1;
+            }
+        }
+    }
+
+    if removed > 0 {
+        tracing::info!(removed, "Removed chunk spill files left by a writer that stopped");
+    }
+    Ok(())
+}
+
+/// Flush a directory's entries to disk, so a rename in it survives a crash.
+fn sync_dir(dir: &Path) -> Result<()> {
+    fs::File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .with_context(|| format!("syncing directory path='{}'", dir.display()))?;
+    Ok(())
+}
+
+fn set_page(page: &dump::Page, rendered_html: Option<&str>, mut page_cap: wmc::page::Builder) {
+    page_cap.set_ns_id(page.ns_id);
+    page_cap.set_id(page.id);
+    page_cap.set_title(&page.title);
+    if let Some(revision) = page.revision.as_ref() {
+        let mut revision_cap = page_cap.init_revision();
+        revision_cap.set_id(revision.id);
+
+        if let Some(text) = revision.text.as_ref() {
+            revision_cap.reborrow().set_text(text.as_str());
+        }
+        if let Some(html) = rendered_html {
+            revision_cap.reborrow().set_rendered_html(html);
+        }
+
+        {
+            let mut parent_id_build = revision_cap.reborrow().init_parent_id();
+            match revision.parent_id {
+                None => parent_id_build.set_none(()),
+                Some(parent_id) => parent_id_build.set_some(parent_id),
+            }
+        }
+
+        {
+            let mut ts_build = revision_cap.reborrow().init_timestamp();
+            match revision.timestamp {
+                None => ts_build.set_none(()),
+                Some(dt) => {
+                    let mut ts_some_build = ts_build.init_some();
+                    ts_some_build.set_utc_timestamp_secs(dt.naive_utc().timestamp());
+                }
+            }
+        }
+
+        {
+            let mut sha1_build = revision_cap.reborrow().init_sha1();
+            match revision.sha1.as_ref() {
+                None => sha1_build.set_none(()),
+                Some(sha1) => {
+                    let mut sha1_some_build = sha1_build.init_some();
+                    sha1_some_build.set_hash0(
+                        u64::from_be_bytes(sha1.0[0..8].try_into().unwrap()));
+                    sha1_some_build.set_hash8(
+                        u64::from_be_bytes(sha1.0[8..16].try_into().unwrap()));
+                    sha1_some_build.set_hash16(
+                        u32::from_be_bytes(sha1.0[16..20].try_into().unwrap()));
+                }
+            }
+        }
+    }
+}
+
+impl ReaderSegments for ChunkSegments {
+    fn get_segment(&self, idx: u32) -> Option<&[u8]> {
+        match self {
+            ChunkSegments::Mapped(segments) => segments.get_segment(idx),
+            ChunkSegments::Pread(segments) => segments.get_segment(idx),
+            ChunkSegments::Remote(segments) => segments.get_segment(idx),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            ChunkSegments::Mapped(segments) => segments.len(),
+            ChunkSegments::Pread(segments) => ReaderSegments::len(segments),
+            ChunkSegments::Remote(segments) => segments.len(),
+        }
+    }
 }
 
 impl<'lock> WriteLockGuard<'lock> {
@@ -377,134 +689,378 @@ impl<'lock> WriteLockGuard<'lock> {
         let chunk_id = self.next_chunk_id();
 
         let out_path = chunk_path(&*self.out_dir, chunk_id);
+        let pending_path = pending_chunk_path(&self.out_dir, chunk_id);
         let temp_path = self.temp_dir.join(
             out_path.file_name().expect("Chunk file name"));
 
+        let spill_path = temp_path.with_extension(SPILL_FILE_EXTENSION);
+
         fs::create_dir_all(out_path.parent().expect("parent of out_path"))?;
         fs::create_dir_all(temp_path.parent().expect("parent of temp_path"))?;
 
+        let spill = BufWriter::with_capacity(64 * 1024, fs::File::create(&*spill_path)?);
+        let spill_file = RemoveOnDrop::new(spill_path);
+
         Ok(Builder {
-            capb: TypedBuilder::<wmc::chunk::Owned, HeapAllocator>::new_default(),
+            checksums_path: pread::checksums_path(&out_path),
             chunk_id,
-            curr_bytes_len_estimate: 0,
             max_chunk_len: self.max_chunk_len,
+            max_pages_per_chunk: self.max_pages_per_chunk,
+            size_strategy: self.size_strategy,
             out_path,
-            pages: Vec::new(),
-            temp_path,
+            pages_len: 0,
+            spill,
+            spill_file,
+            spill_words: 0,
+            pending_path,
 
             phantom_lock: PhantomData,
         })
     }
+
+    /// The IDs of all chunk files in the store in ascending order, whether or not the index
+    /// has them.
+    pub(crate) fn chunk_ids(&self) -> Result<Vec<ChunkId>> {
+        let mut ids = chunk_id_iter_in(&self.out_dir).collect::<Result<Vec<ChunkId>>>()?;
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Map the file of chunk `id`, or return `None` if it doesn't exist.
+    pub(crate) fn map_chunk(&self, id: ChunkId) -> Result<Option<MappedChunk>> {
+        map_chunk(&self.dump_name, &self.out_dir, self.read_mode, id)
+    }
+
+    /// Remove the files of chunks `chunk_ids` and their checksums, e.g. once the index has no
+    /// pages in them. Missing files are not an error.
+    ///
+    /// The next chunk ID is recorded in the store config file first, so the IDs of the
+    /// removed chunks aren't used again even if they were the highest. The deletion audit
+    /// log and chunk provenance refer to chunks by ID.
+    pub(crate) fn remove_chunks(&self, chunk_ids: &[ChunkId]) -> Result<()> {
+        if chunk_ids.is_empty() {
+            return Ok(());
+        }
+
+        record_next_chunk_id(&self.store_path,
+                             ChunkId(self.next_chunk_id.load(Ordering::SeqCst)))?;
+        for &chunk_id in chunk_ids {
+            let path = chunk_path(&self.out_dir, chunk_id);
+            remove_file_if_exists(&pread::checksums_path(&path))?;
+            remove_file_if_exists(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Recover the chunks the index records as pending, see [`PendingChunk`]. Chunks in
+    /// `roll_back_ids` have no pages in the index, so their files are removed. Chunks in
+    /// `finish_ids` have pages in the index, written by a version that committed pages before
+    /// renaming the chunk file, so their renames are finished. Pending files of chunks the
+    /// index doesn't have are removed.
+    pub(crate) fn recover_pending(&self, finish_ids: &[ChunkId], roll_back_ids: &[ChunkId]
+    ) -> Result<RecoveredChunks> {
+        let mut recovered = RecoveredChunks::default();
+
+        for &chunk_id in roll_back_ids {
+            let out_path = chunk_path(&self.out_dir, chunk_id);
+            remove_file_if_exists(&pending_chunk_path(&self.out_dir, chunk_id))?;
+            remove_file_if_exists(&pread::checksums_path(&out_path))?;
+            remove_file_if_exists(&out_path)?;
+            recovered.rolled_back.push(chunk_id);
+        }
+
+        for &chunk_id in finish_ids {
+            let out_path = chunk_path(&self.out_dir, chunk_id);
+            let pending_path = pending_chunk_path(&self.out_dir, chunk_id);
+            if out_path.try_exists()? {
+                recovered.committed.push(chunk_id);
+            } else if pending_path.try_exists()? {
+                fs::rename(&pending_path, &out_path)?;
+                sync_dir(&self.out_dir)?;
+                recovered.committed.push(chunk_id);
+            } else {
+                recovered.lost.push(chunk_id);
+                continue;
+            }
+
+            // Chunk IDs are allocated after the highest chunk file, which this might be now.
+            self.next_chunk_id.fetch_max(chunk_id.0 + 1, Ordering::SeqCst);
+        }
+
+        if self.out_dir.try_exists()? {
+            for item in fs::read_dir(&self.out_dir)? {
+                let item = item?;
+                let name = item.file_name();
+                let Some(captures) = lazy_regex!("^articles-([0-9a-f]{16}).cap.pending$")
+                                         .captures(&name.to_string_lossy())
+                                         .map(|c| c[1].to_string())
+                else {
+                    continue;
+                };
+                let chunk_id = ChunkId(u64::from_str_radix(&captures, 16)
+                                           .expect("parse u64 from prevalidated hex String"));
+                if !finish_ids.contains(&chunk_id) {
+                    fs::remove_file(item.path())?;
+                    remove_file_if_exists(
+                        &pread::checksums_path(&chunk_path(&self.out_dir, chunk_id)))?;
+                    recovered.stray_files_removed += 1;
+                }
+            }
+        }
+
+        Ok(recovered)
+    }
 }
 
 impl<'lock> Builder<'lock> {
     pub fn push(&mut self, page: &dump::Page) -> Result<StorePageId> {
-        let page = page.clone();
-        self.curr_bytes_len_estimate +=
-            u64::try_from(page.title.len() +
-            match page.revision {
-                Some(dump::Revision { text: Some(ref text), .. }) => text.len(),
-                _ => 0,
-            }).expect("usize as u64");
-        self.pages.push(page);
-        let idx = self.pages.len() - 1;
+        self.push_with_rendered_html(page, None)
+    }
+
+    /// Push a page with its revision's text rendered to HTML, see
+    /// [`MappedPage::rendered_html`]. `rendered_html` is dropped if the page has no revision.
+    pub fn push_with_rendered_html(&mut self, page: &dump::Page, rendered_html: Option<&str>
+    ) -> Result<StorePageId> {
+        let mut page_capb = TypedBuilder::<wmc::page::Owned, HeapAllocator>::new_default();
+        set_page(page, rendered_html, page_capb.init_root());
+        self.spill_words += u64::try_from(
+            capnp::serialize::compute_serialized_size_in_words(page_capb.borrow_inner()))
+            .expect("usize as u64");
+        capnp::serialize::write_message(&mut self.spill, page_capb.borrow_inner())?;
+
+        let idx = self.pages_len;
+        self.pages_len += 1;
 
         Ok(StorePageId {
             chunk_id: self.chunk_id,
-            page_chunk_index: PageChunkIndex(idx.try_into().expect("usize as u64")),
+            page_chunk_index: PageChunkIndex(idx),
         })
     }
 
-    pub fn write_all(mut self) -> Result<ChunkMeta> {
-        let pages_len = self.pages.len();
-        let chunk_cap: wmc::chunk::Builder = self.capb.init_root();
-        let mut pages_cap = chunk_cap.init_pages(pages_len.try_into()
-                                                     .expect("pages.len() usize into u32"));
-
-        let pages = std::mem::take(&mut self.pages);
-        for (idx, page) in pages.into_iter().enumerate() {
-            let mut page_cap = pages_cap.reborrow().try_get(idx.try_into()
-                                    .expect("page chunk index u32 from usize"))
-                                    .expect("pages_cap.len() == pages.len()");
-            page_cap.set_ns_id(page.ns_id);
-            page_cap.set_id(page.id);
-            page_cap.set_title(&*page.title);
-            if let Some(revision) = page.revision {
-                let mut revision_cap = page_cap.init_revision();
-                revision_cap.set_id(revision.id);
-
-                if let Some(text) = revision.text {
-                    revision_cap.reborrow().set_text(text.as_str());
-                }
+    /// Write the chunk to its pending path. See [`PendingChunk`].
+    pub fn write_pending(self) -> Result<PendingChunk> {
+        // The estimate is an upper bound, see `words_estimate()`, so the whole chunk fits in
+        // one segment of that size less the segment table.
+        let bytes_len_estimate = self.curr_bytes_len_estimate();
+        let segment_words = u32::try_from(self.words_estimate() - 1).unwrap_or(u32::MAX);
+
+        let spill_file = self.spill.into_inner().map_err(|e| e.into_error())?;
+        drop(spill_file);
+
+        let pending_file_guard = RemoveOnDrop::new(self.pending_path.clone());
+        let pending_file = fs::OpenOptions::new()
+                               .read(true)
+                               .write(true)
+                               .create(true)
+                               .truncate(true)
+                               .open(&self.pending_path)?;
+        pending_file.set_len(ONE_SEGMENT_TABLE_LEN + u64::from(segment_words) * 8)?;
+        let mut map = unsafe { MmapMut::map_mut(&pending_file)? };
+        let (segment_table, segment) = map.split_at_mut(
+            usize::try_from(ONE_SEGMENT_TABLE_LEN).expect("u64 as usize"));
+
+        let mut capb = TypedBuilder::<wmc::chunk::Owned, FileSegmentAllocator>::new(
+            capnp::message::Builder::new(FileSegmentAllocator::new(segment)));
+
+        let chunk_cap: wmc::chunk::Builder = capb.init_root();
+        let mut pages_cap = chunk_cap.init_pages(self.pages_len.try_into()
+                                                     .expect("pages_len u64 into u32"));
+
+        let spill_path = self.spill_file.path().to_owned();
+        let mut spill_read = BufReader::with_capacity(64 * 1024, fs::File::open(&*spill_path)?);
+        let read_options = *ReaderOptions::new().traversal_limit_in_words(None);
+        for idx in 0..pages_cap.len() {
+            let page_message = capnp::serialize::read_message(&mut spill_read, read_options)?;
+            let page_cap = page_message.get_root::<wmc::page::Reader>()?;
+            pages_cap.set_with_caveats(idx, page_cap)?;
+        }
+        drop(spill_read);
+        drop(self.spill_file);
+
+        let segments = capb.borrow_inner().get_segments_for_output();
+        let bytes_len = match &*segments {
+            [segment] => {
+                let segment_words = u32::try_from(segment.len() / 8).expect("u32 segment len");
+                drop(segments);
+                drop(capb);
+
+                segment_table[0..4].copy_from_slice(&0_u32.to_le_bytes());
+                segment_table[4..8].copy_from_slice(&segment_words.to_le_bytes());
+                map.flush()?;
+                drop(map);
+
+                let bytes_len = ONE_SEGMENT_TABLE_LEN + u64::from(segment_words) * 8;
+                pending_file.set_len(bytes_len)?;
+                pending_file.sync_all()?;
+                bytes_len
+            },
+            _ => {
+                // The first segment was too short, so write the whole message to another file
+                // and replace the pending file with it.
+                tracing::warn!(chunk_id = %self.chunk_id, segments_len = segments.len(),
+                               bytes_len_estimate,
+                               "Chunk message was longer than its estimated length");
+                drop(segments);
+                let full_path = spill_path.with_extension(FULL_MESSAGE_FILE_EXTENSION);
+                let full_file_guard = RemoveOnDrop::new(full_path.clone());
+                let mut writer = BufWriter::with_capacity(16 * 1024,
+                                                          fs::File::create(&full_path)?);
+                capnp::serialize::write_message(&mut writer, capb.borrow_inner())?;
+                writer.flush()?;
+                writer.get_ref().sync_all()?;
+                drop(writer);
+                drop(capb);
+                drop(map);
+
+                fs::rename(&full_path, &self.pending_path)?;
+                full_file_guard.keep();
+                fs::metadata(&self.pending_path)?.len()
+            },
+        };
+        drop(pending_file);
+
+        // The checksum file is written to its final path; it's only read with the chunk file.
+        let mut pending_read = BufReader::with_capacity(64 * 1024,
+                                                        fs::File::open(&self.pending_path)?);
+        let mut checksums = ChecksumWriter::new(io::sink());
+        io::copy(&mut pending_read, &mut checksums)?;
+        debug_assert_eq!(checksums.bytes_len(), bytes_len);
+        checksums.finish(&self.checksums_path)?;
+        pending_file_guard.keep();
+
+        Ok(PendingChunk {
+            meta: ChunkMeta {
+                bytes_len: Bytes(bytes_len),
+                bytes_len_estimate: Some(Bytes(bytes_len_estimate)),
+                id: self.chunk_id,
+                pages_len: self.pages_len,
+                path: self.out_path,
+            },
+            pending_path: self.pending_path,
+        })
+    }
 
-                {
-                    let mut parent_id_build = revision_cap.reborrow().init_parent_id();
-                    match revision.parent_id {
-                        None => parent_id_build.set_none(()),
-                        Some(parent_id) => parent_id_build.set_some(parent_id),
-                    }
-                }
+    /// The length in bytes the chunk file would have if it was written now, see
+    /// `words_estimate()`.
+    pub fn curr_bytes_len_estimate(&self) -> u64 {
+        self.words_estimate() * 8
+    }
 
-                {
-                    let mut ts_build = revision_cap.reborrow().init_timestamp();
-                    match revision.timestamp {
-                        None => ts_build.set_none(()),
-                        Some(dt) => {
-                            let mut ts_some_build = ts_build.init_some();
-                            ts_some_build.set_utc_timestamp_secs(dt.naive_utc().timestamp());
-                        }
-                    }
-                }
+    /// The capnp words the chunk message would have if it was written now.
+    ///
+    /// Each spilled page message is a segment table, a root pointer, and the page struct
+    /// with its content. In the chunk each page struct is inline in the pages list, so the
+    /// chunk is the page messages without their segment tables and root pointers, plus the
+    /// chunk's own overhead. Page messages of more than one segment have larger segment
+    /// tables and far pointers, which are not copied, so this is an upper bound.
+    fn words_estimate(&self) -> u64 {
+        self.spill_words - self.pages_len * SPILL_PAGE_OVERHEAD_WORDS + CHUNK_OVERHEAD_WORDS
+    }
 
-                {
-                    let mut sha1_build = revision_cap.reborrow().init_sha1();
-                    match revision.sha1 {
-                        None => sha1_build.set_none(()),
-                        Some(sha1) => {
-                            let mut sha1_some_build = sha1_build.init_some();
-                            sha1_some_build.set_hash0(
-                                u64::from_be_bytes(sha1.0[0..8].try_into().unwrap()));
-                            sha1_some_build.set_hash8(
-                                u64::from_be_bytes(sha1.0[8..16].try_into().unwrap()));
-                            sha1_some_build.set_hash16(
-                                u32::from_be_bytes(sha1.0[16..20].try_into().unwrap()));
-                        }
-                    }
-                }
-            }
+    /// Whether the chunk should be written now, by its size strategy.
+    pub fn is_full(&self) -> bool {
+        let bytes_full = self.curr_bytes_len_estimate() > self.max_chunk_len;
+        let pages_full = self.pages_len >= self.max_pages_per_chunk;
+        match self.size_strategy {
+            ChunkSizeStrategy::Bytes => bytes_full,
+            ChunkSizeStrategy::Pages => pages_full,
+            ChunkSizeStrategy::First => bytes_full || pages_full,
         }
+    }
+}
 
-        let temp_file = fs::File::create(&*self.temp_path)?;
-        let mut buf_writer = BufWriter::with_capacity(16 * 1024, temp_file);
-        capnp::serialize::write_message(&mut buf_writer, self.capb.borrow_inner())?;
-        drop(self.capb);
-        buf_writer.flush()?;
-        buf_writer.get_ref().sync_all()?;
-        let bytes_len = buf_writer.stream_position()?;
-        drop(buf_writer);
+impl PendingChunk {
+    pub fn meta(&self) -> &ChunkMeta {
+        &self.meta
+    }
 
-        fs::rename(&*self.temp_path, &*self.out_path)?;
+    /// Rename the chunk file from its pending path to its chunk path, making it visible to
+    /// readers.
+    pub fn commit(self) -> Result<ChunkMeta> {
+        fs::rename(&self.pending_path, &self.meta.path)?;
+        sync_dir(self.meta.path.parent().expect("parent of chunk path"))?;
+        Ok(self.meta)
+    }
+}
 
-        Ok(ChunkMeta {
-            bytes_len: Bytes(bytes_len),
-            id: self.chunk_id,
-            pages_len: pages_len.try_into().expect("Convert usize to u64"),
-            path: self.out_path,
-        })
+impl RemoveOnDrop {
+    fn new(path: PathBuf) -> RemoveOnDrop {
+        RemoveOnDrop {
+            path: Some(path),
+        }
     }
 
-    #[allow(dead_code)] // Not used at the moment.
-    pub fn curr_bytes_len_estimate(&self) -> u64 {
-        self.curr_bytes_len_estimate
+    fn path(&self) -> &Path {
+        self.path.as_deref().expect("RemoveOnDrop path")
     }
 
-    pub fn is_full(&self) -> bool {
-        self.curr_bytes_len_estimate > self.max_chunk_len
+    /// Keep the file instead of removing it.
+    fn keep(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        if let Err(err) = remove_file_if_exists(&path) {
+            tracing::error!(%err, path = %path.display(), "RemoveOnDrop::drop error");
+        }
+    }
+}
+
+impl<'map> FileSegmentAllocator<'map> {
+    /// `first` must be zeroed, e.g. the mapped space added to a file by extending it.
+    fn new(first: &'map mut [u8]) -> FileSegmentAllocator<'map> {
+        FileSegmentAllocator {
+            first_ptr: first.as_ptr(),
+            first: Some(first),
+            heap: HeapAllocator::new(),
+        }
+    }
+}
+
+unsafe impl Allocator for FileSegmentAllocator<'_> {
+    fn allocate_segment(&mut self, minimum_size: u32) -> (*mut u8, u32) {
+        if let Some(first) = self.first.take() {
+            let words = u32::try_from(first.len() / 8).unwrap_or(u32::MAX);
+            if words >= minimum_size {
+                return (first.as_mut_ptr(), words);
+            }
+        }
+        self.heap.allocate_segment(minimum_size)
+    }
+
+    unsafe fn deallocate_segment(&mut self, ptr: *mut u8, word_size: u32, words_used: u32) {
+        // The first segment belongs to the memory map.
+        if ptr.cast_const() != self.first_ptr {
+            unsafe { self.heap.deallocate_segment(ptr, word_size, words_used) };
+        }
     }
 }
 
 impl MappedChunk {
-    fn get_page<'a, 'b>(&'a self, idx: PageChunkIndex
+    pub(crate) fn new(
+        dump_name: DumpName,
+        id: ChunkId,
+        len: u64,
+        path: PathBuf,
+        segments: ChunkSegments,
+    ) -> MappedChunk {
+        let reader = Reader::new(segments, ReaderOptions::default());
+
+        MappedChunk {
+            dump_name,
+            id,
+            len,
+            path,
+            reader: reader.into_typed::<wmc::chunk::Owned>(),
+        }
+    }
+
+    pub(crate) fn get_page<'a, 'b>(&'a self, idx: PageChunkIndex
     ) -> Result<wmc::page::Reader<'b>>
         where 'a: 'b
     {
@@ -518,7 +1074,7 @@ impl MappedChunk {
         Ok(page)
     }
 
-    fn get_mapped_page(self, idx: PageChunkIndex) -> Result<MappedPage> {
+    pub(crate) fn get_mapped_page(self, idx: PageChunkIndex) -> Result<MappedPage> {
         Ok(MappedPage {
             dump_name: self.dump_name.clone(),
             store_id: StorePageId {
@@ -550,12 +1106,13 @@ impl MappedChunk {
         Ok(iter)
     }
 
-    fn meta(&self) -> Result<ChunkMeta> {
+    pub(crate) fn meta(&self) -> Result<ChunkMeta> {
         let chunk: wmc::chunk::Reader<'_> = self.reader.get()?;
         let pages = chunk.get_pages()?;
 
         Ok(ChunkMeta {
             bytes_len: Bytes(self.len),
+            bytes_len_estimate: None,
             id: self.id,
             pages_len: u64::from(pages.len()),
             path: self.path.clone(),
@@ -575,6 +1132,24 @@ impl MappedPage {
     pub fn store_id(&self) -> StorePageId {
         self.store_id
     }
+
+    /// The page's latest revision rendered to HTML, if it was pre-rendered, e.g. by
+    /// [`Store::prerender`](crate::Store::prerender).
+    pub fn rendered_html(&self) -> Result<Option<String>> {
+        rendered_html(&self.borrow()?)
+    }
+}
+
+/// The HTML of a page's revision if it was pre-rendered, see [`MappedPage::rendered_html`].
+pub(crate) fn rendered_html(page_cap: &wmc::page::Reader) -> Result<Option<String>> {
+    if !page_cap.has_revision() {
+        return Ok(None);
+    }
+    let rev_cap = page_cap.get_revision()?;
+    if !rev_cap.has_rendered_html() {
+        return Ok(None);
+    }
+    Ok(Some(rev_cap.get_rendered_html()?.to_string()))
 }
 
 impl<'a, 'b> TryFrom<&'a wmc::page::Reader<'b>> for dump::Page {
@@ -590,7 +1165,9 @@ impl<'a, 'b> TryFrom<&'a wmc::page::Reader<'b>> for dump::Page {
                 let rev = page.revision.as_mut()
                               .expect("page_cap has revision so page should too");
                 rev.text = Some(text.to_string());
-                rev.categories = wikitext::parse_categories(text);
+                // The wiki's own category namespace names aren't stored, so only
+                // `Category:` links are found here.
+                rev.categories = wikitext::parse_categories(text, &[]);
             }
         }
 
@@ -645,5 +1222,126 @@ pub fn convert_store_page_to_dump_page_without_body<'a, 'b>(
         } else {
             None
         },
+        // Not stored in chunks, see `dump::Page::restrictions`.
+        restrictions: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wikimedia::dump::{CategoryName, PageCategory};
+
+    #[test]
+    fn builder_bytes_len_estimate() {
+        let temp_dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                       /* keep: */ false).unwrap();
+        let mut store = Options {
+            dump_name: DumpName("testwiki".to_string()),
+            max_chunk_len: MAX_LEN_DEFAULT,
+            max_pages_per_chunk: MAX_PAGES_DEFAULT,
+            path: temp_dir.path().unwrap().join("chunks"),
+            read_mode: ChunkReadMode::Mmap,
+            size_strategy: ChunkSizeStrategy::Bytes,
+            store_path: temp_dir.path().unwrap().to_owned(),
+        }.build().unwrap();
+        let guard = store.try_write_lock().unwrap();
+        let mut builder = guard.chunk_builder().unwrap();
+
+        // Pages with many small fields, which a text length estimate would miss, and one
+        // page too long for one segment of its spill message.
+        for id in 0..101 {
+            let text_len = if id == 100 { 100_000 } else { usize::try_from(id).unwrap() };
+            builder.push(&dump::Page {
+                ns_id: 0,
+                id,
+                title: format!("Page {id}"),
+                revision: Some(dump::Revision {
+                    id,
+                    parent_id: None,
+                    timestamp: None,
+                    text: Some("x".repeat(text_len)),
+                    sha1: None,
+                    categories: (0..20).map(|idx| PageCategory {
+                        name: CategoryName(format!("C{idx}")),
+                        sort_key: None,
+                    }).collect(),
+                }),
+                restrictions: None,
+            }).unwrap();
+        }
+
+        let pending = builder.write_pending().unwrap();
+        let meta = pending.meta();
+        let estimate = meta.bytes_len_estimate.unwrap().0;
+        assert!(estimate >= meta.bytes_len.0 && estimate - meta.bytes_len.0 <= 64,
+                "estimate = {estimate}, bytes_len = {}", meta.bytes_len.0);
+
+        // The chunk reads back from its file, checked against its checksums.
+        let meta = pending.commit().unwrap();
+        assert_eq!(fs::metadata(&meta.path).unwrap().len(), meta.bytes_len.0);
+        for read_mode in [ChunkReadMode::Mmap, ChunkReadMode::Pread] {
+            let chunk = map_chunk(&DumpName("testwiki".to_string()),
+                                  &temp_dir.path().unwrap().join("chunks"), read_mode, meta.id)
+                            .unwrap().unwrap();
+            let pages = chunk.pages_iter().unwrap()
+                             .map(|(_store_id, page_cap)| dump::Page::try_from(&page_cap))
+                             .collect::<Result<Vec<dump::Page>>>().unwrap();
+            assert_eq!(pages.len(), 101);
+            assert_eq!(pages[100].revision.as_ref().unwrap().text.as_ref().unwrap().len(),
+                       100_000);
+            assert_eq!(pages[7].title, "Page 7");
+        }
+    }
+
+    #[test]
+    fn builder_removes_spill_file() {
+        let temp_dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                       /* keep: */ false).unwrap();
+        let chunks_path = temp_dir.path().unwrap().join("chunks");
+        let mut store = Options {
+            dump_name: DumpName("testwiki".to_string()),
+            max_chunk_len: MAX_LEN_DEFAULT,
+            max_pages_per_chunk: MAX_PAGES_DEFAULT,
+            path: chunks_path.clone(),
+            read_mode: ChunkReadMode::Mmap,
+            size_strategy: ChunkSizeStrategy::Bytes,
+            store_path: temp_dir.path().unwrap().to_owned(),
+        }.build().unwrap();
+
+        // Left by a writer that stopped.
+        let stale_dir = chunks_path.join("temp").join("stopped");
+        fs::create_dir_all(&stale_dir).unwrap();
+        let stale_path = stale_dir.join("articles-0000000000000000.pages");
+        fs::write(&stale_path, b"stale").unwrap();
+        let other_path = stale_dir.join("notes.txt");
+        fs::write(&other_path, b"other").unwrap();
+
+        let guard = store.try_write_lock().unwrap();
+        assert!(!stale_path.exists());
+        assert!(other_path.exists());
+
+        let page = dump::Page {
+            ns_id: 0,
+            id: 1,
+            title: "Page 1".to_string(),
+            revision: None,
+            restrictions: None,
+        };
+
+        // Dropped without writing, e.g. after an error.
+        let mut builder = guard.chunk_builder().unwrap();
+        builder.push(&page).unwrap();
+        let spill_path = builder.spill_file.path().to_owned();
+        assert!(spill_path.exists());
+        drop(builder);
+        assert!(!spill_path.exists());
+
+        let mut builder = guard.chunk_builder().unwrap();
+        builder.push(&page).unwrap();
+        let spill_path = builder.spill_file.path().to_owned();
+        let pending = builder.write_pending().unwrap();
+        assert!(!spill_path.exists());
+        assert!(pending.pending_path.exists());
+    }
+}
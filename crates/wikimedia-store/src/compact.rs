@@ -0,0 +1,143 @@
+//! Rewrite chunks without the copies of pages the index no longer refers to, so the text of
+//! deleted and superseded pages is removed from disk.
+//!
+//! Chunks are immutable, so a chunk is compacted by writing the current copies of its pages
+//! to new chunks, pointing the index at the new copies, and removing the old chunk file. The
+//! new chunks keep the old chunk's provenance and the pages' pre-rendered HTML.
+
+use crate::{
+    chunk::{self, ChunkMeta, MappedChunk},
+    ChunkId,
+    commit_pending_chunk,
+    index::{self, ChunkProvenance},
+    rebuild::LOOKUP_IDS_MAX,
+    remove_empty_chunks,
+    StorePageId,
+};
+use std::collections::HashSet;
+use wikimedia::{
+    dump,
+    Result,
+};
+
+/// Compact chunks `chunk_ids`, see [`compact`](crate::compact). Chunks whose pages are all
+/// current are left as they are, and chunks with no current pages are removed. Returns the
+/// IDs of the chunk files removed.
+pub(crate) fn compact_chunks(chunk_write_guard: &chunk::WriteLockGuard,
+                             index: &mut index::Index,
+                             chunk_ids: impl IntoIterator<Item = ChunkId>
+) -> Result<Vec<ChunkId>>
+{
+    let chunk_ids = chunk_ids.into_iter().collect::<Vec<ChunkId>>();
+    for &chunk_id in chunk_ids.iter() {
+        if index.pages_len_in_chunks(&[chunk_id])? == 0 {
+            continue;
+        }
+        let Some(chunk) = chunk_write_guard.map_chunk(chunk_id)? else {
+            continue;
+        };
+        let current = current_store_ids(index, chunk_id, &chunk)?;
+        if current.len() == chunk.pages_iter()?.count() {
+            continue;
+        }
+
+        rewrite_chunk(chunk_write_guard, index, chunk_id, &chunk, &current, None)?;
+        tracing::debug!(%chunk_id, pages_len = current.len(), "Compacted chunk");
+    }
+
+    remove_empty_chunks(chunk_write_guard, index, chunk_ids)
+}
+
+/// The store IDs of the copies of pages in `chunk`, chunk `chunk_id`, that the index refers
+/// to.
+pub(crate) fn current_store_ids(index: &index::Index, chunk_id: ChunkId, chunk: &MappedChunk
+) -> Result<HashSet<StorePageId>> {
+    let mediawiki_ids = chunk.pages_iter()?
+        .map(|(_store_id, page_cap)| page_cap.get_id())
+        .collect::<Vec<u64>>();
+    let mut current = HashSet::<StorePageId>::with_capacity(mediawiki_ids.len());
+    for ids in mediawiki_ids.chunks(LOOKUP_IDS_MAX) {
+        current.extend(index.get_pages_by_mediawiki_ids(ids)?
+                           .into_iter()
+                           .map(|page| page.store_id())
+                           .filter(|store_id| store_id.chunk_id == chunk_id));
+    }
+    Ok(current)
+}
+
+/// Write the copies of pages in `chunk`, chunk `chunk_id`, at `current` to new chunks and
+/// point the index at them. If `replacement` is given, it is written in place of the
+/// current copy of the page with its MediaWiki ID, without pre-rendered HTML, replacing
+/// that page's index rows.
+///
+/// Returns where `replacement` was written, or `None` if `chunk` has no current copy of it.
+/// The caller removes `chunk` once the index has no pages in it, see
+/// [`remove_empty_chunks`].
+pub(crate) fn rewrite_chunk(chunk_write_guard: &chunk::WriteLockGuard,
+                            index: &mut index::Index,
+                            chunk_id: ChunkId,
+                            chunk: &MappedChunk,
+                            current: &HashSet<StorePageId>,
+                            replacement: Option<&dump::Page>,
+) -> Result<Option<StorePageId>>
+{
+    let provenance = index.get_chunk_provenance(chunk_id)?;
+    let mut chunk_builder = chunk_write_guard.chunk_builder()?;
+    let mut index_batch_builder = index.import_batch_builder()?;
+    let mut chunk_pages_len = 0_u64;
+    let mut replacement_store_id = None;
+    for (store_id, page_cap) in chunk.pages_iter()? {
+        if !current.contains(&store_id) {
+            continue;
+        }
+
+        match replacement {
+            Some(page) if page.id == page_cap.get_id() => {
+                let new_store_id = chunk_builder.push(page)?;
+                index_batch_builder.replace_page(page.id);
+                index_batch_builder.push(page, new_store_id)?;
+                replacement_store_id = Some(new_store_id);
+            },
+            _ => {
+                let page = dump::Page::try_from(&page_cap)?;
+                let html = chunk::rendered_html(&page_cap)?;
+                let new_store_id = chunk_builder.push_with_rendered_html(&page,
+                                                                         html.as_deref())?;
+                index_batch_builder.move_page(page.id, new_store_id);
+            },
+        }
+        chunk_pages_len += 1;
+
+        if chunk_builder.is_full() {
+            commit_chunk(index, chunk_builder, index_batch_builder, provenance.as_ref())?;
+            chunk_builder = chunk_write_guard.chunk_builder()?;
+            index_batch_builder = index.import_batch_builder()?;
+            chunk_pages_len = 0;
+        }
+    }
+    if chunk_pages_len > 0 {
+        commit_chunk(index, chunk_builder, index_batch_builder, provenance.as_ref())?;
+    }
+
+    Ok(replacement_store_id)
+}
+
+/// Commit a chunk of pages written again and point the index at them, in phases like an
+/// import, see `Store::import_chunk()`. The new chunk keeps the replaced chunk's provenance.
+pub(crate) fn commit_chunk(
+    index: &index::Index,
+    chunk_builder: chunk::Builder,
+    mut index_batch_builder: index::ImportBatchBuilder,
+    provenance: Option<&ChunkProvenance>,
+) -> Result<ChunkMeta> {
+    let chunk_meta = commit_pending_chunk(index, chunk_builder.write_pending()?)?;
+    index_batch_builder.set_chunk(&chunk_meta);
+    if let Some(provenance) = provenance {
+        index_batch_builder.set_chunk_provenance(ChunkProvenance {
+            chunk_id: chunk_meta.id,
+            ..provenance.clone()
+        });
+    }
+    index_batch_builder.commit()?;
+    Ok(chunk_meta)
+}
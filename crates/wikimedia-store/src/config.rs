@@ -0,0 +1,247 @@
+//! The store config file, which records where a store's chunks and index are kept, and
+//! describes the data in them.
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+use wikimedia::{bail_kind, dump::{DumpName, SiteInfo}, Result};
+
+/// The name of the config file, found in the root directory of a store.
+pub(crate) const FILE_NAME: &str = "store.json";
+
+/// The version of the store's layout on disk, written to new config files. Stores with a
+/// newer version can't be opened.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct Config {
+    pub chunks_path: PathBuf,
+    pub index_path: PathBuf,
+
+    /// The base URL of a remote store to read chunks and the index from.
+    /// When set, `chunks_path` is used as a local cache for remote chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_url: Option<String>,
+
+    /// Where the store's pages are published on the web. When not set, the site is
+    /// derived from the dump name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<SiteInfo>,
+
+    /// The dump the store's pages are imported from. Config files written before this was
+    /// recorded don't have it, and it is added when the store is next opened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dump_name: Option<DumpName>,
+
+    /// The version of the store's layout on disk, see [`FORMAT_VERSION`].
+    #[serde(default = "format_version_default")]
+    pub format_version: u32,
+
+    #[serde(default)]
+    pub chunk_compression: ChunkCompression,
+
+    /// The FTS5 `tokenize` option of the index's page search table, or `None` for the
+    /// default tokenizer. Updated from the index when the store is opened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fts_tokenize: Option<String>,
+
+    /// When the store was created. Not known for stores created before this was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+
+    /// The lowest chunk ID not used yet, recorded when chunk files are removed so their IDs
+    /// aren't used again. New chunks get the higher of this and the highest chunk file's ID
+    /// plus one. `None` until a chunk is removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_chunk_id: Option<u64>,
+}
+
+/// How a store's chunk files are compressed.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChunkCompression {
+    /// Chunks are uncompressed Cap'n Proto messages, so they can be memory mapped.
+    #[default]
+    None,
+}
+
+/// Config files written before `format_version` was recorded have version 1.
+fn format_version_default() -> u32 {
+    1
+}
+
+impl Config {
+    /// The default layout, with chunks and index in subdirectories of the store root.
+    pub fn default_for(store_path: &Path) -> Config {
+        Config {
+            chunks_path: store_path.join("chunks"),
+            index_path: store_path.join("index"),
+            remote_url: None,
+            site: None,
+            dump_name: None,
+            format_version: FORMAT_VERSION,
+            chunk_compression: ChunkCompression::None,
+            fts_tokenize: None,
+            created: None,
+            next_chunk_id: None,
+        }
+    }
+
+    /// Read the config file in `store_path`, returning `None` if it doesn't exist.
+    pub fn read(store_path: &Path) -> Result<Option<Config>> {
+        let path = store_path.join(FILE_NAME);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(
+                || format!("reading store config file path='{}'", path.display())),
+        };
+
+        let config: Config = serde_json::from_slice(&bytes)
+            .with_context(|| format!("parsing store config file path='{}'", path.display()))?;
+        Ok(Some(config))
+    }
+
+    /// Write this config to the config file in `store_path`, replacing any existing file.
+    pub fn write(&self, store_path: &Path) -> Result<()> {
+        let path = store_path.join(FILE_NAME);
+        let temp_path = store_path.join(format!("{FILE_NAME}.tmp"));
+
+        let write = || -> Result<()> {
+            fs::create_dir_all(store_path)?;
+            let mut file = fs::File::create(&temp_path)?;
+            serde_json::to_writer_pretty(&mut file, self)?;
+            file.write_all(b"\n")?;
+            file.sync_all()?;
+            fs::rename(&temp_path, &path)?;
+            Ok(())
+        };
+        write().with_context(|| format!("writing store config file path='{}'", path.display()))
+    }
+
+    /// Resolve the config for the store at `store_path`.
+    ///
+    /// If the store has a config file, it is used, and `dump_name` and any requested paths
+    /// must match it. Otherwise the requested paths (or the defaults) are used and saved in a
+    /// new config file.
+    ///
+    /// A requested `site` replaces the site in an existing config file.
+    pub fn resolve(
+        store_path: &Path,
+        dump_name: &DumpName,
+        chunks_path: Option<&Path>,
+        index_path: Option<&Path>,
+        remote_url: Option<&str>,
+        site: Option<&SiteInfo>,
+    ) -> Result<Config> {
+        if let Some(mut existing) = Config::read(store_path)? {
+            if existing.format_version > FORMAT_VERSION {
+                bail!("The store config file has format version {version}, newer than this \
+                       version supports ({FORMAT_VERSION}). path='{path}'",
+                      version = existing.format_version,
+                      path = store_path.join(FILE_NAME).display());
+            }
+            match existing.dump_name.as_ref() {
+                Some(existing_dump_name) if existing_dump_name != dump_name =>
+                    bail_kind!(User,
+                               "Requested dump name '{requested}' does not match \
+                                the dump name '{existing}' in the store config file.",
+                               requested = dump_name.0,
+                               existing = existing_dump_name.0),
+                Some(_) => (),
+                None => {
+                    existing.dump_name = Some(dump_name.clone());
+                    existing.write(store_path)?;
+                },
+            }
+            if let Some(chunks_path) = chunks_path {
+                if chunks_path != existing.chunks_path {
+                    bail_kind!(User,
+                               "Requested chunks path '{requested}' does not match \
+                                the chunks path '{existing}' in the store config file.",
+                               requested = chunks_path.display(),
+                               existing = existing.chunks_path.display());
+                }
+            }
+            if let Some(index_path) = index_path {
+                if index_path != existing.index_path {
+                    bail_kind!(User,
+                               "Requested index path '{requested}' does not match \
+                                the index path '{existing}' in the store config file.",
+                               requested = index_path.display(),
+                               existing = existing.index_path.display());
+                }
+            }
+            if let Some(remote_url) = remote_url {
+                if Some(remote_url) != existing.remote_url.as_deref() {
+                    bail_kind!(User,
+                               "Requested remote URL '{remote_url}' does not match \
+                                the remote URL {existing:?} in the store config file.",
+                               existing = existing.remote_url);
+                }
+            }
+            if let Some(site) = site {
+                if Some(site) != existing.site.as_ref() {
+                    existing.site = Some(site.clone());
+                    existing.write(store_path)?;
+                }
+            }
+            return Ok(existing);
+        }
+
+        let default = Config::default_for(store_path);
+        let config = Config {
+            chunks_path: chunks_path.map(|p| p.to_owned()).unwrap_or(default.chunks_path),
+            index_path: index_path.map(|p| p.to_owned()).unwrap_or(default.index_path),
+            remote_url: remote_url.map(|s| s.to_string()),
+            site: site.cloned(),
+            dump_name: Some(dump_name.clone()),
+            created: Some(Utc::now()),
+            ..default
+        };
+        config.write(store_path)?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_checks_dump_name() {
+        let temp_dir = wikimedia::TempDir::create(&std::env::temp_dir(), false /* keep */)
+            .unwrap();
+        let store_path = temp_dir.path().unwrap();
+        let enwiki = DumpName("enwiki".to_string());
+
+        let created = Config::resolve(store_path, &enwiki, None, None, None, None).unwrap();
+        assert_eq!(created.dump_name.as_ref(), Some(&enwiki));
+        assert_eq!(created.format_version, FORMAT_VERSION);
+        assert!(created.created.is_some());
+        assert_eq!(Config::read(store_path).unwrap(), Some(created.clone()));
+
+        assert_eq!(Config::resolve(store_path, &enwiki, None, None, None, None).unwrap(),
+                   created);
+        assert!(Config::resolve(store_path, &DumpName("dewiki".to_string()),
+                                None, None, None, None).is_err());
+
+        // A config file written before the dump name was recorded gets it added.
+        fs::write(store_path.join(FILE_NAME),
+                  r#"{ "chunks_path": "c", "index_path": "i" }"#).unwrap();
+        let resolved = Config::resolve(store_path, &enwiki, None, None, None, None).unwrap();
+        assert_eq!(resolved.dump_name.as_ref(), Some(&enwiki));
+        assert_eq!((resolved.format_version, resolved.created), (1, None));
+        assert_eq!(Config::read(store_path).unwrap(), Some(resolved));
+
+        let mut newer = created;
+        newer.format_version = FORMAT_VERSION + 1;
+        newer.write(store_path).unwrap();
+        assert!(Config::resolve(store_path, &enwiki, None, None, None, None).is_err());
+    }
+}
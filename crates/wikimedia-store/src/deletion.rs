@@ -0,0 +1,635 @@
+//! Delete the pages in a list from a store in throttled batches, recording each in an audit
+//! log, e.g. to comply with takedowns or the page suppressions Wikimedia publishes.
+//!
+//! Pages are deleted from the index, so they can't be read, searched, or linked to, and
+//! their rows in other tables are deleted with them. Then the other pages of each chunk that
+//! held a deleted page are written to a new chunk, and the old chunk file is removed, so the
+//! deleted pages' text is not kept on disk.
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use crate::{
+    capnp::wikimedia_capnp as wmc,
+    chunk,
+    ChunkId,
+    ChunkStore,
+    compact,
+    index,
+    PageEvent,
+    Store,
+    StorePageId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration as StdDuration,
+};
+use wikimedia::{
+    bail_kind,
+    cancel,
+    Result,
+    slug,
+    util::fmt::Duration,
+};
+
+/// A page to delete, from one line of a deletion list, see [`parse_list`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ListEntry {
+    pub page: PageRef,
+
+    /// Why the page is deleted, copied to the audit log.
+    pub reason: Option<String>,
+}
+
+/// How a [`ListEntry`] refers to a page.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageRef {
+    MediawikiId(u64),
+
+    /// A page's slug, normalised from the title or slug in the list.
+    Slug(String),
+}
+
+/// How to apply a deletion list, see [`Store::apply_deletion_list`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    batch_size: usize,
+    batch_interval: StdDuration,
+    dry_run: bool,
+    audit_log: Option<PathBuf>,
+    vacuum: bool,
+}
+
+/// The file name of the default audit log, in the store's directory.
+pub const AUDIT_LOG_FILE_NAME: &str = "deletions.jsonl";
+
+/// What one [`AuditRecord`] records.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditAction {
+    /// The page was deleted from the index, and its text from its chunk file once the
+    /// chunk is written again, see [`AuditAction::ChunkRemoved`].
+    Deleted,
+
+    /// The page would have been deleted, but this was a dry run.
+    WouldDelete,
+
+    /// No page in the store matched the entry.
+    NotFound,
+
+    /// A chunk file that held deleted pages was removed, after any other pages in it were
+    /// written to a new chunk.
+    ChunkRemoved,
+}
+
+/// One line of the audit log, as JSON.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    pub time: DateTime<Utc>,
+    pub action: AuditAction,
+
+    /// The list entry this record is about. `None` for [`AuditAction::ChunkRemoved`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<ListEntry>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mediawiki_id: Option<u64>,
+
+    /// The ID of the deleted copy's revision, so [`Store::rebuild_index`] can tell it from
+    /// copies of the page imported later. Not recorded by earlier versions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<StorePageId>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_id: Option<ChunkId>,
+}
+
+/// The result of [`Store::apply_deletion_list`].
+#[derive(Clone, Debug, Serialize)]
+pub struct DeletionReport {
+    pub entries_len: u64,
+
+    /// Pages deleted, or that would be deleted in a dry run.
+    pub pages_deleted: u64,
+
+    pub not_found: u64,
+
+    /// Chunk files that held deleted pages, now removed. Their other pages are written to
+    /// new chunks.
+    pub chunks_removed: u64,
+    pub dry_run: bool,
+    pub audit_log: PathBuf,
+    pub duration: Duration,
+}
+
+/// Parse a deletion list, with one page per line.
+///
+/// A line is a MediaWiki page ID, or a page title or slug, optionally followed by a tab and
+/// the reason the page is deleted. A title made only of digits must be prefixed with
+/// `title:`, e.g. `title:1984`. Blank lines and lines starting with `#` are ignored.
+pub fn parse_list(list: impl BufRead) -> Result<Vec<ListEntry>> {
+    let mut entries = Vec::new();
+    for (line_idx, line) in list.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (page, reason) = match line.split_once('\t') {
+            Some((page, reason)) => (page.trim(), Some(reason.trim())),
+            None => (line.trim(), None),
+        };
+        let page = match page.strip_prefix("title:") {
+            Some(title) => PageRef::Slug(slug::normalize_slug(title)),
+            None => match page.parse::<u64>() {
+                Ok(mediawiki_id) => PageRef::MediawikiId(mediawiki_id),
+                Err(_) => PageRef::Slug(slug::normalize_slug(page)),
+            },
+        };
+        if matches!(&page, PageRef::Slug(slug) if slug.is_empty()) {
+            bail_kind!(User, "Deletion list line {line_no} has an empty title",
+                       line_no = line_idx + 1);
+        }
+
+        entries.push(ListEntry {
+            page,
+            reason: reason.filter(|reason| !reason.is_empty()).map(str::to_string),
+        });
+    }
+
+    Ok(entries)
+}
+
+impl Options {
+    /// Delete up to `batch_size` pages in each index transaction. The default is 100.
+    pub fn batch_size(&mut self, batch_size: usize) -> &mut Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Wait `batch_interval` between batches, so readers of the store aren't held up. The
+    /// default is 1 second.
+    pub fn batch_interval(&mut self, batch_interval: StdDuration) -> &mut Self {
+        self.batch_interval = batch_interval;
+        self
+    }
+
+    /// Only find the pages that would be deleted and record them in the audit log.
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Append audit records to the file at `audit_log`. The default is
+    /// [`AUDIT_LOG_FILE_NAME`] in the store's directory.
+    pub fn audit_log(&mut self, audit_log: PathBuf) -> &mut Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Vacuum the index after deleting, so no deleted rows are left in the index file's free
+    /// pages. This rewrites the whole index.
+    pub fn vacuum(&mut self, vacuum: bool) -> &mut Self {
+        self.vacuum = vacuum;
+        self
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            batch_size: 100,
+            batch_interval: StdDuration::from_secs(1),
+            dry_run: false,
+            audit_log: None,
+            vacuum: false,
+        }
+    }
+}
+
+impl Store {
+    /// Delete the pages in `entries` from the store as set in `options`, appending a record
+    /// of each entry to the audit log as it's applied. See [`deletion`](crate::deletion).
+    ///
+    /// Takes the store's write lock. Stores with remote chunks can't be changed. If the
+    /// store's [`Options::cancellation`](crate::Options::cancellation) token is cancelled,
+    /// the batches already deleted are kept, recorded, and removed from their chunk files,
+    /// and a [`Cancelled`](wikimedia::ErrorKind::Cancelled) error is returned.
+    #[tracing::instrument(level = "debug", name = "Store::apply_deletion_list()",
+                          skip(self, entries),
+                          fields(self.index_path = %self.opts.index_path.display(),
+                                 entries_len = entries.len()))]
+    pub fn apply_deletion_list(&mut self, entries: &[ListEntry], options: &Options
+    ) -> Result<DeletionReport> {
+        let start = std::time::Instant::now();
+
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot delete pages from a store with remote chunks.");
+        };
+        let chunk_write_guard = chunk_store.try_write_lock()?;
+
+        let audit_log_path = options.audit_log.clone()
+                                    .unwrap_or_else(|| self.opts.path.join(AUDIT_LOG_FILE_NAME));
+        let mut audit_log = AuditLog::open(&audit_log_path)?;
+
+        let mut report = DeletionReport {
+            entries_len: entries.len() as u64,
+            pages_deleted: 0,
+            not_found: 0,
+            chunks_removed: 0,
+            dry_run: options.dry_run,
+            audit_log: audit_log_path.clone(),
+            duration: Duration(StdDuration::ZERO),
+        };
+        let mut touched_chunks = BTreeSet::<ChunkId>::new();
+        let mut handled = HashSet::<u64>::new();
+        let mut cancelled = None;
+
+        for (batch_idx, batch) in entries.chunks(options.batch_size).enumerate() {
+            // Clean up after the batches already deleted before returning.
+            if let Err(e) = cancel::check(&self.opts.cancellation, "Applying deletion list") {
+                cancelled = Some(e);
+                break;
+            }
+            if batch_idx > 0 && !options.batch_interval.is_zero() {
+                std::thread::sleep(options.batch_interval);
+            }
+
+            // A page listed more than once is only deleted for its first entry.
+            let pages = resolve_entries(&self.index, batch)?
+                .into_iter()
+                .map(|(entry, page)| {
+                    (entry, page.filter(|page| handled.insert(page.mediawiki_id)))
+                })
+                .collect::<Vec<_>>();
+            let mediawiki_ids = pages.iter()
+                                     .filter_map(|(_entry, page)| page.as_ref())
+                                     .map(|page| page.mediawiki_id)
+                                     .collect::<Vec<u64>>();
+            if !options.dry_run {
+                self.index.delete_pages(&mediawiki_ids)?;
+            }
+
+            // Record the batch once it's committed.
+            for (entry, page) in pages {
+                let Some(page) = page else {
+                    report.not_found += 1;
+                    audit_log.write(&AuditRecord::for_entry(AuditAction::NotFound, entry, None))?;
+                    continue;
+                };
+
+                report.pages_deleted += 1;
+                touched_chunks.insert(page.store_id().chunk_id);
+                let revision_id = revision_id(&chunk_write_guard, page.store_id())?;
+                if options.dry_run {
+                    audit_log.write(&AuditRecord {
+                        revision_id,
+                        ..AuditRecord::for_entry(AuditAction::WouldDelete, entry, Some(&page))
+                    })?;
+                    continue;
+                }
+
+                audit_log.write(&AuditRecord {
+                    revision_id,
+                    ..AuditRecord::for_entry(AuditAction::Deleted, entry, Some(&page))
+                })?;
+                self.opts.page_events.send(PageEvent::Deleted {
+                    mediawiki_id: page.mediawiki_id,
+                    store_id: page.store_id(),
+                    slug: page.slug,
+                });
+            }
+            audit_log.flush()?;
+
+            tracing::debug!(batch_idx, pages_deleted = report.pages_deleted,
+                            not_found = report.not_found, "Applied deletion list batch");
+        }
+
+        if !options.dry_run && report.pages_deleted > 0 {
+            let removed_chunks = compact::compact_chunks(&chunk_write_guard, &mut self.index,
+                                                         touched_chunks)?;
+            for chunk_id in removed_chunks {
+                report.chunks_removed += 1;
+                audit_log.write(&AuditRecord {
+                    chunk_id: Some(chunk_id),
+                    ..AuditRecord::new(AuditAction::ChunkRemoved)
+                })?;
+            }
+            audit_log.flush()?;
+
+            crate::clean_up_deleted_pages(&mut self.index)?;
+            if options.vacuum {
+                self.index.vacuum()?;
+            }
+        }
+        if let Some(e) = cancelled {
+            return Err(e);
+        }
+
+        report.duration = Duration(start.elapsed());
+        tracing::info!(pages_deleted = report.pages_deleted, not_found = report.not_found,
+                       chunks_removed = report.chunks_removed, dry_run = report.dry_run,
+                       audit_log = %audit_log_path.display(),
+                       "Applied deletion list");
+
+        Ok(report)
+    }
+}
+
+/// Look up the page each entry in `entries` refers to.
+fn resolve_entries<'a>(index: &index::Index, entries: &'a [ListEntry]
+) -> Result<Vec<(&'a ListEntry, Option<index::Page>)>> {
+    let mediawiki_ids = entries.iter()
+                               .filter_map(|entry| match entry.page {
+                                   PageRef::MediawikiId(id) => Some(id),
+                                   PageRef::Slug(_) => None,
+                               })
+                               .collect::<Vec<u64>>();
+    let mut by_id = index.get_pages_by_mediawiki_ids(&mediawiki_ids)?;
+
+    let mut pages = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let page = match &entry.page {
+            PageRef::MediawikiId(id) =>
+                by_id.iter()
+                     .position(|page| page.mediawiki_id == *id)
+                     .map(|idx| by_id.swap_remove(idx)),
+            PageRef::Slug(slug) => index.get_page_by_slug(slug)?,
+        };
+        pages.push((entry, page));
+    }
+
+    Ok(pages)
+}
+
+impl AuditRecord {
+    fn new(action: AuditAction) -> AuditRecord {
+        AuditRecord {
+            time: Utc::now(),
+            action,
+            entry: None,
+            mediawiki_id: None,
+            revision_id: None,
+            slug: None,
+            store_id: None,
+            chunk_id: None,
+        }
+    }
+
+    fn for_entry(action: AuditAction, entry: &ListEntry, page: Option<&index::Page>
+    ) -> AuditRecord {
+        AuditRecord {
+            entry: Some(entry.clone()),
+            mediawiki_id: page.map(|page| page.mediawiki_id),
+            slug: page.map(|page| page.slug.clone()),
+            store_id: page.map(|page| page.store_id()),
+            ..AuditRecord::new(action)
+        }
+    }
+}
+
+/// The ID of the revision of the copy of a page at `store_id`, or `None` if it has none.
+fn revision_id(chunk_write_guard: &chunk::WriteLockGuard, store_id: StorePageId
+) -> Result<Option<u64>> {
+    let Some(chunk) = chunk_write_guard.map_chunk(store_id.chunk_id)? else {
+        return Ok(None);
+    };
+    let page_cap = chunk.get_page(store_id.page_chunk_index)?;
+    if !page_cap.has_revision() {
+        return Ok(None);
+    }
+    Ok(Some(page_cap.get_revision()?.get_id()))
+}
+
+/// The copies of pages deleted according to an audit log, from [`read_deleted_pages`].
+#[derive(Debug, Default)]
+pub(crate) struct DeletedPages {
+    /// The MediaWiki ID and revision ID of each deleted copy.
+    revisions: HashSet<(u64, u64)>,
+
+    /// The MediaWiki ID and store ID of deleted copies recorded without a revision ID, i.e.
+    /// by earlier versions or for pages without a revision.
+    store_ids: HashSet<(u64, StorePageId)>,
+}
+
+impl DeletedPages {
+    pub(crate) fn len(&self) -> usize {
+        self.revisions.len() + self.store_ids.len()
+    }
+
+    /// Whether `page_cap`, the copy of a page at `store_id`, was deleted.
+    ///
+    /// Copies are matched by MediaWiki ID and revision ID rather than by store ID, so a page
+    /// written later to a store ID that a deleted copy had is not taken for it. A page
+    /// imported again with the same revision after it was deleted does match.
+    pub(crate) fn contains(&self, page_cap: &wmc::page::Reader, store_id: StorePageId
+    ) -> Result<bool> {
+        let mediawiki_id = page_cap.get_id();
+        if page_cap.has_revision()
+            && self.revisions.contains(&(mediawiki_id, page_cap.get_revision()?.get_id()))
+        {
+            return Ok(true);
+        }
+        Ok(self.store_ids.contains(&(mediawiki_id, store_id)))
+    }
+}
+
+/// The copies of pages deleted according to the audit log at `path`, e.g. so
+/// [`Store::rebuild_index`] doesn't index them again. An audit log that doesn't exist has
+/// none.
+pub(crate) fn read_deleted_pages(path: &Path) -> Result<DeletedPages> {
+    /// The fields of an [`AuditRecord`] read back.
+    #[derive(Deserialize)]
+    struct DeletedRecord {
+        action: AuditAction,
+        mediawiki_id: Option<u64>,
+        revision_id: Option<u64>,
+        store_id: Option<String>,
+    }
+
+    let file = match fs::File::open(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound =>
+            return Ok(DeletedPages::default()),
+        res => res.with_context(|| format!("opening deletion audit log path='{}'",
+                                           path.display()))?,
+    };
+
+    let mut deleted = DeletedPages::default();
+    for (line_idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: DeletedRecord = serde_json::from_str(&line)
+            .with_context(|| format!("parsing deletion audit log line {line_no} path='{}'",
+                                     path.display(), line_no = line_idx + 1))?;
+        let (AuditAction::Deleted, Some(mediawiki_id)) = (record.action, record.mediawiki_id)
+        else {
+            continue;
+        };
+        match (record.revision_id, record.store_id) {
+            (Some(revision_id), _) => {
+                deleted.revisions.insert((mediawiki_id, revision_id));
+            },
+            (None, Some(store_id)) => {
+                deleted.store_ids.insert((mediawiki_id, store_id.parse()?));
+            },
+            (None, None) => (),
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// The audit log file, with one JSON [`AuditRecord`] per line, appended to by each run.
+struct AuditLog {
+    path: PathBuf,
+    file: BufWriter<fs::File>,
+}
+
+impl AuditLog {
+    fn open(path: &Path) -> Result<AuditLog> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+                       .create(true)
+                       .append(true)
+                       .open(path)
+                       .with_context(|| format!("opening deletion audit log path='{}'",
+                                                path.display()))?;
+        Ok(AuditLog {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+        })
+    }
+
+    fn write(&mut self, record: &AuditRecord) -> Result<()> {
+        serde_json::to_writer(&mut self.file, record)?;
+        self.file.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flush the records written so far and sync them to disk.
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+            .with_context(|| format!("syncing deletion audit log path='{}'",
+                                     self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options as StoreOptions;
+    use super::*;
+    use std::io::BufWriter as StdBufWriter;
+    use wikimedia::{
+        dump::{local::{Compression, OpenSpec}, synthetic, DumpName},
+        TempDir,
+    };
+
+    #[test]
+    fn parse_list_lines() {
+        let list = "# Takedowns\n\
+                    \n\
+                    42\n\
+                    Machine learning\tDMCA 2026-01\n\
+                    title:1984\r\n";
+        assert_eq!(parse_list(list.as_bytes()).unwrap(), vec![
+            ListEntry { page: PageRef::MediawikiId(42), reason: None },
+            ListEntry {
+                page: PageRef::Slug("Machine_learning".to_string()),
+                reason: Some("DMCA 2026-01".to_string()),
+            },
+            ListEntry { page: PageRef::Slug("1984".to_string()), reason: None },
+        ]);
+
+        assert!(parse_list("title: \n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn apply_deletes_and_audits() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = StdBufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages: 5,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let mut store = StoreOptions::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .build().unwrap();
+        store.import(open_spec.open().unwrap(), /* continue_import: */ false).unwrap();
+
+        let audit_log = dir_path.join("audit.jsonl");
+        let mut options = Options::default();
+        options.batch_size(2)
+               .batch_interval(StdDuration::ZERO)
+               .audit_log(audit_log.clone());
+
+        let entries = parse_list("2\nPage 2\tcourt order\nMissing page\n".as_bytes()).unwrap();
+
+        let report = store.apply_deletion_list(&entries, options.clone().dry_run(true))
+                          .unwrap();
+        assert_eq!((report.pages_deleted, report.not_found), (2, 1));
+        assert!(store.get_page_by_slug("Page_1").unwrap().is_some());
+
+        let deleted_id = store.get_page_by_slug("Page_1").unwrap().unwrap().store_id();
+        let report = store.apply_deletion_list(&entries, &options).unwrap();
+        assert_eq!((report.pages_deleted, report.not_found, report.chunks_removed), (2, 1, 1));
+        assert!(store.get_page_by_slug("Page_1").unwrap().is_none());
+        assert!(store.get_page_by_slug("Page_2").unwrap().is_none());
+        assert!(store.get_page_by_slug("Page_3").unwrap().is_some());
+
+        // The chunk is written again without the deleted pages' text.
+        assert!(store.get_page_by_store_id(deleted_id).unwrap().is_none());
+        assert_eq!(store.chunks_len().unwrap(), 1);
+        let chunk_id = store.chunk_id_iter().next().unwrap().unwrap();
+        let chunk = store.map_chunk(chunk_id).unwrap().unwrap();
+        let mut mediawiki_ids = chunk.pages_iter().unwrap()
+                                     .map(|(_store_id, page_cap)| page_cap.get_id())
+                                     .collect::<Vec<u64>>();
+        mediawiki_ids.sort();
+        assert_eq!(mediawiki_ids, [1, 4, 5]);
+
+        let records = fs::read_to_string(&audit_log).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["action"]
+                            .as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(records, ["would-delete", "would-delete", "not-found",
+                             "deleted", "deleted", "not-found", "chunk-removed"]);
+
+        // Deleting the rest of the pages removes their chunk.
+        let entries = parse_list("1\n4\n5\n".as_bytes()).unwrap();
+        let report = store.apply_deletion_list(&entries, &options).unwrap();
+        assert_eq!(report.pages_deleted, 3);
+        assert_eq!(report.chunks_removed, 1);
+        assert!(store.fsck_index(/* delete: */ false).unwrap().is_ok());
+    }
+}
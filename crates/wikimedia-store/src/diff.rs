@@ -0,0 +1,150 @@
+//! Line and word level diffs of page revision texts.
+
+use serde::Serialize;
+
+/// A line by line diff between an old and a new text.
+///
+/// Changed lines also mark which words changed, see [`DiffLine::spans`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Diff {
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiffLine {
+    pub tag: ChangeTag,
+
+    /// The 0-based index of this line in the old text, if it is in the old text.
+    pub old_index: Option<usize>,
+
+    /// The 0-based index of this line in the new text, if it is in the new text.
+    pub new_index: Option<usize>,
+
+    /// The text of the line split into spans, without the trailing newline.
+    ///
+    /// Spans with `emphasized == true` are the words that changed within the line.
+    pub spans: Vec<DiffSpan>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiffSpan {
+    pub emphasized: bool,
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeTag {
+    Equal,
+    Delete,
+    Insert,
+}
+
+impl Diff {
+    /// Diff two texts, for example the revision texts of two pages, or the text of a page
+    /// in the store against a revision fetched from the live wiki.
+    pub fn from_texts(old: &str, new: &str) -> Diff {
+        let text_diff = similar::TextDiff::from_lines(old, new);
+
+        let lines = text_diff.ops().iter()
+            .flat_map(|op| text_diff.iter_inline_changes(op))
+            .map(|change| {
+                let spans = change.iter_strings_lossy()
+                    .map(|(emphasized, text)| DiffSpan {
+                        emphasized,
+                        text: text.into_owned(),
+                    })
+                    .collect::<Vec<DiffSpan>>();
+
+                DiffLine {
+                    tag: change.tag().into(),
+                    old_index: change.old_index(),
+                    new_index: change.new_index(),
+                    spans: trim_line_end(spans),
+                }
+            })
+            .collect::<Vec<DiffLine>>();
+
+        Diff { lines }
+    }
+
+    /// Returns true if the old and new texts were the same.
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.tag == ChangeTag::Equal)
+    }
+
+    /// Count the lines (deleted, inserted) by this diff.
+    pub fn line_counts(&self) -> (u64, u64) {
+        self.lines.iter().fold((0, 0), |(deleted, inserted), line| match line.tag {
+            ChangeTag::Delete => (deleted + 1, inserted),
+            ChangeTag::Insert => (deleted, inserted + 1),
+            ChangeTag::Equal => (deleted, inserted),
+        })
+    }
+}
+
+impl DiffLine {
+    /// The text of the line, without the trailing newline.
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| &*span.text).collect()
+    }
+}
+
+impl From<similar::ChangeTag> for ChangeTag {
+    fn from(tag: similar::ChangeTag) -> ChangeTag {
+        match tag {
+            similar::ChangeTag::Equal => ChangeTag::Equal,
+            similar::ChangeTag::Delete => ChangeTag::Delete,
+            similar::ChangeTag::Insert => ChangeTag::Insert,
+        }
+    }
+}
+
+/// Remove the line terminator from the last span, dropping the span if that leaves it empty.
+fn trim_line_end(mut spans: Vec<DiffSpan>) -> Vec<DiffSpan> {
+    if let Some(last) = spans.last_mut() {
+        let trimmed_len = last.text.trim_end_matches(['\n', '\r']).len();
+        last.text.truncate(trimmed_len);
+        if last.text.is_empty() {
+            spans.pop();
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_texts() {
+        let diff = Diff::from_texts("a\nb\n", "a\nb\n");
+        assert!(diff.is_empty());
+        assert_eq!(diff.line_counts(), (0, 0));
+        assert_eq!(diff.lines.len(), 2);
+        assert_eq!(diff.lines[1].text(), "b");
+    }
+
+    #[test]
+    fn changed_line() {
+        let diff = Diff::from_texts("one\nthe quick fox\nthree\n",
+                                    "one\nthe slow fox\nthree\n");
+        assert!(!diff.is_empty());
+        assert_eq!(diff.line_counts(), (1, 1));
+
+        let tags = diff.lines.iter().map(|line| line.tag).collect::<Vec<_>>();
+        assert_eq!(tags, vec![ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert,
+                              ChangeTag::Equal]);
+
+        let deleted = &diff.lines[1];
+        assert_eq!(deleted.old_index, Some(1));
+        assert_eq!(deleted.new_index, None);
+        assert_eq!(deleted.text(), "the quick fox");
+
+        let emphasized = deleted.spans.iter()
+                                .filter(|span| span.emphasized)
+                                .map(|span| &*span.text)
+                                .collect::<Vec<&str>>();
+        assert_eq!(emphasized, vec!["quick"]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Export a store's pages to files in other formats.
+//!
+//! Every format is written by the shared parallel pipeline in [`pipeline`], which reads
+//! chunks on worker threads, formats their pages, and writes the results in order on the
+//! calling thread. A format only has to implement [`pipeline::Format`].
+
+pub mod pipeline;
+
+use crate::{metadata, Store};
+use serde::Serialize;
+use std::io::Write;
+use wikimedia::{
+    dump,
+    Result,
+    util::fmt::{Bytes, Duration, Sha1Hash},
+};
+
+/// The formats pages can be exported to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// One JSON object per line, each a serialized [`dump::Page`].
+    Jsonl,
+}
+
+/// How to export, see [`Store::export_pages`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    format: Format,
+    pipeline: pipeline::Options,
+}
+
+/// The result of [`Store::export_pages`].
+#[derive(Clone, Debug, Serialize)]
+pub struct ExportResult {
+    pub pages_len: u64,
+    pub bytes_len: Bytes,
+    pub duration: Duration,
+}
+
+struct Jsonl;
+
+impl Format {
+    /// The format's name, e.g. `jsonl`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Jsonl => "jsonl",
+        }
+    }
+}
+
+impl Options {
+    pub fn new(format: Format) -> Options {
+        Options {
+            format,
+            pipeline: pipeline::Options::default(),
+        }
+    }
+
+    /// Set the options for the pipeline, e.g. whether output is in MediaWiki ID order.
+    pub fn pipeline(&mut self, pipeline: pipeline::Options) -> &mut Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Only export pages in the namespace with key `ns_id`, e.g. 0 for articles. By default
+    /// pages in every namespace are exported.
+    pub fn namespace(&mut self, ns_id: i64) -> &mut Self {
+        self.pipeline.namespace(ns_id);
+        self
+    }
+}
+
+impl Store {
+    /// Export all the store's pages to `out` as set in `options`.
+    ///
+    /// If the store's [`Options::cancellation`](crate::Options::cancellation) token is
+    /// cancelled, the export stops after writing the pages already formatted and returns a
+    /// [`Cancelled`](wikimedia::ErrorKind::Cancelled) error.
+    pub fn export_pages(&self, options: &Options, out: &mut dyn Write) -> Result<ExportResult> {
+        let format: &dyn pipeline::Format = match options.format {
+            Format::Jsonl => &Jsonl,
+        };
+        pipeline::run(self, format, &options.pipeline, out)
+    }
+
+    /// Describe an export written by [`Store::export_pages`] with `options`, for a
+    /// reproducible dataset. `result` is the export's result and `sha1` is the hash of what
+    /// it wrote.
+    pub fn export_manifest(&self, options: &Options, result: &ExportResult, sha1: Sha1Hash
+    ) -> Result<metadata::ExportManifest>
+    {
+        let store_info = self.store_info()?;
+        let import_runs = self.index.get_import_runs_with_chunks()?
+            .into_iter()
+            .map(|run| metadata::ImportRunInfo {
+                id: run.id,
+                started: run.started,
+                finished: run.finished,
+                store_version: run.store_version,
+            })
+            .collect();
+
+        Ok(metadata::ExportManifest {
+            schema_version: metadata::SCHEMA_VERSION,
+            dump_name: store_info.dump_name,
+            dump_version: store_info.dump_version,
+            site_url: self.site().map(|site| site.site_url),
+            import_runs,
+            store_version: crate::VERSION.to_string(),
+            format: options.format.name().to_string(),
+            ordered: options.pipeline.is_ordered(),
+            namespace: options.pipeline.get_namespace(),
+            pages_len: result.pages_len,
+            bytes_len: result.bytes_len.0,
+            sha1: sha1.to_string(),
+        })
+    }
+}
+
+impl pipeline::Format for Jsonl {
+    fn write_page(&self, page: &dump::Page, out: &mut Vec<u8>) -> Result<()> {
+        serde_json::to_writer(&mut *out, page)?;
+        out.push(b'\n');
+        Ok(())
+    }
+}
@@ -0,0 +1,522 @@
+//! The parallel pipeline shared by all exporters.
+//!
+//! The store's pages are split into work items: whole chunks, or when output must be in
+//! MediaWiki ID order, batches of pages in that order. Worker threads claim items in
+//! sequence, format their pages with a [`Format`] into a buffer, and send the buffers to
+//! the calling thread, which writes them to the output.
+//!
+//! Unordered output is written as soon as each item is done. Ordered output goes through a
+//! reorder buffer that holds items done early until the items before them are written. Both
+//! limit the items in flight to [`Options::window`], so a slow item doesn't let memory grow
+//! without bound.
+
+use crate::{ChunkId, MappedChunk, QueryLimit, Store, StorePageId};
+use super::ExportResult;
+use anyhow::format_err;
+use std::{
+    collections::{BTreeMap, btree_map::Entry, VecDeque},
+    io::Write,
+    sync::{Condvar, Mutex, mpsc},
+    time::{Duration as StdDuration, Instant},
+};
+use wikimedia::{
+    dump,
+    Result,
+    util::{
+        fmt::{self, Bytes, Duration},
+        progress,
+    },
+};
+
+/// Formats pages for an export. Called from worker threads.
+pub trait Format: Sync {
+    /// Write anything that comes before the first page.
+    fn write_header(&self, _out: &mut Vec<u8>) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_page(&self, page: &dump::Page, out: &mut Vec<u8>) -> Result<()>;
+
+    /// Write anything that comes after the last page.
+    fn write_footer(&self, _out: &mut Vec<u8>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Options for the pipeline, see [`run`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    namespace: Option<i64>,
+    ordered: bool,
+    threads: usize,
+    window: usize,
+    progress: bool,
+}
+
+/// Pages per work item when exporting in order.
+const ORDERED_BATCH_LEN: u64 = 1000;
+
+/// Chunk IDs to read from the index at a time when exporting unordered.
+const CHUNK_IDS_PAGE_LEN: u64 = 1000;
+
+const PROGRESS_INTERVAL: StdDuration = StdDuration::from_secs(2);
+
+enum WorkItem {
+    Chunk(ChunkId),
+    Pages(Vec<StorePageId>),
+}
+
+/// The formatted pages of one work item.
+struct WorkOutput {
+    seq: u64,
+    pages_len: u64,
+    buf: Vec<u8>,
+}
+
+/// Hands out work items in sequence, shared by the workers.
+struct Source<'store> {
+    store: &'store Store,
+    ordered: bool,
+    window: u64,
+
+    state: Mutex<SourceState>,
+
+    /// Notified when an item is written, or when the export stops.
+    written_cond: Condvar,
+}
+
+struct SourceState {
+    /// The sequence number of the next item to hand out.
+    next_seq: u64,
+
+    /// The count of items written to the output.
+    written: u64,
+
+    /// Set when there are no more items, or the export has failed.
+    stop: bool,
+
+    pending: VecDeque<WorkItem>,
+    chunk_id_lower_bound: Option<ChunkId>,
+    mediawiki_id_lower_bound: Option<u64>,
+}
+
+/// Holds outputs that are done before the outputs sequenced before them.
+struct ReorderBuffer {
+    next_seq: u64,
+    waiting: BTreeMap<u64, WorkOutput>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            namespace: None,
+            ordered: false,
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            window: 0,
+            progress: false,
+        }
+    }
+}
+
+impl Options {
+    /// Write pages in ascending MediaWiki ID order, so the same store always exports the
+    /// same output. Otherwise pages are written in chunk order as workers finish, which is
+    /// faster. Default false.
+    pub fn ordered(&mut self, ordered: bool) -> &mut Self {
+        self.ordered = ordered;
+        self
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    /// Only write pages in the namespace with key `ns_id`. Pages are still read from every
+    /// chunk, so this is only a little faster than exporting every page.
+    pub fn namespace(&mut self, ns_id: i64) -> &mut Self {
+        self.namespace = Some(ns_id);
+        self
+    }
+
+    pub fn get_namespace(&self) -> Option<i64> {
+        self.namespace
+    }
+
+    /// The count of worker threads. Default the available parallelism.
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        assert!(threads > 0, "export pipeline threads must be > 0");
+        self.threads = threads;
+        self
+    }
+
+    /// The most work items in flight at once, including items held in the reorder buffer.
+    /// 0, the default, uses 4 times the count of threads.
+    pub fn window(&mut self, window: usize) -> &mut Self {
+        self.window = window;
+        self
+    }
+
+    /// Print progress to stderr every few seconds. Default false.
+    pub fn progress(&mut self, progress: bool) -> &mut Self {
+        self.progress = progress;
+        self
+    }
+
+    fn window_len(&self) -> usize {
+        match self.window {
+            0 => self.threads * 4,
+            window => window.max(1),
+        }
+    }
+}
+
+/// Export every page in `store` to `out` with `format`.
+pub fn run(store: &Store, format: &dyn Format, options: &Options, out: &mut dyn Write
+) -> Result<ExportResult>
+{
+    let start = Instant::now();
+    let pages_total = match options.namespace {
+        None => store.index.pages_len()?,
+        Some(ns_id) => store.index.pages_len_by_namespace()?
+                           .into_iter()
+                           .find(|(key, _pages_len)| *key == ns_id)
+                           .map_or(0, |(_key, pages_len)| pages_len),
+    };
+    let window = options.window_len();
+
+    tracing::debug!(ordered = options.ordered, threads = options.threads, window,
+                    pages_total, "export pipeline starting");
+
+    let source = Source {
+        store,
+        ordered: options.ordered,
+        window: u64::try_from(window).expect("usize as u64"),
+        state: Mutex::new(SourceState {
+            next_seq: 0,
+            written: 0,
+            stop: false,
+            pending: VecDeque::new(),
+            chunk_id_lower_bound: None,
+            mediawiki_id_lower_bound: None,
+        }),
+        written_cond: Condvar::new(),
+    };
+
+    let mut bytes_len = 0_u64;
+    let mut buf = Vec::new();
+    format.write_header(&mut buf)?;
+    out.write_all(&buf)?;
+    bytes_len += buf.len() as u64;
+
+    let pages_len = std::thread::scope(|scope| -> Result<u64> {
+        let (send, recv) = mpsc::sync_channel::<Result<WorkOutput>>(window);
+
+        for thread_idx in 0..options.threads {
+            let send = send.clone();
+            let source = &source;
+            std::thread::Builder::new()
+                .name(format!("export-{thread_idx}"))
+                .spawn_scoped(scope, move || source.work(format, options.namespace, send))?;
+        }
+        drop(send);
+
+        let res = write_outputs(&source, recv, options, pages_total, start, out, &mut bytes_len);
+        if res.is_err() {
+            source.stop();
+        }
+        res
+    })?;
+
+    buf.clear();
+    format.write_footer(&mut buf)?;
+    out.write_all(&buf)?;
+    out.flush()?;
+    bytes_len += buf.len() as u64;
+
+    let duration = start.elapsed();
+    tracing::debug!(pages_len, bytes_len, duration = %Duration(duration),
+                    "export pipeline done");
+
+    Ok(ExportResult {
+        pages_len,
+        bytes_len: Bytes(bytes_len),
+        duration: Duration(duration),
+    })
+}
+
+/// Receive outputs from the workers and write them in order to `out`. Returns the count
+/// of pages written.
+fn write_outputs(
+    source: &Source<'_>,
+    recv: mpsc::Receiver<Result<WorkOutput>>,
+    options: &Options,
+    pages_total: u64,
+    start: Instant,
+    out: &mut dyn Write,
+    bytes_len: &mut u64,
+) -> Result<u64> {
+    let mut reorder = options.ordered.then(ReorderBuffer::new);
+    let mut tracker = progress::Tracker::new_at(start, Some(pages_total));
+    let mut next_progress = start + PROGRESS_INTERVAL;
+    let mut pages_len = 0_u64;
+
+    // Ends when every worker has finished and dropped its sender.
+    for output in recv.iter() {
+        let output = output?;
+        let ready = match reorder.as_mut() {
+            Some(reorder) => reorder.push(output),
+            None => vec![output],
+        };
+
+        for output in ready {
+            out.write_all(&output.buf)?;
+            *bytes_len += output.buf.len() as u64;
+            pages_len += output.pages_len;
+            source.written();
+        }
+        source.store.check_cancelled("Export")?;
+
+        let now = Instant::now();
+        if options.progress && now >= next_progress {
+            next_progress = now + PROGRESS_INTERVAL;
+            print_progress(tracker.update_at(pages_len, now));
+        }
+    }
+
+    if let Some(reorder) = reorder {
+        if !reorder.is_empty() {
+            return Err(format_err!("export pipeline finished with outputs left in the reorder \
+                                    buffer next_seq={next_seq} waiting={waiting}",
+                                   next_seq = reorder.next_seq,
+                                   waiting = reorder.waiting.len()));
+        }
+    }
+
+    if options.progress {
+        print_progress(tracker.update(pages_len));
+    }
+
+    Ok(pages_len)
+}
+
+fn print_progress(progress: progress::Progress) {
+    let now = chrono::Local::now();
+    eprintln!("{now}     Export: \
+               {percent:>6}\
+               {remaining}\
+               {eta}",
+              now = fmt::chrono_time(now),
+              percent = progress.percent_str().unwrap_or_default(),
+              remaining = match progress.remaining {
+                  Some(dur) => format!("   remaining: {:>16}", Duration(dur)),
+                  None => "".to_string(),
+              },
+              eta = match progress.eta(now) {
+                  Some(eta) => format!("   ETA: {}", fmt::chrono_time(eta)),
+                  None => "".to_string(),
+              });
+
+    tracing::debug!(pages_done = progress.done,
+                    pages_total = progress.total,
+                    page_rate = progress.rate,
+                    "export progress");
+}
+
+impl<'store> Source<'store> {
+    /// A worker thread's main loop: claim items and send their outputs until there are
+    /// none left.
+    fn work(&self, format: &dyn Format, namespace: Option<i64>,
+            send: mpsc::SyncSender<Result<WorkOutput>>
+    ) {
+        loop {
+            let (seq, item) = match self.claim() {
+                Ok(Some(claimed)) => claimed,
+                Ok(None) => return,
+                Err(e) => {
+                    self.stop();
+                    let _ = send.send(Err(e));
+                    return;
+                }
+            };
+
+            let output = self.format_item(format, namespace, seq, item);
+            let failed = output.is_err();
+            if send.send(output).is_err() || failed {
+                // The writer has stopped, or this worker has sent it an error.
+                self.stop();
+                return;
+            }
+        }
+    }
+
+    /// Claim the next work item, waiting while the window is full. Returns `Ok(None)` when
+    /// there are no more items.
+    fn claim(&self) -> Result<Option<(u64, WorkItem)>> {
+        let mut state = self.lock()?;
+        loop {
+            if state.stop {
+                return Ok(None);
+            }
+            if state.next_seq - state.written < self.window {
+                break;
+            }
+            state = self.written_cond.wait(state)
+                        .map_err(|_e| format_err!("PoisonError waiting for export window"))?;
+        }
+
+        if state.pending.is_empty() {
+            self.fill(&mut state)?;
+        }
+        let Some(item) = state.pending.pop_front() else {
+            state.stop = true;
+            self.written_cond.notify_all();
+            return Ok(None);
+        };
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        Ok(Some((seq, item)))
+    }
+
+    /// Read the next page of work items from the index.
+    fn fill(&self, state: &mut SourceState) -> Result<()> {
+        if self.ordered {
+            let ids = self.store.index.get_store_page_ids(state.mediawiki_id_lower_bound,
+                                                          ORDERED_BATCH_LEN)?;
+            if let Some((last, _)) = ids.last() {
+                state.mediawiki_id_lower_bound = Some(*last);
+                state.pending.push_back(
+                    WorkItem::Pages(ids.into_iter().map(|(_, id)| id).collect()));
+            }
+        } else {
+            let ids = self.store.index.get_chunk_ids(state.chunk_id_lower_bound,
+                                                     QueryLimit::internal(CHUNK_IDS_PAGE_LEN))?;
+            state.chunk_id_lower_bound = ids.last().copied();
+            state.pending.extend(ids.into_iter().map(WorkItem::Chunk));
+        }
+        Ok(())
+    }
+
+    fn format_item(&self, format: &dyn Format, namespace: Option<i64>, seq: u64, item: WorkItem
+    ) -> Result<WorkOutput> {
+        let mut output = WorkOutput {
+            seq,
+            pages_len: 0,
+            buf: Vec::new(),
+        };
+        let mut write_page = |page: dump::Page| -> Result<()> {
+            if namespace.is_some_and(|ns_id| ns_id != page.ns_id) {
+                return Ok(());
+            }
+            format.write_page(&page, &mut output.buf)?;
+            output.pages_len += 1;
+            Ok(())
+        };
+
+        match item {
+            WorkItem::Chunk(chunk_id) => {
+                let chunk = self.map_chunk(chunk_id)?;
+                for (_id, page_cap) in chunk.pages_iter()? {
+                    write_page(dump::Page::try_from(&page_cap)?)?;
+                }
+            },
+            WorkItem::Pages(ids) => {
+                // Consecutive MediaWiki IDs are usually in the same few chunks, so map each
+                // chunk once per item.
+                let mut chunks = BTreeMap::<ChunkId, MappedChunk>::new();
+                for id in ids {
+                    let chunk = match chunks.entry(id.chunk_id) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert(self.map_chunk(id.chunk_id)?),
+                    };
+                    let page_cap = chunk.get_page(id.page_chunk_index)?;
+                    write_page(dump::Page::try_from(&page_cap)?)?;
+                }
+            },
+        }
+
+        Ok(output)
+    }
+
+    fn map_chunk(&self, chunk_id: ChunkId) -> Result<MappedChunk> {
+        self.store.map_chunk(chunk_id)?
+            .ok_or_else(|| format_err!("Chunk in the index not found in the store \
+                                        chunk_id={chunk_id}"))
+    }
+
+    /// Record that an item was written, making room in the window.
+    fn written(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.written += 1;
+        }
+        self.written_cond.notify_all();
+    }
+
+    /// Stop handing out items, e.g. because the export has failed.
+    fn stop(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stop = true;
+        }
+        self.written_cond.notify_all();
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, SourceState>> {
+        self.state.lock()
+            .map_err(|_e| format_err!("PoisonError locking export pipeline source"))
+    }
+}
+
+impl ReorderBuffer {
+    fn new() -> ReorderBuffer {
+        ReorderBuffer {
+            next_seq: 0,
+            waiting: BTreeMap::new(),
+        }
+    }
+
+    /// Add an output, returning the outputs now ready to write in sequence order.
+    fn push(&mut self, output: WorkOutput) -> Vec<WorkOutput> {
+        self.waiting.insert(output.seq, output);
+
+        let mut ready = Vec::new();
+        while let Some(output) = self.waiting.remove(&self.next_seq) {
+            ready.push(output);
+            self.next_seq += 1;
+        }
+        ready
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(seq: u64) -> WorkOutput {
+        WorkOutput {
+            seq,
+            pages_len: 1,
+            buf: seq.to_string().into_bytes(),
+        }
+    }
+
+    fn seqs(outputs: Vec<WorkOutput>) -> Vec<u64> {
+        outputs.into_iter().map(|output| output.seq).collect()
+    }
+
+    #[test]
+    fn reorder_buffer() {
+        let mut reorder = ReorderBuffer::new();
+        assert_eq!(seqs(reorder.push(output(1))), Vec::<u64>::new());
+        assert_eq!(seqs(reorder.push(output(2))), Vec::<u64>::new());
+        assert!(!reorder.is_empty());
+        assert_eq!(seqs(reorder.push(output(0))), vec![0, 1, 2]);
+        assert_eq!(seqs(reorder.push(output(3))), vec![3]);
+        assert_eq!(seqs(reorder.push(output(5))), Vec::<u64>::new());
+        assert_eq!(seqs(reorder.push(output(4))), vec![4, 5]);
+        assert!(reorder.is_empty());
+    }
+}
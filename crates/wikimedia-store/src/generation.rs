@@ -0,0 +1,183 @@
+//! Generations of a store's data, to update a store while it is being served.
+//!
+//! A generation is a complete store in the subdirectory `generations/{name}` of a serving
+//! store, usually imported from one dump version. The serving store's config file is the
+//! pointer to its current generation: switching generations rewrites it to use the
+//! generation's chunks and index. The config file is replaced atomically, so a store opened
+//! at any time uses either the old or the new generation. Use
+//! [`Store::publish`](crate::Store::publish) to switch an open store too.
+//!
+//! The generation switched away from is marked as retired, and removed by
+//! [`Generations::remove_retired`] once no reader should still be using it. Generations that
+//! were never switched to, e.g. one still being imported, are never removed.
+
+use anyhow::{bail, Context, format_err};
+use crate::config::Config;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration as StdDuration,
+};
+use wikimedia::Result;
+
+/// The name of the file that marks a generation as retired. Its modification time is when
+/// the generation was retired.
+const RETIRED_FILE_NAME: &str = "retired";
+
+/// The generations of the store at `store_path`.
+#[derive(Clone, Debug)]
+pub struct Generations {
+    store_path: PathBuf,
+}
+
+impl Generations {
+    pub fn new(store_path: &Path) -> Generations {
+        Generations {
+            store_path: store_path.to_owned(),
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.store_path.join("generations")
+    }
+
+    /// The root directory of generation `name`. Open a store there to import into it.
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            bail!("Invalid store generation name '{name}'");
+        }
+        Ok(self.dir().join(name))
+    }
+
+    /// The name of the generation with root directory `path`.
+    pub fn name_of(&self, path: &Path) -> Result<String> {
+        let name = path.strip_prefix(self.dir()).ok()
+                       .and_then(|rel_path| rel_path.to_str())
+                       .filter(|name| !name.is_empty() && !name.contains(['/', '\\']))
+                       .ok_or_else(|| format_err!(
+                           "Store generation path '{path}' is not a directory in '{dir}'",
+                           path = path.display(),
+                           dir = self.dir().display()))?;
+        Ok(name.to_string())
+    }
+
+    /// The name of the generation the serving store uses, or `None` if it doesn't use one.
+    pub fn current(&self) -> Result<Option<String>> {
+        let Some(config) = Config::read(&self.store_path)? else {
+            return Ok(None);
+        };
+        let Ok(rel_path) = config.chunks_path.strip_prefix(self.dir()) else {
+            return Ok(None);
+        };
+        Ok(rel_path.iter().next().and_then(|name| name.to_str()).map(|name| name.to_string()))
+    }
+
+    /// Switch the serving store to generation `name`, which must have been imported, and
+    /// retire the previous generation.
+    ///
+    /// The serving store's site is kept. Stores already open keep using the generation they
+    /// were opened with, so reopen them to use the new generation.
+    pub fn switch(&self, name: &str) -> Result<()> {
+        let previous = self.current()?;
+        let generation_path = self.path(name)?;
+        let generation = Config::read(&generation_path)?.ok_or_else(|| format_err!(
+            "Store generation not found path='{}'", generation_path.display()))?;
+
+        let existing = Config::read(&self.store_path)?;
+        if existing.as_ref().is_some_and(|config| config.remote_url.is_some()) {
+            bail!("Cannot switch the generation of a store with a remote URL.");
+        }
+
+        let config = Config {
+            chunks_path: generation.chunks_path,
+            index_path: generation.index_path,
+            remote_url: None,
+            site: existing.and_then(|config| config.site).or(generation.site),
+            ..generation
+        };
+        config.write(&self.store_path)?;
+
+        // A generation switched back to is in use again.
+        let retired_path = generation_path.join(RETIRED_FILE_NAME);
+        match fs::remove_file(&retired_path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e).with_context(
+                || format!("removing retired marker path='{}'", retired_path.display())),
+        }
+
+        if let Some(previous) = previous.filter(|previous| previous != name) {
+            let retired_path = self.path(&previous)?.join(RETIRED_FILE_NAME);
+            fs::File::create(&retired_path).with_context(
+                || format!("writing retired marker path='{}'", retired_path.display()))?;
+        }
+
+        tracing::info!(generation = name, store_path = %self.store_path.display(),
+                       "Switched store generation");
+        Ok(())
+    }
+
+    /// Remove generations retired for longer than `grace_period`, returning the names
+    /// removed.
+    ///
+    /// Readers that opened the store before a switch keep using the retired generation, so
+    /// the grace period should be long enough for them to reopen the store or finish.
+    pub fn remove_retired(&self, grace_period: StdDuration) -> Result<Vec<String>> {
+        let current = self.current()?;
+        let dir = self.dir();
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).with_context(
+                || format!("listing store generations path='{}'", dir.display())),
+        };
+
+        let mut removed = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if current.as_ref() == Some(&name) {
+                continue;
+            }
+
+            let retired_age = match fs::metadata(entry.path().join(RETIRED_FILE_NAME)) {
+                Ok(metadata) => metadata.modified()?.elapsed().unwrap_or(StdDuration::ZERO),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if retired_age < grace_period {
+                continue;
+            }
+
+            fs::remove_dir_all(entry.path()).with_context(
+                || format!("removing store generation path='{}'", entry.path().display()))?;
+            tracing::info!(generation = name, "Removed retired store generation");
+            removed.push(name);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_names() {
+        let generations = Generations::new(Path::new("stores/enwiki"));
+        let path = generations.path("20230301").unwrap();
+        assert_eq!(path, Path::new("stores/enwiki/generations/20230301"));
+        assert_eq!(generations.name_of(&path).unwrap(), "20230301");
+
+        assert!(generations.path("").is_err());
+        assert!(generations.path("..").is_err());
+        assert!(generations.path("a/b").is_err());
+        assert!(generations.name_of(Path::new("stores/enwiki/generations")).is_err());
+        assert!(generations.name_of(Path::new("stores/enwiki/generations/a/b")).is_err());
+        assert!(generations.name_of(Path::new("stores/dewiki/generations/a")).is_err());
+    }
+}
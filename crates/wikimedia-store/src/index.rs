@@ -2,49 +2,331 @@
 //! there are indexes implemented in this module that contain the serialised
 //! page's location in a chunk file.
 
-use anyhow::{Context, format_err};
+use anyhow::{bail, Context, format_err};
+use chrono::{DateTime, TimeZone, Utc};
 use crate::{
-    chunk::{ChunkId, PageChunkIndex},
-    MAX_QUERY_LIMIT,
+    chunk::{ChunkId, ChunkMeta, PageChunkIndex},
+    QueryLimit,
     StorePageId,
 };
-use rusqlite::{config::DbConfig, Connection, OpenFlags, OptionalExtension, Transaction,
-               TransactionBehavior};
-use sea_query::{ColumnDef, enum_def, Expr, extension::sqlite::SqliteExpr,
+use rusqlite::{config::DbConfig, Connection, functions::FunctionFlags, OpenFlags,
+               OptionalExtension, Transaction, TransactionBehavior};
+use sea_query::{Alias, ColumnDef, Condition, enum_def, Expr, extension::sqlite::SqliteExpr,
                 Iden, InsertStatement, OnConflict, Order, Query,
-                SelectStatement, SimpleExpr, SqliteQueryBuilder, Table};
-use sea_query_rusqlite::{RusqliteBinder, RusqliteValues};
+                SelectStatement, SimpleExpr, SqliteQueryBuilder, Table, Value};
+use sea_query_rusqlite::{RusqliteBinder, RusqliteValue, RusqliteValues};
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
+    fmt::{self, Display},
     fs,
-    path::PathBuf,
-    sync::{Mutex, MutexGuard},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    result::Result as StdResult,
+    str::FromStr,
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Mutex, MutexGuard},
+    time::{Duration as StdDuration, Instant},
 };
+use valuable::Valuable;
 use wikimedia::{
-    dump::{self, CategorySlug},
+    bail_kind,
+    cancel::CancellationToken,
+    dump::{self, CategoryName, CategorySlug},
+    lazy_regex,
     Result,
     slug,
+    util::fmt::Duration,
+    wikitext::{self, PageFlags, TextStats},
 };
 
+/// The current schema version, stored in sqlite's `user_version`. Indexes with an older
+/// version are migrated when opened if [`AutoMigrate`] allows, see [`Migration`]. Indexes
+/// with a newer version can't be opened.
+pub const SCHEMA_VERSION: i64 = 21;
+
+/// Which pending [`Migration`]s are applied when a store's index is opened. An index with
+/// pending migrations that aren't allowed can't be opened until they are applied, e.g. with
+/// [`Options::migrate_index`](crate::Options::migrate_index).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AutoMigrate {
+    /// Apply pending migrations if they are all safe. The default.
+    #[default]
+    Safe,
+
+    /// Apply all pending migrations, however long they take.
+    All,
+
+    /// Apply none.
+    Off,
+}
+
+/// One step upgrading a store's index to a newer schema version. Migrations are applied in
+/// order of version, in one transaction, and recorded as [`IndexMigration`]s.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Migration {
+    /// The schema version this migration upgrades to.
+    pub version: i64,
+    pub name: &'static str,
+
+    /// Safe migrations add tables or columns without rewriting existing rows, so they are
+    /// quick however large the index is. Others can take a long time on a large index.
+    pub safe: bool,
+
+    /// Applies the migration if its changes are missing, returning true if the full text
+    /// search table must be rebuilt after creating the schema. Changes are checked because
+    /// indexes created before schema versions were recorded have version 0.
+    #[serde(skip)]
+    apply: fn(&Transaction) -> Result<bool>,
+}
+
+/// Every migration, in order of version.
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "page namespace IDs", safe: false,
+                apply: Index::migrate_page_ns_ids },
+    Migration { version: 2, name: "external content full text search", safe: false,
+                apply: Index::migrate_external_content_fts },
+    Migration { version: 3, name: "category names", safe: false,
+                apply: Index::migrate_category_names },
+    Migration { version: 4, name: "page text statistics", safe: true,
+                apply: Index::migrate_page_text_stats },
+    Migration { version: 5, name: "page flags", safe: true,
+                apply: Index::migrate_page_flags },
+    Migration { version: 6, name: "page category sort keys", safe: false,
+                apply: Index::migrate_page_category_sort_keys },
+    Migration { version: 7, name: "page redirects", safe: true,
+                apply: Index::migrate_page_redirects },
+    Migration { version: 8, name: "chunk list", safe: false,
+                apply: Index::migrate_chunk_list },
+    Migration { version: 9, name: "page properties", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 10, name: "chunk commit state", safe: true,
+                apply: Index::migrate_chunk_committed },
+    Migration { version: 11, name: "spelling suggestions", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 12, name: "page links", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 13, name: "slug disambiguations", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 14, name: "import file update times", safe: true,
+                apply: Index::migrate_import_file_updated },
+    Migration { version: 15, name: "page revision timestamps", safe: true,
+                apply: Index::migrate_page_revision_timestamps },
+    Migration { version: 16, name: "import runs and chunk provenance", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 17, name: "page templates", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 18, name: "page redirect fragments", safe: true,
+                apply: Index::migrate_page_redirect_fragments },
+    Migration { version: 19, name: "saved searches", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 20, name: "migration history", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+    Migration { version: 21, name: "page namespace index", safe: true,
+                apply: Index::migrate_tables_created_with_schema },
+];
+
+impl AutoMigrate {
+    /// Why an index with `pending` migrations can't be opened with this setting, or `None`
+    /// if it can.
+    pub(crate) fn problem(self, pending: &[Migration]) -> Option<String> {
+        let allowed = match self {
+            AutoMigrate::Safe => pending.iter().all(|migration| migration.safe),
+            AutoMigrate::All => true,
+            AutoMigrate::Off => pending.is_empty(),
+        };
+        if allowed {
+            return None;
+        }
+
+        Some(format!("The store index needs migrations that {self} doesn't allow: {names}. \
+                      Run `wmd migrate-index` to apply them.",
+                     names = pending.iter().map(|migration| migration.to_string())
+                                           .collect::<Vec<String>>().join(", ")))
+    }
+}
+
+impl Display for AutoMigrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AutoMigrate::Safe => "auto-migrate 'safe'",
+            AutoMigrate::All => "auto-migrate 'all'",
+            AutoMigrate::Off => "auto-migrate 'off'",
+        })
+    }
+}
+
+impl Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{version} ({name})", version = self.version, name = self.name)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Index {
     /// An open connection to the sqlite database. Always `Some(_)`
     /// except for briefly during `Index::clear()`.
     conn: Option<Mutex<Connection>>,
     opts: Options,
+
+    /// Rows per insert statement for the next import batch. Starts at
+    /// `opts.batch.max_values_per_batch` and changes when `opts.batch.adaptive` is set.
+    values_per_batch: AtomicUsize,
+
+    /// The index's layout, found from its files when the connection is opened.
+    layout: Layout,
 }
 
 #[derive(Debug)]
 pub(crate) struct Options {
-    pub max_values_per_batch: usize,
+    pub batch: BatchOptions,
     pub path: PathBuf,
+
+    /// Log read queries that take at least this long, see `Index::query_rows()`.
+    pub slow_query_threshold: Option<StdDuration>,
+
+    /// The tokenizer for a new full text search index. Existing indexes keep theirs.
+    pub fts_tokenizer: Option<FtsTokenizer>,
+
+    /// Which pending migrations to apply when the index is opened.
+    pub auto_migrate: AutoMigrate,
+
+    /// The layout of a new index. Existing indexes keep theirs.
+    pub layout: Layout,
+}
+
+/// How the index's tables are split between database files in the index directory, chosen
+/// when the index is created.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// Every table in `index.db`. The default.
+    #[default]
+    Single,
+
+    /// The page full text search and spelling tables in a second file, `search.db`, and
+    /// every other table in `index.db`. Splits a very large index, such as enwiki's, in two,
+    /// so each file can be vacuumed and backed up in less time and space.
+    ///
+    /// The search table keeps its own copy of page titles, because an FTS5 table can only
+    /// read its content from a table in the same file. Commits are atomic for each file but
+    /// not for both, so a crash during a commit can leave searches out of step with pages;
+    /// [`Store::build_fts`](crate::Store::build_fts) repairs that. Exported indexes have the
+    /// single file layout.
+    SplitSearch,
 }
 
+/// The file with the search tables of an index with [`Layout::SplitSearch`], attached to
+/// connections to `index.db` as the schema named by `SEARCH_SCHEMA`.
+const SEARCH_DB_FILE_NAME: &str = "search.db";
+
+const SEARCH_SCHEMA: &str = "search";
+
+/// A SQL function registered on index connections that lowercases its text argument with
+/// Unicode case folding rules. `COLLATE NOCASE` and `lower()` only fold ASCII letters.
+const LOWER_UNICODE_FUNCTION: &str = "lower_unicode";
+
+/// How page titles are split into tokens for full text search, set when the index is
+/// created. See the SQLite FTS5 documentation on tokenizers for details of the options.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum FtsTokenizer {
+    /// Split titles into words at spaces and punctuation, including '_'. Suits languages
+    /// that separate words with spaces. The default.
+    Unicode61 {
+        /// 0 keeps diacritics, 1 removes them from characters that are a single code point,
+        /// and 2 removes them from all characters. Default 1.
+        remove_diacritics: u8,
+
+        /// Extra characters to split tokens at.
+        separators: String,
+
+        /// Extra characters to keep in tokens, e.g. '-' to keep hyphenated words together.
+        token_chars: String,
+    },
+
+    /// Index every sequence of 3 characters, so search terms match any part of a title.
+    /// Suits languages written without spaces between words, such as Japanese and Chinese.
+    /// Search terms shorter than 3 characters match nothing.
+    Trigram {
+        case_sensitive: bool,
+    },
+}
+
+/// How rows are inserted into the index during an import.
+///
+/// Each chunk's pages are committed to the index in one transaction, so the transaction size
+/// and commit cadence are set by the store's maximum chunk length.
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    /// The number of rows inserted by each insert statement, or the initial number when
+    /// `adaptive` is set.
+    pub max_values_per_batch: usize,
+
+    /// Adjust the rows per insert statement after each commit: shrink it when a commit is
+    /// slower than `target_commit_latency` or the database was busy, and grow it when a
+    /// commit is much faster.
+    pub adaptive: bool,
+
+    /// The commit latency to aim for when `adaptive` is set.
+    pub target_commit_latency: StdDuration,
+
+    /// How many times to retry a commit that failed because the database was busy
+    /// (`SQLITE_BUSY`), e.g. locked by another process.
+    pub busy_retries: u32,
+}
+
+/// The batch sizes used to commit one chunk's pages to the index, for tuning `BatchOptions`.
+#[derive(Clone, Debug, Valuable)]
+pub struct BatchStats {
+    /// Rows per insert statement used for this batch.
+    pub values_per_batch: usize,
+
+    /// Rows inserted, over all tables.
+    pub values_len: usize,
+    pub statements_len: usize,
+    pub commit_duration: Duration,
+
+    /// Commits retried because the database was busy.
+    pub busy_retries: u32,
+
+    /// Rows per insert statement chosen for the next batch.
+    pub next_values_per_batch: usize,
+}
+
+pub const VALUES_PER_BATCH_DEFAULT: usize = 100;
+pub const VALUES_PER_BATCH_MIN: usize = 10;
+
+/// The page table has the most columns, 8, so this stays under sqlite's limit of 32766
+/// bound parameters per statement.
+pub const VALUES_PER_BATCH_MAX: usize = 4000;
+
+const BUSY_RETRY_DELAY: StdDuration = StdDuration::from_millis(50);
+
 pub(crate) struct ImportBatchBuilder<'index> {
     index: &'index Index,
     category_batch: BatchInsert,
     page_batch: BatchInsert,
     page_categories_batch: BatchInsert,
-    page_fts_batch: BatchInsert,
+    page_props_batch: BatchInsert,
+    page_links_batch: BatchInsert,
+    page_templates_batch: BatchInsert,
+
+    /// Progress through the current dump file, committed with the batch.
+    import_file_progress: Option<ImportFileProgress>,
+
+    /// The chunk this batch's pages were written to: (id, pages_len, bytes_len), committed
+    /// with the batch.
+    chunk: Option<(ChunkId, u64, u64)>,
+
+    /// Where the chunk's pages came from, committed with the batch.
+    chunk_provenance: Option<ChunkProvenance>,
+
+    /// The MediaWiki IDs of pages to delete with their rows before inserting the batch, see
+    /// [`ImportBatchBuilder::replace_page`].
+    replaced_pages: Vec<u64>,
+
+    /// Pages to point at a new copy when the batch commits, keeping their other rows, see
+    /// [`ImportBatchBuilder::move_page`].
+    moved_pages: Vec<(u64, StorePageId)>,
 }
 
 struct BatchInsert {
@@ -56,7 +338,7 @@ struct BatchInsert {
     values_len: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 #[enum_def]
 #[allow(dead_code)] // The private fields are using in PageIden (generated from this).
 pub struct Page {
@@ -64,603 +346,4791 @@ pub struct Page {
     chunk_id: u64,
     page_chunk_index: u64,
     pub slug: String,
+
+    /// The page's namespace key, negative only for virtual namespaces, which are not
+    /// imported.
+    pub ns_id: i64,
+
+    /// Length of the page's wikitext in bytes.
+    ///
+    /// This and `word_count` are `None` for pages without text, and for pages imported
+    /// before they were added to the index until the pages are imported again.
+    pub text_bytes: Option<u64>,
+
+    /// Approximate count of words in the page, see [`TextStats::words`].
+    pub word_count: Option<u64>,
+
+    /// Whether the page is a stub, disambiguation page, or list, see
+    /// [`wikitext::classify_page`]. `None` for pages imported before this was added to the
+    /// index.
+    pub page_flags: Option<PageFlags>,
+
+    /// The slug of the page this page redirects to, from its `#REDIRECT [[...]]` text, see
+    /// [`wikitext::parse_redirect`]. `None` for pages that aren't redirects, and for pages
+    /// imported before this was added to the index.
+    pub redirect_slug: Option<String>,
+
+    /// The anchor of the section of the target page this page redirects to, e.g.
+    /// `Early_history` from `#REDIRECT [[Rust#Early history]]`, see
+    /// [`wikitext::parse_redirect_target`]. `None` for redirects to the top of a page, and
+    /// for pages imported before this was added to the index.
+    pub redirect_fragment: Option<String>,
+
+    /// When the page's latest revision was saved, to the second. `None` for pages without a
+    /// revision timestamp, and for pages imported before this was added to the index.
+    pub revision_timestamp: Option<DateTime<Utc>>,
+}
+
+/// The `page` columns read by [`Page::from_row`], in order.
+const PAGE_COLUMNS: [PageIden; 11] = [
+    PageIden::MediawikiId,
+    PageIden::ChunkId,
+    PageIden::PageChunkIndex,
+    PageIden::Slug,
+    PageIden::NsId,
+    PageIden::TextBytes,
+    PageIden::WordCount,
+    PageIden::PageFlags,
+    PageIden::RedirectSlug,
+    PageIden::RedirectFragment,
+    PageIden::RevisionTimestamp,
+];
+
+/// Where a redirect page points, from [`Store::follow_redirect`](crate::Store::follow_redirect).
+#[derive(Clone, Debug, Serialize)]
+pub struct Redirect {
+    /// The target page's index entry.
+    pub target: Page,
+
+    /// The anchor of the section of the target page to show, see [`Page::redirect_fragment`].
+    pub fragment: Option<String>,
+}
+
+/// A page related to another page, from
+/// [`Store::related_pages`](crate::Store::related_pages).
+#[derive(Clone, Debug, Serialize)]
+pub struct RelatedPage {
+    pub page: Page,
+
+    /// The count of categories both pages are in.
+    pub shared_categories: u64,
+
+    /// The count of links between the pages: 1 if one page links to the other, 2 if they
+    /// link to each other.
+    pub links: u64,
+}
+
+/// Pages whose slugs are equal ignoring case, from
+/// [`Store::slug_collisions`](crate::Store::slug_collisions).
+///
+/// Looking up a slug in the group finds the page with exactly that slug, if there is only
+/// one. Otherwise, e.g. for a slug that differs from them all in case, the lookup finds the
+/// page chosen by [`Store::write_slug_disambiguations`](crate::Store::write_slug_disambiguations),
+/// or nothing if none was chosen.
+#[derive(Clone, Debug, Serialize)]
+pub struct SlugCollision {
+    /// The pages, ordered by slug then MediaWiki ID.
+    pub pages: Vec<Page>,
+
+    /// The MediaWiki ID of the page chosen for lookups of the group's slugs, if one was
+    /// chosen.
+    pub disambiguation: Option<u64>,
+}
+
+/// A title linked to by pages in the index that isn't in the index itself, from
+/// [`Store::red_links`](crate::Store::red_links).
+///
+/// Only articles' links to other articles are indexed, see [`wikitext::parse_links`], so
+/// red links are always to articles. A redirect page counts as the title being present.
+#[derive(Clone, Debug, Serialize)]
+pub struct RedLink {
+    /// The slug the links target.
+    pub slug: String,
+
+    /// The count of pages that link to the slug.
+    pub links_len: u64,
 }
 
-#[derive(Clone, Debug)]
-#[enum_def]
-#[allow(dead_code)] // The private fields are using in PageFtsIden (generated from this).
-struct PageFts {
-    title: String,
-    mediawiki_id: u64,
-    rank: f64,
+/// Pages whose latest revision was saved after a time, from
+/// [`Store::pages_modified_since`](crate::Store::pages_modified_since).
+#[derive(Clone, Debug, Serialize)]
+pub struct PageChanges {
+    /// The pages, ordered by revision timestamp then MediaWiki ID.
+    pub pages: Vec<Page>,
+
+    /// Where to continue from to get the following pages. `None` when there are no more.
+    pub next_cursor: Option<ChangesCursor>,
 }
 
-#[derive(Clone, Debug)]
-#[enum_def]
-#[allow(dead_code)] // PageCategoriesIden (generated from this) is used.
-struct PageCategories {
-    mediawiki_id: u64,
-    category_slug: String,
+/// A position in pages ordered by revision timestamp then MediaWiki ID, from
+/// [`PageChanges::next_cursor`].
+///
+/// Formatted as `{unix_seconds}-{mediawiki_id}`, e.g. `1679387424-12`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChangesCursor {
+    pub timestamp: i64,
+    pub mediawiki_id: u64,
 }
 
-#[derive(Debug)]
-#[enum_def]
-#[allow(dead_code)] // CategoryIden (generated from this) is used.
-struct Category {
-    slug: String,
+impl Display for ChangesCursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{timestamp}-{mediawiki_id}",
+               timestamp = self.timestamp, mediawiki_id = self.mediawiki_id)
+    }
 }
 
-impl Page {
-    pub fn store_id(&self) -> StorePageId {
-        StorePageId {
-            chunk_id: ChunkId(self.chunk_id),
-            page_chunk_index: PageChunkIndex(self.page_chunk_index),
+impl FromStr for ChangesCursor {
+    type Err = wikimedia::Error;
+
+    fn from_str(s: &str) -> Result<ChangesCursor> {
+        let parsed = s.split_once('-').and_then(|(timestamp, mediawiki_id)|
+            Some(ChangesCursor {
+                timestamp: timestamp.parse().ok()?,
+                mediawiki_id: mediawiki_id.parse().ok()?,
+            }));
+        match parsed {
+            Some(cursor) => Ok(cursor),
+            None => bail_kind!(User, "Bad changes cursor '{s}', expected \
+                                      '{{unix_seconds}}-{{mediawiki_id}}'"),
         }
     }
 }
 
-impl Options {
-    pub(crate) fn build(self) -> Result<Index> {
-        Index::new(self)
+impl Serialize for ChangesCursor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
-impl Index {
-    fn new(opts: Options) -> Result<Index> {
-        let conn = Self::new_conn(&opts)?;
+/// Categories with more pages than this are too broad to relate their pages, e.g. "Living
+/// people", so they are skipped by `Index::related_pages()`.
+const RELATED_CATEGORY_PAGES_MAX: u64 = 1000;
 
-        let mut index = Index {
-            conn: Some(Mutex::new(conn)),
+/// The most pages linking to a page read by `Index::related_pages()`.
+const RELATED_LINKS_TO_MAX: u64 = 10_000;
 
-            opts: opts,
-        };
+/// Options for [`Store::page_search`](crate::Store::page_search).
+#[derive(Clone, Debug, Default)]
+pub struct PageSearchOptions {
+    /// The maximum count of pages to return.
+    pub limit: QueryLimit,
 
-        index.ensure_schema()?;
+    /// Only return pages with at least this many words, e.g. to exclude stubs.
+    ///
+    /// Pages without a word count are excluded when this or `max_words` is set.
+    pub min_words: Option<u64>,
 
-        Ok(index)
-    }
+    /// Only return pages with at most this many words.
+    pub max_words: Option<u64>,
 
-    fn new_conn(opts: &Options) -> Result<Connection> {
-        fs::create_dir_all(&*opts.path)?;
-        let db_path = opts.path.join("index.db");
+    /// Only return pages with all of these flags.
+    ///
+    /// Flags can also be set in the query with terms such as `is:stub`.
+    pub required_flags: PageFlags,
 
-        let open_flags =
-            OpenFlags::SQLITE_OPEN_READ_WRITE |
-            OpenFlags::SQLITE_OPEN_CREATE |
-            OpenFlags::SQLITE_OPEN_URI |
-            OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    /// Only return pages with none of these flags. Pages without flags in the index are
+    /// treated as having none.
+    ///
+    /// Flags can also be set in the query with terms such as `-is:disambiguation`.
+    pub excluded_flags: PageFlags,
 
-        let mut conn = Connection::open_with_flags(db_path, open_flags)?;
+    /// Only return pages in this category.
+    pub category: Option<CategorySlug>,
 
-        conn.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)?;
-        conn.set_db_config(DbConfig::SQLITE_DBCONFIG_ENABLE_FKEY, true)?;
+    /// Only return pages in the namespace with this ID.
+    pub namespace: Option<i64>,
 
-        conn.trace(Some(|s: &str| tracing::trace!(sql = s, "Index::conn::trace")));
+    pub order: PageSearchOrder,
+}
 
-        // TODO: more safety pragmas.
-        conn.pragma_update(None, "journal_mode", "WAL")?;
+/// The most matching pages [`Store::page_search_facets`](crate::Store::page_search_facets)
+/// counts.
+pub const PAGE_SEARCH_FACET_PAGES_MAX: u64 = 10_000;
 
-        Ok(conn)
-    }
+/// Counts of the pages matching a page search by namespace and by category, from
+/// [`Store::page_search_facets`](crate::Store::page_search_facets).
+#[derive(Clone, Debug, Serialize)]
+pub struct PageSearchFacets {
+    /// The namespaces of matching pages, with the most matches first.
+    pub namespaces: Vec<NamespaceFacet>,
 
-    fn ensure_schema(&mut self) -> Result<()> {
-        let schema_sql = [
-                // Table category
-                Table::create()
-                    .table(CategoryIden::Table)
-                    .if_not_exists()
-                    .col(ColumnDef::new(CategoryIden::Slug)
-                             .text()
-                             .not_null()
-                             .primary_key())
-                    .build(SqliteQueryBuilder)
-                    + " STRICT, WITHOUT ROWID",
+    /// The categories with the most matching pages, with the most matches first.
+    pub categories: Vec<CategoryFacet>,
 
-                // Table page
-                Table::create()
-                    .table(PageIden::Table)
-                    .if_not_exists()
-                    .col(ColumnDef::new(PageIden::MediawikiId)
-                            .integer()
-                            .not_null()
-                            .primary_key())
-                    .col(ColumnDef::new(PageIden::ChunkId)
-                            .integer()
-                            .not_null())
-                    .col(ColumnDef::new(PageIden::PageChunkIndex)
-                            .integer()
-                            .not_null())
-                    .col(ColumnDef::new(PageIden::Slug)
-                            .text()
-                            .not_null()
-                    )
-                    .build(SqliteQueryBuilder)
-                    + " STRICT",
-                format!(r#"
-                    CREATE INDEX IF NOT EXISTS index_page_by_slug ON {page_table}
-                    ({page_slug} COLLATE NOCASE)
-                "#, page_table = PageIden::Table.to_string(),
-                    page_slug = PageIden::Slug.to_string()),
+    /// True if the search matched more than [`PAGE_SEARCH_FACET_PAGES_MAX`] pages, so only
+    /// that many were counted.
+    pub truncated: bool,
+}
 
-                // Table page_fts (with FTS5)
-                format!(r#"
-                    CREATE VIRTUAL TABLE IF NOT EXISTS {page_fts__table} USING fts5(
-                        {page_fts__title},
-                        {page_fts__mediawiki_id} UNINDEXED,
-                        prefix = 2, prefix = 3
-                    )
-                "#, page_fts__table = PageFtsIden::Table.to_string(),
-                    page_fts__title = PageFtsIden::Title.to_string(),
-                    page_fts__mediawiki_id = PageFtsIden::MediawikiId.to_string()),
+#[derive(Clone, Debug, Serialize)]
+pub struct NamespaceFacet {
+    pub ns_id: i64,
 
-                // Table page_categories
-                Table::create()
-                    .table(PageCategoriesIden::Table)
-                    .if_not_exists()
-                    .col(ColumnDef::new(PageCategoriesIden::MediawikiId)
-                             .integer()
-                             .not_null())
-                    .col(ColumnDef::new(PageCategoriesIden::CategorySlug)
-                             .text()
-                             .not_null()
-                    )
-                    .primary_key(sea_query::Index::create()
-                                     .col(PageCategoriesIden::MediawikiId)
-                                     .col(PageCategoriesIden::CategorySlug)
-                                     .unique())
-                    .build(SqliteQueryBuilder)
-                    + " STRICT",
-                sea_query::Index::create()
-                    .name("index_page_categories_by_category_slug")
-                    .if_not_exists()
-                    .table(PageCategoriesIden::Table)
-                    .col(PageCategoriesIden::CategorySlug)
-                    .col(PageCategoriesIden::MediawikiId)
-                    .unique()
-                    .build(SqliteQueryBuilder),
-            ]
-            .join("; ");
+    /// The count of matching pages in the namespace.
+    pub pages_len: u64,
+}
 
-        self.conn()?.execute_batch(&schema_sql)?;
+/// A category with a count of its pages, from a page search's facets or
+/// [`Store::top_categories`](crate::Store::top_categories).
+#[derive(Clone, Debug, Serialize)]
+pub struct CategoryFacet {
+    pub category: Category,
 
-        Ok(())
-    }
+    /// The count of matching pages in the category.
+    pub pages_len: u64,
+}
 
-    fn drop_all(&mut self) -> Result<()> {
-        let drop_sql = [
-                Table::drop()
-                    .table(CategoryIden::Table)
-                    .if_exists()
-                    .build(SqliteQueryBuilder),
-                Table::drop()
-                    .table(PageCategoriesIden::Table)
-                    .if_exists()
-                    .build(SqliteQueryBuilder),
-                Table::drop()
-                    .table(PageFtsIden::Table)
-                    .if_exists()
-                    .build(SqliteQueryBuilder),
-                Table::drop()
-                    .table(PageIden::Table)
-                    .if_exists()
-                    .build(SqliteQueryBuilder),
-            ]
-            .join("; ");
+/// A read query of the index, to explain with
+/// [`Store::explain_index_query`](crate::Store::explain_index_query).
+#[derive(Clone, Debug)]
+pub enum IndexQuery {
+    /// See [`Store::get_category`](crate::Store::get_category).
+    Categories {
+        slug_lower_bound: Option<CategorySlug>,
+        limit: QueryLimit,
+    },
 
-        self.conn()?.execute_batch(&drop_sql)?;
+    /// See [`Store::get_category_pages`](crate::Store::get_category_pages).
+    CategoryPages {
+        slug: CategorySlug,
+        page_mediawiki_id_lower_bound: Option<u64>,
+        namespace: Option<i64>,
+        limit: QueryLimit,
+    },
 
-        Ok(())
-    }
+    /// See [`Store::get_pages_by_namespace`](crate::Store::get_pages_by_namespace).
+    NamespacePages {
+        ns_id: i64,
+        slug_lower_bound: Option<String>,
+        limit: QueryLimit,
+    },
 
-    pub(crate) fn clear(&mut self) -> Result<()> {
-        self.drop_all()
-            .with_context(
-                || "in Index::clear() while dropping all objects")?;
-        self.vacuum()?;
+    /// See [`Store::get_indexed_page_by_slug`](crate::Store::get_indexed_page_by_slug).
+    PageBySlug {
+        slug: String,
+    },
 
-        // Drop old connection. Closing a sqlite connection seems to
-        // help reduce DB size after dropping all the tables.
-        if let Some(conn /* : Mutex<Connection> */) = self.conn.take() {
-            conn.into_inner()
-                .map_err(|_e: std::sync::PoisonError<_>|
-                         format_err!("PoisonError locking connection mutex in store::Index"))?
-                .close()
-                .map_err(|(_conn, err)| err)?;
-        }
+    /// See [`Store::page_search`](crate::Store::page_search).
+    PageSearch {
+        query: String,
+        options: PageSearchOptions,
+    },
+}
 
-        // Create new connection.
-        let _ = self.conn.insert(Mutex::new(Self::new_conn(&self.opts)?));
+/// How sqlite runs an index query and how long it took, from
+/// [`Store::explain_index_query`](crate::Store::explain_index_query).
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryExplanation {
+    pub name: &'static str,
+    pub sql: String,
+    pub params: Vec<String>,
 
-        self.ensure_schema()
-            .with_context(
-                || "in Index::clear() while creating the schame")?;
+    /// The lines of sqlite's `EXPLAIN QUERY PLAN` output, indented to show the plan's tree.
+    pub plan: Vec<String>,
 
-        Ok(())
-    }
+    /// The rows returned when the query was run.
+    pub rows_len: u64,
+    pub duration_ms: f64,
+}
 
-    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::optimise",
-                          skip(self))]
-    pub(crate) fn optimise(&mut self) -> Result<()> {
-        self.vacuum()?;
-        self.conn()?.execute("ANALYZE;", [])
+/// The order of page search results.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSearchOrder {
+    /// Best matches first: pages titled exactly the search terms, then titles starting with
+    /// them, then by full text search rank.
+    #[default]
+    Relevance,
+
+    /// Most words first.
+    Longest,
+
+    /// Fewest words first.
+    Shortest,
+}
+
+impl PageSearchOrder {
+    /// The order's name, as serialized, e.g. `relevance`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PageSearchOrder::Relevance => "relevance",
+            PageSearchOrder::Longest => "longest",
+            PageSearchOrder::Shortest => "shortest",
+        }
+    }
+}
+
+impl Display for PageSearchOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PageSearchOrder {
+    type Err = wikimedia::Error;
+
+    fn from_str(s: &str) -> Result<PageSearchOrder> {
+        match s {
+            "relevance" => Ok(PageSearchOrder::Relevance),
+            "longest" => Ok(PageSearchOrder::Longest),
+            "shortest" => Ok(PageSearchOrder::Shortest),
+            _ => bail_kind!(User, "Bad page search order '{s}', expected 'relevance', \
+                                   'longest', or 'shortest'"),
+        }
+    }
+}
+
+/// Full text search of page titles.
+///
+/// This is an FTS5 external content table over `page.slug`, so the titles are not stored
+/// twice. The default unicode61 tokenizer splits on '_' as well as ' ', so slugs are
+/// tokenized the same as titles; see [`FtsTokenizer`] for the others. Rows are kept in sync
+/// with `page` by triggers, see `page_fts_schema_sql()`.
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // The private fields are using in PageFtsIden (generated from this).
+struct PageFts {
+    /// The page's `mediawiki_id`.
+    rowid: u64,
+    slug: String,
+    rank: f64,
+}
+
+/// A word in the page full text search index, used to suggest spellings for search
+/// queries, see `Index::suggest()`.
+///
+/// Filled from the `page_fts_vocab` table by `Index::rebuild_spelling()`, which is not kept
+/// in sync by triggers like `page_fts`. The words are themselves indexed by trigram in the
+/// FTS5 table `spell_term_trigram`, see `spelling_schema_sql()`.
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // SpellTermIden (generated from this) is used.
+struct SpellTerm {
+    id: u64,
+    term: String,
+
+    /// The number of pages with the word in their title.
+    pages_len: u64,
+}
+
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // PageCategoriesIden (generated from this) is used.
+struct PageCategories {
+    mediawiki_id: u64,
+    category_slug: String,
+
+    /// The page's sort key in the category from its category link, or its title if the link
+    /// has none. Compared case insensitively, as MediaWiki's default collation does.
+    sort_key: String,
+}
+
+/// A property of a page, such as its display title. See the `PAGE_PROP_*` constants for
+/// the names used.
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // PagePropsIden (generated from this) is used.
+struct PageProps {
+    mediawiki_id: u64,
+    name: String,
+    value: String,
+}
+
+/// A link from a page to an article, parsed from the page's wikitext by
+/// [`wikitext::parse_links`]. Used to find related pages, see `Index::related_pages()`.
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // PageLinksIden (generated from this) is used.
+struct PageLinks {
+    mediawiki_id: u64,
+
+    /// The slug of the linked page, which may not be in the index.
+    target_slug: String,
+}
+
+/// A template or module a page transcludes, parsed from the page's wikitext by
+/// [`wikitext::parse_templates`]. See `Index::get_template_pages()`.
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // PageTemplatesIden (generated from this) is used.
+struct PageTemplates {
+    mediawiki_id: u64,
+
+    /// The slug of the transcluded page, e.g. `Template:Infobox_person`, which may not be in
+    /// the index.
+    template_slug: String,
+}
+
+/// The page chosen for lookups of a slug that matches several pages ignoring case, see
+/// [`SlugCollision`].
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // SlugDisambiguationIden (generated from this) is used.
+struct SlugDisambiguation {
+    /// Compared ignoring case, as in `index_page_by_slug`.
+    slug: String,
+    mediawiki_id: u64,
+}
+
+/// The page property with the title to show for a page, see
+/// [`wikitext::PageProps::display_title`]. Named as in MediaWiki's `page_props` table.
+pub const PAGE_PROP_DISPLAY_TITLE: &str = "displaytitle";
+
+/// The page property with the key a page is sorted by in categories, see
+/// [`wikitext::PageProps::default_sort`].
+pub const PAGE_PROP_DEFAULT_SORT: &str = "defaultsort";
+
+/// The page property with a page's protection, see [`dump::Page::restrictions`].
+pub const PAGE_PROP_RESTRICTIONS: &str = "restrictions";
+
+/// A chunk file with pages in the index, recorded when the chunk's pages are committed.
+///
+/// This lists chunks without reading the chunks directory, which is slow with many chunks
+/// and not possible for remote stores.
+#[derive(Clone, Debug)]
+#[enum_def]
+#[allow(dead_code)] // ChunkIden (generated from this) is used.
+struct Chunk {
+    id: u64,
+    pages_len: u64,
+
+    /// The length of the chunk file in bytes. NULL for chunks indexed before schema version
+    /// 8 until the store fills it in, see `Index::get_chunk_ids_without_bytes_len()`.
+    bytes_len: Option<u64>,
+
+    /// False while the chunk's pages are committed but its file is still at its pending
+    /// path, see `chunk::PendingChunk`. The store finishes pending chunks when it's opened.
+    committed: bool,
+}
+
+/// One run of [`Store::import`](crate::Store::import), recorded when it starts.
+#[derive(Clone, Debug, Serialize)]
+#[enum_def]
+pub struct ImportRun {
+    pub id: u64,
+    pub started: DateTime<Utc>,
+
+    /// When the import finished. `None` while it's running, or if it failed or was stopped.
+    pub finished: Option<DateTime<Utc>>,
+
+    /// The version of `wikimedia-store` that ran the import.
+    pub store_version: String,
+}
+
+/// A [`Migration`] applied to the index, recorded when it's applied. Indexes migrated before
+/// schema version 20 have no record of their earlier migrations.
+#[derive(Clone, Debug, Serialize)]
+#[enum_def]
+pub struct IndexMigration {
+    pub version: i64,
+    pub name: String,
+
+    /// The index's schema version before the migrations applied with this one.
+    pub from_version: i64,
+    pub applied: DateTime<Utc>,
+}
+
+/// An index's schema version and migrations, see `migration_status_at()`.
+#[derive(Clone, Debug)]
+pub(crate) struct MigrationStatus {
+    pub schema_version: i64,
+
+    /// The migrations the index needs, in the order they are applied.
+    pub pending: Vec<Migration>,
+
+    /// The migrations applied to the index, oldest first.
+    pub history: Vec<IndexMigration>,
+}
+
+/// The longest name of a [`SavedSearch`], in characters.
+pub const SAVED_SEARCH_NAME_MAX: usize = 100;
+
+/// A page search saved by name, see [`Store::save_search`](crate::Store::save_search).
+///
+/// Flag filters are saved as terms in `query`, e.g. `-is:disambiguation`.
+#[derive(Clone, Debug, Serialize)]
+#[enum_def]
+pub struct SavedSearch {
+    /// Unique in the store. Up to [`SAVED_SEARCH_NAME_MAX`] characters, not containing `/`.
+    pub name: String,
+    pub query: String,
+    pub min_words: Option<u64>,
+    pub max_words: Option<u64>,
+    pub category: Option<CategorySlug>,
+    pub namespace: Option<i64>,
+    pub order: PageSearchOrder,
+
+    /// When the search was last saved.
+    pub saved: DateTime<Utc>,
+}
+
+impl SavedSearch {
+    /// The options to run the search with [`Store::page_search`](crate::Store::page_search).
+    pub fn page_search_options(&self, limit: QueryLimit) -> PageSearchOptions {
+        PageSearchOptions {
+            limit,
+            min_words: self.min_words,
+            max_words: self.max_words,
+            category: self.category.clone(),
+            namespace: self.namespace,
+            order: self.order,
+            ..Default::default()
+        }
+    }
+
+    fn validate_name(name: &str) -> Result<()> {
+        if name.trim().is_empty() {
+            bail_kind!(User, "Saved search names must not be empty");
+        }
+        if name.chars().count() > SAVED_SEARCH_NAME_MAX {
+            bail_kind!(User, "Saved search name is longer than {SAVED_SEARCH_NAME_MAX} \
+                              characters name='{name}'");
+        }
+        if name.contains('/') {
+            bail_kind!(User, "Saved search names must not contain '/' name='{name}'");
+        }
+        Ok(())
+    }
+}
+
+/// Where a chunk's pages came from, recorded when the chunk's pages are committed. Chunks
+/// imported before schema version 16 have none.
+///
+/// The pages are a contiguous run of pages in one dump file. To read them again, open the
+/// file at `source_seek` and skip `file_pages_start` pages, or for an uncompressed file seek
+/// straight to `source_seek + xml_offset_start`.
+#[derive(Clone, Debug, Serialize)]
+#[enum_def]
+pub struct ChunkProvenance {
+    pub chunk_id: ChunkId,
+
+    /// The [`ImportRun`] that wrote the chunk.
+    pub import_run_id: u64,
+
+    /// Path of the dump file.
+    pub source_path: String,
+
+    /// The `FileSpec::seek` offset the file was first opened with. The counts and offsets
+    /// below are from here.
+    pub source_seek: Option<u64>,
+
+    /// Count of pages read from the file before the chunk's first page. Pages skipped for
+    /// exceeding a parse limit are counted.
+    pub file_pages_start: u64,
+
+    /// Count of pages read from the file up to and including the chunk's last page.
+    pub file_pages_end: u64,
+
+    /// Offset in the file's uncompressed XML just after the page before the chunk's first
+    /// page.
+    pub xml_offset_start: u64,
+
+    /// Offset in the file's uncompressed XML just after the chunk's last page.
+    pub xml_offset_end: u64,
+}
+
+/// Totals over the chunks in the index, from [`Store::chunks_len`](crate::Store::chunks_len)
+/// and store info.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ChunkTotals {
+    pub chunks_len: u64,
+
+    /// The total length of chunks with a known length in bytes.
+    pub bytes_len: u64,
+}
+
+/// A category that at least one page in the store belongs to.
+#[derive(Clone, Debug, Serialize)]
+#[enum_def]
+pub struct Category {
+    pub slug: CategorySlug,
+
+    /// The display name, e.g. `Machine learning` for the slug `Machine_learning`.
+    pub name: CategoryName,
+}
+
+/// How far an import got through one dump file, updated as each chunk is committed.
+#[derive(Clone, Debug, Serialize)]
+#[enum_def]
+pub struct ImportFileProgress {
+    /// Path of the dump file.
+    pub path: String,
+
+    /// The `FileSpec::seek` offset the file was first opened with.
+    pub seek: Option<u64>,
+
+    /// Count of pages read from the file (after `seek`) that are committed to the store.
+    pub pages_committed: u64,
+
+    /// Offset in the source file just after the last committed page.
+    ///
+    /// Only set for uncompressed files, where this offset can be seeked to directly.
+    pub source_offset: Option<u64>,
+
+    /// Whether all pages in the file have been imported.
+    pub complete: bool,
+
+    /// When pages from the file were last committed. `None` for files imported before this
+    /// was recorded.
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// Counts of index rows that refer to rows missing from the index, for example when an
+/// import failed part way through. See [`Store::fsck_index`](crate::Store::fsck_index).
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct IndexOrphans {
+    /// Pages in chunks that are not in the chunk table, so were never committed.
+    pub pages_without_chunk: u64,
+
+    /// Category links from pages that are not in the index.
+    pub page_categories: u64,
+
+    /// Properties of pages that are not in the index.
+    pub page_props: u64,
+
+    /// Links from pages that are not in the index.
+    pub page_links: u64,
+
+    /// Template transclusions by pages that are not in the index.
+    pub page_templates: u64,
+
+    /// Slug disambiguations that choose pages that are not in the index.
+    pub slug_disambiguations: u64,
+
+    /// Full text search rows for pages that are not in the index.
+    pub page_fts: u64,
+
+    /// Categories that no page in the index belongs to.
+    pub categories: u64,
+}
+
+impl IndexOrphans {
+    pub fn total(&self) -> u64 {
+        self.pages_without_chunk + self.page_categories + self.page_props + self.page_links
+            + self.page_templates + self.slug_disambiguations + self.page_fts + self.categories
+    }
+}
+
+impl SlugCollision {
+    /// The page to choose for lookups of the group's slugs: an article rather than a
+    /// redirect, then the page with the most text, then the oldest page.
+    pub fn preferred(&self) -> Option<&Page> {
+        self.pages.iter().min_by_key(|page| (page.is_redirect(),
+                                             page.ns_id != 0,
+                                             std::cmp::Reverse(page.text_bytes),
+                                             page.mediawiki_id))
+    }
+}
+
+impl RelatedPage {
+    /// How related the pages are, higher is more related.
+    pub fn score(&self) -> u64 {
+        self.shared_categories + self.links
+    }
+}
+
+impl Page {
+    /// Read a row with the columns in `PAGE_COLUMNS`.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Page> {
+        Ok(Page {
+            mediawiki_id: row.get(0)?,
+            chunk_id: row.get(1)?,
+            page_chunk_index: row.get(2)?,
+            slug: row.get(3)?,
+            ns_id: row.get(4)?,
+            text_bytes: row.get(5)?,
+            word_count: row.get(6)?,
+            page_flags: row.get::<_, Option<u32>>(7)?.map(PageFlags),
+            redirect_slug: row.get(8)?,
+            redirect_fragment: row.get(9)?,
+            revision_timestamp: row.get::<_, Option<i64>>(10)?
+                                   .and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+        })
+    }
+
+    /// The page's title for display, e.g. `Machine learning` for the slug
+    /// `Machine_learning`. Slugs keep the case of titles, so this is the title as written.
+    pub fn title(&self) -> String {
+        slug::slug_to_title(&self.slug)
+    }
+
+    pub fn store_id(&self) -> StorePageId {
+        StorePageId {
+            chunk_id: ChunkId(self.chunk_id),
+            page_chunk_index: PageChunkIndex(self.page_chunk_index),
+        }
+    }
+
+    /// The page's flags, or none if they weren't computed when it was imported.
+    pub fn flags(&self) -> PageFlags {
+        self.page_flags.unwrap_or_default()
+    }
+
+    /// Whether the page redirects to another page, if known.
+    pub fn is_redirect(&self) -> bool {
+        self.redirect_slug.is_some()
+    }
+
+    /// The page's text statistics, if they were computed when it was imported.
+    pub fn text_stats(&self) -> Option<TextStats> {
+        Some(TextStats {
+            bytes: self.text_bytes?,
+            words: self.word_count?,
+        })
+    }
+}
+
+impl Category {
+    /// Read a row with the columns slug, name.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Category> {
+        Ok(Category {
+            slug: CategorySlug(row.get(0)?),
+            name: CategoryName(row.get(1)?),
+        })
+    }
+}
+
+impl ImportRun {
+    /// Read a row with the columns id, started, finished, store_version.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<ImportRun> {
+        let timestamp = |secs: i64| Utc.timestamp_opt(secs, 0).single();
+        Ok(ImportRun {
+            id: row.get(0)?,
+            started: timestamp(row.get(1)?).unwrap_or_default(),
+            finished: row.get::<_, Option<i64>>(2)?.and_then(timestamp),
+            store_version: row.get(3)?,
+        })
+    }
+}
+
+impl SavedSearch {
+    /// Read a row with the columns name, query, min_words, max_words, category, namespace,
+    /// order, saved.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<SavedSearch> {
+        Ok(SavedSearch {
+            name: row.get(0)?,
+            query: row.get(1)?,
+            min_words: row.get(2)?,
+            max_words: row.get(3)?,
+            category: row.get::<_, Option<String>>(4)?.map(CategorySlug),
+            namespace: row.get(5)?,
+            // Orders this version doesn't know were saved by a later one, so fall back to
+            // the default.
+            order: row.get::<_, String>(6)?.parse().unwrap_or_default(),
+            saved: Utc.timestamp_opt(row.get(7)?, 0).single().unwrap_or_default(),
+        })
+    }
+}
+
+impl IndexMigration {
+    /// Read a row with the columns version, name, from_version, applied.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<IndexMigration> {
+        Ok(IndexMigration {
+            version: row.get(0)?,
+            name: row.get(1)?,
+            from_version: row.get(2)?,
+            applied: Utc.timestamp_opt(row.get(3)?, 0).single().unwrap_or_default(),
+        })
+    }
+}
+
+impl ChunkProvenance {
+    /// Read a row with the columns chunk_id, import_run_id, source_path, source_seek,
+    /// file_pages_start, file_pages_end, xml_offset_start, xml_offset_end.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<ChunkProvenance> {
+        Ok(ChunkProvenance {
+            chunk_id: ChunkId(row.get(0)?),
+            import_run_id: row.get(1)?,
+            source_path: row.get(2)?,
+            source_seek: row.get(3)?,
+            file_pages_start: row.get(4)?,
+            file_pages_end: row.get(5)?,
+            xml_offset_start: row.get(6)?,
+            xml_offset_end: row.get(7)?,
+        })
+    }
+}
+
+impl ImportFileProgress {
+    /// Read a row with the columns path, seek, pages_committed, source_offset, complete,
+    /// updated.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<ImportFileProgress> {
+        Ok(ImportFileProgress {
+            path: row.get(0)?,
+            seek: row.get(1)?,
+            pages_committed: row.get(2)?,
+            source_offset: row.get(3)?,
+            complete: row.get(4)?,
+            updated: row.get::<_, Option<i64>>(5)?
+                        .and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+        })
+    }
+}
+
+impl Options {
+    pub(crate) fn build(self) -> Result<Index> {
+        Index::new(self)
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> BatchOptions {
+        BatchOptions {
+            max_values_per_batch: VALUES_PER_BATCH_DEFAULT,
+            adaptive: false,
+            target_commit_latency: StdDuration::from_millis(500),
+            busy_retries: 5,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// The rows per insert statement to use after a commit that took `latency`.
+    fn next_values_per_batch(&self, curr: usize, latency: StdDuration, busy: bool) -> usize {
+        if !self.adaptive {
+            return curr;
+        }
+
+        let next = if busy || latency > self.target_commit_latency {
+            curr / 2
+        } else if latency < self.target_commit_latency / 2 {
+            curr + curr / 2
+        } else {
+            curr
+        };
+        next.clamp(VALUES_PER_BATCH_MIN, VALUES_PER_BATCH_MAX)
+    }
+}
+
+impl Index {
+    fn new(opts: Options) -> Result<Index> {
+        let (conn, layout) = Self::new_conn(&opts)?;
+
+        let mut index = Index {
+            conn: Some(Mutex::new(conn)),
+            values_per_batch: AtomicUsize::new(
+                opts.batch.max_values_per_batch.clamp(1, VALUES_PER_BATCH_MAX)),
+            layout,
+
+            opts: opts,
+        };
+
+        let fts_tokenize = index.opts.fts_tokenizer.as_ref().and_then(FtsTokenizer::fts5_args);
+        index.ensure_schema(fts_tokenize.as_deref(), index.opts.auto_migrate)?;
+
+        if let Some(tokenizer) = index.opts.fts_tokenizer.as_ref() {
+            let current = index.fts_tokenize()?;
+            if current != tokenizer.fts5_args() {
+                tracing::warn!(?tokenizer, current,
+                               "The store's full text search index has a different tokenizer \
+                                to the one requested. Rebuild it to use the new tokenizer, \
+                                e.g. with `wmd build-fts`.");
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Open a connection to the index, attaching its search database if it has one.
+    fn new_conn(opts: &Options) -> Result<(Connection, Layout)> {
+        fs::create_dir_all(&*opts.path)?;
+        let db_path = opts.path.join("index.db");
+        let search_path = opts.path.join(SEARCH_DB_FILE_NAME);
+
+        let new_index = !db_path.try_exists()?;
+        if new_index {
+            // Left from an index that was deleted, e.g. to rebuild it from its chunks.
+            remove_db_files(&search_path)?;
+        }
+        let layout = match (new_index, search_path.try_exists()?) {
+            (_, true) => Layout::SplitSearch,
+            (true, false) => opts.layout,
+            (false, false) => Layout::Single,
+        };
+
+        let open_flags =
+            OpenFlags::SQLITE_OPEN_READ_WRITE |
+            OpenFlags::SQLITE_OPEN_CREATE |
+            OpenFlags::SQLITE_OPEN_URI |
+            OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+        let mut conn = Connection::open_with_flags(db_path, open_flags)?;
+
+        conn.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)?;
+        conn.set_db_config(DbConfig::SQLITE_DBCONFIG_ENABLE_FKEY, true)?;
+
+        conn.trace(Some(|s: &str| tracing::trace!(sql = s, "Index::conn::trace")));
+
+        conn.create_scalar_function(
+            LOWER_UNICODE_FUNCTION, 1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| Ok(ctx.get::<Option<String>>(0)?.map(|text| text.to_lowercase())))?;
+
+        if layout == Layout::SplitSearch {
+            let search_path_str = search_path.to_str().ok_or_else(
+                || format_err!("Index path is not UTF-8 path='{}'", search_path.display()))?;
+            conn.execute(&format!("ATTACH DATABASE ?1 AS {SEARCH_SCHEMA}"), [search_path_str])
+                .with_context(|| format!("attaching index search database path='{}'",
+                                         search_path.display()))?;
+        }
+
+        // TODO: more safety pragmas.
+        // Without a schema name this sets every attached database's journal mode.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        Ok((conn, layout))
+    }
+
+    /// The schema that holds the search tables.
+    fn search_schema(&self) -> &'static str {
+        match self.layout {
+            Layout::Single => "main",
+            Layout::SplitSearch => SEARCH_SCHEMA,
+        }
+    }
+
+    /// Create any missing tables, migrating older schemas first if `auto_migrate` allows.
+    /// A new full text search table is created with FTS5 tokenizer arguments
+    /// `fts_tokenize`, or the default tokenizer if `None`.
+    fn ensure_schema(&mut self, fts_tokenize: Option<&str>, auto_migrate: AutoMigrate
+    ) -> Result<()> {
+        let schema_sql = [
+                // Table category
+                Table::create()
+                    .table(CategoryIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(CategoryIden::Slug)
+                             .text()
+                             .not_null()
+                             .primary_key())
+                    .col(ColumnDef::new(CategoryIden::Name)
+                             .text()
+                             .not_null())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT, WITHOUT ROWID",
+
+                // Table page
+                Table::create()
+                    .table(PageIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(PageIden::MediawikiId)
+                            .integer()
+                            .not_null()
+                            .primary_key())
+                    .col(ColumnDef::new(PageIden::ChunkId)
+                            .integer()
+                            .not_null())
+                    .col(ColumnDef::new(PageIden::PageChunkIndex)
+                            .integer()
+                            .not_null())
+                    .col(ColumnDef::new(PageIden::Slug)
+                            .text()
+                            .not_null()
+                    )
+                    .col(ColumnDef::new(PageIden::NsId)
+                            .integer()
+                            .not_null())
+                    .col(ColumnDef::new(PageIden::TextBytes)
+                            .integer())
+                    .col(ColumnDef::new(PageIden::WordCount)
+                            .integer())
+                    .col(ColumnDef::new(PageIden::PageFlags)
+                            .integer())
+                    .col(ColumnDef::new(PageIden::RedirectSlug)
+                            .text())
+                    .col(ColumnDef::new(PageIden::RedirectFragment)
+                            .text())
+                    .col(ColumnDef::new(PageIden::RevisionTimestamp)
+                            .integer())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT",
+                format!(r#"
+                    CREATE INDEX IF NOT EXISTS index_page_by_slug ON {page_table}
+                    ({page_slug} COLLATE NOCASE)
+                "#, page_table = PageIden::Table.to_string(),
+                    page_slug = PageIden::Slug.to_string()),
+                sea_query::Index::create()
+                    .name("index_page_by_revision_timestamp")
+                    .if_not_exists()
+                    .table(PageIden::Table)
+                    .col(PageIden::RevisionTimestamp)
+                    .col(PageIden::MediawikiId)
+                    .build(SqliteQueryBuilder),
+                sea_query::Index::create()
+                    .name("index_page_by_ns_id")
+                    .if_not_exists()
+                    .table(PageIden::Table)
+                    .col(PageIden::NsId)
+                    .col(PageIden::Slug)
+                    .build(SqliteQueryBuilder),
+
+                // Table page_fts (with FTS5)
+                page_fts_schema_sql(self.layout, fts_tokenize),
+
+                // Tables spell_term, spell_term_trigram (with FTS5), page_fts_vocab (with
+                // fts5vocab)
+                spelling_schema_sql(self.search_schema()),
+
+                // Table page_categories
+                Table::create()
+                    .table(PageCategoriesIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(PageCategoriesIden::MediawikiId)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(PageCategoriesIden::CategorySlug)
+                             .text()
+                             .not_null()
+                    )
+                    .col(ColumnDef::new(PageCategoriesIden::SortKey)
+                             .text()
+                             .not_null()
+                             .extra("COLLATE NOCASE".to_string()))
+                    .primary_key(sea_query::Index::create()
+                                     .col(PageCategoriesIden::MediawikiId)
+                                     .col(PageCategoriesIden::CategorySlug)
+                                     .unique())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT",
+                sea_query::Index::create()
+                    .name("index_page_categories_by_category_slug")
+                    .if_not_exists()
+                    .table(PageCategoriesIden::Table)
+                    .col(PageCategoriesIden::CategorySlug)
+                    .col(PageCategoriesIden::MediawikiId)
+                    .unique()
+                    .build(SqliteQueryBuilder),
+                sea_query::Index::create()
+                    .name("index_page_categories_by_sort_key")
+                    .if_not_exists()
+                    .table(PageCategoriesIden::Table)
+                    .col(PageCategoriesIden::CategorySlug)
+                    .col(PageCategoriesIden::SortKey)
+                    .col(PageCategoriesIden::MediawikiId)
+                    .unique()
+                    .build(SqliteQueryBuilder),
+
+                // Table page_props
+                Table::create()
+                    .table(PagePropsIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(PagePropsIden::MediawikiId)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(PagePropsIden::Name)
+                             .text()
+                             .not_null())
+                    .col(ColumnDef::new(PagePropsIden::Value)
+                             .text()
+                             .not_null())
+                    .primary_key(sea_query::Index::create()
+                                     .col(PagePropsIden::MediawikiId)
+                                     .col(PagePropsIden::Name)
+                                     .unique())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT, WITHOUT ROWID",
+
+                // Table page_links
+                Table::create()
+                    .table(PageLinksIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(PageLinksIden::MediawikiId)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(PageLinksIden::TargetSlug)
+                             .text()
+                             .not_null())
+                    .primary_key(sea_query::Index::create()
+                                     .col(PageLinksIden::MediawikiId)
+                                     .col(PageLinksIden::TargetSlug)
+                                     .unique())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT, WITHOUT ROWID",
+                sea_query::Index::create()
+                    .name("index_page_links_by_target_slug")
+                    .if_not_exists()
+                    .table(PageLinksIden::Table)
+                    .col(PageLinksIden::TargetSlug)
+                    .col(PageLinksIden::MediawikiId)
+                    .unique()
+                    .build(SqliteQueryBuilder),
+
+                // Table page_templates
+                Table::create()
+                    .table(PageTemplatesIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(PageTemplatesIden::MediawikiId)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(PageTemplatesIden::TemplateSlug)
+                             .text()
+                             .not_null())
+                    .primary_key(sea_query::Index::create()
+                                     .col(PageTemplatesIden::MediawikiId)
+                                     .col(PageTemplatesIden::TemplateSlug)
+                                     .unique())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT, WITHOUT ROWID",
+                sea_query::Index::create()
+                    .name("index_page_templates_by_template_slug")
+                    .if_not_exists()
+                    .table(PageTemplatesIden::Table)
+                    .col(PageTemplatesIden::TemplateSlug)
+                    .col(PageTemplatesIden::MediawikiId)
+                    .unique()
+                    .build(SqliteQueryBuilder),
+
+                // Table slug_disambiguation
+                Table::create()
+                    .table(SlugDisambiguationIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(SlugDisambiguationIden::Slug)
+                             .text()
+                             .not_null()
+                             .primary_key()
+                             .extra("COLLATE NOCASE".to_string()))
+                    .col(ColumnDef::new(SlugDisambiguationIden::MediawikiId)
+                             .integer()
+                             .not_null())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT, WITHOUT ROWID",
+
+                // Table chunk
+                chunk_schema_sql(),
+
+                // Table import_file_progress
+                Table::create()
+                    .table(ImportFileProgressIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImportFileProgressIden::Path)
+                             .text()
+                             .not_null()
+                             .primary_key())
+                    .col(ColumnDef::new(ImportFileProgressIden::Seek)
+                             .integer())
+                    .col(ColumnDef::new(ImportFileProgressIden::PagesCommitted)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ImportFileProgressIden::SourceOffset)
+                             .integer())
+                    .col(ColumnDef::new(ImportFileProgressIden::Complete)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ImportFileProgressIden::Updated)
+                             .integer())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT",
+
+                // Table import_run
+                Table::create()
+                    .table(ImportRunIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ImportRunIden::Id)
+                             .integer()
+                             .not_null()
+                             .primary_key())
+                    .col(ColumnDef::new(ImportRunIden::Started)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ImportRunIden::Finished)
+                             .integer())
+                    .col(ColumnDef::new(ImportRunIden::StoreVersion)
+                             .text()
+                             .not_null())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT",
+
+                // Table chunk_provenance
+                Table::create()
+                    .table(ChunkProvenanceIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ChunkProvenanceIden::ChunkId)
+                             .integer()
+                             .not_null()
+                             .primary_key())
+                    .col(ColumnDef::new(ChunkProvenanceIden::ImportRunId)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ChunkProvenanceIden::SourcePath)
+                             .text()
+                             .not_null())
+                    .col(ColumnDef::new(ChunkProvenanceIden::SourceSeek)
+                             .integer())
+                    .col(ColumnDef::new(ChunkProvenanceIden::FilePagesStart)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ChunkProvenanceIden::FilePagesEnd)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ChunkProvenanceIden::XmlOffsetStart)
+                             .integer()
+                             .not_null())
+                    .col(ColumnDef::new(ChunkProvenanceIden::XmlOffsetEnd)
+                             .integer()
+                             .not_null())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT",
+
+                // Table saved_search
+                Table::create()
+                    .table(SavedSearchIden::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(SavedSearchIden::Name)
+                             .text()
+                             .not_null()
+                             .primary_key())
+                    .col(ColumnDef::new(SavedSearchIden::Query)
+                             .text()
+                             .not_null())
+                    .col(ColumnDef::new(SavedSearchIden::MinWords)
+                             .integer())
+                    .col(ColumnDef::new(SavedSearchIden::MaxWords)
+                             .integer())
+                    .col(ColumnDef::new(SavedSearchIden::Category)
+                             .text())
+                    .col(ColumnDef::new(SavedSearchIden::Namespace)
+                             .integer())
+                    .col(ColumnDef::new(SavedSearchIden::Order)
+                             .text()
+                             .not_null())
+                    .col(ColumnDef::new(SavedSearchIden::Saved)
+                             .integer()
+                             .not_null())
+                    .build(SqliteQueryBuilder)
+                    + " STRICT",
+
+                // Table index_migration
+                index_migration_schema_sql(),
+            ]
+            .join("; ");
+
+        // Migrate existing databases before creating indexes on new columns.
+        let rebuild_fts = self.migrate(auto_migrate)?;
+
+        self.conn()?.execute_batch(&schema_sql)?;
+
+        if rebuild_fts {
+            tracing::info!("Migrating store index: rebuilding page full text search");
+            self.rebuild_fts()?;
+        }
+
+        self.conn()?.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(())
+    }
+
+    /// Apply the pending migrations in `MIGRATIONS` to upgrade a database created by an
+    /// older version to the current schema, in one transaction, recording each in the
+    /// index_migration table. Readers keep seeing the old schema until it commits.
+    ///
+    /// Fails without changing the database if `auto_migrate` doesn't allow every pending
+    /// migration.
+    ///
+    /// Returns true if the full text search table must be rebuilt after creating the schema.
+    fn migrate(&mut self, auto_migrate: AutoMigrate) -> Result<bool> {
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let (version, pending) = pending_migrations(&txn)?;
+        if version > SCHEMA_VERSION {
+            bail_kind!(User,
+                       "Store index has schema version {version}, newer than this version \
+                        supports ({SCHEMA_VERSION}). Upgrade wmd to open it.");
+        }
+        if pending.is_empty() {
+            return Ok(false);
+        }
+        if let Some(problem) = auto_migrate.problem(&pending) {
+            bail_kind!(User, "{problem}");
+        }
+
+        txn.execute_batch(&index_migration_schema_sql())?;
+
+        let mut rebuild_fts = false;
+        for migration in pending.iter() {
+            tracing::debug!(version = migration.version, name = migration.name,
+                            safe = migration.safe, "Applying store index migration");
+            rebuild_fts |= (migration.apply)(&txn)
+                .with_context(|| format!("in Index::migrate() applying migration {migration}"))?;
+
+            txn.execute(&format!(
+                "INSERT OR REPLACE INTO {index_migration_table} \
+                     ({version}, {name}, {from_version}, {applied}) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                index_migration_table = IndexMigrationIden::Table.to_string(),
+                version = IndexMigrationIden::Version.to_string(),
+                name = IndexMigrationIden::Name.to_string(),
+                from_version = IndexMigrationIden::FromVersion.to_string(),
+                applied = IndexMigrationIden::Applied.to_string()),
+                rusqlite::params![migration.version, migration.name, version,
+                                  Utc::now().timestamp()])?;
+        }
+
+        txn.commit()
+           .with_context(|| format!("in Index::migrate() from version {version}"))?;
+
+        tracing::info!(from_version = version, to_version = SCHEMA_VERSION,
+                       migrations_len = pending.len(), "Migrated store index");
+
+        Ok(rebuild_fts)
+    }
+
+    /// Version 1 added page.ns_id. Pages were only indexed by slug before, so derive the
+    /// namespace from the slug's prefix.
+    fn migrate_page_ns_ids(txn: &Transaction) -> Result<bool> {
+        let page_table = PageIden::Table.to_string();
+        let missing_ns_id = table_columns(txn, &page_table)?
+            .is_some_and(|columns| !columns.contains(&PageIden::NsId.to_string()));
+        if !missing_ns_id {
+            return Ok(false);
+        }
+
+        tracing::info!("Migrating store index: adding page namespace IDs");
+
+        txn.execute_batch(&format!(
+            "ALTER TABLE {page_table} ADD COLUMN {ns_id} INTEGER NOT NULL DEFAULT 0",
+            ns_id = PageIden::NsId.to_string()))?;
+
+        let pages = {
+            let mut statement = txn.prepare(&format!(
+                "SELECT {mediawiki_id}, {slug} FROM {page_table}",
+                mediawiki_id = PageIden::MediawikiId.to_string(),
+                slug = PageIden::Slug.to_string()))?;
+            let rows = statement.query_map([], |row| Ok((row.get::<_, u64>(0)?,
+                                                         row.get::<_, String>(1)?)))?;
+            rows.collect::<rusqlite::Result<Vec<(u64, String)>>>()?
+        };
+
+        let mut update = txn.prepare(&format!(
+            "UPDATE {page_table} SET {ns_id} = ?1 WHERE {mediawiki_id} = ?2",
+            ns_id = PageIden::NsId.to_string(),
+            mediawiki_id = PageIden::MediawikiId.to_string()))?;
+        for (mediawiki_id, slug) in pages.iter() {
+            let ns_id = dump::Namespace::from_page_slug(slug).key();
+            if ns_id != 0 {
+                update.execute(rusqlite::params![ns_id, mediawiki_id])?;
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Version 2 made page_fts an external content table. Drop a page_fts with its own copy
+    /// of titles, to be created again with the rest of the schema then rebuilt.
+    fn migrate_external_content_fts(txn: &Transaction) -> Result<bool> {
+        let page_fts_sql: Option<String> = txn.query_row(
+            "SELECT sql FROM sqlite_schema WHERE type = 'table' AND name = ?1",
+            [PageFtsIden::Table.to_string()],
+            |row| row.get(0)).optional()?;
+        let rebuild_fts = page_fts_sql.is_some_and(|sql| !sql.contains("content="));
+        if rebuild_fts {
+            txn.execute_batch(&format!("DROP TABLE {page_fts__table}",
+                                       page_fts__table = PageFtsIden::Table.to_string()))?;
+        }
+
+        Ok(rebuild_fts)
+    }
+
+    /// Version 3 added category.name. Category slugs are normalised names, so the name is
+    /// the slug with spaces for underscores.
+    fn migrate_category_names(txn: &Transaction) -> Result<bool> {
+        let category_table = CategoryIden::Table.to_string();
+        let missing_category_name = table_columns(txn, &category_table)?
+            .is_some_and(|columns| !columns.contains(&CategoryIden::Name.to_string()));
+        if !missing_category_name {
+            return Ok(false);
+        }
+
+        tracing::info!("Migrating store index: adding category names");
+
+        txn.execute_batch(&format!(
+            "ALTER TABLE {category_table} ADD COLUMN {name} TEXT NOT NULL DEFAULT ''; \
+             UPDATE {category_table} SET {name} = replace({slug}, '_', ' ')",
+            name = CategoryIden::Name.to_string(),
+            slug = CategoryIden::Slug.to_string()))?;
+
+        Ok(false)
+    }
+
+    /// Version 4 added page.text_bytes and page.word_count. These are computed from page
+    /// text during import, so they stay NULL until pages are imported again.
+    fn migrate_page_text_stats(txn: &Transaction) -> Result<bool> {
+        let page_table = PageIden::Table.to_string();
+        let missing_text_stats = table_columns(txn, &page_table)?
+            .is_some_and(|columns| !columns.contains(&PageIden::WordCount.to_string()));
+        if !missing_text_stats {
+            return Ok(false);
+        }
+
+        tracing::info!("Migrating store index: adding page text statistics");
+
+        txn.execute_batch(&format!(
+            "ALTER TABLE {page_table} ADD COLUMN {text_bytes} INTEGER; \
+             ALTER TABLE {page_table} ADD COLUMN {word_count} INTEGER",
+            text_bytes = PageIden::TextBytes.to_string(),
+            word_count = PageIden::WordCount.to_string()))?;
+
+        Ok(false)
+    }
+
+    /// Version 5 added page.page_flags, computed during import like the text statistics.
+    fn migrate_page_flags(txn: &Transaction) -> Result<bool> {
+        add_column_if_missing(txn, "page flags", &PageIden::Table, &PageIden::PageFlags,
+                              "INTEGER")?;
+
+        Ok(false)
+    }
+
+    /// Version 6 added page_categories.sort_key. Sort keys weren't parsed before, so use
+    /// each page's title until pages are imported again.
+    fn migrate_page_category_sort_keys(txn: &Transaction) -> Result<bool> {
+        let page_categories_table = PageCategoriesIden::Table.to_string();
+        let missing_sort_key = table_columns(txn, &page_categories_table)?
+            .is_some_and(|columns| !columns.contains(&PageCategoriesIden::SortKey.to_string()));
+        if !missing_sort_key {
+            return Ok(false);
+        }
+
+        tracing::info!("Migrating store index: adding page category sort keys");
+
+        txn.execute_batch(&format!(
+            "ALTER TABLE {page_categories_table} \
+                 ADD COLUMN {sort_key} TEXT NOT NULL DEFAULT '' COLLATE NOCASE; \
+             UPDATE {page_categories_table} SET {sort_key} = coalesce( \
+                 (SELECT replace({page_table}.{slug}, '_', ' ') FROM {page_table} \
+                  WHERE {page_table}.{mediawiki_id} = \
+                        {page_categories_table}.{mediawiki_id}), \
+                 '')",
+            sort_key = PageCategoriesIden::SortKey.to_string(),
+            page_table = PageIden::Table.to_string(),
+            slug = PageIden::Slug.to_string(),
+            mediawiki_id = PageIden::MediawikiId.to_string()))?;
+
+        Ok(false)
+    }
+
+    /// Version 7 added page.redirect_slug, parsed from page text during import, so
+    /// redirects aren't followed until pages are imported again.
+    fn migrate_page_redirects(txn: &Transaction) -> Result<bool> {
+        add_column_if_missing(txn, "page redirects", &PageIden::Table, &PageIden::RedirectSlug,
+                              "TEXT")?;
+
+        Ok(false)
+    }
+
+    /// Version 8 added the chunk table. List the chunks with pages in the index; the store
+    /// fills in their lengths from the chunk files when it is opened.
+    fn migrate_chunk_list(txn: &Transaction) -> Result<bool> {
+        let page_table = PageIden::Table.to_string();
+        let missing_chunk_table = table_columns(txn, &page_table)?.is_some()
+            && table_columns(txn, &ChunkIden::Table.to_string())?.is_none();
+        if !missing_chunk_table {
+            return Ok(false);
+        }
+
+        tracing::info!("Migrating store index: adding chunk list");
+
+        txn.execute_batch(&chunk_schema_sql())?;
+        txn.execute_batch(&format!(
+            "INSERT INTO {chunk_table} ({chunk_id}, {pages_len}) \
+             SELECT {page_chunk_id}, count(*) FROM {page_table} \
+             GROUP BY {page_chunk_id}",
+            chunk_table = ChunkIden::Table.to_string(),
+            chunk_id = ChunkIden::Id.to_string(),
+            pages_len = ChunkIden::PagesLen.to_string(),
+            page_chunk_id = PageIden::ChunkId.to_string()))?;
+
+        Ok(false)
+    }
+
+    /// Version 10 added chunk.committed. Chunks were renamed into place before their pages
+    /// were committed, so all existing chunks are committed.
+    fn migrate_chunk_committed(txn: &Transaction) -> Result<bool> {
+        add_column_if_missing(txn, "chunk commit state", &ChunkIden::Table,
+                              &ChunkIden::Committed, "INTEGER NOT NULL DEFAULT 1")?;
+
+        Ok(false)
+    }
+
+    /// Version 14 added import_file_progress.updated. When earlier imports happened is
+    /// unknown, so it is left NULL.
+    fn migrate_import_file_updated(txn: &Transaction) -> Result<bool> {
+        add_column_if_missing(txn, "import file update times",
+                              &ImportFileProgressIden::Table, &ImportFileProgressIden::Updated,
+                              "INTEGER")?;
+
+        Ok(false)
+    }
+
+    /// Version 15 added page.revision_timestamp, so pages imported before aren't listed as
+    /// changed until they are imported again.
+    fn migrate_page_revision_timestamps(txn: &Transaction) -> Result<bool> {
+        add_column_if_missing(txn, "page revision timestamps", &PageIden::Table,
+                              &PageIden::RevisionTimestamp, "INTEGER")?;
+
+        Ok(false)
+    }
+
+    /// Version 18 added page.redirect_fragment, so redirects go to the top of their target
+    /// page until pages are imported again.
+    fn migrate_page_redirect_fragments(txn: &Transaction) -> Result<bool> {
+        add_column_if_missing(txn, "page redirect fragments", &PageIden::Table,
+                              &PageIden::RedirectFragment, "TEXT")?;
+
+        Ok(false)
+    }
+
+    /// Migrations that only add tables, which are created with the rest of the schema
+    /// after migrating. Tables of page data are empty until pages are imported again.
+    fn migrate_tables_created_with_schema(_txn: &Transaction) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Rebuild the page full text search index from the page table.
+    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::rebuild_fts",
+                          skip(self))]
+    pub(crate) fn rebuild_fts(&mut self) -> Result<()> {
+        self.conn()?.execute_batch(&rebuild_fts_sql(self.layout))
+            .with_context(
+                || "in Index::rebuild_fts() while rebuilding the page_fts table")?;
+        Ok(())
+    }
+
+    /// Recreate the page full text search table with a new tokenizer, then rebuild it.
+    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::retokenize_fts",
+                          skip(self))]
+    pub(crate) fn retokenize_fts(&mut self, tokenizer: &FtsTokenizer) -> Result<()> {
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        // The triggers on `page` refer to page_fts by name, so they work with the new table.
+        txn.execute_batch(&format!("DROP TABLE IF EXISTS {page_fts__table}; {schema_sql}",
+                                   page_fts__table = PageFtsIden::Table.to_string(),
+                                   schema_sql = page_fts_schema_sql(
+                                       self.layout, tokenizer.fts5_args().as_deref())))?;
+        txn.execute_batch(&rebuild_fts_sql(self.layout))
+            .with_context(
+                || "in Index::retokenize_fts() while rebuilding the page_fts table")?;
+
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Rebuild the words used for spelling suggestions from the page full text search
+    /// index.
+    ///
+    /// Words are only collected for word tokenizers. With the trigram tokenizer the index
+    /// has no words, so the table is left empty and there are no suggestions.
+    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::rebuild_spelling",
+                          skip(self))]
+    pub(crate) fn rebuild_spelling(&mut self) -> Result<()> {
+        let is_trigram = self.fts_tokenize()?
+                             .is_some_and(|args| args.starts_with("trigram"));
+
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        txn.execute(&format!("DELETE FROM {spell_term_table}",
+                             spell_term_table = SpellTermIden::Table.to_string()), [])?;
+        if !is_trigram {
+            txn.execute(&format!(
+                "INSERT INTO {spell_term_table}({term}, {pages_len}) \
+                 SELECT term, doc FROM {page_fts_vocab_table}",
+                spell_term_table = SpellTermIden::Table.to_string(),
+                term = SpellTermIden::Term.to_string(),
+                pages_len = SpellTermIden::PagesLen.to_string(),
+                page_fts_vocab_table = PAGE_FTS_VOCAB_TABLE), [])?;
+        }
+        txn.execute(&format!(
+            "INSERT INTO {spell_term_trigram_table}({spell_term_trigram_table}) \
+             VALUES('rebuild')",
+            spell_term_trigram_table = SPELL_TERM_TRIGRAM_TABLE), [])
+            .with_context(
+                || "in Index::rebuild_spelling() while rebuilding the trigram table")?;
+
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Suggest a spelling for a page search query, or `None` if every word in it is in a
+    /// page title or has no close match.
+    ///
+    /// Each unknown word is replaced by the word in the index with the fewest edits from it,
+    /// preferring words in more titles. Flag filters and FTS5 operators are kept as they are.
+    pub(crate) fn suggest(&self, query: &str) -> Result<Option<String>> {
+        let conn = self.conn()?;
+        let mut known_statement = conn.prepare_cached(&format!(
+            "SELECT 1 FROM {page_fts__table} WHERE {page_fts__table} MATCH ?1 LIMIT 1",
+            page_fts__table = PageFtsIden::Table.to_string()))?;
+        let mut candidates_statement = conn.prepare_cached(&format!(
+            "SELECT {spell_term_table}.{term}, {spell_term_table}.{pages_len} \
+             FROM {spell_term_trigram_table} \
+             INNER JOIN {spell_term_table} \
+                 ON {spell_term_table}.{id} = {spell_term_trigram_table}.rowid \
+             WHERE {spell_term_trigram_table} MATCH ?1 \
+             ORDER BY {spell_term_trigram_table}.rank \
+             LIMIT {SUGGEST_CANDIDATES_MAX}",
+            spell_term_table = SpellTermIden::Table.to_string(),
+            term = SpellTermIden::Term.to_string(),
+            pages_len = SpellTermIden::PagesLen.to_string(),
+            id = SpellTermIden::Id.to_string(),
+            spell_term_trigram_table = SPELL_TERM_TRIGRAM_TABLE))?;
+
+        let mut suggestion = String::with_capacity(query.len());
+        let mut changed = false;
+        let mut end = 0;
+        for word in lazy_regex!(r"(?:^|\s)-?is:\S*|[\p{L}\p{N}]+").find_iter(query) {
+            let text = word.as_str();
+            if text.trim_start().starts_with("is:") || text.trim_start().starts_with("-is:")
+                || ["AND", "OR", "NOT", "NEAR"].contains(&text)
+            {
+                continue;
+            }
+
+            let fts_word = format!("\"{text}\"");
+            if known_statement.exists([&fts_word])? {
+                continue;
+            }
+
+            let lower: Vec<char> = text.to_lowercase().chars().collect();
+            if lower.len() < 3 {
+                continue;
+            }
+            let trigrams = lower.windows(3)
+                                .map(|trigram| format!("\"{}\"", String::from_iter(trigram)))
+                                .collect::<Vec<String>>()
+                                .join(" OR ");
+            let distance_max = if lower.len() <= 4 { 1 } else { 2 };
+
+            let mut best: Option<(usize, u64, String)> = None;
+            let mut rows = candidates_statement.query([&trigrams])?;
+            while let Some(row) = rows.next()? {
+                let term: String = row.get(0)?;
+                let pages_len: u64 = row.get(1)?;
+                let distance = edit_distance(&lower, &term.chars().collect::<Vec<char>>());
+                if distance > distance_max {
+                    continue;
+                }
+                let is_better = best.as_ref().is_none_or(|(best_distance, best_pages_len, _)|
+                    (distance, std::cmp::Reverse(pages_len))
+                        < (*best_distance, std::cmp::Reverse(*best_pages_len)));
+                if is_better {
+                    best = Some((distance, pages_len, term));
+                }
+            }
+
+            if let Some((_, _, term)) = best {
+                suggestion.push_str(&query[end..word.start()]);
+                suggestion.push_str(&term);
+                end = word.end();
+                changed = true;
+            }
+        }
+        suggestion.push_str(&query[end..]);
+
+        Ok(changed.then_some(suggestion))
+    }
+
+    /// The FTS5 tokenizer arguments the page full text search table was created with, or
+    /// `None` for the default tokenizer.
+    pub(crate) fn fts_tokenize(&self) -> Result<Option<String>> {
+        let sql: Option<String> = self.conn()?.query_row(
+            &format!("SELECT sql FROM {schema}.sqlite_schema \
+                      WHERE type = 'table' AND name = ?1",
+                     schema = self.search_schema()),
+            [PageFtsIden::Table.to_string()],
+            |row| row.get(0)).optional()?;
+        Ok(sql.as_deref().and_then(parse_fts_tokenize))
+    }
+
+    fn drop_all(&mut self) -> Result<()> {
+        let drop_sql = [
+                Table::drop()
+                    .table(CategoryIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                format!("DROP TABLE IF EXISTS {spell_term_trigram_table}; \
+                         DROP TABLE IF EXISTS {page_fts_vocab_table}",
+                        spell_term_trigram_table = SPELL_TERM_TRIGRAM_TABLE,
+                        page_fts_vocab_table = PAGE_FTS_VOCAB_TABLE),
+                Table::drop()
+                    .table(SpellTermIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(PageCategoriesIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(PagePropsIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(PageLinksIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(PageTemplatesIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(SlugDisambiguationIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(PageFtsIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(PageIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(ImportFileProgressIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+                Table::drop()
+                    .table(ChunkIden::Table)
+                    .if_exists()
+                    .build(SqliteQueryBuilder),
+            ]
+            .join("; ");
+
+        self.conn()?.execute_batch(&drop_sql)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn clear(&mut self) -> Result<()> {
+        // Keep the full text search tokenizer unless a new one was requested.
+        let fts_tokenize = match self.opts.fts_tokenizer.as_ref() {
+            Some(tokenizer) => tokenizer.fts5_args(),
+            None => self.fts_tokenize()?,
+        };
+
+        self.drop_all()
+            .with_context(
+                || "in Index::clear() while dropping all objects")?;
+        self.vacuum()?;
+
+        // Drop old connection. Closing a sqlite connection seems to
+        // help reduce DB size after dropping all the tables.
+        self.close_conn()?;
+
+        // Create new connection.
+        let (conn, layout) = Self::new_conn(&self.opts)?;
+        let _ = self.conn.insert(Mutex::new(conn));
+        self.layout = layout;
+
+        self.ensure_schema(fts_tokenize.as_deref(), self.opts.auto_migrate)
+            .with_context(
+                || "in Index::clear() while creating the schame")?;
+
+        Ok(())
+    }
+
+    fn close_conn(&mut self) -> Result<()> {
+        if let Some(conn /* : Mutex<Connection> */) = self.conn.take() {
+            conn.into_inner()
+                .map_err(|_e: std::sync::PoisonError<_>|
+                         format_err!("PoisonError locking connection mutex in store::Index"))?
+                .close()
+                .map_err(|(_conn, err)| err)?;
+        }
+        Ok(())
+    }
+
+    /// Write a compacted copy of the database to a new file at `path`.
+    ///
+    /// The copy is a consistent snapshot even while pages are being imported. It's a sqlite
+    /// database with the schema version in its `user_version`, like the index itself, so
+    /// it can be served as a remote store's index too.
+    ///
+    /// When `cancellation` is cancelled the copy is interrupted and the partly written file
+    /// removed.
+    pub(crate) fn export(&self, path: &Path, cancellation: &CancellationToken) -> Result<()> {
+        if path.try_exists()? {
+            bail_kind!(User, "Index export file already exists path='{}'", path.display());
+
+        }
+        let path_str = path.to_str().ok_or_else(
+            || format_err!("Index export path is not UTF-8 path='{}'", path.display()))?;
+
+        let conn = self.conn()?;
+        let interrupt = conn.get_interrupt_handle();
+        let done = AtomicBool::new(false);
+        let res = std::thread::scope(|scope| {
+            // `VACUUM INTO` is one long statement, so watch for cancellation on another
+            // thread and interrupt it from there.
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    if cancellation.is_cancelled() {
+                        interrupt.interrupt();
+                        return;
+                    }
+                    std::thread::sleep(StdDuration::from_millis(100));
+                }
+            });
+            let res = conn.execute("VACUUM INTO ?1", [path_str]);
+            done.store(true, Ordering::SeqCst);
+            res
+        });
+
+        if res.is_err() && cancellation.is_cancelled() {
+            match fs::remove_file(path) {
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+            bail_kind!(Cancelled, "Index export cancelled");
+        }
+        res.with_context(|| format!("in Index::export() writing path='{}'", path.display()))?;
+        drop(conn);
+
+        if self.layout == Layout::SplitSearch {
+            self.export_search_tables(path)
+                .with_context(|| format!("in Index::export() adding search tables path='{}'",
+                                         path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the search tables to an index file exported from an index with
+    /// [`Layout::SplitSearch`], whose `VACUUM INTO` only copied `index.db`, so the file has
+    /// the single file layout.
+    fn export_search_tables(&self, path: &Path) -> Result<()> {
+        let search_path = self.opts.path.join(SEARCH_DB_FILE_NAME);
+        let search_path_str = search_path.to_str().ok_or_else(
+            || format_err!("Index path is not UTF-8 path='{}'", search_path.display()))?;
+
+        let mut conn = Connection::open(path)?;
+        conn.execute(&format!("ATTACH DATABASE ?1 AS {SEARCH_SCHEMA}"), [search_path_str])?;
+        let txn = conn.transaction()?;
+        txn.execute_batch(&format!(
+            "{page_fts_schema_sql}; \
+             {rebuild_fts_sql}; \
+             {spelling_schema_sql}; \
+             INSERT INTO main.{spell_term_table} \
+                 SELECT * FROM {SEARCH_SCHEMA}.{spell_term_table}; \
+             INSERT INTO main.{spell_term_trigram_table}({spell_term_trigram_table}) \
+                 VALUES('rebuild')",
+            page_fts_schema_sql = page_fts_schema_sql(Layout::Single,
+                                                      self.fts_tokenize()?.as_deref()),
+            rebuild_fts_sql = rebuild_fts_sql(Layout::Single),
+            spelling_schema_sql = spelling_schema_sql("main"),
+            spell_term_table = SpellTermIden::Table.to_string(),
+            spell_term_trigram_table = SPELL_TERM_TRIGRAM_TABLE))?;
+        txn.commit()?;
+        conn.execute(&format!("DETACH DATABASE {SEARCH_SCHEMA}"), [])?;
+        Ok(())
+    }
+
+    /// Replace the database with a copy of an index file written by `Index::export()`, then
+    /// migrate it to the current schema.
+    ///
+    /// Other connections to this index must be closed first.
+    pub(crate) fn replace_from_file(&mut self, path: &Path) -> Result<()> {
+        let version = check_index_file(path)
+            .with_context(|| format!("checking index file path='{}'", path.display()))?;
+        tracing::info!(path = %path.display(), schema_version = version,
+                       "Replacing store index");
+
+        let db_path = self.opts.path.join("index.db");
+        let temp_path = self.opts.path.join("index.db.import");
+        fs::copy(path, &temp_path)
+            .with_context(|| format!("copying index file from='{from}' to='{to}'",
+                                     from = path.display(), to = temp_path.display()))?;
+
+        self.close_conn()?;
+
+        // Closing the last connection normally checkpoints and removes these, but they
+        // must not be applied to the new database if they are left over.
+        for suffix in ["-wal", "-shm"] {
+            match fs::remove_file(self.opts.path.join(format!("index.db{suffix}"))) {
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        fs::rename(&temp_path, &db_path)?;
+
+        // Index files written by `Index::export()` have the single file layout, with their
+        // own search tables.
+        remove_db_files(&self.opts.path.join(SEARCH_DB_FILE_NAME))?;
+
+        let (conn, layout) = Self::new_conn(&self.opts)?;
+        let _ = self.conn.insert(Mutex::new(conn));
+        self.layout = layout;
+        let fts_tokenize = self.opts.fts_tokenizer.as_ref().and_then(FtsTokenizer::fts5_args);
+        // Replacing the index is already a deliberate change, so apply every migration the
+        // new file needs.
+        self.ensure_schema(fts_tokenize.as_deref(), AutoMigrate::All)
+            .with_context(
+                || "in Index::replace_from_file() while migrating the schema")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::optimise",
+                          skip(self))]
+    pub(crate) fn optimise(&mut self) -> Result<()> {
+        self.vacuum()?;
+        self.analyze()?;
+        self.optimise_fts()?;
+        Ok(())
+    }
+
+    /// Update the statistics SQLite's query planner uses.
+    pub(crate) fn analyze(&self) -> Result<()> {
+        self.conn()?.execute("ANALYZE;", [])
+            .with_context(
+                || "in Index::analyze()")?;
+        Ok(())
+    }
+
+    /// Run SQLite's `PRAGMA optimize`, which analyzes tables whose statistics are out of
+    /// date.
+    pub(crate) fn pragma_optimize(&self) -> Result<()> {
+        self.conn()?.execute_batch("PRAGMA optimize;")
             .with_context(
-                || "in Index::optimise() while analysing the database")?;
+                || "in Index::pragma_optimize()")?;
+        Ok(())
+    }
+
+    /// Merge the segments of the full text search index.
+    pub(crate) fn optimise_fts(&self) -> Result<()> {
         self.conn()?.execute(&*format!(
             "INSERT INTO {page_fts__table}({page_fts__table}) VALUES('optimize')",
             page_fts__table = PageFtsIden::Table.to_string()
             ), [])
             .with_context(
-                || "in Index::optimise() while optimising the page_fts table")?;
+                || "in Index::optimise_fts()")?;
+        Ok(())
+    }
+
+    /// Run SQLite's `PRAGMA integrity_check`, returning the problems it found, or an empty
+    /// `Vec` if the database is OK. Reads the whole database.
+    pub(crate) fn integrity_check(&self) -> Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("PRAGMA integrity_check;")?;
+        let mut problems = stmt.query_map([], |row| row.get::<_, String>(0))?
+                               .collect::<rusqlite::Result<Vec<String>>>()?;
+        if problems == ["ok"] {
+            problems.clear();
+        }
+        Ok(problems)
+    }
+
+    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::vacuum",
+                          skip(self))]
+    pub(crate) fn vacuum(&mut self) -> Result<()> {
+        self.conn()?.execute("VACUUM;", [])
+            .with_context(
+                || "in Index::vacuum()")?;
+        if self.layout == Layout::SplitSearch {
+            self.conn()?.execute(&format!("VACUUM {SEARCH_SCHEMA};"), [])
+                .with_context(
+                    || "in Index::vacuum() while vacuuming the search database")?;
+        }
+        Ok(())
+    }
+
+    fn conn(&self) -> Result<MutexGuard<Connection>> {
+        self.conn.as_ref().ok_or_else(|| format_err!("self.conn is None"))?
+            .lock()
+            .map_err(|_e: std::sync::PoisonError<_>|
+                     format_err!("PoisonError locking connection mutex in store::Index"))
+    }
+
+    pub(crate) fn import_batch_builder<'index>(&'index self
+    ) -> Result<ImportBatchBuilder<'index>> {
+        Ok(ImportBatchBuilder::new(self))
+    }
+
+    pub(crate) fn get_category(&self, slug_lower_bound: Option<&CategorySlug>, limit: QueryLimit
+    ) -> Result<Vec<Category>>
+    {
+        self.query_rows("get_category", &Self::get_category_query(slug_lower_bound, limit),
+                        Category::from_row)
+    }
+
+    fn get_category_query(slug_lower_bound: Option<&CategorySlug>, limit: QueryLimit
+    ) -> SelectStatement
+    {
+        Query::select()
+            .from(CategoryIden::Table)
+            .column(CategoryIden::Slug)
+            .column(CategoryIden::Name)
+            .limit(limit.get())
+            .and_where_option(slug_lower_bound.map(
+                |lower| Expr::col(CategoryIden::Slug).gt(lower.0.as_str())))
+            .take()
+    }
+
+    pub(crate) fn get_category_by_slug(&self, slug: &CategorySlug
+    ) -> Result<Option<Category>>
+    {
+        let query = Query::select()
+            .from(CategoryIden::Table)
+            .column(CategoryIden::Slug)
+            .column(CategoryIden::Name)
+            .and_where(Expr::col(CategoryIden::Slug).eq(slug.0.as_str()))
+            .take();
+
+        Ok(self.query_rows("get_category_by_slug", &query, Category::from_row)?.pop())
+    }
+
+    /// Get the pages in a category, ordered by sort key then MediaWiki ID.
+    ///
+    /// `page_mediawiki_id_lower_bound` is the last page of the previous call, to return the
+    /// pages after it in that order.
+    pub(crate) fn get_category_pages(
+        &self,
+        slug: &CategorySlug,
+        page_mediawiki_id_lower_bound: Option<u64>,
+        namespace: Option<i64>,
+        limit: QueryLimit,
+    ) -> Result<Vec<Page>>
+    {
+        let query = Self::get_category_pages_query(slug, page_mediawiki_id_lower_bound,
+                                                   namespace, limit);
+        self.query_rows("get_category_pages", &query, Page::from_row)
+    }
+
+    /// Get pages whose latest revision was saved after `since`, continuing after `cursor`.
+    pub(crate) fn pages_modified_since(
+        &self,
+        since: DateTime<Utc>,
+        cursor: Option<ChangesCursor>,
+        limit: QueryLimit,
+    ) -> Result<PageChanges>
+    {
+        let limit = limit.get();
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            .and_where(Expr::col(PageIden::RevisionTimestamp).gt(since.timestamp()))
+            .and_where_option(cursor.map(
+                |cursor|
+                Expr::cust_with_values(
+                    &format!("({revision_timestamp}, {mediawiki_id}) > (?, ?)",
+                             revision_timestamp = PageIden::RevisionTimestamp.to_string(),
+                             mediawiki_id = PageIden::MediawikiId.to_string()),
+                    [Value::from(cursor.timestamp), Value::from(cursor.mediawiki_id)])))
+            .order_by(PageIden::RevisionTimestamp, Order::Asc)
+            .order_by(PageIden::MediawikiId, Order::Asc)
+            .limit(limit)
+            .take();
+
+        let pages = self.query_rows("pages_modified_since", &query, Page::from_row)?;
+        let next_cursor = match pages.last() {
+            Some(last) if pages.len() as u64 == limit => Some(ChangesCursor {
+                timestamp: last.revision_timestamp.expect("filtered on revision_timestamp")
+                               .timestamp(),
+                mediawiki_id: last.mediawiki_id,
+            }),
+            _ => None,
+        };
+        Ok(PageChanges {
+            pages,
+            next_cursor,
+        })
+    }
+
+    fn get_category_pages_query(
+        slug: &CategorySlug,
+        page_mediawiki_id_lower_bound: Option<u64>,
+        namespace: Option<i64>,
+        limit: QueryLimit,
+    ) -> SelectStatement
+    {
+        let limit = limit.get();
+        let page_categories_table = PageCategoriesIden::Table.to_string();
+
+        Query::select()
+            .columns(PAGE_COLUMNS.map(|col| (PageIden::Table, col)))
+            .from(PageCategoriesIden::Table)
+            .inner_join(PageIden::Table,
+                        Expr::col((PageCategoriesIden::Table, PageCategoriesIden::MediawikiId))
+                            .equals((PageIden::Table, PageIden::MediawikiId)))
+            .and_where(Expr::col((PageCategoriesIden::Table, PageCategoriesIden::CategorySlug))
+                           .eq(&*slug.0))
+            .and_where_option(namespace.map(
+                |ns_id| Expr::col((PageIden::Table, PageIden::NsId)).eq(ns_id)))
+            .and_where_option(page_mediawiki_id_lower_bound.map(
+                |id|
+                Expr::cust_with_values(
+                    &format!(
+                        "({page_categories_table}.{sort_key}, \
+                          {page_categories_table}.{mediawiki_id}) > \
+                         (SELECT {sort_key}, {mediawiki_id} FROM {page_categories_table} \
+                          WHERE {category_slug} = ? AND {mediawiki_id} = ?)",
+                        sort_key = PageCategoriesIden::SortKey.to_string(),
+                        mediawiki_id = PageCategoriesIden::MediawikiId.to_string(),
+                        category_slug = PageCategoriesIden::CategorySlug.to_string()),
+                    [Value::from(slug.0.clone()), Value::from(id)])))
+            .order_by((PageCategoriesIden::Table, PageCategoriesIden::SortKey), Order::Asc)
+            .order_by((PageCategoriesIden::Table, PageCategoriesIden::MediawikiId), Order::Asc)
+            .limit(limit)
+            .take()
+    }
+
+    /// Get the IDs of chunks in the index in ascending order, after `lower_bound` if set.
+    pub(crate) fn get_chunk_ids(&self, lower_bound: Option<ChunkId>, limit: QueryLimit
+    ) -> Result<Vec<ChunkId>>
+    {
+        let query = Query::select()
+            .from(ChunkIden::Table)
+            .column(ChunkIden::Id)
+            .and_where_option(lower_bound.map(|lower| Expr::col(ChunkIden::Id).gt(lower.0)))
+            .order_by(ChunkIden::Id, Order::Asc)
+            .limit(limit.get())
+            .take();
+
+        self.query_rows("get_chunk_ids", &query, |row| Ok(ChunkId(row.get(0)?)))
+    }
+
+    /// Get the IDs of chunks recorded as pending, whose files may be at their pending paths
+    /// or chunk paths, in ascending order. See `chunk::PendingChunk`.
+    pub(crate) fn get_pending_chunk_ids(&self) -> Result<Vec<ChunkId>> {
+        let query = Query::select()
+            .from(ChunkIden::Table)
+            .column(ChunkIden::Id)
+            .and_where(Expr::col(ChunkIden::Committed).eq(false))
+            .order_by(ChunkIden::Id, Order::Asc)
+            .take();
+
+        self.query_rows("get_pending_chunk_ids", &query, |row| Ok(ChunkId(row.get(0)?)))
+    }
+
+    /// Record the chunk `chunk_meta` as pending, before its file is renamed to its chunk path.
+    /// Its pages are committed later with an [`ImportBatchBuilder`] that sets the chunk. See
+    /// `chunk::PendingChunk`.
+    pub(crate) fn put_pending_chunk(&self, chunk_meta: &ChunkMeta) -> Result<()> {
+        let (sql, params) = Query::insert()
+            .into_table(ChunkIden::Table)
+            .columns([ChunkIden::Id, ChunkIden::PagesLen, ChunkIden::BytesLen,
+                      ChunkIden::Committed])
+            .values([chunk_meta.id.0.into(), chunk_meta.pages_len.into(),
+                     chunk_meta.bytes_len.0.into(), false.into()])?
+            .on_conflict(OnConflict::column(ChunkIden::Id)
+                             .update_columns([ChunkIden::PagesLen, ChunkIden::BytesLen,
+                                              ChunkIden::Committed])
+                             .to_owned())
+            .build_rusqlite(SqliteQueryBuilder);
+        self.conn()?.execute(&sql, &*params.as_params())?;
+
+        Ok(())
+    }
+
+    /// Record that the files of chunks `chunk_ids` are at their chunk paths.
+    pub(crate) fn set_chunks_committed(&self, chunk_ids: &[ChunkId]) -> Result<()> {
+        if chunk_ids.is_empty() {
+            return Ok(());
+        }
+
+        let (sql, params) = Query::update()
+            .table(ChunkIden::Table)
+            .value(ChunkIden::Committed, true)
+            .and_where(Expr::col(ChunkIden::Id).is_in(chunk_ids.iter().map(|id| id.0)))
+            .build_rusqlite(SqliteQueryBuilder);
+        self.conn()?.execute(&sql, &*params.as_params())?;
+
+        Ok(())
+    }
+
+    /// Get the IDs of chunks indexed before their lengths were recorded, in ascending order.
+    pub(crate) fn get_chunk_ids_without_bytes_len(&self) -> Result<Vec<ChunkId>> {
+        let query = Query::select()
+            .from(ChunkIden::Table)
+            .column(ChunkIden::Id)
+            .and_where(Expr::col(ChunkIden::BytesLen).is_null())
+            .order_by(ChunkIden::Id, Order::Asc)
+            .take();
+
+        self.query_rows("get_chunk_ids_without_bytes_len", &query,
+                        |row| Ok(ChunkId(row.get(0)?)))
+    }
+
+    pub(crate) fn set_chunk_bytes_len(&self, chunk_id: ChunkId, bytes_len: u64) -> Result<()> {
+        let (sql, params) = Query::update()
+            .table(ChunkIden::Table)
+            .value(ChunkIden::BytesLen, bytes_len)
+            .and_where(Expr::col(ChunkIden::Id).eq(chunk_id.0))
+            .build_rusqlite(SqliteQueryBuilder);
+        let params2 = &*params.as_params();
+
+        self.conn()?.execute(&sql, params2)?;
+        Ok(())
+    }
+
+    pub(crate) fn get_chunk_totals(&self) -> Result<ChunkTotals> {
+        let query = Query::select()
+            .from(ChunkIden::Table)
+            .expr(Expr::col(ChunkIden::Id).count())
+            .expr(Expr::col(ChunkIden::BytesLen).sum())
+            .take();
+
+        let totals = self.query_rows(
+            "get_chunk_totals", &query,
+            |row| Ok(ChunkTotals {
+                chunks_len: row.get(0)?,
+                bytes_len: row.get::<_, Option<u64>>(1)?.unwrap_or(0),
+            }))?;
+        Ok(totals.first().copied().unwrap_or(ChunkTotals { chunks_len: 0, bytes_len: 0 }))
+    }
+
+    pub(crate) fn get_import_file_progress(&self, path: &str
+    ) -> Result<Option<ImportFileProgress>>
+    {
+        let (sql, params) = Query::select()
+            .from(ImportFileProgressIden::Table)
+            .columns([ImportFileProgressIden::Path,
+                      ImportFileProgressIden::Seek,
+                      ImportFileProgressIden::PagesCommitted,
+                      ImportFileProgressIden::SourceOffset,
+                      ImportFileProgressIden::Complete,
+                      ImportFileProgressIden::Updated])
+            .and_where(Expr::col(ImportFileProgressIden::Path).eq(path))
+            .build_rusqlite(SqliteQueryBuilder);
+        let params2 = &*params.as_params();
+
+        let conn = self.conn()?;
+
+        conn.query_row(&*sql, params2, ImportFileProgress::from_row)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Record that an import is starting, returning the new [`ImportRun`]'s ID.
+    pub(crate) fn start_import_run(&self) -> Result<u64> {
+        let (sql, params) = Query::insert()
+            .into_table(ImportRunIden::Table)
+            .columns([ImportRunIden::Started, ImportRunIden::StoreVersion])
+            .values([chrono::Utc::now().timestamp().into(), crate::VERSION.into()])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let conn = self.conn()?;
+        conn.execute(&sql, &*params.as_params())?;
+        Ok(conn.last_insert_rowid().try_into().expect("u64 from positive rowid"))
+    }
+
+    /// Record that the import run `id` finished without an error.
+    pub(crate) fn finish_import_run(&self, id: u64) -> Result<()> {
+        let (sql, params) = Query::update()
+            .table(ImportRunIden::Table)
+            .value(ImportRunIden::Finished, chrono::Utc::now().timestamp())
+            .and_where(Expr::col(ImportRunIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+        self.conn()?.execute(&sql, &*params.as_params())?;
+        Ok(())
+    }
+
+    pub(crate) fn get_import_run(&self, id: u64) -> Result<Option<ImportRun>> {
+        let query = Query::select()
+            .from(ImportRunIden::Table)
+            .columns([ImportRunIden::Id,
+                      ImportRunIden::Started,
+                      ImportRunIden::Finished,
+                      ImportRunIden::StoreVersion])
+            .and_where(Expr::col(ImportRunIden::Id).eq(id))
+            .take();
+        Ok(self.query_rows("get_import_run", &query, ImportRun::from_row)?.pop())
+    }
+
+    /// The import runs that wrote the store's chunks, in ID order. Chunks imported before
+    /// provenance was recorded have no import run, so aren't counted.
+    pub(crate) fn get_import_runs_with_chunks(&self) -> Result<Vec<ImportRun>> {
+        let query = Query::select()
+            .from(ImportRunIden::Table)
+            .columns([ImportRunIden::Id,
+                      ImportRunIden::Started,
+                      ImportRunIden::Finished,
+                      ImportRunIden::StoreVersion])
+            .and_where(Expr::col(ImportRunIden::Id).in_subquery(
+                Query::select()
+                    .distinct()
+                    .column(ChunkProvenanceIden::ImportRunId)
+                    .from(ChunkProvenanceIden::Table)
+                    .take()))
+            .order_by(ImportRunIden::Id, Order::Asc)
+            .take();
+        self.query_rows("get_import_runs_with_chunks", &query, ImportRun::from_row)
+    }
+
+    /// Save `search`, replacing any saved search with the same name.
+    pub(crate) fn put_saved_search(&self, search: &SavedSearch) -> Result<()> {
+        SavedSearch::validate_name(&search.name)?;
+
+        let (sql, params) = Query::insert()
+            .into_table(SavedSearchIden::Table)
+            .columns([SavedSearchIden::Name,
+                      SavedSearchIden::Query,
+                      SavedSearchIden::MinWords,
+                      SavedSearchIden::MaxWords,
+                      SavedSearchIden::Category,
+                      SavedSearchIden::Namespace,
+                      SavedSearchIden::Order,
+                      SavedSearchIden::Saved])
+            .values([search.name.as_str().into(),
+                     search.query.as_str().into(),
+                     search.min_words.into(),
+                     search.max_words.into(),
+                     search.category.as_ref().map(|slug| slug.0.clone()).into(),
+                     search.namespace.into(),
+                     search.order.as_str().into(),
+                     search.saved.timestamp().into()])?
+            .on_conflict(OnConflict::column(SavedSearchIden::Name)
+                             .update_columns([SavedSearchIden::Query,
+                                              SavedSearchIden::MinWords,
+                                              SavedSearchIden::MaxWords,
+                                              SavedSearchIden::Category,
+                                              SavedSearchIden::Namespace,
+                                              SavedSearchIden::Order,
+                                              SavedSearchIden::Saved])
+                             .to_owned())
+            .build_rusqlite(SqliteQueryBuilder);
+        self.conn()?.execute(&sql, &*params.as_params())?;
+        Ok(())
+    }
+
+    /// All saved searches, ordered by name.
+    pub(crate) fn get_saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        let query = Self::saved_search_query()
+            .order_by(SavedSearchIden::Name, Order::Asc)
+            .take();
+        self.query_rows("get_saved_searches", &query, SavedSearch::from_row)
+    }
+
+    pub(crate) fn get_saved_search(&self, name: &str) -> Result<Option<SavedSearch>> {
+        let query = Self::saved_search_query()
+            .and_where(Expr::col(SavedSearchIden::Name).eq(name))
+            .take();
+        Ok(self.query_rows("get_saved_search", &query, SavedSearch::from_row)?.pop())
+    }
+
+    fn saved_search_query() -> SelectStatement {
+        Query::select()
+            .from(SavedSearchIden::Table)
+            .columns([SavedSearchIden::Name,
+                      SavedSearchIden::Query,
+                      SavedSearchIden::MinWords,
+                      SavedSearchIden::MaxWords,
+                      SavedSearchIden::Category,
+                      SavedSearchIden::Namespace,
+                      SavedSearchIden::Order,
+                      SavedSearchIden::Saved])
+            .take()
+    }
+
+    /// Delete the saved search `name`. Returns false if there was none.
+    pub(crate) fn delete_saved_search(&self, name: &str) -> Result<bool> {
+        let (sql, params) = Query::delete()
+            .from_table(SavedSearchIden::Table)
+            .and_where(Expr::col(SavedSearchIden::Name).eq(name))
+            .build_rusqlite(SqliteQueryBuilder);
+        let deleted = self.conn()?.execute(&sql, &*params.as_params())?;
+        Ok(deleted > 0)
+    }
+
+    pub(crate) fn get_chunk_provenance(&self, chunk_id: ChunkId
+    ) -> Result<Option<ChunkProvenance>>
+    {
+        let query = Query::select()
+            .from(ChunkProvenanceIden::Table)
+            .columns([ChunkProvenanceIden::ChunkId,
+                      ChunkProvenanceIden::ImportRunId,
+                      ChunkProvenanceIden::SourcePath,
+                      ChunkProvenanceIden::SourceSeek,
+                      ChunkProvenanceIden::FilePagesStart,
+                      ChunkProvenanceIden::FilePagesEnd,
+                      ChunkProvenanceIden::XmlOffsetStart,
+                      ChunkProvenanceIden::XmlOffsetEnd])
+            .and_where(Expr::col(ChunkProvenanceIden::ChunkId).eq(chunk_id.0))
+            .take();
+        Ok(self.query_rows("get_chunk_provenance", &query, ChunkProvenance::from_row)?.pop())
+    }
+
+    /// Get the import progress of every dump file imported into the store, ordered by path.
+    pub(crate) fn get_import_file_progress_all(&self) -> Result<Vec<ImportFileProgress>> {
+        let (sql, params) = Query::select()
+            .from(ImportFileProgressIden::Table)
+            .columns([ImportFileProgressIden::Path,
+                      ImportFileProgressIden::Seek,
+                      ImportFileProgressIden::PagesCommitted,
+                      ImportFileProgressIden::SourceOffset,
+                      ImportFileProgressIden::Complete,
+                      ImportFileProgressIden::Updated])
+            .order_by(ImportFileProgressIden::Path, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+        let params2 = &*params.as_params();
+
+        let conn = self.conn()?;
+        let mut statement = conn.prepare_cached(&sql)?;
+        let rows = statement.query_map(params2, ImportFileProgress::from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<ImportFileProgress>>>()?)
+    }
+
+    /// Get the properties of a page by name, see the `PAGE_PROP_*` constants.
+    pub(crate) fn get_page_props(&self, mediawiki_id: u64) -> Result<HashMap<String, String>> {
+        let query = Query::select()
+            .from(PagePropsIden::Table)
+            .columns([PagePropsIden::Name, PagePropsIden::Value])
+            .and_where(Expr::col(PagePropsIden::MediawikiId).eq(mediawiki_id))
+            .take();
+
+        let props = self.query_rows("get_page_props", &query,
+                                    |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(props.into_iter().collect())
+    }
+
+    /// Get the property `name` of each page in `mediawiki_ids` that has it, by MediaWiki ID.
+    pub(crate) fn get_page_prop_values(&self, name: &str, mediawiki_ids: &[u64]
+    ) -> Result<HashMap<u64, String>>
+    {
+        if mediawiki_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let query = Query::select()
+            .from(PagePropsIden::Table)
+            .columns([PagePropsIden::MediawikiId, PagePropsIden::Value])
+            .and_where(Expr::col(PagePropsIden::Name).eq(name))
+            .and_where(Expr::col(PagePropsIden::MediawikiId)
+                           .is_in(mediawiki_ids.iter().copied()))
+            .take();
+
+        let values = self.query_rows("get_page_prop_values", &query,
+                                     |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(values.into_iter().collect())
+    }
+
+    /// Count the pages in the index.
+    pub(crate) fn pages_len(&self) -> Result<u64> {
+        let query = Query::select()
+            .from(PageIden::Table)
+            .expr(Expr::col(PageIden::MediawikiId).count())
+            .take();
+
+        let counts = self.query_rows("pages_len", &query, |row| row.get(0))?;
+        Ok(counts.first().copied().unwrap_or(0))
+    }
+
+    /// Get up to `limit` distinct articles chosen at random, not including redirects.
+    ///
+    /// Each is the first article at or after a random MediaWiki ID, so articles after large
+    /// gaps in the IDs are more likely to be chosen. Returns fewer than `limit` articles if
+    /// the same one is chosen more than once.
+    pub(crate) fn random_articles(&self, limit: u64) -> Result<Vec<Page>> {
+        let id = PageIden::MediawikiId.to_string();
+        let page_table = PageIden::Table.to_string();
+        let random_id = format!(
+            "(SELECT min({id}) FROM {page_table}) \
+             + abs(random()) % ((SELECT max({id}) FROM {page_table}) \
+                                - (SELECT min({id}) FROM {page_table}) + 1)");
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            .and_where(Expr::col(PageIden::MediawikiId).gte(Expr::cust(&random_id)))
+            .and_where(Expr::col(PageIden::NsId).eq(dump::Namespace::PAGE.key()))
+            .and_where(Expr::col(PageIden::RedirectSlug).is_null())
+            .order_by(PageIden::MediawikiId, Order::Asc)
+            .limit(1)
+            .take();
+
+        let mut out = Vec::<Page>::new();
+        for _ in 0..limit {
+            for page in self.query_rows("random_articles", &query, Page::from_row)? {
+                if !out.iter().any(|p| p.mediawiki_id == page.mediawiki_id) {
+                    out.push(page);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Get up to `limit` categories with the most pages, most first.
+    ///
+    /// This counts every category link in the index, so it is slow for large stores.
+    pub(crate) fn top_categories(&self, limit: QueryLimit) -> Result<Vec<CategoryFacet>> {
+        let pages_len = Alias::new("pages_len");
+        self.query_rows(
+            "top_categories",
+            &Query::select()
+                .from(PageCategoriesIden::Table)
+                .inner_join(CategoryIden::Table,
+                            Expr::col((CategoryIden::Table, CategoryIden::Slug))
+                                .equals((PageCategoriesIden::Table,
+                                         PageCategoriesIden::CategorySlug)))
+                .column((CategoryIden::Table, CategoryIden::Slug))
+                .column((CategoryIden::Table, CategoryIden::Name))
+                .expr_as(Expr::col((PageCategoriesIden::Table,
+                                    PageCategoriesIden::MediawikiId)).count(),
+                         pages_len.clone())
+                .group_by_columns([(CategoryIden::Table, CategoryIden::Slug),
+                                   (CategoryIden::Table, CategoryIden::Name)])
+                .order_by(pages_len, Order::Desc)
+                .order_by((CategoryIden::Table, CategoryIden::Slug), Order::Asc)
+                .limit(limit.get())
+                .take(),
+            |row| Ok(CategoryFacet {
+                category: Category::from_row(row)?,
+                pages_len: row.get(2)?,
+            }))
+    }
+
+    /// Count the pages in each namespace, ordered by namespace key.
+    pub(crate) fn pages_len_by_namespace(&self) -> Result<Vec<(i64, u64)>> {
+        let query = Query::select()
+            .from(PageIden::Table)
+            .column(PageIden::NsId)
+            .expr(Expr::col(PageIden::MediawikiId).count())
+            .group_by_col(PageIden::NsId)
+            .order_by(PageIden::NsId, Order::Asc)
+            .take();
+
+        self.query_rows("pages_len_by_namespace", &query, |row| Ok((row.get(0)?, row.get(1)?)))
+    }
+
+    /// Count the categories in the index.
+    pub(crate) fn categories_len(&self) -> Result<u64> {
+        let query = Query::select()
+            .from(CategoryIden::Table)
+            .expr(Expr::col(CategoryIden::Slug).count())
+            .take();
+
+        let counts = self.query_rows("categories_len", &query, |row| row.get(0))?;
+        Ok(counts.first().copied().unwrap_or(0))
+    }
+
+    /// The length in bytes of the index database file, not including its write-ahead log.
+    pub(crate) fn db_bytes_len(&self) -> Result<u64> {
+        Ok(fs::metadata(self.opts.path.join("index.db"))?.len())
+    }
+
+    /// The length in bytes of the search database file of an index with
+    /// [`Layout::SplitSearch`], not including its write-ahead log.
+    pub(crate) fn search_db_bytes_len(&self) -> Result<Option<u64>> {
+        match self.layout {
+            Layout::Single => Ok(None),
+            Layout::SplitSearch =>
+                Ok(Some(fs::metadata(self.opts.path.join(SEARCH_DB_FILE_NAME))?.len())),
+        }
+    }
+
+    /// Count the rows that refer to missing rows, see [`IndexOrphans`].
+    pub(crate) fn find_orphans(&self) -> Result<IndexOrphans> {
+        let conn = self.conn()?;
+        let count = |from_where: &str| -> Result<u64> {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {from_where}"), [], |row| row.get(0))
+                .with_context(|| format!("in Index::find_orphans() FROM {from_where}"))
+        };
+
+        let orphans = IndexOrphans {
+            pages_without_chunk: count(&orphan_sql::pages_without_chunk())?,
+            page_categories: count(&orphan_sql::page_categories())?,
+            page_props: count(&orphan_sql::page_props())?,
+            page_links: count(&orphan_sql::page_links())?,
+            page_templates: count(&orphan_sql::page_templates())?,
+            slug_disambiguations: count(&orphan_sql::slug_disambiguations())?,
+            page_fts: count(&orphan_sql::page_fts())?,
+            categories: count(&orphan_sql::categories())?,
+        };
+        Ok(orphans)
+    }
+
+    /// Delete the pages in chunks `chunk_ids` and the chunks' rows, e.g. because their chunk
+    /// files are missing. Returns the count of pages deleted.
+    ///
+    /// Rows that referred to the pages are left, and found by `find_orphans()`.
+    pub(crate) fn delete_chunks(&mut self, chunk_ids: &[ChunkId]) -> Result<u64> {
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let mut pages_deleted = 0;
+        for chunk_id in chunk_ids {
+            pages_deleted += txn.execute(
+                &format!("DELETE FROM {page_table} WHERE {chunk_id_col} = ?1",
+                         page_table = PageIden::Table.to_string(),
+                         chunk_id_col = PageIden::ChunkId.to_string()),
+                [chunk_id.0])? as u64;
+            txn.execute(
+                &format!("DELETE FROM {chunk_table} WHERE {id} = ?1",
+                         chunk_table = ChunkIden::Table.to_string(),
+                         id = ChunkIden::Id.to_string()),
+                [chunk_id.0])?;
+        }
+
+        txn.commit()?;
+        Ok(pages_deleted)
+    }
+
+    /// Delete the pages with MediaWiki IDs `mediawiki_ids` and their rows from the index in
+    /// one transaction, see [`delete_page_with_txn`]. Returns the count of pages deleted.
+    pub(crate) fn delete_pages(&mut self, mediawiki_ids: &[u64]) -> Result<u64> {
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let mut pages_deleted = 0;
+        for mediawiki_id in mediawiki_ids {
+            pages_deleted += delete_page_with_txn(&txn, *mediawiki_id)?;
+        }
+
+        txn.commit()?;
+        Ok(pages_deleted)
+    }
+
+    /// Delete the rows that refer to missing rows, see [`IndexOrphans`]. Returns the counts
+    /// deleted.
+    ///
+    /// Deleting pages without a chunk can leave more orphans in other tables, which are
+    /// deleted too, so the counts can be higher than `find_orphans()` returned.
+    pub(crate) fn delete_orphans(&mut self) -> Result<IndexOrphans> {
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let delete = |from_where: &str| -> Result<u64> {
+            Ok(txn.execute(&format!("DELETE FROM {from_where}"), [])
+                  .with_context(|| format!("in Index::delete_orphans() FROM {from_where}"))?
+               as u64)
+        };
+
+        // Pages first, then the rows that refer to them. Deleting a page deletes its full
+        // text search row with a trigger.
+        let pages_without_chunk = delete(&orphan_sql::pages_without_chunk())?;
+        let page_categories = delete(&orphan_sql::page_categories())?;
+        let page_props = delete(&orphan_sql::page_props())?;
+        let page_links = delete(&orphan_sql::page_links())?;
+        let page_templates = delete(&orphan_sql::page_templates())?;
+        let slug_disambiguations = delete(&orphan_sql::slug_disambiguations())?;
+        let categories = delete(&orphan_sql::categories())?;
+
+        // Full text search rows can't be deleted without their old values, so rebuild the
+        // table from `page` instead.
+        let page_fts: u64 = txn.query_row(
+            &format!("SELECT COUNT(*) FROM {}", orphan_sql::page_fts()), [], |row| row.get(0))?;
+        if page_fts > 0 {
+            txn.execute_batch(&rebuild_fts_sql(self.layout))
+                .with_context(|| "in Index::delete_orphans() while rebuilding page_fts")?;
+        }
+
+        txn.commit()?;
+
+        Ok(IndexOrphans {
+            pages_without_chunk,
+            page_categories,
+            page_props,
+            page_links,
+            page_templates,
+            slug_disambiguations,
+            page_fts,
+            categories,
+        })
+    }
+
+    /// Count the pages in chunks `chunk_ids`.
+    pub(crate) fn pages_len_in_chunks(&self, chunk_ids: &[ChunkId]) -> Result<u64> {
+        let query = Query::select()
+            .from(PageIden::Table)
+            .expr(Expr::col(PageIden::MediawikiId).count())
+            .and_where(Expr::col(PageIden::ChunkId).is_in(chunk_ids.iter().map(|id| id.0)))
+            .take();
+
+        let counts = self.query_rows("pages_len_in_chunks", &query, |row| row.get(0))?;
+        Ok(counts.first().copied().unwrap_or(0))
+    }
+
+    /// Get the pages in the chunks `chunk_ids`, in no particular order.
+    pub(crate) fn get_pages_in_chunks(&self, chunk_ids: &[ChunkId]) -> Result<Vec<Page>> {
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            .and_where(Expr::col(PageIden::ChunkId).is_in(chunk_ids.iter().map(|id| id.0)))
+            .take();
+        self.query_rows("get_pages_in_chunks", &query, Page::from_row)
+    }
+
+    pub(crate) fn put_import_file_progress(&self, progress: &ImportFileProgress) -> Result<()> {
+        let conn = self.conn()?;
+        Self::put_import_file_progress_with_conn(&conn, progress)
+    }
+
+    fn put_import_file_progress_with_conn(conn: &Connection, progress: &ImportFileProgress
+    ) -> Result<()>
+    {
+        let (sql, params) = Query::insert()
+            .into_table(ImportFileProgressIden::Table)
+            .columns([ImportFileProgressIden::Path,
+                      ImportFileProgressIden::Seek,
+                      ImportFileProgressIden::PagesCommitted,
+                      ImportFileProgressIden::SourceOffset,
+                      ImportFileProgressIden::Complete,
+                      ImportFileProgressIden::Updated])
+            .values([progress.path.as_str().into(),
+                     progress.seek.into(),
+                     progress.pages_committed.into(),
+                     progress.source_offset.into(),
+                     progress.complete.into(),
+                     progress.updated.map(|updated| updated.timestamp()).into()])?
+            .on_conflict(OnConflict::column(ImportFileProgressIden::Path)
+                             .update_columns([ImportFileProgressIden::Seek,
+                                              ImportFileProgressIden::PagesCommitted,
+                                              ImportFileProgressIden::SourceOffset,
+                                              ImportFileProgressIden::Complete,
+                                              ImportFileProgressIden::Updated])
+                             .to_owned())
+            .build_rusqlite(SqliteQueryBuilder);
+        let params2 = &*params.as_params();
+
+        let mut statement = conn.prepare_cached(&*sql)?;
+        statement.execute(params2)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn get_store_page_id_by_mediawiki_id(&self, id: u64) -> Result<Option<StorePageId>> {
+        let query = Query::select()
+            .from(PageIden::Table)
+            .column(PageIden::ChunkId)
+            .column(PageIden::PageChunkIndex)
+            .and_where(Expr::col(PageIden::MediawikiId).eq(id))
+            .take();
+        self.single_row_select_to_store_page_id(query)
+    }
+
+    /// Get the pages with MediaWiki IDs `mediawiki_ids` that are in the index, in no
+    /// particular order.
+    pub(crate) fn get_pages_by_mediawiki_ids(&self, mediawiki_ids: &[u64]) -> Result<Vec<Page>> {
+        if mediawiki_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            .and_where(Expr::col(PageIden::MediawikiId).is_in(mediawiki_ids.iter().copied()))
+            .take();
+        self.query_rows("get_pages_by_mediawiki_ids", &query, Page::from_row)
+    }
+
+    /// Get the pages in the namespace with key `ns_id`, ordered by slug.
+    ///
+    /// `slug_lower_bound` is the last slug of the previous call, to return the pages after
+    /// it in that order.
+    pub(crate) fn get_pages_by_namespace(
+        &self,
+        ns_id: i64,
+        slug_lower_bound: Option<&str>,
+        limit: QueryLimit,
+    ) -> Result<Vec<Page>>
+    {
+        self.query_rows("get_pages_by_namespace",
+                        &Self::get_pages_by_namespace_query(ns_id, slug_lower_bound, limit),
+                        Page::from_row)
+    }
+
+    fn get_pages_by_namespace_query(
+        ns_id: i64,
+        slug_lower_bound: Option<&str>,
+        limit: QueryLimit,
+    ) -> SelectStatement
+    {
+        // Ordered by the columns of index_page_by_ns_id, so a page of results only reads
+        // that many index entries.
+        Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            .and_where(Expr::col(PageIden::NsId).eq(ns_id))
+            .and_where_option(slug_lower_bound.map(
+                |slug| Expr::col(PageIden::Slug).gt(slug)))
+            .order_by(PageIden::Slug, Order::Asc)
+            .limit(limit.get())
+            .take()
+    }
+
+    /// Find up to `limit` articles related to the page with MediaWiki ID `mediawiki_id`, most
+    /// related first.
+    ///
+    /// Pages are related by the categories they share, except very large categories, and
+    /// by links between them in either direction. Only articles are returned, not
+    /// redirects or pages in other namespaces.
+    pub(crate) fn related_pages(&self, mediawiki_id: u64, limit: u64
+    ) -> Result<Vec<RelatedPage>> {
+        let Some(page) = self.get_pages_by_mediawiki_ids(&[mediawiki_id])?.pop() else {
+            return Ok(Vec::new());
+        };
+
+        // (shared_categories, links) by MediaWiki ID.
+        let mut scores = HashMap::<u64, (u64, u64)>::new();
+
+        let categories: Vec<String> = self.query_rows(
+            "related_pages categories",
+            &Query::select()
+                .from(PageCategoriesIden::Table)
+                .column(PageCategoriesIden::CategorySlug)
+                .and_where(Expr::col(PageCategoriesIden::MediawikiId).eq(mediawiki_id))
+                .take(),
+            |row| row.get(0))?;
+        for category_slug in categories {
+            let category_pages: Vec<u64> = self.query_rows(
+                "related_pages category_pages",
+                &Query::select()
+                    .from(PageCategoriesIden::Table)
+                    .column(PageCategoriesIden::MediawikiId)
+                    .and_where(Expr::col(PageCategoriesIden::CategorySlug).eq(category_slug))
+                    .limit(RELATED_CATEGORY_PAGES_MAX + 1)
+                    .take(),
+                |row| row.get(0))?;
+            if category_pages.len() as u64 > RELATED_CATEGORY_PAGES_MAX {
+                continue;
+            }
+            for id in category_pages {
+                scores.entry(id).or_default().0 += 1;
+            }
+        }
+
+        let links_from: Vec<u64> = self.query_rows(
+            "related_pages links_from",
+            &Query::select()
+                .from(PageLinksIden::Table)
+                .column((PageIden::Table, PageIden::MediawikiId))
+                // Compare with the collation of index_page_by_slug so it can be used, then
+                // exactly.
+                .inner_join(PageIden::Table, Expr::cust(&format!(
+                    "{page_table}.{slug} = {page_links_table}.{target_slug} COLLATE NOCASE \
+                     AND {page_table}.{slug} = {page_links_table}.{target_slug}",
+                    page_table = PageIden::Table.to_string(),
+                    slug = PageIden::Slug.to_string(),
+                    page_links_table = PageLinksIden::Table.to_string(),
+                    target_slug = PageLinksIden::TargetSlug.to_string())))
+                .and_where(Expr::col((PageLinksIden::Table, PageLinksIden::MediawikiId))
+                               .eq(mediawiki_id))
+                .take(),
+            |row| row.get(0))?;
+        let links_to: Vec<u64> = self.query_rows(
+            "related_pages links_to",
+            &Query::select()
+                .from(PageLinksIden::Table)
+                .column(PageLinksIden::MediawikiId)
+                .and_where(Expr::col(PageLinksIden::TargetSlug).eq(&*page.slug))
+                .limit(RELATED_LINKS_TO_MAX)
+                .take(),
+            |row| row.get(0))?;
+        for id in links_from.into_iter().chain(links_to) {
+            scores.entry(id).or_default().1 += 1;
+        }
+
+        scores.remove(&mediawiki_id);
+        let mut scores = scores.into_iter().collect::<Vec<(u64, (u64, u64))>>();
+        scores.sort_by_key(|(id, (shared_categories, links))|
+                           (std::cmp::Reverse(shared_categories + links), *id));
+
+        // Read more pages than needed in case some are skipped.
+        let candidates = &scores[..scores.len().min(usize::try_from(limit * 4)?)];
+        let mut pages = self.get_pages_by_mediawiki_ids(
+            &candidates.iter().map(|(id, _)| *id).collect::<Vec<u64>>())?
+            .into_iter()
+            .map(|page| (page.mediawiki_id, page))
+            .collect::<HashMap<u64, Page>>();
+
+        Ok(candidates.iter()
+                     .filter_map(|(id, (shared_categories, links))| {
+                         let page = pages.remove(id)
+                                         .filter(|page| page.ns_id == 0 && !page.is_redirect())?;
+                         Some(RelatedPage {
+                             page,
+                             shared_categories: *shared_categories,
+                             links: *links,
+                         })
+                     })
+                     .take(usize::try_from(limit)?)
+                     .collect())
+    }
+
+    /// Get the MediaWiki IDs and store IDs of pages in ascending MediaWiki ID order.
+    ///
+    /// To get the next pages, pass the last MediaWiki ID returned as `lower_bound`.
+    pub(crate) fn get_store_page_ids(&self, lower_bound: Option<u64>, limit: u64
+    ) -> Result<Vec<(u64, StorePageId)>>
+    {
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns([PageIden::MediawikiId, PageIden::ChunkId, PageIden::PageChunkIndex])
+            .and_where_option(lower_bound.map(|lower| Expr::col(PageIden::MediawikiId).gt(lower)))
+            .order_by(PageIden::MediawikiId, Order::Asc)
+            .limit(limit)
+            .take();
+
+        self.query_rows("get_store_page_ids", &query,
+                        |row| Ok((row.get(0)?, StorePageId {
+                            chunk_id: ChunkId(row.get(1)?),
+                            page_chunk_index: PageChunkIndex(row.get(2)?),
+                        })))
+    }
+
+    pub(crate) fn get_store_page_id_by_slug(&self, slug: &str) -> Result<Option<StorePageId>> {
+        Ok(self.get_page_by_slug(slug)?.map(|page| page.store_id()))
+    }
+
+    /// Get a page by slug, ignoring case if only one page matches.
+    ///
+    /// When more than one page matches ignoring case, only an exact match is returned.
+    /// When more than one page matches ignoring case, only an exact match is returned, or
+    /// the page chosen for the slug by `write_slug_disambiguations()` if there isn't exactly
+    /// one exact match.
+    pub(crate) fn get_page_by_slug(&self, slug: &str) -> Result<Option<Page>> {
+        let mut out = self.query_rows("get_page_by_slug", &Self::get_page_by_slug_query(slug),
+                                      Page::from_row)?;
+
+        let out_len = out.len();
+        if out_len <= 1 {
+            return Ok(out.pop());
+        }
+
+        let exact_pages_len = out.iter().filter(|p| p.slug == slug).count();
+        tracing::debug!(
+            out_len,
+            exact_pages_len,
+            %slug,
+            "get_page_by_slug: exact_pages filter");
+        if exact_pages_len == 1 {
+            return Ok(out.into_iter().find(|p| p.slug == slug));
+        }
+
+        if let Some(mediawiki_id) = self.get_slug_disambiguation(slug)? {
+            if let Some(idx) = out.iter().position(|p| p.mediawiki_id == mediawiki_id) {
+                return Ok(Some(out.swap_remove(idx)));
+            }
+        }
+
+        if exact_pages_len > 1 {
+            tracing::warn!(
+                out_len,
+                exact_pages_len,
+                %slug,
+                "get_page_by_slug: more than 1 exact match");
+        }
+        Ok(None)
+    }
+
+    /// The MediaWiki ID of the page chosen for lookups of `slug`, compared ignoring case.
+    fn get_slug_disambiguation(&self, slug: &str) -> Result<Option<u64>> {
+        let query = Query::select()
+            .from(SlugDisambiguationIden::Table)
+            .column(SlugDisambiguationIden::MediawikiId)
+            .and_where(Expr::col(SlugDisambiguationIden::Slug).eq(slug))
+            .take();
+        Ok(self.query_rows("get_slug_disambiguation", &query, |row| row.get(0))?.pop())
+    }
+
+    /// Find the groups of pages whose slugs are equal ignoring case, ordered by slug. See
+    /// [`SlugCollision`].
+    pub(crate) fn slug_collisions(&self) -> Result<Vec<SlugCollision>> {
+        let slug_nocase = format!("{slug} COLLATE NOCASE", slug = PageIden::Slug.to_string());
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            // Compare with the collation of index_page_by_slug so it can be used.
+            .and_where(Expr::cust(&format!(
+                "{slug_nocase} IN (SELECT {slug_nocase} FROM {page_table} \
+                                   GROUP BY {slug_nocase} HAVING COUNT(*) > 1)",
+                page_table = PageIden::Table.to_string())))
+            .order_by_expr(Expr::cust(&slug_nocase), Order::Asc)
+            .order_by(PageIden::Slug, Order::Asc)
+            .order_by(PageIden::MediawikiId, Order::Asc)
+            .take();
+        let pages = self.query_rows("slug_collisions", &query, Page::from_row)?;
+
+        // NOCASE only folds ASCII letters.
+        let disambiguations: HashMap<String, u64> = self.query_rows(
+            "slug_collisions disambiguations",
+            &Query::select()
+                .from(SlugDisambiguationIden::Table)
+                .columns([SlugDisambiguationIden::Slug, SlugDisambiguationIden::MediawikiId])
+                .take(),
+            |row| Ok((row.get::<_, String>(0)?.to_ascii_lowercase(), row.get(1)?)))?
+            .into_iter().collect();
+
+        let mut collisions = Vec::<SlugCollision>::new();
+        for page in pages {
+            match collisions.last_mut() {
+                Some(collision) if collision.pages[0].slug.eq_ignore_ascii_case(&page.slug) =>
+                    collision.pages.push(page),
+                _ => collisions.push(SlugCollision {
+                    disambiguation: disambiguations.get(&page.slug.to_ascii_lowercase())
+                                                   .copied(),
+                    pages: vec![page],
+                }),
+            }
+        }
+        Ok(collisions)
+    }
+
+    /// Replace all slug disambiguations with `disambiguations`, pairs of a slug and the
+    /// MediaWiki ID of the page to choose for lookups of it.
+    pub(crate) fn write_slug_disambiguations(&mut self, disambiguations: &[(String, u64)]
+    ) -> Result<()> {
+        let mut conn = self.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        txn.execute(&Query::delete()
+                        .from_table(SlugDisambiguationIden::Table)
+                        .to_string(SqliteQueryBuilder), [])?;
+        for (slug, mediawiki_id) in disambiguations {
+            let (sql, values) = Query::insert()
+                .into_table(SlugDisambiguationIden::Table)
+                .columns([SlugDisambiguationIden::Slug, SlugDisambiguationIden::MediawikiId])
+                .values_panic([slug.as_str().into(), (*mediawiki_id).into()])
+                .build_rusqlite(SqliteQueryBuilder);
+            txn.execute(&sql, &*values.as_params())?;
+        }
+
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Get up to `limit` link target slugs that no page in the index has, ignoring case, with
+    /// the most linked first.
+    pub(crate) fn red_links(&self, limit: u64) -> Result<Vec<RedLink>> {
+        let query = Query::select()
+            .from(PageLinksIden::Table)
+            .column(PageLinksIden::TargetSlug)
+            .expr_as(Expr::col(PageLinksIden::MediawikiId).count(), Alias::new("links_len"))
+            .group_by_col(PageLinksIden::TargetSlug)
+            // Compare with the collation of index_page_by_slug so it can be used.
+            .and_having(Expr::cust(&format!(
+                "NOT EXISTS (SELECT 1 FROM {page_table} \
+                             WHERE {page_table}.{slug} = {page_links_table}.{target_slug} \
+                                   COLLATE NOCASE)",
+                page_table = PageIden::Table.to_string(),
+                slug = PageIden::Slug.to_string(),
+                page_links_table = PageLinksIden::Table.to_string(),
+                target_slug = PageLinksIden::TargetSlug.to_string())))
+            .order_by(Alias::new("links_len"), Order::Desc)
+            .order_by(PageLinksIden::TargetSlug, Order::Asc)
+            .limit(limit)
+            .take();
+
+        self.query_rows("red_links", &query, |row| Ok(RedLink {
+            slug: row.get(0)?,
+            links_len: row.get(1)?,
+        }))
+    }
+
+    /// Get the slugs of the templates and modules the page with MediaWiki ID `mediawiki_id`
+    /// transcludes, sorted.
+    pub(crate) fn get_page_templates(&self, mediawiki_id: u64) -> Result<Vec<String>> {
+        self.query_rows(
+            "get_page_templates",
+            &Query::select()
+                .from(PageTemplatesIden::Table)
+                .column(PageTemplatesIden::TemplateSlug)
+                .and_where(Expr::col(PageTemplatesIden::MediawikiId).eq(mediawiki_id))
+                .order_by(PageTemplatesIden::TemplateSlug, Order::Asc)
+                .take(),
+            |row| row.get(0))
+    }
+
+    /// Get the pages that transclude the template or module with slug `template_slug`,
+    /// ordered by MediaWiki ID.
+    ///
+    /// If `indirect` is true, pages that transclude it through other templates in the index
+    /// are returned too. `page_mediawiki_id_lower_bound` is the last page of the previous
+    /// call, to return the pages after it.
+    pub(crate) fn get_template_pages(
+        &self,
+        template_slug: &str,
+        indirect: bool,
+        page_mediawiki_id_lower_bound: Option<u64>,
+        limit: QueryLimit,
+    ) -> Result<Vec<Page>>
+    {
+        let limit = limit.get();
+        let page_table = PageIden::Table.to_string();
+        let page_templates_table = PageTemplatesIden::Table.to_string();
+        let mediawiki_id = PageIden::MediawikiId.to_string();
+        let template_slug_col = PageTemplatesIden::TemplateSlug.to_string();
+
+        // The templates that transclude `template_slug`, and the templates that transclude
+        // those, and so on. UNION skips slugs already found, so cycles end.
+        let templates = if indirect {
+            format!(
+                "WITH RECURSIVE templates(slug) AS ( \
+                     SELECT ? \
+                     UNION \
+                     SELECT {page_table}.{slug} FROM {page_templates_table} \
+                     INNER JOIN templates \
+                         ON {page_templates_table}.{template_slug_col} = templates.slug \
+                     INNER JOIN {page_table} \
+                         ON {page_table}.{mediawiki_id} = \
+                            {page_templates_table}.{mediawiki_id}) \
+                 SELECT slug FROM templates",
+                slug = PageIden::Slug.to_string())
+        } else {
+            "SELECT ?".to_string()
+        };
+
+        let query = Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            .and_where(Expr::cust_with_values(
+                &format!("{mediawiki_id} IN (SELECT {mediawiki_id} FROM {page_templates_table} \
+                                            WHERE {template_slug_col} IN ({templates}))"),
+                [Value::from(template_slug)]))
+            .and_where_option(page_mediawiki_id_lower_bound.map(
+                |id| Expr::col(PageIden::MediawikiId).gt(id)))
+            .order_by(PageIden::MediawikiId, Order::Asc)
+            .limit(limit)
+            .take();
+        self.query_rows("get_template_pages", &query, Page::from_row)
+    }
+
+    fn get_page_by_slug_query(slug: &str) -> SelectStatement {
+        Query::select()
+            .from(PageIden::Table)
+            .columns(PAGE_COLUMNS)
+            // Compare with the collation of index_page_by_slug so it can be used. LIKE would
+            // treat `_` in slugs as a wildcard.
+            .and_where(Expr::cust_with_values(
+                &format!("{slug} = ? COLLATE NOCASE", slug = PageIden::Slug.to_string()),
+                [slug]))
+            .limit(100)
+            .take()
+    }
+
+    pub(crate) fn page_search(&self, query: &str, options: &PageSearchOptions
+    ) -> Result<Vec<Page>> {
+        self.query_rows("page_search", &Self::page_search_query(query, options)?, Page::from_row)
+    }
+
+    /// Count the pages matching a page search by namespace and by category, returning up to
+    /// `categories_limit` categories. See [`PageSearchFacets`].
+    pub(crate) fn page_search_facets(
+        &self,
+        query: &str,
+        options: &PageSearchOptions,
+        categories_limit: u64,
+    ) -> Result<PageSearchFacets>
+    {
+        let matches = Self::page_search_matches_query(query, options)?
+                          .limit(PAGE_SEARCH_FACET_PAGES_MAX)
+                          .take();
+        let results = Alias::new("results");
+        let pages_len = Alias::new("pages_len");
+
+        let namespaces = self.query_rows(
+            "page_search_facets namespaces",
+            &Query::select()
+                .from_subquery(matches.clone(), results.clone())
+                .column((results.clone(), PageIden::NsId))
+                .expr_as(Expr::col((results.clone(), PageIden::MediawikiId)).count(),
+                         pages_len.clone())
+                .group_by_col((results.clone(), PageIden::NsId))
+                .order_by(pages_len.clone(), Order::Desc)
+                .order_by((results.clone(), PageIden::NsId), Order::Asc)
+                .take(),
+            |row| Ok(NamespaceFacet {
+                ns_id: row.get(0)?,
+                pages_len: row.get(1)?,
+            }))?;
+
+        let categories = self.query_rows(
+            "page_search_facets categories",
+            &Query::select()
+                .from_subquery(matches, results.clone())
+                .inner_join(PageCategoriesIden::Table,
+                            Expr::col((PageCategoriesIden::Table,
+                                       PageCategoriesIden::MediawikiId))
+                                .equals((results.clone(), PageIden::MediawikiId)))
+                .inner_join(CategoryIden::Table,
+                            Expr::col((CategoryIden::Table, CategoryIden::Slug))
+                                .equals((PageCategoriesIden::Table,
+                                         PageCategoriesIden::CategorySlug)))
+                .column((CategoryIden::Table, CategoryIden::Slug))
+                .column((CategoryIden::Table, CategoryIden::Name))
+                .expr_as(Expr::col((results, PageIden::MediawikiId)).count(),
+                         pages_len.clone())
+                .group_by_columns([(CategoryIden::Table, CategoryIden::Slug),
+                                   (CategoryIden::Table, CategoryIden::Name)])
+                .order_by(pages_len, Order::Desc)
+                .order_by((CategoryIden::Table, CategoryIden::Slug), Order::Asc)
+                .limit(QueryLimit::new(categories_limit).get())
+                .take(),
+            |row| Ok(CategoryFacet {
+                category: Category {
+                    slug: CategorySlug(row.get(0)?),
+                    name: CategoryName(row.get(1)?),
+                },
+                pages_len: row.get(2)?,
+            }))?;
+
+        let truncated = namespaces.iter().map(|facet| facet.pages_len).sum::<u64>()
+                            >= PAGE_SEARCH_FACET_PAGES_MAX;
+        Ok(PageSearchFacets {
+            namespaces,
+            categories,
+            truncated,
+        })
+    }
+
+    fn page_search_query(query: &str, options: &PageSearchOptions) -> Result<SelectStatement> {
+        let limit = options.limit.get();
+        let word_count = (PageIden::Table, PageIden::WordCount);
+
+        let mut select = Self::page_search_matches_query(query, options)?;
+        select.limit(limit);
+        match options.order {
+            PageSearchOrder::Relevance => (),
+            PageSearchOrder::Longest => {
+                select.order_by(word_count, Order::Desc);
+            },
+            PageSearchOrder::Shortest => {
+                select.order_by(word_count, Order::Asc);
+            },
+        }
+        // Break ties by relevance.
+        let (terms, _required_flags, _excluded_flags) = parse_flag_filters(query)?;
+        if let Some(title_rank) = Self::page_search_title_rank(&terms) {
+            select.order_by_expr(title_rank, Order::Asc);
+        }
+        select.order_by((PageFtsIden::Table, PageFtsIden::Rank), Order::Asc);
+
+        Ok(select)
+    }
+
+    /// An expression ranking a page search result by how its title matches the search
+    /// terms `terms`, ignoring case: 0 for the same title, 1 for a title starting with the
+    /// terms, and 2 otherwise. Each alternative of an `OR` query is compared separately.
+    /// Returns `None` if the terms have no words to compare, e.g. only operators.
+    ///
+    /// Without this, a search for `Paris` can rank the article below dozens of pages that
+    /// mention Paris more often in fewer words.
+    fn page_search_title_rank(terms: &str) -> Option<SimpleExpr> {
+        let slugs = fts_query_title_slugs(terms);
+        if slugs.is_empty() {
+            return None;
+        }
+
+        let slug_col = format!("{LOWER_UNICODE_FUNCTION}({page_table}.{slug})",
+                               page_table = PageIden::Table.to_string(),
+                               slug = PageIden::Slug.to_string());
+        let same = vec!["?"; slugs.len()].join(", ");
+        let starts_with = vec![format!("substr({slug_col}, 1, ?) = ?"); slugs.len()]
+                              .join(" OR ");
+        let values = slugs.iter().map(|slug| Value::from(slug.clone()))
+                          .chain(slugs.iter().flat_map(|slug| [
+                              Value::from(slug.chars().count() as u64),
+                              Value::from(slug.clone())]))
+                          .collect::<Vec<Value>>();
+        Some(Expr::cust_with_values(
+            &format!("CASE WHEN {slug_col} IN ({same}) THEN 0 \
+                           WHEN {starts_with} THEN 1 \
+                           ELSE 2 END"),
+            values))
+    }
+
+    /// The pages matching a page search, without a limit or order.
+    fn page_search_matches_query(query: &str, options: &PageSearchOptions
+    ) -> Result<SelectStatement> {
+        let word_count = (PageIden::Table, PageIden::WordCount);
+
+        let (query, required_flags, excluded_flags) = parse_flag_filters(query)?;
+        let required_flags = required_flags | options.required_flags;
+        let excluded_flags = excluded_flags | options.excluded_flags;
+        if query.trim().is_empty() {
+            bail!("Page search query must have search terms as well as filters");
+        }
+        let page_flags_sql = format!("coalesce({page_table}.{page_flags}, 0)",
+                                     page_table = PageIden::Table.to_string(),
+                                     page_flags = PageIden::PageFlags.to_string());
+
+        let mut select = Query::select();
+        select
+            .columns(PAGE_COLUMNS.map(|col| (PageIden::Table, col)))
+            .from(PageFtsIden::Table)
+            .inner_join(PageIden::Table,
+                        Expr::col((PageFtsIden::Table, PageFtsIden::Rowid))
+                            .equals((PageIden::Table, PageIden::MediawikiId)))
+            .and_where(Expr::col(PageFtsIden::Table).matches(Expr::value(query)))
+            .and_where_option((!required_flags.is_empty()).then(|| Expr::cust_with_values(
+                &format!("({page_flags_sql} & ?) = ?"),
+                [required_flags.0, required_flags.0])))
+            .and_where_option((!excluded_flags.is_empty()).then(|| Expr::cust_with_values(
+                &format!("({page_flags_sql} & ?) = 0"),
+                [excluded_flags.0])))
+            .and_where_option(options.min_words.map(
+                |min| Expr::col(word_count).gte(min)))
+            .and_where_option(options.max_words.map(
+                |max| Expr::col(word_count).lte(max)))
+            .and_where_option(options.namespace.map(
+                |ns_id| Expr::col((PageIden::Table, PageIden::NsId)).eq(ns_id)));
+        if let Some(category) = options.category.as_ref() {
+            // Each full text search match is looked up in the covering index
+            // index_page_categories_by_category_slug on (category_slug, mediawiki_id), so
+            // this doesn't need its own index.
+            select.inner_join(
+                PageCategoriesIden::Table,
+                Condition::all()
+                    .add(Expr::col((PageCategoriesIden::Table, PageCategoriesIden::MediawikiId))
+                             .equals((PageIden::Table, PageIden::MediawikiId)))
+                    .add(Expr::col((PageCategoriesIden::Table, PageCategoriesIden::CategorySlug))
+                             .eq(&*category.0)));
+        }
+
+        Ok(select)
+    }
+
+    fn single_row_select_to_store_page_id(&self, select: SelectStatement
+    ) -> Result<Option<StorePageId>>
+    {
+        let mut ids = self.query_rows(
+            "store_page_id", &select,
+            |row| -> rusqlite::Result<StorePageId> {
+                Ok(StorePageId {
+                    chunk_id: ChunkId(row.get(0)?),
+                    page_chunk_index: PageChunkIndex(row.get(1)?),
+                })
+            })?;
+        Ok(ids.pop())
+    }
+
+    /// Run a read query and return its rows.
+    ///
+    /// When `opts.slow_query_threshold` is set and the query takes at least that long, it is
+    /// logged at warn level with its SQL, redacted parameters, and query plan.
+    fn query_rows<T>(&self, name: &'static str, select: &SelectStatement,
+                     mut from_row: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>
+    ) -> Result<Vec<T>>
+    {
+        let (sql, params) = select.build_rusqlite(SqliteQueryBuilder);
+        let params2 = &*params.as_params();
+
+        let conn = self.conn()?;
+        let start = Instant::now();
+
+        let mut statement = conn.prepare_cached(&*sql)?;
+        let mut rows = statement.query(params2)?;
+        let mut out = Vec::<T>::new();
+        while let Some(row) = rows.next()? {
+            out.push(from_row(row)?);
+        }
+
+        let duration = start.elapsed();
+        if self.opts.slow_query_threshold.is_some_and(|threshold| duration >= threshold) {
+            let plan = match query_plan(&conn, &sql, &params) {
+                Ok(plan) => plan.join("\n"),
+                Err(e) => format!("Error getting query plan: {e:#}"),
+            };
+            tracing::warn!(query = name,
+                           sql,
+                           params = ?redact_params(&params),
+                           duration = Duration(duration).as_value(),
+                           rows_len = out.len(),
+                           plan,
+                           "Slow index query");
+        }
+
+        Ok(out)
+    }
+
+    /// Run a read query, returning its query plan and how long it took.
+    pub(crate) fn explain(&self, query: &IndexQuery) -> Result<QueryExplanation> {
+        let (name, select) = match query {
+            IndexQuery::Categories { slug_lower_bound, limit } =>
+                ("get_category", Self::get_category_query(slug_lower_bound.as_ref(), *limit)),
+            IndexQuery::CategoryPages { slug, page_mediawiki_id_lower_bound, namespace,
+                                        limit } =>
+                ("get_category_pages",
+                 Self::get_category_pages_query(slug, *page_mediawiki_id_lower_bound,
+                                                *namespace, *limit)),
+            IndexQuery::NamespacePages { ns_id, slug_lower_bound, limit } =>
+                ("get_pages_by_namespace",
+                 Self::get_pages_by_namespace_query(*ns_id, slug_lower_bound.as_deref(),
+                                                    *limit)),
+            IndexQuery::PageBySlug { slug } =>
+                ("get_page_by_slug", Self::get_page_by_slug_query(slug)),
+            IndexQuery::PageSearch { query, options } =>
+                ("page_search", Self::page_search_query(query, options)?),
+        };
+        let (sql, params) = select.build_rusqlite(SqliteQueryBuilder);
+
+        let conn = self.conn()?;
+        let plan = query_plan(&conn, &sql, &params)?;
+
+        let start = Instant::now();
+        let mut statement = conn.prepare(&*sql)?;
+        let mut rows = statement.query(&*params.as_params())?;
+        let mut rows_len = 0_u64;
+        while rows.next()?.is_some() {
+            rows_len += 1;
+        }
+        let duration = start.elapsed();
+
+        Ok(QueryExplanation {
+            name,
+            sql,
+            params: params.0.iter().map(|RusqliteValue(value)| format!("{value:?}")).collect(),
+            plan,
+            rows_len,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        })
+    }
+}
+
+impl BatchInsert {
+    fn new(init_fn: impl Fn() -> InsertStatement + 'static, max_batch_len: usize) -> BatchInsert {
+        BatchInsert {
+            built: Vec::new(),
+            curr_statement_values_len: 0,
+            max_batch_len,
+            statement: init_fn(),
+            values_len: 0,
+
+            // This consumes `init_fn`, so initialise it last.
+            init_fn: Box::new(init_fn),
+        }
+    }
+
+    fn push_values<I>(&mut self, values: I) -> Result<()>
+        where I: IntoIterator<Item = SimpleExpr>
+    {
+        self.statement.values(values)?;
+
+        self.curr_statement_values_len += 1;
+        self.values_len += 1;
+
+        if self.curr_statement_values_len >= self.max_batch_len {
+            let built_query = self.statement.build_rusqlite(SqliteQueryBuilder);
+            self.built.push(built_query);
+            self.curr_statement_values_len = 0;
+            let _old = std::mem::replace(&mut self.statement, (self.init_fn)());
+        }
+
         Ok(())
     }
 
-    #[tracing::instrument(level = "debug", target = "wikimedia_store::index::vacuum",
-                          skip(self))]
-    fn vacuum(&mut self) -> Result<()> {
-        self.conn()?.execute("VACUUM;", [])
-            .with_context(
-                || "in Index::vacuum()")?;
+    /// Build the last partial statement, after which no more values can be pushed.
+    fn finish(&mut self) {
+        if self.curr_statement_values_len > 0 {
+            let built_final = self.statement.build_rusqlite(SqliteQueryBuilder);
+            self.built.push(built_final);
+            self.curr_statement_values_len = 0;
+        }
+    }
+
+    fn execute_all(&self, txn: &Transaction) -> Result<()> {
+        for (sql, params) in self.built.iter() {
+            let params2 = params.as_params();
+            txn.execute(sql, &*params2)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'index> ImportBatchBuilder<'index> {
+    fn new(index: &'index Index) -> ImportBatchBuilder<'index> {
+        let values_per_batch = index.values_per_batch.load(Ordering::SeqCst);
+        ImportBatchBuilder {
+            index,
+            category_batch: BatchInsert::new(
+                || Query::insert()
+                       .into_table(CategoryIden::Table)
+                       .columns([CategoryIden::Slug,
+                                 CategoryIden::Name])
+                       .on_conflict(OnConflict::new().do_nothing().to_owned())
+                       .to_owned(),
+                values_per_batch),
+            page_batch: BatchInsert::new(
+                || Query::insert()
+                       .into_table(PageIden::Table)
+                       .columns(PAGE_COLUMNS)
+                       .on_conflict(OnConflict::new().do_nothing().to_owned())
+                       .to_owned(),
+                values_per_batch),
+            page_categories_batch: BatchInsert::new(
+                || Query::insert()
+                       .into_table(PageCategoriesIden::Table)
+                       .columns([PageCategoriesIden::MediawikiId,
+                                 PageCategoriesIden::CategorySlug,
+                                 PageCategoriesIden::SortKey])
+                       .on_conflict(OnConflict::new().do_nothing().to_owned())
+                       .to_owned(),
+                values_per_batch),
+            page_props_batch: BatchInsert::new(
+                || Query::insert()
+                       .into_table(PagePropsIden::Table)
+                       .columns([PagePropsIden::MediawikiId,
+                                 PagePropsIden::Name,
+                                 PagePropsIden::Value])
+                       .on_conflict(OnConflict::new().do_nothing().to_owned())
+                       .to_owned(),
+                values_per_batch),
+            page_links_batch: BatchInsert::new(
+                || Query::insert()
+                       .into_table(PageLinksIden::Table)
+                       .columns([PageLinksIden::MediawikiId,
+                                 PageLinksIden::TargetSlug])
+                       .on_conflict(OnConflict::new().do_nothing().to_owned())
+                       .to_owned(),
+                values_per_batch),
+            page_templates_batch: BatchInsert::new(
+                || Query::insert()
+                       .into_table(PageTemplatesIden::Table)
+                       .columns([PageTemplatesIden::MediawikiId,
+                                 PageTemplatesIden::TemplateSlug])
+                       .on_conflict(OnConflict::new().do_nothing().to_owned())
+                       .to_owned(),
+                values_per_batch),
+            import_file_progress: None,
+            chunk: None,
+            chunk_provenance: None,
+            replaced_pages: Vec::new(),
+            moved_pages: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, page: &dump::Page, store_page_id: StorePageId) -> Result<()> {
+        let page_slug = slug::title_to_slug(&*page.title);
+        let redirect = page.revision_text().and_then(wikitext::parse_redirect_target);
+        let redirect_slug = redirect.as_ref().map(|target| slug::title_to_slug(&target.title));
+        let text_stats = page.revision_text().map(TextStats::from_wikitext);
+        // Flags only make sense for articles.
+        let page_flags = match (page.revision_text(), text_stats) {
+            (Some(text), Some(stats)) if page.ns_id == 0 =>
+                Some(wikitext::classify_page(&page.title, text, &stats)),
+            (Some(_), Some(_)) => Some(PageFlags::default()),
+            _ => None,
+        };
+
+        self.page_batch.push_values([
+            page.id.into(),
+            store_page_id.chunk_id.0.into(),
+            store_page_id.page_chunk_index.0.into(),
+            page_slug.clone().into(),
+            page.ns_id.into(),
+            text_stats.map(|stats| stats.bytes).into(),
+            text_stats.map(|stats| stats.words).into(),
+            page_flags.map(|flags| flags.0).into(),
+            redirect_slug.clone().into(),
+            redirect.and_then(|target| target.fragment).into(),
+            page.revision.as_ref()
+                .and_then(|rev| rev.timestamp)
+                .map(|timestamp| timestamp.timestamp())
+                .into(),
+        ])?;
+
+        if let Some(ref rev) = page.revision {
+            for category in rev.categories.iter() {
+                let category_slug = category.name.to_slug();
+                self.category_batch.push_values([
+                    category_slug.0.clone().into(),
+                    category.name.0.clone().into(),
+                ])?;
+                self.page_categories_batch.push_values([
+                    page.id.into(),
+                    category_slug.0.into(),
+                    category.sort_key.as_ref().unwrap_or(&page.title).clone().into(),
+                ])?;
+            }
+        }
+
+        let props = page.revision_text()
+                        .map(|text| wikitext::parse_page_props(&page.title, text))
+                        .unwrap_or_default();
+        let props = [
+            (PAGE_PROP_DISPLAY_TITLE, props.display_title),
+            (PAGE_PROP_DEFAULT_SORT, props.default_sort),
+            (PAGE_PROP_RESTRICTIONS, page.restrictions.clone()),
+        ];
+        for (name, value) in props {
+            if let Some(value) = value {
+                self.page_props_batch.push_values([page.id.into(), name.into(), value.into()])?;
+            }
+        }
+
+        // Only articles' links are indexed. A redirect's link is its target, which is in
+        // `page.redirect_slug`.
+        if let Some(text) = page.revision_text()
+                                .filter(|_| page.ns_id == 0 && redirect_slug.is_none()) {
+            for title in wikitext::parse_links(text) {
+                let target_slug = slug::title_to_slug(&title);
+                if target_slug != page_slug {
+                    self.page_links_batch.push_values([page.id.into(), target_slug.into()])?;
+                }
+            }
+        }
+
+        // Templates are indexed in every namespace, so templates that transclude other
+        // templates can be followed by `Index::get_template_pages()`.
+        if let Some(text) = page.revision_text().filter(|_| redirect_slug.is_none()) {
+            for title in wikitext::parse_templates(text) {
+                let template_slug = slug::title_to_slug(&title);
+                if template_slug != page_slug {
+                    self.page_templates_batch.push_values([page.id.into(),
+                                                           template_slug.into()])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the progress through the current dump file to record when this batch commits.
+    pub(crate) fn set_import_file_progress(&mut self, progress: ImportFileProgress) {
+        self.import_file_progress = Some(progress);
+    }
+
+    /// Set the chunk this batch's pages were written to, to record as committed when this
+    /// batch commits. The chunk's file must already be at its chunk path.
+    pub(crate) fn set_chunk(&mut self, chunk_meta: &ChunkMeta) {
+        self.chunk = Some((chunk_meta.id, chunk_meta.pages_len, chunk_meta.bytes_len.0));
+    }
+
+    /// Set where the chunk's pages came from, to record when this batch commits.
+    pub(crate) fn set_chunk_provenance(&mut self, provenance: ChunkProvenance) {
+        self.chunk_provenance = Some(provenance);
+    }
+
+    /// Delete the page with MediaWiki ID `mediawiki_id` and its rows when this batch commits,
+    /// before inserting the batch's pages.
+    ///
+    /// Pages already in the index are otherwise kept, with their categories, links, and
+    /// properties, when the batch inserts the same MediaWiki ID.
+    pub(crate) fn replace_page(&mut self, mediawiki_id: u64) {
+        self.replaced_pages.push(mediawiki_id);
+    }
+
+    /// Point the page with MediaWiki ID `mediawiki_id` at its copy at `store_id` when this
+    /// batch commits, keeping its categories, links, and properties. The copy must have the
+    /// same revision, e.g. one with its HTML pre-rendered.
+    pub(crate) fn move_page(&mut self, mediawiki_id: u64, store_id: StorePageId) {
+        self.moved_pages.push((mediawiki_id, store_id));
+    }
+
+    #[tracing::instrument(level = "trace", skip(self),
+                          fields(category_batch.len = self.category_batch.values_len,
+                                 page_batch.len = self.page_batch.values_len,
+                                 page_categories_batch.len =
+                                     self.page_categories_batch.values_len,
+                                 page_props_batch.len = self.page_props_batch.values_len,
+                                 page_links_batch.len = self.page_links_batch.values_len,
+                                 page_templates_batch.len =
+                                     self.page_templates_batch.values_len))]
+    pub(crate) fn commit(mut self) -> Result<BatchStats> {
+        let batches = [&mut self.category_batch, &mut self.page_batch,
+                       &mut self.page_categories_batch, &mut self.page_props_batch,
+                       &mut self.page_links_batch, &mut self.page_templates_batch];
+        for batch in batches {
+            batch.finish();
+        }
+
+        let opts = &self.index.opts.batch;
+        let values_per_batch = self.page_batch.max_batch_len;
+        let start = Instant::now();
+        let mut busy_retries = 0;
+
+        loop {
+            match self.try_commit() {
+                Err(e) if is_busy(&e) && busy_retries < opts.busy_retries => {
+                    busy_retries += 1;
+                    tracing::warn!(busy_retries, "Index database busy, retrying commit");
+                    std::thread::sleep(BUSY_RETRY_DELAY * 2_u32.pow(busy_retries - 1));
+                },
+                res => break res?,
+            }
+        }
+
+        let commit_duration = start.elapsed();
+        let next_values_per_batch =
+            opts.next_values_per_batch(values_per_batch, commit_duration, busy_retries > 0);
+        self.index.values_per_batch.store(next_values_per_batch, Ordering::SeqCst);
+
+        let batches = [&self.category_batch, &self.page_batch,
+                       &self.page_categories_batch, &self.page_props_batch,
+                       &self.page_links_batch, &self.page_templates_batch];
+        Ok(BatchStats {
+            values_per_batch,
+            values_len: batches.iter().map(|batch| batch.values_len).sum(),
+            statements_len: batches.iter().map(|batch| batch.built.len()).sum(),
+            commit_duration: Duration(commit_duration),
+            busy_retries,
+            next_values_per_batch,
+        })
+    }
+
+    fn try_commit(&self) -> Result<()> {
+        let mut conn = self.index.conn()?;
+        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        for mediawiki_id in self.replaced_pages.iter() {
+            delete_page_with_txn(&txn, *mediawiki_id)?;
+        }
+
+        self.category_batch.execute_all(&txn)?;
+        self.page_batch.execute_all(&txn)?;
+        self.page_categories_batch.execute_all(&txn)?;
+        self.page_props_batch.execute_all(&txn)?;
+        self.page_links_batch.execute_all(&txn)?;
+        self.page_templates_batch.execute_all(&txn)?;
+
+        for (mediawiki_id, store_id) in self.moved_pages.iter() {
+            let (sql, params) = Query::update()
+                .table(PageIden::Table)
+                .values([(PageIden::ChunkId, store_id.chunk_id.0.into()),
+                         (PageIden::PageChunkIndex, store_id.page_chunk_index.0.into())])
+                .and_where(Expr::col(PageIden::MediawikiId).eq(*mediawiki_id))
+                .build_rusqlite(SqliteQueryBuilder);
+            txn.execute(&sql, &*params.as_params())?;
+        }
+
+        if let Some((chunk_id, pages_len, bytes_len)) = self.chunk {
+            // The chunk file is already at its chunk path, see `chunk::PendingChunk`.
+            let (sql, params) = Query::insert()
+                .into_table(ChunkIden::Table)
+                .columns([ChunkIden::Id, ChunkIden::PagesLen, ChunkIden::BytesLen,
+                          ChunkIden::Committed])
+                .values([chunk_id.0.into(), pages_len.into(), bytes_len.into(), true.into()])?
+                .on_conflict(OnConflict::column(ChunkIden::Id)
+                                 .update_columns([ChunkIden::PagesLen, ChunkIden::BytesLen,
+                                                  ChunkIden::Committed])
+                                 .to_owned())
+                .build_rusqlite(SqliteQueryBuilder);
+            txn.execute(&sql, &*params.as_params())?;
+        }
+
+        if let Some(provenance) = self.chunk_provenance.as_ref() {
+            let (sql, params) = Query::insert()
+                .into_table(ChunkProvenanceIden::Table)
+                .columns([ChunkProvenanceIden::ChunkId,
+                          ChunkProvenanceIden::ImportRunId,
+                          ChunkProvenanceIden::SourcePath,
+                          ChunkProvenanceIden::SourceSeek,
+                          ChunkProvenanceIden::FilePagesStart,
+                          ChunkProvenanceIden::FilePagesEnd,
+                          ChunkProvenanceIden::XmlOffsetStart,
+                          ChunkProvenanceIden::XmlOffsetEnd])
+                .values([provenance.chunk_id.0.into(),
+                         provenance.import_run_id.into(),
+                         provenance.source_path.as_str().into(),
+                         provenance.source_seek.into(),
+                         provenance.file_pages_start.into(),
+                         provenance.file_pages_end.into(),
+                         provenance.xml_offset_start.into(),
+                         provenance.xml_offset_end.into()])?
+                .on_conflict(OnConflict::column(ChunkProvenanceIden::ChunkId)
+                                 .update_columns([ChunkProvenanceIden::ImportRunId,
+                                                  ChunkProvenanceIden::SourcePath,
+                                                  ChunkProvenanceIden::SourceSeek,
+                                                  ChunkProvenanceIden::FilePagesStart,
+                                                  ChunkProvenanceIden::FilePagesEnd,
+                                                  ChunkProvenanceIden::XmlOffsetStart,
+                                                  ChunkProvenanceIden::XmlOffsetEnd])
+                                 .to_owned())
+                .build_rusqlite(SqliteQueryBuilder);
+            txn.execute(&sql, &*params.as_params())?;
+        }
+
+        if let Some(progress) = self.import_file_progress.as_ref() {
+            Index::put_import_file_progress_with_conn(&txn, progress)?;
+        }
+
+        txn.commit()?;
+
         Ok(())
     }
+}
+
+/// Delete the page with MediaWiki ID `mediawiki_id` and the rows that refer to it by
+/// MediaWiki ID in `txn`. Returns the count of pages deleted, 0 or 1.
+///
+/// Its full text search row is deleted by a trigger, and its categories that no other page
+/// is in are deleted too. Slug disambiguations left without pages are orphans, see
+/// [`Index::delete_orphans`].
+fn delete_page_with_txn(txn: &Transaction, mediawiki_id: u64) -> Result<u64> {
+    let delete = |table: &dyn Iden| -> Result<u64> {
+        Ok(txn.execute(&format!("DELETE FROM {table} WHERE {mediawiki_id_col} = ?1",
+                                table = table.to_string(),
+                                mediawiki_id_col = PageIden::MediawikiId.to_string()),
+                       [mediawiki_id])? as u64)
+    };
+
+    txn.execute(
+        &format!("DELETE FROM {category_table} WHERE {slug} IN \
+                      (SELECT {category_slug} FROM {page_categories_table} \
+                       WHERE {mediawiki_id_col} = ?1) \
+                  AND NOT EXISTS \
+                      (SELECT 1 FROM {page_categories_table} \
+                       WHERE {category_slug} = {category_table}.{slug} \
+                       AND {mediawiki_id_col} != ?1)",
+                 category_table = CategoryIden::Table.to_string(),
+                 slug = CategoryIden::Slug.to_string(),
+                 category_slug = PageCategoriesIden::CategorySlug.to_string(),
+                 page_categories_table = PageCategoriesIden::Table.to_string(),
+                 mediawiki_id_col = PageCategoriesIden::MediawikiId.to_string()),
+        [mediawiki_id])?;
+    delete(&PageCategoriesIden::Table)?;
+    delete(&PagePropsIden::Table)?;
+    delete(&PageLinksIden::Table)?;
+    delete(&PageTemplatesIden::Table)?;
+    delete(&PageIden::Table)
+}
+
+/// The schema version of the index in directory `path`, read without opening it for writing
+/// or migrating it, or `None` if there is no index there.
+pub fn schema_version_at(path: &Path) -> Result<Option<i64>> {
+    let db_path = path.join("index.db");
+    if !db_path.try_exists()? {
+        return Ok(None);
+    }
+
+    let conn = Connection::open_with_flags(
+        &db_path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .with_context(|| format!("opening store index path='{}'", db_path.display()))?;
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(Some(version))
+}
+
+/// The migration status of the index in directory `path`, read without opening it for
+/// writing or migrating it, or `None` if there is no index there.
+pub(crate) fn migration_status_at(path: &Path) -> Result<Option<MigrationStatus>> {
+    let db_path = path.join("index.db");
+    if !db_path.try_exists()? {
+        return Ok(None);
+    }
+
+    let mut conn = Connection::open_with_flags(
+        &db_path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .with_context(|| format!("opening store index path='{}'", db_path.display()))?;
+    let txn = conn.transaction()?;
+    let (schema_version, pending) = pending_migrations(&txn)?;
+    let history = migration_history(&txn)?;
+    Ok(Some(MigrationStatus { schema_version, pending, history }))
+}
+
+/// The schema version of the database and the migrations it needs. A database without a
+/// page table is new, so needs none; its schema is created at the current version.
+fn pending_migrations(txn: &Transaction) -> Result<(i64, Vec<Migration>)> {
+    let version: i64 = txn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if table_columns(txn, &PageIden::Table.to_string())?.is_none() {
+        return Ok((version, Vec::new()));
+    }
+
+    let pending = MIGRATIONS.iter()
+                            .filter(|migration| migration.version > version)
+                            .copied()
+                            .collect();
+    Ok((version, pending))
+}
+
+/// The migrations recorded in the index_migration table, oldest first.
+fn migration_history(txn: &Transaction) -> Result<Vec<IndexMigration>> {
+    if table_columns(txn, &IndexMigrationIden::Table.to_string())?.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let (sql, params) = Query::select()
+        .from(IndexMigrationIden::Table)
+        .columns([IndexMigrationIden::Version,
+                  IndexMigrationIden::Name,
+                  IndexMigrationIden::FromVersion,
+                  IndexMigrationIden::Applied])
+        .order_by(IndexMigrationIden::Applied, Order::Asc)
+        .order_by(IndexMigrationIden::Version, Order::Asc)
+        .build_rusqlite(SqliteQueryBuilder);
+    let mut statement = txn.prepare(&sql)?;
+    let rows = statement.query_map(&*params.as_params(), IndexMigration::from_row)?;
+    Ok(rows.collect::<rusqlite::Result<Vec<IndexMigration>>>()?)
+}
+
+/// Add `column` with type and constraints `definition` to `table` if the table exists
+/// without it, logging that the index is migrated to add `description`.
+fn add_column_if_missing(txn: &Transaction, description: &str, table: &dyn Iden,
+                         column: &dyn Iden, definition: &str) -> Result<()> {
+    let table = table.to_string();
+    let column = column.to_string();
+    let missing = table_columns(txn, &table)?
+        .is_some_and(|columns| !columns.contains(&column));
+    if missing {
+        tracing::info!("Migrating store index: adding {description}");
+        txn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))?;
+    }
+    Ok(())
+}
+
+/// Remove the database file at `path` with its write-ahead log and shared memory files, if
+/// they exist.
+fn remove_db_files(path: &Path) -> Result<()> {
+    let mut paths = vec![path.to_owned()];
+    for suffix in ["-wal", "-shm"] {
+        let mut suffixed = path.as_os_str().to_owned();
+        suffixed.push(suffix);
+        paths.push(PathBuf::from(suffixed));
+    }
+    for path in paths.iter() {
+        match fs::remove_file(path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Check that `path` is a store index that this version can open, returning its schema
+/// version.
+fn check_index_file(path: &Path) -> Result<i64> {
+    let mut conn = Connection::open_with_flags(
+        path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        bail_kind!(User,
+                   "Index file has schema version {version}, newer than this version supports \
+                    ({SCHEMA_VERSION}). Upgrade wmd to import it.");
+    }
+
+    let txn = conn.transaction()?;
+    if table_columns(&txn, &PageIden::Table.to_string())?.is_none() {
+        bail_kind!(User, "File is not a store index");
+    }
+    let check: String = txn.pragma_query_value(None, "quick_check", |row| row.get(0))?;
+    if check != "ok" {
+        bail_kind!(Verification, "Index file failed sqlite's quick_check: {check}");
+    }
+
+    Ok(version)
+}
+
+/// The names of a table's columns, or `None` if the table doesn't exist.
+fn table_columns(txn: &Transaction, table: &str) -> Result<Option<Vec<String>>> {
+    let exists = txn.query_row(
+        "SELECT count(*) FROM sqlite_schema WHERE type = 'table' AND name = ?1",
+        [table],
+        |row| row.get::<_, i64>(0))? > 0;
+    if !exists {
+        return Ok(None);
+    }
+
+    let statement = txn.prepare(&format!("SELECT * FROM {table} LIMIT 0"))?;
+    let columns = statement.column_names().into_iter().map(String::from).collect();
+    Ok(Some(columns))
+}
+
+/// The index_migration table, created before migrating so migrations can be recorded.
+fn index_migration_schema_sql() -> String {
+    Table::create()
+        .table(IndexMigrationIden::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(IndexMigrationIden::Version)
+                 .integer()
+                 .not_null()
+                 .primary_key())
+        .col(ColumnDef::new(IndexMigrationIden::Name)
+                 .text()
+                 .not_null())
+        .col(ColumnDef::new(IndexMigrationIden::FromVersion)
+                 .integer()
+                 .not_null())
+        .col(ColumnDef::new(IndexMigrationIden::Applied)
+                 .integer()
+                 .not_null())
+        .build(SqliteQueryBuilder)
+        + " STRICT"
+}
+
+/// The page_fts table and the triggers that keep it in sync with the page table.
+fn chunk_schema_sql() -> String {
+    Table::create()
+        .table(ChunkIden::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(ChunkIden::Id)
+                 .integer()
+                 .not_null()
+                 .primary_key())
+        .col(ColumnDef::new(ChunkIden::PagesLen)
+                 .integer()
+                 .not_null())
+        .col(ColumnDef::new(ChunkIden::BytesLen)
+                 .integer())
+        .col(ColumnDef::new(ChunkIden::Committed)
+                 .integer()
+                 .not_null()
+                 .default(1))
+        .build(SqliteQueryBuilder)
+        + " STRICT"
+}
+
+/// `FROM ... WHERE ...` clauses selecting each kind of orphan row in [`IndexOrphans`], for
+/// both counting and deleting them.
+mod orphan_sql {
+    use super::*;
+
+    pub(super) fn pages_without_chunk() -> String {
+        format!("{page_table} WHERE {chunk_id} NOT IN (SELECT {id} FROM {chunk_table})",
+                page_table = PageIden::Table.to_string(),
+                chunk_id = PageIden::ChunkId.to_string(),
+                id = ChunkIden::Id.to_string(),
+                chunk_table = ChunkIden::Table.to_string())
+    }
+
+    pub(super) fn page_categories() -> String {
+        without_page(&PageCategoriesIden::Table.to_string(),
+                     &PageCategoriesIden::MediawikiId.to_string())
+    }
+
+    pub(super) fn page_props() -> String {
+        without_page(&PagePropsIden::Table.to_string(), &PagePropsIden::MediawikiId.to_string())
+    }
+
+    pub(super) fn page_links() -> String {
+        without_page(&PageLinksIden::Table.to_string(), &PageLinksIden::MediawikiId.to_string())
+    }
+
+    pub(super) fn page_templates() -> String {
+        without_page(&PageTemplatesIden::Table.to_string(),
+                     &PageTemplatesIden::MediawikiId.to_string())
+    }
+
+    pub(super) fn slug_disambiguations() -> String {
+        without_page(&SlugDisambiguationIden::Table.to_string(),
+                     &SlugDisambiguationIden::MediawikiId.to_string())
+    }
+
+    /// The FTS5 `docsize` shadow table has a row for each row in the full text search index.
+    pub(super) fn page_fts() -> String {
+        without_page(&format!("{}_docsize", PageFtsIden::Table.to_string()), "id")
+    }
+
+    pub(super) fn categories() -> String {
+        format!("{category_table} WHERE {slug} NOT IN \
+                 (SELECT {category_slug} FROM {page_categories_table})",
+                category_table = CategoryIden::Table.to_string(),
+                slug = CategoryIden::Slug.to_string(),
+                category_slug = PageCategoriesIden::CategorySlug.to_string(),
+                page_categories_table = PageCategoriesIden::Table.to_string())
+    }
+
+    fn without_page(table: &str, mediawiki_id_col: &str) -> String {
+        format!("{table} WHERE {mediawiki_id_col} NOT IN (SELECT {mediawiki_id} FROM {page_table})",
+                mediawiki_id = PageIden::MediawikiId.to_string(),
+                page_table = PageIden::Table.to_string())
+    }
+}
+
+impl Default for FtsTokenizer {
+    fn default() -> FtsTokenizer {
+        FtsTokenizer::Unicode61 {
+            remove_diacritics: 1,
+            separators: String::new(),
+            token_chars: String::new(),
+        }
+    }
+}
+
+impl FtsTokenizer {
+    /// The value of the FTS5 `tokenize` option for this tokenizer, or `None` for the
+    /// default tokenizer, so indexes created before this was configurable match it.
+    fn fts5_args(&self) -> Option<String> {
+        if *self == FtsTokenizer::default() {
+            return None;
+        }
+
+        let args = match self {
+            FtsTokenizer::Unicode61 { remove_diacritics, separators, token_chars } => {
+                let mut args = format!("unicode61 remove_diacritics {remove_diacritics}");
+                if !separators.is_empty() {
+                    args += &format!(" separators {}", sql_quote(separators));
+                }
+                if !token_chars.is_empty() {
+                    args += &format!(" tokenchars {}", sql_quote(token_chars));
+                }
+                args
+            },
+            FtsTokenizer::Trigram { case_sensitive } =>
+                format!("trigram case_sensitive {}", u8::from(*case_sensitive)),
+        };
+        Some(args)
+    }
+}
 
-    fn conn(&self) -> Result<MutexGuard<Connection>> {
-        self.conn.as_ref().ok_or_else(|| format_err!("self.conn is None"))?
-            .lock()
-            .map_err(|_e: std::sync::PoisonError<_>|
-                     format_err!("PoisonError locking connection mutex in store::Index"))
+/// Quote `s` as an SQL string literal.
+fn sql_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Parse the FTS5 `tokenize` option out of a full text search table's `CREATE` statement.
+fn parse_fts_tokenize(create_sql: &str) -> Option<String> {
+    let captures = lazy_regex!(r"tokenize\s*=\s*'((?:[^']|'')*)'").captures(create_sql)?;
+    Some(captures[1].replace("''", "'"))
+}
+
+fn page_fts_schema_sql(layout: Layout, tokenize: Option<&str>) -> String {
+    let tokenize = match tokenize {
+        Some(args) => format!(",\n            tokenize = {}", sql_quote(args)),
+        None => String::new(),
+    };
+
+    match layout {
+        Layout::Single => format!(r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS {page_fts__table} USING fts5(
+            {slug},
+            content = {page_table}, content_rowid = {mediawiki_id},
+            prefix = 2, prefix = 3{tokenize}
+        );
+        CREATE TRIGGER IF NOT EXISTS {page_fts__table}_after_insert
+        AFTER INSERT ON {page_table} BEGIN
+            INSERT INTO {page_fts__table}(rowid, {slug})
+            VALUES (new.{mediawiki_id}, new.{slug});
+        END;
+        CREATE TRIGGER IF NOT EXISTS {page_fts__table}_after_delete
+        AFTER DELETE ON {page_table} BEGIN
+            INSERT INTO {page_fts__table}({page_fts__table}, rowid, {slug})
+            VALUES ('delete', old.{mediawiki_id}, old.{slug});
+        END;
+        CREATE TRIGGER IF NOT EXISTS {page_fts__table}_after_update
+        AFTER UPDATE OF {slug} ON {page_table} BEGIN
+            INSERT INTO {page_fts__table}({page_fts__table}, rowid, {slug})
+            VALUES ('delete', old.{mediawiki_id}, old.{slug});
+            INSERT INTO {page_fts__table}(rowid, {slug})
+            VALUES (new.{mediawiki_id}, new.{slug});
+        END
+    "#, page_fts__table = PageFtsIden::Table.to_string(),
+            page_table = PageIden::Table.to_string(),
+            mediawiki_id = PageIden::MediawikiId.to_string(),
+            slug = PageIden::Slug.to_string()),
+
+        // A table in the search database can't read `page`, so it stores its own copy of
+        // slugs. Triggers in `index.db` can't refer to the search database, so these are
+        // temporary triggers on this connection; unqualified names in them are looked up
+        // in each attached database.
+        Layout::SplitSearch => format!(r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS {SEARCH_SCHEMA}.{page_fts__table} USING fts5(
+            {slug},
+            prefix = 2, prefix = 3{tokenize}
+        );
+        CREATE TEMP TRIGGER IF NOT EXISTS {page_fts__table}_after_insert
+        AFTER INSERT ON {page_table} BEGIN
+            INSERT INTO {page_fts__table}(rowid, {slug})
+            VALUES (new.{mediawiki_id}, new.{slug});
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS {page_fts__table}_after_delete
+        AFTER DELETE ON {page_table} BEGIN
+            DELETE FROM {page_fts__table} WHERE rowid = old.{mediawiki_id};
+        END;
+        CREATE TEMP TRIGGER IF NOT EXISTS {page_fts__table}_after_update
+        AFTER UPDATE OF {slug} ON {page_table} BEGIN
+            DELETE FROM {page_fts__table} WHERE rowid = old.{mediawiki_id};
+            INSERT INTO {page_fts__table}(rowid, {slug})
+            VALUES (new.{mediawiki_id}, new.{slug});
+        END
+    "#, page_fts__table = PageFtsIden::Table.to_string(),
+            page_table = PageIden::Table.to_string(),
+            mediawiki_id = PageIden::MediawikiId.to_string(),
+            slug = PageIden::Slug.to_string()),
     }
+}
 
-    pub(crate) fn import_batch_builder<'index>(&'index self
-    ) -> Result<ImportBatchBuilder<'index>> {
-        Ok(ImportBatchBuilder::new(self))
+/// Rebuild the page_fts table of an index with `layout` from the page table.
+fn rebuild_fts_sql(layout: Layout) -> String {
+    match layout {
+        Layout::Single => format!(
+            "INSERT INTO {page_fts__table}({page_fts__table}) VALUES('rebuild')",
+            page_fts__table = PageFtsIden::Table.to_string()),
+        Layout::SplitSearch => format!(
+            "DELETE FROM {page_fts__table}; \
+             INSERT INTO {page_fts__table}(rowid, {slug}) \
+                 SELECT {mediawiki_id}, {slug} FROM {page_table}",
+            page_fts__table = PageFtsIden::Table.to_string(),
+            page_table = PageIden::Table.to_string(),
+            mediawiki_id = PageIden::MediawikiId.to_string(),
+            slug = PageIden::Slug.to_string()),
     }
+}
 
-    pub(crate) fn get_category(&self, slug_lower_bound: Option<&CategorySlug>, limit: Option<u64>
-    ) -> Result<Vec<dump::CategorySlug>>
-    {
-        let limit = limit.unwrap_or(MAX_QUERY_LIMIT).min(MAX_QUERY_LIMIT);
+/// The FTS5 table indexing `spell_term.term` by trigram, to find candidate spellings.
+const SPELL_TERM_TRIGRAM_TABLE: &str = "spell_term_trigram";
 
-        let (sql, params) = Query::select()
-            .from(CategoryIden::Table)
-            .column(CategoryIden::Slug)
-            .limit(limit)
-            .and_where_option(slug_lower_bound.map(
-                |lower| Expr::col(CategoryIden::Slug).gt(lower.0.as_str())))
-            .build_rusqlite(SqliteQueryBuilder);
-        let params2 = &*params.as_params();
+/// The fts5vocab table listing the words in `page_fts`, one row per word.
+const PAGE_FTS_VOCAB_TABLE: &str = "page_fts_vocab";
 
-        let conn = self.conn()?;
-        let mut statement = conn.prepare_cached(&*sql)?;
-        let mut rows = statement.query(params2)?;
+/// The most words sharing trigrams with an unknown word to compare it with in
+/// `Index::suggest()`.
+const SUGGEST_CANDIDATES_MAX: u64 = 200;
 
-        let mut out = Vec::with_capacity(limit.try_into().expect("u64 to usize"));
+/// The spelling tables in `schema`, which must also have the page_fts table.
+fn spelling_schema_sql(schema: &str) -> String {
+    format!(r#"
+        CREATE TABLE IF NOT EXISTS {schema}.{spell_term_table} (
+            {id} INTEGER PRIMARY KEY,
+            {term} TEXT NOT NULL,
+            {pages_len} INTEGER NOT NULL
+        ) STRICT;
+        CREATE VIRTUAL TABLE IF NOT EXISTS {schema}.{spell_term_trigram_table} USING fts5(
+            {term},
+            content = {spell_term_table}, content_rowid = {id},
+            tokenize = 'trigram'
+        );
+        CREATE VIRTUAL TABLE IF NOT EXISTS {schema}.{page_fts_vocab_table}
+            USING fts5vocab({page_fts__table}, 'row')
+    "#, spell_term_table = SpellTermIden::Table.to_string(),
+        id = SpellTermIden::Id.to_string(),
+        term = SpellTermIden::Term.to_string(),
+        pages_len = SpellTermIden::PagesLen.to_string(),
+        spell_term_trigram_table = SPELL_TERM_TRIGRAM_TABLE,
+        page_fts_vocab_table = PAGE_FTS_VOCAB_TABLE,
+        page_fts__table = PageFtsIden::Table.to_string())
+}
 
-        while let Some(row) = rows.next()? {
-            let slug = row.get_ref(0)?
-                          .as_str()?;
-            out.push(dump::CategorySlug(slug.to_string()));
-        }
+/// The number of single character insertions, deletions, substitutions, and swaps of
+/// adjacent characters to change `a` into `b` (the optimal string alignment distance).
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    // Rows i - 2, i - 1, and i of the distance matrix.
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
 
-        Ok(out)
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                curr[j] = curr[j].min(prev2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut curr);
     }
 
-    pub(crate) fn get_category_pages(
-        &self,
-        slug: &CategorySlug,
-        page_mediawiki_id_lower_bound: Option<u64>,
-        limit: Option<u64>,
-    ) -> Result<Vec<Page>>
-    {
-        let limit = limit.unwrap_or(MAX_QUERY_LIMIT).min(MAX_QUERY_LIMIT);
+    prev[b.len()]
+}
 
-        let (sql, params) = Query::select()
-            .column((PageIden::Table, PageIden::MediawikiId))
-            .column((PageIden::Table, PageIden::ChunkId))
-            .column((PageIden::Table, PageIden::PageChunkIndex))
-            .column((PageIden::Table, PageIden::Slug))
-            .from(PageCategoriesIden::Table)
-            .inner_join(PageIden::Table,
-                        Expr::col((PageCategoriesIden::Table, PageCategoriesIden::MediawikiId))
-                            .equals((PageIden::Table, PageIden::MediawikiId)))
-            .and_where(Expr::col((PageCategoriesIden::Table, PageCategoriesIden::CategorySlug))
-                           .eq(&*slug.0))
-            .and_where_option(page_mediawiki_id_lower_bound.map(
-                |id|
-                Expr::col((PageCategoriesIden::Table, PageCategoriesIden::MediawikiId))
-                    .gt(id)))
-            .limit(limit)
-            .build_rusqlite(SqliteQueryBuilder);
-        let params2 = &*params.as_params();
+/// Split `is:{flag}` and `-is:{flag}` filters out of a page search query.
+///
+/// Returns the rest of the query, the required flags, and the excluded flags.
+fn parse_flag_filters(query: &str) -> Result<(String, PageFlags, PageFlags)> {
+    let mut terms = Vec::new();
+    let mut required = PageFlags::default();
+    let mut excluded = PageFlags::default();
 
-        let conn = self.conn()?;
-        let mut statement = conn.prepare_cached(&*sql)?;
-        let mut rows = statement.query(params2)?;
+    for term in query.split_whitespace() {
+        let (flags, name) = match (term.strip_prefix("is:"), term.strip_prefix("-is:")) {
+            (Some(name), _) => (&mut required, name),
+            (_, Some(name)) => (&mut excluded, name),
+            (None, None) => {
+                terms.push(term);
+                continue;
+            },
+        };
+        let Some(flag) = PageFlags::from_name(name) else {
+            bail!("Unknown page search filter '{term}'. Known flags: {known}",
+                  known = PageFlags::NAMED.iter().map(|(_, name)| *name)
+                                               .collect::<Vec<_>>().join(", "));
+        };
+        *flags = *flags | flag;
+    }
 
-        let mut out = Vec::<Page>::with_capacity(limit.try_into().expect("u64 to usize"));
+    Ok((terms.join(" "), required, excluded))
+}
 
-        while let Some(row) = rows.next()? {
-            let page = Page {
-                mediawiki_id: row.get(0)?,
-                chunk_id: row.get(1)?,
-                page_chunk_index: row.get(2)?,
-                slug: row.get(3)?,
-            };
+/// The titles a full text search query `query` could be looking for, as slugs lowercased
+/// like [`LOWER_UNICODE_FUNCTION`], one for each alternative of an `OR` query.
+///
+/// Quotes, prefix stars, initial token carets, parentheses, column filters, and the `AND`
+/// and `NEAR` operators are full text search syntax, not part of a title, and the term after
+/// `NOT` is excluded from the results, so it's left out.
+fn fts_query_title_slugs(query: &str) -> Vec<String> {
+    let mut alternatives = vec![Vec::<String>::new()];
+    let mut skip_next = false;
+
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.peek().copied() {
+        // Each iteration reads one token: a quoted phrase, a bare word, or punctuation.
+        let token: String = match c {
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        // A doubled quote is an escaped quote in the phrase.
+                        '"' if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            phrase.push('"');
+                        },
+                        '"' => break,
+                        c => phrase.push(c),
+                    }
+                }
+                phrase
+            },
+            c if c.is_whitespace() || matches!(c, '(' | ')' | '*' | '^' | '+' | ',') => {
+                chars.next();
+                continue;
+            },
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.peek().copied() {
+                    if c.is_whitespace() || matches!(c, '"' | '(' | ')' | '*' | '^' | '+' | ',') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                match &*word {
+                    "AND" | "NEAR" => continue,
+                    "OR" => {
+                        alternatives.push(Vec::new());
+                        continue;
+                    },
+                    "NOT" => {
+                        skip_next = true;
+                        continue;
+                    },
+                    // A column filter, e.g. `slug:paris`.
+                    _ if word.ends_with(':') => continue,
+                    _ => match word.split_once(':') {
+                        Some((_column, word)) => word.to_string(),
+                        None => word,
+                    },
+                }
+            },
+        };
 
-            out.push(page);
+        if std::mem::take(&mut skip_next) {
+            continue;
         }
+        alternatives.last_mut().expect("alternatives is not empty").push(token);
+    }
 
-        Ok(out)
+    let mut slugs = Vec::<String>::new();
+    for words in alternatives {
+        let slug = slug::normalize_slug(&words.join(" ")).to_lowercase();
+        if !slug.is_empty() && !slugs.contains(&slug) {
+            slugs.push(slug);
+        }
     }
+    slugs
+}
 
-    pub(crate) fn get_store_page_id_by_mediawiki_id(&self, id: u64) -> Result<Option<StorePageId>> {
-        let query = Query::select()
-            .from(PageIden::Table)
-            .column(PageIden::ChunkId)
-            .column(PageIden::PageChunkIndex)
-            .and_where(Expr::col(PageIden::MediawikiId).eq(id))
-            .take();
-        self.single_row_select_to_store_page_id(query)
+/// Run `EXPLAIN QUERY PLAN` for a query, returning each line of the plan indented by its
+/// depth in the plan's tree.
+fn query_plan(conn: &Connection, sql: &str, params: &RusqliteValues) -> Result<Vec<String>> {
+    let mut statement = conn.prepare(&format!("EXPLAIN QUERY PLAN {sql}"))?;
+    let rows = statement.query_map(
+        &*params.as_params(),
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(3)?)))?;
+
+    let mut depths = HashMap::<i64, usize>::new();
+    let mut out = Vec::new();
+    for row in rows {
+        let (id, parent, detail) = row?;
+        let depth = depths.get(&parent).map_or(0, |parent_depth| parent_depth + 1);
+        depths.insert(id, depth);
+        out.push(format!("{indent}{detail}", indent = "  ".repeat(depth)));
     }
+    Ok(out)
+}
 
-    pub(crate) fn get_store_page_id_by_slug(&self, slug: &str) -> Result<Option<StorePageId>> {
-        let query = Query::select()
-            .from(PageIden::Table)
-            .column(PageIden::MediawikiId)
-            .column(PageIden::ChunkId)
-            .column(PageIden::PageChunkIndex)
-            .column(PageIden::Slug)
-            .and_where(Expr::col(PageIden::Slug).like(slug))
-            .limit(100)
-            .take();
+/// Describe query parameters for logs without the text of search queries and slugs.
+fn redact_params(params: &RusqliteValues) -> Vec<String> {
+    params.0.iter().map(|RusqliteValue(value)| match value {
+        Value::String(s) => format!("String(len={})", s.as_ref().map_or(0, |s| s.len())),
+        Value::Char(_) => "Char".to_string(),
+        Value::Bytes(b) => format!("Bytes(len={})", b.as_ref().map_or(0, |b| b.len())),
+        _ => format!("{value:?}"),
+    }).collect()
+}
 
-        let (sql, params) = query.build_rusqlite(SqliteQueryBuilder);
-        let params2 = &*params.as_params();
+/// Returns true if `e` is sqlite's `SQLITE_BUSY` error.
+fn is_busy(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<rusqlite::Error>(),
+             Some(rusqlite::Error::SqliteFailure(
+                 rusqlite::ffi::Error { code: rusqlite::ErrorCode::DatabaseBusy, .. }, _)))
+}
 
-        let conn = self.conn()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut statement = conn.prepare_cached(&*sql)?;
-        let mut rows = statement.query(params2)?;
+    /// An empty index in `dir`.
+    fn test_index(dir: &wikimedia::TempDir) -> Index {
+        test_index_with(dir, Layout::Single, AutoMigrate::Off)
+    }
 
-        let mut out = Vec::<Page>::with_capacity(8);
+    /// Open the index in `dir`, creating it with `layout` if it doesn't exist.
+    fn test_index_with(dir: &wikimedia::TempDir, layout: Layout, auto_migrate: AutoMigrate
+    ) -> Index {
+        Options {
+            batch: BatchOptions::default(),
+            path: dir.path().unwrap().join("index"),
+            slow_query_threshold: None,
+            fts_tokenizer: None,
+            auto_migrate,
+            layout,
+        }.build().unwrap()
+    }
 
-        while let Some(row) = rows.next()? {
-            let page = Page {
-                mediawiki_id: row.get(0)?,
-                chunk_id: row.get(1)?,
-                page_chunk_index: row.get(2)?,
-                slug: row.get(3)?,
-            };
+    fn test_dir() -> wikimedia::TempDir {
+        wikimedia::TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                   /* keep: */ false).unwrap()
+    }
 
-            out.push(page);
+    fn test_page(id: u64, ns_id: i64, title: &str, categories: &[&str]) -> dump::Page {
+        dump::Page {
+            ns_id,
+            id,
+            title: title.to_string(),
+            revision: Some(dump::Revision {
+                id: id + 1000,
+                parent_id: None,
+                timestamp: None,
+                text: Some(format!("{title} is a page.")),
+                sha1: None,
+                categories: categories.iter()
+                                      .map(|name| dump::PageCategory {
+                                          name: CategoryName(name.to_string()),
+                                          sort_key: None,
+                                      })
+                                      .collect(),
+            }),
+            restrictions: None,
         }
+    }
 
-        let out_len = out.len();
-        match out_len {
-            0 => Ok(None),
-            1 => {
-                let page = out.first().expect("out.len == 1");
-                Ok(Some(page.store_id()))
-            },
-            _ => {
-                let exact_pages: Vec<Page> = out.into_iter().filter(|p| p.slug == slug).collect();
-                tracing::debug!(
-                    out_len,
-                    exact_pages_len = exact_pages.len(),
-                    %slug,
-                    "get_store_page_id_by_slug: exact_pages filter");
-                match exact_pages.len() {
-                    0 => Ok(None),
-                    1 => {
-                        let page = exact_pages.first().expect("exact_pages.len == 1");
-                        Ok(Some(page.store_id()))
-                    },
-                    _ => {
-                        tracing::warn!(
-                            out_len,
-                            exact_pages_len = exact_pages.len(),
-                            %slug,
-                            "get_store_page_id_by_slug: more than 1 exact match");
-                        Ok(None)
-                    },
-                }
-            }
+    /// Index `pages` as if they were written to one chunk.
+    fn seed(index: &Index, pages: &[dump::Page]) {
+        let mut batch = index.import_batch_builder().unwrap();
+        for (i, page) in pages.iter().enumerate() {
+            batch.push(page, StorePageId {
+                chunk_id: ChunkId(1),
+                page_chunk_index: PageChunkIndex(i as u64),
+            }).unwrap();
         }
+        batch.commit().unwrap();
     }
 
-    pub(crate) fn page_search(&self, query: &str, limit: Option<u64>
-    ) -> Result<Vec<Page>> {
+    fn slugs(pages: &[Page]) -> Vec<&str> {
+        pages.iter().map(|page| &*page.slug).collect()
+    }
 
-        let limit = limit.unwrap_or(MAX_QUERY_LIMIT).min(MAX_QUERY_LIMIT);
+    #[test]
+    fn parse_flag_filters_terms() {
+        let (query, required, excluded) =
+            parse_flag_filters("mercury -is:disambiguation  planet is:STUB").unwrap();
+        assert_eq!(query, "mercury planet");
+        assert_eq!(required, PageFlags::STUB);
+        assert_eq!(excluded, PageFlags::DISAMBIGUATION);
 
-        let (sql, params) = Query::select()
-            .column((PageIden::Table, PageIden::MediawikiId))
-            .column((PageIden::Table, PageIden::ChunkId))
-            .column((PageIden::Table, PageIden::PageChunkIndex))
-            .column((PageIden::Table, PageIden::Slug))
-            .from(PageFtsIden::Table)
-            .inner_join(PageIden::Table,
-                        Expr::col((PageFtsIden::Table, PageFtsIden::MediawikiId))
-                            .equals((PageIden::Table, PageIden::MediawikiId)))
-            .and_where(Expr::col(PageFtsIden::Table).matches(Expr::value(query)))
-            .order_by((PageFtsIden::Table, PageFtsIden::Rank), Order::Asc)
-            .limit(limit)
-            .build_rusqlite(SqliteQueryBuilder);
-        let params2 = &*params.as_params();
+        assert_eq!(parse_flag_filters("mercury").unwrap(),
+                   ("mercury".to_string(), PageFlags::default(), PageFlags::default()));
+        assert!(parse_flag_filters("is:redirect").is_err());
+    }
 
-        let conn = self.conn()?;
-        let mut statement = conn.prepare_cached(&*sql)?;
-        let mut rows = statement.query(params2)?;
+    #[test]
+    fn page_search_in_category() {
+        let dir = test_dir();
+        let index = test_index(&dir);
+        seed(&index, &[test_page(1, 0, "Mercury (planet)", &["Planets"]),
+                       test_page(2, 0, "Mercury (element)", &["Chemical elements"]),
+                       test_page(3, 0, "Venus", &["Planets"])]);
 
-        let mut out = Vec::<Page>::with_capacity(limit.try_into().expect("u64 to usize"));
+        let options = PageSearchOptions {
+            category: Some(CategorySlug("Planets".to_string())),
+            ..PageSearchOptions::default()
+        };
+        assert_eq!(slugs(&index.page_search("mercury", &options).unwrap()),
+                   ["Mercury_(planet)"]);
 
-        while let Some(row) = rows.next()? {
-            let page = Page {
-                mediawiki_id: row.get(0)?,
-                chunk_id: row.get(1)?,
-                page_chunk_index: row.get(2)?,
-                slug: row.get(3)?,
-            };
+        let mut all = index.page_search("mercury", &PageSearchOptions::default()).unwrap();
+        all.sort_by_key(|page| page.mediawiki_id);
+        assert_eq!(slugs(&all), ["Mercury_(planet)", "Mercury_(element)"]);
+    }
 
-            out.push(page);
-        }
+    #[test]
+    fn category_pages_in_namespace() {
+        let dir = test_dir();
+        let index = test_index(&dir);
+        seed(&index, &[test_page(1, 0, "Mercury (planet)", &["Planets"]),
+                       test_page(2, 1, "Talk:Mercury (planet)", &["Planets"]),
+                       test_page(3, 0, "Venus", &["Planets"])]);
 
-        Ok(out)
+        let slug = CategorySlug("Planets".to_string());
+        let pages = |namespace| index.get_category_pages(&slug, None, namespace,
+                                                         QueryLimit::default()).unwrap();
+        assert_eq!(slugs(&pages(Some(1))), ["Talk:Mercury_(planet)"]);
+        assert_eq!(slugs(&pages(Some(0))), ["Mercury_(planet)", "Venus"]);
+        assert_eq!(pages(None).len(), 3);
     }
 
-    fn single_row_select_to_store_page_id(&self, select: SelectStatement
-    ) -> Result<Option<StorePageId>>
-    {
-        let (sql, params) = select.build_rusqlite(SqliteQueryBuilder);
-        let params2 = &*params.as_params();
+    #[test]
+    fn page_search_in_namespace() {
+        let dir = test_dir();
+        let index = test_index(&dir);
+        seed(&index, &[test_page(1, 0, "Infobox", &[]),
+                       test_page(2, 10, "Template:Infobox", &[]),
+                       test_page(3, 10, "Template:Navbox", &[])]);
 
-        let conn = self.conn()?;
+        let options = PageSearchOptions {
+            namespace: Some(10),
+            ..PageSearchOptions::default()
+        };
+        assert_eq!(slugs(&index.page_search("infobox", &options).unwrap()),
+                   ["Template:Infobox"]);
+        assert_eq!(index.page_search("infobox", &PageSearchOptions::default()).unwrap().len(),
+                   2);
+    }
 
-        conn.query_row(
-            &*sql, params2,
-            |row| -> rusqlite::Result<StorePageId> {
-                Ok(StorePageId {
-                    chunk_id: ChunkId(row.get(0)?),
-                    page_chunk_index: PageChunkIndex(row.get(1)?),
-                })
-            }
-        ).optional()
-         .map_err(|e| e.into())
+    #[test]
+    fn page_search_ranks_title_matches_first() {
+        let dir = test_dir();
+        let index = test_index(&dir);
+        seed(&index, &[test_page(1, 0, "Émile Zola Émile Zola", &[]),
+                       test_page(2, 0, "Émile Zola bibliography", &[]),
+                       test_page(3, 0, "Émile Zola", &[]),
+                       test_page(4, 0, "Paris Paris", &[]),
+                       test_page(5, 0, "Paris", &[])]);
+
+        let search = |query: &str| -> Vec<u64> {
+            index.page_search(query, &PageSearchOptions::default()).unwrap()
+                 .iter().map(|page| page.mediawiki_id).collect()
+        };
+        // Without the title rank, full text search ranks page 1 first.
+        assert_eq!(search("zola"), [1, 3, 2]);
+        assert_eq!(search("émile zola"), [3, 1, 2]);
+        assert_eq!(search(r#""ÉMILE ZOLA" -is:disambiguation"#), [3, 1, 2]);
+        assert_eq!(search("émile zola bibliography"), [2]);
+        let mut exact = search(r#""émile zola" OR paris"#)[..2].to_vec();
+        exact.sort();
+        assert_eq!(exact, [3, 5]);
     }
-}
 
-impl BatchInsert {
-    fn new(init_fn: impl Fn() -> InsertStatement + 'static, max_batch_len: usize) -> BatchInsert {
-        BatchInsert {
-            built: Vec::new(),
-            curr_statement_values_len: 0,
-            max_batch_len,
-            statement: init_fn(),
-            values_len: 0,
+    #[test]
+    fn page_fts_follows_page_rows() {
+        for layout in [Layout::Single, Layout::SplitSearch] {
+            let dir = test_dir();
+            let mut index = test_index_with(&dir, layout, AutoMigrate::Off);
+            let search = |index: &Index, query: &str| -> Vec<u64> {
+                index.page_search(query, &PageSearchOptions::default()).unwrap()
+                     .iter().map(|page| page.mediawiki_id).collect()
+            };
+            let fts_integrity_check = |index: &Index| {
+                index.conn().unwrap().execute(
+                    &format!("INSERT INTO {schema}.page_fts(page_fts, rank) \
+                              VALUES ('integrity-check', 1)",
+                             schema = index.search_schema()),
+                    []).unwrap();
+            };
 
-            // This consumes `init_fn`, so initialise it last.
-            init_fn: Box::new(init_fn),
+            seed(&index, &[test_page(1, 0, "Mercury", &[]),
+                           test_page(2, 0, "Venus", &[])]);
+            assert_eq!(search(&index, "mercury"), [1], "{layout:?}");
+            fts_integrity_check(&index);
+
+            index.conn().unwrap().execute(
+                "UPDATE page SET slug = 'Mercury_(planet)' WHERE mediawiki_id = 1", []).unwrap();
+            assert_eq!(search(&index, "planet"), [1], "{layout:?}");
+            fts_integrity_check(&index);
+
+            // Updates to other columns leave the search row alone.
+            index.conn().unwrap().execute(
+                "UPDATE page SET ns_id = 4 WHERE mediawiki_id = 1", []).unwrap();
+            assert_eq!(search(&index, "planet"), [1], "{layout:?}");
+
+            assert_eq!(index.delete_pages(&[1]).unwrap(), 1);
+            assert!(search(&index, "mercury").is_empty(), "{layout:?}");
+            assert_eq!(search(&index, "venus"), [2], "{layout:?}");
+            fts_integrity_check(&index);
         }
     }
 
-    fn push_values<I>(&mut self, values: I) -> Result<()>
-        where I: IntoIterator<Item = SimpleExpr>
-    {
-        self.statement.values(values)?;
+    #[test]
+    fn migrate_page_fts_to_external_content() {
+        let dir = test_dir();
+        let index = test_index(&dir);
+        seed(&index, &[test_page(1, 0, "Mercury", &[]),
+                       test_page(2, 0, "Venus", &[])]);
+        drop(index);
 
-        self.curr_statement_values_len += 1;
-        self.values_len += 1;
+        // Version 1 kept its own copy of slugs in page_fts, which could be stale.
+        let conn = Connection::open(dir.path().unwrap().join("index/index.db")).unwrap();
+        conn.execute_batch(
+            "DROP TRIGGER page_fts_after_insert; \
+             DROP TRIGGER page_fts_after_delete; \
+             DROP TRIGGER page_fts_after_update; \
+             DROP TABLE page_fts; \
+             CREATE VIRTUAL TABLE page_fts USING fts5(slug); \
+             INSERT INTO page_fts(rowid, slug) VALUES (1, 'Stale');").unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        drop(conn);
 
-        if self.curr_statement_values_len >= self.max_batch_len {
-            let built_query = self.statement.build_rusqlite(SqliteQueryBuilder);
-            self.built.push(built_query);
-            self.curr_statement_values_len = 0;
-            let _old = std::mem::replace(&mut self.statement, (self.init_fn)());
-        }
+        let index = test_index_with(&dir, Layout::Single, AutoMigrate::All);
+        let search = |query: &str| -> Vec<u64> {
+            index.page_search(query, &PageSearchOptions::default()).unwrap()
+                 .iter().map(|page| page.mediawiki_id).collect()
+        };
+        assert_eq!(search("mercury"), [1]);
+        assert_eq!(search("venus"), [2]);
+        assert!(search("stale").is_empty());
 
-        Ok(())
+        // New pages are indexed by the triggers the migration created.
+        seed(&index, &[test_page(3, 0, "Mars", &[])]);
+        assert_eq!(search("mars"), [3]);
     }
 
-    fn execute_all(mut self, txn: &Transaction) -> Result<()> {
-        if self.curr_statement_values_len > 0 {
-            let built_final = self.statement.build_rusqlite(SqliteQueryBuilder);
-            self.built.push(built_final);
-        }
+    #[test]
+    fn fts_query_title_slugs_parses_terms() {
+        assert_eq!(fts_query_title_slugs("new york"), ["new_york"]);
+        assert_eq!(fts_query_title_slugs(r#""New York" OR big apple*"#),
+                   ["new_york", "big_apple"]);
+        assert_eq!(fts_query_title_slugs("slug:paris AND (france NOT texas)"),
+                   ["paris_france"]);
+        assert_eq!(fts_query_title_slugs(r#""say ""hi""""#), [r#"say_"hi""#]);
+        assert!(fts_query_title_slugs("OR NOT x").is_empty());
+    }
 
-        for (sql, params) in self.built.into_iter() {
-            let params2 = params.as_params();
-            txn.execute(&*sql, &*params2)?;
-        }
+    #[test]
+    fn redact_params_hides_text() {
+        let params = RusqliteValues(vec![RusqliteValue(Value::from("Machine learning")),
+                                         RusqliteValue(Value::from(12_u64))]);
+        assert_eq!(redact_params(&params), ["String(len=16)", "BigUnsigned(Some(12))"]);
+    }
 
-        Ok(())
+    #[test]
+    fn next_values_per_batch() {
+        let ms = StdDuration::from_millis;
+        let fixed = BatchOptions::default();
+        assert_eq!(fixed.next_values_per_batch(100, ms(2000), true), 100);
+
+        let adaptive = BatchOptions {
+            adaptive: true,
+            ..BatchOptions::default()
+        };
+        assert_eq!(adaptive.next_values_per_batch(100, ms(2000), false), 50);
+        assert_eq!(adaptive.next_values_per_batch(100, ms(10), true), 50);
+        assert_eq!(adaptive.next_values_per_batch(100, ms(300), false), 100);
+        assert_eq!(adaptive.next_values_per_batch(100, ms(10), false), 150);
+        assert_eq!(adaptive.next_values_per_batch(VALUES_PER_BATCH_MIN, ms(2000), false),
+                   VALUES_PER_BATCH_MIN);
+        assert_eq!(adaptive.next_values_per_batch(VALUES_PER_BATCH_MAX, ms(10), false),
+                   VALUES_PER_BATCH_MAX);
     }
-}
 
-impl<'index> ImportBatchBuilder<'index> {
-    fn new(index: &'index Index) -> ImportBatchBuilder<'index> {
-        ImportBatchBuilder {
-            index,
-            category_batch: BatchInsert::new(
-                || Query::insert()
-                       .into_table(CategoryIden::Table)
-                       .columns([CategoryIden::Slug])
-                       .on_conflict(OnConflict::new().do_nothing().to_owned())
-                       .to_owned(),
-                index.opts.max_values_per_batch),
-            page_batch: BatchInsert::new(
-                || Query::insert()
-                       .into_table(PageIden::Table)
-                       .columns([PageIden::MediawikiId,
-                                 PageIden::ChunkId,
-                                 PageIden::PageChunkIndex,
-                                 PageIden::Slug])
-                       .on_conflict(OnConflict::new().do_nothing().to_owned())
-                       .to_owned(),
-                index.opts.max_values_per_batch),
-            page_fts_batch: BatchInsert::new(
-                || Query::insert()
-                       .into_table(PageFtsIden::Table)
-                       .columns([PageFtsIden::MediawikiId,
-                                 PageFtsIden::Title])
-//                       .on_conflict(OnConflict::new().do_nothing().to_owned())
-                       .to_owned(),
-                index.opts.max_values_per_batch),
-            page_categories_batch: BatchInsert::new(
-                || Query::insert()
-                       .into_table(PageCategoriesIden::Table)
-                       .columns([PageCategoriesIden::MediawikiId,
-                                 PageCategoriesIden::CategorySlug])
-                       .on_conflict(OnConflict::new().do_nothing().to_owned())
-                       .to_owned(),
-                index.opts.max_values_per_batch),
-        }
+    #[test]
+    fn edit_distance_counts_edits() {
+        let distance = |a: &str, b: &str| edit_distance(&a.chars().collect::<Vec<char>>(),
+                                                        &b.chars().collect::<Vec<char>>());
+        assert_eq!(distance("mercury", "mercury"), 0);
+        assert_eq!(distance("mercuy", "mercury"), 1);
+        assert_eq!(distance("mecrury", "mercury"), 1);
+        assert_eq!(distance("marcury", "mercury"), 1);
+        assert_eq!(distance("abc", "xyz"), 3);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("東京", "東京都"), 1);
     }
 
-    pub(crate) fn push(&mut self, page: &dump::Page, store_page_id: StorePageId) -> Result<()> {
-        let page_slug = slug::title_to_slug(&*page.title);
+    #[test]
+    fn slug_collision_preferred() {
+        let page = |mediawiki_id, slug: &str, text_bytes, redirect_slug: Option<&str>| Page {
+            mediawiki_id,
+            chunk_id: 0,
+            page_chunk_index: 0,
+            slug: slug.to_string(),
+            ns_id: 0,
+            text_bytes: Some(text_bytes),
+            word_count: None,
+            page_flags: None,
+            redirect_slug: redirect_slug.map(|slug| slug.to_string()),
+            redirect_fragment: None,
+            revision_timestamp: None,
+        };
+        let collision = |pages| SlugCollision { pages, disambiguation: None };
 
-        self.page_batch.push_values([
-            page.id.into(),
-            store_page_id.chunk_id.0.into(),
-            store_page_id.page_chunk_index.0.into(),
-            page_slug.into()
-        ])?;
+        let preferred = |collision: &SlugCollision| collision.preferred().unwrap().mediawiki_id;
+        assert_eq!(preferred(&collision(vec![page(1, "Foo_bar", 2000, Some("Foo_Bar")),
+                                             page(2, "Foo_Bar", 100, None)])), 2);
+        assert_eq!(preferred(&collision(vec![page(1, "Foo_bar", 100, None),
+                                             page(2, "Foo_Bar", 200, None)])), 2);
+        assert_eq!(preferred(&collision(vec![page(2, "Foo_bar", 100, None),
+                                             page(1, "Foo_Bar", 100, None)])), 1);
+        assert!(collision(Vec::new()).preferred().is_none());
+    }
 
-        self.page_fts_batch.push_values([
-            page.id.into(),
-            (&page.title).into(),
-        ])?;
+    #[test]
+    fn fts_tokenize_round_trip() {
+        assert_eq!(FtsTokenizer::default().fts5_args(), None);
+        assert_eq!(parse_fts_tokenize(&page_fts_schema_sql(Layout::Single, None)), None);
 
-        if let Some(ref rev) = page.revision {
-            for category_name in rev.categories.iter() {
-                self.category_batch.push_values([
-                    category_name.to_slug().0.into(),
-                ])?;
-                self.page_categories_batch.push_values([
-                    page.id.into(),
-                    category_name.to_slug().0.into(),
-                ])?;
-            }
-        }
+        let unicode61 = FtsTokenizer::Unicode61 {
+            remove_diacritics: 2,
+            separators: "'-".to_string(),
+            token_chars: String::new(),
+        };
+        let args = unicode61.fts5_args().unwrap();
+        assert_eq!(args, "unicode61 remove_diacritics 2 separators '''-'");
+        assert_eq!(parse_fts_tokenize(&page_fts_schema_sql(Layout::Single, Some(&args)))
+                       .unwrap(),
+                   args);
 
-        Ok(())
+        let trigram = FtsTokenizer::Trigram { case_sensitive: false };
+        assert_eq!(trigram.fts5_args().unwrap(), "trigram case_sensitive 0");
     }
 
-    #[tracing::instrument(level = "trace", skip(self),
-                          fields(category_batch.len = self.category_batch.values_len,
-                                 page_batch.len = self.page_batch.values_len,
-                                 page_categories_batch.len =
-                                     self.page_categories_batch.values_len))]
-    pub(crate) fn commit(self) -> Result<()> {
-        let mut conn = self.index.conn()?;
-        let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    #[test]
+    fn changes_cursor_round_trip() {
+        let cursor = ChangesCursor { timestamp: 1679387424, mediawiki_id: 12 };
+        assert_eq!(cursor.to_string(), "1679387424-12");
+        assert_eq!("1679387424-12".parse::<ChangesCursor>().unwrap(), cursor);
+        assert!("1679387424".parse::<ChangesCursor>().is_err());
+        assert!("1679387424-x".parse::<ChangesCursor>().is_err());
+    }
 
-        self.category_batch.execute_all(&txn)?;
-        self.page_batch.execute_all(&txn)?;
-        self.page_categories_batch.execute_all(&txn)?;
-        self.page_fts_batch.execute_all(&txn)?;
+    #[test]
+    fn migrate_on_open() {
+        let dir = wikimedia::TempDir::create(
+            &std::env::temp_dir().join("wikimedia-store-tests"), /* keep: */ false).unwrap();
+        let path = dir.path().unwrap().join("index");
+        let open = |auto_migrate| Options {
+            batch: BatchOptions::default(),
+            path: path.clone(),
+            slow_query_threshold: None,
+            fts_tokenizer: None,
+            auto_migrate,
+            layout: Layout::Single,
+        }.build();
+        let set_version = |version: i64| {
+            Connection::open(path.join("index.db")).unwrap()
+                .pragma_update(None, "user_version", version).unwrap();
+        };
+        let status = || migration_status_at(&path).unwrap().unwrap();
 
-        txn.commit()?;
+        // A new index needs no migrations.
+        drop(open(AutoMigrate::Off).unwrap());
+        assert_eq!(status().schema_version, SCHEMA_VERSION);
+        assert!(status().pending.is_empty());
+        assert!(status().history.is_empty());
 
-        Ok(())
+        // Version 7 is from before the chunk list, which isn't a safe migration.
+        set_version(7);
+        assert_eq!(status().pending.iter().map(|migration| migration.version)
+                                          .collect::<Vec<i64>>(),
+                   (8..=SCHEMA_VERSION).collect::<Vec<i64>>());
+        let e = open(AutoMigrate::Safe).unwrap_err();
+        assert_eq!(wikimedia::ErrorKind::of(&e), wikimedia::ErrorKind::User);
+        assert_eq!(status().schema_version, 7);
+
+        drop(open(AutoMigrate::All).unwrap());
+        let migrated = status();
+        assert_eq!(migrated.schema_version, SCHEMA_VERSION);
+        assert!(migrated.pending.is_empty());
+        assert_eq!(migrated.history.len(), (SCHEMA_VERSION - 7) as usize);
+        assert_eq!((migrated.history[0].version, migrated.history[0].from_version), (8, 7));
+
+        // Versions 19 to 21 only add tables and indexes.
+        set_version(18);
+        assert!(open(AutoMigrate::Off).is_err());
+        drop(open(AutoMigrate::Safe).unwrap());
+        assert_eq!(status().schema_version, SCHEMA_VERSION);
     }
 }
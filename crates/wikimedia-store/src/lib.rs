@@ -2,81 +2,370 @@
 
 #![feature(
     async_closure,
-    iterator_try_collect,
     iterator_try_reduce,
 )]
 
 pub mod capnp;
 
+pub mod bench;
 mod chunk;
+mod compact;
+mod config;
+pub mod deletion;
+pub mod diff;
+pub mod export;
+pub mod generation;
 pub mod index;
+mod limit;
+pub mod metadata;
+mod namespace_filter;
+pub mod page_events;
+mod pread;
+pub mod prerender;
+mod quota;
+pub mod rebuild;
+mod remote;
+mod schedule;
 
 pub use chunk::{
-    ChunkId, ChunkMeta, convert_store_page_to_dump_page_without_body, MappedChunk, MappedPage,
+    ChunkId, ChunkMeta, ChunkReadMode, ChunkSizeStrategy, convert_store_page_to_dump_page_without_body, MappedChunk, MappedPage,
     StorePageId,
 };
+pub use limit::QueryLimit;
+pub use namespace_filter::NamespaceFilter;
+pub use page_events::{PageEvent, PageEventPage};
+pub use quota::{Quota, QuotaExceeded, QuotaLimit};
 
-use anyhow::Context;
+use anyhow::{bail, Context, format_err};
 use derive_builder::UninitializedFieldError;
 use rayon::prelude::*;
 use std::{
+    cell::Cell,
+    collections::HashMap,
     fmt::Debug,
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     result::Result as StdResult,
-    sync::atomic::{AtomicI64, AtomicU64, Ordering},
-    time::Instant,
+    sync::{
+        Arc,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration as StdDuration, Instant},
 };
+use serde::Serialize;
 use valuable::Valuable;
 use wikimedia::{
+    bail_kind,
+    cancel::{self, CancellationToken},
     dump::{
         self,
         CategorySlug,
         DumpName,
-        local::{FileSpec, JobFiles, OpenJobFile},
+        local::{Compression, DumpParseError, FileSpec, JobFiles, LimitExceeded, OpenJobFile},
+        Namespace,
+        SiteInfo,
     },
     Error,
+    ErrorKind,
+    events::{self, Event},
+    KindError,
     Result,
     try2,
-    util::fmt::{self, ByteRate, Bytes, Duration},
+    util::{
+        fmt::{self, ByteRate, Bytes, Duration},
+        progress,
+    },
+    wikitext,
 };
 
 #[derive(Clone, Debug, Default)]
 pub struct Options {
+    auto_migrate: Option<index::AutoMigrate>,
+    cancellation: Option<CancellationToken>,
+    chunk_read_mode: Option<ChunkReadMode>,
+    chunk_size_strategy: Option<ChunkSizeStrategy>,
+    chunks_path: Option<PathBuf>,
     dump_name: Option<DumpName>,
+    events: Option<events::Sender>,
+    fts_tokenizer: Option<index::FtsTokenizer>,
+    index_batch: Option<index::BatchOptions>,
+    index_layout: Option<index::Layout>,
+    import_namespaces: Option<NamespaceFilter>,
+    index_path: Option<PathBuf>,
+    io_concurrency: Option<usize>,
     max_chunk_len: Option<u64>,
+    max_pages_per_chunk: Option<u64>,
+    page_events: Option<page_events::Sender>,
     path: Option<PathBuf>,
+    quota: Option<Quota>,
+    remote_url: Option<String>,
+    site: Option<SiteInfo>,
+    slow_query_threshold: Option<StdDuration>,
+    threads: Option<usize>,
+    write_rate_limit: Option<u64>,
 }
 
 struct OptionsBuilt {
+    /// The options the store was built with, to build it again, see [`Store::publish`].
+    built_from: Options,
+
+    cancellation: CancellationToken,
+    chunk_read_mode: ChunkReadMode,
+    chunk_size_strategy: ChunkSizeStrategy,
+    chunks_path: PathBuf,
     dump_name: DumpName,
+    events: Option<events::Sender>,
+    import_namespaces: NamespaceFilter,
+    index_batch: index::BatchOptions,
+    index_path: PathBuf,
     max_chunk_len: u64,
+    max_pages_per_chunk: u64,
+    page_events: page_events::Sender,
     path: PathBuf,
+    quota: Quota,
+    scheduler: schedule::Scheduler,
+    site: Option<SiteInfo>,
+    slow_query_threshold: Option<StdDuration>,
 }
 
 pub struct Store {
-    chunk_store: chunk::Store,
+    chunk_store: ChunkStore,
     index: index::Index,
     opts: OptionsBuilt,
 }
 
-#[derive(Clone, Debug, Valuable)]
+/// Where a store's chunks are read from.
+enum ChunkStore {
+    Local(chunk::Store),
+    Remote(remote::RemoteChunkStore),
+}
+
+/// The result of [`Store::import`], also logged when the import is done.
+#[derive(Clone, Debug, Serialize, Valuable)]
 pub struct ImportResult {
     pub chunk_bytes_total: Bytes,
     pub chunk_write_rate: ByteRate,
     pub chunks_len: u64,
     pub duration: Duration,
+
+    /// Total time spent committing chunks' pages to the index, over all import threads.
+    pub index_commit_duration: Duration,
+
+    /// Rows inserted into the index, over all tables.
+    pub index_values_total: u64,
+
+    /// The ID of this import's [`index::ImportRun`], recorded in each chunk's
+    /// [`index::ChunkProvenance`].
+    pub import_run_id: u64,
+
     pub pages_total: u64,
+
+    /// Pages skipped because they exceeded a parse limit, see
+    /// [`LimitExceeded::is_page_skipped`].
+    pub pages_skipped_total: u64,
+
+    /// Pages not imported because of their namespace, see [`Options::import_namespaces`].
+    pub pages_excluded_total: u64,
+
+    /// The threads the import ran on, see [`Options::threads`].
+    pub threads: usize,
+
+    /// The configured limit on `chunk_write_rate`, see [`Options::write_rate_limit`].
+    pub write_rate_limit: Option<ByteRate>,
+
+    /// Total time import threads waited to stay under `write_rate_limit`.
+    pub write_throttled_duration: Duration,
+
+    /// The import was cancelled, see [`Options::cancellation`]. The chunks imported before
+    /// that were committed, and continuing the import will import the rest.
+    pub cancelled: bool,
+
+    /// The results for each source file, ordered by path. Files that failed to import are not
+    /// included.
+    pub files: Vec<ImportFileResult>,
+}
+
+/// The part of an [`ImportResult`] from one source file.
+#[derive(Clone, Debug, Serialize, Valuable)]
+pub struct ImportFileResult {
+    /// The canonical path of the source file.
+    pub path: String,
+
+    /// The file was completely imported by a previous import, and skipped by this one
+    /// because it was continued.
+    pub already_imported: bool,
+
+    pub pages: u64,
+
+    /// Pages skipped because they exceeded a parse limit.
+    pub pages_skipped: u64,
+
+    /// Pages not imported because of their namespace.
+    pub pages_excluded: u64,
+
+    pub chunks_len: u64,
+    pub chunk_bytes: Bytes,
+    pub duration: Duration,
+}
+
+/// The result of [`Options::migrate_index`].
+#[derive(Clone, Debug, Serialize)]
+pub struct MigrationReport {
+    pub from_version: i64,
+    pub to_version: i64,
+    pub dry_run: bool,
+
+    /// The migrations applied, or that a dry run would apply.
+    pub migrations: Vec<index::Migration>,
+
+    /// The migrations recorded in the index, oldest first.
+    pub history: Vec<index::IndexMigration>,
+
+    pub duration: Duration,
+}
+
+/// The result of [`Store::fsck_index`].
+#[derive(Clone, Debug, Serialize)]
+pub struct FsckReport {
+    /// Chunks in the index without chunk files.
+    pub missing_chunks: Vec<ChunkId>,
+
+    /// Pages in the index in `missing_chunks`.
+    pub pages_in_missing_chunks: u64,
+
+    pub orphans: index::IndexOrphans,
+
+    /// The rows deleted, if deleting was requested and there was anything to delete. Pages
+    /// in missing chunks are counted in `pages_without_chunk`.
+    pub deleted: Option<index::IndexOrphans>,
+}
+
+impl FsckReport {
+    /// True if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.missing_chunks.is_empty() && self.orphans.total() == 0
+    }
+}
+
+/// What [`Store::maintain`] does. By default it does everything but `vacuum`.
+#[derive(Clone, Debug)]
+pub struct MaintenanceOptions {
+    /// Run SQLite's `integrity_check` on the index, which reads all of it.
+    pub integrity_check: bool,
+
+    /// Update the index's query planner statistics, with `ANALYZE` then `PRAGMA optimize`.
+    pub analyze: bool,
+
+    /// Merge the segments of the full text search index of page titles.
+    pub optimise_fts: bool,
+
+    /// Rebuild the index file to reclaim free space. This needs as much free disk space as
+    /// the index takes, and blocks readers while it runs.
+    pub vacuum: bool,
+
+    /// Check every chunk in the index has a chunk file.
+    pub check_chunks: bool,
+}
+
+/// The result of [`Store::maintain`].
+#[derive(Clone, Debug, Serialize)]
+pub struct MaintenanceReport {
+    /// The steps run, in order.
+    pub steps: Vec<MaintenanceStep>,
+
+    /// Problems found by SQLite's `integrity_check`. Empty if the index is OK or the check
+    /// wasn't run.
+    pub integrity_errors: Vec<String>,
+
+    /// Chunks in the index without chunk files. Empty if they weren't checked.
+    pub missing_chunks: Vec<ChunkId>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MaintenanceStep {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> MaintenanceOptions {
+        MaintenanceOptions {
+            integrity_check: true,
+            analyze: true,
+            optimise_fts: true,
+            vacuum: false,
+            check_chunks: true,
+        }
+    }
+}
+
+impl MaintenanceReport {
+    /// True if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.integrity_errors.is_empty() && self.missing_chunks.is_empty()
+    }
+}
+
+/// The result of [`Store::verify_chunks`].
+#[derive(Clone, Debug, Serialize)]
+pub struct ChunkVerifyReport {
+    pub chunks_len: u64,
+    pub pages_len: u64,
+    pub bytes_len: Bytes,
+
+    /// The threads chunks were verified on.
+    pub threads: usize,
+
+    pub duration: Duration,
+
+    /// The chunks that failed verification, in ascending chunk ID order.
+    pub errors: Vec<ChunkVerifyError>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChunkVerifyError {
+    pub chunk_id: ChunkId,
+    pub error: String,
+}
+
+impl ChunkVerifyReport {
+    /// True if every chunk was verified.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
 }
 
 #[derive(Clone, Debug, Valuable)]
 pub struct ImportChunkResult {
     pub chunk_meta: chunk::ChunkMeta,
     pub duration: Duration,
+    pub index_batch: index::BatchStats,
+
+    /// Pages skipped because they exceeded a parse limit.
+    pub pages_skipped: u64,
+}
+
+/// Tracks progress through one dump file during an import, to record in the index.
+struct ImportFileTracker {
+    path: String,
+
+    /// The seek offset the file was first opened with.
+    seek: Option<u64>,
+
+    /// Pages committed before the file was opened for this import.
+    pages_base: u64,
+
+    /// For uncompressed files, the source file offset reading started from.
+    offset_base: Option<u64>,
+
+    page_end_offset: Arc<AtomicU64>,
+    pages_read: Arc<AtomicU64>,
 }
 
 enum ImportEnd {
     PageLimit,
+    Cancelled,
     Err(Error),
 }
 
@@ -89,72 +378,1116 @@ macro_rules! try_import {
                 return ::std::result::Result::Err(ImportEnd::Err(e.into())),
             ::std::result::Result::Ok(v) => v,
         }
-    }
-}
+    }
+}
+
+/// The most results a store query returns, see [`QueryLimit`].
+pub const MAX_QUERY_LIMIT: u64 = 100;
+
+/// This crate's version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+impl Options {
+    pub fn dump_name(&mut self, dump_name: DumpName) -> &mut Self {
+        self.dump_name = Some(dump_name);
+        self
+    }
+
+    /// The root directory of the store, which holds the store config file.
+    ///
+    /// Chunks and the index are kept in subdirectories of this path unless
+    /// `chunks_path` or `index_path` are set.
+    pub fn path(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// The directory to keep chunks in, defaults to `path/chunks`.
+    ///
+    /// Only used when creating a new store, after which it is read from the store config
+    /// file. If set for an existing store it must match the store config file.
+    pub fn chunks_path(&mut self, chunks_path: impl Into<PathBuf>) -> &mut Self {
+        self.chunks_path = Some(chunks_path.into());
+        self
+    }
+
+    /// The directory to keep the index in, defaults to `path/index`.
+    ///
+    /// Only used when creating a new store, after which it is read from the store config
+    /// file. If set for an existing store it must match the store config file.
+    pub fn index_path(&mut self, index_path: impl Into<PathBuf>) -> &mut Self {
+        self.index_path = Some(index_path.into());
+        self
+    }
+
+    /// Read chunks and the index from a remote store hosted on a static HTTP server,
+    /// e.g. `https://example.com/stores/enwiki`.
+    ///
+    /// The remote index is downloaded to `index_path` on first open and reused after that.
+    /// Chunks are fetched on demand with HTTP range requests and cached under `chunks_path`.
+    /// A store with a remote URL is read-only.
+    ///
+    /// Only used when creating a new store, after which it is read from the store config
+    /// file. If set for an existing store it must match the store config file.
+    pub fn remote_url(&mut self, remote_url: impl Into<String>) -> &mut Self {
+        self.remote_url = Some(remote_url.into());
+        self
+    }
+
+    /// The maximum length in bytes of a chunk file written by an import, defaults to 10 MB.
+    ///
+    /// Each chunk's pages are committed to the index in one transaction, so this also sets
+    /// the size and cadence of index commits during an import.
+    pub fn max_chunk_len(&mut self, max_chunk_len: u64) -> &mut Self {
+        self.max_chunk_len = Some(max_chunk_len);
+        self
+    }
+
+    /// The maximum number of pages in a chunk file written by an import, defaults to
+    /// 100,000. Only used by the [`ChunkSizeStrategy::Pages`] and
+    /// [`ChunkSizeStrategy::First`] strategies.
+    pub fn max_pages_per_chunk(&mut self, max_pages_per_chunk: u64) -> &mut Self {
+        self.max_pages_per_chunk = Some(max_pages_per_chunk);
+        self
+    }
+
+    /// Which limits make a chunk full during an import. Defaults to
+    /// [`ChunkSizeStrategy::First`] if [`Options::max_pages_per_chunk`] is set, otherwise
+    /// [`ChunkSizeStrategy::Bytes`].
+    pub fn chunk_size_strategy(&mut self, strategy: ChunkSizeStrategy) -> &mut Self {
+        self.chunk_size_strategy = Some(strategy);
+        self
+    }
+
+    /// How chunk files are read, defaults to [`ChunkReadMode::Mmap`]. Use
+    /// [`ChunkReadMode::Pread`] for a store on a network filesystem such as NFS or SMB.
+    pub fn chunk_read_mode(&mut self, read_mode: ChunkReadMode) -> &mut Self {
+        self.chunk_read_mode = Some(read_mode);
+        self
+    }
+
+    /// How rows are inserted into the index during an import,
+    /// defaults to [`index::BatchOptions::default()`].
+    pub fn index_batch(&mut self, index_batch: index::BatchOptions) -> &mut Self {
+        self.index_batch = Some(index_batch);
+        self
+    }
+
+    /// Where the store's pages are published on the web, used for links to live pages and
+    /// for interwiki links.
+    ///
+    /// Defaults to the Wikimedia site for the dump name, see
+    /// [`SiteInfo::default_for_dump`]. When set, the site is saved in the store config file
+    /// and used from then on.
+    pub fn site(&mut self, site: SiteInfo) -> &mut Self {
+        self.site = Some(site);
+        self
+    }
+
+    /// How page titles are tokenised for full text search when the store's index is
+    /// created, defaults to [`index::FtsTokenizer::default()`].
+    ///
+    /// An existing index keeps its tokenizer; use [`Store::retokenize_fts`] to change it.
+    pub fn fts_tokenizer(&mut self, tokenizer: index::FtsTokenizer) -> &mut Self {
+        self.fts_tokenizer = Some(tokenizer);
+        self
+    }
+
+    /// Which namespaces' pages imports write to the store, defaults to every namespace. See
+    /// [`NamespaceFilter`].
+    pub fn import_namespaces(&mut self, filter: NamespaceFilter) -> &mut Self {
+        self.import_namespaces = Some(filter);
+        self
+    }
+
+    /// How the index's tables are split between files when the store's index is created,
+    /// defaults to [`index::Layout::Single`]. An existing index keeps its layout.
+    pub fn index_layout(&mut self, layout: index::Layout) -> &mut Self {
+        self.index_layout = Some(layout);
+        self
+    }
+
+    /// Which pending index migrations to apply when the store is opened, defaults to
+    /// [`index::AutoMigrate::Safe`]. A store whose index needs other migrations can't be
+    /// opened until they are applied with [`Options::migrate_index`].
+    pub fn auto_migrate(&mut self, auto_migrate: index::AutoMigrate) -> &mut Self {
+        self.auto_migrate = Some(auto_migrate);
+        self
+    }
+
+    /// Stop imports and exports cleanly when `token` is cancelled, see [`Store::import`],
+    /// [`Store::export_index`], and [`Store::export_pages`].
+    pub fn cancellation(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Send progress events to `events` during imports.
+    pub fn events(&mut self, events: events::Sender) -> &mut Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Send page events to `page_events` when pages are inserted, updated, or deleted, e.g. to
+    /// share one sender between stores. By default each store has its own, see
+    /// [`Store::subscribe_page_events`].
+    pub fn page_events(&mut self, page_events: page_events::Sender) -> &mut Self {
+        self.page_events = Some(page_events);
+        self
+    }
+
+    /// The count of threads imports and chunk verification run on. By default they share
+    /// rayon's global thread pool, which has a thread per CPU.
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// The maximum count of source files or chunk files read at once by imports and chunk
+    /// verification, unlimited by default. Set it low for stores on hard disks, where
+    /// reading many files in parallel is slower than reading a few.
+    pub fn io_concurrency(&mut self, io_concurrency: usize) -> &mut Self {
+        self.io_concurrency = Some(io_concurrency);
+        self
+    }
+
+    /// The most bytes per second imports write to chunk files, unlimited by default. Set it
+    /// so a background import doesn't starve other programs of disk bandwidth.
+    pub fn write_rate_limit(&mut self, bytes_per_sec: u64) -> &mut Self {
+        self.write_rate_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Limits on the store's size, checked by imports before each chunk is written, and by
+    /// [`Store::upsert_page`] and [`Store::upsert_pages`] before writing new pages. Unlimited
+    /// by default.
+    ///
+    /// An import that reaches the quota stops with a [`QuotaExceeded`] error after
+    /// committing the chunks it wrote, so it can be continued once there is room. Upserts
+    /// that reach it return the same error.
+    pub fn quota(&mut self, quota: Quota) -> &mut Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Log index queries that take at least this long at warn level, with their SQL and
+    /// query plan. Disabled by default.
+    pub fn slow_query_threshold(&mut self, threshold: StdDuration) -> &mut Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Check whether this version can open the store, without opening it or changing any
+    /// files.
+    pub fn compatibility(&self) -> Result<metadata::StoreCompatibility> {
+        let path = self.path.as_ref().cloned()
+                       .ok_or_else(|| UninitializedFieldError::new("path"))?;
+
+        let config = config::Config::read(&path)?;
+        let (index_path, remote_url) = match config.as_ref() {
+            Some(config) => (config.index_path.clone(), config.remote_url.clone()),
+            None => (self.index_path.clone()
+                         .unwrap_or_else(|| config::Config::default_for(&path).index_path),
+                     self.remote_url.clone()),
+        };
+        let migration_status = index::migration_status_at(&index_path)?;
+        let index_schema_version = migration_status.as_ref()
+                                                   .map(|status| status.schema_version);
+
+        let format_version = config.as_ref().map(|config| config.format_version);
+        let problem = match (format_version, index_schema_version) {
+            (Some(version), _) if version > config::FORMAT_VERSION => Some(format!(
+                "The store config file has format version {version}, newer than this version \
+                 supports ({supported}).",
+                supported = config::FORMAT_VERSION)),
+            (_, Some(version)) if version > index::SCHEMA_VERSION => Some(format!(
+                "The store index has schema version {version}, newer than this version \
+                 supports ({supported}).",
+                supported = index::SCHEMA_VERSION)),
+            _ => migration_status.as_ref().and_then(
+                |status| self.auto_migrate.unwrap_or_default().problem(&status.pending)),
+        };
+
+        Ok(metadata::StoreCompatibility {
+            schema_version: metadata::SCHEMA_VERSION,
+            path: path.display().to_string(),
+            exists: config.is_some() || index_schema_version.is_some(),
+            remote_url,
+            index_schema_version,
+            supported_index_schema_version: index::SCHEMA_VERSION,
+            can_open: problem.is_none(),
+            migrates_index: index_schema_version
+                .is_some_and(|version| version < index::SCHEMA_VERSION),
+            problem,
+        })
+    }
+
+    /// Apply the pending migrations to the store's index, whichever
+    /// [`auto_migrate`](Self::auto_migrate) allows, without opening the rest of the store.
+    /// With `dry_run`, list them without changing any files.
+    ///
+    /// Migrations are applied in one transaction, so an interrupted migration changes
+    /// nothing, and other processes reading the index, such as a web server, keep reading
+    /// the old schema until it commits. Writers wait for it to finish.
+    #[tracing::instrument(level = "debug", name = "Options::migrate_index()", skip(self))]
+    pub fn migrate_index(&self, dry_run: bool) -> Result<MigrationReport> {
+        let start = Instant::now();
+        let path = self.path.as_ref().cloned()
+                       .ok_or_else(|| UninitializedFieldError::new("path"))?;
+
+        let index_path = match config::Config::read(&path)? {
+            Some(config) => config.index_path,
+            None => self.index_path.clone()
+                        .unwrap_or_else(|| config::Config::default_for(&path).index_path),
+        };
+        let Some(mut status) = index::migration_status_at(&index_path)? else {
+            bail_kind!(User, "No store index to migrate path='{}'", index_path.display());
+        };
+        if status.schema_version > index::SCHEMA_VERSION {
+            bail_kind!(User,
+                       "The store index has schema version {version}, newer than this version \
+                        supports ({supported}). Upgrade wmd to migrate it.",
+                       version = status.schema_version,
+                       supported = index::SCHEMA_VERSION);
+        }
+
+        if !dry_run && !status.pending.is_empty() {
+            tracing::info!(from_version = status.schema_version,
+                           migrations_len = status.pending.len(),
+                           "Migrating store index");
+            let index = index::Options {
+                batch: self.index_batch.clone().unwrap_or_default(),
+                path: index_path.clone(),
+                slow_query_threshold: self.slow_query_threshold,
+                fts_tokenizer: None,
+                auto_migrate: index::AutoMigrate::All,
+                layout: index::Layout::default(),
+            }.build()?;
+            drop(index);
+
+            status.history = index::migration_status_at(&index_path)?
+                .map(|status| status.history)
+                .unwrap_or_default();
+        }
+
+        Ok(MigrationReport {
+            from_version: status.schema_version,
+            to_version: index::SCHEMA_VERSION,
+            dry_run,
+            migrations: status.pending,
+            history: status.history,
+            duration: Duration(start.elapsed()),
+        })
+    }
+
+    /// Open an existing store or create a new one.
+    pub fn build(&self) -> Result<Store> {
+        let path = self.path.as_ref().cloned()
+                       .ok_or_else(|| UninitializedFieldError::new("path"))?;
+        let dump_name = self.dump_name.as_ref().cloned()
+                            .ok_or_else(|| UninitializedFieldError::new("dump_name"))?;
+
+        let max_pages_per_chunk = self.max_pages_per_chunk.unwrap_or(chunk::MAX_PAGES_DEFAULT);
+        if max_pages_per_chunk == 0 || max_pages_per_chunk > chunk::MAX_PAGES_MAX {
+            bail_kind!(User, "The maximum pages per chunk must be from 1 to {max}, was \
+                              {max_pages_per_chunk}.",
+                       max = chunk::MAX_PAGES_MAX);
+        }
+        let chunk_size_strategy = self.chunk_size_strategy.unwrap_or(
+            match self.max_pages_per_chunk {
+                Some(_) => ChunkSizeStrategy::First,
+                None => ChunkSizeStrategy::Bytes,
+            });
+
+        let config = config::Config::resolve(&path,
+                                             &dump_name,
+                                             self.chunks_path.as_deref(),
+                                             self.index_path.as_deref(),
+                                             self.remote_url.as_deref(),
+                                             self.site.as_ref())?;
+
+        let opts = OptionsBuilt {
+            built_from: self.clone(),
+            cancellation: self.cancellation.clone().unwrap_or_default(),
+            chunk_read_mode: self.chunk_read_mode.unwrap_or_default(),
+            chunk_size_strategy,
+            chunks_path: config.chunks_path,
+            dump_name: dump_name.clone(),
+            events: self.events.clone(),
+            import_namespaces: self.import_namespaces.clone().unwrap_or_default(),
+            index_batch: self.index_batch.clone().unwrap_or_default(),
+            index_path: config.index_path,
+            max_chunk_len: self.max_chunk_len.unwrap_or(chunk::MAX_LEN_DEFAULT),
+            max_pages_per_chunk,
+            page_events: self.page_events.clone().unwrap_or_default(),
+            path: path.clone(),
+            quota: self.quota.unwrap_or_default(),
+            scheduler: schedule::Scheduler::new(self.threads, self.io_concurrency,
+                                                self.write_rate_limit)?,
+            site: config.site,
+            slow_query_threshold: self.slow_query_threshold,
+        };
+
+        let mut chunk_store = match config.remote_url {
+            None => ChunkStore::Local(chunk::Options {
+                dump_name: opts.dump_name.clone(),
+                max_chunk_len: opts.max_chunk_len,
+                max_pages_per_chunk: opts.max_pages_per_chunk,
+                path: opts.chunks_path.clone(),
+                read_mode: opts.chunk_read_mode,
+                size_strategy: opts.chunk_size_strategy,
+                store_path: opts.path.clone(),
+            }.build()?),
+            Some(base_url) => {
+                let remote = remote::Options {
+                    base_url,
+                    cache_path: opts.chunks_path.clone(),
+                    dump_name: opts.dump_name.clone(),
+                }.build()?;
+                remote.download_index(&opts.index_path)?;
+                ChunkStore::Remote(remote)
+            },
+        };
+
+        let mut index = index::Options {
+            batch: opts.index_batch.clone(),
+            path: opts.index_path.clone(),
+            slow_query_threshold: opts.slow_query_threshold,
+            fts_tokenizer: self.fts_tokenizer.clone(),
+            auto_migrate: self.auto_migrate.unwrap_or_default(),
+            layout: self.index_layout.unwrap_or_default(),
+        }.build()?;
+
+        if let ChunkStore::Local(chunk_store) = &mut chunk_store {
+            record_fts_tokenize(&opts.path, &index)?;
+
+            if !index.get_pending_chunk_ids()?.is_empty() {
+                // An import stopped part way through committing a chunk. Finish it, unless
+                // another process holds the write lock, e.g. because it is still importing.
+                match chunk_store.try_write_lock() {
+                    Ok(guard) => recover_pending_chunks(&guard, &mut index)?,
+                    Err(e) if e.downcast_ref::<std::io::Error>()
+                               .is_some_and(|e| e.kind() == std::io::ErrorKind::WouldBlock) =>
+                        tracing::debug!("Store is locked, not recovering pending chunks"),
+                    Err(e) => return Err(e),
+                }
+            }
+            fill_chunk_bytes_len(chunk_store, &index)?;
+        }
+
+        Ok(Store {
+            chunk_store,
+            index,
+
+            // This moves opts into Store, so do that last.
+            opts,
+        })
+    }
+}
+
+/// Record the index's full text search tokenizer in the store config file, if it changed.
+fn record_fts_tokenize(store_path: &Path, index: &index::Index) -> Result<()> {
+    let Some(mut config) = config::Config::read(store_path)? else {
+        return Ok(());
+    };
+    let fts_tokenize = index.fts_tokenize()?;
+    if config.fts_tokenize != fts_tokenize {
+        config.fts_tokenize = fts_tokenize;
+        config.write(store_path)?;
+    }
+    Ok(())
+}
+
+/// Commit `pending_chunk`'s file to its chunk path, recording it as pending in the index
+/// first. The caller then commits the chunk's pages to the index with an index batch that
+/// sets the chunk, see `chunk::PendingChunk`.
+fn commit_pending_chunk(index: &index::Index, pending_chunk: chunk::PendingChunk
+) -> Result<ChunkMeta>
+{
+    index.put_pending_chunk(pending_chunk.meta())?;
+    pending_chunk.commit()
+}
+
+/// Recover chunks an import left pending when it stopped, see `chunk::PendingChunk`.
+///
+/// Chunks without pages in the index are rolled back; continuing the import imports their
+/// pages again. Chunks with pages were left by an older version, which committed pages
+/// before renaming chunk files, so they are finished instead. Those whose files are lost are
+/// deleted from the index with their pages, though the import's recorded progress still
+/// includes them, so import those files again in full.
+fn recover_pending_chunks(chunk_write_guard: &chunk::WriteLockGuard, index: &mut index::Index
+) -> Result<()>
+{
+    let mut finish = Vec::new();
+    let mut roll_back = Vec::new();
+    for chunk_id in index.get_pending_chunk_ids()? {
+        match index.pages_len_in_chunks(&[chunk_id])? {
+            0 => roll_back.push(chunk_id),
+            _ => finish.push(chunk_id),
+        }
+    }
+    let recovered = chunk_write_guard.recover_pending(&finish, &roll_back)?;
+    index.set_chunks_committed(&recovered.committed)?;
+    index.delete_chunks(&recovered.rolled_back)?;
+
+    if !recovered.committed.is_empty() || !recovered.rolled_back.is_empty()
+        || recovered.stray_files_removed > 0
+    {
+        tracing::info!(committed = recovered.committed.len(),
+                       rolled_back = recovered.rolled_back.len(),
+                       stray_files_removed = recovered.stray_files_removed,
+                       "Recovered chunks left pending by an interrupted import");
+    }
+
+    if !recovered.lost.is_empty() {
+        tracing::warn!(lost = ?recovered.lost,
+                       "Chunks left pending by an interrupted import have no chunk files. \
+                        Deleting their pages from the index.");
+        index.delete_chunks(&recovered.lost)?;
+        index.delete_orphans()?;
+    }
+
+    Ok(())
+}
+
+/// Remove the chunks in `chunk_ids` that the index has no pages in, from the index and their
+/// files, e.g. after deleting pages. Returns the IDs of the chunks removed.
+pub(crate) fn remove_empty_chunks(chunk_write_guard: &chunk::WriteLockGuard,
+                                  index: &mut index::Index,
+                                  chunk_ids: impl IntoIterator<Item = ChunkId>
+) -> Result<Vec<ChunkId>>
+{
+    let mut empty_chunks = Vec::new();
+    for chunk_id in chunk_ids {
+        if index.pages_len_in_chunks(&[chunk_id])? == 0 {
+            empty_chunks.push(chunk_id);
+        }
+    }
+
+    index.delete_chunks(&empty_chunks)?;
+    chunk_write_guard.remove_chunks(&empty_chunks)?;
+
+    Ok(empty_chunks)
+}
+
+/// Check the store with index `index` has room in `quota` for another chunk of pages written
+/// outside an import, see [`Options::quota`].
+fn check_quota(quota: &Quota, index: &index::Index) -> Result<()> {
+    if quota.is_unlimited() {
+        return Ok(());
+    }
+    quota.check(index.get_chunk_totals()?.bytes_len, index.pages_len()?)
+         .map_err(|e| Error::from(e).context(KindError::new(
+             ErrorKind::User,
+             "Pages not written because the store is full. Raise its quota to write them")))
+}
+
+/// Delete the rows of pages deleted from the index that other tables still have, and the
+/// deleted titles left in the spelling words and merged-away full text search segments.
+pub(crate) fn clean_up_deleted_pages(index: &mut index::Index) -> Result<()> {
+    let orphans = index.delete_orphans()?;
+    tracing::debug!(?orphans, "Deleted rows of deleted pages");
+
+    index.rebuild_spelling()?;
+    index.optimise_fts()?;
+    Ok(())
+}
+
+/// Run one step of [`Store::maintain`], recording how long it took in `steps`.
+fn maintenance_step<T>(steps: &mut Vec<MaintenanceStep>, name: &'static str,
+                       f: impl FnOnce() -> Result<T>
+) -> Result<T>
+{
+    let start = Instant::now();
+    let res = f().with_context(|| format!("in store maintenance step {name}"))?;
+    let duration = Duration(start.elapsed());
+    tracing::info!(step = name, %duration, "Store maintenance step done");
+    steps.push(MaintenanceStep { name, duration });
+    Ok(res)
+}
+
+/// Indexes from before the chunk list recorded chunk lengths are migrated without them, so
+/// read them from the local chunk files once.
+fn fill_chunk_bytes_len(chunk_store: &chunk::Store, index: &index::Index) -> Result<()> {
+    for chunk_id in index.get_chunk_ids_without_bytes_len()? {
+        if let Some(meta) = chunk_store.get_chunk_meta_by_chunk_id(chunk_id)? {
+            index.set_chunk_bytes_len(chunk_id, meta.bytes_len.0)?;
+        }
+    }
+    Ok(())
+}
+
+impl ImportFileTracker {
+    fn progress(&self, complete: bool) -> index::ImportFileProgress {
+        index::ImportFileProgress {
+            path: self.path.clone(),
+            seek: self.seek,
+            pages_committed: self.pages_base + self.pages_read.load(Ordering::SeqCst),
+            source_offset: self.offset_base.map(
+                |base| base + self.page_end_offset.load(Ordering::SeqCst)),
+            complete,
+            updated: Some(chrono::Utc::now()),
+        }
+    }
+
+    /// The count of pages read from the file after its first seek offset, and the offset in
+    /// the file's uncompressed XML just after the last of them, relative to that seek offset.
+    fn position(&self) -> (u64, u64) {
+        let page_end_offset = self.page_end_offset.load(Ordering::SeqCst);
+        (self.pages_base + self.pages_read.load(Ordering::SeqCst),
+         match self.offset_base {
+             // A continued uncompressed file is opened at a later offset than its first seek.
+             Some(base) => base + page_end_offset - self.seek.unwrap_or(0),
+             None => page_end_offset,
+         })
+    }
+}
+
+impl Store {
+    #[tracing::instrument(level = "debug", name = "Store::clear()", skip_all,
+                          fields(self.path = %self.opts.path.display(),
+                                 self.chunks_path = %self.opts.chunks_path.display(),
+                                 self.index_path = %self.opts.index_path.display()))]
+    pub fn clear(&mut self) -> Result<()> {
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot clear a store with remote chunks.");
+        };
+        chunk_store.clear()?;
+        self.index.clear()?;
+
+        Ok(())
+    }
+
+    /// Switch this store to the generation with root directory `generation_path`, a store
+    /// imported in this store's `generations` directory, then reopen it.
+    ///
+    /// The store is reopened with the [`Options`] it was built with, so e.g. its page event
+    /// subscribers keep receiving events. Other readers switch when they next open the store.
+    /// See [`generation`] for details.
+    pub fn publish(&mut self, generation_path: &Path) -> Result<()> {
+        let generations = generation::Generations::new(&self.opts.path);
+        generations.switch(&generations.name_of(generation_path)?)?;
+
+        // Reopen with the options the store was built with, except the chunk and index
+        // paths, which are now the generation's from the store config file.
+        let mut options = self.opts.built_from.clone();
+        options.chunks_path = None;
+        options.index_path = None;
+        *self = options.build()?;
+
+        Ok(())
+    }
+
+    /// Return a [`Cancelled`](wikimedia::ErrorKind::Cancelled) error if the store's
+    /// [`Options::cancellation`] token has been cancelled. `what` describes the work that
+    /// stopped.
+    pub(crate) fn check_cancelled(&self, what: &str) -> Result<()> {
+        cancel::check(&self.opts.cancellation, what)
+    }
+
+    /// Write a compact copy of the store's index to a new file at `path`.
+    ///
+    /// To clone a store, copy its chunk files (e.g. with rsync) and this file, then use
+    /// [`Store::import_index`] in the new store. The file is a sqlite database, so it can
+    /// also be served as the index of a remote store.
+    ///
+    /// If the store's [`Options::cancellation`] token is cancelled, the export stops and the
+    /// partly written file is removed.
+    pub fn export_index(&self, path: &Path) -> Result<()> {
+        self.index.export(path, &self.opts.cancellation)
+    }
+
+    /// Replace the store's index with a copy of an index file written by
+    /// [`Store::export_index`], migrating it if it's from an older version.
+    ///
+    /// The store's chunks should already be copied from the exported store. Returns the IDs
+    /// of chunks in the new index without chunk files, which should be empty.
+    ///
+    /// Takes the store's write lock, and other processes should not have the store open.
+    #[tracing::instrument(level = "debug", name = "Store::import_index()", skip(self),
+                          fields(self.index_path = %self.opts.index_path.display()))]
+    pub fn import_index(&mut self, path: &Path) -> Result<Vec<ChunkId>> {
+        {
+            let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+                bail_kind!(User, "Cannot import an index into a store with remote chunks.");
+
+            };
+            let _chunk_write_guard = chunk_store.try_write_lock()?;
+
+            self.index.replace_from_file(path)?;
+        }
+
+        let ChunkStore::Local(chunk_store) = &self.chunk_store else {
+            unreachable!("chunk_store was matched as local above");
+        };
+        fill_chunk_bytes_len(chunk_store, &self.index)?;
+
+        let mut missing = Vec::new();
+        for chunk_id in self.chunk_id_iter() {
+            let chunk_id = chunk_id?;
+            if !chunk_store.chunk_exists(chunk_id)? {
+                missing.push(chunk_id);
+            }
+        }
+        if !missing.is_empty() {
+            tracing::warn!(missing_len = missing.len(), first_missing = %missing[0],
+                           "Imported index refers to chunks that are not in the store");
+        }
+
+        Ok(missing)
+    }
+
+    /// Rebuild the full text search index of page titles from the page index, and the words
+    /// used for spelling suggestions from that, then optimise it.
+    ///
+    /// Existing indexes are migrated when a store is opened, so this is only needed to
+    /// repair the full text search index.
+    #[tracing::instrument(level = "debug", name = "Store::build_fts()", skip_all,
+                          fields(self.index_path = %self.opts.index_path.display()))]
+    pub fn build_fts(&mut self) -> Result<()> {
+        self.index.rebuild_fts()?;
+        self.index.rebuild_spelling()?;
+        self.index.optimise()?;
+
+        Ok(())
+    }
+
+    /// Recreate the full text search index of page titles with a new tokenizer, then
+    /// optimise it.
+    #[tracing::instrument(level = "debug", name = "Store::retokenize_fts()", skip(self),
+                          fields(self.index_path = %self.opts.index_path.display()))]
+    pub fn retokenize_fts(&mut self, tokenizer: &index::FtsTokenizer) -> Result<()> {
+        self.index.retokenize_fts(tokenizer)?;
+        record_fts_tokenize(&self.opts.path, &self.index)?;
+        self.index.rebuild_spelling()?;
+        self.index.optimise()?;
+
+        Ok(())
+    }
+
+    /// Write `page` to the store, replacing the page with the same MediaWiki ID and all its
+    /// index rows in one transaction, e.g. to correct a bad page without importing its dump
+    /// again. Returns where the page was written.
+    ///
+    /// A replacement is written with the other pages of the replaced copy's chunk to a new
+    /// chunk, and the old chunk file is removed, so the replaced text is not kept on disk.
+    /// This writes the whole chunk again. A new page is written to a new chunk of its own; to
+    /// write many new pages to shared chunks, use [`Store::upsert_pages`].
+    ///
+    /// Takes the store's write lock. Stores with remote chunks can't be changed.
+    #[tracing::instrument(level = "debug", name = "Store::upsert_page()", skip(self, page),
+                          fields(page.id = page.id, page.title = page.title))]
+    pub fn upsert_page(&mut self, page: &dump::Page) -> Result<StorePageId> {
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot write pages to a store with remote chunks.");
+        };
+        let chunk_write_guard = chunk_store.try_write_lock()?;
+        recover_pending_chunks(&chunk_write_guard, &mut self.index)?;
+
+        let previous_store_id = self.index.get_store_page_id_by_mediawiki_id(page.id)?;
+
+        let mut rewritten_store_id = None;
+        if let Some(previous_store_id) = previous_store_id {
+            if let Some(chunk) = chunk_write_guard.map_chunk(previous_store_id.chunk_id)? {
+                let current = compact::current_store_ids(&self.index,
+                                                         previous_store_id.chunk_id, &chunk)?;
+                rewritten_store_id = compact::rewrite_chunk(
+                    &chunk_write_guard, &mut self.index, previous_store_id.chunk_id, &chunk,
+                    &current, Some(page))?;
+            }
+        }
+
+        let store_page_id = match rewritten_store_id {
+            Some(store_page_id) => store_page_id,
+            None => {
+                check_quota(&self.opts.quota, &self.index)?;
+
+                // Commit in phases like an import, see `Store::import_chunk()`.
+                let mut chunk_builder = chunk_write_guard.chunk_builder()?;
+                let store_page_id = chunk_builder.push(page)?;
+                let chunk_meta = commit_pending_chunk(&self.index,
+                                                      chunk_builder.write_pending()?)?;
+
+                let mut index_batch_builder = self.index.import_batch_builder()?;
+                index_batch_builder.replace_page(page.id);
+                index_batch_builder.push(page, store_page_id)?;
+                index_batch_builder.set_chunk(&chunk_meta);
+                index_batch_builder.commit()?;
+                store_page_id
+            },
+        };
+
+        if let Some(previous_store_id) = previous_store_id {
+            compact::compact_chunks(&chunk_write_guard, &mut self.index,
+                                    [previous_store_id.chunk_id])?;
+        }
+
+        let event_page = PageEventPage {
+            mediawiki_id: page.id,
+            store_id: store_page_id,
+            ns_id: page.ns_id,
+            title: page.title.clone(),
+            revision_id: page.revision.as_ref().map(|revision| revision.id),
+        };
+        self.opts.page_events.send(match previous_store_id {
+            Some(previous_store_id) => PageEvent::Updated {
+                page: event_page,
+                previous_store_id,
+            },
+            None => PageEvent::Inserted(event_page),
+        });
+
+        Ok(store_page_id)
+    }
+
+    /// Write `pages` to the store as [`Store::upsert_page`] does, except pages with MediaWiki
+    /// IDs not already in the store are written together to new chunks, filled as in an
+    /// import, rather than each to a chunk of its own. If `pages` has several pages with the
+    /// same MediaWiki ID, the last is written. Returns where each page in `pages` was
+    /// written.
+    ///
+    /// Each new chunk is checked against the store's [`Options::quota`]. If it's reached, the
+    /// chunks already written are kept and a [`QuotaExceeded`] error is returned.
+    ///
+    /// Takes the store's write lock. Stores with remote chunks can't be changed.
+    #[tracing::instrument(level = "debug", name = "Store::upsert_pages()", skip_all,
+                          fields(pages_len = pages.len()))]
+    pub fn upsert_pages(&mut self, pages: &[dump::Page]) -> Result<Vec<StorePageId>> {
+        // The index in `pages` of the page written for each MediaWiki ID.
+        let last_idx = pages.iter().enumerate()
+                            .map(|(idx, page)| (page.id, idx))
+                            .collect::<HashMap<u64, usize>>();
+
+        let mut store_ids = HashMap::<u64, StorePageId>::with_capacity(last_idx.len());
+        let mut new_pages = Vec::<&dump::Page>::new();
+        for (idx, page) in pages.iter().enumerate() {
+            if last_idx[&page.id] != idx {
+                continue;
+            }
+            if self.index.get_store_page_id_by_mediawiki_id(page.id)?.is_some() {
+                store_ids.insert(page.id, self.upsert_page(page)?);
+            } else {
+                new_pages.push(page);
+            }
+        }
+
+        if !new_pages.is_empty() {
+            let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+                bail_kind!(User, "Cannot write pages to a store with remote chunks.");
+            };
+            let chunk_write_guard = chunk_store.try_write_lock()?;
+            recover_pending_chunks(&chunk_write_guard, &mut self.index)?;
+
+            // Commit in phases like an import, see `Store::import_chunk()`.
+            check_quota(&self.opts.quota, &self.index)?;
+            let mut chunk_builder = chunk_write_guard.chunk_builder()?;
+            let mut index_batch_builder = self.index.import_batch_builder()?;
+            let mut chunk_pages = Vec::<(&dump::Page, StorePageId)>::new();
+            let mut written = Vec::<(&dump::Page, StorePageId)>::with_capacity(new_pages.len());
+            for page in new_pages {
+                let store_id = chunk_builder.push(page)?;
+                index_batch_builder.push(page, store_id)?;
+                chunk_pages.push((page, store_id));
+
+                if chunk_builder.is_full() {
+                    compact::commit_chunk(&self.index, chunk_builder, index_batch_builder,
+                                          None)?;
+                    written.append(&mut chunk_pages);
+                    check_quota(&self.opts.quota, &self.index)?;
+                    chunk_builder = chunk_write_guard.chunk_builder()?;
+                    index_batch_builder = self.index.import_batch_builder()?;
+                }
+            }
+            if !chunk_pages.is_empty() {
+                compact::commit_chunk(&self.index, chunk_builder, index_batch_builder, None)?;
+                written.append(&mut chunk_pages);
+            }
+
+            for (page, store_id) in written {
+                store_ids.insert(page.id, store_id);
+                self.opts.page_events.send(PageEvent::Inserted(PageEventPage {
+                    mediawiki_id: page.id,
+                    store_id,
+                    ns_id: page.ns_id,
+                    title: page.title.clone(),
+                    revision_id: page.revision.as_ref().map(|revision| revision.id),
+                }));
+            }
+        }
+
+        Ok(pages.iter().map(|page| store_ids[&page.id]).collect())
+    }
+
+    /// Delete the page stored at `id` from the index with all its rows in one transaction,
+    /// e.g. to remove a bad page without importing its dump again. Returns the page's index
+    /// entry, or `None` if the index doesn't refer to `id`.
+    ///
+    /// The other pages of the page's chunk are then written to a new chunk and the old chunk
+    /// file is removed, so the page's text is not kept on disk, and the deleted page's rows
+    /// are cleaned up as [`Store::apply_deletion_list`] does. To delete many pages, see
+    /// [`Store::apply_deletion_list`], which writes each chunk again once.
+    ///
+    /// Takes the store's write lock. Stores with remote chunks can't be changed.
+    #[tracing::instrument(level = "debug", name = "Store::delete_page()", skip(self),
+                          fields(%id))]
+    pub fn delete_page(&mut self, id: StorePageId) -> Result<Option<index::Page>> {
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot delete pages from a store with remote chunks.");
+        };
+
+        // Chunks are immutable, so the MediaWiki ID read before taking the lock is current.
+        let Some(mapped_page) = chunk_store.get_page_by_store_id(id)? else {
+            return Ok(None);
+        };
+        let mediawiki_id = mapped_page.borrow()?.get_id();
+        drop(mapped_page);
+
+        let chunk_write_guard = chunk_store.try_write_lock()?;
+        let Some(page) = self.index.get_pages_by_mediawiki_ids(&[mediawiki_id])?
+                             .pop()
+                             .filter(|page| page.store_id() == id) else {
+            return Ok(None);
+        };
+        self.index.delete_pages(&[mediawiki_id])?;
+        compact::compact_chunks(&chunk_write_guard, &mut self.index, [id.chunk_id])?;
+        clean_up_deleted_pages(&mut self.index)?;
+
+        self.opts.page_events.send(PageEvent::Deleted {
+            mediawiki_id,
+            store_id: id,
+            slug: page.slug.clone(),
+        });
+
+        Ok(Some(page))
+    }
+
+    /// Check that the index is consistent with itself and the chunk files, reporting rows
+    /// that refer to missing pages or chunks.
+    ///
+    /// If `delete` is true, the pages in missing chunks and all orphan rows are deleted.
+    /// This takes the store's write lock. Stores with remote chunks can only be checked.
+    #[tracing::instrument(level = "debug", name = "Store::fsck_index()", skip(self),
+                          fields(self.index_path = %self.opts.index_path.display()))]
+    pub fn fsck_index(&mut self, delete: bool) -> Result<FsckReport> {
+        let ChunkStore::Local(chunk_store) = &self.chunk_store else {
+            if delete {
+                bail_kind!(User, "Cannot delete from the index of a store with remote chunks.");
+            }
+            return Ok(FsckReport {
+                missing_chunks: Vec::new(),
+                pages_in_missing_chunks: 0,
+                orphans: self.index.find_orphans()?,
+                deleted: None,
+            });
+        };
+
+        let mut missing_chunks = Vec::new();
+        for chunk_id in self.chunk_id_iter() {
+            let chunk_id = chunk_id?;
+            if !chunk_store.chunk_exists(chunk_id)? {
+                missing_chunks.push(chunk_id);
+            }
+        }
 
-pub const MAX_QUERY_LIMIT: u64 = 100;
+        let mut report = FsckReport {
+            pages_in_missing_chunks: self.index.pages_len_in_chunks(&missing_chunks)?,
+            missing_chunks,
+            orphans: self.index.find_orphans()?,
+            deleted: None,
+        };
 
-impl Options {
-    pub fn dump_name(&mut self, dump_name: DumpName) -> &mut Self {
-        self.dump_name = Some(dump_name);
-        self
-    }
+        if delete && !report.is_ok() {
+            let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+                unreachable!("chunk_store was matched as local above");
+            };
+            let _chunk_write_guard = chunk_store.try_write_lock()?;
 
-    pub fn path(&mut self, path: impl Into<PathBuf>) -> &mut Self {
-        self.path = Some(path.into());
-        self
+            let deleted_pages = match self.opts.page_events.has_subscribers() {
+                true => self.index.get_pages_in_chunks(&report.missing_chunks)?,
+                false => Vec::new(),
+            };
+            let pages_deleted = self.index.delete_chunks(&report.missing_chunks)?;
+            for page in deleted_pages {
+                self.opts.page_events.send(PageEvent::Deleted {
+                    mediawiki_id: page.mediawiki_id,
+                    store_id: page.store_id(),
+                    slug: page.slug,
+                });
+            }
+            let mut deleted = self.index.delete_orphans()?;
+            deleted.pages_without_chunk += pages_deleted;
+
+            tracing::info!(deleted = ?deleted, "Deleted orphan rows from the store index");
+            report.deleted = Some(deleted);
+        }
+
+        Ok(report)
     }
 
-    /// Open an existing store or create a new one.
-    pub fn build(&self) -> Result<Store> {
-        let path = self.path.as_ref().cloned()
-                       .ok_or_else(|| UninitializedFieldError::new("path"))?;
-        let dump_name = self.dump_name.as_ref().cloned()
-                            .ok_or_else(|| UninitializedFieldError::new("dump_name"))?;
+    /// Check and optimise the store's index as set in `options`, e.g. on a schedule
+    /// separate from imports, which only optimise the index when they finish.
+    ///
+    /// Takes the store's write lock for the steps that change the index, so they can't run
+    /// during an import. Stores with remote chunks can't be maintained.
+    #[tracing::instrument(level = "debug", name = "Store::maintain()", skip(self),
+                          fields(self.index_path = %self.opts.index_path.display()))]
+    pub fn maintain(&mut self, options: &MaintenanceOptions) -> Result<MaintenanceReport> {
+        let ChunkStore::Local(chunk_store) = &self.chunk_store else {
+            bail_kind!(User, "Cannot maintain a store with remote chunks.");
+        };
 
-        let opts = OptionsBuilt {
-            dump_name: dump_name.clone(),
-            max_chunk_len: self.max_chunk_len.unwrap_or(chunk::MAX_LEN_DEFAULT),
-            path: path.clone(),
+        let mut steps = Vec::new();
+        let integrity_errors = match options.integrity_check {
+            true => maintenance_step(&mut steps, "integrity_check",
+                                     || self.index.integrity_check())?,
+            false => Vec::new(),
+        };
+        let missing_chunks = match options.check_chunks {
+            true => maintenance_step(&mut steps, "check_chunks", || {
+                let mut missing_chunks = Vec::new();
+                for chunk_id in self.chunk_id_iter() {
+                    let chunk_id = chunk_id?;
+                    if !chunk_store.chunk_exists(chunk_id)? {
+                        missing_chunks.push(chunk_id);
+                    }
+                }
+                Ok(missing_chunks)
+            })?,
+            false => Vec::new(),
         };
 
-        let index = index::Options {
-            max_values_per_batch: 100,
-            path: path.join("index"),
-        }.build()?;
+        // The steps above only read, the steps below change the index.
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            unreachable!("chunk_store was matched as local above");
+        };
+        let _chunk_write_guard = chunk_store.try_write_lock()?;
+        let index = &mut self.index;
+        if options.optimise_fts {
+            maintenance_step(&mut steps, "optimise_fts", || index.optimise_fts())?;
+        }
+        if options.vacuum {
+            maintenance_step(&mut steps, "vacuum", || index.vacuum())?;
+        }
+        if options.analyze {
+            maintenance_step(&mut steps, "analyze", || {
+                index.analyze()?;
+                index.pragma_optimize()
+            })?;
+        }
 
-        let chunk_store = chunk::Options {
-            dump_name: opts.dump_name.clone(),
-            max_chunk_len: opts.max_chunk_len,
-            path: path.join("chunks"),
-        }.build()?;
+        Ok(MaintenanceReport {
+            steps,
+            integrity_errors,
+            missing_chunks,
+        })
+    }
 
-        Ok(Store {
-            chunk_store,
-            index,
+    /// Read every page of every chunk in the index, and check the index has no more pages in
+    /// each chunk than the chunk file.
+    ///
+    /// Chunks are verified in parallel on the threads set by [`Options::threads`], reading
+    /// at most [`Options::io_concurrency`] chunks at once. Unlike [`Store::fsck_index`]
+    /// this reads all of each chunk file, so it takes about as long as reading the store.
+    #[tracing::instrument(level = "debug", name = "Store::verify_chunks()", skip(self),
+                          fields(self.index_path = %self.opts.index_path.display()))]
+    pub fn verify_chunks(&self) -> Result<ChunkVerifyReport> {
+        let start = Instant::now();
+        let chunk_ids = self.chunk_id_vec()?;
+        let scheduler = &self.opts.scheduler;
+
+        tracing::info!(chunks_len = chunk_ids.len(), threads = scheduler.threads(),
+                       "Starting chunk verification");
+
+        let results = scheduler.install(|| chunk_ids.into_par_iter()
+            .map(|chunk_id| {
+                let _io_permit = scheduler.io_permit();
+                let res = self.verify_chunk(chunk_id);
+                if let Err(e) = res.as_ref() {
+                    tracing::warn!(%chunk_id, err = format!("{e:#}"), "Chunk verification failed");
+                }
+                (chunk_id, res)
+            })
+            .collect::<Vec<(ChunkId, Result<ChunkMeta>)>>());
+
+        let mut report = ChunkVerifyReport {
+            chunks_len: 0,
+            pages_len: 0,
+            bytes_len: Bytes(0),
+            threads: scheduler.threads(),
+            duration: Duration(StdDuration::ZERO),
+            errors: Vec::new(),
+        };
+        for (chunk_id, res) in results {
+            report.chunks_len += 1;
+            match res {
+                Ok(meta) => {
+                    report.pages_len += meta.pages_len;
+                    report.bytes_len.0 += meta.bytes_len.0;
+                },
+                Err(e) => report.errors.push(ChunkVerifyError {
+                    chunk_id,
+                    error: format!("{e:#}"),
+                }),
+            }
+        }
+        report.duration = Duration(start.elapsed());
 
-            // This moves opts into Store, so do that last.
-            opts,
-        })
+        tracing::info!(chunks_len = report.chunks_len, errors_len = report.errors.len(),
+                       duration = report.duration.as_value(),
+                       "Chunk verification done");
+
+        Ok(report)
     }
-}
 
-impl Store {
-    #[tracing::instrument(level = "debug", name = "Store::clear()", skip_all,
-                          fields(self.path = %self.opts.path.display()))]
-    pub fn clear(&mut self) -> Result<()> {
-        self.chunk_store.clear()?;
-        self.index.clear()?;
+    fn verify_chunk(&self, chunk_id: ChunkId) -> Result<ChunkMeta> {
+        let chunk = self.map_chunk(chunk_id)?
+                        .ok_or_else(|| format_err!("Chunk file not found"))?;
+        let meta = chunk.meta()?;
 
-        Ok(())
+        let mut pages_len = 0_u64;
+        for (store_id, page_cap) in chunk.pages_iter()? {
+            dump::Page::try_from(&page_cap)
+                .with_context(|| format!("reading page store_id={store_id}"))?;
+            pages_len += 1;
+        }
+
+        // Pages whose MediaWiki ID was already in the index are kept in the chunk but not
+        // indexed, so the index may have fewer pages in the chunk but never more.
+        let index_pages_len = self.index.pages_len_in_chunks(&[chunk_id])?;
+        if index_pages_len > pages_len {
+            bail!("Chunk has {pages_len} pages but the index has {index_pages_len} in it");
+        }
+
+        Ok(meta)
     }
 
-    pub fn import(&mut self, job_files: JobFiles) -> Result<ImportResult> {
+    /// Import pages from `job_files` into the store.
+    ///
+    /// Progress through each file is recorded in the index as each chunk is committed.
+    /// If `continue_import` is true, files completed by a previous import are skipped and
+    /// partially imported files are continued after the last committed page.
+    ///
+    /// If the store's [`Options::cancellation`] token is cancelled, each import thread stops
+    /// after committing its current chunk, and the result has `cancelled` set. The spelling
+    /// index is not rebuilt until an import completes.
+    pub fn import(&mut self, job_files: JobFiles, continue_import: bool
+    ) -> Result<ImportResult> {
         let start = Instant::now();
 
-        let chunk_write_guard = self.chunk_store.try_write_lock()?;
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot import into a store with remote chunks.");
+        };
+        let chunk_write_guard = chunk_store.try_write_lock()?;
+        recover_pending_chunks(&chunk_write_guard, &mut self.index)?;
+
+        let index = &self.index;
+        let import_run_id = index.start_import_run()?;
 
-        let files = job_files.open_files_par_iter()?;
+        let files = job_files.file_specs().to_vec()
+            .into_par_iter()
+            .with_max_len(1) // Each thread processes one file at a time
+            .map(|spec: FileSpec| (spec.path.clone(),
+                                   Self::open_import_file(index, spec, continue_import)));
         let total_source_bytes = job_files.files_total_len();
         let num_source_files = job_files.file_specs().len();
 
@@ -164,12 +1497,33 @@ impl Store {
             open_spec = job_files.open_spec().as_value(),
             "Starting import");
 
-        let index = &self.index;
+        let send_event = |event: Event| if let Some(events) = self.opts.events.as_ref() {
+            events.send(event);
+        };
+        send_event(Event::ImportStarted {
+            dump: self.opts.dump_name.0.clone(),
+            source_files_len: num_source_files,
+            source_bytes: total_source_bytes.0,
+        });
+
+        // The store's size before this import, to check the quota against.
+        let (quota_base_bytes, quota_base_pages) = match self.opts.quota.is_unlimited() {
+            true => (0, 0),
+            false => (index.get_chunk_totals()?.bytes_len, index.pages_len()?),
+        };
 
         let chunk_bytes_total = AtomicU64::new(0);
         let chunks_len = AtomicU64::new(0);
+        let index_commit_nanos = AtomicU64::new(0);
+        let index_values_total = AtomicU64::new(0);
         let pages_total = AtomicU64::new(0);
+        let pages_skipped_total = AtomicU64::new(0);
+        let pages_excluded_total = AtomicU64::new(0);
+        let write_throttled_nanos = AtomicU64::new(0);
+        let file_results = Mutex::new(Vec::<ImportFileResult>::new());
         let total_source_bytes_read = AtomicU64::new(0);
+        let progress_tracker = Mutex::new(progress::Tracker::new_at(
+            start, Some(total_source_bytes.0)));
 
         const PROGRESS_INTERVAL_SECS: i64 = 2;
         assert!(PROGRESS_INTERVAL_SECS > 0);
@@ -178,23 +1532,82 @@ impl Store {
             chrono::Utc::now().timestamp()
              + PROGRESS_INTERVAL_SECS);
 
-        let end = files.try_for_each(
-            |file: Result<OpenJobFile>| -> StdResult<(), ImportEnd> {
+        let scheduler = &self.opts.scheduler;
+        let end = scheduler.install(|| files.try_for_each(
+            |(spec_path, file): (PathBuf, Result<Option<(OpenJobFile, ImportFileTracker)>>)|
+             -> StdResult<(), ImportEnd>
+            {
+                let _io_permit = scheduler.io_permit();
+                if self.opts.cancellation.is_cancelled() {
+                    return Err(ImportEnd::Cancelled);
+                }
+                let file_start = Instant::now();
+                let Some((file, tracker)) = try_import!(file) else {
+                    // File was already imported.
+                    let path = std::fs::canonicalize(&spec_path).unwrap_or(spec_path);
+                    file_results.lock().expect("file_results lock").push(ImportFileResult {
+                        path: path.to_string_lossy().into_owned(),
+                        already_imported: true,
+                        pages: 0,
+                        pages_skipped: 0,
+                        pages_excluded: 0,
+                        chunks_len: 0,
+                        chunk_bytes: Bytes(0),
+                        duration: Duration(file_start.elapsed()),
+                    });
+                    return Ok(());
+                };
+                let mut file_result = ImportFileResult {
+                    path: tracker.path.clone(),
+                    already_imported: false,
+                    pages: 0,
+                    pages_skipped: 0,
+                    pages_excluded: 0,
+                    chunks_len: 0,
+                    chunk_bytes: Bytes(0),
+                    duration: Duration(StdDuration::ZERO),
+                };
                 let OpenJobFile {
                     file_spec,
                     pages_iter,
                     source_bytes_read,
                     uncompressed_bytes_read,
-                } = try_import!(file);
-
-                let mut pages = pages_iter.peekable();
+                    ..
+                } = file;
+
+                // Filtered before `peekable()`, so a chunk is only started when an included
+                // page is left.
+                let namespaces = &self.opts.import_namespaces;
+                let pages_excluded = Cell::new(0_u64);
+                let mut pages = pages_iter
+                    .filter(|page| match page {
+                        Ok(page) if !namespaces.allows(page.ns_id) => {
+                            pages_excluded.set(pages_excluded.get() + 1);
+                            false
+                        },
+                        _ => true,
+                    })
+                    .peekable();
+
+                // Read before `pages.peek()` reads the first page of the next chunk.
+                let mut chunk_start = tracker.position();
 
                 while pages.peek().is_some() {
+                    if self.opts.cancellation.is_cancelled() {
+                        return Err(ImportEnd::Cancelled);
+                    }
                     if let Some(limit) = job_files.open_spec().limit.as_ref().copied() {
                         if pages_total.load(Ordering::SeqCst) > limit {
                             return Err(ImportEnd::PageLimit);
                         }
                     }
+                    try_import!(self.opts.quota.check(
+                        quota_base_bytes + chunk_bytes_total.load(Ordering::SeqCst),
+                        quota_base_pages + pages_total.load(Ordering::SeqCst))
+                        .map_err(|e| Error::from(e).context(KindError::new(
+                            ErrorKind::User,
+                            "Import stopped because the store is full. Raise its quota and \
+                             continue the import to import the rest"))));
 
                     let source_bytes_read_before = source_bytes_read.load(Ordering::SeqCst);
 
@@ -202,8 +1615,9 @@ impl Store {
                     let index_batch_builder = try_import!(index.import_batch_builder());
 
                     let res = try_import!(
-                        Self::import_chunk(&file_spec, &mut pages, chunk_builder,
-                                           index_batch_builder)
+                        Self::import_chunk(&tracker, &mut pages, chunk_builder, index,
+                                           index_batch_builder, import_run_id, chunk_start,
+                                           &self.opts.page_events)
                             .with_context(||
                                 format!("While importing a chunk from file {file_spec:?} \
                                          source_bytes_read={source_bytes_read:?} \
@@ -213,6 +1627,12 @@ impl Store {
                                         uncompressed_bytes_read =
                                             Bytes(uncompressed_bytes_read.load(
                                                 Ordering::SeqCst)))));
+                    chunk_start = tracker.position();
+
+                    let throttled = scheduler.throttle_write(res.chunk_meta.bytes_len.0);
+                    write_throttled_nanos.fetch_add(
+                        u64::try_from(throttled.as_nanos()).unwrap_or(u64::MAX),
+                        Ordering::SeqCst);
 
                     // fetch_add counters.
                     let chunk_bytes_total_curr =
@@ -220,6 +1640,21 @@ impl Store {
                     let pages_total_curr = pages_total.fetch_add(res.chunk_meta.pages_len,
                                                                  Ordering::SeqCst);
                     let chunks_len_curr = chunks_len.fetch_add(1, Ordering::SeqCst);
+                    pages_skipped_total.fetch_add(res.pages_skipped, Ordering::SeqCst);
+                    let chunk_pages_excluded = pages_excluded.take();
+                    pages_excluded_total.fetch_add(chunk_pages_excluded, Ordering::SeqCst);
+                    file_result.pages += res.chunk_meta.pages_len;
+                    file_result.pages_skipped += res.pages_skipped;
+                    file_result.pages_excluded += chunk_pages_excluded;
+                    file_result.chunks_len += 1;
+                    file_result.chunk_bytes.0 += res.chunk_meta.bytes_len.0;
+                    index_commit_nanos.fetch_add(
+                        u64::try_from(res.index_batch.commit_duration.0.as_nanos())
+                            .unwrap_or(u64::MAX),
+                        Ordering::SeqCst);
+                    index_values_total.fetch_add(
+                        u64::try_from(res.index_batch.values_len).unwrap_or(u64::MAX),
+                        Ordering::SeqCst);
                     let source_bytes_read_after = source_bytes_read.load(Ordering::SeqCst);
                     let source_bytes_read_diff =
                         source_bytes_read_after - source_bytes_read_before;
@@ -227,6 +1662,15 @@ impl Store {
                         total_source_bytes_read.fetch_add(source_bytes_read_diff,
                                                           Ordering::SeqCst);
 
+                    send_event(Event::ChunkCommitted {
+                        dump: self.opts.dump_name.0.clone(),
+                        chunk_id: res.chunk_meta.id.0,
+                        pages_len: res.chunk_meta.pages_len,
+                        pages_total: pages_total_curr + res.chunk_meta.pages_len,
+                        source_bytes_read: total_source_bytes_read_curr + source_bytes_read_diff,
+                        source_bytes: total_source_bytes.0,
+                    });
+
                     let now = chrono::Utc::now();
                     let now_ts = now.timestamp();
                     let curr_next_progress_ts = next_progress_ts.load(Ordering::SeqCst);
@@ -250,23 +1694,32 @@ impl Store {
                             // We succeded in the update, so we are
                             // the thread to print the current
                             // progress.
-                            try_import!(Self::print_import_progress(start,
+                            try_import!(Self::print_import_progress(&progress_tracker,
                                                                     &file_spec,
                                                                     chunk_bytes_total_curr,
                                                                     pages_total_curr,
                                                                     chunks_len_curr,
-                                                                    total_source_bytes.0,
                                                                     total_source_bytes_read_curr,
                                                                     source_bytes_read_diff));
                         }
                     } // End check whether we should print progress.
                 }; // Loop while there are more pages in the import file.
 
+                // Pages excluded after the file's last chunk.
+                let chunk_pages_excluded = pages_excluded.take();
+                pages_excluded_total.fetch_add(chunk_pages_excluded, Ordering::SeqCst);
+                file_result.pages_excluded += chunk_pages_excluded;
+
+                try_import!(index.put_import_file_progress(&tracker.progress(/* complete: */ true)));
+
+                file_result.duration = Duration(file_start.elapsed());
+                file_results.lock().expect("file_results lock").push(file_result);
+
                 tracing::debug!(input_file = %file_spec.path.display(),
                                 "Finished importing from file");
 
                 Ok(())
-            }); // parallel for each over all files.
+            })); // parallel for each over all files.
 
         // Log stats before checking `end` for an Error.
         let chunk_bytes_total = Bytes(chunk_bytes_total.into_inner());
@@ -277,33 +1730,108 @@ impl Store {
             chunk_write_rate: ByteRate::new(chunk_bytes_total, duration.0),
             chunks_len: chunks_len.into_inner(),
             duration,
+            index_commit_duration: Duration(
+                StdDuration::from_nanos(index_commit_nanos.into_inner())),
+            index_values_total: index_values_total.into_inner(),
+            import_run_id,
             pages_total: pages_total.into_inner(),
+            pages_skipped_total: pages_skipped_total.into_inner(),
+            pages_excluded_total: pages_excluded_total.into_inner(),
+            threads: scheduler.threads(),
+            write_rate_limit: scheduler.write_rate_limit().map(|limit| ByteRate(limit as f64)),
+            write_throttled_duration: Duration(
+                StdDuration::from_nanos(write_throttled_nanos.into_inner())),
+            cancelled: matches!(end, Err(ImportEnd::Cancelled)),
+            files: {
+                let mut files = file_results.into_inner().expect("file_results lock");
+                files.sort_by(|a, b| a.path.cmp(&b.path));
+                files
+            },
         };
 
         tracing::info!(res = res.as_value(),
                        "Import done");
 
+        send_event(Event::ImportFinished {
+            dump: self.opts.dump_name.0.clone(),
+            pages_total: res.pages_total,
+            duration_secs: res.duration.0.as_secs_f64(),
+            error: match &end {
+                Err(ImportEnd::Err(e)) => Some(format!("{e:#}")),
+                Err(ImportEnd::Cancelled) => Some("Import cancelled".to_string()),
+                _ => None,
+            },
+        });
+
         if let Err(ImportEnd::Err(e)) = end {
             return Err(e);
         }
+        if res.cancelled {
+            // The import run is left unfinished, like one that failed.
+            tracing::warn!(chunks_len = res.chunks_len,
+                           "Import cancelled after committing its current chunks");
+            return Ok(res);
+        }
+        self.index.finish_import_run(import_run_id)?;
 
+        self.index.rebuild_spelling()?;
         self.index.optimise()?;
 
         Ok(res)
     }
 
     fn import_chunk<'lock, 'index>(
-        _file_spec: &FileSpec,
+        tracker: &ImportFileTracker,
         pages: &mut dyn Iterator<Item = Result<dump::Page>>,
         mut chunk_builder: chunk::Builder<'lock>,
+        index: &'index index::Index,
         mut index_batch_builder: index::ImportBatchBuilder<'index>,
+        import_run_id: u64,
+        (file_pages_start, xml_offset_start): (u64, u64),
+        page_events: &page_events::Sender,
     ) -> Result<ImportChunkResult> {
         let start = Instant::now();
+        let mut pages_skipped = 0;
+
+        // Sent once the chunk is committed, if anything is subscribed.
+        let send_page_events = page_events.has_subscribers();
+        let mut chunk_page_events = Vec::<PageEvent>::new();
 
         for page in pages {
-            let page: dump::Page = page?;
+            let page: dump::Page = match page {
+                Ok(page) => page,
+                Err(e) if e.downcast_ref::<LimitExceeded>()
+                           .is_some_and(LimitExceeded::is_page_skipped) => {
+                    let xml_range = e.downcast_ref::<DumpParseError>()
+                                     .map(|context| context.xml_start..context.xml_end);
+                    tracing::warn!(err = format!("{e:#}"),
+                                   path = %tracker.path,
+                                   ?xml_range,
+                                   "Skipped a dump page that exceeded a parse limit");
+                    pages_skipped += 1;
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
 
             let store_page_id = chunk_builder.push(&page)?;
+            if send_page_events {
+                let event_page = PageEventPage {
+                    mediawiki_id: page.id,
+                    store_id: store_page_id,
+                    ns_id: page.ns_id,
+                    title: page.title.clone(),
+                    revision_id: page.revision.as_ref().map(|revision| revision.id),
+                };
+                chunk_page_events.push(
+                    match index.get_store_page_id_by_mediawiki_id(page.id)? {
+                        Some(previous_store_id) => PageEvent::Updated {
+                            page: event_page,
+                            previous_store_id,
+                        },
+                        None => PageEvent::Inserted(event_page),
+                    });
+            }
             index_batch_builder.push(&page, store_page_id)?;
 
             if chunk_builder.is_full() {
@@ -311,24 +1839,118 @@ impl Store {
             }
         }
 
-        let chunk_meta = chunk_builder.write_all()?;
-        index_batch_builder.commit()?;
+        // Commit in phases, so a crash at any point leaves a store that
+        // `recover_pending_chunks()` can roll back. See `chunk::PendingChunk`.
+        let chunk_meta = commit_pending_chunk(index, chunk_builder.write_pending()?)?;
+        index_batch_builder.set_chunk(&chunk_meta);
+        let (file_pages_end, xml_offset_end) = tracker.position();
+        index_batch_builder.set_chunk_provenance(index::ChunkProvenance {
+            chunk_id: chunk_meta.id,
+            import_run_id,
+            source_path: tracker.path.clone(),
+            source_seek: tracker.seek,
+            file_pages_start,
+            file_pages_end,
+            xml_offset_start,
+            xml_offset_end,
+        });
+        index_batch_builder.set_import_file_progress(tracker.progress(/* complete: */ false));
+        let index_batch = index_batch_builder.commit()?;
+
+        for event in chunk_page_events {
+            page_events.send(event);
+        }
 
         let res = ImportChunkResult {
             chunk_meta,
             duration: Duration(start.elapsed()),
+            index_batch,
+            pages_skipped,
         };
 
+        tracing::debug!(res = res.as_value(), "Imported chunk");
+
         Ok(res)
     }
 
+    /// Open a dump file to import, continuing from the progress recorded in the index
+    /// if `continue_import` is true.
+    ///
+    /// Returns `Ok(None)` if the file was already completely imported.
+    fn open_import_file(
+        index: &index::Index,
+        mut spec: FileSpec,
+        continue_import: bool,
+    ) -> Result<Option<(OpenJobFile, ImportFileTracker)>> {
+        let path = std::fs::canonicalize(&spec.path)
+                       .with_context(|| format!("While canonicalizing import file path '{path}'",
+                                                path = spec.path.display()))?
+                       .to_string_lossy()
+                       .into_owned();
+
+        let prev = match continue_import {
+            true => index.get_import_file_progress(&path)?,
+            false => None,
+        };
+
+        let seek = spec.seek;
+        let mut pages_base = 0;
+        let mut skip_pages = 0;
+
+        if let Some(prev) = prev.as_ref() {
+            if prev.complete {
+                tracing::info!(path, pages = prev.pages_committed,
+                               "Skipping file already imported");
+                return Ok(None);
+            }
+
+            // Source offsets are only recorded for uncompressed files.
+            match prev.source_offset {
+                Some(offset) => {
+                    spec.seek = Some(offset);
+                    pages_base = prev.pages_committed;
+                },
+                None => {
+                    // Compressed files can't be seeked to an arbitrary page, so re-read from
+                    // the original offset and skip the pages already committed.
+                    spec.seek = prev.seek;
+                    skip_pages = prev.pages_committed;
+                },
+            }
+
+            tracing::info!(path, pages_committed = prev.pages_committed,
+                           seek = spec.seek, skip_pages,
+                           "Continuing import of partially imported file");
+        }
+
+        let mut file = spec.open()?;
+
+        for page in file.pages_iter.by_ref().take(skip_pages.try_into().expect("usize from u64")) {
+            let _ = page?;
+        }
+
+        let tracker = ImportFileTracker {
+            path,
+            seek: prev.as_ref().map_or(seek, |prev| prev.seek),
+            pages_base,
+            // `file.file_spec` has the detected compression if `spec` had `Compression::Auto`.
+            offset_base: match file.file_spec.compression {
+                Compression::None => Some(spec.seek.unwrap_or(0)),
+                _ => None,
+            },
+            page_end_offset: file.page_end_offset.clone(),
+            pages_read: file.pages_read.clone(),
+        };
+
+        Ok(Some((file, tracker)))
+    }
+
     fn print_import_progress(
-        start: Instant,
+        progress_tracker: &Mutex<progress::Tracker>,
         file_spec: &FileSpec,
         chunk_bytes_total_curr: u64,
         pages_total_curr: u64,
         chunks_len_curr: u64,
-        total_source_bytes: u64,
         total_source_bytes_read_curr: u64,
         source_bytes_read_diff: u64,
      ) -> Result<()> {
@@ -336,41 +1958,16 @@ impl Store {
         let now = chrono::Local::now();
 
         // Calculate derived stats.
-        let percent_complete =
-            ((total_source_bytes_read_curr as f64) /
-             (total_source_bytes as f64)) * 100.0;
-
-        let duration_so_far = start.elapsed();
+        let progress = progress_tracker.lock()
+            .map_err(|_e| format_err!("PoisonError locking import progress tracker"))?
+            .update(total_source_bytes_read_curr);
+        let total_source_bytes = progress.total.unwrap_or(0);
         let total_source_bytes_remaining =
-            total_source_bytes - total_source_bytes_read_curr;
-
-        let est_remaining_duration: Option<Duration> =
-            match total_source_bytes_read_curr {
-                0 => None,
-                bytes_done => {
-                    let secs: f64 =
-                        (duration_so_far.as_secs_f64() / (bytes_done as f64))
-                        * (total_source_bytes_remaining as f64);
-
-                    let nanos = secs * 1_000_000_000.0;
-                    let dur = std::time::Duration::from_nanos(nanos as u64);
-                    Some(Duration(dur))
-                }
-            };
-
-        let eta: Option<String> = est_remaining_duration.and_then(
-            |dur| -> Option<String> {
-                let chrono_time =
-                    now
-                    + match chrono::Duration::from_std(dur.0) {
-                        Ok(chrono_dur) => chrono_dur,
-                        Err(_e) => return None,
-                    };
-                let s = fmt::chrono_time(chrono_time);
-                Some(s)
-            });
-
-        let percent_complete_str = format!("{percent_complete:3.1}%");
+            total_source_bytes.saturating_sub(total_source_bytes_read_curr);
+        let est_remaining_duration: Option<Duration> = progress.remaining.map(Duration);
+        let eta: Option<String> = progress.eta(now).map(fmt::chrono_time);
+        let percent_complete = progress.fraction.unwrap_or(0.0) * 100.0;
+        let percent_complete_str = progress.percent_str().unwrap_or_default();
 
         writeln!(std::io::stdout(),
                  "{now}     Import: \
@@ -398,7 +1995,8 @@ impl Store {
             total_source_bytes = total_source_bytes.as_value(),
             total_source_bytes_remaining =
                 Bytes(total_source_bytes_remaining).as_value(),
-            duration_so_far = Duration(duration_so_far).as_value(),
+            duration_so_far = Duration(progress.elapsed).as_value(),
+            source_byte_rate = progress.rate.map(ByteRate).as_value(),
 
             // Import total derived stats
             percent_complete,
@@ -415,28 +2013,204 @@ impl Store {
         Ok(())
     }
 
-    pub fn get_category(&self, slug_lower_bound: Option<&CategorySlug>, limit: Option<u64>
-    ) -> Result<Vec<dump::CategorySlug>>
+    /// Receive events for the pages inserted, updated, or deleted from now on, see
+    /// [`page_events`].
+    pub fn subscribe_page_events(&self) -> page_events::Receiver<PageEvent> {
+        self.opts.page_events.subscribe()
+    }
+
+    /// Where the store's pages are published on the web, or `None` if not known.
+    pub fn site(&self) -> Option<SiteInfo> {
+        self.opts.site.clone().or_else(|| SiteInfo::default_for_dump(&self.opts.dump_name))
+    }
+
+    pub fn get_category(&self, slug_lower_bound: Option<&CategorySlug>, limit: QueryLimit
+    ) -> Result<Vec<index::Category>>
     {
         self.index.get_category(slug_lower_bound, limit)
     }
 
+    /// Get a category by slug. To look a category up by name, convert the name with
+    /// `CategoryName::to_slug()` or parse either form with `CategorySlug::new()`.
+    pub fn get_category_by_slug(&self, slug: &CategorySlug) -> Result<Option<index::Category>> {
+        self.index.get_category_by_slug(slug)
+    }
+
+    /// Get the pages in a category, ordered by their sort keys in the category like
+    /// MediaWiki's category listings. If `namespace` is set, only pages in the namespace
+    /// with that key are returned, e.g. 0 for articles without their talk pages.
+    ///
+    /// To get the next pages, pass the MediaWiki ID of the last page returned as
+    /// `page_mediawiki_id_lower_bound`.
     pub fn get_category_pages(
         &self,
         slug: &CategorySlug,
         page_mediawiki_id_lower_bound: Option<u64>,
-        limit: Option<u64>,
+        namespace: Option<i64>,
+        limit: QueryLimit,
+    ) -> Result<Vec<index::Page>>
+    {
+        self.index.get_category_pages(slug, page_mediawiki_id_lower_bound, namespace, limit)
+    }
+
+    /// Get the pages in the namespace with key `ns_id`, ordered by slug like MediaWiki's
+    /// `Special:AllPages`, e.g. 14 for every category page.
+    ///
+    /// To get the next pages, pass the slug of the last page returned as `slug_lower_bound`.
+    pub fn get_pages_by_namespace(
+        &self,
+        ns_id: i64,
+        slug_lower_bound: Option<&str>,
+        limit: QueryLimit,
+    ) -> Result<Vec<index::Page>>
+    {
+        self.index.get_pages_by_namespace(ns_id, slug_lower_bound, limit)
+    }
+
+    /// Get pages whose latest revision was saved after `since`, ordered by revision
+    /// timestamp then MediaWiki ID, e.g. for a search indexer to sync changes from the store.
+    ///
+    /// Returns at most `limit` pages. Pass the result's `next_cursor` back as `cursor` to get
+    /// the pages after them. Pages imported before revision timestamps were indexed are not
+    /// included.
+    pub fn pages_modified_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        cursor: Option<index::ChangesCursor>,
+        limit: QueryLimit,
+    ) -> Result<index::PageChanges>
+    {
+        self.index.pages_modified_since(since, cursor, limit)
+    }
+
+    /// Full text search of page titles.
+    pub fn page_search(&self, query: &str, options: &index::PageSearchOptions
+    ) -> Result<Vec<index::Page>> {
+        self.index.page_search(query, options)
+    }
+
+    /// Count the pages matching a page search by namespace, and in the `categories_limit`
+    /// categories with the most matches, e.g. to offer filters that refine the search. See
+    /// [`index::PageSearchFacets`].
+    pub fn page_search_facets(&self, query: &str, options: &index::PageSearchOptions,
+                              categories_limit: u64
+    ) -> Result<index::PageSearchFacets> {
+        self.index.page_search_facets(query, options, categories_limit)
+    }
+
+    /// Find up to `limit` articles related to the page with MediaWiki ID `mediawiki_id` by
+    /// their shared categories and links, most related first. See [`index::RelatedPage`].
+    pub fn related_pages(&self, mediawiki_id: u64, limit: u64) -> Result<Vec<index::RelatedPage>> {
+        self.index.related_pages(mediawiki_id, limit)
+    }
+
+    /// Get up to `limit` articles chosen at random, not including redirects, e.g. for a
+    /// default main page.
+    pub fn random_articles(&self, limit: u64) -> Result<Vec<index::Page>> {
+        self.index.random_articles(limit)
+    }
+
+    /// Get up to `limit` categories with the most pages, most first. This counts every
+    /// category link, so it is slow for large stores.
+    pub fn top_categories(&self, limit: QueryLimit) -> Result<Vec<index::CategoryFacet>> {
+        self.index.top_categories(limit)
+    }
+
+    /// Find the groups of pages whose slugs are equal ignoring case, which can't all be
+    /// found by slug. See [`index::SlugCollision`].
+    pub fn slug_collisions(&self) -> Result<Vec<index::SlugCollision>> {
+        self.index.slug_collisions()
+    }
+
+    /// Find up to `limit` titles that pages in the store link to but that aren't in the
+    /// store, with the most linked first. See [`index::RedLink`].
+    pub fn red_links(&self, limit: u64) -> Result<Vec<index::RedLink>> {
+        self.index.red_links(limit)
+    }
+
+    /// Get the slugs of the templates and modules a page transcludes, e.g.
+    /// `Template:Infobox_person`, sorted. See [`wikimedia::wikitext::parse_templates`].
+    pub fn get_page_templates(&self, mediawiki_id: u64) -> Result<Vec<String>> {
+        self.index.get_page_templates(mediawiki_id)
+    }
+
+    /// Get the pages that transclude a template or module, ordered by MediaWiki ID.
+    ///
+    /// If `indirect` is true, pages that transclude it through other templates in the store
+    /// are returned too, e.g. to find every page affected by a change to a template. To get
+    /// the next pages, pass the MediaWiki ID of the last page returned as
+    /// `page_mediawiki_id_lower_bound`.
+    pub fn get_template_pages(
+        &self,
+        template_slug: &str,
+        indirect: bool,
+        page_mediawiki_id_lower_bound: Option<u64>,
+        limit: QueryLimit,
     ) -> Result<Vec<index::Page>>
     {
-        self.index.get_category_pages(slug, page_mediawiki_id_lower_bound, limit)
+        self.index.get_template_pages(template_slug, indirect, page_mediawiki_id_lower_bound,
+                                      limit)
+    }
+
+    /// Choose a page for lookups of the slugs of each slug collision, see
+    /// [`index::SlugCollision::preferred`], replacing any chosen before. Returns the
+    /// collisions with their new disambiguations.
+    ///
+    /// This takes the store's write lock. Stores with remote chunks can't be changed.
+    pub fn write_slug_disambiguations(&mut self) -> Result<Vec<index::SlugCollision>> {
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot change the index of a store with remote chunks.");
+        };
+        let _chunk_write_guard = chunk_store.try_write_lock()?;
+
+        let mut collisions = self.index.slug_collisions()?;
+        let mut disambiguations = Vec::<(String, u64)>::new();
+        for collision in collisions.iter_mut() {
+            collision.disambiguation = collision.preferred().map(|page| page.mediawiki_id);
+            if let Some(mediawiki_id) = collision.disambiguation {
+                disambiguations.push((collision.pages[0].slug.clone(), mediawiki_id));
+            }
+        }
+        self.index.write_slug_disambiguations(&disambiguations)?;
+
+        tracing::info!(disambiguations = disambiguations.len(),
+                       "Wrote slug disambiguations to the store index");
+        Ok(collisions)
+    }
+
+    /// Suggest a respelling of a page search query with words from page titles, e.g. to
+    /// show when a search has few results. Returns `None` if there's nothing to correct.
+    ///
+    /// Suggestions use words from when the store was last imported or its full text search
+    /// index was built.
+    pub fn suggest(&self, query: &str) -> Result<Option<String>> {
+        self.index.suggest(query)
+    }
+
+    /// Full text search of the titles of pages in a category. For more search options set
+    /// `PageSearchOptions::category` and use [`Store::page_search`].
+    pub fn search_in_category(&self, category_slug: &CategorySlug, query: &str,
+                              namespace: Option<i64>, limit: QueryLimit
+    ) -> Result<Vec<index::Page>> {
+        self.index.page_search(query, &index::PageSearchOptions {
+            limit,
+            category: Some(category_slug.clone()),
+            namespace,
+            ..Default::default()
+        })
     }
 
-    pub fn page_search(&self, query: &str, limit: Option<u64>) -> Result<Vec<index::Page>> {
-        self.index.page_search(query, limit)
+    /// Run an index query and return how sqlite ran it, for diagnosing slow queries.
+    pub fn explain_index_query(&self, query: &index::IndexQuery
+    ) -> Result<index::QueryExplanation> {
+        self.index.explain(query)
     }
 
     pub fn get_page_by_store_id(&self, id: StorePageId) -> Result<Option<MappedPage>> {
-        self.chunk_store.get_page_by_store_id(id)
+        match &self.chunk_store {
+            ChunkStore::Local(chunk_store) => chunk_store.get_page_by_store_id(id),
+            ChunkStore::Remote(remote) => remote.get_page_by_store_id(id),
+        }
     }
 
     pub fn get_page_by_slug(&self, slug: &str) -> Result<Option<MappedPage>> {
@@ -444,24 +2218,854 @@ impl Store {
         self.get_page_by_store_id(id)
     }
 
+    /// Get a page's index entry by slug, ignoring case if only one page matches. The entry's
+    /// slug is the page's canonical slug.
+    pub fn get_indexed_page_by_slug(&self, slug: &str) -> Result<Option<index::Page>> {
+        self.index.get_page_by_slug(slug)
+    }
+
+    /// Get a page by slug, following it if it's a redirect, as MediaWiki shows pages. Also
+    /// returns the anchor of the section of the page to show, if the redirect has one.
+    ///
+    /// See [`Store::follow_redirect`] for which redirects are followed.
+    pub fn get_page_by_slug_following_redirect(&self, slug: &str
+    ) -> Result<Option<(MappedPage, Option<String>)>>
+    {
+        let page = try2!(self.index.get_page_by_slug(slug));
+        let (page, fragment) = match self.follow_redirect(&page)? {
+            Some(redirect) => (redirect.target, redirect.fragment),
+            None => (page, None),
+        };
+        let mapped = try2!(self.get_page_by_store_id(page.store_id()));
+        Ok(Some((mapped, fragment)))
+    }
+
+    /// Get where a redirect page points: the target page's index entry and the section of
+    /// it to show.
+    ///
+    /// Returns `None` if `page` isn't a redirect, or if its target isn't in the store or is
+    /// `page` itself. As on MediaWiki only one redirect is followed, so the target may be
+    /// another redirect.
+    pub fn follow_redirect(&self, page: &index::Page) -> Result<Option<index::Redirect>> {
+        let Some(redirect_slug) = page.redirect_slug.as_ref() else {
+            return Ok(None);
+        };
+        let target = match self.index.get_page_by_slug(redirect_slug)? {
+            Some(target) if target.mediawiki_id != page.mediawiki_id => target,
+            _ => return Ok(None),
+        };
+        Ok(Some(index::Redirect {
+            target,
+            fragment: page.redirect_fragment.clone(),
+        }))
+    }
+
     pub fn get_page_by_mediawiki_id(&self, id: u64) -> Result<Option<MappedPage>> {
         let store_page_id = try2!(self.index.get_store_page_id_by_mediawiki_id(id));
         self.get_page_by_store_id(store_page_id)
     }
 
+    /// Get a page's properties by name, such as its display title, see
+    /// [`index::PAGE_PROP_DISPLAY_TITLE`] and the other `PAGE_PROP_*` constants.
+    ///
+    /// Pages imported before page properties were added to the index have none.
+    pub fn get_page_props(&self, mediawiki_id: u64) -> Result<HashMap<String, String>> {
+        self.index.get_page_props(mediawiki_id)
+    }
+
+    /// Get the display titles of the pages in `mediawiki_ids` that have one, by MediaWiki
+    /// ID, e.g. to show search results.
+    pub fn get_display_titles(&self, mediawiki_ids: &[u64]) -> Result<HashMap<u64, String>> {
+        self.index.get_page_prop_values(index::PAGE_PROP_DISPLAY_TITLE, mediawiki_ids)
+    }
+
+    /// Parse the sections of the page with MediaWiki ID `id` from its wikitext into a tree,
+    /// without rendering it, see [`wikitext::section_tree`]. Section byte ranges are into
+    /// the text of the page's revision. Returns `None` if the page isn't in the store.
+    pub fn get_page_sections(&self, id: u64) -> Result<Option<Vec<wikitext::SectionNode>>> {
+        let page = try2!(self.get_page_by_mediawiki_id(id));
+        let page_cap = page.borrow()?;
+        if !page_cap.has_revision() {
+            return Ok(Some(Vec::new()));
+        }
+        let revision_cap = page_cap.get_revision()?;
+        if !revision_cap.has_text() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let sections = wikitext::parse_sections(revision_cap.get_text()?);
+        Ok(Some(wikitext::section_tree(sections)))
+    }
+
+    /// Diff the revision texts of two pages in the store.
+    ///
+    /// Returns an error if either page is not found.
+    pub fn diff(&self, old: StorePageId, new: StorePageId) -> Result<diff::Diff> {
+        let old_text = self.get_page_revision_text(old)?;
+        let new_text = self.get_page_revision_text(new)?;
+        Ok(diff::Diff::from_texts(&old_text, &new_text))
+    }
+
+    fn get_page_revision_text(&self, id: StorePageId) -> Result<String> {
+        let page = self.get_page_by_store_id(id)?
+                       .ok_or_else(|| format_err!("Page not found store_page_id={id}"))?;
+        let page_cap = page.borrow()?;
+        let page_dump = dump::Page::try_from(&page_cap)?;
+        Ok(page_dump.revision_text().unwrap_or("").to_string())
+    }
+
+    /// Get the IDs of chunks in the store in ascending order.
+    ///
+    /// To get the next chunks, pass the last ID returned as `chunk_id_lower_bound`.
+    pub fn chunk_ids(&self, chunk_id_lower_bound: Option<ChunkId>, limit: QueryLimit
+    ) -> Result<Vec<ChunkId>>
+    {
+        self.index.get_chunk_ids(chunk_id_lower_bound, limit)
+    }
+
+    /// The count of chunks in the store.
+    pub fn chunks_len(&self) -> Result<u64> {
+        Ok(self.index.get_chunk_totals()?.chunks_len)
+    }
+
+    /// Get the IDs of all chunks in the store in ascending order. Prefer
+    /// [`Store::chunk_id_iter`] or [`Store::chunk_ids`] for large stores.
     pub fn chunk_id_vec(&self) -> Result<Vec<ChunkId>> {
-        self.chunk_store.chunk_id_vec()
+        self.chunk_id_iter().collect()
     }
 
-    pub fn chunk_id_iter(&self) -> impl Iterator<Item = Result<ChunkId>> {
-        self.chunk_store.chunk_id_iter()
+    /// Iterate over the IDs of all chunks in the store in ascending order, reading them from
+    /// the index a page at a time.
+    pub fn chunk_id_iter(&self) -> impl Iterator<Item = Result<ChunkId>> + '_ {
+        const PAGE_LEN: u64 = 1000;
+
+        let mut lower_bound = None;
+        let mut done = false;
+        std::iter::from_fn(move || -> Option<Result<Vec<ChunkId>>> {
+            if done {
+                return None;
+            }
+            let ids = match self.index.get_chunk_ids(lower_bound,
+                                                     QueryLimit::internal(PAGE_LEN)) {
+                Ok(ids) => ids,
+                Err(e) => {
+                    done = true;
+                    return Some(Err(e));
+                }
+            };
+            done = (ids.len() as u64) < PAGE_LEN;
+            lower_bound = ids.last().copied();
+            (!ids.is_empty()).then_some(Ok(ids))
+        }).flat_map(|page: Result<Vec<ChunkId>>| -> Box<dyn Iterator<Item = Result<ChunkId>>> {
+            match page {
+                Ok(ids) => Box::new(ids.into_iter().map(Ok)),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }
+        })
     }
 
     pub fn get_chunk_meta_by_chunk_id(&self, chunk_id: ChunkId) -> Result<Option<ChunkMeta>> {
-        self.chunk_store.get_chunk_meta_by_chunk_id(chunk_id)
+        match &self.chunk_store {
+            ChunkStore::Local(chunk_store) => chunk_store.get_chunk_meta_by_chunk_id(chunk_id),
+            ChunkStore::Remote(remote) => remote.get_chunk_meta_by_chunk_id(chunk_id),
+        }
     }
 
     pub fn map_chunk(&self, chunk_id: ChunkId) -> Result<Option<MappedChunk>> {
-        self.chunk_store.map_chunk(chunk_id)
+        match &self.chunk_store {
+            ChunkStore::Local(chunk_store) => chunk_store.map_chunk(chunk_id),
+            ChunkStore::Remote(remote) => remote.map_chunk(chunk_id),
+        }
+    }
+
+    /// Describe a chunk in the stable schema of [`metadata::ChunkInfo`].
+    ///
+    /// If `hash` is true the SHA1 hash of a local chunk file is calculated, which reads the
+    /// whole file.
+    pub fn chunk_info(&self, chunk_id: ChunkId, hash: bool
+    ) -> Result<Option<metadata::ChunkInfo>>
+    {
+        let meta = try2!(self.get_chunk_meta_by_chunk_id(chunk_id));
+
+        let sha1 = match (&self.chunk_store, hash) {
+            (ChunkStore::Local(_), true) => {
+                let file = std::fs::File::open(&meta.path)
+                    .with_context(|| format!("opening chunk file to hash it path='{}'",
+                                             meta.path.display()))?;
+                Some(fmt::Sha1Hash::calculate_from_reader(std::io::BufReader::new(file))?
+                         .to_hex_string())
+            },
+            _ => None,
+        };
+
+        Ok(Some(metadata::ChunkInfo {
+            schema_version: metadata::SCHEMA_VERSION,
+            id: meta.id.0,
+            dump_name: self.opts.dump_name.0.clone(),
+            pages_len: meta.pages_len,
+            bytes_len: meta.bytes_len.0,
+            sha1,
+            source: match self.chunk_provenance(chunk_id)? {
+                None => None,
+                Some(provenance) => Some(metadata::ChunkSourceInfo {
+                    imported: self.import_run(provenance.import_run_id)?
+                                  .map(|run| run.started),
+                    path: provenance.source_path,
+                    seek: provenance.source_seek,
+                    pages_start: provenance.file_pages_start,
+                    pages_end: provenance.file_pages_end,
+                    xml_offset_start: provenance.xml_offset_start,
+                    xml_offset_end: provenance.xml_offset_end,
+                    import_run_id: provenance.import_run_id,
+                }),
+            },
+        }))
+    }
+
+    /// Where the pages in chunk `chunk_id` were imported from, or `None` for chunks imported
+    /// before this was recorded.
+    pub fn chunk_provenance(&self, chunk_id: ChunkId) -> Result<Option<index::ChunkProvenance>> {
+        self.index.get_chunk_provenance(chunk_id)
+    }
+
+    pub fn import_run(&self, id: u64) -> Result<Option<index::ImportRun>> {
+        self.index.get_import_run(id)
+    }
+
+    /// Save a page search in the index, replacing any saved search with the same name.
+    ///
+    /// Saved searches are kept when pages are imported again, so can be run later with
+    /// [`Store::page_search`] and [`index::SavedSearch::page_search_options`], e.g. to
+    /// watch for new matching pages.
+    pub fn save_search(&mut self, search: &index::SavedSearch) -> Result<()> {
+        self.index.put_saved_search(search)
+    }
+
+    /// All saved searches, ordered by name.
+    pub fn saved_searches(&self) -> Result<Vec<index::SavedSearch>> {
+        self.index.get_saved_searches()
+    }
+
+    pub fn saved_search(&self, name: &str) -> Result<Option<index::SavedSearch>> {
+        self.index.get_saved_search(name)
+    }
+
+    /// Delete the saved search `name`. Returns false if there was none.
+    pub fn delete_saved_search(&mut self, name: &str) -> Result<bool> {
+        self.index.delete_saved_search(name)
+    }
+
+    /// Describe the store in the stable schema of [`metadata::StoreInfo`].
+    pub fn store_info(&self) -> Result<metadata::StoreInfo> {
+        let chunk_totals = self.index.get_chunk_totals()?;
+        let mut chunks_bytes_len = chunk_totals.bytes_len;
+
+        // Remote stores migrated from an index without chunk lengths can't fill them in
+        // when opened, so add them up here.
+        for chunk_id in self.index.get_chunk_ids_without_bytes_len()? {
+            let meta = self.get_chunk_meta_by_chunk_id(chunk_id)?
+                           .ok_or_else(|| format_err!("Chunk not found chunk_id={chunk_id}"))?;
+            chunks_bytes_len += meta.bytes_len.0;
+        }
+
+        let sources = self.index.get_import_file_progress_all()?
+            .into_iter()
+            .map(|progress| metadata::SourceInfo {
+                path: progress.path,
+                pages_len: progress.pages_committed,
+                complete: progress.complete,
+                updated: progress.updated,
+            })
+            .collect::<Vec<metadata::SourceInfo>>();
+
+        // Sources imported before update times were recorded sort first.
+        let latest_source = sources.iter().max_by(|a, b| (a.updated, &a.path)
+                                                            .cmp(&(b.updated, &b.path)));
+
+        let namespaces = self.index.pages_len_by_namespace()?
+            .into_iter()
+            .map(|(key, pages_len)| metadata::NamespaceInfo {
+                key,
+                name: match Namespace::from_key(key) {
+                    Ok(ns) => ns.name().to_string(),
+                    Err(_) => format!("Namespace {key}"),
+                },
+                pages_len,
+            })
+            .collect::<Vec<metadata::NamespaceInfo>>();
+
+        Ok(metadata::StoreInfo {
+            schema_version: metadata::SCHEMA_VERSION,
+            dump_name: self.opts.dump_name.0.clone(),
+            remote_url: match &self.chunk_store {
+                ChunkStore::Local(_) => None,
+                ChunkStore::Remote(remote) => Some(remote.base_url().to_string()),
+            },
+            chunks_len: chunk_totals.chunks_len,
+            pages_len: self.index.pages_len()?,
+            chunks_bytes_len,
+            index_bytes_len: self.index.db_bytes_len()?,
+            index_search_bytes_len: self.index.search_db_bytes_len()?,
+            namespaces,
+            categories_len: self.index.categories_len()?,
+            dump_version: latest_source.and_then(|source| dump::local::version_from_path(
+                                                              Path::new(&source.path)))
+                                       .map(|version| version.0),
+            last_import: sources.iter().filter_map(|source| source.updated).max(),
+            sources,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::BufWriter};
+    use wikimedia::{
+        dump::{local::OpenSpec, synthetic},
+        TempDir,
+    };
+
+    /// A store in `dir` with `pages` synthetic pages imported.
+    fn synthetic_store(dir: &TempDir, pages: u64) -> Store {
+        synthetic_store_with_layout(dir, pages, index::Layout::Single)
+    }
+
+    fn synthetic_store_with_layout(dir: &TempDir, pages: u64, layout: index::Layout) -> Store {
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = BufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let mut store = Options::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .index_layout(layout)
+            .build().unwrap();
+        store.import(open_spec.open().unwrap(), /* continue_import: */ false).unwrap();
+        store
+    }
+
+    #[test]
+    fn publish_keeps_options() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let site = SiteInfo {
+            site_url: "https://wiki.example.com".to_string(),
+            language: Some("en".to_string()),
+            article_path: "/page/$1".to_string(),
+        };
+        let page_events = page_events::Sender::new();
+        let mut receiver = page_events.subscribe();
+        let mut store = Options::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .site(site.clone())
+            .page_events(page_events)
+            .build().unwrap();
+
+        let generation_path = generation::Generations::new(&dir_path.join("store"))
+                                  .path("20230301").unwrap();
+        let mut generation = Options::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(generation_path.clone())
+            .build().unwrap();
+        generation.upsert_page(&dump::Page {
+            ns_id: 0,
+            id: 1,
+            title: "Generation page".to_string(),
+            revision: None,
+            restrictions: None,
+        }).unwrap();
+        drop(generation);
+
+        store.publish(&generation_path).unwrap();
+        assert!(store.get_page_by_slug("Generation_page").unwrap().is_some());
+        assert_eq!(store.site(), Some(site));
+
+        store.upsert_page(&dump::Page {
+            ns_id: 0,
+            id: 2,
+            title: "Published page".to_string(),
+            revision: None,
+            restrictions: None,
+        }).unwrap();
+        assert!(matches!(receiver.try_recv().unwrap(),
+                         PageEvent::Inserted(page) if page.mediawiki_id == 2));
+    }
+
+    #[test]
+    fn upsert_pages_shares_chunks() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let mut store = synthetic_store(&dir, 5);
+        let chunks_len = store.chunks_len().unwrap();
+
+        let page = |id: u64, title: &str| dump::Page {
+            ns_id: 0,
+            id,
+            title: title.to_string(),
+            revision: None,
+            restrictions: None,
+        };
+        let store_ids = store.upsert_pages(&[page(100, "First"),
+                                             page(101, "Second"),
+                                             page(3, "Corrected page"),
+                                             page(100, "First again")]).unwrap();
+        assert_eq!(store_ids.len(), 4);
+        assert_eq!(store_ids[0], store_ids[3]);
+        assert_eq!(store_ids[0].chunk_id, store_ids[1].chunk_id);
+
+        // The new pages share one new chunk, and the replacement is written with the rest
+        // of its chunk.
+        assert_eq!(store.chunks_len().unwrap(), chunks_len + 1);
+        assert!(store.get_page_by_slug("First").unwrap().is_none());
+        assert_eq!(store.get_page_by_slug("First_again").unwrap().unwrap().store_id(),
+                   store_ids[0]);
+        assert_eq!(store.get_page_by_slug("Corrected_page").unwrap().unwrap().store_id(),
+                   store_ids[2]);
+        assert!(store.fsck_index(/* delete: */ false).unwrap().is_ok());
+    }
+
+    #[test]
+    fn upsert_checks_quota() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        drop(synthetic_store(&dir, 5));
+        let mut store = Options::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir.path().unwrap().join("store"))
+            .quota(Quota {
+                max_chunk_bytes: None,
+                max_pages: Some(5),
+            })
+            .build().unwrap();
+
+        let page = |id: u64, title: &str| dump::Page {
+            ns_id: 0,
+            id,
+            title: title.to_string(),
+            revision: None,
+            restrictions: None,
+        };
+
+        // Replacing a page doesn't add one, so it's allowed.
+        store.upsert_page(&page(3, "Corrected page")).unwrap();
+
+        let err = store.upsert_page(&page(100, "New page")).unwrap_err();
+        let exceeded = err.downcast_ref::<QuotaExceeded>().unwrap();
+        assert_eq!((exceeded.limit, exceeded.max, exceeded.used), (QuotaLimit::Pages, 5, 5));
+        assert!(store.upsert_pages(&[page(101, "Other new page")]).unwrap_err()
+                     .downcast_ref::<QuotaExceeded>().is_some());
+        assert!(store.get_page_by_mediawiki_id(100).unwrap().is_none());
+        assert!(store.get_page_by_mediawiki_id(101).unwrap().is_none());
+    }
+
+    #[test]
+    fn upsert_then_delete_page() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let mut store = synthetic_store(&dir, 5);
+
+        let page = |id: u64, title: &str| dump::Page {
+            ns_id: 0,
+            id,
+            title: title.to_string(),
+            revision: Some(dump::Revision {
+                id: id + 2_000_000,
+                parent_id: None,
+                timestamp: None,
+                text: Some("{{Fixed template}} Corrected text.".to_string()),
+                sha1: None,
+                categories: Vec::new(),
+            }),
+            restrictions: None,
+        };
+
+        // Replace page 3, "Page 2", twice. Each replacement writes its chunk again, so the
+        // replaced copies can't be read.
+        let original_id = store.get_page_by_slug("Page_2").unwrap().unwrap().store_id();
+        let first_id = store.upsert_page(&page(3, "Corrected page")).unwrap();
+        let store_id = store.upsert_page(&page(3, "Corrected page")).unwrap();
+        assert!(store.get_page_by_store_id(original_id).unwrap().is_none());
+        assert!(store.get_page_by_store_id(first_id).unwrap().is_none());
+        assert!(store.get_page_by_slug("Page_2").unwrap().is_none());
+        let mapped = store.get_page_by_slug("Corrected_page").unwrap().unwrap();
+        assert_eq!(mapped.store_id(), store_id);
+        assert_eq!(store.get_page_templates(3).unwrap(), ["Template:Fixed_template"]);
+        assert_eq!(store.chunk_id_iter().count(), 1);
+
+        let inserted_id = store.upsert_page(&page(100, "New page")).unwrap();
+        assert!(store.get_page_by_mediawiki_id(100).unwrap().is_some());
+
+        let deleted = store.delete_page(inserted_id).unwrap().unwrap();
+        assert_eq!(deleted.mediawiki_id, 100);
+        assert!(store.get_page_by_mediawiki_id(100).unwrap().is_none());
+        assert!(store.get_page_by_store_id(inserted_id).unwrap().is_none());
+        assert!(store.delete_page(inserted_id).unwrap().is_none());
+
+        // Deleting a page from a chunk with other pages writes them to a new chunk.
+        store.delete_page(store_id).unwrap().unwrap();
+        assert!(store.get_page_by_store_id(store_id).unwrap().is_none());
+        assert!(store.get_page_templates(3).unwrap().is_empty());
+        assert!(store.page_search("Corrected", &index::PageSearchOptions::default())
+                     .unwrap().is_empty());
+        assert!(store.get_page_by_slug("Page_1").unwrap().is_some());
+
+        let fsck = store.fsck_index(/* delete: */ false).unwrap();
+        assert!(fsck.is_ok(), "{fsck:?}");
+        assert_eq!(store.chunk_id_iter().count(), 1);
+    }
+
+    #[test]
+    fn fsck_index_missing_chunk_and_orphans() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let mut store = synthetic_store(&dir, 5);
+        assert!(store.fsck_index(/* delete: */ false).unwrap().is_ok());
+
+        // Lose the chunk file of a page written after the import.
+        let mut page = dump::Page::try_from(
+            &store.get_page_by_mediawiki_id(2).unwrap().unwrap().borrow().unwrap()).unwrap();
+        page.id = 100;
+        page.title = "Lost page".to_string();
+        let lost_id = store.upsert_page(&page).unwrap();
+        fs::remove_file(store.opts.chunks_path.join(chunk::chunk_file_name(lost_id.chunk_id)))
+            .unwrap();
+
+        // Rows for pages that aren't in the index.
+        let conn = rusqlite::Connection::open(store.opts.index_path.join("index.db")).unwrap();
+        conn.execute_batch(
+            "INSERT INTO page_links(mediawiki_id, target_slug) VALUES (999, 'Page_1'); \
+             INSERT INTO page_props(mediawiki_id, name, value) \
+                 VALUES (999, 'displaytitle', 'Gone');").unwrap();
+        drop(conn);
+
+        let report = store.fsck_index(/* delete: */ false).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.missing_chunks, [lost_id.chunk_id]);
+        assert_eq!(report.pages_in_missing_chunks, 1);
+        assert_eq!((report.orphans.page_links, report.orphans.page_props), (1, 1));
+        assert!(report.deleted.is_none());
+        assert_eq!(store.index.get_pages_by_mediawiki_ids(&[100]).unwrap().len(), 1);
+
+        let report = store.fsck_index(/* delete: */ true).unwrap();
+        let deleted = report.deleted.unwrap();
+        assert_eq!(deleted.pages_without_chunk, 1);
+        // The lost page's own link is an orphan once the page is deleted.
+        assert_eq!((deleted.page_links, deleted.page_props), (2, 1));
+        assert!(store.index.get_pages_by_mediawiki_ids(&[100]).unwrap().is_empty());
+        assert!(store.get_page_by_mediawiki_id(2).unwrap().is_some());
+
+        let report = store.fsck_index(/* delete: */ false).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+    }
+
+    #[test]
+    fn continue_interrupted_import() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = BufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages: 30,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+        let open = |limit: Option<u64>| {
+            let mut open_spec = OpenSpec::builder();
+            open_spec.file(dump_path.clone())
+                     .compression(Compression::None);
+            if let Some(limit) = limit {
+                open_spec.limit(limit);
+            }
+            open_spec.build().unwrap().open().unwrap()
+        };
+
+        let open_store = |cancellation: CancellationToken| -> Store {
+            Options::default()
+                .dump_name(DumpName("syntheticwiki".to_string()))
+                .path(dir_path.join("store"))
+                .max_pages_per_chunk(4)
+                .cancellation(cancellation)
+                .build().unwrap()
+        };
+        let mut store = open_store(CancellationToken::new());
+
+        // Stop part way through the file, after committing 3 chunks.
+        let res = store.import(open(Some(9)), /* continue_import: */ false).unwrap();
+        assert_eq!((res.pages_total, res.chunks_len), (12, 3));
+
+        // A cancelled import leaves the file's progress as it was.
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        drop(store);
+        let mut store = open_store(cancellation);
+        let res = store.import(open(None), /* continue_import: */ true).unwrap();
+        assert!(res.cancelled);
+        assert_eq!(res.pages_total, 0);
+        drop(store);
+        let mut store = open_store(CancellationToken::new());
+
+        // Continuing seeks to the end of the last page committed.
+        let res = store.import(open(None), /* continue_import: */ true).unwrap();
+        assert_eq!(res.pages_total, 18);
+        assert!(!res.files[0].already_imported);
+
+        let res = store.import(open(None), /* continue_import: */ true).unwrap();
+        assert_eq!(res.pages_total, 0);
+        assert!(res.files[0].already_imported);
+
+        // Each page is in exactly one chunk.
+        let mut mediawiki_ids = Vec::new();
+        for chunk_id in store.chunk_id_iter() {
+            let chunk = store.map_chunk(chunk_id.unwrap()).unwrap().unwrap();
+            mediawiki_ids.extend(chunk.pages_iter().unwrap()
+                                      .map(|(_store_id, page_cap)| page_cap.get_id()));
+        }
+        mediawiki_ids.sort();
+        assert_eq!(mediawiki_ids, (1..=30).collect::<Vec<u64>>());
+        assert_eq!(store.index.pages_len().unwrap(), 30);
+    }
+
+    #[test]
+    fn recover_pending_chunks_on_open() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let mut store = synthetic_store(&dir, 5);
+        let mut page = dump::Page::try_from(
+            &store.get_page_by_mediawiki_id(2).unwrap().unwrap().borrow().unwrap()).unwrap();
+
+        let ChunkStore::Local(chunk_store) = &mut store.chunk_store else {
+            panic!("Expected a local chunk store");
+        };
+        let chunk_write_guard = chunk_store.try_write_lock().unwrap();
+
+        // An import that stopped after writing a chunk's pending file, before committing its
+        // pages. Readers don't find the pages.
+        page.id = 100;
+        let mut chunk_builder = chunk_write_guard.chunk_builder().unwrap();
+        chunk_builder.push(&page).unwrap();
+        let pending_chunk = chunk_builder.write_pending().unwrap();
+        let rolled_back_id = pending_chunk.meta().id;
+        store.index.put_pending_chunk(pending_chunk.meta()).unwrap();
+        drop(pending_chunk);
+        assert!(store.index.get_pages_by_mediawiki_ids(&[100]).unwrap().is_empty());
+
+        // An import by an older version that stopped after committing a chunk's pages,
+        // before renaming its pending file.
+        page.id = 101;
+        let mut chunk_builder = chunk_write_guard.chunk_builder().unwrap();
+        let store_page_id = chunk_builder.push(&page).unwrap();
+        let pending_chunk = chunk_builder.write_pending().unwrap();
+        let finished_id = pending_chunk.meta().id;
+        let mut index_batch_builder = store.index.import_batch_builder().unwrap();
+        index_batch_builder.push(&page, store_page_id).unwrap();
+        index_batch_builder.set_chunk(pending_chunk.meta());
+        index_batch_builder.commit().unwrap();
+        store.index.put_pending_chunk(pending_chunk.meta()).unwrap();
+        drop(pending_chunk);
+
+        drop(chunk_write_guard);
+        let chunks_path = store.opts.chunks_path.clone();
+        drop(store);
+        let pending_path = |chunk_id: ChunkId|
+            chunks_path.join(format!("{}.pending", chunk::chunk_file_name(chunk_id)));
+        assert!(pending_path(rolled_back_id).exists());
+        assert!(pending_path(finished_id).exists());
+
+        let mut store = Options::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir.path().unwrap().join("store"))
+            .build().unwrap();
+        assert!(store.index.get_pending_chunk_ids().unwrap().is_empty());
+
+        assert!(!pending_path(rolled_back_id).exists());
+        assert!(!chunks_path.join(chunk::chunk_file_name(rolled_back_id)).exists());
+        assert!(store.get_page_by_mediawiki_id(100).unwrap().is_none());
+
+        assert!(!pending_path(finished_id).exists());
+        assert!(chunks_path.join(chunk::chunk_file_name(finished_id)).exists());
+        assert!(store.get_page_by_mediawiki_id(101).unwrap().is_some());
+
+        let chunk_ids = store.chunk_id_iter().collect::<Result<Vec<ChunkId>>>().unwrap();
+        assert!(!chunk_ids.contains(&rolled_back_id));
+        assert!(chunk_ids.contains(&finished_id));
+        assert!(store.fsck_index(/* delete: */ false).unwrap().is_ok());
+    }
+
+    #[test]
+    fn save_run_and_delete_search() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let mut store = synthetic_store(&dir, 5);
+
+        let mut search = index::SavedSearch {
+            name: "Pages".to_string(),
+            query: "page".to_string(),
+            min_words: None,
+            max_words: None,
+            category: None,
+            namespace: Some(0),
+            order: index::PageSearchOrder::Longest,
+            saved: chrono::Utc::now(),
+        };
+        store.save_search(&search).unwrap();
+        search.query = "page 2".to_string();
+        store.save_search(&search).unwrap();
+
+        let saved = store.saved_searches().unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].query, "page 2");
+        assert_eq!(saved[0].order, index::PageSearchOrder::Longest);
+
+        let saved = store.saved_search("Pages").unwrap().unwrap();
+        let pages = store.page_search(&saved.query,
+                                      &saved.page_search_options(QueryLimit::default()))
+                         .unwrap();
+        assert_eq!(pages[0].slug, "Page_2");
+
+        search.name = "a/b".to_string();
+        assert!(store.save_search(&search).is_err());
+
+        // Saved searches are kept when the store is cleared.
+        store.clear().unwrap();
+        assert!(store.saved_search("Pages").unwrap().is_some());
+
+        assert!(store.delete_saved_search("Pages").unwrap());
+        assert!(!store.delete_saved_search("Pages").unwrap());
+        assert!(store.saved_searches().unwrap().is_empty());
+    }
+
+    #[test]
+    fn split_search_layout() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let mut store = synthetic_store_with_layout(&dir, 5, index::Layout::SplitSearch);
+        let index_path = dir.path().unwrap().join("store").join("index");
+        assert!(index_path.join("search.db").exists());
+        assert!(store.store_info().unwrap().index_search_bytes_len.is_some());
+
+        let search = |store: &Store, query: &str| -> Vec<u64> {
+            store.page_search(query, &index::PageSearchOptions::default()).unwrap()
+                 .into_iter().map(|page| page.mediawiki_id).collect()
+        };
+        assert_eq!(search(&store, "page 2"), [3]);
+
+        // Writes to the page table in index.db update the full text search in search.db.
+        let store_id = store.upsert_page(&dump::Page {
+            ns_id: 0,
+            id: 3,
+            title: "Corrected page".to_string(),
+            revision: None,
+            restrictions: None,
+        }).unwrap();
+        assert!(search(&store, "\"page 2\"").is_empty());
+        assert_eq!(search(&store, "corrected"), [3]);
+        store.delete_page(store_id).unwrap().unwrap();
+        assert!(search(&store, "corrected").is_empty());
+
+        store.retokenize_fts(&index::FtsTokenizer::Trigram { case_sensitive: false }).unwrap();
+        assert_eq!(search(&store, "\"age_4\""), [5]);
+        store.build_fts().unwrap();
+        let fsck = store.fsck_index(/* delete: */ false).unwrap();
+        assert!(fsck.is_ok(), "{fsck:?}");
+
+        // Exports use the single layout, with the full text search in the exported file.
+        let export_path = dir.path().unwrap().join("export.db");
+        store.export_index(&export_path).unwrap();
+        store.import_index(&export_path).unwrap();
+        assert!(!index_path.join("search.db").exists());
+        assert!(store.store_info().unwrap().index_search_bytes_len.is_none());
+        assert_eq!(search(&store, "\"age_4\""), [5]);
+    }
+
+    #[test]
+    fn import_namespace_filter() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let pages = [(1, 0, "Alpha"), (2, 1, "Talk:Alpha"), (3, 0, "Beta"),
+                     (4, 14, "Category:Things"), (5, 3, "User talk:Someone"),
+                     (6, 10, "Template:Box")];
+        let mut dump = String::from(r#"<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.10/" version="0.10" xml:lang="en">
+  <siteinfo>
+    <dbname>syntheticwiki</dbname>
+  </siteinfo>
+"#);
+        for (id, ns_id, title) in pages {
+            dump.push_str(&format!(r#"  <page>
+    <title>{title}</title>
+    <ns>{ns_id}</ns>
+    <id>{id}</id>
+    <revision>
+      <id>{revision_id}</id>
+      <text xml:space="preserve">Text of {title}.</text>
+    </revision>
+  </page>
+"#, revision_id = id + 1_000_000));
+        }
+        dump.push_str("</mediawiki>\n");
+        let dump_path = dir_path.join("namespaces.xml");
+        fs::write(&dump_path, dump).unwrap();
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let mut store = Options::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .import_namespaces(NamespaceFilter {
+                include: Vec::new(),
+                exclude: vec![14],
+                exclude_talk: true,
+            })
+            .build().unwrap();
+        let res = store.import(open_spec.open().unwrap(), /* continue_import: */ false)
+                       .unwrap();
+        assert_eq!(res.pages_total, 3);
+        assert_eq!(res.pages_excluded_total, 3);
+        assert_eq!(res.files[0].pages_excluded, 3);
+
+        let slugs = |ns_id: i64, slug_lower_bound: Option<&str>, limit: u64| -> Vec<String> {
+            store.get_pages_by_namespace(ns_id, slug_lower_bound, QueryLimit::from(Some(limit)))
+                 .unwrap()
+                 .into_iter().map(|page| page.slug).collect()
+        };
+        assert_eq!(slugs(0, None, 1), ["Alpha"]);
+        assert_eq!(slugs(0, Some("Alpha"), 10), ["Beta"]);
+        assert_eq!(slugs(10, None, 10), ["Template:Box"]);
+        assert!(slugs(1, None, 10).is_empty());
+        assert!(slugs(14, None, 10).is_empty());
+
+        let pages = store.page_search("box", &index::PageSearchOptions {
+            namespace: Some(0),
+            ..index::PageSearchOptions::default()
+        }).unwrap();
+        assert!(pages.is_empty());
+        let pages = store.page_search("box", &index::PageSearchOptions {
+            namespace: Some(10),
+            ..index::PageSearchOptions::default()
+        }).unwrap();
+        assert_eq!(pages[0].mediawiki_id, 6);
     }
 }
@@ -0,0 +1,63 @@
+//! The count of results a store query returns, see [`QueryLimit`].
+
+use crate::MAX_QUERY_LIMIT;
+
+/// The most results a store query returns.
+///
+/// Limits from callers are clamped to [`MAX_QUERY_LIMIT`], and no limit means that maximum,
+/// so requests can't make the store read unbounded results. Only the store itself can set a
+/// larger limit, to page through all results in fewer queries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QueryLimit(u64);
+
+impl QueryLimit {
+    /// The default limit, [`MAX_QUERY_LIMIT`].
+    pub const MAX: QueryLimit = QueryLimit(MAX_QUERY_LIMIT);
+
+    /// A limit of `limit` results, clamped to [`MAX_QUERY_LIMIT`].
+    pub fn new(limit: u64) -> QueryLimit {
+        QueryLimit(limit.min(MAX_QUERY_LIMIT))
+    }
+
+    /// A limit of `limit` results that may be more than [`MAX_QUERY_LIMIT`], for queries
+    /// within the store that page through all results.
+    pub(crate) fn internal(limit: u64) -> QueryLimit {
+        QueryLimit(limit)
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for QueryLimit {
+    fn default() -> QueryLimit {
+        QueryLimit::MAX
+    }
+}
+
+impl From<u64> for QueryLimit {
+    fn from(limit: u64) -> QueryLimit {
+        QueryLimit::new(limit)
+    }
+}
+
+/// `None` is the default limit.
+impl From<Option<u64>> for QueryLimit {
+    fn from(limit: Option<u64>) -> QueryLimit {
+        limit.map_or(QueryLimit::MAX, QueryLimit::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps() {
+        assert_eq!(QueryLimit::from(None).get(), MAX_QUERY_LIMIT);
+        assert_eq!(QueryLimit::from(Some(10)).get(), 10);
+        assert_eq!(QueryLimit::from(MAX_QUERY_LIMIT + 1).get(), MAX_QUERY_LIMIT);
+        assert_eq!(QueryLimit::internal(MAX_QUERY_LIMIT + 1).get(), MAX_QUERY_LIMIT + 1);
+    }
+}
@@ -0,0 +1,276 @@
+//! Stable JSON descriptions of a store and its chunks, for scripts and external tools.
+//!
+//! Unlike types such as [`ChunkMeta`](crate::ChunkMeta), these don't expose the store's
+//! internal layout, and their JSON form only changes with [`SCHEMA_VERSION`]. New optional
+//! fields may be added without a version change; removing, renaming, or changing the meaning
+//! of a field increments the version.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The current version of the JSON schema, written to `schema_version` in each object.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A chunk file of pages in a store.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChunkInfo {
+    pub schema_version: u32,
+
+    pub id: u64,
+
+    /// The dump the chunk's pages were imported from, e.g. `enwiki`.
+    pub dump_name: String,
+
+    pub pages_len: u64,
+
+    /// The length of the chunk file in bytes.
+    pub bytes_len: u64,
+
+    /// The SHA1 hash of the chunk file as a lower case hex string. Not set for chunks of a
+    /// remote store, which are not downloaded in full.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+
+    /// Where the chunk's pages were imported from. Not set for chunks imported before this
+    /// was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ChunkSourceInfo>,
+}
+
+/// The part of a dump file that a chunk's pages were imported from.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChunkSourceInfo {
+    /// The path of the dump file when it was imported.
+    pub path: String,
+
+    /// The offset in the dump file reading started from, e.g. the start of a stream in a
+    /// multistream dump. The page counts and XML offsets are from here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seek: Option<u64>,
+
+    /// The count of pages in the file before the chunk's first page.
+    pub pages_start: u64,
+
+    /// The count of pages in the file up to and including the chunk's last page.
+    pub pages_end: u64,
+
+    /// The offset in the file's uncompressed XML just after the page before the chunk's
+    /// first page.
+    pub xml_offset_start: u64,
+
+    /// The offset in the file's uncompressed XML just after the chunk's last page.
+    pub xml_offset_end: u64,
+
+    /// The ID of the import run that wrote the chunk.
+    pub import_run_id: u64,
+
+    /// When that import run started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported: Option<DateTime<Utc>>,
+}
+
+/// A summary of a store.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StoreInfo {
+    pub schema_version: u32,
+
+    /// The dump the store's pages were imported from, e.g. `enwiki`.
+    pub dump_name: String,
+
+    /// The base URL of the remote store this store reads from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_url: Option<String>,
+
+    pub chunks_len: u64,
+
+    /// The count of pages in the index.
+    pub pages_len: u64,
+
+    /// The total length of the chunk files in bytes.
+    pub chunks_bytes_len: u64,
+
+    /// The length of the index database file in bytes.
+    pub index_bytes_len: u64,
+
+    /// The length of the index's search database file in bytes, if its search tables are
+    /// in a file of their own. See [`index::Layout`](crate::index::Layout).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_search_bytes_len: Option<u64>,
+
+    /// The count of pages in each namespace that has pages, ordered by namespace key.
+    #[serde(default)]
+    pub namespaces: Vec<NamespaceInfo>,
+
+    /// The count of categories pages are in.
+    #[serde(default)]
+    pub categories_len: u64,
+
+    /// The dump version of the source file imported most recently, e.g. `20230320`, if its
+    /// path includes one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dump_version: Option<String>,
+
+    /// When pages were last imported, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_import: Option<DateTime<Utc>>,
+
+    /// The dump files pages were imported from.
+    pub sources: Vec<SourceInfo>,
+}
+
+/// The pages in one namespace of a store.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NamespaceInfo {
+    /// The namespace key, e.g. 0 for articles.
+    pub key: i64,
+
+    /// The namespace name, e.g. `Talk`. Articles are in the namespace named `Page`.
+    pub name: String,
+
+    pub pages_len: u64,
+}
+
+/// A dump file that pages were imported from.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SourceInfo {
+    /// The path of the dump file when it was imported.
+    pub path: String,
+
+    /// The count of pages imported from the file.
+    pub pages_len: u64,
+
+    /// Whether every page in the file was imported.
+    pub complete: bool,
+
+    /// When pages from the file were last imported. Not set for files imported before this
+    /// was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// Whether this version of the store can open a store, from
+/// [`Options::compatibility`](crate::Options::compatibility).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StoreCompatibility {
+    pub schema_version: u32,
+
+    /// The root directory of the store.
+    pub path: String,
+
+    /// Whether the store has a config file or an index. A store that doesn't exist is
+    /// created when opened.
+    pub exists: bool,
+
+    /// The base URL of the remote store this store reads from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_url: Option<String>,
+
+    /// The schema version of the store's index, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_schema_version: Option<i64>,
+
+    /// The newest index schema version this version supports. Older indexes are migrated
+    /// to it when opened.
+    pub supported_index_schema_version: i64,
+
+    /// Whether the store can be opened.
+    pub can_open: bool,
+
+    /// Whether opening the store will migrate its index to a newer schema version, after
+    /// which older versions can't open it.
+    pub migrates_index: bool,
+
+    /// Why the store can't be opened, when `can_open` is false.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub problem: Option<String>,
+}
+
+/// A description of an export of a store's pages, written with it so a dataset made from
+/// the store can be reproduced and attributed. From
+/// [`Store::export_manifest`](crate::Store::export_manifest).
+///
+/// It only depends on the store's pages and the export options, so an ordered export of the
+/// same store has the same manifest each time.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ExportManifest {
+    pub schema_version: u32,
+
+    /// The dump the store's pages were imported from, e.g. `enwiki`.
+    pub dump_name: String,
+
+    /// The dump version of the source file imported most recently, e.g. `20230320`, if its
+    /// path includes one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dump_version: Option<String>,
+
+    /// The site the pages were published on, e.g. `https://en.wikipedia.org`, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_url: Option<String>,
+
+    /// The imports that wrote the store's pages, in ID order.
+    pub import_runs: Vec<ImportRunInfo>,
+
+    /// The version of `wikimedia-store` that wrote the export.
+    pub store_version: String,
+
+    /// The export format, e.g. `jsonl`.
+    pub format: String,
+
+    /// Whether pages were written in MediaWiki ID order. Unordered exports of the same store
+    /// may differ in page order, and so in `sha1`.
+    pub ordered: bool,
+
+    /// The key of the only namespace exported, if the export was filtered by namespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<i64>,
+
+    pub pages_len: u64,
+
+    /// The length of the export file in bytes.
+    pub bytes_len: u64,
+
+    /// The SHA1 hash of the export file as a lower case hex string.
+    pub sha1: String,
+}
+
+/// An import that wrote pages to a store.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ImportRunInfo {
+    pub id: u64,
+    pub started: DateTime<Utc>,
+
+    /// When the import finished. Not set if it is still running, failed, or was stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<DateTime<Utc>>,
+
+    /// The version of `wikimedia-store` that ran the import.
+    pub store_version: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Field names are part of the schema, so changing them must fail this test.
+    #[test]
+    fn chunk_info_json() {
+        let info = ChunkInfo {
+            schema_version: SCHEMA_VERSION,
+            id: 3,
+            dump_name: "enwiki".to_string(),
+            pages_len: 10,
+            bytes_len: 2048,
+            sha1: None,
+            source: None,
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "schema_version": 1,
+            "id": 3,
+            "dump_name": "enwiki",
+            "pages_len": 10,
+            "bytes_len": 2048,
+        }));
+        assert_eq!(serde_json::from_value::<ChunkInfo>(json).unwrap(), info);
+    }
+}
@@ -0,0 +1,71 @@
+//! Choose which namespaces' pages an import writes, see [`NamespaceFilter`].
+
+use serde::Serialize;
+use valuable::Valuable;
+
+/// Which namespaces' pages [`Store::import`](crate::Store::import) writes to the store, e.g.
+/// to leave out talk pages, which are a large part of some dumps. See
+/// [`Options::import_namespaces`](crate::Options::import_namespaces).
+///
+/// Includes every namespace by default. Pages left out are counted in the import's
+/// `pages_excluded_total`. Pages written with [`Store::upsert_page`](crate::Store::upsert_page)
+/// are not filtered.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Valuable)]
+pub struct NamespaceFilter {
+    /// Namespace keys to import. If empty, every namespace not excluded is imported.
+    pub include: Vec<i64>,
+
+    /// Namespace keys not to import.
+    pub exclude: Vec<i64>,
+
+    /// Don't import pages in talk namespaces, which MediaWiki gives odd positive keys, e.g.
+    /// `Talk` (1) and `User talk` (3).
+    pub exclude_talk: bool,
+}
+
+impl NamespaceFilter {
+    pub fn is_unfiltered(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && !self.exclude_talk
+    }
+
+    /// Returns true if pages in the namespace with key `ns_id` are imported.
+    pub fn allows(&self, ns_id: i64) -> bool {
+        (self.include.is_empty() || self.include.contains(&ns_id))
+            && !self.exclude.contains(&ns_id)
+            && !(self.exclude_talk && Self::is_talk_key(ns_id))
+    }
+
+    fn is_talk_key(ns_id: i64) -> bool {
+        ns_id > 0 && ns_id % 2 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows() {
+        assert!(NamespaceFilter::default().is_unfiltered());
+        assert!(NamespaceFilter::default().allows(1));
+
+        let filter = NamespaceFilter {
+            include: vec![0, 1, 14],
+            exclude: vec![14],
+            exclude_talk: false,
+        };
+        assert!(filter.allows(0));
+        assert!(filter.allows(1));
+        assert!(!filter.allows(2));
+        assert!(!filter.allows(14));
+
+        let filter = NamespaceFilter {
+            exclude_talk: true,
+            ..NamespaceFilter::default()
+        };
+        assert!(filter.allows(0));
+        assert!(filter.allows(-1));
+        assert!(!filter.allows(1));
+        assert!(!filter.allows(829));
+    }
+}
@@ -0,0 +1,146 @@
+//! Events sent when a store's pages are inserted, updated, or deleted, so embedders can keep
+//! external systems such as search clusters and caches in sync without polling
+//! [`Store::pages_modified_since`](crate::Store::pages_modified_since).
+//!
+//! Like the progress events in [`wikimedia::events`], page events are sent on a broadcast
+//! channel that never blocks the sender, so a slow subscriber can't hold up an import. A
+//! subscriber that falls more than [`CAPACITY`] events behind misses the oldest ones, and its
+//! next receive returns how many it missed, after which it should resynchronise from the
+//! changes API.
+//!
+//! Events are only sent once the change is committed to the index, and the extra index
+//! lookups to build them are skipped while there are no subscribers.
+
+use crate::StorePageId;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+pub use broadcast::{error::RecvError, Receiver};
+
+/// The count of events buffered for each subscriber before the oldest are dropped.
+pub const CAPACITY: usize = 4096;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PageEvent {
+    /// A page with a MediaWiki ID not already in the store was inserted.
+    Inserted(PageEventPage),
+
+    /// A page with a MediaWiki ID already in the store was written again, e.g. by importing
+    /// a later dump, replacing the previous copy in the index.
+    Updated {
+        #[serde(flatten)]
+        page: PageEventPage,
+
+        /// Where the replaced copy of the page was stored.
+        previous_store_id: StorePageId,
+    },
+
+    /// A page was deleted from the store's index.
+    Deleted {
+        mediawiki_id: u64,
+        store_id: StorePageId,
+        slug: String,
+    },
+}
+
+/// The page an [`PageEvent::Inserted`] or [`PageEvent::Updated`] event is about.
+#[derive(Clone, Debug, Serialize)]
+pub struct PageEventPage {
+    pub mediawiki_id: u64,
+    pub store_id: StorePageId,
+    pub ns_id: i64,
+    pub title: String,
+
+    /// The ID of the page's latest revision, if it has one.
+    pub revision_id: Option<u64>,
+}
+
+/// Sends [`PageEvent`]s to every current subscriber. Clones send to the same subscribers.
+#[derive(Clone, Debug)]
+pub struct Sender(broadcast::Sender<PageEvent>);
+
+impl Sender {
+    pub fn new() -> Sender {
+        let (sender, _receiver) = broadcast::channel(CAPACITY);
+        Sender(sender)
+    }
+
+    /// Send an event to the current subscribers without blocking. The event is dropped if
+    /// there are none.
+    pub fn send(&self, event: PageEvent) {
+        let _ = self.0.send(event);
+    }
+
+    /// Whether anything is subscribed, so events are worth building.
+    pub fn has_subscribers(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    /// Receive the events sent from now on.
+    pub fn subscribe(&self) -> Receiver<PageEvent> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for Sender {
+    fn default() -> Sender {
+        Sender::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options as StoreOptions;
+    use super::*;
+    use std::{fs, io::BufWriter};
+    use wikimedia::{
+        dump::{local::{Compression, OpenSpec}, synthetic, DumpName},
+        TempDir,
+    };
+
+    #[test]
+    fn import_sends_inserted_then_updated() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = BufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages: 5,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let mut store = StoreOptions::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .build().unwrap();
+        let mut receiver = store.subscribe_page_events();
+
+        let mut import = || {
+            store.import(open_spec.clone().open().unwrap(), /* continue_import: */ false)
+                 .unwrap();
+            std::iter::from_fn(|| receiver.try_recv().ok()).collect::<Vec<PageEvent>>()
+        };
+
+        let inserted = import();
+        assert_eq!(inserted.len(), 5);
+        assert!(inserted.iter().all(|event| matches!(event, PageEvent::Inserted(_))));
+
+        let updated = import();
+        assert_eq!(updated.len(), 5);
+        let PageEvent::Updated { page, previous_store_id } = &updated[0] else {
+            panic!("Expected an updated event, got {event:?}", event = updated[0]);
+        };
+        assert_ne!(page.store_id.to_string(), previous_store_id.to_string());
+        assert_eq!(serde_json::to_value(&updated[0]).unwrap()["type"], "updated");
+    }
+}
@@ -0,0 +1,412 @@
+//! Read chunk files with positional reads checked against checksums, instead of memory
+//! mapping them, see [`crate::ChunkReadMode::Pread`].
+//!
+//! Each chunk file written by an import has a checksum file next to it,
+//! `articles-{chunk_id:016x}.cap.crc32`, holding the block length as a little endian `u32`,
+//! then the CRC-32 of each block of the chunk file in order, also little endian `u32`s. The
+//! last block may be shorter than the block length.
+//!
+//! Like remote chunks, only the segment table is read when a chunk is opened and each capnp
+//! segment is read when first accessed. A read covers whole blocks, which are checked against
+//! the checksum file and read again if they don't match, e.g. if a network filesystem
+//! returned stale data from its cache. Chunks written before checksum files existed are read
+//! the same way without the check.
+
+use anyhow::{bail, Context};
+use capnp::message::ReaderSegments;
+use crate::remote::{self, SegmentSpec};
+use std::{
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use wikimedia::Result;
+
+/// The capnp segments of a chunk file, each read when first accessed.
+pub(crate) struct PreadSegments {
+    checksums: Option<Checksums>,
+    file: fs::File,
+    path: PathBuf,
+    segments: Vec<SegmentSpec>,
+}
+
+/// The contents of a chunk's checksum file.
+struct Checksums {
+    block_len: u64,
+    crcs: Vec<u32>,
+}
+
+/// Calculates the checksums of a chunk file as it is written, see
+/// [`ChecksumWriter::finish`].
+pub(crate) struct ChecksumWriter<W: Write> {
+    block_filled: u64,
+    block_hasher: crc32fast::Hasher,
+    bytes_len: u64,
+    crcs: Vec<u32>,
+    inner: W,
+}
+
+/// The length of each checksummed block of a chunk file written by an import.
+const BLOCK_LEN: u64 = 64 * 1024;
+
+/// How many times a block is read before a checksum mismatch is returned as an error.
+const READ_ATTEMPTS: u32 = 3;
+
+/// How long to wait before reading a block again after a checksum mismatch.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+impl PreadSegments {
+    /// Open the chunk file at `path` and read its segment table. Returns `None` if there's
+    /// no chunk file.
+    pub(crate) fn open(path: &Path) -> Result<Option<PreadSegments>> {
+        let file = match fs::File::open(path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+            Ok(f) => f,
+        };
+        let checksums = Checksums::read(&checksums_path(path))?;
+
+        let mut segments = PreadSegments {
+            checksums,
+            file,
+            path: path.to_owned(),
+            segments: Vec::new(),
+        };
+
+        // The first word holds the segment count and the length of the first segment.
+        let mut table = segments.read_checked(0, 8)?;
+        let table_len = remote::segment_table_len(remote::segment_count(&table)?);
+        if table_len > 8 {
+            table = segments.read_checked(0, table_len)?;
+        }
+        let (specs, len) = remote::parse_segment_table(&table)?;
+
+        let file_len = segments.file.metadata()?.len();
+        if file_len < len {
+            bail!("Chunk file was shorter than its segment table \
+                   expected_len={len} actual_len={file_len} path='{path}'",
+                  path = path.display());
+        }
+
+        segments.segments = specs;
+        Ok(Some(segments))
+    }
+
+    /// The length of the chunk message in bytes, from its segment table.
+    pub(crate) fn len(&self) -> u64 {
+        self.segments.last()
+            .map(|spec| spec.offset + spec.len_words * 8)
+            .unwrap_or(0)
+    }
+
+    /// Read segment `idx` now if it hasn't been read, so errors reading it are returned
+    /// here instead of logged by [`ReaderSegments::get_segment`].
+    pub(crate) fn load(&self, idx: usize) -> Result<()> {
+        let Some(spec) = self.segments.get(idx) else {
+            bail!("Chunk segment index out of bounds idx={idx} len={len}",
+                  len = self.segments.len());
+        };
+        spec.words.get_or_try_init(|| self.load_segment(spec))?;
+        Ok(())
+    }
+
+    fn load_segment(&self, spec: &SegmentSpec) -> Result<Vec<u64>> {
+        let bytes = self.read_checked(spec.offset, spec.len_words * 8)?;
+        Ok(bytes.chunks_exact(8)
+                .map(|word| u64::from_ne_bytes(word.try_into().expect("8 byte word")))
+                .collect())
+    }
+
+    /// Read `len` bytes at `offset`, checking the blocks they're in against the checksum
+    /// file if there is one.
+    fn read_checked(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let Some(checksums) = self.checksums.as_ref() else {
+            let mut buf = vec![0_u8; usize::try_from(len).expect("usize from u64")];
+            self.read_exact_at(&mut buf, offset)?;
+            return Ok(buf);
+        };
+
+        let block_len = checksums.block_len;
+        let first_block = offset / block_len;
+        let end_block = (offset + len).div_ceil(block_len);
+        let blocks_offset = first_block * block_len;
+
+        let mut buf = Vec::new();
+        for block_idx in first_block..end_block {
+            let expected = *checksums.crcs
+                .get(usize::try_from(block_idx).expect("usize from u64"))
+                .with_context(|| format!(
+                    "Chunk checksum file has no checksum for block {block_idx} path='{path}'",
+                    path = self.path.display()))?;
+            let block = self.read_block(block_idx * block_len, block_len, expected)?;
+            buf.extend_from_slice(&block);
+        }
+
+        let start = usize::try_from(offset - blocks_offset).expect("usize from u64");
+        let end = start + usize::try_from(len).expect("usize from u64");
+        if buf.len() < end {
+            bail!("Chunk file was truncated reading offset={offset} len={len} path='{path}'",
+                  path = self.path.display());
+        }
+        buf.truncate(end);
+        buf.drain(..start);
+        Ok(buf)
+    }
+
+    /// Read the block at `offset`, at most `block_len` bytes, retrying until its CRC-32 is
+    /// `expected`.
+    fn read_block(&self, offset: u64, block_len: u64, expected: u32) -> Result<Vec<u8>> {
+        let mut attempt = 1;
+        loop {
+            let mut block = vec![0_u8; usize::try_from(block_len).expect("usize from u64")];
+            let read_len = self.read_at_most(&mut block, offset)?;
+            block.truncate(read_len);
+
+            let actual = crc32fast::hash(&block);
+            if actual == expected {
+                return Ok(block);
+            }
+            if attempt >= READ_ATTEMPTS {
+                bail!("Chunk block checksum mismatch after {attempt} reads \
+                       offset={offset} expected={expected:08x} actual={actual:08x} \
+                       path='{path}'",
+                      path = self.path.display());
+            }
+
+            tracing::warn!(offset, attempt, path = %self.path.display(),
+                           "Chunk block checksum mismatch, reading it again");
+            attempt += 1;
+            thread::sleep(RETRY_DELAY);
+        }
+    }
+
+    /// Read until `buf` is full or the end of the file, returning the count of bytes read.
+    fn read_at_most(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match read_at(&self.file, &mut buf[filled..],
+                          offset + u64::try_from(filled).expect("u64 from usize")) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).with_context(|| format!(
+                    "reading chunk file offset={offset} path='{path}'",
+                    path = self.path.display())),
+            }
+        }
+        Ok(filled)
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        let len = self.read_at_most(buf, offset)?;
+        if len < buf.len() {
+            bail!("Chunk file was truncated reading offset={offset} len={expected} \
+                   path='{path}'",
+                  expected = buf.len(),
+                  path = self.path.display());
+        }
+        Ok(())
+    }
+}
+
+impl ReaderSegments for PreadSegments {
+    fn get_segment(&self, idx: u32) -> Option<&[u8]> {
+        let idx = usize::try_from(idx).expect("usize from u32");
+        let spec = self.segments.get(idx)?;
+
+        // ReaderSegments can't return an error, so log any error and return None,
+        // which the capnp reader will report as a missing segment.
+        let words = spec.words.get_or_try_init(|| self.load_segment(spec))
+            .map_err(|e| tracing::error!(err = format!("{e:#}"),
+                                         path = %self.path.display(), idx,
+                                         "Error reading chunk segment"))
+            .ok()?;
+
+        // Safety: any initialised `[u64]` is also a valid `[u8]` of 8 times the length,
+        // and the returned slice borrows `words`, which lives as long as `self`.
+        Some(unsafe {
+            std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8)
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.segments.len()
+    }
+}
+
+impl Checksums {
+    /// Read the checksum file at `path`, or `None` if there isn't one.
+    fn read(path: &Path) -> Result<Option<Checksums>> {
+        let bytes = match fs::read(path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!(
+                "reading chunk checksum file path='{path}'", path = path.display())),
+            Ok(bytes) => bytes,
+        };
+
+        let mut words = bytes.chunks(4).map(|word| -> Result<u32> {
+            Ok(u32::from_le_bytes(word.try_into().with_context(|| format!(
+                "Chunk checksum file length is not a multiple of 4 path='{path}'",
+                path = path.display()))?))
+        });
+        let block_len = match words.next() {
+            None | Some(Ok(0)) => bail!("Chunk checksum file has no block length path='{path}'",
+                                        path = path.display()),
+            Some(block_len) => u64::from(block_len?),
+        };
+
+        Ok(Some(Checksums {
+            block_len,
+            crcs: words.collect::<Result<Vec<u32>>>()?,
+        }))
+    }
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub(crate) fn new(inner: W) -> ChecksumWriter<W> {
+        ChecksumWriter {
+            block_filled: 0,
+            block_hasher: crc32fast::Hasher::new(),
+            bytes_len: 0,
+            crcs: Vec::new(),
+            inner,
+        }
+    }
+
+    /// The count of bytes written so far.
+    pub(crate) fn bytes_len(&self) -> u64 {
+        self.bytes_len
+    }
+
+    /// Write the checksum file for the bytes written to `path` and sync it, returning the
+    /// inner writer.
+    pub(crate) fn finish(mut self, path: &Path) -> Result<W> {
+        if self.block_filled > 0 {
+            self.crcs.push(self.block_hasher.clone().finalize());
+        }
+
+        let mut bytes = Vec::with_capacity((self.crcs.len() + 1) * 4);
+        bytes.extend_from_slice(&u32::try_from(BLOCK_LEN).expect("u32 from BLOCK_LEN")
+                                    .to_le_bytes());
+        for crc in self.crcs.iter() {
+            bytes.extend_from_slice(&crc.to_le_bytes());
+        }
+
+        // Closure to add context to errors.
+        (|| {
+            let mut file = fs::File::create(path)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+            anyhow::Ok(())
+        })().with_context(|| format!("writing chunk checksum file path='{path}'",
+                                     path = path.display()))?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Write at most to the end of the current block, so each block's bytes go to its
+        // own hasher.
+        let block_remaining = usize::try_from(BLOCK_LEN - self.block_filled)
+                                  .expect("usize from u64");
+        let len = buf.len().min(block_remaining);
+        let written = self.inner.write(&buf[..len])?;
+
+        self.block_hasher.update(&buf[..written]);
+        self.block_filled += u64::try_from(written).expect("u64 from usize");
+        self.bytes_len += u64::try_from(written).expect("u64 from usize");
+        if self.block_filled == BLOCK_LEN {
+            let hasher = std::mem::replace(&mut self.block_hasher, crc32fast::Hasher::new());
+            self.crcs.push(hasher.finalize());
+            self.block_filled = 0;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The path of the checksum file for the chunk file at `chunk_path`.
+pub(crate) fn checksums_path(chunk_path: &Path) -> PathBuf {
+    let mut name = chunk_path.file_name().expect("chunk file name").to_owned();
+    name.push(".crc32");
+    chunk_path.with_file_name(name)
+}
+
+/// Read from `file` at `offset` without moving its cursor, so one file can be read from
+/// many threads.
+#[cfg(unix)]
+fn read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{self, ChunkId};
+    use super::*;
+    use wikimedia::TempDir;
+
+    /// Write `bytes` to a chunk file and its checksum file in a new temporary directory.
+    fn write_chunk(bytes: &[u8]) -> (TempDir, PathBuf) {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let path = dir.path().unwrap().join(chunk::chunk_file_name(ChunkId(0)));
+        let mut writer = ChecksumWriter::new(fs::File::create(&path).unwrap());
+        writer.write_all(bytes).unwrap();
+        writer.finish(&checksums_path(&path)).unwrap();
+        (dir, path)
+    }
+
+    fn message_bytes(words: u32) -> Vec<u8> {
+        // 1 segment of `words` words.
+        let mut bytes: Vec<u8> = [0_u32, words].iter().flat_map(|n| n.to_le_bytes()).collect();
+        bytes.extend((0..(words * 8)).map(|i| (i % 251) as u8));
+        bytes
+    }
+
+    #[test]
+    fn checksums_cover_partial_last_block() {
+        let bytes = message_bytes(20_000);
+        let (_dir, path) = write_chunk(&bytes);
+
+        let checksums = Checksums::read(&checksums_path(&path)).unwrap().unwrap();
+        assert_eq!(checksums.block_len, BLOCK_LEN);
+        assert_eq!(checksums.crcs.len(), 3);
+        assert_eq!(checksums.crcs[2], crc32fast::hash(&bytes[(2 * 64 * 1024)..]));
+    }
+
+    #[test]
+    fn segment_read_across_blocks() {
+        let bytes = message_bytes(20_000);
+        let (_dir, path) = write_chunk(&bytes);
+
+        let segments = PreadSegments::open(&path).unwrap().unwrap();
+        assert_eq!(segments.len(), u64::try_from(bytes.len()).unwrap());
+        assert_eq!(segments.get_segment(0).unwrap(), &bytes[8..]);
+    }
+
+    #[test]
+    fn corrupt_block_is_an_error() {
+        let mut bytes = message_bytes(20_000);
+        let (_dir, path) = write_chunk(&bytes);
+
+        bytes[100_000] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+
+        let segments = PreadSegments::open(&path).unwrap().unwrap();
+        let err = segments.load(0).unwrap_err();
+        assert!(format!("{err:#}").contains("checksum mismatch"), "err = {err:#}");
+    }
+}
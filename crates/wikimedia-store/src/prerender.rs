@@ -0,0 +1,259 @@
+//! Store pages' HTML with them, so a web server can serve it without rendering their
+//! wikitext on each request. Rendering with pandoc can take seconds for a long page on a
+//! low-power server, while reading pre-rendered HTML takes as long as reading the wikitext.
+//!
+//! Chunks are immutable, so [`Store::prerender`] writes each chunk's pages again with their
+//! HTML to new chunks, points the index at the new copies, and removes the old chunk file.
+//! Only the current copy of each page is written. Pre-rendered HTML takes about as much disk
+//! space again as the wikitext.
+//!
+//! A page written again by an import or [`Store::upsert_page`] has no HTML until it is
+//! rendered again, so HTML never belongs to an older revision.
+
+use crate::{
+    chunk,
+    ChunkStore,
+    compact::{commit_chunk, current_store_ids},
+    recover_pending_chunks,
+    remove_empty_chunks,
+    Store,
+};
+use serde::Serialize;
+use std::time::{Duration as StdDuration, Instant};
+use wikimedia::{
+    bail_kind,
+    cancel,
+    dump,
+    Result,
+    util::fmt::Duration,
+};
+
+/// The result of [`Store::prerender`].
+#[derive(Clone, Debug, Serialize)]
+pub struct PrerenderReport {
+    /// Chunk files read.
+    pub chunks_read: u64,
+
+    /// Chunk files written with pages' HTML.
+    pub chunks_written: u64,
+
+    /// Chunk files replaced by the chunks written, now removed.
+    pub chunks_removed: u64,
+
+    pub pages_rendered: u64,
+
+    /// Pages that already had HTML, kept as it was.
+    pub pages_already_rendered: u64,
+
+    /// Pages the render function returned no HTML for.
+    pub pages_not_rendered: u64,
+
+    pub duration: Duration,
+}
+
+impl Store {
+    /// Render the current copy of each page in the store to HTML with `render` and store
+    /// the HTML with the page, see [`prerender`](crate::prerender). Read it back with
+    /// [`MappedPage::rendered_html`](crate::MappedPage::rendered_html).
+    ///
+    /// `render` returns `None` to leave a page without HTML, e.g. for namespaces that are
+    /// rarely viewed. Pages that already have HTML are only rendered again if `rerender` is
+    /// true, and chunks whose pages all have HTML are not written again, so running this
+    /// again after an import only renders the pages imported.
+    ///
+    /// Takes the store's write lock. Stores with remote chunks can't be pre-rendered. If the
+    /// store's [`Options::cancellation`](crate::Options::cancellation) token is cancelled,
+    /// the chunks already written are kept, and a
+    /// [`Cancelled`](wikimedia::ErrorKind::Cancelled) error is returned. Run this again to
+    /// render the rest.
+    #[tracing::instrument(level = "debug", name = "Store::prerender()", skip(self, render),
+                          fields(self.path = %self.opts.path.display()))]
+    pub fn prerender(
+        &mut self,
+        rerender: bool,
+        mut render: impl FnMut(&dump::Page) -> Result<Option<String>>,
+    ) -> Result<PrerenderReport> {
+        let start = Instant::now();
+
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot pre-render pages in a store with remote chunks.");
+        };
+        let chunk_write_guard = chunk_store.try_write_lock()?;
+        recover_pending_chunks(&chunk_write_guard, &mut self.index)?;
+
+        // Chunks written below get new IDs, so they aren't read again.
+        let chunk_ids = chunk_write_guard.chunk_ids()?;
+        tracing::info!(chunks_len = chunk_ids.len(), rerender, "Pre-rendering store pages");
+
+        let mut report = PrerenderReport {
+            chunks_read: 0,
+            chunks_written: 0,
+            chunks_removed: 0,
+            pages_rendered: 0,
+            pages_already_rendered: 0,
+            pages_not_rendered: 0,
+            duration: Duration(StdDuration::ZERO),
+        };
+
+        for chunk_id in chunk_ids {
+            cancel::check(&self.opts.cancellation, "Pre-rendering store pages")?;
+
+            let Some(chunk) = chunk_write_guard.map_chunk(chunk_id)? else {
+                continue;
+            };
+            report.chunks_read += 1;
+
+            // The index's copy of each page, so superseded and deleted copies are skipped.
+            let current = current_store_ids(&self.index, chunk_id, &chunk)?;
+
+            let mut unrendered = false;
+            for (store_id, page_cap) in chunk.pages_iter()? {
+                if current.contains(&store_id) {
+                    unrendered |= rerender || chunk::rendered_html(&page_cap)?.is_none();
+                }
+            }
+            if !unrendered {
+                report.pages_already_rendered += current.len() as u64;
+                continue;
+            }
+
+            let provenance = self.index.get_chunk_provenance(chunk_id)?;
+            let mut chunk_builder = chunk_write_guard.chunk_builder()?;
+            let mut index_batch_builder = self.index.import_batch_builder()?;
+            let mut chunk_pages_len = 0_u64;
+            for (store_id, page_cap) in chunk.pages_iter()? {
+                if !current.contains(&store_id) {
+                    continue;
+                }
+
+                let page = dump::Page::try_from(&page_cap)?;
+                let html = match chunk::rendered_html(&page_cap)? {
+                    Some(html) if !rerender => {
+                        report.pages_already_rendered += 1;
+                        Some(html)
+                    },
+                    _ => {
+                        let html = render(&page)?;
+                        match html {
+                            Some(_) => report.pages_rendered += 1,
+                            None => report.pages_not_rendered += 1,
+                        }
+                        html
+                    },
+                };
+
+                let new_store_id = chunk_builder.push_with_rendered_html(&page, html.as_deref())?;
+                index_batch_builder.move_page(page.id, new_store_id);
+                chunk_pages_len += 1;
+
+                // Pages are larger with their HTML, so one chunk may need several.
+                if chunk_builder.is_full() {
+                    commit_chunk(&self.index, chunk_builder, index_batch_builder,
+                                 provenance.as_ref())?;
+                    report.chunks_written += 1;
+                    chunk_builder = chunk_write_guard.chunk_builder()?;
+                    index_batch_builder = self.index.import_batch_builder()?;
+                    chunk_pages_len = 0;
+                }
+            }
+            if chunk_pages_len > 0 {
+                commit_chunk(&self.index, chunk_builder, index_batch_builder,
+                             provenance.as_ref())?;
+                report.chunks_written += 1;
+            }
+
+            drop(chunk);
+            report.chunks_removed += remove_empty_chunks(
+                &chunk_write_guard, &mut self.index, [chunk_id])?.len() as u64;
+
+            tracing::debug!(%chunk_id, pages_rendered = report.pages_rendered,
+                            "Pre-rendered chunk");
+        }
+
+        report.duration = Duration(start.elapsed());
+        tracing::info!(chunks_read = report.chunks_read,
+                       chunks_written = report.chunks_written,
+                       chunks_removed = report.chunks_removed,
+                       pages_rendered = report.pages_rendered,
+                       pages_already_rendered = report.pages_already_rendered,
+                       pages_not_rendered = report.pages_not_rendered,
+                       duration = %report.duration,
+                       "Pre-rendered store pages");
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Options as StoreOptions, Store};
+    use std::{fs, io::BufWriter};
+    use wikimedia::{
+        dump::{self, DumpName, local::{Compression, OpenSpec}, synthetic},
+        TempDir,
+    };
+
+    #[test]
+    fn prerender_pages() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = BufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages: 5,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let mut store: Store = StoreOptions::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .build().unwrap();
+        store.import(open_spec.open().unwrap(), /* continue_import: */ false).unwrap();
+        let rendered_html = |store: &Store, slug: &str| -> Option<String> {
+            store.get_page_by_slug(slug).unwrap().unwrap().rendered_html().unwrap()
+        };
+        assert_eq!(rendered_html(&store, "Page_2"), None);
+
+        // Leave page 5, "Page 4", unrendered.
+        let render = |page: &dump::Page| -> wikimedia::Result<Option<String>> {
+            Ok((page.id != 5).then(|| format!("<p>{title}</p>", title = page.title)))
+        };
+        let report = store.prerender(/* rerender: */ false, render).unwrap();
+        assert_eq!((report.chunks_read, report.chunks_written, report.chunks_removed),
+                   (1, 1, 1));
+        assert_eq!((report.pages_rendered, report.pages_not_rendered), (4, 1));
+        assert_eq!(rendered_html(&store, "Page_2").unwrap(), "<p>Page 2</p>");
+        assert_eq!(rendered_html(&store, "Page_4"), None);
+
+        // Pages with HTML are kept; the rest are rendered.
+        let report = store.prerender(/* rerender: */ false, |_| Ok(Some("new".to_string())))
+                          .unwrap();
+        assert_eq!((report.pages_rendered, report.pages_already_rendered), (1, 4));
+        assert_eq!(rendered_html(&store, "Page_2").unwrap(), "<p>Page 2</p>");
+        assert_eq!(rendered_html(&store, "Page_4").unwrap(), "new");
+
+        // A page written again has no HTML until it's rendered again.
+        let mut page = dump::Page::try_from(
+            &store.get_page_by_slug("Page_2").unwrap().unwrap().borrow().unwrap()).unwrap();
+        page.title = "Corrected page".to_string();
+        store.upsert_page(&page).unwrap();
+        assert_eq!(rendered_html(&store, "Corrected_page"), None);
+        let report = store.prerender(/* rerender: */ false, |_| Ok(Some("new".to_string())))
+                          .unwrap();
+        assert_eq!((report.chunks_written, report.pages_rendered), (1, 1));
+        assert_eq!(rendered_html(&store, "Corrected_page").unwrap(), "new");
+
+        let fsck = store.fsck_index(/* delete: */ false).unwrap();
+        assert!(fsck.is_ok(), "{fsck:?}");
+        assert_eq!(store.chunks_len().unwrap(), 1);
+    }
+}
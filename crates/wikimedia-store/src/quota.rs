@@ -0,0 +1,98 @@
+//! Limits on a store's size, see [`Quota`].
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+use valuable::Valuable;
+
+/// Limits on a store's size, checked by [`Store::import`](crate::Store::import) before each
+/// chunk is written, and by [`Store::upsert_page`](crate::Store::upsert_page) and
+/// [`Store::upsert_pages`](crate::Store::upsert_pages) before writing new pages. See
+/// [`Options::quota`](crate::Options::quota).
+///
+/// Each import thread checks the quota before writing a chunk, so an import may exceed it by
+/// up to one chunk per thread.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Valuable)]
+pub struct Quota {
+    /// The most bytes of chunk files the store may have. Unlimited if `None`.
+    pub max_chunk_bytes: Option<u64>,
+
+    /// The most pages the store may have. Unlimited if `None`.
+    pub max_pages: Option<u64>,
+}
+
+/// Which part of a [`Quota`] was exceeded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QuotaLimit {
+    ChunkBytes,
+    Pages,
+}
+
+/// Error returned when an import or upsert stops because the store reached its [`Quota`].
+/// The chunks written before that were committed.
+///
+/// Returned wrapped in an [`Error`](wikimedia::Error); check for it with
+/// `err.downcast_ref::<QuotaExceeded>()`.
+#[derive(Clone, Debug)]
+pub struct QuotaExceeded {
+    pub limit: QuotaLimit,
+    pub max: u64,
+    pub used: u64,
+}
+
+impl Quota {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_chunk_bytes.is_none() && self.max_pages.is_none()
+    }
+
+    /// Check a store with `chunk_bytes` bytes of chunk files and `pages` pages has room for
+    /// another chunk.
+    pub fn check(&self, chunk_bytes: u64, pages: u64) -> Result<(), QuotaExceeded> {
+        if let Some(max) = self.max_chunk_bytes.filter(|max| chunk_bytes >= *max) {
+            return Err(QuotaExceeded { limit: QuotaLimit::ChunkBytes, max, used: chunk_bytes });
+        }
+        if let Some(max) = self.max_pages.filter(|max| pages >= *max) {
+            return Err(QuotaExceeded { limit: QuotaLimit::Pages, max, used: pages });
+        }
+        Ok(())
+    }
+}
+
+impl Display for QuotaLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QuotaLimit::ChunkBytes => "chunk_bytes",
+            QuotaLimit::Pages => "pages",
+        })
+    }
+}
+
+impl Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Store quota exceeded limit={limit} max={max} used={used}",
+               limit = self.limit, max = self.max, used = self.used)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        assert!(Quota::default().check(u64::MAX, u64::MAX).is_ok());
+
+        let quota = Quota {
+            max_chunk_bytes: Some(1000),
+            max_pages: Some(10),
+        };
+        assert!(quota.check(999, 9).is_ok());
+
+        let err = quota.check(1000, 0).unwrap_err();
+        assert_eq!((err.limit, err.max, err.used), (QuotaLimit::ChunkBytes, 1000, 1000));
+
+        let err = quota.check(0, 12).unwrap_err();
+        assert_eq!((err.limit, err.max, err.used), (QuotaLimit::Pages, 10, 12));
+    }
+}
@@ -0,0 +1,323 @@
+//! Rebuild a store's index from its chunk files, e.g. after the index file is lost or
+//! corrupted, without importing the dump again.
+//!
+//! Every index table derived from pages is regenerated: pages and their full text search,
+//! categories, properties, links, templates, and the chunk list. Saved searches, import runs,
+//! and chunk provenance are kept if the index still has them. Import file progress is lost,
+//! so continuing an interrupted import starts its files again.
+//!
+//! A page imported again leaves its previous copy in an older chunk, as can a store written
+//! by an older version, which didn't write chunks again without replaced and deleted pages.
+//! Chunks are read newest first, and only the newest copy of each MediaWiki ID is indexed.
+//! Pages deleted by [`Store::apply_deletion_list`] with the default audit log are not indexed
+//! again, but pages deleted with another audit log are, if their copy still exists. Deleted
+//! copies are recognised by MediaWiki ID and revision ID, so a page written again with a new
+//! revision after it was deleted is indexed.
+
+use crate::{ChunkStore, deletion, recover_pending_chunks, remove_empty_chunks, Store};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    time::{Duration as StdDuration, Instant},
+};
+use wikimedia::{
+    bail_kind,
+    cancel,
+    dump,
+    Result,
+    util::fmt::Duration,
+};
+
+/// The most MediaWiki IDs looked up in the index in one query.
+pub(crate) const LOOKUP_IDS_MAX: usize = 500;
+
+/// The result of [`Store::rebuild_index`].
+#[derive(Clone, Debug, Serialize)]
+pub struct RebuildReport {
+    /// Chunk files read.
+    pub chunks_len: u64,
+
+    pub pages_indexed: u64,
+
+    /// Older copies of pages written again later, not indexed.
+    pub pages_superseded: u64,
+
+    /// Pages recorded as deleted in the deletion audit log, not indexed.
+    pub pages_deleted: u64,
+
+    /// Chunk files with no pages left to index, now removed.
+    pub chunks_removed: u64,
+
+    pub duration: Duration,
+}
+
+impl Store {
+    /// Clear the store's index and index every page in its chunk files again. See
+    /// [`rebuild`](crate::rebuild).
+    ///
+    /// If the index file is corrupted so the store can't be opened, delete `index.db` in the
+    /// store's index directory first; a new empty index is created when the store is opened.
+    ///
+    /// Takes the store's write lock. Stores with remote chunks can't be rebuilt. If the
+    /// store's [`Options::cancellation`](crate::Options::cancellation) token is cancelled,
+    /// the chunks already read stay indexed, and a
+    /// [`Cancelled`](wikimedia::ErrorKind::Cancelled) error is returned. Run this again to
+    /// finish the index.
+    #[tracing::instrument(level = "debug", name = "Store::rebuild_index()", skip_all,
+                          fields(self.path = %self.opts.path.display(),
+                                 self.index_path = %self.opts.index_path.display()))]
+    pub fn rebuild_index(&mut self) -> Result<RebuildReport> {
+        let start = Instant::now();
+
+        let ChunkStore::Local(chunk_store) = &mut self.chunk_store else {
+            bail_kind!(User, "Cannot rebuild the index of a store with remote chunks.");
+        };
+        let chunk_write_guard = chunk_store.try_write_lock()?;
+
+        // Finish or remove chunks an interrupted import left pending, so each chunk file is
+        // either complete or gone.
+        recover_pending_chunks(&chunk_write_guard, &mut self.index)?;
+
+        let deleted = deletion::read_deleted_pages(
+            &self.opts.path.join(deletion::AUDIT_LOG_FILE_NAME))?;
+        let chunk_ids = chunk_write_guard.chunk_ids()?;
+        tracing::info!(chunks_len = chunk_ids.len(), deleted_len = deleted.len(),
+                       "Rebuilding store index from chunk files");
+
+        self.index.clear()?;
+
+        let mut report = RebuildReport {
+            chunks_len: 0,
+            pages_indexed: 0,
+            pages_superseded: 0,
+            pages_deleted: 0,
+            chunks_removed: 0,
+            duration: Duration(StdDuration::ZERO),
+        };
+
+        // Newest first, so the first copy of a page indexed is its latest.
+        for chunk_id in chunk_ids.into_iter().rev() {
+            cancel::check(&self.opts.cancellation, "Rebuilding the store index")?;
+
+            let Some(chunk) = chunk_write_guard.map_chunk(chunk_id)? else {
+                continue;
+            };
+            report.chunks_len += 1;
+
+            let mut candidates = Vec::new();
+            for (store_id, page_cap) in chunk.pages_iter()? {
+                if deleted.contains(&page_cap, store_id)? {
+                    report.pages_deleted += 1;
+                } else {
+                    candidates.push(page_cap.get_id());
+                }
+            }
+
+            let mut indexed = HashSet::<u64>::with_capacity(candidates.len());
+            for ids in candidates.chunks(LOOKUP_IDS_MAX) {
+                indexed.extend(self.index.get_pages_by_mediawiki_ids(ids)?
+                                   .into_iter()
+                                   .map(|page| page.mediawiki_id));
+            }
+
+            let mut index_batch_builder = self.index.import_batch_builder()?;
+            let mut chunk_pages_indexed = 0_u64;
+            for (store_id, page_cap) in chunk.pages_iter()? {
+                if deleted.contains(&page_cap, store_id)? {
+                    continue;
+                }
+                if !indexed.insert(page_cap.get_id()) {
+                    report.pages_superseded += 1;
+                    continue;
+                }
+                index_batch_builder.push(&dump::Page::try_from(&page_cap)?, store_id)?;
+                chunk_pages_indexed += 1;
+            }
+            index_batch_builder.set_chunk(&chunk.meta()?);
+            index_batch_builder.commit()?;
+            report.pages_indexed += chunk_pages_indexed;
+
+            if chunk_pages_indexed == 0 {
+                drop(chunk);
+                report.chunks_removed += remove_empty_chunks(
+                    &chunk_write_guard, &mut self.index, [chunk_id])?.len() as u64;
+            }
+
+            tracing::debug!(%chunk_id, chunk_pages_indexed,
+                            pages_indexed = report.pages_indexed, "Rebuilt chunk index");
+        }
+
+        self.index.rebuild_spelling()?;
+        self.index.optimise()?;
+
+        report.duration = Duration(start.elapsed());
+        tracing::info!(chunks_len = report.chunks_len, pages_indexed = report.pages_indexed,
+                       pages_superseded = report.pages_superseded,
+                       pages_deleted = report.pages_deleted,
+                       chunks_removed = report.chunks_removed,
+                       duration = %report.duration,
+                       "Rebuilt store index");
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{deletion, index, Options as StoreOptions, Store};
+    use std::{fs, io::BufWriter};
+    use wikimedia::{
+        dump::{self, DumpName, local::{Compression, OpenSpec}, synthetic},
+        TempDir,
+    };
+
+    #[test]
+    fn rebuild_index_from_chunks() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = BufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages: 5,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let store_path = dir_path.join("store");
+        let open_store = || -> Store {
+            StoreOptions::default()
+                .dump_name(DumpName("syntheticwiki".to_string()))
+                .path(store_path.clone())
+                .build().unwrap()
+        };
+        let mut store = open_store();
+        store.import(open_spec.open().unwrap(), /* continue_import: */ false).unwrap();
+
+        // Correct page 3, "Page 2", and delete page 1, "Page 0", with a deletion list. Both
+        // write the chunk again, so neither old copy is left to index.
+        store.upsert_page(&dump::Page {
+            ns_id: 0,
+            id: 3,
+            title: "Corrected page".to_string(),
+            revision: Some(dump::Revision {
+                id: 2_000_003,
+                parent_id: None,
+                timestamp: None,
+                text: Some("Corrected text.".to_string()),
+                sha1: None,
+                categories: Vec::new(),
+            }),
+            restrictions: None,
+        }).unwrap();
+        let mut deletion_options = deletion::Options::default();
+        deletion_options.batch_interval(std::time::Duration::ZERO);
+        store.apply_deletion_list(&deletion::parse_list(&b"1\n"[..]).unwrap(),
+                                  &deletion_options).unwrap();
+        store.save_search(&index::SavedSearch {
+            name: "Pages".to_string(),
+            query: "page".to_string(),
+            min_words: None,
+            max_words: None,
+            category: None,
+            namespace: None,
+            order: index::PageSearchOrder::Relevance,
+            saved: chrono::Utc::now(),
+        }).unwrap();
+
+        // Lose the index.
+        drop(store);
+        fs::remove_file(store_path.join("index").join("index.db")).unwrap();
+        let mut store = open_store();
+        assert!(store.get_page_by_mediawiki_id(2).unwrap().is_none());
+
+        let report = store.rebuild_index().unwrap();
+        assert_eq!(report.chunks_len, 1);
+        assert_eq!(report.pages_indexed, 4);
+        assert_eq!(report.pages_superseded, 0);
+        assert_eq!(report.pages_deleted, 0);
+        assert_eq!(report.chunks_removed, 0);
+
+        assert!(store.get_page_by_mediawiki_id(1).unwrap().is_none());
+        assert!(store.get_page_by_slug("Page_2").unwrap().is_none());
+        assert!(store.get_page_by_slug("Corrected_page").unwrap().is_some());
+        let pages = store.page_search("page 4", &index::PageSearchOptions::default()).unwrap();
+        assert_eq!(pages[0].mediawiki_id, 5);
+        assert_eq!(store.chunks_len().unwrap(), 1);
+
+        let fsck = store.fsck_index(/* delete: */ false).unwrap();
+        assert!(fsck.is_ok(), "{fsck:?}");
+    }
+
+    #[test]
+    fn rebuild_after_last_chunk_removed() {
+        let dir = TempDir::create(&std::env::temp_dir().join("wikimedia-store-tests"),
+                                  /* keep: */ false).unwrap();
+        let dir_path = dir.path().unwrap();
+
+        let dump_path = dir_path.join("synthetic.xml");
+        let mut dump_file = BufWriter::new(fs::File::create(&dump_path).unwrap());
+        synthetic::write_dump(&mut dump_file, &synthetic::Options {
+            pages: 3,
+            text_len: 100,
+            ..synthetic::Options::default()
+        }).unwrap();
+        drop(dump_file);
+
+        let mut open_spec = OpenSpec::builder();
+        open_spec.file(dump_path)
+                 .compression(Compression::None);
+        let open_spec = open_spec.build().unwrap();
+
+        let mut store = StoreOptions::default()
+            .dump_name(DumpName("syntheticwiki".to_string()))
+            .path(dir_path.join("store"))
+            .build().unwrap();
+        store.import(open_spec.open().unwrap(), /* continue_import: */ false).unwrap();
+        let removed_chunk_id = store.chunk_id_iter().next().unwrap().unwrap();
+
+        // Delete every page, which removes the store's only, and so highest, chunk.
+        let mut deletion_options = deletion::Options::default();
+        deletion_options.batch_interval(std::time::Duration::ZERO);
+        let report = store.apply_deletion_list(&deletion::parse_list(&b"1\n2\n3\n"[..])
+                                                   .unwrap(),
+                                               &deletion_options).unwrap();
+        assert_eq!(report.chunks_removed, 1);
+        assert_eq!(store.chunks_len().unwrap(), 0);
+
+        // Write pages again: page 1 with a new revision, and a new page. Their chunk doesn't
+        // reuse the removed chunk's ID, so they're not taken for the deleted pages.
+        for (id, title) in [(1, "Restored page"), (10, "New page")] {
+            store.upsert_page(&dump::Page {
+                ns_id: 0,
+                id,
+                title: title.to_string(),
+                revision: Some(dump::Revision {
+                    id: 3_000_000 + id,
+                    parent_id: None,
+                    timestamp: None,
+                    text: Some("New text.".to_string()),
+                    sha1: None,
+                    categories: Vec::new(),
+                }),
+                restrictions: None,
+            }).unwrap();
+        }
+        assert!(store.chunk_id_iter().map(Result::unwrap)
+                     .all(|chunk_id| chunk_id > removed_chunk_id));
+
+        let report = store.rebuild_index().unwrap();
+        assert_eq!(report.pages_indexed, 2);
+        assert_eq!(report.pages_deleted, 0);
+        assert!(store.get_page_by_slug("Restored_page").unwrap().is_some());
+        assert!(store.get_page_by_slug("New_page").unwrap().is_some());
+        assert!(store.get_page_by_mediawiki_id(2).unwrap().is_none());
+    }
+}
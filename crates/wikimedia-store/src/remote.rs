@@ -0,0 +1,409 @@
+//! Read a store whose files are hosted on a static HTTP server.
+//!
+//! The remote server should serve the store's default layout under a base URL:
+//! the sqlite index at `{base_url}/index/index.db` and chunk files at
+//! `{base_url}/chunks/articles-{chunk_id:016x}.cap`. The server must support HTTP range
+//! requests.
+//!
+//! The index is downloaded once and then reused. Chunks are read one capnp segment at a
+//! time, fetched with range requests when first accessed and cached locally.
+
+use anyhow::{bail, Context};
+use capnp::message::ReaderSegments;
+use crate::chunk::{self, ChunkId, ChunkMeta, ChunkSegments, MappedChunk, MappedPage,
+                   StorePageId};
+use once_cell::sync::OnceCell;
+use std::{
+    fs,
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::runtime::{Runtime, RuntimeFlavor};
+use wikimedia::{
+    dump::DumpName,
+    http,
+    Result,
+    try2,
+};
+
+pub(crate) struct RemoteChunkStore {
+    base_url: String,
+    blocker: Blocker,
+    cache_path: PathBuf,
+    client: http::Client,
+    dump_name: DumpName,
+}
+
+pub(crate) struct Options {
+    pub base_url: String,
+
+    /// The local directory to cache fetched chunk segments in.
+    pub cache_path: PathBuf,
+    pub dump_name: DumpName,
+}
+
+/// The capnp segments of a remote chunk. Each segment is fetched when first accessed.
+pub(crate) struct RemoteSegments {
+    blocker: Blocker,
+    cache_dir: PathBuf,
+    client: http::Client,
+    segments: Vec<SegmentSpec>,
+    url: String,
+}
+
+pub(crate) struct SegmentSpec {
+    /// Offset of the segment from the start of the chunk file in bytes.
+    pub offset: u64,
+
+    /// Length of the segment in 8 byte words.
+    pub len_words: u64,
+
+    /// The segment contents once fetched. Stored as `u64` to get 8 byte alignment,
+    /// as required by capnp.
+    pub words: OnceCell<Vec<u64>>,
+}
+
+/// Runs futures to completion from synchronous store code, see [`Blocker::block_on`].
+///
+/// Clones share one runtime, which is built the first time it is needed.
+#[derive(Clone, Default)]
+struct Blocker(Arc<BlockerRuntime>);
+
+#[derive(Default)]
+struct BlockerRuntime(OnceCell<Runtime>);
+
+const INDEX_FILE_NAME: &str = "index.db";
+const SEGMENT_TABLE_FILE_NAME: &str = "segment-table";
+
+impl Options {
+    pub fn build(self) -> Result<RemoteChunkStore> {
+        let client = http::download_client(&http::Options::builder()
+                                                .cache_path(self.cache_path.join("http_cache"))
+                                                .build()?)?;
+
+        Ok(RemoteChunkStore {
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            blocker: Blocker::default(),
+            cache_path: self.cache_path,
+            client,
+            dump_name: self.dump_name,
+        })
+    }
+}
+
+impl RemoteChunkStore {
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Download the remote index to `index_path`, unless a copy is already there.
+    pub fn download_index(&self, index_path: &Path) -> Result<()> {
+        let file_path = index_path.join(INDEX_FILE_NAME);
+        if file_path.try_exists()? {
+            tracing::debug!(path = %file_path.display(),
+                            "RemoteChunkStore::download_index() reusing existing index");
+            return Ok(());
+        }
+
+        fs::create_dir_all(index_path)?;
+
+        let temp_path = index_path.join(format!("{INDEX_FILE_NAME}.download"));
+        if temp_path.try_exists()? {
+            fs::remove_file(&temp_path)?;
+        }
+
+        let url = format!("{base}/index/{INDEX_FILE_NAME}", base = self.base_url);
+        tracing::info!(url, path = %file_path.display(), "Downloading remote store index");
+
+        let request = self.client.get(&url).build()?;
+        self.blocker.block_on(http::download_file(&self.client, request, &temp_path, None))??;
+        fs::rename(&temp_path, &file_path)?;
+
+        Ok(())
+    }
+
+    pub fn get_page_by_store_id(&self, id: StorePageId) -> Result<Option<MappedPage>> {
+        let chunk: MappedChunk = try2!(self.map_chunk(id.chunk_id));
+        let page: MappedPage = chunk.get_mapped_page(id.page_chunk_index)?;
+        Ok(Some(page))
+    }
+
+    pub fn get_chunk_meta_by_chunk_id(&self, chunk_id: ChunkId) -> Result<Option<ChunkMeta>> {
+        let chunk = try2!(self.map_chunk(chunk_id));
+        Ok(Some(chunk.meta()?))
+    }
+
+    /// Open a remote chunk. Only the chunk's segment table is fetched here, the segments
+    /// themselves are fetched when first accessed.
+    pub fn map_chunk(&self, id: ChunkId) -> Result<Option<MappedChunk>> {
+        let file_name = chunk::chunk_file_name(id);
+        let url = format!("{base}/chunks/{file_name}", base = self.base_url);
+        let cache_dir = self.cache_path.join(format!("{file_name}.segments"));
+
+        // Closure to add context to errors.
+        (|| -> Result<Option<MappedChunk>> {
+            let table = match self.get_segment_table(&url, &cache_dir)? {
+                None => return Ok(None),
+                Some(table) => table,
+            };
+
+            let (segments, len) = parse_segment_table(&table)?;
+
+            let segments = RemoteSegments {
+                blocker: self.blocker.clone(),
+                cache_dir: cache_dir.clone(),
+                client: self.client.clone(),
+                segments,
+                url: url.clone(),
+            };
+
+            Ok(Some(MappedChunk::new(self.dump_name.clone(), id, len, cache_dir.clone(),
+                                     ChunkSegments::Remote(segments))))
+        })().with_context(|| format!("While opening remote chunk url='{url}'"))
+    }
+
+    /// Returns the raw capnp segment table from the start of the chunk file,
+    /// from the local cache if present, otherwise fetched from the server.
+    fn get_segment_table(&self, url: &str, cache_dir: &Path) -> Result<Option<Vec<u8>>> {
+        let cache_file = cache_dir.join(SEGMENT_TABLE_FILE_NAME);
+        if let Some(table) = read_cache_file(&cache_file)? {
+            return Ok(Some(table));
+        }
+
+        // The first word holds the segment count and the length of the first segment.
+        let Some(mut table) =
+            self.blocker.block_on(http::fetch_range(&self.client, url, 0, 8))??
+        else {
+            return Ok(None);
+        };
+
+        let table_len = segment_table_len(segment_count(&table)?);
+        if table_len > 8 {
+            let Some(rest) =
+                self.blocker.block_on(http::fetch_range(&self.client, url, 8, table_len - 8))??
+            else {
+                bail!("Remote chunk not found after reading the start of its segment table");
+            };
+            table.extend_from_slice(&rest);
+        }
+
+        write_cache_file(&cache_file, &table)?;
+
+        Ok(Some(table))
+    }
+}
+
+impl RemoteSegments {
+    fn load_segment(&self, idx: usize, spec: &SegmentSpec) -> Result<Vec<u64>> {
+        let len_bytes = spec.len_words * 8;
+        let cache_file = self.cache_dir.join(format!("segment-{idx:04}"));
+
+        let bytes = match read_cache_file(&cache_file)? {
+            Some(bytes) => bytes,
+            None => {
+                let bytes = self.blocker.block_on(http::fetch_range(&self.client, &self.url,
+                                                                    spec.offset, len_bytes))??
+                                .ok_or_else(|| anyhow::format_err!(
+                                    "Remote chunk not found while fetching a segment"))?;
+                write_cache_file(&cache_file, &bytes)?;
+                bytes
+            }
+        };
+
+        if u64::try_from(bytes.len()).expect("usize into u64") != len_bytes {
+            bail!("Remote chunk segment was the wrong length \
+                   expected={len_bytes} actual={actual} cache_file='{cache_file}'",
+                  actual = bytes.len(),
+                  cache_file = cache_file.display());
+        }
+
+        Ok(bytes.chunks_exact(8)
+                .map(|word| u64::from_ne_bytes(word.try_into().expect("8 byte word")))
+                .collect())
+    }
+}
+
+impl ReaderSegments for RemoteSegments {
+    fn get_segment(&self, idx: u32) -> Option<&[u8]> {
+        let idx = usize::try_from(idx).expect("usize from u32");
+        let spec = self.segments.get(idx)?;
+
+        // ReaderSegments can't return an error, so log any error and return None,
+        // which the capnp reader will report as a missing segment.
+        let words = spec.words.get_or_try_init(|| self.load_segment(idx, spec))
+            .map_err(|e| tracing::error!(err = format!("{e:#}"), url = self.url, idx,
+                                         "Error fetching remote chunk segment"))
+            .ok()?;
+
+        // Safety: any initialised `[u64]` is also a valid `[u8]` of 8 times the length,
+        // and the returned slice borrows `words`, which lives as long as `self`.
+        Some(unsafe {
+            std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8)
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.segments.len()
+    }
+}
+
+/// Parse a capnp stream segment table, returning each segment's location and
+/// the total length in bytes of the chunk file.
+pub(crate) fn parse_segment_table(table: &[u8]) -> Result<(Vec<SegmentSpec>, u64)> {
+    let count = segment_count(table)?;
+    let table_len = segment_table_len(count);
+    if u64::try_from(table.len()).expect("usize into u64") < table_len {
+        bail!("Chunk segment table was truncated \
+               segment_count={count} table_len={len}",
+              len = table.len());
+    }
+
+    let mut segments = Vec::with_capacity(count.try_into().expect("usize from u64"));
+    let mut offset = table_len;
+    for idx in 0..count {
+        let start = usize::try_from(4 + idx * 4).expect("usize from u64");
+        let len_words = u64::from(u32::from_le_bytes(
+            table[start..(start + 4)].try_into().expect("4 byte slice")));
+        segments.push(SegmentSpec {
+            offset,
+            len_words,
+            words: OnceCell::new(),
+        });
+        offset += len_words * 8;
+    }
+
+    Ok((segments, offset))
+}
+
+pub(crate) fn segment_count(table: &[u8]) -> Result<u64> {
+    if table.len() < 4 {
+        bail!("Chunk segment table was too short len={len}", len = table.len());
+    }
+    let count_minus_one = u32::from_le_bytes(table[0..4].try_into().expect("4 byte slice"));
+    Ok(u64::from(count_minus_one) + 1)
+}
+
+/// The length in bytes of a segment table for `count` segments, including padding.
+pub(crate) fn segment_table_len(count: u64) -> u64 {
+    // A u32 for the count, a u32 for each segment's length, then padding to a whole word.
+    (4 * (count + 1)).next_multiple_of(8)
+}
+
+fn read_cache_file(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("While reading cache file '{path}'",
+                                                 path = path.display())),
+    }
+}
+
+fn write_cache_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+
+    // Closure to add context to errors.
+    (|| {
+        fs::create_dir_all(path.parent().expect("parent of cache file path"))?;
+        fs::write(&temp_path, bytes)?;
+        fs::rename(&temp_path, path)?;
+        anyhow::Ok(())
+    })().with_context(|| format!("While writing cache file '{path}'",
+                                 path = path.display()))
+}
+
+impl Blocker {
+    /// Run a future to completion from synchronous store code.
+    ///
+    /// Inside a multi-threaded tokio runtime this blocks the current worker thread.
+    /// Outside any runtime the future runs on this blocker's own runtime. Inside a
+    /// current thread runtime, whose only thread can't be blocked, the future runs on
+    /// this blocker's runtime from a separate thread.
+    fn block_on<F>(&self, future: F) -> Result<F::Output>
+        where F: Future + Send,
+              F::Output: Send
+    {
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread =>
+                Ok(tokio::task::block_in_place(|| handle.block_on(future))),
+            // A thread that is already running a runtime can't block on another one.
+            Ok(_) => std::thread::scope(|scope| {
+                scope.spawn(|| Ok(self.runtime()?.block_on(future)))
+                     .join()
+                     .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            }),
+            Err(_) => Ok(self.runtime()?.block_on(future)),
+        }
+    }
+
+    fn runtime(&self) -> Result<&Runtime> {
+        // A multi-threaded runtime, so several threads can block on it at once.
+        self.0.0.get_or_try_init(|| {
+            Ok(tokio::runtime::Builder::new_multi_thread()
+                   .worker_threads(1)
+                   .thread_name("remote-store")
+                   .enable_all()
+                   .build()?)
+        })
+    }
+}
+
+impl Drop for BlockerRuntime {
+    fn drop(&mut self) {
+        // Dropping a runtime waits for its tasks, which panics inside another runtime,
+        // so shut it down without waiting.
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_table_len_pads_to_word() {
+        assert_eq!(segment_table_len(1), 8);
+        assert_eq!(segment_table_len(2), 16);
+        assert_eq!(segment_table_len(3), 16);
+        assert_eq!(segment_table_len(4), 24);
+    }
+
+    #[test]
+    fn parse_segment_table_offsets() {
+        // 2 segments: 3 words and 5 words, then 4 bytes of padding.
+        let table: Vec<u8> = [1u32, 3, 5, 0].iter().flat_map(|n| n.to_le_bytes()).collect();
+        let (segments, len) = parse_segment_table(&table).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].offset, segments[0].len_words), (16, 3));
+        assert_eq!((segments[1].offset, segments[1].len_words), (40, 5));
+        assert_eq!(len, 80);
+    }
+
+    #[test]
+    fn block_on_without_runtime_reuses_runtime() {
+        let blocker = Blocker::default();
+        assert_eq!(blocker.block_on(async { 1 }).unwrap(), 1);
+        let runtime: *const Runtime = blocker.runtime().unwrap();
+        assert_eq!(blocker.block_on(async { 2 }).unwrap(), 2);
+        assert!(std::ptr::eq(runtime, blocker.runtime().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn block_on_in_current_thread_runtime() {
+        let blocker = Blocker::default();
+        let out = blocker.block_on(async {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            3
+        }).unwrap();
+        assert_eq!(out, 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn block_on_in_multi_thread_runtime() {
+        let blocker = Blocker::default();
+        assert_eq!(blocker.block_on(async { 4 }).unwrap(), 4);
+    }
+}
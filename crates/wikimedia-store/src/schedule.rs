@@ -0,0 +1,210 @@
+//! Scheduling parallel work on source files and chunks, shared by imports and verification.
+//!
+//! Work runs on a dedicated rayon thread pool when a thread count is set, so heavy
+//! maintenance can be kept to a few cores on a machine that is also serving requests, and
+//! otherwise on rayon's global pool. Separately, an IO limit caps how many files are read at
+//! once, e.g. 1 or 2 on a hard disk, where parallel reads of different files mostly seek.
+//! A write rate limit caps the rate imports write chunks, so a background import doesn't
+//! saturate the disk.
+
+use rayon::ThreadPool;
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+use wikimedia::{bail_kind, Result};
+
+pub(crate) struct Scheduler {
+    /// `None` to use rayon's global pool.
+    pool: Option<ThreadPool>,
+    io_limit: IoLimit,
+
+    /// `None` for no limit.
+    write_limit: Option<RateLimit>,
+}
+
+/// A token bucket of bytes, refilled at `bytes_per_sec` up to one second's worth.
+struct RateLimit {
+    bytes_per_sec: u64,
+
+    /// Bytes available and when they were counted. Negative after a write larger than
+    /// those available, until refilled.
+    bucket: Mutex<(f64, Instant)>,
+}
+
+/// A counting semaphore for files being read.
+struct IoLimit {
+    max: usize,
+    in_use: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permission to read a file, see [`Scheduler::io_permit`]. Released when dropped.
+pub(crate) struct IoPermit<'limit> {
+    limit: &'limit IoLimit,
+}
+
+impl Scheduler {
+    /// Schedule work on `threads` threads, or rayon's global pool if `None`, with at most
+    /// `io_concurrency` files read at once, and at most `write_bytes_per_sec` written per
+    /// second. Each limit is unlimited if `None`.
+    pub(crate) fn new(
+        threads: Option<usize>,
+        io_concurrency: Option<usize>,
+        write_bytes_per_sec: Option<u64>,
+    ) -> Result<Scheduler>
+    {
+        if threads == Some(0) {
+            bail_kind!(User, "The thread count must be at least 1.");
+        }
+        if io_concurrency == Some(0) {
+            bail_kind!(User, "The IO concurrency must be at least 1.");
+        }
+        if write_bytes_per_sec == Some(0) {
+            bail_kind!(User, "The write rate limit must be at least 1 byte per second.");
+        }
+
+        let pool = match threads {
+            None => None,
+            Some(threads) => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|idx| format!("wikimedia-store-{idx}"))
+                    .build()?),
+        };
+
+        Ok(Scheduler {
+            pool,
+            io_limit: IoLimit {
+                max: io_concurrency.unwrap_or(usize::MAX),
+                in_use: Mutex::new(0),
+                released: Condvar::new(),
+            },
+            write_limit: write_bytes_per_sec.map(|bytes_per_sec| RateLimit {
+                bytes_per_sec,
+                bucket: Mutex::new((bytes_per_sec as f64, Instant::now())),
+            }),
+        })
+    }
+
+    /// Run `op` on the scheduler's thread pool, so rayon parallel iterators in it use that
+    /// pool's threads.
+    pub(crate) fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match self.pool.as_ref() {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// The count of threads work is scheduled on.
+    pub(crate) fn threads(&self) -> usize {
+        match self.pool.as_ref() {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        }
+    }
+
+    /// Wait until fewer than the IO concurrency limit of files are being read, then return a
+    /// permit to read one. Hold it while reading the file.
+    pub(crate) fn io_permit(&self) -> IoPermit<'_> {
+        let limit = &self.io_limit;
+        let mut in_use = limit.in_use.lock().expect("IoLimit lock");
+        while *in_use >= limit.max {
+            in_use = limit.released.wait(in_use).expect("IoLimit lock");
+        }
+        *in_use += 1;
+
+        IoPermit {
+            limit,
+        }
+    }
+
+    /// The write rate limit in bytes per second, if set.
+    pub(crate) fn write_rate_limit(&self) -> Option<u64> {
+        self.write_limit.as_ref().map(|limit| limit.bytes_per_sec)
+    }
+
+    /// Count `bytes` just written against the write rate limit, then wait until the write
+    /// rate is back under the limit. Returns how long this waited.
+    pub(crate) fn throttle_write(&self, bytes: u64) -> Duration {
+        let Some(limit) = self.write_limit.as_ref() else {
+            return Duration::ZERO;
+        };
+        let rate = limit.bytes_per_sec as f64;
+
+        let wait = {
+            let mut bucket = limit.bucket.lock().expect("RateLimit lock");
+            let (available, counted) = &mut *bucket;
+            let now = Instant::now();
+            *available = (*available + now.duration_since(*counted).as_secs_f64() * rate)
+                             .min(rate);
+            *counted = now;
+            *available -= bytes as f64;
+            if *available < 0.0 {
+                Duration::from_secs_f64(-*available / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        // Sleep without the lock, other threads' writes wait behind this one's debt.
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        wait
+    }
+}
+
+impl Drop for IoPermit<'_> {
+    fn drop(&mut self) {
+        *self.limit.in_use.lock().expect("IoLimit lock") -= 1;
+        self.limit.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::*;
+
+    #[test]
+    fn io_limit_caps_concurrent_permits() {
+        let scheduler = Scheduler::new(Some(4), Some(2), None).unwrap();
+        assert_eq!(scheduler.threads(), 4);
+
+        let reading = AtomicUsize::new(0);
+        let max_reading = AtomicUsize::new(0);
+        scheduler.install(|| (0..32).into_par_iter().for_each(|_| {
+            let _permit = scheduler.io_permit();
+            let curr = reading.fetch_add(1, Ordering::SeqCst) + 1;
+            max_reading.fetch_max(curr, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            reading.fetch_sub(1, Ordering::SeqCst);
+        }));
+
+        assert!(max_reading.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn zero_counts_rejected() {
+        assert!(Scheduler::new(Some(0), None, None).is_err());
+        assert!(Scheduler::new(None, Some(0), None).is_err());
+        assert!(Scheduler::new(None, None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn write_limit_throttles_after_burst() {
+        let scheduler = Scheduler::new(None, None, Some(1000)).unwrap();
+        assert_eq!(scheduler.write_rate_limit(), Some(1000));
+
+        // The first second's worth is written without waiting, the next waits for it.
+        assert_eq!(scheduler.throttle_write(1000), Duration::ZERO);
+        let wait = scheduler.throttle_write(100);
+        assert!(wait > Duration::from_millis(50) && wait <= Duration::from_millis(100),
+                "{wait:?}");
+
+        let unlimited = Scheduler::new(None, None, None).unwrap();
+        assert_eq!(unlimited.throttle_write(u64::MAX), Duration::ZERO);
+    }
+}
@@ -0,0 +1,542 @@
+//! Data types used in Wikimedia data dumps and their metadata.
+
+mod namespace;
+pub use namespace::{Case as NamespaceCase, Namespace};
+
+use chrono::{DateTime, FixedOffset};
+use crate::{
+    bail_kind,
+    Error,
+    Result,
+    fmt::Sha1Hash,
+    slug,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    result::Result as StdResult,
+    str::FromStr,
+};
+use valuable::Valuable;
+
+/// The dumps server's index of the latest run of every dump, from `index.json`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DumpsIndex {
+    pub wikis: BTreeMap<String, DumpIndexEntry>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DumpIndexEntry {
+    #[serde(default)]
+    pub jobs: BTreeMap<String, JobStatus>,
+}
+
+/// Metadata about a dump available on the dumps server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DumpInfo {
+    pub name: DumpName,
+
+    /// The version of the latest run of this dump, if known.
+    pub latest_version: Option<Version>,
+
+    /// When a job in the latest run of this dump was last updated, if known.
+    /// Formatted like `2023-03-21 08:30:24`.
+    pub last_updated: Option<String>,
+
+    /// The overall status of the latest run of this dump, if known.
+    pub status: Option<Status>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DumpVersionStatus {
+    pub jobs: BTreeMap<String, JobStatus>,
+
+    #[allow(dead_code)] // Not used currently
+    pub version: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobStatus {
+    pub status: Status,
+
+    /// When the job was last updated, formatted like `2023-03-21 08:30:24`.
+    #[serde(default)]
+    pub updated: String,
+
+    #[serde(default)]
+    pub files: BTreeMap<String, FileMetadata>,
+}
+
+/// The status of a dump job, or of a file in a job.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Done,
+    InProgress,
+    Failed,
+    Waiting,
+    Skipped,
+
+    /// A status string not listed above.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FileMetadata {
+    /// File length in bytes. Missing for jobs with status "waiting".
+    pub size: Option<u64>,
+
+    /// File relative URL under the dumps root. Missing for jobs with status "waiting".
+    pub url: Option<String>,
+
+    /// Expected SHA1 hash of the file's data, formatted as a lowercase hex string.
+    pub sha1: Option<String>,
+
+    #[allow(dead_code)] // Not used currently
+    pub md5: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobOutput {
+    pub name: String,
+
+    /// Sum of the sizes of each file.
+    pub files_size: u64,
+
+    /// Count of files.
+    pub files_count: usize,
+
+    /// Count of files with status done. Files can be done before the whole job is.
+    pub files_done_count: usize,
+
+    /// The status of each file, by file name.
+    pub file_statuses: BTreeMap<String, Status>,
+
+    #[serde(flatten)]
+    pub status: JobStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileInfoOutput {
+    pub name: String,
+
+    pub status: Status,
+
+    #[serde(flatten)]
+    pub metadata: FileMetadata,
+}
+
+/// The name of a dump, e.g. `enwiki`.
+///
+/// Construct with `str::parse` to validate a name from user input: names are used in store
+/// and download paths, so must be 1 to 64 lowercase ASCII letters, digits, `_` or `-`.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize, Valuable)]
+#[serde(transparent)]
+pub struct DumpName(pub String);
+
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize, Valuable)]
+#[serde(transparent)]
+pub struct Version(pub String);
+
+#[derive(Clone, Debug)]
+pub enum VersionSpec {
+    Latest,
+    Version(Version),
+}
+
+/// The name of a job in a dump version, e.g. `articlesdump`.
+///
+/// Construct with `str::parse` to validate a name from user input, with the same rules as
+/// [`DumpName`]. See [`WELL_KNOWN_JOBS`] for the jobs commonly found in a dump version.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Valuable)]
+pub struct JobName(pub String);
+
+/// Jobs commonly found in a dump version, with a description of the files each writes.
+///
+/// The dumps server's `dumpstatus.json` lists the jobs in a version without descriptions.
+pub const WELL_KNOWN_JOBS: &[(&str, &str)] = &[
+    (JobName::ARTICLES,
+     "Current revisions of articles, templates, and file description pages \
+      (pages-articles)"),
+    (JobName::ARTICLES_MULTISTREAM,
+     "Current revisions of articles etc. as in articlesdump, in bzip2 streams of 100 pages \
+      with an index of stream offsets (pages-articles-multistream)"),
+    ("metacurrentdump", "Current revisions of all pages, including talk and user pages \
+                         (pages-meta-current)"),
+    (JobName::META_HISTORY,
+     "All revisions of all pages, compressed with bzip2 (pages-meta-history)"),
+    ("metahistory7zdump", "All revisions of all pages, compressed with 7-Zip \
+                           (pages-meta-history)"),
+    ("xmlstubsdump", "Page and revision metadata without revision text (stub-*)"),
+    ("abstractsdump", "The first paragraph of each article (abstract)"),
+    ("allpagetitlesdump", "The titles of all pages (all-titles)"),
+    ("namespaces", "The site's name and namespaces (siteinfo-namespaces)"),
+    ("pagetable", "Base data for each page, as an SQL table (page)"),
+    ("categorylinkstable", "Each page's categories, as an SQL table (categorylinks)"),
+    ("redirecttable", "Each redirect's target, as an SQL table (redirect)"),
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Page {
+    pub ns_id: i64,
+    pub id: u64,
+    pub title: String,
+    pub revision: Option<Revision>,
+
+    /// The page's protection from the dump's `<restrictions>`, e.g.
+    /// `edit=autoconfirmed:move=sysop`. Chunk files don't store this, so it is `None` for
+    /// pages read from a store; the store's index records it as a page property.
+    pub restrictions: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Revision {
+    pub id: u64,
+    pub parent_id: Option<u64>,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub text: Option<String>,
+    pub sha1: Option<Sha1Hash>,
+    pub categories: Vec<PageCategory>,
+}
+
+/// A page's membership of a category, from a link such as
+/// `[[Category:Living people|Smith, John]]`.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct PageCategory {
+    pub name: CategoryName,
+
+    /// The key the page is sorted by in the category's page listing, from the link or the
+    /// page's `{{DEFAULTSORT:}}`, e.g. `Smith, John`. The page title is used when this is
+    /// `None`.
+    pub sort_key: Option<String>,
+}
+
+/// A category's display name without the `Category:` prefix, e.g. `Machine learning`.
+///
+/// Construct with [`CategoryName::new`] or `str::parse` to normalise names written by hand.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+pub struct CategoryName(pub String);
+
+/// A category's slug without the `Category:` prefix, e.g. `Machine_learning`.
+///
+/// The store's index and URLs refer to categories by slug. Construct with
+/// [`CategorySlug::new`] or `str::parse` to accept either a name or a slug.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+pub struct CategorySlug(pub String);
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Done => "done",
+            Status::InProgress => "in-progress",
+            Status::Failed => "failed",
+            Status::Waiting => "waiting",
+            Status::Skipped => "skipped",
+            Status::Unknown => "unknown",
+        })
+    }
+}
+
+impl JobStatus {
+    /// Returns the files in this job that are done, even if the whole job is not.
+    pub fn done_files(&self) -> impl Iterator<Item = (&String, &FileMetadata)> {
+        self.files.iter().filter(|(_name, meta)| meta.status(self.status) == Status::Done)
+    }
+}
+
+impl DumpIndexEntry {
+    /// Summarise the status of all jobs in this dump run.
+    ///
+    /// Returns `None` if there are no jobs.
+    pub fn status(&self) -> Option<Status> {
+        if self.jobs.is_empty() {
+            return None;
+        }
+
+        let any = |status: Status| self.jobs.values().any(|job| job.status == status);
+        Some(if any(Status::InProgress) {
+            Status::InProgress
+        } else if any(Status::Failed) {
+            Status::Failed
+        } else if self.jobs.values().all(|job| matches!(job.status,
+                                                        Status::Done | Status::Skipped)) {
+            Status::Done
+        } else {
+            Status::Waiting
+        })
+    }
+
+    /// The latest time a job in this dump run was updated.
+    pub fn last_updated(&self) -> Option<&str> {
+        self.jobs.values().map(|job| &*job.updated).filter(|s| !s.is_empty()).max()
+    }
+}
+
+impl FileMetadata {
+    /// The status of this file, given the status of its job.
+    ///
+    /// While a job is running, each file is done once its size, URL and SHA1 hash have been
+    /// published.
+    pub fn status(&self, job_status: Status) -> Status {
+        match job_status {
+            Status::Done => Status::Done,
+            _ if self.size.is_some() && self.url.is_some() && self.sha1.is_some()
+                => Status::Done,
+            job_status => job_status,
+        }
+    }
+}
+
+impl Display for CategoryName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Category:{name}", name = self.0)
+    }
+}
+
+impl CategoryName {
+    /// Normalise a category name or slug, with or without the `Category:` prefix,
+    /// e.g. `Category:Machine_learning` to `Machine learning`.
+    pub fn new(name_or_slug: &str) -> CategoryName {
+        let name_or_slug = name_or_slug.trim();
+        let name = match name_or_slug.get(..CATEGORY_PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(CATEGORY_PREFIX)
+                => &name_or_slug[CATEGORY_PREFIX.len()..],
+            _ => name_or_slug,
+        };
+        CategoryName(slug::normalize_title(name))
+    }
+
+    pub fn to_slug(&self) -> CategorySlug {
+        CategorySlug(slug::title_to_slug(&self.0))
+    }
+}
+
+impl CategorySlug {
+    /// Normalise a category name or slug, with or without the `Category:` prefix,
+    /// e.g. `Category:Machine learning` to `Machine_learning`.
+    pub fn new(name_or_slug: &str) -> CategorySlug {
+        CategoryName::new(name_or_slug).to_slug()
+    }
+
+    pub fn to_name(&self) -> CategoryName {
+        CategoryName(slug::slug_to_title(&self.0))
+    }
+}
+
+const CATEGORY_PREFIX: &str = "Category:";
+
+impl From<&CategoryName> for CategorySlug {
+    fn from(name: &CategoryName) -> CategorySlug {
+        name.to_slug()
+    }
+}
+
+impl From<&CategorySlug> for CategoryName {
+    fn from(slug: &CategorySlug) -> CategoryName {
+        slug.to_name()
+    }
+}
+
+impl FromStr for CategoryName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CategoryName> {
+        Ok(CategoryName::new(s))
+    }
+}
+
+impl FromStr for CategorySlug {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CategorySlug> {
+        Ok(CategorySlug::new(s))
+    }
+}
+
+impl FromStr for DumpName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DumpName> {
+        validate_name("dump", s)?;
+        Ok(DumpName(s.to_string()))
+    }
+}
+
+impl JobName {
+    pub const ARTICLES: &'static str = "articlesdump";
+    pub const ARTICLES_MULTISTREAM: &'static str = "articlesmultistreamdump";
+    pub const META_HISTORY: &'static str = "metahistorybz2dump";
+
+    pub fn articles() -> JobName {
+        JobName(JobName::ARTICLES.to_string())
+    }
+
+    pub fn articles_multistream() -> JobName {
+        JobName(JobName::ARTICLES_MULTISTREAM.to_string())
+    }
+
+    pub fn meta_history() -> JobName {
+        JobName(JobName::META_HISTORY.to_string())
+    }
+
+    /// The description of this job from [`WELL_KNOWN_JOBS`], if it is listed there.
+    pub fn description(&self) -> Option<&'static str> {
+        WELL_KNOWN_JOBS.iter()
+                       .find(|(name, _)| *name == self.0)
+                       .map(|(_, description)| *description)
+    }
+}
+
+impl FromStr for JobName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<JobName> {
+        validate_name("job", s)?;
+        Ok(JobName(s.to_string()))
+    }
+}
+
+const NAME_MAX_LEN: usize = 64;
+
+fn validate_name(kind: &str, s: &str) -> Result<()> {
+    if s.is_empty() || s.len() > NAME_MAX_LEN {
+        bail_kind!(User, "The {kind} name must be 1 to {NAME_MAX_LEN} characters long, \
+                          but was {len} characters long.",
+                   len = s.len());
+    }
+    if !lazy_regex!(r"^[a-z0-9_-]+$").is_match(s) {
+        bail_kind!(User, "The {kind} name '{s}' must contain only lowercase ASCII letters, \
+                          digits, '_', and '-'.");
+    }
+    Ok(())
+}
+
+impl FromStr for VersionSpec {
+    type Err = clap::Error;
+
+    fn from_str(s: &str) -> StdResult<VersionSpec, clap::Error> {
+        if s == "latest" {
+            return Ok(VersionSpec::Latest);
+        }
+
+        if lazy_regex!(r"^\d{8}$").is_match(s) {
+            Ok(VersionSpec::Version(Version(s.to_string())))
+        } else {
+            Err(clap::error::Error::raw(
+                clap::error::ErrorKind::ValueValidation,
+                "The value must be 8 numerical digits (e.g. \"20230301\") \
+                 or the string \"latest\"."))
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = clap::Error;
+
+    fn from_str(s: &str) -> StdResult<Version, clap::Error> {
+        if lazy_regex!(r"^\d{8}$").is_match(s) {
+            Ok(Version(s.to_string()))
+        } else {
+            Err(clap::error::Error::raw(
+                clap::error::ErrorKind::ValueValidation,
+                "The value must be 8 numerical digits (e.g. \"20230301\")."))
+        }
+    }
+}
+
+impl Page {
+    pub fn revision_text(&self) -> Option<&str> {
+        self.revision.as_ref()
+            .and_then(|r| r.text.as_ref())
+            .map(|t| t.as_str())
+    }
+
+    pub fn namespace(&self) -> Result<Namespace> {
+        Namespace::from_key(self.ns_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dumps_index_status() {
+        let index: DumpsIndex = serde_json::from_str(r#"{
+            "wikis": {
+                "enwiki": {
+                    "jobs": {
+                        "articlesdump": {
+                            "status": "done",
+                            "updated": "2023-03-21 08:30:24",
+                            "files": {
+                                "enwiki-20230320-pages-articles1.xml.bz2": {
+                                    "size": 10,
+                                    "url": "/enwiki/20230320/enwiki-20230320-pages-articles1.xml.bz2",
+                                    "sha1": "abc"
+                                }
+                            }
+                        },
+                        "metahistorybz2dump": {
+                            "status": "in-progress",
+                            "updated": "2023-03-22 01:02:03",
+                            "files": {
+                                "done.xml.bz2": { "size": 1, "url": "/a", "sha1": "abc" },
+                                "waiting.xml.bz2": {}
+                            }
+                        },
+                        "xmlstubsdumprecombine": {
+                            "status": "some-new-status",
+                            "updated": "2023-03-20 00:00:00"
+                        }
+                    }
+                },
+                "emptywiki": {}
+            }
+        }"#).unwrap();
+
+        let enwiki = &index.wikis["enwiki"];
+        assert_eq!(enwiki.status(), Some(Status::InProgress));
+        assert_eq!(enwiki.last_updated(), Some("2023-03-22 01:02:03"));
+        assert_eq!(enwiki.jobs["xmlstubsdumprecombine"].status, Status::Unknown);
+
+        let history = &enwiki.jobs["metahistorybz2dump"];
+        let done = history.done_files().map(|(name, _)| &**name).collect::<Vec<&str>>();
+        assert_eq!(done, vec!["done.xml.bz2"]);
+        assert_eq!(history.files["waiting.xml.bz2"].status(history.status),
+                   Status::InProgress);
+
+        assert_eq!(index.wikis["emptywiki"].status(), None);
+    }
+
+    #[test]
+    fn category_name_and_slug() {
+        let name = CategoryName::new("Category:Machine_learning");
+        assert_eq!(name, CategoryName("Machine learning".to_string()));
+        assert_eq!(name.to_slug(), CategorySlug("Machine_learning".to_string()));
+        assert_eq!(name.to_slug().to_name(), name);
+
+        assert_eq!("category: Living  people".parse::<CategorySlug>().unwrap(),
+                   CategorySlug("Living_people".to_string()));
+        assert_eq!(CategorySlug::new("Living_people"), CategorySlug::new("Living people"));
+    }
+
+    #[test]
+    fn dump_and_job_name_validation() {
+        assert_eq!("zh_classicalwiki".parse::<DumpName>().unwrap().0, "zh_classicalwiki");
+        assert!("".parse::<DumpName>().is_err());
+        assert!("../enwiki".parse::<DumpName>().is_err());
+        assert!("EnWiki".parse::<DumpName>().is_err());
+        assert!("a".repeat(65).parse::<DumpName>().is_err());
+
+        let job = "articlesdump".parse::<JobName>().unwrap();
+        assert_eq!(job, JobName::articles());
+        assert!(job.description().is_some());
+        assert_eq!("unlistedjob".parse::<JobName>().unwrap().description(), None);
+        assert!("articles dump".parse::<JobName>().is_err());
+    }
+}
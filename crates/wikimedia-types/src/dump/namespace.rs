@@ -1,10 +1,15 @@
 use anyhow::bail;
 use crate::Result;
-use std::cmp::PartialEq;
+use std::{cmp::PartialEq, str::FromStr};
 
+/// A MediaWiki namespace, e.g. `Talk` or `Category`.
+///
+/// Namespace keys are signed: the virtual namespaces `Media` (-2) and `Special` (-1) have
+/// negative keys. Page namespace IDs are `i64` throughout: in dump XML, the store's chunk
+/// schema and its index.
 #[derive(Clone, Debug, Eq)]
 pub struct Namespace {
-    key: i32,
+    key: i64,
     case: Case,
     name: Option<&'static str>,
     talk: bool,
@@ -18,7 +23,7 @@ pub enum Case {
 
 /// Accessors
 impl Namespace {
-    pub fn key(&self) -> i32 {
+    pub fn key(&self) -> i64 {
         self.key
     }
 
@@ -37,6 +42,17 @@ impl Namespace {
     pub fn talk(&self) -> bool {
         self.talk
     }
+
+    /// Returns true for the virtual namespaces `Media` and `Special`, which have no pages
+    /// of their own in a dump or the store.
+    pub fn is_virtual(&self) -> bool {
+        Self::is_virtual_key(self.key)
+    }
+
+    /// Returns true if a namespace key is for a virtual namespace, including unknown keys.
+    pub fn is_virtual_key(key: i64) -> bool {
+        key < 0
+    }
 }
 
 /// Lookup
@@ -61,6 +77,10 @@ impl Namespace {
             13 => Self::HELP_TALK,
             14 => Self::CATEGORY,
             15 => Self::CATEGORY_TALK,
+            100 => Self::PORTAL,
+            101 => Self::PORTAL_TALK,
+            118 => Self::DRAFT,
+            119 => Self::DRAFT_TALK,
             710 => Self::TIMEDTEXT,
             711 => Self::TIMEDTEXT_TALK,
             828 => Self::MODULE,
@@ -73,7 +93,7 @@ impl Namespace {
             _ => bail!("Namespace not found with key {key}"),
         };
 
-        assert_eq!(ns.key as i64, key);
+        assert_eq!(ns.key, key);
         Ok(ns)
     }
 
@@ -97,6 +117,10 @@ impl Namespace {
             Some("Help talk") => Self::HELP_TALK,
             Some("Category") => Self::CATEGORY,
             Some("Category talk") => Self::CATEGORY_TALK,
+            Some("Portal") => Self::PORTAL,
+            Some("Portal talk") => Self::PORTAL_TALK,
+            Some("Draft") => Self::DRAFT,
+            Some("Draft talk") => Self::DRAFT_TALK,
             Some("TimedText") => Self::TIMEDTEXT,
             Some("TimedText talk") => Self::TIMEDTEXT_TALK,
             Some("Module") => Self::MODULE,
@@ -110,27 +134,26 @@ impl Namespace {
         })
     }
 
-    pub fn from_page_slug(slug: &str) -> Result<Namespace> {
-        let prefix = match slug.split_once(':') {
-            None => None,
-            Some((prefix, _)) => Some(prefix.replace('_', " ")),
-        };
-
-        let prefix_str = match prefix.as_ref() {
-            None => None,
-            Some(p) => Some(p.as_str()),
-        };
-
-        Self::from_name(prefix_str)
+    /// The namespace of a page from its slug, e.g. `Category:Living_people`.
+    ///
+    /// Slugs without a known namespace prefix are in the main namespace, as in MediaWiki,
+    /// e.g. `Star_Wars:_Episode_I`.
+    pub fn from_page_slug(slug: &str) -> Namespace {
+        match slug.split_once(':') {
+            None => Self::PAGE,
+            Some((prefix, _)) =>
+                Self::from_name(Some(&*prefix.replace('_', " "))).unwrap_or(Self::PAGE),
+        }
     }
 
-    pub fn from_page_title(title: &str) -> Result<Namespace> {
-        let prefix = match title.split_once(':') {
-            None => None,
-            Some((prefix, _)) => Some(prefix),
-        };
-
-        Self::from_name(prefix)
+    /// The namespace of a page from its title, e.g. `Category:Living people`.
+    ///
+    /// Titles without a known namespace prefix are in the main namespace, as in MediaWiki.
+    pub fn from_page_title(title: &str) -> Namespace {
+        match title.split_once(':') {
+            None => Self::PAGE,
+            Some((prefix, _)) => Self::from_name(Some(prefix)).unwrap_or(Self::PAGE),
+        }
     }
 }
 
@@ -262,6 +285,34 @@ impl Namespace {
         talk: true,
     };
 
+    pub const PORTAL: Namespace = Namespace {
+        key: 100,
+        case: Case::FirstLetter,
+        name: Some("Portal"),
+        talk: false,
+    };
+
+    pub const PORTAL_TALK: Namespace = Namespace {
+        key: 101,
+        case: Case::FirstLetter,
+        name: Some("Portal talk"),
+        talk: true,
+    };
+
+    pub const DRAFT: Namespace = Namespace {
+        key: 118,
+        case: Case::FirstLetter,
+        name: Some("Draft"),
+        talk: false,
+    };
+
+    pub const DRAFT_TALK: Namespace = Namespace {
+        key: 119,
+        case: Case::FirstLetter,
+        name: Some("Draft talk"),
+        talk: true,
+    };
+
     pub const TIMEDTEXT: Namespace = Namespace {
         key: 710,
         case: Case::FirstLetter,
@@ -319,8 +370,56 @@ impl Namespace {
     };
 }
 
+/// Parses a namespace key, e.g. `1`, or name, e.g. `Talk` or `User_talk`. `Main` is the
+/// main namespace, also named `Page`.
+impl FromStr for Namespace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Namespace> {
+        if let Ok(key) = s.parse::<i64>() {
+            return Namespace::from_key(key);
+        }
+        match s {
+            "Main" | "main" => Ok(Namespace::PAGE),
+            _ => Namespace::from_name(Some(&*s.replace('_', " "))),
+        }
+    }
+}
+
 impl PartialEq<Self> for Namespace {
     fn eq(&self, other: &Self) -> bool {
         self.key == other.key
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_key() {
+        assert_eq!(Namespace::from_key(-2).unwrap(), Namespace::MEDIA);
+        assert_eq!(Namespace::from_key(-1).unwrap().name(), "Special");
+        assert!(Namespace::MEDIA.is_virtual());
+        assert!(!Namespace::PAGE.is_virtual());
+        assert!(Namespace::from_key(-3).is_err());
+    }
+
+    #[test]
+    fn from_page_title() {
+        assert_eq!(Namespace::from_page_title("Category talk:Foo"), Namespace::CATEGORY_TALK);
+        assert_eq!(Namespace::from_page_title("Foo"), Namespace::PAGE);
+        assert_eq!(Namespace::from_page_title("Star Wars: Episode I"), Namespace::PAGE);
+        assert_eq!(Namespace::from_page_slug("User_talk:Foo"), Namespace::USER_TALK);
+        assert_eq!(Namespace::from_page_slug("Star_Wars:_Episode_I"), Namespace::PAGE);
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("1".parse::<Namespace>().unwrap(), Namespace::TALK);
+        assert_eq!("User_talk".parse::<Namespace>().unwrap(), Namespace::USER_TALK);
+        assert_eq!("Main".parse::<Namespace>().unwrap(), Namespace::PAGE);
+        assert!("Nonsense".parse::<Namespace>().is_err());
+        assert!("99999".parse::<Namespace>().is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Kinds of error, so callers can tell failures apart without matching on error messages.
+//!
+//! Errors are [`anyhow::Error`]s. Code that knows what kind of failure it has returns a
+//! [`KindError`], usually with [`bail_kind!`](crate::bail_kind). [`ErrorKind::of`] finds it in
+//! an error's chain of causes, whatever context was added to the error since. Errors without
+//! a `KindError` are classified from well-known causes, such as I/O errors, and HTTP client
+//! errors with the `http` feature.
+
+use std::{
+    fmt::{self, Display},
+    io,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// The user asked for something that can't be done, e.g. with invalid arguments or a
+    /// file that doesn't exist.
+    User,
+
+    /// A network request failed, e.g. a connection error or an HTTP error response.
+    Network,
+
+    /// Data failed verification, e.g. a downloaded file had the wrong hash, or an index
+    /// failed its integrity check.
+    Verification,
+
+    /// The work was cancelled before it finished, e.g. because the user pressed ctrl-c. See
+    /// `wikimedia::cancel`.
+    Cancelled,
+
+    /// Any other error, probably a bug.
+    Internal,
+}
+
+/// An error with an [`ErrorKind`].
+#[derive(Debug)]
+pub struct KindError {
+    kind: ErrorKind,
+    message: String,
+}
+
+/// Return early with a [`KindError`], like [`anyhow::bail!`]. The first argument is the name
+/// of an [`ErrorKind`] variant.
+///
+/// ```
+/// # use wikimedia_types::{bail_kind, ErrorKind, Result};
+/// fn check_len(len: u64) -> Result<()> {
+///     if len == 0 {
+///         bail_kind!(Verification, "File was empty len={len}");
+///     }
+///     Ok(())
+/// }
+/// assert_eq!(ErrorKind::of(&check_len(0).unwrap_err()), ErrorKind::Verification);
+/// ```
+#[macro_export]
+macro_rules! bail_kind {
+    ($kind:ident, $($arg:tt)+) => {
+        return std::result::Result::Err($crate::format_err_kind!($kind, $($arg)+))
+    };
+}
+
+/// Construct a [`KindError`] as an [`Error`](crate::Error), like [`anyhow::format_err!`]. The
+/// first argument is the name of an [`ErrorKind`] variant.
+#[macro_export]
+macro_rules! format_err_kind {
+    ($kind:ident, $($arg:tt)+) => {
+        $crate::Error::from(
+            $crate::KindError::new($crate::ErrorKind::$kind, std::format!($($arg)+)))
+    };
+}
+
+impl ErrorKind {
+    /// The kind of `err`: the kind of the outermost [`KindError`] in its chain of causes, or
+    /// else classified from its causes, or else [`ErrorKind::Internal`].
+    pub fn of(err: &anyhow::Error) -> ErrorKind {
+        // `anyhow::Error::downcast_ref` also finds a `KindError` added with `.context()`.
+        let kind_err = err.downcast_ref::<KindError>().or_else(
+            || err.chain().find_map(|cause| cause.downcast_ref::<KindError>()));
+        if let Some(kind_err) = kind_err {
+            return kind_err.kind;
+        }
+
+        for cause in err.chain() {
+            #[cfg(feature = "http")]
+            if cause.is::<reqwest::Error>() || cause.is::<reqwest_middleware::Error>() {
+                return ErrorKind::Network;
+            }
+            if cause.is::<clap::Error>() {
+                return ErrorKind::User;
+            }
+            if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+                if matches!(io_err.kind(), io::ErrorKind::NotFound
+                                           | io::ErrorKind::PermissionDenied
+                                           | io::ErrorKind::AlreadyExists) {
+                    return ErrorKind::User;
+                }
+            }
+        }
+
+        ErrorKind::Internal
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::User => "user",
+            ErrorKind::Network => "network",
+            ErrorKind::Verification => "verification",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Internal => "internal",
+        })
+    }
+}
+
+impl KindError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> KindError {
+        KindError {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl Display for KindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for KindError {}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+    use super::*;
+
+    #[test]
+    fn kind_of() {
+        let err = anyhow::Error::new(KindError::new(ErrorKind::Network, "HTTP error"))
+                      .context("while downloading");
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Network);
+        assert_eq!(format!("{err:#}"), "while downloading: HTTP error");
+
+        let err = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+                      .context("opening file")
+                      .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::User);
+
+        // The outermost kind wins.
+        let err = anyhow::Error::new(KindError::new(ErrorKind::Network, "inner"))
+                      .context(KindError::new(ErrorKind::Verification, "outer"));
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Verification);
+
+        assert_eq!(ErrorKind::of(&anyhow::format_err!("other")), ErrorKind::Internal);
+    }
+}
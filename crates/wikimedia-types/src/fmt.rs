@@ -86,6 +86,14 @@ impl Sha1Hash {
         Sha1Hash(sha1_bytes)
     }
 
+    /// Calculate the hash of everything read from `read`.
+    pub fn calculate_from_reader(mut read: impl std::io::Read) -> Result<Sha1Hash> {
+        let mut sha1_hasher = Sha1::new();
+        std::io::copy(&mut read, &mut sha1_hasher)?;
+        let sha1_bytes: [u8; 20] = sha1_hasher.finalize().into();
+        Ok(Sha1Hash(sha1_bytes))
+    }
+
     pub fn to_hex_string(&self) -> String {
         hex::encode(self.0)
     }
@@ -280,6 +288,15 @@ impl Structable for ByteRate {
     }
 }
 
+impl serde::Serialize for ByteRate {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        let serializable = valuable_serde::Serializable::new(self);
+        serializable.serialize(serializer)
+    }
+}
+
 impl ByteRate {
     pub fn new(bytes: Bytes, duration: StdDuration) -> ByteRate {
         let secs = duration.as_secs_f64();
@@ -376,6 +393,15 @@ impl Structable for Duration {
     }
 }
 
+impl serde::Serialize for Duration {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        let serializable = valuable_serde::Serializable::new(self);
+        serializable.serialize(serializer)
+    }
+}
+
 pub fn bytes(len: u64) -> String {
     human_format::Formatter::new()
         .with_scales(human_format::Scales::Binary())
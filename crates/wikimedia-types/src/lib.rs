@@ -0,0 +1,22 @@
+//! Data types for Wikimedia dumps and their metadata, such as pages, revisions, dump names and
+//! namespaces, with the slug and formatting helpers they use.
+//!
+//! These are re-exported by the `wikimedia` crate, e.g. `wikimedia_types::dump::Page` is
+//! also `wikimedia::dump::Page`. Depend on this crate directly to use the types without
+//! compiling the HTTP client and compression libraries `wikimedia` needs to download and
+//! read dumps.
+
+// Imported first to import its macro.
+#[macro_use]
+mod lazy_regex;
+
+// The rest of these sub-modules are in alphabetical order.
+pub mod dump;
+mod error_kind;
+pub mod fmt;
+pub mod slug;
+
+pub use error_kind::{ErrorKind, KindError};
+
+pub type Error = anyhow::Error;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,50 @@
+//! Conversions between MediaWiki page titles and slugs.
+//!
+//! A title is the display form with spaces, e.g. `Machine learning`. A slug is the form used
+//! in URLs and the store's index, with underscores instead of spaces, e.g. `Machine_learning`.
+
+/// Convert a title to a slug, e.g. `Machine learning` to `Machine_learning`.
+pub fn title_to_slug(title: &str) -> String {
+    title.replace(' ', "_")
+}
+
+/// Convert a slug to a title, e.g. `Machine_learning` to `Machine learning`.
+pub fn slug_to_title(slug: &str) -> String {
+    slug.replace('_', " ")
+}
+
+/// Normalise a title or slug written by hand or in wikitext to a title.
+///
+/// Underscores become spaces, leading and trailing whitespace is removed, and runs of
+/// whitespace are collapsed to a single space, as MediaWiki does.
+pub fn normalize_title(title_or_slug: &str) -> String {
+    title_or_slug.split(|c: char| c == '_' || c.is_whitespace())
+                 .filter(|word| !word.is_empty())
+                 .collect::<Vec<&str>>()
+                 .join(" ")
+}
+
+/// Normalise a title or slug written by hand or in wikitext to a slug, see
+/// [`normalize_title`].
+pub fn normalize_slug(title_or_slug: &str) -> String {
+    title_to_slug(&normalize_title(title_or_slug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(title_to_slug("Machine learning"), "Machine_learning");
+        assert_eq!(slug_to_title("Machine_learning"), "Machine learning");
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_title("Machine_learning"), "Machine learning");
+        assert_eq!(normalize_title("  Machine \t learning_ "), "Machine learning");
+        assert_eq!(normalize_title(""), "");
+        assert_eq!(normalize_slug(" Machine  learning"), "Machine_learning");
+    }
+}
@@ -0,0 +1,317 @@
+//! Authentication for admin requests to the web interface.
+//!
+//! Admin requests are those to routes that may change state, and every request under
+//! `/admin`, whatever its method. [`router`](crate::router) adds [`require_admin`] to those
+//! routes, so they must be authenticated by the [`Authenticator`] set with
+//! [`WebState::set_authenticator`], and are rejected if none is set. Other requests are not
+//! authenticated.
+//!
+//! Browsers send HTTP basic authentication credentials and cookies with requests from other
+//! sites too, so admin requests that may change state, i.e. with a method other than GET,
+//! HEAD, or OPTIONS, are rejected if their `Sec-Fetch-Site` or `Origin` headers show they
+//! came from another origin. Requests with neither header are not from a browser and are
+//! allowed.
+//!
+//! Each admin request is logged with the target [`AUDIT_TARGET`], including the user that
+//! made it, so an audit log can be kept by filtering on that target.
+//!
+//! [`ApiToken`] and [`BasicAuth`] are provided. Implement [`Authenticator`] for other
+//! schemes, e.g. to check OpenID Connect tokens.
+
+use axum::{
+    extract::State,
+    headers::{authorization::{Basic, Bearer}, Authorization, HeaderMapExt},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use crate::WebState;
+use std::{fmt::Debug, sync::Arc};
+
+/// The `tracing` target of admin request audit events.
+pub const AUDIT_TARGET: &str = "wikimedia_web::audit";
+
+/// Checks the credentials of admin requests.
+pub trait Authenticator: Debug + Send + Sync {
+    /// Authenticate a request from its headers. Returns the name of the user, used in audit
+    /// events, or `None` if the credentials are missing or wrong.
+    fn authenticate(&self, headers: &HeaderMap) -> Option<String>;
+
+    /// The value of the `WWW-Authenticate` header in responses to requests that were not
+    /// authenticated.
+    fn challenge(&self) -> HeaderValue;
+}
+
+/// Authenticates requests with a shared secret token in the header
+/// `Authorization: Bearer TOKEN`.
+#[derive(Clone)]
+pub struct ApiToken {
+    token: String,
+}
+
+/// Authenticates requests with HTTP basic authentication against a list of users and
+/// passwords.
+#[derive(Clone)]
+pub struct BasicAuth {
+    users: Vec<(String, String)>,
+}
+
+impl ApiToken {
+    pub fn new(token: String) -> ApiToken {
+        ApiToken {
+            token,
+        }
+    }
+}
+
+impl Debug for ApiToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ApiToken").finish_non_exhaustive()
+    }
+}
+
+impl Authenticator for ApiToken {
+    fn authenticate(&self, headers: &HeaderMap) -> Option<String> {
+        let bearer = headers.typed_get::<Authorization<Bearer>>()?;
+        constant_time_eq(bearer.token().as_bytes(), self.token.as_bytes())
+            .then(|| "api-token".to_string())
+    }
+
+    fn challenge(&self) -> HeaderValue {
+        HeaderValue::from_static("Bearer")
+    }
+}
+
+impl BasicAuth {
+    /// Allow each `(user, password)` in `users`.
+    pub fn new(users: Vec<(String, String)>) -> BasicAuth {
+        BasicAuth {
+            users,
+        }
+    }
+}
+
+impl Debug for BasicAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BasicAuth")
+         .field("users", &self.users.iter().map(|(user, _)| user).collect::<Vec<_>>())
+         .finish_non_exhaustive()
+    }
+}
+
+impl Authenticator for BasicAuth {
+    fn authenticate(&self, headers: &HeaderMap) -> Option<String> {
+        let basic = headers.typed_get::<Authorization<Basic>>()?;
+        self.users.iter()
+            .find(|(user, password)|
+                  user == basic.username()
+                  && constant_time_eq(password.as_bytes(), basic.password().as_bytes()))
+            .map(|(user, _)| user.clone())
+    }
+
+    fn challenge(&self) -> HeaderValue {
+        HeaderValue::from_static(r#"Basic realm="wmd admin""#)
+    }
+}
+
+/// Compare secrets in time that depends only on their lengths, so a wrong guess doesn't
+/// reveal how much of it was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0_u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Whether requests with `method` may change state, see the [module documentation](self).
+fn is_unsafe_method(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Whether a browser sent a request with `headers` from a page on another origin, see the
+/// [module documentation](self).
+fn is_cross_origin(headers: &HeaderMap) -> bool {
+    if let Some(site) = headers.get("sec-fetch-site") {
+        return !matches!(site.as_bytes(), b"same-origin" | b"none");
+    }
+
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return false;
+    };
+    // `Origin` is `SCHEME://HOST[:PORT]`, or `null` for opaque origins.
+    let origin_host = origin.to_str().ok()
+                            .and_then(|origin| origin.split_once("://"))
+                            .map(|(_scheme, host)| host);
+    let host = headers.get(header::HOST).and_then(|host| host.to_str().ok());
+    match (origin_host, host) {
+        (Some(origin_host), Some(host)) => !origin_host.eq_ignore_ascii_case(host),
+        _ => true,
+    }
+}
+
+/// Middleware that authenticates admin requests and logs audit events for them. It
+/// authenticates every request it sees, so add it only to admin routes, e.g. with
+/// [`Router::route_layer`](axum::Router::route_layer).
+pub(crate) async fn require_admin<B>(
+    State(state): State<Arc<WebState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+
+    if is_unsafe_method(&method) && is_cross_origin(request.headers()) {
+        tracing::warn!(target: AUDIT_TARGET, %method, %uri,
+                       "Admin request rejected: cross-origin");
+        return crate::error_response("Forbidden",
+                                     &"Admin requests from other sites are not allowed.",
+                                     StatusCode::FORBIDDEN);
+    }
+
+    let Some(authenticator) = state.authenticator() else {
+        tracing::warn!(target: AUDIT_TARGET, %method, %uri,
+                       "Admin request rejected: no authentication is configured");
+        return crate::error_response("Forbidden",
+                                     &"Admin requests are disabled because no authentication \
+                                       is configured.",
+                                     StatusCode::FORBIDDEN);
+    };
+
+    let Some(user) = authenticator.authenticate(request.headers()) else {
+        tracing::warn!(target: AUDIT_TARGET, %method, %uri,
+                       "Admin request rejected: not authenticated");
+        let mut response = crate::error_response("Unauthorized",
+                                                 &"Admin requests must be authenticated.",
+                                                 StatusCode::UNAUTHORIZED);
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, authenticator.challenge());
+        return response;
+    };
+
+    let response = next.run(request).await;
+
+    tracing::info!(target: AUDIT_TARGET, user, %method, %uri,
+                   status = response.status().as_u16(),
+                   "Admin request");
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn api_token() {
+        let auth = ApiToken::new("secret".to_string());
+        assert_eq!(auth.authenticate(&headers("Bearer secret")).as_deref(), Some("api-token"));
+        assert_eq!(auth.authenticate(&headers("Bearer secreT")), None);
+        assert_eq!(auth.authenticate(&headers("Bearer secret2")), None);
+        assert_eq!(auth.authenticate(&HeaderMap::new()), None);
+        assert!(!format!("{auth:?}").contains("secret"));
+    }
+
+    #[test]
+    fn basic_auth() {
+        let auth = BasicAuth::new(vec![("alice".to_string(), "pw1".to_string()),
+                                       ("bob".to_string(), "pw2".to_string())]);
+        // "bob:pw2" and "bob:pw1" in base64.
+        assert_eq!(auth.authenticate(&headers("Basic Ym9iOnB3Mg==")).as_deref(), Some("bob"));
+        assert_eq!(auth.authenticate(&headers("Basic Ym9iOnB3MQ==")), None);
+        assert_eq!(auth.authenticate(&headers("Bearer pw2")), None);
+        assert!(!format!("{auth:?}").contains("pw1"));
+    }
+
+    #[test]
+    fn unsafe_methods() {
+        assert!(!is_unsafe_method(&Method::GET));
+        assert!(!is_unsafe_method(&Method::HEAD));
+        assert!(is_unsafe_method(&Method::POST));
+        assert!(is_unsafe_method(&Method::DELETE));
+    }
+
+    #[test]
+    fn cross_origin() {
+        fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+            pairs.iter()
+                 .map(|&(name, value)| (header::HeaderName::from_static(name),
+                                        HeaderValue::from_static(value)))
+                 .collect()
+        }
+
+        // Not from a browser.
+        assert!(!is_cross_origin(&headers(&[("host", "localhost:8089")])));
+
+        assert!(!is_cross_origin(&headers(&[("sec-fetch-site", "same-origin")])));
+        assert!(!is_cross_origin(&headers(&[("sec-fetch-site", "none")])));
+        assert!(is_cross_origin(&headers(&[("sec-fetch-site", "same-site"),
+                                           ("origin", "http://localhost:8089"),
+                                           ("host", "localhost:8089")])));
+        assert!(is_cross_origin(&headers(&[("sec-fetch-site", "cross-site")])));
+
+        assert!(!is_cross_origin(&headers(&[("origin", "http://localhost:8089"),
+                                            ("host", "localhost:8089")])));
+        assert!(is_cross_origin(&headers(&[("origin", "http://example.com"),
+                                           ("host", "localhost:8089")])));
+        assert!(is_cross_origin(&headers(&[("origin", "http://localhost"),
+                                           ("host", "localhost:8089")])));
+        assert!(is_cross_origin(&headers(&[("origin", "null"),
+                                           ("host", "localhost:8089")])));
+        assert!(is_cross_origin(&headers(&[("origin", "http://localhost:8089")])));
+    }
+
+    #[tokio::test]
+    async fn router_requires_admin() {
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        async fn status(state: &Arc<WebState>, request: Request<Body>) -> StatusCode {
+            crate::router(state.clone()).oneshot(request).await.unwrap().status()
+        }
+        fn request(method: Method, uri: &str, headers: &[(&str, &str)]
+        ) -> Request<Body> {
+            let mut builder = Request::builder().method(method).uri(uri);
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            builder.body(Body::empty()).unwrap()
+        }
+
+        // Stores are opened on their first request, so this one needn't exist.
+        let new_state = || {
+            let dump_name = wikimedia::dump::DumpName("simplewiki".to_string());
+            let mut store_options = wikimedia_store::Options::default();
+            store_options.dump_name(dump_name.clone())
+                         .path("out/stores/simplewiki");
+            WebState::new(vec![(dump_name, store_options)], "out".into()).unwrap()
+        };
+
+        let no_auth = Arc::new(new_state());
+        assert_eq!(status(&no_auth, request(Method::GET, "/admin/progress", &[])).await,
+                   StatusCode::FORBIDDEN);
+
+        let mut state = new_state();
+        state.set_authenticator(Arc::new(ApiToken::new("secret".to_string())));
+        let state = Arc::new(state);
+        let bearer = ("authorization", "Bearer secret");
+        assert_eq!(status(&state, request(Method::GET, "/admin/progress", &[])).await,
+                   StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&state, request(Method::GET, "/admin/progress/events", &[])).await,
+                   StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&state, request(Method::POST, "/admin/progress", &[])).await,
+                   StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&state, request(Method::GET, "/admin/progress",
+                                          &[bearer])).await,
+                   StatusCode::OK);
+
+        assert_eq!(status(&state, request(Method::POST, "/simplewiki/saved-search", &[])).await,
+                   StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&state, request(Method::POST, "/simplewiki/saved-search",
+                                          &[bearer,
+                                            ("origin", "http://example.com"),
+                                            ("host", "localhost:8089")])).await,
+                   StatusCode::FORBIDDEN);
+    }
+}
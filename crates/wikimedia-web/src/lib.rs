@@ -0,0 +1,1856 @@
+//! A web interface to browse the pages in Wikimedia stores.
+//!
+//! [`router`] returns an [`axum::Router`] serving the pages, categories, and search of the
+//! stores in a [`WebState`], so it can be served by an application with its own routes and
+//! middleware. It is also served by the `wmd web` command.
+//!
+//! Requests that may change state and requests under `/admin` are admin requests,
+//! authenticated as described in [`auth`].
+//!
+//! Links between pages are absolute paths, so merge the router into the application's root
+//! router rather than nesting it under a path.
+//!
+//! ```no_run
+//! # async fn example() -> wikimedia::Result<()> {
+//! use std::sync::Arc;
+//! use wikimedia::dump::DumpName;
+//! use wikimedia_web::WebState;
+//!
+//! let dump_name = DumpName("simplewiki".to_string());
+//! let mut store_options = wikimedia_store::Options::default();
+//! store_options.dump_name(dump_name.clone())
+//!              .path("out/stores/simplewiki");
+//!
+//! let state = WebState::new(vec![(dump_name, store_options)], "out".into())?;
+//! let app = axum::Router::new()
+//!     .route("/health", axum::routing::get(|| async { "OK" }))
+//!     .merge(wikimedia_web::router(Arc::new(state)));
+//!
+//! axum::Server::bind(&"127.0.0.1:8089".parse()?)
+//!     .serve(app.into_make_service())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod auth;
+pub mod proxy;
+mod state;
+pub use state::WebState;
+
+/// This crate's version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+use askama::Template;
+use axum::{
+    extract::{Form, Path, Query, RawQuery, State},
+    headers::ContentType,
+    http::{header, status::StatusCode},
+    middleware,
+    response::{IntoResponse, Redirect, Response, sse::{self, KeepAlive, Sse}},
+    Router,
+    routing,
+    TypedHeader,
+};
+use futures::{future::{self, Either}, stream::{self, Stream}};
+use percent_encoding::{AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::{
+    any::Any,
+    collections::HashMap,
+    convert::Infallible,
+    fmt::{self, Display},
+    future::Future,
+    path::PathBuf,
+    result::Result as StdResult,
+    sync::{Arc, MutexGuard},
+};
+use tower_http::catch_panic::CatchPanicLayer;
+use wikimedia::{
+    dump::{self, CategorySlug, DumpName, Namespace, SiteInfo},
+    ErrorKind,
+    events,
+    slug,
+    Result,
+    try2,
+    util::fmt::{Bytes, Sha1Hash},
+    web_api,
+    wikitext::{self, TextStats},
+};
+use wikimedia_store::{self as store, diff, index, QueryLimit, StorePageId};
+
+type WebResult<T> = StdResult<T, WebError>;
+
+/// The routes of the web interface, serving the stores in `state`.
+///
+/// Admin requests are authenticated, see [`auth`]. Panics in handlers are caught and returned
+/// as error pages. Add other middleware, e.g. for tracing requests, to the returned router or
+/// the application's router.
+pub fn router(state: Arc<WebState>) -> Router {
+    // Every route here is authenticated, whatever the request's method.
+    let admin = Router::new()
+        .route("/:dump_name/saved-search", routing::post(post_saved_search))
+        .route("/:dump_name/saved-search/:name/delete", routing::post(post_delete_saved_search))
+
+        .route("/admin/progress", routing::get(get_progress))
+        .route("/admin/progress/events", routing::get(get_progress_events))
+
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_admin));
+
+    Router::new()
+        .route("/", routing::get(get_index))
+        .route("/:dump_name", routing::get(get_store_main_page))
+        .route("/:dump_name/index", routing::get(get_store_index))
+        .route("/:dump_name/category", routing::get(get_categories))
+        .route("/:dump_name/category/by-name/:category_slug",
+               routing::get(get_category_by_slug))
+
+        .route("/:dump_name/diff", routing::get(get_store_page_diff))
+        .route("/:dump_name/page/by-id/:page_id", routing::get(get_page_by_id))
+        .route("/:dump_name/page/by-id/:page_id/diff", routing::get(get_page_diff))
+        .route("/:dump_name/page/by-id/:page_id/history", routing::get(get_page_history))
+        .route("/:dump_name/page/by-id/:page_id/rev/:rev_id", routing::get(get_page_revision))
+        .route("/:dump_name/page/by-store-id/:page_store_id", routing::get(get_page_by_store_id))
+        .route("/:dump_name/page/by-title/:page_slug", routing::get(get_page_by_slug))
+
+        .route("/:dump_name/page/search", routing::get(get_store_page_search))
+        .route("/page/search", routing::get(get_page_search))
+
+        .route("/:dump_name/api/v1/changes", routing::get(get_store_changes))
+        .route("/api/v1/changes", routing::get(get_changes))
+        .route("/:dump_name/api/v1/page/:page_id/sections",
+               routing::get(get_store_page_sections))
+        .route("/api/v1/page/:page_id/sections", routing::get(get_page_sections))
+        .route("/:dump_name/api/v1/saved-searches", routing::get(get_store_saved_searches))
+        .route("/api/v1/saved-searches", routing::get(get_saved_searches))
+        .route("/:dump_name/api/v1/saved-searches/:name/results",
+               routing::get(get_store_saved_search_results))
+        .route("/api/v1/saved-searches/:name/results",
+               routing::get(get_saved_search_results))
+
+        .route("/static/highlight.css", routing::get(get_highlight_css))
+
+        .route("/test_panic", routing::get(|| async { panic!("Test panic") as () }))
+
+        .merge(admin)
+
+        .fallback(router_fallback)
+
+        .with_state(state)
+
+        .layer(CatchPanicLayer::custom(handle_panic))
+}
+
+struct WebError(Response);
+
+impl WebError {
+    fn from_std_error<E>(e: E) -> WebError
+        where E: std::error::Error + Send + Sync + 'static
+    {
+        let anyhow_err = anyhow::Error::new(e);
+        WebError::from(anyhow_err)
+    }
+}
+
+impl IntoResponse for WebError {
+    fn into_response(self) -> Response {
+        self.0
+    }
+}
+
+impl From<anyhow::Error> for WebError {
+    fn from(e: anyhow::Error) -> WebError {
+        WebError(_500_response(&&*format!("Error: {e:#}")))
+    }
+}
+
+impl From<fmt::Error> for WebError {
+    fn from(e: fmt::Error) -> WebError {
+        WebError::from_std_error(e)
+    }
+}
+
+impl<T> From<std::sync::PoisonError<MutexGuard<'_, T>>> for WebError {
+    fn from(_e: std::sync::PoisonError<MutexGuard<'_, T>>) -> WebError {
+        // PoisonError is from trying to unlock a poisoned mutex. It
+        // contains the MutexGuard in case you want to clear the poison and continue.
+        // However MutexGuard is not Send, and axum wants errors from handlers to be Send.
+        // So we special case this conversion from sync::PoisonError<MutexGuard>
+        // to ignore the inner value and make sure we are Send.
+        WebError(_500_response(&"PoisonError unlocking Mutex in web module"))
+    }
+}
+
+#[cfg(any())]
+impl<E> From<E> for WebError
+    where E: std::error::Error + Send + Sync + 'static
+{
+    fn from(e: E) -> WebError {
+        WebError(_500_response(&*format!("Error: {e}")))
+    }
+}
+
+#[derive(askama::Template)]
+#[template(path = "error.html")]
+struct ErrorHtml<'a> {
+    title: &'static str,
+    message: &'a str,
+}
+
+fn _500_response(msg: &dyn Display) -> Response {
+    error_response("Error", msg, StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn _400_response(msg: &dyn Display) -> Response {
+    error_response("Bad request", msg, StatusCode::BAD_REQUEST)
+}
+
+fn _404_response(msg: &dyn Display) -> Response {
+    error_response("Not found", msg, StatusCode::NOT_FOUND)
+}
+
+/// A 301 Moved Permanently response, as MediaWiki returns for non-canonical page URLs.
+fn moved_permanently_response(location: &str) -> Response {
+    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, location.to_string())]).into_response()
+}
+
+fn error_response(title: &'static str, msg: &dyn Display, status: StatusCode) -> Response {
+    let msg = msg.to_string();
+
+    tracing::error!(%title, %msg,
+                    status_u16 = status.as_u16(),
+                    status_str = status.as_str(),
+                    "web::error_response");
+
+    let template = ErrorHtml {
+        title: title,
+        message: &*msg,
+    };
+
+    let html = match template.render() {
+        Ok(html) => html,
+        Err(e) => format!(
+            "<html>\
+             <head>\
+             <title>{title}</title>\
+             </head>\
+             <body>\
+             <h1>{title}</h1>\
+             <pre>{msg}</pre>\
+             <p>Additional error rendering the error:</p>\
+             <pre>{e}</pre>\
+             </body>\
+             </html>"),
+    };
+
+    (
+        status,
+        TypedHeader(ContentType::html()),
+        html,
+    ).into_response()
+}
+
+fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response {
+    let s = if let Some(s) = err.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else {
+        "Unknown panic message".to_string()
+    };
+
+    tracing::error!("panic: {s}");
+
+    _500_response(&format!("panic: {s}"))
+}
+
+async fn router_fallback() -> impl IntoResponse {
+    _404_response(&"Route not found")
+}
+
+#[derive(askama::Template)]
+#[template(path = "index.html")]
+struct IndexHtml {
+    title: String,
+    stores: Vec<StoreSummaryHtml>,
+}
+
+struct StoreSummaryHtml {
+    dump_name: String,
+
+    /// `Err` with a message if the store could not be opened.
+    info: StdResult<StoreStatsHtml, String>,
+}
+
+struct StoreStatsHtml {
+    pages_len: u64,
+    namespaces: Vec<store::metadata::NamespaceInfo>,
+    categories_len: u64,
+    dump_version: String,
+    last_import: String,
+    chunks_len: u64,
+    chunks_bytes: Bytes,
+    index_bytes: Bytes,
+}
+
+impl From<store::metadata::StoreInfo> for StoreStatsHtml {
+    fn from(info: store::metadata::StoreInfo) -> StoreStatsHtml {
+        StoreStatsHtml {
+            pages_len: info.pages_len,
+            namespaces: info.namespaces,
+            categories_len: info.categories_len,
+            dump_version: info.dump_version.unwrap_or_else(|| "unknown".to_string()),
+            last_import: match info.last_import {
+                Some(time) => time.format("%Y-%m-%d %H:%M UTC").to_string(),
+                None => "unknown".to_string(),
+            },
+            chunks_len: info.chunks_len,
+            chunks_bytes: Bytes(info.chunks_bytes_len),
+            index_bytes: Bytes(info.index_bytes_len + info.index_search_bytes_len.unwrap_or(0)),
+        }
+    }
+}
+
+async fn get_index(
+    State(state): State<Arc<WebState>>,
+) -> impl IntoResponse {
+    let stores = state.dump_names().into_iter().map(|dump_name| {
+        let info = state.store(&dump_name.0)
+                        .and_then(|store| store.store_info())
+                        .map(StoreStatsHtml::from)
+                        .map_err(|e| format!("{e:#}"));
+        StoreSummaryHtml {
+            dump_name: dump_name.0,
+            info,
+        }
+    }).collect::<Vec<StoreSummaryHtml>>();
+
+    IndexHtml {
+        title: "Stores".to_string(),
+        stores,
+    }
+}
+
+/// The slug of the page shown at a store's root, if the store has it.
+const MAIN_PAGE_SLUG: &str = "Main_Page";
+
+/// Articles linked from a store's default main page.
+const STORE_INDEX_RANDOM_ARTICLES: u64 = 10;
+
+/// Categories linked from a store's default main page.
+const STORE_INDEX_TOP_CATEGORIES: u64 = 20;
+
+#[derive(askama::Template)]
+#[template(path = "store_index.html")]
+struct StoreIndexHtml {
+    title: String,
+    dump_name: String,
+    info: StoreStatsHtml,
+    random_articles: Vec<PageLinkHtml>,
+    top_categories: Vec<index::CategoryFacet>,
+    saved_searches: Vec<SavedSearchHtml>,
+
+    /// Why the store's main page isn't shown instead, if this is the store's root.
+    notice: Option<String>,
+}
+
+struct PageLinkHtml {
+    title: String,
+    href: String,
+}
+
+struct SavedSearchHtml {
+    name: String,
+    query: String,
+
+    /// The search's results page.
+    href: String,
+
+    /// The search's results as JSON.
+    results_href: String,
+
+    /// Where to post to delete the search.
+    delete_href: String,
+}
+
+/// Show the store's `Main_Page` at its root, or the default main page if the store doesn't
+/// have one or it can't be rendered, e.g. in a new store with a partial import.
+async fn get_store_main_page(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+) -> WebResult<Response> {
+    let page = state.store(&dump_name)?
+                    .get_page_by_slug_following_redirect(MAIN_PAGE_SLUG)?
+                    .map(|(page, _fragment)| page);
+    let notice = match page {
+        None => format!("This store has no {MAIN_PAGE_SLUG} page."),
+        Some(page) => {
+            let query = SinglePageQuery {
+                debug: None,
+                redirect: None,
+                section: None,
+            };
+            let response = response_from_mapped_page(Some(page), &state, query).await
+                               .unwrap_or_else(|e| e.into_response());
+            if response.status().is_success() {
+                return Ok(response);
+            }
+            tracing::warn!(dump_name, status = %response.status(),
+                           "Error rendering main page, showing the default main page");
+            format!("This store's {MAIN_PAGE_SLUG} page could not be shown.")
+        },
+    };
+
+    Ok(store_index(&state, dump_name, Some(notice))?.into_response())
+}
+
+/// Show the store's default main page, even if it has a `Main_Page`.
+async fn get_store_index(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+) -> WebResult<impl IntoResponse> {
+    Ok(store_index(&state, dump_name, None)?)
+}
+
+fn store_index(state: &WebState, dump_name: String, notice: Option<String>
+) -> Result<StoreIndexHtml> {
+    let store = state.store(&dump_name)?;
+    let info = store.store_info()?;
+    let random_articles = store.random_articles(STORE_INDEX_RANDOM_ARTICLES)?
+        .into_iter()
+        .map(|page| PageLinkHtml {
+            title: page.title(),
+            href: page_by_title_path(&dump_name, &page.slug),
+        })
+        .collect();
+    let top_categories = store.top_categories(QueryLimit::new(STORE_INDEX_TOP_CATEGORIES))?;
+    let saved_searches = store.saved_searches()?
+        .into_iter()
+        .map(|search| {
+            let name = percent_encoding::utf8_percent_encode(&search.name, NON_ALPHANUMERIC);
+            SavedSearchHtml {
+                href: saved_search_href(&dump_name, &search),
+                results_href: format!("/{dump_name}/api/v1/saved-searches/{name}/results"),
+                delete_href: format!("/{dump_name}/saved-search/{name}/delete"),
+                name: search.name,
+                query: search.query,
+            }
+        })
+        .collect();
+    Ok(StoreIndexHtml {
+        title: format!("Index for {dump_name}"),
+        dump_name,
+        info: info.into(),
+        random_articles,
+        top_categories,
+        saved_searches,
+        notice,
+    })
+}
+
+#[derive(Deserialize)]
+struct GetCategoryQuery {
+    limit: Option<u64>,
+    slug_lower_bound: Option<String>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "categories.html")]
+struct CategoriesHtml<'a> {
+    title: &'a str,
+    dump_name: String,
+
+    categories: Vec<index::Category>,
+    show_more_href: Option<String>,
+}
+
+async fn get_categories(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+    Query(query): Query<GetCategoryQuery>
+) -> WebResult<impl IntoResponse> {
+
+    let limit = QueryLimit::from(query.limit);
+
+    let categories = state.store(&*dump_name)?
+        .get_category(
+            query.slug_lower_bound.as_ref().map(|s| CategorySlug::new(s)).as_ref(),
+            limit)?;
+
+    let last_slug = categories.last().map(|category| category.slug.clone());
+    let len = u64::try_from(categories.len()).expect("u64 from usize");
+
+    let show_more_href =
+        if let Some(CategorySlug(slug_lower_bound)) = last_slug {
+            if limit.get() == len {
+                let limit_pair = match query.limit {
+                    Some(limit) => format!("&limit={}", limit),
+                    None => "".to_string(),
+                };
+
+                Some(format!(
+                    "/{dump_name}/category?slug_lower_bound={slug_lower_bound}{limit_pair}"))
+            } else { None }
+        } else { None };
+
+    Ok(CategoriesHtml {
+        title: "Categories",
+        dump_name,
+
+        categories,
+        show_more_href,
+    })
+}
+
+#[derive(Deserialize)]
+struct GetCategoryBySlugQuery {
+    limit: Option<u64>,
+    page_mediawiki_id_lower_bound: Option<u64>,
+
+    /// Only show pages in the namespace with this ID, e.g. 0 for articles.
+    namespace: Option<i64>,
+
+    /// Search the titles of pages in the category instead of listing them.
+    query: Option<String>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "category.html")]
+struct CategoryHtml {
+    title: String,
+    dump_name: String,
+    category_slug: String,
+    query: Option<String>,
+
+    /// The namespace filter, kept by the form, and the namespace's name.
+    namespace: Option<i64>,
+    namespace_name: Option<&'static str>,
+
+    pages: Vec<index::Page>,
+    show_more_href: Option<String>,
+}
+
+async fn get_category_by_slug(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, category_slug)): Path<(String, String)>,
+    Query(query): Query<GetCategoryBySlugQuery>,
+) -> WebResult<impl IntoResponse> {
+
+    let limit = QueryLimit::from(query.limit);
+
+    // Accept a category name as well as a slug in the path.
+    let category_slug = CategorySlug::new(&category_slug);
+
+    let query_string = query.query.filter(|query| !query.trim().is_empty());
+
+    let store = state.store(&*dump_name)?;
+    // Show the category's name as written in pages if it's in the index.
+    let title = match store.get_category_by_slug(&category_slug)? {
+        Some(category) => category.name.0,
+        None => category_slug.to_name().0,
+    };
+    let pages: Vec<index::Page> = match query_string.as_ref() {
+        Some(query_string) => store.search_in_category(&category_slug, query_string,
+                                                       query.namespace, limit)?,
+        None => store.get_category_pages(
+            &category_slug,
+            query.page_mediawiki_id_lower_bound,
+            query.namespace,
+            limit,
+        )?,
+    };
+
+    // Drop the MutexGuard.
+    drop(store);
+
+    let page_mediawiki_id_lower_bound = pages.last().map(|page| page.mediawiki_id);
+    let len = u64::try_from(pages.len()).expect("u64 from usize");
+
+    let show_more_href =
+        if let Some(page_mediawiki_id_lower_bound) = page_mediawiki_id_lower_bound {
+            // Search results are ordered by relevance, so they can't be continued from the
+            // last page.
+            if len == limit.get() && query_string.is_none() {
+                let limit_pair = match query.limit {
+                    Some(limit) => format!("&limit={}", limit),
+                    None => "".to_string(),
+                };
+                let namespace_pair = match query.namespace {
+                    Some(namespace) => format!("&namespace={namespace}"),
+                    None => "".to_string(),
+                };
+
+                Some(format!("/{dump_name}/category/by-name/{slug}\
+                              ?page_mediawiki_id_lower_bound={page_mediawiki_id_lower_bound}\
+                              {limit_pair}{namespace_pair}",
+                             slug = category_slug.0))
+            } else { None }
+        } else { None };
+
+    Ok(CategoryHtml {
+        title,
+        dump_name,
+        category_slug: category_slug.0,
+        query: query_string,
+
+        namespace: query.namespace,
+        namespace_name: query.namespace.and_then(|key| dump::Namespace::from_key(key).ok())
+                                       .map(|ns| ns.name()),
+
+        pages,
+        show_more_href,
+    })
+}
+
+/// The CSS for highlighted code in pages, which `page.html` links to.
+async fn get_highlight_css(
+    State(state): State<Arc<WebState>>,
+) -> WebResult<Response> {
+    let css = state.highlight_css().await?;
+    Ok(([(header::CONTENT_TYPE, "text/css; charset=utf-8"),
+         (header::CACHE_CONTROL, "max-age=3600")],
+        css.to_string()).into_response())
+}
+
+#[derive(askama::Template)]
+#[template(path = "progress.html")]
+struct ProgressHtml {
+    title: String,
+}
+
+async fn get_progress() -> ProgressHtml {
+    ProgressHtml {
+        title: "Progress".to_string(),
+    }
+}
+
+/// Stream progress events from downloads and imports as server-sent events, named by their
+/// type with their JSON as data. A client that falls behind gets a `lagged` event with the
+/// count of events it missed.
+async fn get_progress_events(
+    State(state): State<Arc<WebState>>,
+) -> Sse<impl Stream<Item = StdResult<sse::Event, Infallible>>> {
+    let receiver = state.events().subscribe();
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => {
+                let json = serde_json::to_value(&event)
+                    .expect("events::Event serializes to JSON");
+                let name = json["type"].as_str().unwrap_or("event").to_string();
+                sse::Event::default().event(name).data(json.to_string())
+            },
+            Err(events::RecvError::Lagged(missed)) =>
+                sse::Event::default().event("lagged").data(missed.to_string()),
+            Err(events::RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[derive(Deserialize)]
+struct SinglePageQuery {
+    debug: Option<bool>,
+
+    /// `no` to show a redirect page itself instead of redirecting to its target, as in
+    /// MediaWiki.
+    redirect: Option<String>,
+
+    /// The anchor of a section to show on its own instead of the whole page, e.g. `History`.
+    /// Useful for very large pages.
+    section: Option<String>,
+}
+
+async fn get_page_by_id(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_id)): Path<(String, u64)>,
+    Query(query): Query<SinglePageQuery>,
+) -> WebResult<impl IntoResponse> {
+
+    let page = state.store(&*dump_name)?.get_page_by_mediawiki_id(page_id)?;
+
+    response_from_mapped_page(page, &*state, query).await
+}
+
+async fn get_page_by_store_id(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_store_id)): Path<(String, String)>,
+    Query(query): Query<SinglePageQuery>,
+) -> WebResult<impl IntoResponse> {
+
+    let page_store_id = page_store_id.parse::<store::StorePageId>()?;
+
+    let page = state.store(&*dump_name)?.get_page_by_store_id(page_store_id)?;
+
+    response_from_mapped_page(page, &*state, query).await
+}
+
+async fn get_page_by_slug(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_slug)): Path<(String, String)>,
+    Query(query): Query<SinglePageQuery>,
+    RawQuery(raw_query): RawQuery,
+) -> WebResult<Response> {
+
+    let page = {
+        let store = state.store(&*dump_name)?;
+        match store.get_indexed_page_by_slug(&slug::normalize_slug(&page_slug))? {
+            None => None,
+            Some(indexed_page) => {
+                if let Some(location) = canonical_page_location(&store, &dump_name, &page_slug,
+                                                                &indexed_page, &query,
+                                                                raw_query.as_deref())? {
+                    return Ok(moved_permanently_response(&location));
+                }
+
+                Some(store.get_page_by_store_id(indexed_page.store_id())?)
+            },
+        }
+    };
+
+    match page {
+        Some(page) => response_from_mapped_page(page, &*state, query).await,
+        // The store's MutexGuard is dropped above, so it isn't held across the fetch.
+        None => response_from_live_page(&state, dump_name, &page_slug, query).await,
+    }
+}
+
+/// Serve a page missing from the store from the live wiki, if the store has a live proxy.
+async fn response_from_live_page(
+    state: &WebState,
+    dump_name: String,
+    page_slug: &str,
+    query: SinglePageQuery,
+) -> WebResult<Response> {
+    let Some(live_proxy) = state.live_proxy(&dump_name) else {
+        return Ok(_404_response(&"Page not found"));
+    };
+    let Some(page) = live_proxy.get_page(&slug::normalize_slug(page_slug)).await? else {
+        return Ok(_404_response(&"Page not found"));
+    };
+
+    // Once saved, later requests for the page are served from the store. The page is still
+    // served if saving it fails, e.g. while an import holds the store's write lock.
+    if live_proxy.save_pages() && live_proxy.queue_save(page.clone())? {
+        if let Err(err) = state.save_live_pages(&dump_name) {
+            tracing::warn!(%err, "Error saving live pages to store");
+        }
+    }
+
+    let site = state.site(&dump_name)?;
+    let index_data = PageIndexData {
+        page_props: HashMap::new(),
+        related: Vec::new(),
+    };
+    response_from_dump_page(page, None, index_data, DumpName(dump_name), site,
+                            RenderContext::new(state), query).await
+}
+
+/// Characters percent-encoded in a page slug in a URL path. Like MediaWiki's
+/// `wfUrlencode()`, except `/` is encoded because the slug is a single path segment here.
+const SLUG_PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-').remove(b'.').remove(b'_').remove(b'~')
+    .remove(b'!').remove(b'$').remove(b'(').remove(b')').remove(b'*').remove(b',')
+    .remove(b':').remove(b';').remove(b'@');
+
+/// Characters percent-encoded in a section anchor in a URL fragment.
+const FRAGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'<').add(b'>').add(b'`');
+
+/// The path of a page's canonical URL.
+fn page_by_title_path(dump_name: &str, page_slug: &str) -> String {
+    format!("/{dump_name}/page/by-title/{slug}",
+            slug = percent_encoding::utf8_percent_encode(page_slug, SLUG_PATH_ENCODE_SET))
+}
+
+/// Where to permanently redirect a request for a page by title, if anywhere, as MediaWiki
+/// does:
+///
+/// * To the page's own slug if the requested slug is different, e.g. in case or with spaces
+///   for underscores. The query string is kept.
+/// * To the target of a redirect page, unless the query has `redirect=no`, with the
+///   redirect's section as the URL fragment if it has one. Redirects to missing pages
+///   aren't followed. A redirect to another redirect is only followed once, to the second
+///   redirect with `redirect=no`, so redirect loops end.
+fn canonical_page_location(
+    store: &store::Store,
+    dump_name: &str,
+    requested_slug: &str,
+    page: &index::Page,
+    query: &SinglePageQuery,
+    raw_query: Option<&str>,
+) -> Result<Option<String>> {
+    if requested_slug != page.slug {
+        let query_suffix = raw_query.map(|raw_query| format!("?{raw_query}"))
+                                    .unwrap_or_default();
+        return Ok(Some(format!("{path}{query_suffix}",
+                               path = page_by_title_path(dump_name, &page.slug))));
+    }
+
+    if query.redirect.as_deref() == Some("no") {
+        return Ok(None);
+    }
+    let Some(redirect) = store.follow_redirect(page)? else {
+        return Ok(None);
+    };
+
+    let query_suffix = if redirect.target.is_redirect() { "?redirect=no" } else { "" };
+    let fragment_suffix = redirect.fragment.as_deref()
+        .map(|fragment| format!("#{fragment}",
+                                fragment = percent_encoding::utf8_percent_encode(
+                                    fragment, FRAGMENT_ENCODE_SET)))
+        .unwrap_or_default();
+    Ok(Some(format!("{path}{query_suffix}{fragment_suffix}",
+                    path = page_by_title_path(dump_name, &redirect.target.slug))))
+}
+
+#[derive(askama::Template)]
+#[template(path = "page.html")]
+struct PageHtml {
+    title: String,
+
+    mediawiki_id: u64,
+    wikitext_html: String,
+
+    /// Set if the page was too large or slow to render in full, see
+    /// [`wikitext::RenderLimits`].
+    render_notice: Option<String>,
+
+    text_stats: TextStats,
+    text_bytes: Bytes,
+
+    dump_name: String,
+
+    /// The URL of this page on the live site, if known.
+    site_page_url: Option<String>,
+
+    /// Empty unless the page has enough sections for a table of contents.
+    toc: Vec<TocEntryHtml>,
+
+    /// The section shown instead of the whole page, if one was requested.
+    section: Option<PageSectionHtml>,
+
+    related: Vec<RelatedPageHtml>,
+}
+
+struct TocEntryHtml {
+    /// The section's number, e.g. `2.1`.
+    number: String,
+    title: String,
+    anchor: String,
+
+    /// Nesting depth in the table of contents, from 0.
+    depth: usize,
+
+    /// Link to show only this section.
+    section_href: String,
+}
+
+struct PageSectionHtml {
+    title: String,
+    whole_page_href: String,
+}
+
+/// Show a table of contents on pages with at least this many sections, as MediaWiki does,
+/// unless their wikitext has `__NOTOC__`. With `__FORCETOC__` one is shown for fewer.
+const TOC_MIN_SECTIONS: usize = 4;
+
+/// The entries of a page's table of contents, numbered as on MediaWiki.
+fn toc_entries(wikitext: &str, sections: &[wikitext::Section]) -> Vec<TocEntryHtml> {
+    let show = wikitext.contains("__FORCETOC__")
+        || (sections.len() >= TOC_MIN_SECTIONS && !wikitext.contains("__NOTOC__"));
+    if !show {
+        return Vec::new();
+    }
+
+    // (level, count) for each heading level the current section is nested in.
+    let mut numbers = Vec::<(u8, u32)>::new();
+    sections.iter().map(|section| {
+        while numbers.last().is_some_and(|&(level, _)| level > section.level) {
+            numbers.pop();
+        }
+        match numbers.last_mut() {
+            Some((level, count)) if *level == section.level => *count += 1,
+            _ => numbers.push((section.level, 1)),
+        }
+
+        TocEntryHtml {
+            number: numbers.iter().map(|(_, count)| count.to_string())
+                           .collect::<Vec<String>>().join("."),
+            title: section.title.clone(),
+            anchor: section.anchor.clone(),
+            depth: numbers.len() - 1,
+            section_href: format!(
+                "?section={anchor}",
+                anchor = percent_encoding::utf8_percent_encode(&section.anchor,
+                                                               SLUG_PATH_ENCODE_SET)),
+        }
+    }).collect()
+}
+
+struct RelatedPageHtml {
+    href: String,
+
+    /// The page's display title if it has one, otherwise its title.
+    title: String,
+}
+
+/// The most related articles to show on a page.
+const RELATED_PAGES_LIMIT: u64 = 8;
+
+#[derive(askama::Template)]
+#[template(path = "page_debug.html")]
+struct PageDebugHtml {
+    title: String,
+
+    namespace: dump::Namespace,
+    mediawiki_id: u64,
+    slug: String,
+
+    /// `None` for a page served from the live wiki, see [`proxy`].
+    store_page_id: Option<StorePageId>,
+
+    revision_id: Option<u64>,
+    revision_parent_id: Option<u64>,
+    revision_timestamp_string: Option<String>,
+    revision_text_sha1: Option<Sha1Hash>,
+
+    /// The page's properties from the index, sorted by name.
+    page_props: Vec<(String, String)>,
+
+    wikitext: String,
+
+    dump_name: String,
+
+    /// The URL of this page on the live site, if known.
+    site_page_url: Option<String>,
+}
+
+fn response_from_mapped_page(
+    page: Option<store::MappedPage>,
+    state: &WebState,
+    query: SinglePageQuery,
+) -> impl Future<Output = WebResult<Response>> + Send {
+    let Some(page) = page else {
+        return Either::Left(future::ok(_404_response(&"Page not found")));
+    };
+
+    // MappedPage is not Send, so convert it to a dump::Page before returning a future.
+    let store_page_id = page.store_id();
+    let page_dump = match mapped_page_to_dump_page(&page) {
+        Ok(p) => p,
+        Err(e) => return Either::Left(future::err(e.into())),
+    };
+
+    let site = match state.site(&page.dump_name().0) {
+        Ok(site) => site,
+        Err(e) => return Either::Left(future::err(e.into())),
+    };
+
+    let index_data = match page_index_data(state, &page.dump_name().0, page_dump.id, &query) {
+        Ok(index_data) => index_data,
+        Err(e) => return Either::Left(future::err(e.into())),
+    };
+
+    let prerendered = match page.rendered_html() {
+        Ok(html) => html,
+        Err(e) => return Either::Left(future::err(e.into())),
+    };
+
+    Either::Right(response_from_dump_page(page_dump, Some(store_page_id), index_data,
+                                          page.dump_name(), site,
+                                          RenderContext {
+                                              prerendered,
+                                              ..RenderContext::new(state)
+                                          },
+                                          query))
+}
+
+/// What rendering a page's wikitext needs from the [`WebState`], copied so it can be used
+/// in a `Send` future.
+struct RenderContext {
+    out_dir: PathBuf,
+    limits: wikitext::RenderLimits,
+
+    /// The page's HTML from the store, if it was pre-rendered with `wmd prerender-store`.
+    /// Served for the whole page instead of rendering its wikitext.
+    prerendered: Option<String>,
+}
+
+impl RenderContext {
+    fn new(state: &WebState) -> RenderContext {
+        RenderContext {
+            out_dir: state.out_dir().to_path_buf(),
+            limits: *state.render_limits(),
+            prerendered: None,
+        }
+    }
+}
+
+/// What the store's index has about a page to show with it.
+struct PageIndexData {
+    page_props: HashMap<String, String>,
+
+    /// Articles related to the page to link to from it. Empty for debug info, which doesn't
+    /// show them.
+    related: Vec<RelatedPageHtml>,
+}
+
+fn page_index_data(state: &WebState, dump_name: &str, mediawiki_id: u64,
+                   query: &SinglePageQuery
+) -> Result<PageIndexData> {
+    let store = state.store(dump_name)?;
+    let page_props = store.get_page_props(mediawiki_id)?;
+    if query.debug.unwrap_or(false) {
+        return Ok(PageIndexData {
+            page_props,
+            related: Vec::new(),
+        });
+    }
+
+    let related = store.related_pages(mediawiki_id, RELATED_PAGES_LIMIT)?;
+    let mut display_titles = store.get_display_titles(
+        &related.iter().map(|related| related.page.mediawiki_id).collect::<Vec<u64>>())?;
+    let related = related.into_iter()
+        .map(|related| RelatedPageHtml {
+            href: page_by_title_path(dump_name, &related.page.slug),
+            title: display_titles.remove(&related.page.mediawiki_id)
+                                 .unwrap_or_else(|| slug::slug_to_title(&related.page.slug)),
+        })
+        .collect();
+
+    Ok(PageIndexData {
+        page_props,
+        related,
+    })
+}
+
+fn mapped_page_to_dump_page(page: &store::MappedPage) -> Result<dump::Page> {
+    let page_cap = page.borrow()?;
+    dump::Page::try_from(&page_cap)
+}
+
+async fn response_from_dump_page(
+    page_dump: dump::Page,
+    store_page_id: Option<StorePageId>,
+    index_data: PageIndexData,
+    dump_name: DumpName,
+    site: Option<SiteInfo>,
+    render: RenderContext,
+    query: SinglePageQuery,
+) -> WebResult<Response> {
+    let site_page_url = site.as_ref().map(|site| site.page_url(&page_dump.title));
+    let slug = slug::title_to_slug(&*page_dump.title);
+    let PageIndexData { mut page_props, related } = index_data;
+
+    if query.debug.unwrap_or(false) {
+        let wikitext = page_dump.revision_text().unwrap_or("").to_string();
+
+        let html = PageDebugHtml {
+            title: format!("{title} - debug info", title = page_dump.title),
+
+            namespace: page_dump.namespace()?,
+            mediawiki_id: page_dump.id,
+            slug,
+            store_page_id,
+            wikitext,
+
+            revision_id: page_dump.revision.as_ref().map(|r| r.id),
+            revision_parent_id: page_dump.revision.as_ref().and_then(|r| r.parent_id),
+            revision_timestamp_string:
+                page_dump.revision.as_ref().and_then(|r| r.timestamp)
+                         .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs,
+                                                      /* use_z: */ true)),
+            revision_text_sha1:
+                page_dump.revision.as_ref().and_then(|r| r.sha1),
+
+            page_props: {
+                let mut page_props = page_props.into_iter().collect::<Vec<_>>();
+                page_props.sort();
+                page_props
+            },
+
+            site_page_url,
+
+            // This moves dump_name, do it last.
+            dump_name: dump_name.0,
+        };
+        Ok(html.into_response())
+    } else {
+        let text = page_dump.revision_text().unwrap_or("");
+        let text_stats = TextStats::from_wikitext(text);
+        let sections = wikitext::parse_sections(text);
+
+        let (toc, section, rendered) = match query.section.as_deref() {
+            None => {
+                let toc = toc_entries(text, &sections);
+                let html = match render.prerendered {
+                    Some(html) => wikitext::RenderedHtml {
+                        html,
+                        limit_exceeded: None,
+                    },
+                    None => wikitext::convert_page_to_html(&page_dump, &dump_name,
+                                                           site.as_ref(), &render.out_dir,
+                                                           &render.limits).await?,
+                };
+                (toc, None, html)
+            },
+            Some(anchor) => {
+                let Some(section) = sections.iter().find(|section| section.anchor == anchor)
+                else {
+                    return Ok(_404_response(&"Section not found"));
+                };
+                let mut section_page = page_dump.clone();
+                if let Some(revision) = section_page.revision.as_mut() {
+                    revision.text = Some(text[section.range.clone()].to_string());
+                }
+                let html = wikitext::convert_page_to_html(&section_page, &dump_name,
+                                                          site.as_ref(), &render.out_dir,
+                                                          &render.limits).await?;
+                let section = PageSectionHtml {
+                    title: section.title.clone(),
+                    whole_page_href: page_by_title_path(&dump_name.0, &slug),
+                };
+                (Vec::new(), Some(section), html)
+            },
+        };
+
+        let html = PageHtml {
+            text_stats,
+            text_bytes: Bytes(text_stats.bytes),
+            title: page_props.remove(index::PAGE_PROP_DISPLAY_TITLE)
+                             .unwrap_or(page_dump.title),
+
+            mediawiki_id: page_dump.id,
+            wikitext_html: rendered.html,
+            render_notice: rendered.limit_exceeded.map(|limit| limit.to_string()),
+
+            site_page_url,
+            toc,
+            section,
+            related,
+
+            // This moves dump_name, do it last.
+            dump_name: dump_name.0,
+        };
+        Ok(html.into_response())
+    }
+}
+
+/// Returns the revisions of a page that are in the store, newest first.
+///
+/// The store currently keeps only the latest revision of each page, so this returns at most
+/// one revision.
+fn page_revisions(page: &dump::Page) -> Vec<&dump::Revision> {
+    let mut revisions: Vec<&dump::Revision> = page.revision.iter().collect();
+    revisions.sort_by_key(|rev| std::cmp::Reverse((rev.timestamp, rev.id)));
+    revisions
+}
+
+fn get_page_dump_by_id(state: &WebState, dump_name: &str, page_id: u64
+) -> Result<Option<(StorePageId, dump::Page)>> {
+    let store = state.store(dump_name)?;
+    let Some(page) = store.get_page_by_mediawiki_id(page_id)? else {
+        return Ok(None);
+    };
+    Ok(Some((page.store_id(), mapped_page_to_dump_page(&page)?)))
+}
+
+#[derive(askama::Template)]
+#[template(path = "page_history.html")]
+struct PageHistoryHtml {
+    title: String,
+    dump_name: String,
+
+    mediawiki_id: u64,
+    revisions: Vec<RevisionSummary>,
+}
+
+struct RevisionSummary {
+    id: u64,
+    timestamp_string: Option<String>,
+
+    /// Length of the revision text in bytes.
+    len: Option<u64>,
+
+    /// Change in length from the previous stored revision, if there is one.
+    len_delta: Option<i64>,
+
+    /// The ID of the previous stored revision, if there is one.
+    prev_id: Option<u64>,
+}
+
+async fn get_page_history(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_id)): Path<(String, u64)>,
+) -> WebResult<Response> {
+
+    let Some((_store_page_id, page)) = get_page_dump_by_id(&state, &dump_name, page_id)?
+    else {
+        return Ok(_404_response(&"Page not found"));
+    };
+
+    let revisions = page_revisions(&page);
+    let text_len = |rev: &dump::Revision| -> Option<u64> {
+        rev.text.as_ref().map(|text| u64::try_from(text.len()).expect("u64 from usize"))
+    };
+
+    let summaries = revisions.iter().enumerate().map(|(idx, rev)| {
+        let prev = revisions.get(idx + 1);
+        let len = text_len(rev);
+        let prev_len = prev.and_then(|prev| text_len(prev));
+        RevisionSummary {
+            id: rev.id,
+            timestamp_string: rev.timestamp.map(
+                |dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, /* use_z: */ true)),
+            len,
+            len_delta: match (len, prev_len) {
+                (Some(len), Some(prev_len)) => Some(len as i64 - prev_len as i64),
+                _ => None,
+            },
+            prev_id: prev.map(|prev| prev.id),
+        }
+    }).collect::<Vec<RevisionSummary>>();
+
+    Ok(PageHistoryHtml {
+        title: format!("{title} - history", title = page.title),
+        dump_name,
+
+        mediawiki_id: page.id,
+        revisions: summaries,
+    }.into_response())
+}
+
+async fn get_page_revision(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_id, rev_id)): Path<(String, u64, u64)>,
+    Query(query): Query<SinglePageQuery>,
+) -> WebResult<Response> {
+
+    let Some((store_page_id, mut page)) = get_page_dump_by_id(&state, &dump_name, page_id)? else {
+        return Ok(_404_response(&"Page not found"));
+    };
+
+    let Some(revision) = page_revisions(&page).into_iter().find(|rev| rev.id == rev_id).cloned()
+    else {
+        return Ok(_404_response(&"Revision not found in the store"));
+    };
+
+    page.revision = Some(revision);
+
+    let site = state.site(&dump_name)?;
+    let index_data = page_index_data(&state, &dump_name, page_id, &query)?;
+    response_from_dump_page(page, Some(store_page_id), index_data, DumpName(dump_name), site,
+                            RenderContext::new(&state), query).await
+}
+
+#[derive(Deserialize)]
+struct PageDiffQuery {
+    from: u64,
+    to: u64,
+}
+
+#[derive(askama::Template)]
+#[template(path = "page_diff.html")]
+struct PageDiffHtml {
+    title: String,
+
+    old_href: String,
+    old_label: String,
+    new_href: String,
+    new_label: String,
+    history_href: Option<String>,
+
+    lines: Vec<DiffLineHtml>,
+}
+
+struct DiffLineHtml {
+    /// One of "delete", "insert", or "equal"; used as a CSS class.
+    kind: &'static str,
+    sign: char,
+    spans: Vec<diff::DiffSpan>,
+}
+
+impl From<diff::DiffLine> for DiffLineHtml {
+    fn from(line: diff::DiffLine) -> DiffLineHtml {
+        let (kind, sign) = match line.tag {
+            diff::ChangeTag::Delete => ("delete", '-'),
+            diff::ChangeTag::Insert => ("insert", '+'),
+            diff::ChangeTag::Equal => ("equal", ' '),
+        };
+        DiffLineHtml {
+            kind,
+            sign,
+            spans: line.spans,
+        }
+    }
+}
+
+async fn get_page_diff(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_id)): Path<(String, u64)>,
+    Query(query): Query<PageDiffQuery>,
+) -> WebResult<Response> {
+
+    let Some((_store_page_id, page)) = get_page_dump_by_id(&state, &dump_name, page_id)?
+    else {
+        return Ok(_404_response(&"Page not found"));
+    };
+
+    let revisions = page_revisions(&page);
+    let find_text = |rev_id: u64| -> Option<&str> {
+        revisions.iter().find(|rev| rev.id == rev_id)
+                 .map(|rev| rev.text.as_deref().unwrap_or(""))
+    };
+    let (Some(from_text), Some(to_text)) = (find_text(query.from), find_text(query.to)) else {
+        return Ok(_404_response(&"Revision not found in the store"));
+    };
+
+    let diff = diff::Diff::from_texts(from_text, to_text);
+
+    let page_href = format!("/{dump_name}/page/by-id/{page_id}", page_id = page.id);
+    Ok(PageDiffHtml {
+        title: format!("{title} - diff", title = page.title),
+
+        old_href: format!("{page_href}/rev/{from}", from = query.from),
+        old_label: format!("Revision {from}", from = query.from),
+        new_href: format!("{page_href}/rev/{to}", to = query.to),
+        new_label: format!("Revision {to}", to = query.to),
+        history_href: Some(format!("{page_href}/history")),
+
+        lines: diff.lines.into_iter().map(DiffLineHtml::from).collect(),
+    }.into_response())
+}
+
+#[derive(Deserialize)]
+struct StorePageDiffQuery {
+    old: String,
+    new: String,
+}
+
+async fn get_store_page_diff(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+    Query(query): Query<StorePageDiffQuery>,
+) -> WebResult<Response> {
+
+    let old = query.old.parse::<StorePageId>()?;
+    let new = query.new.parse::<StorePageId>()?;
+
+    let (old_title, new_title, diff) = {
+        let store = state.store(&dump_name)?;
+        let title = |id: StorePageId| -> Result<Option<String>> {
+            let page = try2!(store.get_page_by_store_id(id));
+            let title = page.borrow()?.get_title()?.to_string();
+            Ok(Some(title))
+        };
+
+        let (Some(old_title), Some(new_title)) = (title(old)?, title(new)?) else {
+            return Ok(_404_response(&"Page not found"));
+        };
+        (old_title, new_title, store.diff(old, new)?)
+    };
+
+    Ok(PageDiffHtml {
+        title: format!("{old_title} vs {new_title} - diff"),
+
+        old_href: format!("/{dump_name}/page/by-store-id/{old}"),
+        old_label: old_title,
+        new_href: format!("/{dump_name}/page/by-store-id/{new}"),
+        new_label: new_title,
+        history_href: None,
+
+        lines: diff.lines.into_iter().map(DiffLineHtml::from).collect(),
+    }.into_response())
+}
+
+#[derive(Deserialize)]
+struct PageSearchQuery {
+    query: Option<String>,
+
+    /// 0 for any length.
+    min_words: Option<u64>,
+    max_words: Option<u64>,
+    order: Option<index::PageSearchOrder>,
+
+    /// Page flag filters added to the query, e.g. `-is:disambiguation`.
+    page_type: Option<String>,
+
+    /// Only show pages in the namespace with this ID.
+    namespace: Option<i64>,
+
+    /// Only show pages in the category with this slug.
+    category: Option<String>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "page_search.html")]
+struct PageSearchHtml {
+    title: String,
+    dump_name: String,
+
+    query: Option<String>,
+    min_words: u64,
+    max_words: Option<u64>,
+    order: &'static str,
+    page_type: String,
+    min_words_options: Vec<SelectOptionHtml>,
+    order_options: Vec<SelectOptionHtml>,
+    page_type_options: Vec<SelectOptionHtml>,
+
+    pages: Vec<PageSearchResultHtml>,
+    show_more_href: Option<String>,
+
+    /// A respelling of the query to offer when there are few results.
+    suggestion: Option<PageSearchSuggestionHtml>,
+
+    /// The namespace and category filters, kept by the form.
+    namespace: Option<i64>,
+    category: Option<String>,
+
+    namespace_facets: Vec<FacetHtml>,
+    category_facets: Vec<FacetHtml>,
+
+    /// Set if the facets count only the first matches, to the count counted.
+    facets_truncated: Option<u64>,
+}
+
+/// A filter that refines a page search, with the count of results it keeps.
+struct FacetHtml {
+    label: String,
+    pages_len: u64,
+
+    /// Applies the filter, or removes it if it is `selected`.
+    href: String,
+    selected: bool,
+}
+
+struct PageSearchSuggestionHtml {
+    query: String,
+    href: String,
+}
+
+struct PageSearchResultHtml {
+    page: index::Page,
+
+    /// The page's display title if it has one, otherwise its slug.
+    title: String,
+}
+
+struct SelectOptionHtml {
+    value: String,
+    label: &'static str,
+    selected: bool,
+}
+
+impl SelectOptionHtml {
+    fn list<T: Display + PartialEq>(options: &[(T, &'static str)], selected: &T
+    ) -> Vec<SelectOptionHtml> {
+        options.iter().map(|(value, label)| SelectOptionHtml {
+            value: value.to_string(),
+            label,
+            selected: value == selected,
+        }).collect()
+    }
+}
+
+/// List pages changed in the default store.
+async fn get_changes(
+    State(state): State<Arc<WebState>>,
+    Query(query): Query<web_api::ChangesQuery>,
+) -> WebResult<Response> {
+    let dump_name = state.default_dump_name();
+    Ok(changes(&state, &dump_name.0, query)?)
+}
+
+async fn get_store_changes(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+    Query(query): Query<web_api::ChangesQuery>,
+) -> WebResult<Response> {
+    Ok(changes(&state, &dump_name, query)?)
+}
+
+fn changes(state: &WebState, dump_name: &str, query: web_api::ChangesQuery) -> Result<Response> {
+    let cursor = match query.cursor.as_deref().map(str::parse::<index::ChangesCursor>) {
+        None => None,
+        Some(Ok(cursor)) => Some(cursor),
+        Some(Err(e)) => return Ok(_400_response(&e)),
+    };
+
+    let changes = state.store(dump_name)?.pages_modified_since(query.since, cursor,
+                                                                query.limit.into())?;
+
+    Ok(axum::Json(web_api::Changes {
+        pages: changes.pages.into_iter().map(|page| web_api::PageChange {
+            url: format!("/{dump_name}/page/by-id/{id}", id = page.mediawiki_id),
+            mediawiki_id: page.mediawiki_id,
+            slug: page.slug,
+            ns_id: page.ns_id,
+            revision_timestamp: page.revision_timestamp,
+        }).collect(),
+        next_cursor: changes.next_cursor.map(|cursor| cursor.to_string()),
+    }).into_response())
+}
+
+async fn get_page_sections(
+    State(state): State<Arc<WebState>>,
+    Path(page_id): Path<u64>,
+) -> WebResult<Response> {
+    let dump_name = state.default_dump_name();
+    Ok(page_sections(&state, &dump_name.0, page_id)?)
+}
+
+async fn get_store_page_sections(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, page_id)): Path<(String, u64)>,
+) -> WebResult<Response> {
+    Ok(page_sections(&state, &dump_name, page_id)?)
+}
+
+fn page_sections(state: &WebState, dump_name: &str, page_id: u64) -> Result<Response> {
+    let Some(sections) = state.store(dump_name)?.get_page_sections(page_id)? else {
+        return Ok(_404_response(&"Page not found"));
+    };
+
+    Ok(axum::Json(web_api::PageSections {
+        mediawiki_id: page_id,
+        sections,
+    }).into_response())
+}
+
+/// Page searches with fewer results than this suggest a respelling of the query.
+const SUGGEST_RESULTS_MAX: usize = 5;
+
+/// The most categories offered as filters on page search results.
+const SEARCH_CATEGORY_FACETS_MAX: u64 = 10;
+
+/// Search the default store.
+async fn get_page_search(
+    State(state): State<Arc<WebState>>,
+    Query(query): Query<PageSearchQuery>,
+) -> WebResult<impl IntoResponse> {
+    let dump_name = state.default_dump_name();
+    Ok(page_search(&state, dump_name, query)?)
+}
+
+async fn get_store_page_search(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+    Query(query): Query<PageSearchQuery>,
+) -> WebResult<impl IntoResponse> {
+    Ok(page_search(&state, DumpName(dump_name), query)?)
+}
+
+fn page_search(state: &WebState, dump_name: DumpName, query: PageSearchQuery
+) -> Result<PageSearchHtml> {
+    let min_words = query.min_words.unwrap_or(0);
+    let order = query.order.unwrap_or_default();
+    let order_name = order.as_str();
+    let min_words_options = SelectOptionHtml::list(
+        &[(0, "Any length"),
+          (100, "100+ words (no stubs)"),
+          (500, "500+ words"),
+          (2000, "2000+ words")],
+        &min_words);
+    let order_options = SelectOptionHtml::list(
+        &[("relevance", "Best match"),
+          ("longest", "Longest first"),
+          ("shortest", "Shortest first")],
+        &order_name);
+    let page_type = query.page_type.unwrap_or_default();
+    let page_type_options = SelectOptionHtml::list(
+        &[("", "All pages"),
+          ("-is:disambiguation", "Not disambiguation pages"),
+          ("-is:stub -is:disambiguation -is:list", "Full articles"),
+          ("is:stub", "Stubs"),
+          ("is:disambiguation", "Disambiguation pages"),
+          ("is:list", "Lists")],
+        &page_type.as_str());
+
+    let Some(query_string) = query.query else {
+        return Ok(PageSearchHtml {
+                title: "Page search".to_string(),
+                dump_name: dump_name.0,
+                query: None,
+                min_words,
+                max_words: query.max_words,
+                order: order_name,
+                page_type,
+                min_words_options,
+                order_options,
+                page_type_options,
+                pages: Vec::with_capacity(0),
+                show_more_href: None,
+                suggestion: None,
+                namespace: query.namespace,
+                category: query.category,
+                namespace_facets: Vec::with_capacity(0),
+                category_facets: Vec::with_capacity(0),
+                facets_truncated: None,
+            });
+    };
+
+    let store = state.store(&*dump_name.0)?;
+
+    let max_words = query.max_words;
+    let search_href = |query: &str, namespace: Option<i64>, category: Option<&str>| {
+        let mut href = format!(
+            "/{dump_name}/page/search?query={query}&min_words={min_words}\
+             &order={order_name}&page_type={page_type}",
+            dump_name = dump_name.0,
+            query = percent_encoding::utf8_percent_encode(query, NON_ALPHANUMERIC),
+            page_type = percent_encoding::utf8_percent_encode(&page_type, NON_ALPHANUMERIC));
+        if let Some(max_words) = max_words {
+            href.push_str(&format!("&max_words={max_words}"));
+        }
+        if let Some(namespace) = namespace {
+            href.push_str(&format!("&namespace={namespace}"));
+        }
+        if let Some(category) = category {
+            href.push_str(&format!(
+                "&category={category}",
+                category = percent_encoding::utf8_percent_encode(category, NON_ALPHANUMERIC)));
+        }
+        href
+    };
+
+    let search_query = format!("{query_string} {page_type}");
+    let search_options = index::PageSearchOptions {
+        limit: QueryLimit::default(), // TODO
+        min_words: Some(min_words).filter(|min| *min > 0),
+        max_words: query.max_words,
+        category: query.category.clone().map(CategorySlug),
+        namespace: query.namespace,
+        order,
+        ..Default::default()
+    };
+    let pages = store.page_search(&search_query, &search_options)?;
+    let facets = store.page_search_facets(&search_query, &search_options,
+                                          SEARCH_CATEGORY_FACETS_MAX)?;
+    let mut display_titles = store.get_display_titles(
+        &pages.iter().map(|page| page.mediawiki_id).collect::<Vec<u64>>())?;
+    let pages = pages.into_iter()
+                     .map(|page| PageSearchResultHtml {
+                         title: display_titles.remove(&page.mediawiki_id)
+                                              .unwrap_or_else(|| page.slug.clone()),
+                         page,
+                     })
+                     .collect::<Vec<_>>();
+
+    let suggestion = if pages.len() < SUGGEST_RESULTS_MAX {
+        store.suggest(&query_string)?.map(|suggestion| PageSearchSuggestionHtml {
+            href: search_href(&suggestion, query.namespace, query.category.as_deref()),
+            query: suggestion,
+        })
+    } else {
+        None
+    };
+
+    let namespace_facets = facets.namespaces.iter()
+        .map(|facet| {
+            let selected = query.namespace == Some(facet.ns_id);
+            FacetHtml {
+                label: Namespace::from_key(facet.ns_id)
+                           .map(|ns| ns.name().to_string())
+                           .unwrap_or_else(|_| format!("Namespace {}", facet.ns_id)),
+                pages_len: facet.pages_len,
+                href: search_href(&query_string,
+                                  (!selected).then_some(facet.ns_id),
+                                  query.category.as_deref()),
+                selected,
+            }
+        })
+        .collect::<Vec<_>>();
+    let category_facets = facets.categories.iter()
+        .map(|facet| {
+            let selected = query.category.as_deref() == Some(&*facet.category.slug.0);
+            FacetHtml {
+                label: facet.category.name.0.clone(),
+                pages_len: facet.pages_len,
+                href: search_href(&query_string,
+                                  query.namespace,
+                                  (!selected).then_some(&*facet.category.slug.0)),
+                selected,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(PageSearchHtml {
+        title: "Page search".to_string(),
+        dump_name: dump_name.0,
+        query: Some(query_string),
+        min_words,
+        max_words: query.max_words,
+        order: order_name,
+        page_type,
+        min_words_options,
+        order_options,
+        page_type_options,
+        pages,
+        show_more_href: None, // TODO
+        suggestion,
+        namespace: query.namespace,
+        category: query.category,
+        namespace_facets,
+        category_facets,
+        facets_truncated: facets.truncated.then_some(index::PAGE_SEARCH_FACET_PAGES_MAX),
+    })
+}
+
+/// The most results returned by the saved search results API.
+const SAVED_SEARCH_RESULTS_MAX: u64 = 100;
+
+/// The form posted from the page search results to save the search.
+#[derive(Deserialize)]
+struct SaveSearchForm {
+    name: String,
+    query: String,
+    min_words: Option<u64>,
+    max_words: Option<u64>,
+    order: Option<index::PageSearchOrder>,
+
+    /// Page flag filters, saved as part of the query.
+    page_type: Option<String>,
+    namespace: Option<i64>,
+    category: Option<String>,
+}
+
+/// Save a page search, then show its results.
+async fn post_saved_search(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+    Form(form): Form<SaveSearchForm>,
+) -> WebResult<Response> {
+    let search = index::SavedSearch {
+        name: form.name.trim().to_string(),
+        query: format!("{query} {page_type}",
+                       query = form.query,
+                       page_type = form.page_type.unwrap_or_default())
+                   .trim().to_string(),
+        min_words: form.min_words.filter(|min| *min > 0),
+        max_words: form.max_words,
+        category: form.category.map(CategorySlug),
+        namespace: form.namespace,
+        order: form.order.unwrap_or_default(),
+        saved: chrono::Utc::now(),
+    };
+
+    match state.store(&dump_name)?.save_search(&search) {
+        Ok(()) => (),
+        Err(e) if ErrorKind::of(&e) == ErrorKind::User => {
+            return Ok(_400_response(&format!("{e:#}")));
+        },
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(Redirect::to(&saved_search_href(&dump_name, &search)).into_response())
+}
+
+async fn post_delete_saved_search(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, name)): Path<(String, String)>,
+) -> WebResult<Response> {
+    if !state.store(&dump_name)?.delete_saved_search(&name)? {
+        return Ok(_404_response(&"Saved search not found"));
+    }
+
+    Ok(Redirect::to(&format!("/{dump_name}/index")).into_response())
+}
+
+/// List the searches saved in the default store.
+async fn get_saved_searches(
+    State(state): State<Arc<WebState>>,
+) -> WebResult<Response> {
+    let dump_name = state.default_dump_name();
+    Ok(saved_searches(&state, &dump_name.0)?)
+}
+
+async fn get_store_saved_searches(
+    State(state): State<Arc<WebState>>,
+    Path(dump_name): Path<String>,
+) -> WebResult<Response> {
+    Ok(saved_searches(&state, &dump_name)?)
+}
+
+fn saved_searches(state: &WebState, dump_name: &str) -> Result<Response> {
+    let searches = state.store(dump_name)?.saved_searches()?;
+    Ok(axum::Json(web_api::SavedSearches {
+        searches: searches.iter()
+                          .map(|search| saved_search_api(dump_name, search))
+                          .collect(),
+    }).into_response())
+}
+
+/// Run a search saved in the default store.
+async fn get_saved_search_results(
+    State(state): State<Arc<WebState>>,
+    Path(name): Path<String>,
+) -> WebResult<Response> {
+    let dump_name = state.default_dump_name();
+    Ok(saved_search_results(&state, &dump_name.0, &name)?)
+}
+
+async fn get_store_saved_search_results(
+    State(state): State<Arc<WebState>>,
+    Path((dump_name, name)): Path<(String, String)>,
+) -> WebResult<Response> {
+    Ok(saved_search_results(&state, &dump_name, &name)?)
+}
+
+fn saved_search_results(state: &WebState, dump_name: &str, name: &str) -> Result<Response> {
+    let store = state.store(dump_name)?;
+    let Some(search) = store.saved_search(name)? else {
+        return Ok(_404_response(&"Saved search not found"));
+    };
+
+    let options = search.page_search_options(QueryLimit::new(SAVED_SEARCH_RESULTS_MAX));
+    let pages = store.page_search(&search.query, &options)?;
+
+    Ok(axum::Json(web_api::SavedSearchResults {
+        search: saved_search_api(dump_name, &search),
+        pages: pages.into_iter().map(|page| web_api::SearchResultPage {
+            url: format!("/{dump_name}/page/by-id/{id}", id = page.mediawiki_id),
+            mediawiki_id: page.mediawiki_id,
+            slug: page.slug,
+            ns_id: page.ns_id,
+            revision_timestamp: page.revision_timestamp,
+        }).collect(),
+    }).into_response())
+}
+
+fn saved_search_api(dump_name: &str, search: &index::SavedSearch) -> web_api::SavedSearch {
+    web_api::SavedSearch {
+        name: search.name.clone(),
+        query: search.query.clone(),
+        min_words: search.min_words,
+        max_words: search.max_words,
+        category: search.category.as_ref().map(|slug| slug.0.clone()),
+        namespace: search.namespace,
+        order: search.order.to_string(),
+        saved: search.saved,
+        url: saved_search_href(dump_name, search),
+    }
+}
+
+/// The path of the page search results for a saved search.
+fn saved_search_href(dump_name: &str, search: &index::SavedSearch) -> String {
+    let mut href = format!(
+        "/{dump_name}/page/search?query={query}&min_words={min_words}&order={order}",
+        query = percent_encoding::utf8_percent_encode(&search.query, NON_ALPHANUMERIC),
+        min_words = search.min_words.unwrap_or(0),
+        order = search.order);
+    if let Some(max_words) = search.max_words {
+        href.push_str(&format!("&max_words={max_words}"));
+    }
+    if let Some(namespace) = search.namespace {
+        href.push_str(&format!("&namespace={namespace}"));
+    }
+    if let Some(category) = search.category.as_ref() {
+        href.push_str(&format!(
+            "&category={category}",
+            category = percent_encoding::utf8_percent_encode(&category.0, NON_ALPHANUMERIC)));
+    }
+    href
+}
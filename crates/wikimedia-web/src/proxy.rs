@@ -0,0 +1,317 @@
+//! Serve pages missing from a store from the live wiki, see [`LiveProxy`].
+
+use anyhow::format_err;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, MutexGuard},
+    time::{Duration as StdDuration, Instant},
+};
+use wikimedia::{
+    bail_kind,
+    dump,
+    live,
+    slug,
+    Result,
+};
+
+/// Gets pages missing from a store from the live wiki's REST API.
+///
+/// Fetched pages are kept in a cache of at most `cache_len` pages for `ttl`, then fetched
+/// again on the next request for them. Pages the live wiki doesn't have are cached too, so
+/// repeated requests for them don't reach the live wiki. Fetches from the live wiki are
+/// limited to `fetches_per_sec`; requests over the limit wait their turn.
+///
+/// If [`LiveProxy::set_save_pages`] is set, fetched pages are also queued to be written to
+/// the store, so they are served from the store afterwards, including after a restart. The
+/// queue is written once it has [`SAVE_BATCH_LEN`] pages, and `wmd proxy` also writes it
+/// every [`SAVE_INTERVAL`], see
+/// [`WebState::save_live_pages`](crate::WebState::save_live_pages). The pages written
+/// together share chunks rather than each getting a chunk file of its own, see
+/// [`Store::upsert_pages`](wikimedia_store::Store::upsert_pages).
+///
+/// `ttl` doesn't apply to saved pages. Once saved, a page is part of the store just like an
+/// imported page, and the store doesn't record when its pages were fetched, so it is served
+/// from the store until it's written again, e.g. by importing a later dump.
+pub struct LiveProxy {
+    client: live::Client,
+    ttl: StdDuration,
+    save_pages: bool,
+
+    cache: Mutex<PageCache>,
+    fetch_limit: Mutex<FetchLimit>,
+
+    /// Pages fetched and not yet saved to the store, if `save_pages` is set.
+    save_queue: Mutex<Vec<dump::Page>>,
+}
+
+/// The default count of pages kept in a [`LiveProxy`]'s cache.
+pub const CACHE_LEN_DEFAULT: usize = 1000;
+
+/// The default limit on fetches from the live wiki per second.
+pub const FETCHES_PER_SEC_DEFAULT: u32 = 5;
+
+/// The count of queued pages that are saved to the store together, see [`LiveProxy`].
+pub const SAVE_BATCH_LEN: usize = 100;
+
+/// How often `wmd proxy` saves queued pages to the store, however many are queued.
+pub const SAVE_INTERVAL: StdDuration = StdDuration::from_secs(60);
+
+/// Pages fetched recently by slug, evicting the least recently used beyond `max_len`.
+struct PageCache {
+    max_len: usize,
+
+    /// The page for each slug, `None` for pages the live wiki doesn't have, with when it was
+    /// fetched and its last use.
+    entries: HashMap<String, CacheEntry>,
+
+    /// Slugs by their last use, oldest first.
+    by_use: BTreeMap<u64, String>,
+    next_use: u64,
+}
+
+struct CacheEntry {
+    fetched: Instant,
+    used: u64,
+    page: Option<dump::Page>,
+}
+
+/// A token bucket of fetches, refilled at `per_sec` up to one second's worth.
+struct FetchLimit {
+    per_sec: u32,
+
+    /// Fetches available and when they were counted. Negative while requests wait.
+    available: f64,
+    counted: Instant,
+}
+
+impl LiveProxy {
+    pub fn new(client: live::Client, ttl: StdDuration) -> LiveProxy {
+        LiveProxy {
+            client,
+            ttl,
+            save_pages: false,
+            cache: Mutex::new(PageCache::new(CACHE_LEN_DEFAULT)),
+            fetch_limit: Mutex::new(FetchLimit::new(FETCHES_PER_SEC_DEFAULT)),
+            save_queue: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Save fetched pages to the store, see [`LiveProxy`]. Off by default.
+    pub fn set_save_pages(&mut self, save_pages: bool) -> &mut Self {
+        self.save_pages = save_pages;
+        self
+    }
+
+    pub fn save_pages(&self) -> bool {
+        self.save_pages
+    }
+
+    /// Keep at most `cache_len` pages in the cache. The default is [`CACHE_LEN_DEFAULT`].
+    pub fn set_cache_len(&mut self, cache_len: usize) -> &mut Self {
+        self.cache = Mutex::new(PageCache::new(cache_len));
+        self
+    }
+
+    /// Fetch at most `fetches_per_sec` pages from the live wiki per second. The default is
+    /// [`FETCHES_PER_SEC_DEFAULT`].
+    pub fn set_fetches_per_sec(&mut self, fetches_per_sec: u32) -> Result<&mut Self> {
+        if fetches_per_sec == 0 {
+            bail_kind!(User, "The live wiki fetch limit must be at least 1 per second.");
+        }
+        self.fetch_limit = Mutex::new(FetchLimit::new(fetches_per_sec));
+        Ok(self)
+    }
+
+    /// Get the page with `slug` from the cache or the live wiki, or `None` if the live wiki
+    /// doesn't have it.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_page(&self, slug: &str) -> Result<Option<dump::Page>> {
+        if let Some(page) = self.lock_cache()?.get(slug, self.ttl) {
+            return Ok(page);
+        }
+
+        let wait = self.lock_fetch_limit()?.take();
+        if !wait.is_zero() {
+            tracing::debug!(slug, ?wait, "Waiting for the live wiki fetch limit");
+            tokio::time::sleep(wait).await;
+        }
+
+        let page = self.client.get_page(&slug::slug_to_title(slug)).await?;
+        tracing::info!(slug, found = page.is_some(), "Fetched page from live wiki");
+        self.lock_cache()?.insert(slug, page.clone(), self.ttl);
+        Ok(page)
+    }
+
+    /// Queue `page` to be saved to the store. Returns true once [`SAVE_BATCH_LEN`] pages are
+    /// queued, so the caller should save them.
+    pub(crate) fn queue_save(&self, page: dump::Page) -> Result<bool> {
+        let mut save_queue = self.lock_save_queue()?;
+        save_queue.push(page);
+        Ok(save_queue.len() >= SAVE_BATCH_LEN)
+    }
+
+    /// Take the pages queued to be saved to the store.
+    pub(crate) fn take_save_queue(&self) -> Result<Vec<dump::Page>> {
+        Ok(std::mem::take(&mut *self.lock_save_queue()?))
+    }
+
+    fn lock_cache(&self) -> Result<MutexGuard<'_, PageCache>> {
+        self.cache.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))
+    }
+
+    fn lock_fetch_limit(&self) -> Result<MutexGuard<'_, FetchLimit>> {
+        self.fetch_limit.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))
+    }
+
+    fn lock_save_queue(&self) -> Result<MutexGuard<'_, Vec<dump::Page>>> {
+        self.save_queue.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))
+    }
+}
+
+impl PageCache {
+    fn new(max_len: usize) -> PageCache {
+        PageCache {
+            max_len: max_len.max(1),
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            next_use: 0,
+        }
+    }
+
+    /// The cached page for `slug`, if it was fetched within `ttl`.
+    fn get(&mut self, slug: &str, ttl: StdDuration) -> Option<Option<dump::Page>> {
+        let entry = self.entries.get_mut(slug)?;
+        if entry.fetched.elapsed() >= ttl {
+            return None;
+        }
+
+        self.by_use.remove(&entry.used);
+        entry.used = self.next_use;
+        self.next_use += 1;
+        self.by_use.insert(entry.used, slug.to_string());
+        Some(entry.page.clone())
+    }
+
+    /// Cache `page` for `slug`, evicting expired pages then the least recently used while
+    /// the cache is full.
+    fn insert(&mut self, slug: &str, page: Option<dump::Page>, ttl: StdDuration) {
+        if let Some(entry) = self.entries.remove(slug) {
+            self.by_use.remove(&entry.used);
+        }
+        if self.entries.len() >= self.max_len {
+            let by_use = &mut self.by_use;
+            self.entries.retain(|_, entry| {
+                let keep = entry.fetched.elapsed() < ttl;
+                if !keep {
+                    by_use.remove(&entry.used);
+                }
+                keep
+            });
+        }
+        while self.entries.len() >= self.max_len {
+            let Some((_used, oldest)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+
+        let used = self.next_use;
+        self.next_use += 1;
+        self.by_use.insert(used, slug.to_string());
+        self.entries.insert(slug.to_string(), CacheEntry {
+            fetched: Instant::now(),
+            used,
+            page,
+        });
+    }
+}
+
+impl FetchLimit {
+    fn new(per_sec: u32) -> FetchLimit {
+        FetchLimit {
+            per_sec,
+            available: f64::from(per_sec),
+            counted: Instant::now(),
+        }
+    }
+
+    /// Take one fetch, returning how long to wait before making it.
+    fn take(&mut self) -> StdDuration {
+        let rate = f64::from(self.per_sec);
+        let now = Instant::now();
+        self.available = (self.available + now.duration_since(self.counted).as_secs_f64()
+                                           * rate)
+                             .min(rate);
+        self.counted = now;
+        self.available -= 1.0;
+        if self.available < 0.0 {
+            StdDuration::from_secs_f64(-self.available / rate)
+        } else {
+            StdDuration::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(id: u64) -> dump::Page {
+        dump::Page {
+            ns_id: 0,
+            id,
+            title: format!("Page {id}"),
+            revision: None,
+            restrictions: None,
+        }
+    }
+
+    #[test]
+    fn page_cache_evicts_least_recently_used() {
+        let ttl = StdDuration::from_secs(60);
+        let mut cache = PageCache::new(2);
+        cache.insert("Page_1", Some(page(1)), ttl);
+        cache.insert("Missing", None, ttl);
+        assert_eq!(cache.get("Page_1", ttl).unwrap().unwrap().id, 1);
+
+        // "Missing" is now the least recently used.
+        cache.insert("Page_3", Some(page(3)), ttl);
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get("Missing", ttl).is_none());
+        assert!(cache.get("Page_1", ttl).is_some());
+        assert!(cache.get("Page_3", ttl).is_some());
+
+        // Expired pages aren't returned.
+        assert!(cache.get("Page_1", StdDuration::ZERO).is_none());
+    }
+
+    #[test]
+    fn save_queue_fills_batches() {
+        let http_options = wikimedia::http::Options::builder()
+            .cache_path(std::env::temp_dir().join("wikimedia-web-tests").join("http_cache"))
+            .build().unwrap();
+        let client = live::Client::new("https://wiki.example.com/w/rest.php", &http_options)
+                         .unwrap();
+        let live_proxy = LiveProxy::new(client, StdDuration::from_secs(60));
+
+        for id in 1..SAVE_BATCH_LEN {
+            assert!(!live_proxy.queue_save(page(id as u64)).unwrap());
+        }
+        assert!(live_proxy.queue_save(page(SAVE_BATCH_LEN as u64)).unwrap());
+        assert_eq!(live_proxy.take_save_queue().unwrap().len(), SAVE_BATCH_LEN);
+        assert!(live_proxy.take_save_queue().unwrap().is_empty());
+    }
+
+    #[test]
+    fn fetch_limit_waits_after_burst() {
+        let mut limit = FetchLimit::new(2);
+        assert!(limit.take().is_zero());
+        assert!(limit.take().is_zero());
+        let wait = limit.take();
+        assert!(wait > StdDuration::from_millis(400) && wait <= StdDuration::from_millis(500),
+                "{wait:?}");
+    }
+}
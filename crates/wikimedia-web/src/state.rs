@@ -0,0 +1,231 @@
+//! The stores served by the web interface.
+
+use anyhow::{bail, format_err};
+use crate::{auth::Authenticator, proxy::LiveProxy};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+use wikimedia::{dump::{DumpName, SiteInfo}, events, Result, wikitext::{self, HighlightStyle, RenderLimits}};
+use wikimedia_store::{self as store, Store};
+
+/// The state shared by the web interface's request handlers, see [`router`](crate::router).
+pub struct WebState {
+    /// The stores served, in the order given to [`WebState::new`]. The first is the default
+    /// store.
+    stores: Vec<StoreEntry>,
+
+    out_dir: PathBuf,
+
+    /// Authenticates admin requests. If `None` they are rejected.
+    authenticator: Option<Arc<dyn Authenticator>>,
+
+    highlight_style: HighlightStyle,
+
+    /// The CSS for `highlight_style`, generated on the first request for it.
+    highlight_css: OnceLock<String>,
+
+    render_limits: RenderLimits,
+
+    /// Progress events shown on the admin progress page.
+    events: events::Sender,
+}
+
+struct StoreEntry {
+    dump_name: DumpName,
+    options: store::Options,
+
+    /// Opened on the first request for this store.
+    store: OnceLock<Mutex<Store>>,
+
+    /// Held while opening the store, so it is only opened once.
+    open_lock: Mutex<()>,
+
+    /// Serves pages missing from the store, if set.
+    live_proxy: Option<LiveProxy>,
+}
+
+impl WebState {
+    /// Serve a store for each dump name, opened with its options on the first request for
+    /// it. The first store is the default, e.g. for page search. Temporary files are written
+    /// in `out_dir`.
+    pub fn new(store_options: Vec<(DumpName, store::Options)>, out_dir: PathBuf
+    ) -> Result<WebState>
+    {
+        if store_options.is_empty() {
+            bail!("The web interface needs at least one store to serve.");
+        }
+
+        let mut stores = Vec::<StoreEntry>::with_capacity(store_options.len());
+        for (dump_name, options) in store_options {
+            if stores.iter().any(|entry| entry.dump_name == dump_name) {
+                bail!("The store for dump '{dump_name}' was given more than once.",
+                      dump_name = dump_name.0);
+            }
+            stores.push(StoreEntry {
+                dump_name,
+                options,
+                store: OnceLock::new(),
+                open_lock: Mutex::new(()),
+                live_proxy: None,
+            });
+        }
+
+        Ok(WebState {
+            stores,
+            out_dir,
+            authenticator: None,
+            highlight_style: HighlightStyle::default(),
+            highlight_css: OnceLock::new(),
+            render_limits: RenderLimits::default(),
+            events: events::Sender::new(),
+        })
+    }
+
+    /// The theme for highlighted code in pages. [`HighlightStyle::default()`] if not set.
+    pub fn set_highlight_style(&mut self, highlight_style: HighlightStyle) -> &mut Self {
+        self.highlight_style = highlight_style;
+        self.highlight_css = OnceLock::new();
+        self
+    }
+
+    /// The CSS for highlighted code in pages, see [`wikitext::highlighting_css`].
+    pub async fn highlight_css(&self) -> Result<&str> {
+        if self.highlight_css.get().is_none() {
+            let css = wikitext::highlighting_css(self.highlight_style, &self.out_dir).await?;
+            // Another request may have set it meanwhile, with the same CSS.
+            let _ = self.highlight_css.set(css);
+        }
+        Ok(self.highlight_css.get().expect("highlight_css set above"))
+    }
+
+    /// Limits on rendering each page, see [`wikitext::convert_page_to_html`].
+    /// [`RenderLimits::default()`] if not set.
+    pub fn set_render_limits(&mut self, render_limits: RenderLimits) -> &mut Self {
+        self.render_limits = render_limits;
+        self
+    }
+
+    pub fn render_limits(&self) -> &RenderLimits {
+        &self.render_limits
+    }
+
+    /// Authenticate admin requests with `authenticator`, see [`auth`](crate::auth). By
+    /// default admin requests are rejected.
+    pub fn set_authenticator(&mut self, authenticator: Arc<dyn Authenticator>) -> &mut Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    pub fn authenticator(&self) -> Option<&dyn Authenticator> {
+        self.authenticator.as_deref()
+    }
+
+    /// Serve pages missing from the store for `dump_name` from the live wiki with
+    /// `live_proxy`. By default requests for missing pages return 404 Not Found.
+    pub fn set_live_proxy(&mut self, dump_name: &str, live_proxy: LiveProxy
+    ) -> Result<&mut Self>
+    {
+        let entry = self.stores.iter_mut()
+            .find(|entry| &*entry.dump_name.0 == dump_name)
+            .ok_or_else(|| format_err!(
+                "WebState::set_live_proxy() error: No store for dump name ({dump_name})"))?;
+        entry.live_proxy = Some(live_proxy);
+        Ok(self)
+    }
+
+    /// The live proxy for the store for `dump_name`, if set.
+    pub fn live_proxy(&self, dump_name: &str) -> Option<&LiveProxy> {
+        self.stores.iter()
+            .find(|entry| &*entry.dump_name.0 == dump_name)
+            .and_then(|entry| entry.live_proxy.as_ref())
+    }
+
+    /// Write the pages that the live proxy for `dump_name` fetched and queued to the store
+    /// together, see [`LiveProxy`]. Returns the count of pages written. If writing them fails
+    /// they are dropped, and queued again when they are next fetched.
+    pub fn save_live_pages(&self, dump_name: &str) -> Result<usize> {
+        let Some(live_proxy) = self.live_proxy(dump_name) else {
+            return Ok(0);
+        };
+        let pages = live_proxy.take_save_queue()?;
+        if pages.is_empty() {
+            return Ok(0);
+        }
+        self.store(dump_name)?.upsert_pages(&pages)?;
+        tracing::info!(pages_len = pages.len(), "Saved live pages to store");
+        Ok(pages.len())
+    }
+
+    /// The sender for progress events shown on the admin progress page at
+    /// `/admin/progress`. Set it in download and store options to show their progress.
+    pub fn events(&self) -> &events::Sender {
+        &self.events
+    }
+
+    /// The directory temporary files are written in.
+    pub fn out_dir(&self) -> &Path {
+        &self.out_dir
+    }
+
+    /// Switch the default store to a new generation for later requests, see
+    /// [`Store::publish`].
+    pub fn publish_store_generation(&self, generation_path: &Path) -> Result<()> {
+        let entry = &self.stores[0];
+        let _open_guard = entry.open_lock.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))?;
+
+        // A store not opened yet will use the new generation when it is opened.
+        let Some(store) = entry.store.get() else {
+            return Ok(());
+        };
+        store.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))?
+            .publish(generation_path)
+    }
+
+    /// Get the store for `dump_name`, opening it if this is the first request for it.
+    pub fn store<'state>(&'state self, dump_name: &str
+    ) -> Result<MutexGuard<'state, Store>>
+    {
+        let entry = self.stores.iter()
+            .find(|entry| &*entry.dump_name.0 == dump_name)
+            .ok_or_else(|| format_err!(
+                "WebState::store() error: No store loaded for dump name ({dump_name})"))?;
+
+        entry.open()?.lock()
+             .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))
+    }
+
+    /// The dump names of the stores served, the default store first.
+    pub fn dump_names(&self) -> Vec<DumpName> {
+        self.stores.iter().map(|entry| entry.dump_name.clone()).collect()
+    }
+
+    /// The dump name of the default store, used for routes without a dump name.
+    pub fn default_dump_name(&self) -> DumpName {
+        self.stores[0].dump_name.clone()
+    }
+
+    pub fn site(&self, dump_name: &str) -> Result<Option<SiteInfo>> {
+        Ok(self.store(dump_name)?.site())
+    }
+}
+
+impl StoreEntry {
+    fn open(&self) -> Result<&Mutex<Store>> {
+        if let Some(store) = self.store.get() {
+            return Ok(store);
+        }
+
+        let _open_guard = self.open_lock.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in web module"))?;
+        if let Some(store) = self.store.get() {
+            return Ok(store);
+        }
+
+        tracing::info!(dump_name = self.dump_name.0, "Opening store");
+        let store = self.options.build()?;
+        Ok(self.store.get_or_init(|| Mutex::new(store)))
+    }
+}
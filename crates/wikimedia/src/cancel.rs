@@ -0,0 +1,68 @@
+//! Stopping long-running work part way through, e.g. when the user presses ctrl-c.
+//!
+//! Work that can stop cleanly takes a [`CancellationToken`] and checks it between units of
+//! work, such as chunks or files, so it stops in a consistent state instead of wherever the
+//! process happened to be when it was killed.
+
+use crate::Result;
+
+pub use tokio_util::sync::CancellationToken;
+
+/// The exit code for a process stopped by a signal, as shells report for SIGINT.
+pub const EXIT_CODE: u8 = 130;
+
+/// Returns a token that is cancelled when this process receives SIGINT (ctrl-c) or, on unix,
+/// SIGTERM.
+///
+/// This replaces the default handling of those signals, which kills the process. A second
+/// signal received after the token was cancelled exits immediately with [`EXIT_CODE`], for
+/// when stopping cleanly takes too long.
+///
+/// Must be called from within a tokio runtime.
+pub fn cancel_on_signal() -> Result<CancellationToken> {
+    let token = CancellationToken::new();
+    let handler_token = token.clone();
+
+    #[cfg(unix)]
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+    tokio::spawn(async move {
+        loop {
+            #[cfg(unix)]
+            let signal = tokio::select! {
+                res = tokio::signal::ctrl_c() => res.map(|()| "SIGINT"),
+                _ = sigterm.recv() => Ok("SIGTERM"),
+            };
+            #[cfg(not(unix))]
+            let signal = tokio::signal::ctrl_c().await.map(|()| "ctrl-c");
+
+            let signal = match signal {
+                Ok(signal) => signal,
+                Err(e) => {
+                    tracing::error!(err = %e, "Error listening for signals");
+                    return;
+                }
+            };
+
+            if handler_token.is_cancelled() {
+                eprintln!("Received {signal} again, exiting now.");
+                std::process::exit(EXIT_CODE.into());
+            }
+
+            tracing::warn!(signal, "Received a signal, stopping after the current unit of work");
+            eprintln!("Received {signal}, stopping cleanly. Send it again to exit now.");
+            handler_token.cancel();
+        }
+    });
+
+    Ok(token)
+}
+
+/// Return a [`Cancelled`](crate::ErrorKind::Cancelled) error if `token` has been cancelled.
+/// `what` describes the work that stopped, e.g. "Download".
+pub fn check(token: &CancellationToken, what: &str) -> Result<()> {
+    if token.is_cancelled() {
+        crate::bail_kind!(Cancelled, "{what} cancelled");
+    }
+    Ok(())
+}
@@ -1,15 +1,15 @@
 //! Operations on Wikimedia article dump archives.
 
+#[cfg(feature = "download")]
 pub mod download;
 pub mod local;
+#[cfg(feature = "download")]
+pub mod metadata_cache;
+#[cfg(feature = "download")]
+pub mod source;
+pub mod synthetic;
 
-mod types;
-pub use types::*;
+mod site;
+pub use site::SiteInfo;
 
-pub fn dump_name_to_wikimedia_url_base(dump: &DumpName) -> Option<String> {
-    match &*dump.0 {
-        "enwiki" => Some("https://en.wikipedia.org/wiki".to_string()),
-        "simplewiki" => Some("https://simple.wikipedia.org/wiki".to_string()),
-        _ => None,
-    }
-}
+pub use wikimedia_types::dump::*;
@@ -1,9 +1,14 @@
 //! Download data from Wikimedia dumps server and mirrors.
 
 use anyhow::{bail, Context, format_err};
+use chrono::{DateTime, Utc};
 use crate::{
-    dump::{self, DumpName, DumpVersionStatus, FileMetadata, JobName, JobStatus,
-           local, Version, VersionSpec},
+    bail_kind,
+    cancel::{self, CancellationToken},
+    dump::{self, DumpInfo, DumpName, DumpsIndex, DumpVersionStatus, FileMetadata, JobName,
+           JobStatus, local, metadata_cache::{self, MetadataCache},
+           source::{DumpSource, WikimediaSource}, Status, Version, VersionSpec},
+    events::{self, Event},
     http,
     Result,
     TempDir,
@@ -14,9 +19,12 @@ use crate::{
     },
 };
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
 use sha1::{Sha1, Digest};
 use std::{
+    fs,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
     time::{Duration as StdDuration, Instant},
 };
 use tokio_stream::StreamExt;
@@ -26,11 +34,92 @@ use valuable::Valuable;
 #[derive(Builder, Clone, Debug)]
 pub struct Options {
     http_options: http::Options,
+    metadata_cache_options: metadata_cache::Options,
+
+    /// Download the files that are done from a job that is not done yet, see
+    /// [`get_job_status`].
+    #[builder(default = "false")]
+    allow_incomplete_job: bool,
 
     #[builder(default = "false")]
     keep_temp_dir: bool,
-    dump_mirror_url: String,
     out_dir: PathBuf,
+
+    /// Where to download job files from, by default the Wikimedia dumps server.
+    #[builder(default = "Arc::new(WikimediaSource::default())")]
+    source: Arc<dyn DumpSource>,
+
+    /// Where to send progress events, if anywhere.
+    #[builder(default, setter(strip_option))]
+    events: Option<events::Sender>,
+
+    /// Other directories laid out like `out_dir` to look in for a copy of each job file
+    /// before downloading it, e.g. a team's shared cache on a file server. A copy is only
+    /// used if its size and SHA1 hash match the job's metadata, so none are used for jobs
+    /// without SHA1 hashes.
+    #[builder(default)]
+    shared_dirs: Vec<PathBuf>,
+
+    /// How to put a copy found in `shared_dirs` into `out_dir`.
+    #[builder(default)]
+    link_mode: LinkMode,
+
+    /// Stop the download when this is cancelled. A file being downloaded is abandoned and
+    /// its temporary file removed; files already downloaded are kept, so running the
+    /// download again continues from the next file.
+    #[builder(default)]
+    cancellation: CancellationToken,
+}
+
+/// How [`download_job`] puts a verified copy of a job file from another directory into its
+/// out dir, see [`OptionsBuilder::shared_dirs`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LinkMode {
+    /// Hard link the copy, or copy it if that fails, e.g. because it's on another file
+    /// system.
+    #[default]
+    HardLink,
+
+    /// Reflink the copy, i.e. share its data until either file is changed, or copy it if
+    /// the file system doesn't support reflinks.
+    Reflink,
+
+    /// Copy the file.
+    Copy,
+}
+
+/// Downloads dump jobs, keeping its HTTP clients between downloads so their connections are
+/// reused, e.g. by a daemon that downloads each new dump version.
+///
+/// Waits [`DOWNLOAD_INTERVAL`] after each file downloaded before the next request for a
+/// job file, across all the jobs it downloads, to avoid being rate limited.
+pub struct Downloader {
+    options: Options,
+    metadata_client: MetadataClient,
+    download_client: http::Client,
+
+    /// When the last file download finished, if any.
+    last_download: Mutex<Option<Instant>>,
+}
+
+/// Time to wait after downloading a job file before the next request for a job file.
+pub const DOWNLOAD_INTERVAL: StdDuration = StdDuration::from_secs(3);
+
+/// Fetches dump metadata from the dumps server, caching it on disk.
+#[derive(Clone)]
+pub struct MetadataClient {
+    cache: MetadataCache,
+    http: http::Client,
+}
+
+/// The files in a job, returned by [`get_file_infos`].
+#[derive(Clone, Debug)]
+pub struct FileInfos {
+    pub version: Version,
+    pub job_status: Status,
+
+    /// File names and metadata, sorted by name.
+    pub files: Vec<(String, FileMetadata)>,
 }
 
 #[derive(Clone, Debug)]
@@ -38,10 +127,29 @@ pub enum ExistingFileStatus {
     NoFile,
     DeletedBecauseIncorrectSize,
     DeletedBecauseIncorrectSha1Hash,
+    DeletedBecauseChangedOnServer,
     NoSha1HashToCheck,
     FileOk,
 }
 
+/// What was checked about a job file when it was last verified, stored next to the job's
+/// files by [`VerifiedRecord::path`].
+///
+/// While the local file's length and modification time are unchanged, it doesn't need to be
+/// hashed again, and the server's `ETag` and `Last-Modified` headers show whether the server's
+/// copy has changed since.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct VerifiedRecord {
+    len: u64,
+    modified: DateTime<Utc>,
+
+    /// The SHA1 hash the file was verified against, if any.
+    sha1: Option<String>,
+
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct DownloadJobResult {
     pub download_ok: u64,
@@ -50,6 +158,10 @@ pub struct DownloadJobResult {
     pub existing_ok: u64,
     pub existing_stats: TransferStats,
 
+    /// Files linked or copied from a shared directory.
+    pub shared_ok: u64,
+    pub shared_stats: TransferStats,
+
     pub duration: util::fmt::Duration,
 }
 
@@ -57,18 +169,115 @@ pub struct DownloadJobResult {
 pub struct DownloadJobFileResult {
     pub kind: DownloadJobFileResultKind,
     pub stats: TransferStats,
+
+    /// Where the job file was downloaded to.
+    pub path: PathBuf,
 }
 
 #[derive(Clone, Debug)]
 pub enum DownloadJobFileResultKind {
     DownloadOk,
     ExistingOk,
+
+    /// Linked or copied from a shared directory.
+    SharedOk,
 }
 
-const DUMPS_WIKIMEDIA_SERVER: &'static str = "https://dumps.wikimedia.org";
+pub(crate) const DUMPS_WIKIMEDIA_SERVER: &str = "https://dumps.wikimedia.org";
+
+impl MetadataClient {
+    pub fn new(
+        http_options: &http::Options,
+        cache_options: metadata_cache::Options,
+    ) -> Result<MetadataClient> {
+        Ok(MetadataClient {
+            cache: MetadataCache::new(cache_options),
+            http: http::metadata_client(http_options)?,
+        })
+    }
+}
 
+/// Get the dumps available on the dumps server, sorted by name.
+///
+/// Reads the server's JSON index, which includes each dump's latest version and status. If that
+/// fails, falls back to scraping the HTML index, which only has dump names.
 #[tracing::instrument(level = "trace", skip(client))]
 pub async fn get_dumps(
+    client: &MetadataClient
+) -> Result<Vec<DumpInfo>> {
+    client.cache.get_or_fetch("dumps", || fetch_dumps(&client.http)).await
+}
+
+async fn fetch_dumps(
+    client: &http::Client
+) -> Result<Vec<DumpInfo>> {
+    let mut dumps = match get_dumps_from_json(client).await {
+        Ok(dumps) => dumps,
+        Err(e) => {
+            tracing::warn!(err = format!("{e:#}"),
+                           "Error getting dumps from the JSON index, \
+                            falling back to the HTML index");
+            get_dumps_from_html(client).await?
+                .into_iter()
+                .map(|name| DumpInfo {
+                    name,
+                    latest_version: None,
+                    last_updated: None,
+                    status: None,
+                })
+                .collect()
+        }
+    };
+
+    tracing::debug!(dumps_count = dumps.len(),
+                    "dumps ret count");
+
+    dumps.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if tracing::enabled!(Level::TRACE) {
+        tracing::trace!(dumps = ?dumps,
+                       "dumps ret data");
+    }
+
+    Ok(dumps)
+}
+
+async fn get_dumps_from_json(
+    client: &http::Client
+) -> Result<Vec<DumpInfo>> {
+    let url = format!("{DUMPS_WIKIMEDIA_SERVER}/index.json");
+
+    let req = client.get(url.clone())
+                    .build()?;
+
+    let fetch_res = http::fetch_text(client, req).await?;
+
+    let index: DumpsIndex = serde_json::from_str(&fetch_res.response_body)
+        .with_context(|| format!("Parsing dumps index url={url}"))?;
+
+    let dumps = index.wikis.into_iter().map(|(name, entry)| {
+        // File URLs are like `/enwiki/20230320/enwiki-20230320-pages-articles1.xml.bz2`.
+        let latest_version = entry.jobs.values()
+            .flat_map(|job| job.files.values())
+            .filter_map(|file| file.url.as_ref())
+            .find_map(|url| {
+                let cap = lazy_regex!(r"^/[-_a-zA-Z0-9]+/(?P<date>\d{8})/").captures(url)?;
+                Some(Version(cap.name("date").expect("regex capture name").as_str()
+                                .to_string()))
+            });
+
+        DumpInfo {
+            latest_version,
+            last_updated: entry.last_updated().map(|s| s.to_string()),
+            status: entry.status(),
+            name: DumpName(name),
+        }
+    }).collect::<Vec<DumpInfo>>();
+
+    Ok(dumps)
+}
+
+async fn get_dumps_from_html(
     client: &http::Client
 ) -> Result<Vec<DumpName>> {
     let url = format!("{DUMPS_WIKIMEDIA_SERVER}/backup-index-bydb.html");
@@ -103,21 +312,23 @@ pub async fn get_dumps(
         dumps.push(DumpName(dump_string));
     }
 
-    tracing::debug!(dumps_count = dumps.len(),
-                    "dumps ret count");
-
-    dumps.sort();
-
-    if tracing::enabled!(Level::TRACE) {
-        tracing::trace!(dumps = dumps.as_value(),
-                       "dumps ret data");
-    }
-
     Ok(dumps)
 }
 
+/// Get the versions of a dump available on the dumps server, sorted oldest first.
+///
+/// The server has no JSON listing of versions, so this scrapes the dump's HTML directory
+/// listing.
 #[tracing::instrument(level = "trace", skip(client))]
 pub async fn get_dump_versions(
+    client: &MetadataClient,
+    dump_name: &DumpName,
+) -> Result<Vec<Version>> {
+    client.cache.get_or_fetch(&format!("{dump_name}/versions", dump_name = dump_name.0),
+                              || fetch_dump_versions(&client.http, dump_name)).await
+}
+
+async fn fetch_dump_versions(
     client: &http::Client,
     dump_name: &DumpName,
 ) -> Result<Vec<Version>> {
@@ -166,7 +377,7 @@ pub async fn get_dump_versions(
 
 #[tracing::instrument(level = "trace", skip(client), ret)]
 pub async fn get_dump_version_status(
-    client: &http::Client,
+    client: &MetadataClient,
     dump_name: &DumpName,
     version_spec: &VersionSpec,
 ) -> Result<(Version, DumpVersionStatus)> {
@@ -176,14 +387,26 @@ pub async fn get_dump_version_status(
         VersionSpec::Latest => {
             let vers = get_dump_versions(&client, dump_name).await?;
             if vers.is_empty() {
-                bail!("No versions found for dump {dump_name}",
-                      dump_name = dump_name.0);
+                bail_kind!(User, "No versions found for dump {dump_name}",
+                           dump_name = dump_name.0);
             }
             let ver = vers.last().expect("vers not empty");
             ver.clone()
         },
     };
 
+    let status = client.cache.get_or_fetch(
+        &format!("{dump_name}/{ver}/dumpstatus", dump_name = dump_name.0, ver = ver.0),
+        || fetch_dump_version_status(&client.http, dump_name, &ver)).await?;
+
+    Ok((ver.clone(), status))
+}
+
+async fn fetch_dump_version_status(
+    client: &http::Client,
+    dump_name: &DumpName,
+    ver: &Version,
+) -> Result<DumpVersionStatus> {
     let url = format!("{DUMPS_WIKIMEDIA_SERVER}/{dump_name}/{ver}/dumpstatus.json",
                       dump_name = dump_name.0,
                       ver = ver.0);
@@ -195,37 +418,44 @@ pub async fn get_dump_version_status(
     let status: DumpVersionStatus = serde_json::from_str(&*fetch_res.response_body)
         .with_context(|| format!("Getting dump version status url={url}"))?;
 
-    Ok((ver.clone(), status))
+    Ok(status)
 }
 
+/// Get the status of a job in a dump version.
+///
+/// Returns an error if the job is not done, unless `allow_incomplete_job` is true. Even when a
+/// job is not done, some of its files may be, see [`FileMetadata::status`].
 #[tracing::instrument(level = "trace", skip(client))]
 pub async fn get_job_status(
-    client: &http::Client,
+    client: &MetadataClient,
     dump_name: &DumpName,
     version_spec: &VersionSpec,
     job_name: &JobName,
+    allow_incomplete_job: bool,
 ) -> Result<(Version, JobStatus)> {
     let (ver, ver_status) = get_dump_version_status(client, dump_name, version_spec).await?;
 
     let Some(job_status) = ver_status.jobs.get(&job_name.0) else {
-        bail!("No status found for job dump_name={dump_name} \
-               version={ver} job_name={job_name}",
-              dump_name = dump_name.0,
-              ver = ver.0,
-              job_name = job_name.0);
+        bail_kind!(User,
+                   "No status found for job dump_name={dump_name} \
+                    version={ver} job_name={job_name}",
+                   dump_name = dump_name.0,
+                   ver = ver.0,
+                   job_name = job_name.0);
     };
 
     if tracing::enabled!(Level::TRACE) {
         tracing::trace!(job_status = format!("{:#?}", job_status), "Job status");
     }
 
-    if job_status.status != "done" {
-        return Err(format_err!("Job status is not 'done' status={status} dump={dump_name} \
-                                version={ver} job={job_name}",
-                               status = job_status.status,
-                               dump_name = dump_name.0,
-                               ver = ver.0,
-                               job_name = job_name.0));
+    if job_status.status != Status::Done && !allow_incomplete_job {
+        bail_kind!(User,
+                   "Job status is not 'done' status={status} dump={dump_name} \
+                    version={ver} job={job_name}",
+                   status = job_status.status,
+                   dump_name = dump_name.0,
+                   ver = ver.0,
+                   job_name = job_name.0);
     }
 
     Ok((ver, job_status.clone()))
@@ -233,14 +463,17 @@ pub async fn get_job_status(
 
 #[tracing::instrument(level = "trace", skip(client), ret)]
 pub async fn get_file_infos(
-    client: &http::Client,
+    client: &MetadataClient,
     dump_name: &DumpName,
     version_spec: &VersionSpec,
     job_name: &JobName,
     file_name_regex: Option<&UserRegex>,
-) -> Result<(Version, Vec<(String, FileMetadata)>)> {
+    allow_incomplete_job: bool,
+) -> Result<FileInfos> {
     let (ver, job_status) = get_job_status(&client, dump_name,
-                                           version_spec, job_name).await?;
+                                           version_spec, job_name,
+                                           allow_incomplete_job).await?;
+    let status = job_status.status;
 
     let mut files: Vec<(String, FileMetadata)> = match file_name_regex {
         None => job_status.files.into_iter().collect(),
@@ -253,10 +486,16 @@ pub async fn get_file_infos(
     };
     files.sort_by(|(a, _), (b, _)| natord::compare(&*a, &*b));
 
-    Ok((ver, files))
+    Ok(FileInfos {
+        version: ver,
+        job_status: status,
+        files,
+    })
 }
 
 
+/// Download a job's files. To download several jobs reusing HTTP connections, use a
+/// [`Downloader`].
 #[tracing::instrument(level = "trace", ret)]
 pub async fn download_job(
     dump_name: &DumpName,
@@ -265,110 +504,254 @@ pub async fn download_job(
     file_name_regex: Option<&UserRegex>,
     options: &Options,
 ) -> Result<DownloadJobResult> {
-    let start_time = Instant::now();
-
-    let metadata_client = http::metadata_client(&options.http_options)?;
-
-    let (version, files) = get_file_infos(
-        &metadata_client,
-        dump_name,
-        version_spec,
-        job_name,
-        file_name_regex).await?;
-
-    let out_dir = &*options.out_dir;
-
-    let job_path = local::job_path(out_dir, dump_name, &version, job_name);
-    let expected_bytes = files.iter().map(|(_, file_meta)| file_meta.size.unwrap_or(0))
-                                     .sum();
-
-    tracing::info!(dump = &*dump_name.0,
-                   version = &*version.0,
-                   job = &*job_name.0,
-
-                   download_dir = %job_path.display(),
-                   expected_bytes = Bytes(expected_bytes).as_value(),
-                   "Starting to download job files");
-
-    let temp_dir = TempDir::create(out_dir, options.keep_temp_dir)?;
-    let download_client = http::download_client(&options.http_options)?;
-
-    let mut download_ok: u64 = 0;
-    let mut download_len: u64 = 0;
-    let mut existing_ok: u64 = 0;
-    let mut existing_len: u64 = 0;
-
-    for (_file_name, file_meta) in files.iter() {
-        let file_res =
-            download_job_file(&download_client, dump_name, &version,
-                                              job_name, &*options.dump_mirror_url, file_meta,
-                                              out_dir, &temp_dir).await
-                .with_context(|| format!(
-                    "while downloading job file \
-                     dump='{dump}' \
-                     version='{version}' \
-                     job='{job}' \
-                     file='{file_rel_url:?}'",
-                    dump = dump_name.0,
-                    version = version.0,
-                    job = job_name.0,
-                    file_rel_url = &file_meta.url))?;
-        match file_res.kind {
-            DownloadJobFileResultKind::DownloadOk => {
-                download_ok += 1;
-                download_len += file_res.stats.len.0;
-
-                // Delay between requests to avoid being rate limited.
-                std::thread::sleep(StdDuration::from_secs(3));
-            },
-            DownloadJobFileResultKind::ExistingOk => {
-                existing_ok += 1;
-                existing_len += file_res.stats.len.0;
-            },
-        };
+    Downloader::new(options.clone())?
+        .download_job(dump_name, version_spec, job_name, file_name_regex).await
+}
+
+/// Download a job's files like [`download_job`], calling `on_file` with each file's path as
+/// soon as it has been downloaded and verified, or an existing copy was found to be OK.
+///
+/// Files are downloaded one at a time in name order. An error returned by `on_file` stops
+/// the download and is returned.
+#[tracing::instrument(level = "trace", ret, skip(on_file))]
+pub async fn download_job_with(
+    dump_name: &DumpName,
+    version_spec: &VersionSpec,
+    job_name: &JobName,
+    file_name_regex: Option<&UserRegex>,
+    options: &Options,
+    on_file: impl FnMut(&Path) -> Result<()>,
+) -> Result<DownloadJobResult> {
+    Downloader::new(options.clone())?
+        .download_job_with(dump_name, version_spec, job_name, file_name_regex, on_file).await
+}
+
+impl Downloader {
+    pub fn new(options: Options) -> Result<Downloader> {
+        Ok(Downloader {
+            metadata_client: MetadataClient::new(&options.http_options,
+                                                 options.metadata_cache_options.clone())?,
+            download_client: http::download_client(&options.http_options)?,
+            last_download: Mutex::new(None),
+            options,
+        })
     }
 
-    drop(temp_dir);
+    /// The client used to fetch dump metadata, e.g. to check for a new version before
+    /// downloading it.
+    pub fn metadata_client(&self) -> &MetadataClient {
+        &self.metadata_client
+    }
+
+    /// Get the files in a job from the downloader's source, e.g. to check whether a new
+    /// version is done before downloading it. See [`DumpSource::get_file_infos`].
+    pub async fn get_file_infos(
+        &self,
+        dump_name: &DumpName,
+        version_spec: &VersionSpec,
+        job_name: &JobName,
+        file_name_regex: Option<&UserRegex>,
+        allow_incomplete_job: bool,
+    ) -> Result<FileInfos> {
+        self.options.source.get_file_infos(&self.metadata_client, dump_name, version_spec,
+                                           job_name, file_name_regex,
+                                           allow_incomplete_job).await
+    }
 
-    let duration = start_time.elapsed();
+    /// Download a job's files. See [`download_job`].
+    pub async fn download_job(
+        &self,
+        dump_name: &DumpName,
+        version_spec: &VersionSpec,
+        job_name: &JobName,
+        file_name_regex: Option<&UserRegex>,
+    ) -> Result<DownloadJobResult> {
+        self.download_job_with(dump_name, version_spec, job_name, file_name_regex,
+                               |_path| Ok(())).await
+    }
 
-    let job_res = DownloadJobResult {
-        download_ok,
-        download_stats: TransferStats::new(Bytes(download_len), duration),
+    /// Download a job's files, calling `on_file` with each file's path. See
+    /// [`download_job_with`].
+    #[tracing::instrument(level = "trace", ret, skip(self, on_file))]
+    pub async fn download_job_with(
+        &self,
+        dump_name: &DumpName,
+        version_spec: &VersionSpec,
+        job_name: &JobName,
+        file_name_regex: Option<&UserRegex>,
+        mut on_file: impl FnMut(&Path) -> Result<()>,
+    ) -> Result<DownloadJobResult> {
+        let start_time = Instant::now();
+        let options = &self.options;
+
+        let FileInfos { version, job_status, mut files } = options.source.get_file_infos(
+            &self.metadata_client,
+            dump_name,
+            version_spec,
+            job_name,
+            file_name_regex,
+            options.allow_incomplete_job).await?;
+
+        if job_status != Status::Done {
+            let files_len = files.len();
+            files.retain(|(_name, file_meta)| file_meta.status(job_status) == Status::Done);
+            tracing::warn!(%job_status,
+                           files_done = files.len(),
+                           files_skipped = files_len - files.len(),
+                           "Job is not done, only downloading files that are done");
+        }
 
-        existing_ok,
-        existing_stats: TransferStats::new(Bytes(existing_len), duration),
+        let out_dir = &*options.out_dir;
 
-        duration: util::fmt::Duration(duration),
-    };
+        let job_path = local::job_path(out_dir, dump_name, &version, job_name);
+        let expected_bytes = files.iter().map(|(_, file_meta)| file_meta.size.unwrap_or(0))
+                                         .sum();
+
+        tracing::info!(dump = &*dump_name.0,
+                       version = &*version.0,
+                       job = &*job_name.0,
+
+                       download_dir = %job_path.display(),
+                       expected_bytes = Bytes(expected_bytes).as_value(),
+                       "Starting to download job files");
+
+        let send_event = |event: Event| if let Some(events) = options.events.as_ref() {
+            events.send(event);
+        };
+        send_event(Event::DownloadStarted {
+            dump: dump_name.0.clone(),
+            version: version.0.clone(),
+            job: job_name.0.clone(),
+            files_len: files.len(),
+            expected_bytes,
+        });
+
+        let temp_dir = TempDir::create(out_dir, options.keep_temp_dir)?;
+
+        let mut download_ok: u64 = 0;
+        let mut download_len: u64 = 0;
+        let mut existing_ok: u64 = 0;
+        let mut existing_len: u64 = 0;
+        let mut shared_ok: u64 = 0;
+        let mut shared_len: u64 = 0;
+
+        for (_file_name, file_meta) in files.iter() {
+            self.wait_for_download_interval().await?;
+            cancel::check(&options.cancellation, "Download")?;
+            let file_res = tokio::select! {
+                res = download_job_file(&self.download_client, dump_name, &version,
+                                        job_name, &*options.source, file_meta,
+                                        out_dir, &options.shared_dirs,
+                                        options.link_mode, &temp_dir) => res,
+                () = options.cancellation.cancelled() => {
+                    // Dropping `temp_dir` on return removes the partly downloaded file.
+                    bail_kind!(Cancelled,
+                               "Download cancelled while downloading file={file_rel_url:?}",
+                               file_rel_url = &file_meta.url);
+                },
+            }
+                    .with_context(|| format!(
+                        "while downloading job file \
+                         dump='{dump}' \
+                         version='{version}' \
+                         job='{job}' \
+                         file='{file_rel_url:?}'",
+                        dump = dump_name.0,
+                        version = version.0,
+                        job = job_name.0,
+                        file_rel_url = file_meta.url))?;
+            match file_res.kind {
+                DownloadJobFileResultKind::DownloadOk => {
+                    download_ok += 1;
+                    download_len += file_res.stats.len.0;
+                    *self.lock_last_download()? = Some(Instant::now());
+                },
+                DownloadJobFileResultKind::ExistingOk => {
+                    existing_ok += 1;
+                    existing_len += file_res.stats.len.0;
+                },
+                DownloadJobFileResultKind::SharedOk => {
+                    shared_ok += 1;
+                    shared_len += file_res.stats.len.0;
+                },
+            };
+
+            send_event(Event::FileVerified {
+                path: file_res.path.display().to_string(),
+                bytes: file_res.stats.len.0,
+                downloaded: matches!(file_res.kind, DownloadJobFileResultKind::DownloadOk),
+            });
+            on_file(&file_res.path)?;
+        }
 
-    tracing::info!(download_ok,
-                   download_stats = job_res.download_stats.as_value(),
+        drop(temp_dir);
 
-                   existing_ok,
-                   existing_stats = job_res.existing_stats.as_value(),
+        let duration = start_time.elapsed();
 
-                   duration = job_res.duration.as_value(),
+        let job_res = DownloadJobResult {
+            download_ok,
+            download_stats: TransferStats::new(Bytes(download_len), duration),
 
-                   dump = &*dump_name.0,
-                   version = &*version.0,
-                   job = &*job_name.0,
+            existing_ok,
+            existing_stats: TransferStats::new(Bytes(existing_len), duration),
 
-                   download_dir = %job_path.display(),
+            shared_ok,
+            shared_stats: TransferStats::new(Bytes(shared_len), duration),
 
-                   "Downloading job files complete");
+            duration: util::fmt::Duration(duration),
+        };
+
+        tracing::info!(download_ok,
+                       download_stats = job_res.download_stats.as_value(),
+
+                       existing_ok,
+                       existing_stats = job_res.existing_stats.as_value(),
+
+                       shared_ok,
+                       shared_stats = job_res.shared_stats.as_value(),
+
+                       duration = job_res.duration.as_value(),
+
+                       dump = &*dump_name.0,
+                       version = &*version.0,
+                       job = &*job_name.0,
+
+                       download_dir = %job_path.display(),
 
-    eprintln!("Downloading job files complete\n\
-               |   download_dir = {download_dir}\n\
-               |   dump         = {dump}\n\
-               |   version      = {version}\n\
-               |   job          = {job}\n",
-              download_dir = job_path.display(),
-              dump = &*dump_name.0,
-              version = &*version.0,
-              job = &*job_name.0);
+                       "Downloading job files complete");
 
-    Ok(job_res)
+        eprintln!("Downloading job files complete\n\
+                   |   download_dir = {download_dir}\n\
+                   |   dump         = {dump}\n\
+                   |   version      = {version}\n\
+                   |   job          = {job}\n",
+                  download_dir = job_path.display(),
+                  dump = &*dump_name.0,
+                  version = &*version.0,
+                  job = &*job_name.0);
+
+        Ok(job_res)
+    }
+
+    /// Wait until [`DOWNLOAD_INTERVAL`] after the last file download finished, to avoid
+    /// being rate limited.
+    async fn wait_for_download_interval(&self) -> Result<()> {
+        let wait = self.lock_last_download()?
+                       .map(|last| DOWNLOAD_INTERVAL.saturating_sub(last.elapsed()))
+                       .unwrap_or_default();
+        if !wait.is_zero() {
+            tokio::select! {
+                () = tokio::time::sleep(wait) => (),
+                () = self.options.cancellation.cancelled() => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn lock_last_download(&self) -> Result<MutexGuard<'_, Option<Instant>>> {
+        self.last_download.lock()
+            .map_err(|_err| format_err!("PoisonError unlocking Mutex in Downloader"))
+    }
 }
 
 #[tracing::instrument(level = "trace", ret, skip(client))]
@@ -377,33 +760,54 @@ async fn download_job_file(
     dump_name: &DumpName,
     ver: &Version,
     job_name: &JobName,
-    dump_mirror_url: &str,
+    source: &dyn DumpSource,
     file_meta: &FileMetadata,
     out_dir: &Path,
+    shared_dirs: &[PathBuf],
+    link_mode: LinkMode,
     temp_dir: &TempDir,
 ) -> Result<DownloadJobFileResult> {
 
     let start = Instant::now();
 
-    let rel_url = file_meta.url.as_ref().map(|s| s.as_str()).ok_or_else(|| format_err!(
-        "File URL missing. Usually this is because the parent job has status 'waiting'."))?;
-    let expected_len = file_meta.size.ok_or_else(|| format_err!(
-        "File size missing. Usually this is because the parent job has status 'waiting'."))?;
-    let expected_len = Bytes(expected_len);
-
-    validate_file_relative_url(rel_url)?;
-
-    let url = format!("{dump_mirror_url}{rel_url}");
+    let url = source.file_url(file_meta)?;
 
     let file_out_path = dump::local::job_file_path(out_dir, dump_name, ver, job_name, file_meta)?;
     let file_name = file_out_path.file_name().expect("non-empty file name");
 
-    match check_existing_file(&*file_out_path, file_meta, &*url).await? {
-        ExistingFileStatus::FileOk | ExistingFileStatus::NoSha1HashToCheck
-            => return Ok(DownloadJobFileResult {
-                             kind: DownloadJobFileResultKind::ExistingOk,
-                             stats: TransferStats::new(expected_len, start.elapsed()),
-                         }),
+    // Check the server's copy before hashing an existing file or downloading a new one.
+    let head = http::fetch_head(client, &url).await?;
+    if head.is_none() {
+        tracing::warn!(url, "Server doesn't support HEAD requests, \
+                             so the file can't be checked before downloading it");
+    }
+    let head_len = head.as_ref().and_then(|head| head.content_len);
+
+    // The size is unknown for sources without job metadata, so use the server's if given.
+    let expected_len = match (file_meta.size.map(Bytes), head_len) {
+        (Some(meta_len), Some(head_len)) if meta_len != head_len =>
+            bail_kind!(Verification,
+                       "The server's copy of the job file is a different size to the dump \
+                        status metadata, the mirror may not have finished syncing this version. \
+                        url='{url}' \
+                        metadata_len={meta_len:?} \
+                        server_len={head_len:?}"),
+        (meta_len, head_len) => meta_len.or(head_len),
+    };
+
+    match check_existing_file(&*file_out_path, file_meta, expected_len, head.as_ref(), &*url)
+              .await? {
+        ExistingFileStatus::FileOk | ExistingFileStatus::NoSha1HashToCheck => {
+            let existing_len = match expected_len {
+                Some(len) => len,
+                None => Bytes(file_out_path.metadata()?.len()),
+            };
+            return Ok(DownloadJobFileResult {
+                kind: DownloadJobFileResultKind::ExistingOk,
+                stats: TransferStats::new(existing_len, start.elapsed()),
+                path: file_out_path,
+            });
+        },
         _ => (),
     };
 
@@ -412,23 +816,55 @@ async fn download_job_file(
 
     std::fs::create_dir_all(&*file_out_dir_path)?;
 
+    for shared_dir in shared_dirs.iter() {
+        let shared_path = dump::local::job_file_path(shared_dir, dump_name, ver, job_name,
+                                                     file_meta)?;
+        if !shared_copy_ok(&shared_path, file_meta, expected_len, &url).await? {
+            continue;
+        }
+
+        link_or_copy(&shared_path, &temp_file_path, link_mode)?;
+        std::fs::rename(&*temp_file_path, &*file_out_path)
+            .with_context(|| format!("While moving a shared job file from its temporary \
+                                      directory to its target directory \
+                                      temp_path='{temp_file_path}' \
+                                      target_path='{file_out_path}'",
+                                     temp_file_path = temp_file_path.display(),
+                                     file_out_path = file_out_path.display()))?;
+        VerifiedRecord::new(&file_out_path, file_meta, head.as_ref())?.write(&file_out_path)?;
+
+        let len = Bytes(file_out_path.metadata()?.len());
+        tracing::info!(url,
+                       shared_path = %shared_path.display(),
+                       out_path = %file_out_path.display(),
+                       ?link_mode,
+                       "download_job_file used a shared copy, file OK");
+
+        return Ok(DownloadJobFileResult {
+            kind: DownloadJobFileResultKind::SharedOk,
+            stats: TransferStats::new(len, start.elapsed()),
+            path: file_out_path,
+        });
+    }
+
     tracing::info!(
         url,
         out_path = %file_out_path.display(),
-        expected_len = expected_len.as_value(),
+        expected_len = expected_len.as_ref().map(|len| len.as_value()),
         "download_job_file starting download");
 
     let download_request = client.get(url.clone())
                                  .build()?;
     let download_result = http::download_file(&client, download_request, &*temp_file_path,
-                                              Some(expected_len)).await?;
-
-    if download_result.stats.len != expected_len {
-        bail!("Download job file was the wrong size \
-               url='{url}' \
-               expected_len={expected_len:?} \
-               file_len={file_len:?}",
-              file_len = download_result.stats.len);
+                                              expected_len).await?;
+
+    if expected_len.is_some_and(|expected_len| download_result.stats.len != expected_len) {
+        bail_kind!(Verification,
+                   "Download job file was the wrong size \
+                    url='{url}' \
+                    expected_len={expected_len:?} \
+                    file_len={file_len:?}",
+                   file_len = download_result.stats.len);
     }
 
     match file_meta.sha1.as_ref() {
@@ -437,8 +873,9 @@ async fn download_job_file(
             let expected_sha1 = expected_sha1.to_lowercase();
             let computed_sha1 = download_result.sha1.to_string();
             if computed_sha1 != expected_sha1 {
-                bail!("Bad SHA1 hash for downloaded job file url='{url}' \
-                       expected_sha1={expected_sha1}, computed_sha1={computed_sha1}");
+                bail_kind!(Verification,
+                           "Bad SHA1 hash for downloaded job file url='{url}' \
+                            expected_sha1={expected_sha1}, computed_sha1={computed_sha1}");
             }
 
             tracing::debug!(sha1 = expected_sha1,
@@ -459,6 +896,8 @@ async fn download_job_file(
                     file_out_path = %file_out_path.display(),
                     "Moved downloaded file from temp directory to output directory");
 
+    VerifiedRecord::new(&file_out_path, file_meta, head.as_ref())?.write(&file_out_path)?;
+
     tracing::info!(url,
                    out_path = %file_out_path.display(),
                    stats = download_result.stats.as_value(),
@@ -467,10 +906,11 @@ async fn download_job_file(
     Ok(DownloadJobFileResult {
         kind: DownloadJobFileResultKind::DownloadOk,
         stats: download_result.stats,
+        path: file_out_path,
     })
 }
 
-fn validate_file_relative_url(url: &str) -> Result<()> {
+pub(crate) fn validate_file_relative_url(url: &str) -> Result<()> {
     // Wrap everyting in a closure to add context with anyhow.
     (|| -> Result<()> {
         if url == "" {
@@ -509,13 +949,13 @@ fn validate_file_relative_url(url: &str) -> Result<()> {
 async fn check_existing_file(
     path: &Path,
     file_meta: &FileMetadata,
+    expected_len: Option<Bytes>,
+    head: Option<&http::HeadResult>,
     url: &str,
 ) -> Result<ExistingFileStatus> {
     // Wrapped in a closure to add context on errors.
     (async || -> Result<ExistingFileStatus> {
 
-        let expected_len = Bytes(file_meta.size.ok_or(format_err!("file_meta missing size"))?);
-
         // Look for an existing file at the output path.
         let existing_meta = match path.metadata() {
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
@@ -544,32 +984,70 @@ async fn check_existing_file(
 
         // Check existing file length
         let existing_len = Bytes(existing_meta.len());
-        if expected_len != existing_len {
-            // Existing file length does not match expected.
-            tracing::warn!(path = %path.display(),
-                           existing_len = existing_len.as_value(),
-                           expected_len = expected_len.as_value(),
-                           url,
-                           "Deleting existing file that was the wrong size");
+        match expected_len {
+            Some(expected_len) if expected_len != existing_len => {
+                // Existing file length does not match expected.
+                tracing::warn!(path = %path.display(),
+                               existing_len = existing_len.as_value(),
+                               expected_len = expected_len.as_value(),
+                               url,
+                               "Deleting existing file that was the wrong size");
 
-            std::fs::remove_file(&*path)
-                .with_context(
-                    || format!("while deleting existing file that was the wrong size \
-                                existing_len={existing_len:?} \
-                                expected_len={expected_len:?}"))?;
+                std::fs::remove_file(&*path)
+                    .with_context(
+                        || format!("while deleting existing file that was the wrong size \
+                                    existing_len={existing_len:?} \
+                                    expected_len={expected_len:?}"))?;
+
+                return Ok(ExistingFileStatus::DeletedBecauseIncorrectSize);
+            },
+            _ => (),
+        }
+
+        // Check the record from when the file was last verified, if it's still for this file.
+        let modified = DateTime::<Utc>::from(existing_meta.modified()?);
+        let expected_sha1 = file_meta.sha1.as_ref().map(|sha1| sha1.to_lowercase());
+        let record = VerifiedRecord::read(path)?
+                         .filter(|record| record.len == existing_len.0
+                                          && record.modified == modified);
+        match record {
+            Some(record) if record.changed_on_server(head) => {
+                if expected_sha1.is_none() {
+                    // Nothing else to check the file against, so download the server's copy.
+                    tracing::warn!(file_path = %path.display(),
+                                   ?record,
+                                   ?head,
+                                   url,
+                                   "Deleting existing file that has changed on the server");
+                    std::fs::remove_file(&*path)
+                        .with_context(
+                            || "while deleting existing file that has changed on the server")?;
+                    return Ok(ExistingFileStatus::DeletedBecauseChangedOnServer);
+                }
 
-            return Ok(ExistingFileStatus::DeletedBecauseIncorrectSize);
+                tracing::warn!(file_path = %path.display(),
+                               url,
+                               "Existing file has changed on the server, \
+                                checking its SHA1 hash");
+            },
+            Some(record) if record.sha1 == expected_sha1 => {
+                tracing::info!(file_path = %path.display(),
+                               url,
+                               "Existing file OK: verified previously and unchanged since.");
+                return Ok(ExistingFileStatus::FileOk);
+            },
+            _ => (),
         }
 
         // Check existing file SHA1 hash
-        let expected_sha1 = match file_meta.sha1.as_ref() {
+        let expected_sha1 = match expected_sha1 {
             // No SHA1 hash in metadata, warn and return OK assuming the download
             // succeeded.
             None => {
                 tracing::warn!(file_path = %path.display(),
                                ?file_meta,
                                url,
-                               "Existing file is the right size, but there's no SHA1 \
+                               "Existing file found, but there's no SHA1 \
                                 hash to check in the dump status file metadata.");
                 return Ok(ExistingFileStatus::NoSha1HashToCheck);
             },
@@ -578,8 +1056,6 @@ async fn check_existing_file(
         };
 
         // SHA1 hash in metadata, check it matches the existing file's hash.
-        let expected_sha1 = expected_sha1.to_lowercase();
-
         let existing_sha1 = calculate_file_sha1(&*path).await?;
 
         if expected_sha1 == existing_sha1.to_string() {
@@ -588,6 +1064,7 @@ async fn check_existing_file(
                            url,
                            "Existing file OK: SHA1 hash and file size are \
                             correct.");
+            VerifiedRecord::new(path, file_meta, head)?.write(path)?;
             return Ok(ExistingFileStatus::FileOk);
         } else {
             // Existing file's SHA1 hash was incorrect, delete it.
@@ -616,6 +1093,155 @@ async fn check_existing_file(
         path = path.display()))
 }
 
+/// Whether the job file at `path` in a shared directory is there and matches the size and
+/// SHA1 hash in its metadata. Problems with the shared copy are logged and return false, so
+/// the file is downloaded instead.
+async fn shared_copy_ok(
+    path: &Path,
+    file_meta: &FileMetadata,
+    expected_len: Option<Bytes>,
+    url: &str,
+) -> Result<bool> {
+    let meta = match path.metadata() {
+        Ok(meta) if meta.is_file() => meta,
+        Ok(_) => return Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), err = %e, url,
+                           "Ignoring shared job file that couldn't be read");
+            return Ok(false);
+        },
+    };
+
+    let Some(expected_sha1) = file_meta.sha1.as_ref().map(|sha1| sha1.to_lowercase()) else {
+        tracing::warn!(path = %path.display(), url,
+                       "Not using shared job file: there's no SHA1 hash to check it against");
+        return Ok(false);
+    };
+    let len = Bytes(meta.len());
+    if expected_len.is_some_and(|expected_len| expected_len != len) {
+        tracing::warn!(path = %path.display(), len = len.as_value(), url,
+                       "Not using shared job file that is the wrong size");
+        return Ok(false);
+    }
+
+    // Trust the shared directory's own record if it's still for this file.
+    let modified = DateTime::<Utc>::from(meta.modified()?);
+    let recorded = VerifiedRecord::read(path)?
+        .is_some_and(|record| record.len == len.0 && record.modified == modified
+                              && record.sha1.as_ref() == Some(&expected_sha1));
+    if recorded {
+        return Ok(true);
+    }
+
+    let sha1 = calculate_file_sha1(path).await?.to_string();
+    if sha1 != expected_sha1 {
+        tracing::warn!(path = %path.display(), sha1, expected_sha1, url,
+                       "Not using shared job file with the wrong SHA1 hash");
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// Put a copy of the file at `from` at the new path `to`, as chosen by `link_mode`.
+fn link_or_copy(from: &Path, to: &Path, link_mode: LinkMode) -> Result<()> {
+    // Closure to add context to errors.
+    (|| -> Result<()> {
+        match link_mode {
+            LinkMode::HardLink => {
+                if let Err(e) = fs::hard_link(from, to) {
+                    tracing::debug!(err = %e, "Hard link failed, copying instead");
+                    fs::copy(from, to)?;
+                }
+            },
+            LinkMode::Reflink => {
+                reflink_copy::reflink_or_copy(from, to)?;
+            },
+            LinkMode::Copy => {
+                fs::copy(from, to)?;
+            },
+        }
+        Ok(())
+    })().with_context(|| format!("linking or copying a shared job file \
+                                  from='{from}' to='{to}' link_mode={link_mode:?}",
+                                 from = from.display(),
+                                 to = to.display()))
+}
+
+impl VerifiedRecord {
+    /// A record for the file at `path`, just verified against `file_meta` and the server's
+    /// headers `head`.
+    fn new(
+        path: &Path,
+        file_meta: &FileMetadata,
+        head: Option<&http::HeadResult>,
+    ) -> Result<VerifiedRecord> {
+        let meta = path.metadata()?;
+        Ok(VerifiedRecord {
+            len: meta.len(),
+            modified: meta.modified()?.into(),
+            sha1: file_meta.sha1.as_ref().map(|sha1| sha1.to_lowercase()),
+            etag: head.and_then(|head| head.etag.clone()),
+            last_modified: head.and_then(|head| head.last_modified.clone()),
+        })
+    }
+
+    /// The path of the record for the job file at `file_path`, in a hidden directory so it
+    /// isn't mistaken for a job file.
+    fn path(file_path: &Path) -> PathBuf {
+        let file_name = file_path.file_name().expect("non-empty file name");
+        let mut record_name = file_name.to_owned();
+        record_name.push(".json");
+        file_path.with_file_name(".verified").join(record_name)
+    }
+
+    fn read(file_path: &Path) -> Result<Option<VerifiedRecord>> {
+        let path = VerifiedRecord::path(file_path);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(
+                || format!("reading verified record path='{}'", path.display())),
+        };
+
+        match serde_json::from_slice(&bytes) {
+            Ok(record) => Ok(Some(record)),
+            Err(e) => {
+                // Hash the file again instead.
+                tracing::warn!(err = %e, path = %path.display(),
+                               "Ignoring verified record that couldn't be parsed");
+                Ok(None)
+            },
+        }
+    }
+
+    fn write(&self, file_path: &Path) -> Result<()> {
+        let path = VerifiedRecord::path(file_path);
+        let temp_path = path.with_extension("json.tmp");
+
+        // Closure to add context to errors.
+        (|| {
+            fs::create_dir_all(path.parent().expect("parent of verified record path"))?;
+            fs::write(&temp_path, serde_json::to_vec_pretty(self)?)?;
+            fs::rename(&temp_path, &path)?;
+            anyhow::Ok(())
+        })().with_context(|| format!("writing verified record path='{}'", path.display()))
+    }
+
+    /// Whether the server's `ETag` or `Last-Modified` header differs from when the file was
+    /// verified. Headers missing from either are not compared.
+    fn changed_on_server(&self, head: Option<&http::HeadResult>) -> bool {
+        let Some(head) = head else {
+            return false;
+        };
+        let differs = |recorded: &Option<String>, current: &Option<String>| {
+            matches!((recorded, current), (Some(recorded), Some(current)) if recorded != current)
+        };
+        differs(&self.etag, &head.etag) || differs(&self.last_modified, &head.last_modified)
+    }
+}
+
 /// Calculate SHA1 hash for data in a file, formatted as a lower-case hex string.
 async fn calculate_file_sha1(
     path: &Path,
@@ -641,7 +1267,64 @@ async fn calculate_file_sha1(
 
 #[cfg(test)]
 mod tests {
-    use super::validate_file_relative_url;
+    use crate::{dump::FileMetadata, http};
+    use std::path::Path;
+    use super::{link_or_copy, LinkMode, shared_copy_ok, validate_file_relative_url,
+                VerifiedRecord};
+
+    #[test]
+    fn verified_record_changed_on_server() {
+        let record = VerifiedRecord {
+            len: 10,
+            modified: chrono::Utc::now(),
+            sha1: None,
+            etag: Some(r#""abc""#.to_string()),
+            last_modified: None,
+        };
+        let head = |etag: Option<&str>, last_modified: Option<&str>| http::HeadResult {
+            response_code: http::StatusCode(reqwest::StatusCode::OK),
+            content_len: None,
+            etag: etag.map(|s| s.to_string()),
+            last_modified: last_modified.map(|s| s.to_string()),
+        };
+
+        assert!(!record.changed_on_server(None));
+        assert!(!record.changed_on_server(Some(&head(Some(r#""abc""#), None))));
+        assert!(!record.changed_on_server(Some(&head(None, Some("Wed, 01 Mar 2023 00:00:00 GMT")))));
+        assert!(record.changed_on_server(Some(&head(Some(r#""def""#), None))));
+
+        assert_eq!(VerifiedRecord::path(Path::new("out/enwiki/20230301/job/file.xml.bz2")),
+                   Path::new("out/enwiki/20230301/job/.verified/file.xml.bz2.json"));
+    }
+
+    #[tokio::test]
+    async fn shared_copy_checked_then_linked() {
+        let temp_dir = crate::TempDir::create(&std::env::temp_dir(), false /* keep */).unwrap();
+        let dir = temp_dir.path().unwrap();
+        let shared_path = dir.join("shared.xml");
+        std::fs::write(&shared_path, "<mediawiki/>").unwrap();
+
+        let meta = |sha1: Option<&str>| FileMetadata {
+            size: Some(12),
+            url: None,
+            sha1: sha1.map(|s| s.to_string()),
+            md5: None,
+        };
+        let sha1 = "de89ad78d41f632ac80e91d277443a96e027a3cf";
+        let len = Some(crate::util::fmt::Bytes(12));
+
+        assert!(!shared_copy_ok(&shared_path, &meta(None), len, "url").await.unwrap());
+        assert!(!shared_copy_ok(&shared_path, &meta(Some(&"0".repeat(40))), len, "url")
+                     .await.unwrap());
+        assert!(shared_copy_ok(&shared_path, &meta(Some(sha1)), len, "url").await.unwrap());
+
+        for (link_mode, name) in [(LinkMode::HardLink, "hard"), (LinkMode::Reflink, "reflink"),
+                                  (LinkMode::Copy, "copy")] {
+            let to = dir.join(name);
+            link_or_copy(&dir.join("shared.xml"), &to, link_mode).unwrap();
+            assert_eq!(std::fs::read_to_string(&to).unwrap(), "<mediawiki/>");
+        }
+    }
 
     #[test]
     fn test_validate_file_relative_url() {
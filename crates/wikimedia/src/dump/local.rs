@@ -1,13 +1,12 @@
 //! Read local copies of Wikimedia dump files.
 
-use anyhow::format_err;
+use anyhow::{bail, format_err};
 use chrono::{DateTime, FixedOffset};
 use clap::{
     builder::PossibleValue,
     ValueEnum,
 };
 use crate::{
-    dump::types::*,
     Error,
     ProgressReader,
     Result,
@@ -33,17 +32,117 @@ use std::{
     result::Result as StdResult,
     sync::{
         Arc,
-        atomic::AtomicU64,
+        atomic::{AtomicU64, Ordering},
     },
     str::FromStr,
 };
 use tracing::Level;
 use valuable::Valuable;
+use wikimedia_types::dump::*;
 
+/// Reads pages from a dump file or from XML exported with MediaWiki's `Special:Export`.
+///
+/// Exports use the same schema as dumps, but may include several revisions of each page,
+/// upload elements, and with older schema versions pages without an `<ns>` element. The
+/// newest revision of each page is kept, and a missing namespace is looked up from the
+/// page title and the namespaces in `<siteinfo>`.
 struct FilePageIter<R: BufRead> {
     buf: Vec<u8>,
     file_path: PathBuf,
-    xml_read: quick_xml::reader::Reader<R>,
+
+    /// The file offset reading started at, see `FileSpec::seek`.
+    seek: Option<u64>,
+
+    /// Offset in the XML of the `<page>` being read, if any.
+    page_start_pos: Option<u64>,
+
+    /// The ID and title of the last page read.
+    prev_page: Option<(u64, String)>,
+
+    /// Namespace ids and names from `<siteinfo>`, if it has been read.
+    namespaces: Vec<(i64, String)>,
+
+    page_end_offset: Arc<AtomicU64>,
+    pages_read: Arc<AtomicU64>,
+    xml_read: quick_xml::reader::Reader<LimitRead<R>>,
+}
+
+/// Limits on the XML read by the dump parser, so a malformed or pathological dump returns
+/// an error instead of using unbounded memory.
+///
+/// An exceeded limit returns a [`LimitExceeded`] error for the page. See
+/// [`LimitExceeded::is_page_skipped`] for whether reading can continue after it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Valuable)]
+pub struct ParseLimits {
+    /// Maximum length in bytes of the text of one element, e.g. a revision's `<text>`.
+    pub max_text_len: u64,
+
+    /// Maximum length in bytes of the XML of one `<page>` element, including all its
+    /// revisions.
+    pub max_page_len: u64,
+
+    /// Maximum bytes the XML buffer grows by to read a single XML event, e.g. a start tag
+    /// or a text node.
+    ///
+    /// This is enforced while reading, so it bounds memory use even when the dump is
+    /// truncated or has no closing tags. Exceeding it ends reading the file.
+    pub max_buf_len: u64,
+}
+
+/// A [`ParseLimits`] limit that was exceeded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Valuable)]
+pub enum Limit {
+    TextLen,
+    PageLen,
+    BufLen,
+}
+
+/// Error returned when parsing XML exceeds one of the [`ParseLimits`].
+///
+/// Returned wrapped in an [`Error`]; check for it with `err.downcast_ref::<LimitExceeded>()`.
+#[derive(Clone, Debug)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    pub max: u64,
+
+    /// Uncompressed byte offset in the XML where the limit was exceeded.
+    pub pos: u64,
+}
+
+/// Context added to errors reading pages from dump XML, with where in the file the error
+/// happened, e.g. to report or skip a malformed part of a dump.
+///
+/// Wraps the underlying error, which is still returned by `err.downcast_ref()`, e.g. for
+/// [`LimitExceeded`]. Check for this context with `err.downcast_ref::<DumpParseError>()`.
+#[derive(Clone, Debug, Valuable)]
+pub struct DumpParseError {
+    pub file_path: PathBuf,
+
+    /// The file offset reading started at, see [`FileSpec::seek`]. The XML offsets are
+    /// relative to this.
+    pub seek: Option<u64>,
+
+    /// Uncompressed byte offset in the XML of the start of the page being read, or of the
+    /// end of the previous page if the error was outside a page.
+    pub xml_start: u64,
+
+    /// Uncompressed byte offset in the XML where the error happened.
+    pub xml_end: u64,
+
+    /// The ID of the last page read before the error, if any.
+    pub prev_page_id: Option<u64>,
+
+    /// The title of the last page read before the error, if any.
+    pub prev_page_title: Option<String>,
+}
+
+/// A `BufRead` that returns an error when more than `ParseLimits::max_buf_len` bytes are
+/// read between calls to `reset()`.
+struct LimitRead<R: BufRead> {
+    inner: R,
+    limits: ParseLimits,
+    remaining: u64,
+    exceeded: bool,
 }
 
 pub struct JobFiles {
@@ -54,6 +153,17 @@ pub struct JobFiles {
 
 pub struct OpenJobFile {
     pub file_spec: FileSpec,
+
+    /// Offset in the uncompressed XML just after the last page returned by `pages_iter`,
+    /// relative to where reading started (i.e. after any `FileSpec::seek`).
+    ///
+    /// For uncompressed files this plus the seek offset is a page boundary that can be
+    /// passed as `FileSpec::seek` to continue reading after that page.
+    pub page_end_offset: Arc<AtomicU64>,
+
+    /// Count of pages returned by `pages_iter` so far.
+    pub pages_read: Arc<AtomicU64>,
+
     pub pages_iter: Box<dyn Iterator<Item = Result<Page>> + Send>,
     pub source_bytes_read: Arc<AtomicU64>,
     pub uncompressed_bytes_read: Arc<AtomicU64>,
@@ -64,6 +174,7 @@ pub struct OpenSpec {
     pub source: SourceSpec,
     pub limit: Option<u64>,
     pub compression: Compression,
+    pub parse_limits: ParseLimits,
 }
 
 #[derive(Clone, Debug, Valuable)]
@@ -71,6 +182,10 @@ pub enum SourceSpec {
     Job(JobSpec),
     Dir(DirSpec),
     File(FileSpec),
+
+    /// A directory of files that each hold a single page, see
+    /// [`OpenSpecBuilder::page_files`].
+    PageFiles(DirSpec),
 }
 
 #[derive(Clone, Debug, Valuable)]
@@ -93,14 +208,79 @@ pub struct FileSpec {
     pub compression: Compression,
     pub path: PathBuf,
     pub seek: Option<u64>,
+    pub parse_limits: ParseLimits,
+}
+
+/// Builds a validated [`OpenSpec`], see [`OpenSpec::builder()`].
+///
+/// Exactly one of `job`, `dir`, `file`, or `page_files` must be set.
+#[derive(Clone, Debug, Default)]
+pub struct OpenSpecBuilder {
+    dumps_dir: Option<PathBuf>,
+    job: Option<(DumpName, Version, JobName)>,
+    dir: Option<PathBuf>,
+    file: Option<PathBuf>,
+    page_files: Option<PathBuf>,
+    seek: Option<u64>,
+    file_name_regex: Option<UserRegex>,
+    compression: Option<Compression>,
+    limit: Option<u64>,
+    parse_limits: Option<ParseLimits>,
 }
 
-#[derive(Clone, Copy, Debug, Valuable)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Valuable)]
 pub enum Compression {
     Bzip2,
+    Gzip,
     LZ4,
     Zstd,
+
+    /// Detected from magic bytes but not supported for reading.
+    Xz,
+
     None,
+
+    /// Detect each file's compression from its first bytes when it is opened, see
+    /// [`Compression::sniff`].
+    Auto,
+}
+
+impl Compression {
+    /// Guess the compression of a file from its extension, e.g. an XML file exported from
+    /// `Special:Export` is usually not compressed.
+    pub fn from_path(path: &Path) -> Compression {
+        Compression::detect(path).unwrap_or(Compression::None)
+    }
+
+    /// The compression of a file with a recognised extension, or `None` for other
+    /// extensions. A `.xml` file is `Some(Compression::None)`.
+    pub fn detect(path: &Path) -> Option<Compression> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bz2") => Some(Compression::Bzip2),
+            Some("gz") => Some(Compression::Gzip),
+            Some("lz4") => Some(Compression::LZ4),
+            Some("xz") => Some(Compression::Xz),
+            Some("zst" | "zstd") => Some(Compression::Zstd),
+            Some("xml") => Some(Compression::None),
+            _ => None,
+        }
+    }
+
+    /// The compression of a file from its first bytes (magic number). Anything not
+    /// recognised is assumed to be uncompressed.
+    pub fn sniff(start: &[u8]) -> Compression {
+        const MAGIC: &[(&[u8], Compression)] = &[
+            (b"BZh", Compression::Bzip2),
+            (&[0x1f, 0x8b], Compression::Gzip),
+            (&[0x04, 0x22, 0x4d, 0x18], Compression::LZ4),
+            (&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Compression::Xz),
+        ];
+
+        MAGIC.iter()
+             .find(|(magic, _)| start.starts_with(magic))
+             .map_or(Compression::None, |(_, compression)| *compression)
+    }
 }
 
 impl FromStr for Compression {
@@ -124,7 +304,7 @@ impl Display for Compression {
 
 impl clap::ValueEnum for Compression {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Bzip2, Self::LZ4, Self::Zstd, Self::None]
+        &[Self::Auto, Self::Bzip2, Self::Gzip, Self::LZ4, Self::Zstd, Self::Xz, Self::None]
     }
 
     fn to_possible_value(&self) -> Option<PossibleValue> {
@@ -134,9 +314,19 @@ impl clap::ValueEnum for Compression {
                               .alias("bz2")
                               .help("Use bzip2 compression. Alias 'bz2'.")
             }
+            Self::Gzip => {
+                PossibleValue::new("gzip")
+                              .alias("gz")
+                              .help("Use gzip compression. Alias 'gz'.")
+            }
             Self::LZ4 => PossibleValue::new("lz4").help("Use LZ4 compression."),
             Self::Zstd => PossibleValue::new("zstd").help("Use zstd compression."),
+            Self::Xz => PossibleValue::new("xz").help("Use xz compression. Not supported yet."),
             Self::None => PossibleValue::new("none").help("Use no compression."),
+            Self::Auto => {
+                PossibleValue::new("auto")
+                              .help("Detect each file's compression from its first bytes.")
+            }
         })
     }
 }
@@ -168,19 +358,41 @@ pub fn job_path(
                          job = &*job.0))
 }
 
+/// The dump version in a job file's path, e.g. `20230320` from
+/// `enwiki/20230320/articlesdump/enwiki-20230320-pages-articles.xml.bz2`.
+///
+/// Looks for 8 digits between `-` separators in the file name, then in each directory name
+/// from the file upwards.
+pub fn version_from_path(path: &Path) -> Option<Version> {
+    path.components()
+        .rev()
+        .filter_map(|component| component.as_os_str().to_str())
+        .flat_map(|name| name.split('-'))
+        .find(|part| lazy_regex!(r"^\d{8}$").is_match(part))
+        .map(|part| Version(part.to_string()))
+}
+
 impl OpenSpec {
+    pub fn builder() -> OpenSpecBuilder {
+        OpenSpecBuilder::default()
+    }
+
     pub fn open(self) -> Result<JobFiles> {
         let file_specs: Vec<FileSpec> = match &self.source {
             SourceSpec::File(file_spec) => vec![file_spec.clone()],
             SourceSpec::Dir(dir_spec) =>
-                file_specs_from_job_dir(&*dir_spec.path, self.compression,
+                file_specs_from_job_dir(&*dir_spec.path, self.compression, self.parse_limits,
                                         dir_spec.file_name_regex.as_ref())?,
             SourceSpec::Job(job_spec) => {
                 let job_path: PathBuf = job_path(&*job_spec.out_dir, &job_spec.dump,
                                                  &job_spec.version, &job_spec.job);
-                file_specs_from_job_dir(&*job_path, self.compression,
+                file_specs_from_job_dir(&*job_path, self.compression, self.parse_limits,
                                         job_spec.file_name_regex.as_ref())?
             },
+            SourceSpec::PageFiles(dir_spec) =>
+                file_specs_from_page_files_dir(&dir_spec.path, self.compression,
+                                               self.parse_limits,
+                                               dir_spec.file_name_regex.as_ref())?,
         };
 
         let files_total_len: u64 =
@@ -209,6 +421,301 @@ impl OpenSpec {
     }
 }
 
+impl OpenSpecBuilder {
+    /// The directory dump jobs are downloaded to, required with `job`.
+    pub fn dumps_dir(&mut self, dumps_dir: impl Into<PathBuf>) -> &mut Self {
+        self.dumps_dir = Some(dumps_dir.into());
+        self
+    }
+
+    /// Read the files of a downloaded job under `dumps_dir`.
+    pub fn job(&mut self, dump: DumpName, version: Version, job: JobName) -> &mut Self {
+        self.job = Some((dump, version, job));
+        self
+    }
+
+    /// Read the files in a directory.
+    pub fn dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Read a single file.
+    pub fn file(&mut self, file: impl Into<PathBuf>) -> &mut Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Read a directory of hand-curated page files, e.g. a small corpus or test fixtures.
+    ///
+    /// Each file holds one page, either as exported XML (`*.xml`, optionally compressed)
+    /// or as wikitext with a front matter header (`*.wikitext` or `*.wiki`), see
+    /// [`parse_page_file_wikitext`]. Files are read in natural order of their names.
+    ///
+    /// If neither `compression` nor `compression_auto` is set, the compression of XML
+    /// files is detected.
+    pub fn page_files(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.page_files = Some(dir.into());
+        self
+    }
+
+    /// Seek to this offset in `file` before reading, e.g. to a stream in a multistream
+    /// dump file.
+    pub fn seek(&mut self, seek: u64) -> &mut Self {
+        self.seek = Some(seek);
+        self
+    }
+
+    /// Only read files in `job`, `dir`, or `page_files` with names that match this regex.
+    pub fn file_name_regex(&mut self, file_name_regex: UserRegex) -> &mut Self {
+        self.file_name_regex = Some(file_name_regex);
+        self
+    }
+
+    /// The compression of the files to read, overriding detection.
+    ///
+    /// Returns an error from `build()` if a file's extension shows a different compression.
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Detect each file's compression from its first bytes when it is opened, so a job or
+    /// directory can hold files with different compressions. See [`Compression::sniff`].
+    ///
+    /// If neither this nor `compression` is set, bzip2 is used.
+    pub fn compression_auto(&mut self) -> &mut Self {
+        self.compression = Some(Compression::Auto);
+        self
+    }
+
+    /// The maximum count of pages to read.
+    pub fn limit(&mut self, limit: u64) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Limits on the XML read for each page. [`ParseLimits::default()`] if not set.
+    pub fn parse_limits(&mut self, parse_limits: ParseLimits) -> &mut Self {
+        self.parse_limits = Some(parse_limits);
+        self
+    }
+
+    pub fn build(&self) -> Result<OpenSpec> {
+        let compression = self.compression.unwrap_or(
+            if self.page_files.is_some() { Compression::Auto } else { Compression::Bzip2 });
+        let parse_limits = self.parse_limits.unwrap_or_default();
+        if parse_limits.max_text_len == 0
+            || parse_limits.max_page_len == 0
+            || parse_limits.max_buf_len == 0
+        {
+            bail!("OpenSpec parse_limits must all be greater than 0. \
+                   parse_limits={parse_limits:?}");
+        }
+
+        // Only a single file is checked against its extension, directories may hold files
+        // with other extensions.
+        if let Some(file) = self.file.as_ref() {
+            match Compression::detect(file) {
+                Some(detected) if compression != Compression::Auto && detected != compression =>
+                    bail!("Compression {compression} doesn't match the file extension, \
+                           which suggests {detected}. path='{path}'",
+                          path = file.display()),
+                _ => (),
+            }
+        }
+
+        if self.seek.is_some() && self.file.is_none() {
+            bail!("OpenSpec seek can only be set with file");
+        }
+        if self.file_name_regex.is_some() && self.file.is_some() {
+            bail!("OpenSpec file_name_regex can only be set with job, dir, or page_files");
+        }
+
+        let source = match (self.job.as_ref(), self.dir.as_ref(), self.file.as_ref(),
+                            self.page_files.as_ref()) {
+            (Some((dump, version, job)), None, None, None) => {
+                let Some(dumps_dir) = self.dumps_dir.as_ref() else {
+                    bail!("OpenSpec dumps_dir must be set with job");
+                };
+                let path = job_path(dumps_dir, dump, version, job);
+                if !path.is_dir() {
+                    bail!("Job directory not found. Is the job downloaded? path='{}'",
+                          path.display());
+                }
+                SourceSpec::Job(JobSpec {
+                    out_dir: dumps_dir.clone(),
+                    dump: dump.clone(),
+                    version: version.clone(),
+                    job: job.clone(),
+                    file_name_regex: self.file_name_regex.clone(),
+                })
+            },
+            (None, Some(dir), None, None) => {
+                if !dir.is_dir() {
+                    bail!("Job directory not found. path='{}'", dir.display());
+                }
+                SourceSpec::Dir(DirSpec {
+                    path: dir.clone(),
+                    file_name_regex: self.file_name_regex.clone(),
+                })
+            },
+            (None, None, Some(file), None) => {
+                if !file.is_file() {
+                    bail!("Job file not found. path='{}'", file.display());
+                }
+                SourceSpec::File(FileSpec {
+                    compression,
+                    path: file.clone(),
+                    seek: self.seek,
+                    parse_limits,
+                })
+            },
+            (None, None, None, Some(dir)) => {
+                if !dir.is_dir() {
+                    bail!("Page files directory not found. path='{}'", dir.display());
+                }
+                SourceSpec::PageFiles(DirSpec {
+                    path: dir.clone(),
+                    file_name_regex: self.file_name_regex.clone(),
+                })
+            },
+            _ => bail!("OpenSpec must have exactly one of job, dir, file, or page_files set"),
+        };
+
+        Ok(OpenSpec {
+            source,
+            limit: self.limit,
+            compression,
+            parse_limits,
+        })
+    }
+}
+
+/// Parse pages from uncompressed dump or `Special:Export` XML.
+///
+/// Used to read dump files, and also suitable for reading XML from other sources and for
+/// fuzzing. Malformed XML returns an error rather than panicking, and XML over `limits`
+/// returns a [`LimitExceeded`] error.
+pub fn parse_pages_from_reader<R: BufRead>(
+    read: R,
+    limits: ParseLimits,
+) -> impl Iterator<Item = Result<Page>> {
+    FilePageIter {
+        buf: Vec::new(),
+        file_path: PathBuf::from("<reader>"),
+        seek: None,
+        page_start_pos: None,
+        prev_page: None,
+        namespaces: Vec::new(),
+        page_end_offset: Arc::new(AtomicU64::new(0)),
+        pages_read: Arc::new(AtomicU64::new(0)),
+        xml_read: quick_xml::reader::Reader::from_reader(LimitRead::new(read, limits)),
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> ParseLimits {
+        // MediaWiki's default maximum page size (`$wgMaxArticleSize`) is 2 MiB, so these
+        // leave plenty of room for large pages and exports with many revisions.
+        ParseLimits {
+            max_text_len: 16 * 1024 * 1024,
+            max_page_len: 256 * 1024 * 1024,
+            max_buf_len: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::TextLen => "max_text_len",
+            Limit::PageLen => "max_page_len",
+            Limit::BufLen => "max_buf_len",
+        })
+    }
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dump XML parse limit exceeded limit={limit} max={max} pos={pos}",
+               limit = self.limit, max = self.max, pos = self.pos)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl LimitExceeded {
+    /// Whether only the page that exceeded the limit was skipped, so the page iterator can
+    /// continue with the next page.
+    ///
+    /// Otherwise the XML reader stopped and no more pages will be read from the file.
+    pub fn is_page_skipped(&self) -> bool {
+        match self.limit {
+            Limit::TextLen | Limit::PageLen => true,
+            Limit::BufLen => false,
+        }
+    }
+}
+
+impl Display for DumpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error parsing dump XML path='{path}' seek={seek:?} xml_range={start}..{end}",
+               path = self.file_path.display(), seek = self.seek,
+               start = self.xml_start, end = self.xml_end)?;
+        match (self.prev_page_id, self.prev_page_title.as_ref()) {
+            (Some(id), Some(title)) => write!(f, " after page id={id} title={title:?}"),
+            _ => f.write_str(" before the first page"),
+        }
+    }
+}
+
+impl<R: BufRead> LimitRead<R> {
+    fn new(inner: R, limits: ParseLimits) -> LimitRead<R> {
+        LimitRead {
+            inner,
+            limits,
+            remaining: limits.max_buf_len,
+            exceeded: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.remaining = self.limits.max_buf_len;
+        self.exceeded = false;
+    }
+}
+
+impl<R: BufRead> std::io::Read for LimitRead<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for LimitRead<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let available = self.inner.fill_buf()?;
+        if available.is_empty() {
+            return Ok(available);
+        }
+        if self.remaining == 0 {
+            self.exceeded = true;
+            return Err(std::io::Error::other("Dump XML parse limit max_buf_len exceeded"));
+        }
+        let len = usize::try_from(self.remaining).unwrap_or(usize::MAX).min(available.len());
+        Ok(&available[..len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.remaining = self.remaining.saturating_sub(u64::try_from(amt).expect("u64 from usize"));
+        self.inner.consume(amt);
+    }
+}
+
 impl JobFiles {
     #[allow(dead_code)] // Not used yet.
     pub fn file_specs(&self) -> &[FileSpec] {
@@ -263,31 +770,69 @@ impl FileSpec {
                         ?self.seek,
                         "dump::local::FileSpec::open_pages_iter()");
 
+        if is_wikitext_page_file(&self.path) {
+            return self.open_wikitext_page_file();
+        }
+
         let mut file_read = std::fs::File::open(&*self.path)?;
         if let Some(offset) = self.seek {
             let _ = file_read.seek(std::io::SeekFrom::Start(offset))?;
         }
 
         let (prog_read, source_bytes_read) = ProgressReader::new(file_read);
-        let file_bufread = BufReader::with_capacity(128 * 1024, prog_read);
+        let mut file_bufread = BufReader::with_capacity(128 * 1024, prog_read);
+
+        let compression = match self.compression {
+            Compression::Auto => {
+                // Peek at the first bytes without consuming them.
+                let compression = Compression::sniff(file_bufread.fill_buf()?);
+                tracing::debug!(path = %self.path.display(), ?compression,
+                                "Detected dump file compression");
+                compression
+            },
+            compression => compression,
+        };
+
+        let page_end_offset = Arc::new(AtomicU64::new(0));
+        let pages_read = Arc::new(AtomicU64::new(0));
 
-        fn into_page_iter<T>(file_path: &Path, inner: T
+        fn into_page_iter<T>(
+            file_path: &Path,
+            inner: T,
+            seek: Option<u64>,
+            parse_limits: ParseLimits,
+            page_end_offset: Arc<AtomicU64>,
+            pages_read: Arc<AtomicU64>,
         ) -> Box<dyn Iterator<Item = Result<Page>> + Send>
             where T: BufRead + Send + 'static
         {
             let xml_buf = Vec::<u8>::with_capacity(100_000);
-            let xml_read = quick_xml::reader::Reader::from_reader(inner);
+            let mut xml_read =
+                quick_xml::reader::Reader::from_reader(LimitRead::new(inner, parse_limits));
+
+            // After seeking, reading starts part way through the document, so closing tags
+            // of elements opened before the seek offset (e.g. `</mediawiki>`) are unmatched.
+            xml_read.check_end_names(seek.is_none());
+
             let page_iter = FilePageIter {
                 buf: xml_buf,
                 file_path: file_path.to_path_buf(),
+                seek,
+                page_start_pos: None,
+                prev_page: None,
+                namespaces: Vec::new(),
+                page_end_offset,
+                pages_read,
                 xml_read,
             }.boxed_send();
             page_iter
         }
 
-        let (uncompressed_bytes_read, pages_iter) = match self.compression {
+        let (uncompressed_bytes_read, pages_iter) = match compression {
             Compression::None => {
-                (source_bytes_read.clone(), into_page_iter(&*self.path, file_bufread))
+                let pages_iter = into_page_iter(&*self.path, file_bufread, self.seek,
+                                                self.parse_limits, page_end_offset.clone(), pages_read.clone());
+                (source_bytes_read.clone(), pages_iter)
             },
             Compression::Bzip2 => {
                 let bzip_decoder = bzip2::bufread::MultiBzDecoder::new(file_bufread);
@@ -296,7 +841,9 @@ impl FileSpec {
                     ProgressReader::new(bzip_decoder);
 
                 let bzip_bufread = BufReader::with_capacity(64 * 1024, uncompressed_prog_read);
-                (uncompressed_bytes_read, into_page_iter(&*self.path, bzip_bufread))
+                let pages_iter = into_page_iter(&*self.path, bzip_bufread, self.seek,
+                                                self.parse_limits, page_end_offset.clone(), pages_read.clone());
+                (uncompressed_bytes_read, pages_iter)
             },
             Compression::LZ4 => {
                 let lz4_decoder = lz4_flex::frame::FrameDecoder::new(file_bufread);
@@ -305,7 +852,9 @@ impl FileSpec {
                     ProgressReader::new(lz4_decoder);
 
                 let lz4_bufread = BufReader::with_capacity(64 * 1024, uncompressed_prog_read);
-                (uncompressed_bytes_read, into_page_iter(&*self.path, lz4_bufread))
+                let pages_iter = into_page_iter(&*self.path, lz4_bufread, self.seek,
+                                                self.parse_limits, page_end_offset.clone(), pages_read.clone());
+                (uncompressed_bytes_read, pages_iter)
             }
             Compression::Zstd => {
                 let zstd_decoder = zstd::stream::read::Decoder::with_buffer(file_bufread)?;
@@ -316,22 +865,206 @@ impl FileSpec {
                 let capacity = zstd::stream::read::Decoder::<'_, std::io::Empty>
                                    ::recommended_output_size();
                 let zstd_bufread = BufReader::with_capacity(capacity, uncompressed_prog_read);
-                (uncompressed_bytes_read, into_page_iter(&*self.path, zstd_bufread))
+                let pages_iter = into_page_iter(&*self.path, zstd_bufread, self.seek,
+                                                self.parse_limits, page_end_offset.clone(), pages_read.clone());
+                (uncompressed_bytes_read, pages_iter)
+            }
+            Compression::Gzip => {
+                let gzip_decoder = flate2::bufread::MultiGzDecoder::new(file_bufread);
+
+                let (uncompressed_prog_read, uncompressed_bytes_read) =
+                    ProgressReader::new(gzip_decoder);
+
+                let gzip_bufread = BufReader::with_capacity(64 * 1024, uncompressed_prog_read);
+                let pages_iter = into_page_iter(&self.path, gzip_bufread, self.seek,
+                                                self.parse_limits, page_end_offset.clone(), pages_read.clone());
+                (uncompressed_bytes_read, pages_iter)
             }
+            Compression::Xz => bail!("xz compressed dump files are not supported yet. \
+                                      Decompress the file first. path='{path}'",
+                                     path = self.path.display()),
+            Compression::Auto => unreachable!("Compression::Auto resolved above"),
         };
 
         Ok(OpenJobFile {
-            file_spec: self.clone(),
+            // Return the detected compression rather than `Auto`.
+            file_spec: FileSpec {
+                compression,
+                ..self.clone()
+            },
+            page_end_offset,
+            pages_read,
             pages_iter,
             source_bytes_read,
             uncompressed_bytes_read,
         })
     }
+
+    /// Open a `*.wikitext` page file, which holds a single page. See
+    /// [`parse_page_file_wikitext`].
+    fn open_wikitext_page_file(&self) -> Result<OpenJobFile> {
+        let text = std::fs::read_to_string(&self.path)?;
+        let len = u64::try_from(text.len())?;
+
+        let page_end_offset = Arc::new(AtomicU64::new(0));
+        let pages_read = Arc::new(AtomicU64::new(0));
+
+        // A seek past the start skips the file's only page.
+        let page = match self.seek {
+            Some(seek) if seek > 0 => None,
+            _ => Some(parse_page_file_wikitext(&text).map_err(|e| {
+                e.context(format!("Parsing page file path='{}'", self.path.display()))
+            })),
+        };
+        let pages_iter = {
+            let page_end_offset = page_end_offset.clone();
+            let pages_read = pages_read.clone();
+            page.into_iter()
+                .inspect(move |_| {
+                    page_end_offset.store(len, Ordering::SeqCst);
+                    pages_read.fetch_add(1, Ordering::SeqCst);
+                })
+                .boxed_send()
+        };
+
+        Ok(OpenJobFile {
+            file_spec: FileSpec {
+                compression: Compression::None,
+                ..self.clone()
+            },
+            page_end_offset,
+            pages_read,
+            pages_iter,
+            source_bytes_read: Arc::new(AtomicU64::new(len)),
+            uncompressed_bytes_read: Arc::new(AtomicU64::new(len)),
+        })
+    }
+}
+
+/// Format `page` as a page file that [`parse_page_file_wikitext`] reads back, e.g. to save
+/// a page to import later.
+///
+/// Categories are not written, because they are parsed from the page text. The page's
+/// revision text is empty if it has none.
+pub fn format_page_file_wikitext(page: &Page) -> String {
+    let mut file = format!("---\ntitle: {title}\nid: {id}\nns: {ns}\n",
+                           title = page.title, id = page.id, ns = page.ns_id);
+    if let Some(revision) = page.revision.as_ref() {
+        file.push_str(&format!("revision_id: {id}\n", id = revision.id));
+        if let Some(parent_id) = revision.parent_id {
+            file.push_str(&format!("parent_id: {parent_id}\n"));
+        }
+        if let Some(timestamp) = revision.timestamp {
+            file.push_str(&format!("timestamp: {timestamp}\n",
+                                   timestamp = timestamp.to_rfc3339()));
+        }
+    }
+    if let Some(restrictions) = page.restrictions.as_ref() {
+        file.push_str(&format!("restrictions: {restrictions}\n"));
+    }
+    file.push_str("---\n");
+    file.push_str(page.revision_text().unwrap_or(""));
+    file
+}
+
+fn is_wikitext_page_file(path: &Path) -> bool {
+    matches!(path.extension().and_then(|ext| ext.to_str()), Some("wikitext" | "wiki"))
+}
+
+/// Parse a page file holding wikitext with a front matter header, e.g.
+///
+/// ```text
+/// ---
+/// title: Talk:Onboarding
+/// id: 7
+/// ns: 1
+/// timestamp: 2023-01-01T00:00:00Z
+/// ---
+/// Welcome! [[Category:Guides]]
+/// ```
+///
+/// `title` and `id` are required. `ns` defaults to 0, `revision_id` to the page id, and
+/// `parent_id`, `timestamp` (RFC 3339), and `restrictions` to none. Other keys are an error.
+/// The page text is everything after the closing `---` line.
+pub fn parse_page_file_wikitext(file: &str) -> Result<Page> {
+    let mut lines = file.split_inclusive('\n');
+    if lines.next().map(str::trim_end) != Some("---") {
+        bail!("Page file must start with a '---' front matter line");
+    }
+
+    let mut title = None;
+    let mut id = None;
+    let mut ns_id = 0_i64;
+    let mut revision_id = None;
+    let mut parent_id = None;
+    let mut timestamp = None;
+    let mut restrictions = None;
+
+    let mut header_len = "---\n".len();
+    let mut closed = false;
+    for line in lines.by_ref() {
+        header_len += line.len();
+        let line = line.trim_end();
+        if line == "---" {
+            closed = true;
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            bail!("Page file front matter line must be 'key: value'. line='{line}'");
+        };
+        let value = value.trim();
+        match key.trim() {
+            "title" => title = Some(value.to_string()),
+            "id" => id = Some(value.parse::<u64>()?),
+            "ns" => ns_id = value.parse::<i64>()?,
+            "revision_id" => revision_id = Some(value.parse::<u64>()?),
+            "parent_id" => parent_id = Some(value.parse::<u64>()?),
+            "timestamp" => timestamp = Some(DateTime::parse_from_rfc3339(value)?),
+            "restrictions" => restrictions = Some(value.to_string()),
+            key => bail!("Unknown page file front matter key '{key}'"),
+        }
+    }
+    if !closed {
+        bail!("Page file front matter must end with a '---' line");
+    }
+
+    let Some(title) = title.filter(|title| !title.is_empty()) else {
+        bail!("Page file front matter must have a title");
+    };
+    let Some(id) = id else {
+        bail!("Page file front matter must have an id");
+    };
+
+    // Header lines may end with "\r\n".
+    let text = file.get(header_len.min(file.len())..)
+                   .unwrap_or_default()
+                   .trim_start_matches(['\r', '\n'])
+                   .to_string();
+    let categories = wikitext::parse_categories(&text, &[]);
+
+    Ok(Page {
+        ns_id,
+        id,
+        title,
+        revision: Some(Revision {
+            id: revision_id.unwrap_or(id),
+            parent_id,
+            timestamp,
+            text: Some(text),
+            sha1: None,
+            categories,
+        }),
+        restrictions,
+    })
 }
 
 fn file_specs_from_job_dir(
     job_path: &Path,
     compression: Compression,
+    parse_limits: ParseLimits,
     user_file_name_regex: Option<&UserRegex>,
 ) -> Result<Vec<FileSpec>>
 {
@@ -350,18 +1083,34 @@ fn file_specs_from_job_dir(
 
                 let name_regex = match compression {
                     Compression::Bzip2 => lazy_regex!(FILE_RE_PREFIX, r#"\.bz2$"#),
+                    Compression::Gzip => lazy_regex!(FILE_RE_PREFIX, r#"\.gz$"#),
                     Compression::LZ4 => lazy_regex!(FILE_RE_PREFIX, r#"\.lz4$"#),
                     Compression::Zstd => lazy_regex!(FILE_RE_PREFIX, r#"\.zstd$"#),
-                    Compression::None => lazy_regex!(FILE_RE_PREFIX, r#"$"#),
+                    Compression::Xz => lazy_regex!(FILE_RE_PREFIX, r#"\.xz$"#),
+                    Compression::None | Compression::Auto => lazy_regex!(FILE_RE_PREFIX, r#"$"#),
+                };
+                let extension_regex = match compression {
+                    Compression::Bzip2 => lazy_regex!(r#"\.bz2$"#),
+                    Compression::Gzip => lazy_regex!(r#"\.gz$"#),
+                    Compression::LZ4 => lazy_regex!(r#"\.lz4$"#),
+                    Compression::Zstd => lazy_regex!(r#"\.zstd$"#),
+                    Compression::Xz => lazy_regex!(r#"\.xz$"#),
+                    Compression::None | Compression::Auto => lazy_regex!(r#"$"#),
                 };
                 let name = dir_entry.file_name().to_string_lossy().into_owned();
-                if name_regex.is_match(&*name)
-                    && user_file_name_regex.as_ref().map_or(true, |re| re.0.is_match(&*name))
-                {
+
+                // A user regex replaces the Wikimedia file name pattern, so files from other
+                // sources (e.g. a MediaWiki XML export) can be imported whatever their name.
+                let is_match = match user_file_name_regex {
+                    Some(re) => re.0.is_match(&name) && extension_regex.is_match(&name),
+                    None => name_regex.is_match(&name),
+                };
+                if is_match {
                     Ok(Some(FileSpec {
                         compression,
                         path: dir_entry.path(),
                         seek: None,
+                        parse_limits,
                     }))
                 } else {
                     Ok(None)
@@ -374,22 +1123,107 @@ fn file_specs_from_job_dir(
     Ok(file_specs)
 }
 
+fn file_specs_from_page_files_dir(
+    dir: &Path,
+    compression: Compression,
+    parse_limits: ParseLimits,
+    user_file_name_regex: Option<&UserRegex>,
+) -> Result<Vec<FileSpec>>
+{
+    let extension_regex = lazy_regex!(r#"\.(xml(\.(bz2|gz|lz4|zstd))?|wikitext|wiki)$"#);
+
+    let mut file_specs = Vec::new();
+    for dir_entry in std::fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = dir_entry.file_name().to_string_lossy().into_owned();
+        if !extension_regex.is_match(&name)
+            || user_file_name_regex.is_some_and(|re| !re.0.is_match(&name))
+        {
+            continue;
+        }
+        let path = dir_entry.path();
+        file_specs.push(FileSpec {
+            compression: if is_wikitext_page_file(&path) { Compression::None } else { compression },
+            path,
+            seek: None,
+            parse_limits,
+        });
+    }
+
+    file_specs.sort_by(|a, b| natord::compare(&a.path.to_string_lossy(),
+                                              &b.path.to_string_lossy()));
+
+    Ok(file_specs)
+}
+
 impl<R: BufRead> Iterator for FilePageIter<R> {
     type Item = Result<Page>;
 
     fn next(&mut self) -> Option<Result<Page>> {
+        let res = self.next_page();
+        let page_start_pos = self.page_start_pos.take();
+        match res {
+            Some(Ok(page)) => {
+                self.prev_page = Some((page.id, page.title.clone()));
+                Some(Ok(page))
+            },
+            Some(Err(e)) => {
+                let xml_end = self.xml_position();
+                let (prev_page_id, prev_page_title) = self.prev_page.clone().unzip();
+                Some(Err(e.context(DumpParseError {
+                    file_path: self.file_path.clone(),
+                    seek: self.seek,
+                    xml_start: page_start_pos.unwrap_or_else(
+                        || self.page_end_offset.load(Ordering::SeqCst)),
+                    xml_end,
+                    prev_page_id,
+                    prev_page_title,
+                })))
+            },
+            None => None,
+        }
+    }
+}
+
+impl<R: BufRead> FilePageIter<R> {
+    /// Offset in the XML read so far.
+    fn xml_position(&self) -> u64 {
+        self.xml_read.buffer_position().try_into().expect("u64 from usize")
+    }
+
+    fn next_page(&mut self) -> Option<Result<Page>> {
         loop {
             let pos = self.xml_read.buffer_position();
-            match try_iter!(self.xml_read.read_event_into(&mut self.buf)) {
+            match try_iter!(read_event(&mut self.xml_read, &mut self.buf)) {
                 Event::Start(b) if b.name().as_ref() == b"page" => {
                     let page_start_pos = pos;
+                    self.page_start_pos = Some(pos.try_into().expect("u64 from usize"));
+                    let max_page_len = self.xml_read.get_ref().limits.max_page_len;
                     self.buf.clear();
                     let mut page_title: Option<String> = None;
                     let mut page_ns_id: Option<i64> = None;
                     let mut page_id: Option<u64> = None;
+                    let mut page_restrictions: Option<String> = None;
                     let mut revision: Option<Revision> = None;
+                    let mut revisions_count: u64 = 0;
                     loop {
-                        match try_iter!(self.xml_read.read_event_into(&mut self.buf)) {
+                        // Events in <page> are handled one at a time, so the buffer only
+                        // needs to hold the current one.
+                        self.buf.clear();
+                        let page_len = self.xml_read.buffer_position() - page_start_pos;
+                        if u64::try_from(page_len).expect("u64 from usize") > max_page_len {
+                            return Some(Err(LimitExceeded {
+                                limit: Limit::PageLen,
+                                max: max_page_len,
+                                pos: self.xml_read.buffer_position().try_into()
+                                         .expect("u64 from usize"),
+                            }.into()));
+                        }
+
+                        match try_iter!(read_event(&mut self.xml_read, &mut self.buf)) {
                             Event::Start(b) if b.name().as_ref() == b"title" => {
                                 page_title = Some(try_iter!(take_element_text(&mut self.xml_read,
                                                                            &mut self.buf,
@@ -401,12 +1235,20 @@ impl<R: BufRead> Iterator for FilePageIter<R> {
                                                       &mut self.buf,
                                                       b"ns")).parse::<i64>()));
                             },
-                            Event::Start(b) if b.name().as_ref() == b"id" => {
+                            Event::Start(b) if b.name().as_ref() == b"id"
+                                               && page_id.is_none() => {
                                 page_id = Some(try_iter!(try_iter!(
                                     take_element_text(&mut self.xml_read,
                                                       &mut self.buf,
                                                       b"id")).parse::<u64>()));
                             },
+                            Event::Start(b) if b.name().as_ref() == b"restrictions" => {
+                                page_restrictions = Some(try_iter!(
+                                    take_element_text(&mut self.xml_read,
+                                                      &mut self.buf,
+                                                      b"restrictions")))
+                                    .filter(|restrictions| !restrictions.is_empty());
+                            },
                             Event::Start(b) if b.name().as_ref() == b"revision" => {
                                 let mut revision_id: Option<u64> = None;
                                 let mut revision_parent_id: Option<u64> = None;
@@ -414,7 +1256,9 @@ impl<R: BufRead> Iterator for FilePageIter<R> {
                                 let mut revision_text: Option<String> = None;
                                 let mut revision_sha1: Option<Sha1Hash> = None;
                                 loop {
-                                    match try_iter!(self.xml_read.read_event_into(&mut self.buf)) {
+                                    self.buf.clear();
+                                    match try_iter!(read_event(&mut self.xml_read,
+                                                               &mut self.buf)) {
                                         // Skip <id> if revision_id is already Some(_).
                                         // This ignores <contributor><id>_</id></contributor>
                                         // in a hacky way without actually handling
@@ -461,6 +1305,8 @@ impl<R: BufRead> Iterator for FilePageIter<R> {
                                                 try_iter!(Sha1Hash::from_base36_str(&*s)));
                                         },
                                         Event::End(b) if b.name().as_ref() == b"revision" => break,
+                                        Event::Eof => return Some(Err(format_err!(
+                                            "Unexpected end of file in <revision>"))),
                                         _ => {},
                                     }
                                 } // end of loop over child nodes of <revision />
@@ -488,58 +1334,478 @@ impl<R: BufRead> Iterator for FilePageIter<R> {
                                     },
                                     (_, _) => {},
                                 }
-                                revision = Some(Revision {
+                                let new_revision = Revision {
                                     id: revision_id,
                                     parent_id: revision_parent_id,
                                     timestamp: revision_timestamp,
                                     categories:
                                         match revision_text {
                                             None => vec![],
-                                            Some(ref text) =>
-                                                wikitext::parse_categories(text.as_str()),
+                                            Some(ref text) => wikitext::parse_categories(
+                                                text.as_str(),
+                                                &category_namespace_names(&self.namespaces)),
                                         },
                                     sha1: revision_sha1,
                                     // This moves revision_text, so do it last.
                                     text: revision_text,
-                                });
+                                };
+
+                                // Exports with history have several revisions, oldest first.
+                                revisions_count += 1;
+                                let is_newer = revision.as_ref().is_none_or(|prev|
+                                    (new_revision.timestamp, new_revision.id)
+                                        >= (prev.timestamp, prev.id));
+                                if is_newer {
+                                    revision = Some(new_revision);
+                                }
+                            },
+                            Event::Start(b) if b.name().as_ref() == b"upload" => {
+                                // Uploaded file metadata in an export, not needed.
+                                let end = b.to_end().into_owned();
+                                try_iter!(self.xml_read.read_to_end_into(end.name(),
+                                                                         &mut self.buf));
                             },
                             Event::End(b) if b.name().as_ref() == b"page" => {
+                                let title = try_iter!(page_title.ok_or(
+                                    format_err!("No page title")));
+                                let ns_id = match page_ns_id {
+                                    Some(ns_id) => ns_id,
+                                    // Exports before schema 0.6 have no <ns>.
+                                    None if !self.namespaces.is_empty() =>
+                                        ns_id_from_title(&self.namespaces, &title),
+                                    None => Namespace::from_page_title(&title).key(),
+                                };
+                                if Namespace::is_virtual_key(ns_id) {
+                                    return Some(Err(format_err!(
+                                        "Page in virtual namespace ns_id={ns_id} \
+                                         page_title={title:?}")));
+                                }
+                                if revisions_count > 1 {
+                                    tracing::trace!(revisions_count, page_title = title,
+                                                    "Kept newest revision of page");
+                                }
                                 let page = Page {
-                                    title: try_iter!(page_title.ok_or(
-                                        format_err!("No page title"))),
+                                    title,
                                     id: try_iter!(page_id.ok_or(
                                         format_err!("No page id"))),
-                                    ns_id: try_iter!(page_ns_id.ok_or(
-                                        format_err!("No page ns"))),
+                                    ns_id,
                                     revision: revision,
+                                    restrictions: page_restrictions,
                                 };
+                                self.page_end_offset.store(
+                                    self.xml_read.buffer_position().try_into()
+                                        .expect("u64 from usize"),
+                                    Ordering::SeqCst);
+                                self.pages_read.fetch_add(1, Ordering::SeqCst);
                                 return Some(Ok(page));
                             },
+                            Event::Eof => return Some(Err(format_err!(
+                                "Unexpected end of file in <page>"))),
                             _ => {},
                         } // match on Event in <page>
                     } // loop on Events in <page>
                 }, // Handle <page>
+                Event::Start(b) if b.name().as_ref() == b"siteinfo" => {
+                    self.buf.clear();
+                    self.namespaces = try_iter!(read_siteinfo_namespaces(&mut self.xml_read,
+                                                                         &mut self.buf));
+                },
                 Event::Eof => return None,
                 _ => {},
             } // match on Event at top level
 
             self.buf.clear();
+            if self.buf.capacity() > MAX_RETAINED_BUF_CAPACITY {
+                // Free memory used by an unusually large page.
+                self.buf = Vec::with_capacity(MAX_RETAINED_BUF_CAPACITY);
+            }
         } // loop on Event at top level
-    } // end of fn next
-} // end of impl Iterator for FilePageIter
+    } // end of fn next_page
+} // end of impl FilePageIter
+
+/// Read buffers larger than this are shrunk after each page.
+const MAX_RETAINED_BUF_CAPACITY: usize = 1024 * 1024;
+
+/// Read the next XML event, with at most `ParseLimits::max_buf_len` bytes read into `buf`.
+fn read_event<'b, R: BufRead>(
+    xml_read: &mut quick_xml::reader::Reader<LimitRead<R>>,
+    buf: &'b mut Vec<u8>,
+) -> Result<Event<'b>> {
+    xml_read.get_mut().reset();
+    match xml_read.read_event_into(buf) {
+        Ok(event) => Ok(event),
+        Err(_) if xml_read.get_ref().exceeded => Err(LimitExceeded {
+            limit: Limit::BufLen,
+            max: xml_read.get_ref().limits.max_buf_len,
+            pos: xml_read.buffer_position().try_into().expect("u64 from usize"),
+        }.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read the namespaces from the children of a `<siteinfo>` element, up to its end.
+fn read_siteinfo_namespaces<R: BufRead>(
+    xml_read: &mut quick_xml::reader::Reader<LimitRead<R>>,
+    buf: &mut Vec<u8>,
+) -> Result<Vec<(i64, String)>> {
+    let mut namespaces = Vec::new();
+    loop {
+        match read_event(xml_read, buf)? {
+            Event::Start(b) if b.name().as_ref() == b"namespace" => {
+                let key = namespace_key(&b)?;
+                let name = take_element_text(xml_read, buf, b"namespace")?;
+                namespaces.push((key, name));
+            },
+            Event::Empty(b) if b.name().as_ref() == b"namespace" => {
+                // The main namespace has no name.
+                namespaces.push((namespace_key(&b)?, "".to_string()));
+            },
+            Event::End(b) if b.name().as_ref() == b"siteinfo" => break,
+            Event::Eof => break,
+            _ => {},
+        }
+        buf.clear();
+    }
+    Ok(namespaces)
+}
+
+fn namespace_key(element: &quick_xml::events::BytesStart) -> Result<i64> {
+    let key = element.try_get_attribute("key")?
+                     .ok_or_else(|| format_err!("<namespace> without a key attribute"))?;
+    Ok(key.unescape_value()?.parse::<i64>()?)
+}
+
+/// Find the namespace of a page from the prefix of its title and a wiki's own namespaces,
+/// e.g. `Talk:Foo` is in the namespace named `Talk`. Titles without a known namespace prefix
+/// are in the main namespace.
+///
+/// See also [`Namespace::from_page_title`], which uses the English Wikipedia's namespaces.
+fn ns_id_from_title(namespaces: &[(i64, String)], title: &str) -> i64 {
+    let Some((prefix, _)) = title.split_once(':') else {
+        return 0;
+    };
+    namespaces.iter()
+              .find(|(_, name)| !name.is_empty() && name == prefix)
+              .map_or(0, |(id, _)| *id)
+}
+
+/// The names of the category namespace in a wiki's own namespaces from `<siteinfo>`, e.g.
+/// `Kategorie` for the German Wikipedia.
+fn category_namespace_names(namespaces: &[(i64, String)]) -> Vec<&str> {
+    namespaces.iter()
+              .filter(|(id, name)| *id == Namespace::CATEGORY.key() && !name.is_empty())
+              .map(|(_, name)| name.as_str())
+              .collect()
+}
 
 fn take_element_text<R: BufRead>(
-    xml_read: &mut quick_xml::reader::Reader<R>,
+    xml_read: &mut quick_xml::reader::Reader<LimitRead<R>>,
     buf: &mut Vec<u8>,
     name: &[u8],
 ) -> Result<String> {
     let mut text = "".to_string();
     loop {
-        match xml_read.read_event_into(buf)? {
-            Event::Text(b) => text = b.unescape()?.into_owned(),
+        match read_event(xml_read, buf)? {
+            Event::Text(b) => text.push_str(&b.unescape()?),
+            Event::CData(b) => text.push_str(std::str::from_utf8(&b)?),
             Event::End(b) if b.name().as_ref() == name => break,
+            Event::Eof => bail!("Unexpected end of file in <{name}>",
+                                name = String::from_utf8_lossy(name)),
             _ => {},
         }
+
+        let max = xml_read.get_ref().limits.max_text_len;
+        if u64::try_from(text.len()).expect("u64 from usize") > max {
+            return Err(LimitExceeded {
+                limit: Limit::TextLen,
+                max,
+                pos: xml_read.buffer_position().try_into().expect("u64 from usize"),
+            }.into());
+        }
     }
     Ok(text)
 }
+
+/// Entry points for the fuzz targets in `/fuzz`. Not a stable API.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzz {
+    use crate::Result;
+
+    /// Read the text of an element named `name` from `data`, which starts just after the
+    /// element's start tag.
+    pub fn take_element_text(data: &[u8], name: &[u8]) -> Result<String> {
+        let mut xml_read = quick_xml::reader::Reader::from_reader(
+            super::LimitRead::new(data, super::ParseLimits::default()));
+        let mut buf = Vec::new();
+        super::take_element_text(&mut xml_read, &mut buf, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_from_job_file_path() {
+        let version = |path: &str| version_from_path(Path::new(path)).map(|v| v.0);
+        assert_eq!(version("/dumps/enwiki-20230320-pages-articles1.xml-p1p41242.bz2"),
+                   Some("20230320".to_string()));
+        assert_eq!(version("/out/dumps/enwiki/20230301/articlesdump/pages.xml"),
+                   Some("20230301".to_string()));
+        assert_eq!(version("/corpus/123456789-pages.xml"), None);
+    }
+
+    fn read_pages(xml: &str) -> Vec<Page> {
+        parse_pages_from_reader(xml.as_bytes(), ParseLimits::default())
+            .collect::<Result<Vec<Page>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn special_export() {
+        let pages = read_pages(r#"
+<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.5/" version="0.5">
+  <siteinfo>
+    <sitename>Internal</sitename>
+    <namespaces>
+      <namespace key="0" case="first-letter" />
+      <namespace key="1" case="first-letter">Talk</namespace>
+      <namespace key="14" case="first-letter">Kategorie</namespace>
+    </namespaces>
+  </siteinfo>
+  <page>
+    <title>Talk:Onboarding</title>
+    <id>7</id>
+    <restrictions>edit=autoconfirmed:move=sysop</restrictions>
+    <revision>
+      <id>20</id>
+      <timestamp>2023-01-01T00:00:00Z</timestamp>
+      <contributor><username>Alice</username><id>3</id></contributor>
+      <text xml:space="preserve">old</text>
+    </revision>
+    <revision>
+      <id>21</id>
+      <parentid>20</parentid>
+      <timestamp>2023-02-01T00:00:00Z</timestamp>
+      <contributor><username>Bob</username><id>4</id></contributor>
+      <text xml:space="preserve">new [[Category:Docs]] [[Kategorie:Handbuch|Bob]]</text>
+    </revision>
+    <upload>
+      <timestamp>2023-03-01T00:00:00Z</timestamp>
+      <contributor><username>Carol</username><id>5</id></contributor>
+      <filename>Diagram.png</filename>
+    </upload>
+  </page>
+  <page>
+    <title>Onboarding: the first week</title>
+    <id>8</id>
+    <revision>
+      <id>30</id>
+      <text xml:space="preserve">text</text>
+    </revision>
+  </page>
+</mediawiki>
+"#);
+
+        assert_eq!(pages.len(), 2);
+
+        assert_eq!(pages[0].id, 7);
+        assert_eq!(pages[0].ns_id, 1);
+        assert_eq!(pages[0].restrictions.as_deref(), Some("edit=autoconfirmed:move=sysop"));
+        let revision = pages[0].revision.as_ref().unwrap();
+        assert_eq!(revision.id, 21);
+        assert_eq!(revision.categories, vec![
+            PageCategory { name: CategoryName("Docs".to_string()), sort_key: None },
+            PageCategory {
+                name: CategoryName("Handbuch".to_string()),
+                sort_key: Some("Bob".to_string()),
+            },
+        ]);
+
+        assert_eq!(pages[1].id, 8);
+        assert_eq!(pages[1].ns_id, 0);
+        assert_eq!(pages[1].restrictions, None);
+    }
+
+    #[test]
+    fn compression_from_path() {
+        assert!(matches!(Compression::from_path(Path::new("export.xml")), Compression::None));
+        assert!(matches!(Compression::from_path(Path::new("a.xml.bz2")), Compression::Bzip2));
+        assert!(matches!(Compression::from_path(Path::new("a.xml.zst")), Compression::Zstd));
+    }
+
+    #[test]
+    fn compression_sniff() {
+        assert_eq!(Compression::sniff(b"BZh91AY&SY"), Compression::Bzip2);
+        assert_eq!(Compression::sniff(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(Compression::sniff(&[0x28, 0xb5, 0x2f, 0xfd, 0x04]), Compression::Zstd);
+        assert_eq!(Compression::sniff(&[0x04, 0x22, 0x4d, 0x18]), Compression::LZ4);
+        assert_eq!(Compression::sniff(b"\xfd7zXZ\x00\x00"), Compression::Xz);
+        assert_eq!(Compression::sniff(b"<mediawiki "), Compression::None);
+        assert_eq!(Compression::sniff(b""), Compression::None);
+    }
+
+    #[test]
+    fn open_spec_builder() {
+        let temp_dir = crate::TempDir::create(&std::env::temp_dir(), false /* keep */).unwrap();
+        let dir = temp_dir.path().unwrap();
+        for name in ["pages-articles1.xml.bz2", "pages-articles2.xml.bz2", "index.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let spec = OpenSpec::builder().dir(dir).compression_auto().limit(10).build().unwrap();
+        assert_eq!(spec.compression, Compression::Auto);
+        assert_eq!(spec.limit, Some(10));
+
+        let spec = OpenSpec::builder().dir(dir).build().unwrap();
+        assert_eq!(spec.compression, Compression::Bzip2);
+
+        // Compression doesn't match the file extension.
+        assert!(OpenSpec::builder().file(dir.join("pages-articles1.xml.bz2"))
+                                   .compression(Compression::Zstd)
+                                   .build().is_err());
+
+        // Missing or conflicting sources.
+        assert!(OpenSpec::builder().dir(dir.join("missing")).build().is_err());
+        assert!(OpenSpec::builder().dir(dir).file(dir.join("index.txt")).build().is_err());
+        assert!(OpenSpec::builder().dir(dir).page_files(dir).build().is_err());
+        assert!(OpenSpec::builder().build().is_err());
+    }
+
+    #[test]
+    fn page_file_wikitext() {
+        let page = parse_page_file_wikitext(
+            "---\ntitle: Talk:Onboarding\nid: 7\nns: 1\n\
+             timestamp: 2023-01-01T00:00:00Z\n---\nWelcome! [[Category:Guides]]\n").unwrap();
+        assert_eq!(page.title, "Talk:Onboarding");
+        assert_eq!((page.id, page.ns_id), (7, 1));
+        let revision = page.revision.unwrap();
+        assert_eq!(revision.id, 7);
+        assert_eq!(revision.timestamp.unwrap().to_rfc3339(), "2023-01-01T00:00:00+00:00");
+        assert_eq!(revision.text.as_deref(), Some("Welcome! [[Category:Guides]]\n"));
+        assert_eq!(revision.categories.len(), 1);
+
+        let page = parse_page_file_wikitext("---\r\ntitle: A\r\nid: 1\r\n---\r\nText")
+            .unwrap();
+        assert_eq!(page.ns_id, 0);
+        assert_eq!(page.revision.unwrap().text.as_deref(), Some("Text"));
+
+        for file in ["title: A\nid: 1\n",
+                     "---\ntitle: A\nid: 1\n",
+                     "---\nid: 1\n---\n",
+                     "---\ntitle: A\n---\n",
+                     "---\ntitle: A\nid: x\n---\n",
+                     "---\ntitle: A\nid: 1\ncolour: red\n---\n"] {
+            assert!(parse_page_file_wikitext(file).is_err(), "file={file:?}");
+        }
+    }
+
+    #[test]
+    fn format_page_file_round_trip() {
+        let file = "---\ntitle: Talk:Onboarding\nid: 7\nns: 1\nrevision_id: 9\nparent_id: 8\n\
+                    timestamp: 2023-01-01T00:00:00+00:00\n---\nWelcome! [[Category:Guides]]\n";
+        let page = parse_page_file_wikitext(file).unwrap();
+        assert_eq!(format_page_file_wikitext(&page), file);
+    }
+
+    #[test]
+    fn open_page_files() {
+        let temp_dir = crate::TempDir::create(&std::env::temp_dir(), false /* keep */).unwrap();
+        let dir = temp_dir.path().unwrap();
+        std::fs::write(dir.join("10-b.wikitext"), "---\ntitle: B\nid: 2\n---\nB text")
+            .unwrap();
+        std::fs::write(dir.join("9-a.xml"),
+                       "<mediawiki><page><title>A</title><ns>0</ns><id>1</id>\
+                        <revision><id>3</id><text>A text</text></revision>\
+                        </page></mediawiki>").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let files = OpenSpec::builder().page_files(dir).build().unwrap().open().unwrap();
+        let titles = files.open_pages_iter().unwrap()
+                          .map(|page| page.unwrap().title)
+                          .collect::<Vec<String>>();
+        assert_eq!(titles, ["A", "B"]);
+    }
+
+    #[test]
+    fn malformed_pages() {
+        let inputs = [
+            "<mediawiki><page><title>A</title><id>1</id>",
+            "<mediawiki><page><title>A</title><id>1</id><revision><id>2</id>",
+            "<mediawiki><page><title>A",
+            "<mediawiki><page><title>A</wrong></page>",
+            "<mediawiki><page><id>not a number</id></page></mediawiki>",
+            "<mediawiki><page><title>&bogus;</title></page></mediawiki>",
+            "<mediawiki><page>\u{0}<<<</page>",
+        ];
+        for xml in inputs {
+            // Must finish with an error rather than panicking or looping forever.
+            let results = parse_pages_from_reader(xml.as_bytes(), ParseLimits::default())
+                              .take(100)
+                              .collect::<Vec<_>>();
+            assert!(results.len() < 100, "xml={xml:?}");
+            assert!(results.iter().any(|r| r.is_err()), "xml={xml:?}");
+        }
+    }
+
+    #[test]
+    fn parse_error_context() {
+        let page_1 = "<page><title>A</title><ns>0</ns><id>1</id></page>";
+        let xml = format!("<mediawiki>{page_1}<page><title>B</title><id>x</id></page>");
+        let results = parse_pages_from_reader(xml.as_bytes(), ParseLimits::default())
+                          .collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        let err = results[1].as_ref().unwrap_err();
+        let context = err.downcast_ref::<DumpParseError>().unwrap();
+        let page_2_start = u64::try_from("<mediawiki>".len() + page_1.len()).unwrap();
+        assert_eq!((context.xml_start, context.prev_page_id, context.prev_page_title.as_deref()),
+                   (page_2_start, Some(1), Some("A")));
+        assert!(context.xml_end > context.xml_start);
+    }
+
+    #[test]
+    fn element_text_cdata() {
+        let pages = read_pages(
+            "<mediawiki><page><title>A &amp; <![CDATA[<B>]]></title><ns>0</ns><id>1</id>\
+             </page></mediawiki>");
+        assert_eq!(pages[0].title, "A & <B>");
+    }
+
+    #[test]
+    fn parse_limits() {
+        let limits = ParseLimits {
+            max_text_len: 100,
+            max_page_len: 1000,
+            max_buf_len: 500,
+        };
+        let page = |id: u64, text_len: usize| format!(
+            "<page><title>P{id}</title><ns>0</ns><id>{id}</id>\
+             <revision><id>{id}</id><text>{text}</text></revision></page>",
+            text = "a".repeat(text_len));
+        let parse = |xml: String| -> Vec<StdResult<u64, Limit>> {
+            parse_pages_from_reader(xml.as_bytes(), limits)
+                .take(100)
+                .map(|res| res.map(|page| page.id)
+                              .map_err(|e| e.downcast_ref::<LimitExceeded>().unwrap().limit))
+                .collect()
+        };
+
+        assert_eq!(parse(format!("<mediawiki>{}{}</mediawiki>", page(1, 100), page(2, 10))),
+                   vec![Ok(1), Ok(2)]);
+
+        // After an error the next page is still read.
+        assert_eq!(parse(format!("<mediawiki>{}{}</mediawiki>", page(1, 101), page(2, 10))),
+                   vec![Err(Limit::TextLen), Ok(2)]);
+
+        let revisions = (0..10).map(|_| "<revision><id>1</id></revision>".repeat(10))
+                               .collect::<String>();
+        assert_eq!(parse(format!("<mediawiki><page><title>P1</title><id>1</id>{revisions}\
+                                  </page>{}</mediawiki>", page(2, 10))),
+                   vec![Err(Limit::PageLen), Ok(2)]);
+
+        // The XML reader stops after max_buf_len is exceeded.
+        assert_eq!(parse(format!("<mediawiki>{}{}</mediawiki>", page(1, 1200), page(2, 10))),
+                   vec![Err(Limit::BufLen)]);
+    }
+}
@@ -0,0 +1,140 @@
+//! A cache on disk of parsed dump metadata, such as the list of dumps, a dump's versions,
+//! and a version's job statuses.
+//!
+//! Cached metadata is used until it is older than a maximum age, or a refresh is requested.
+//! If fetching fresh metadata fails, older cached metadata is used instead, so commands that
+//! only list metadata also work offline.
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use crate::Result;
+use derive_builder::Builder;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration as StdDuration,
+};
+
+#[derive(Builder, Clone, Debug)]
+pub struct Options {
+    /// The directory to keep cached metadata in.
+    path: PathBuf,
+
+    /// Ignore cached metadata and always fetch fresh metadata, then cache that.
+    #[builder(default = "false")]
+    refresh: bool,
+
+    /// How long cached metadata is used for before fetching fresh metadata.
+    #[builder(default = "MAX_AGE_DEFAULT")]
+    max_age: StdDuration,
+}
+
+#[derive(Clone, Debug)]
+pub struct MetadataCache {
+    opts: Options,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Entry<T> {
+    fetched: DateTime<Utc>,
+    data: T,
+}
+
+pub const MAX_AGE_DEFAULT: StdDuration = StdDuration::from_secs(60 * 60);
+
+impl Options {
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+}
+
+impl MetadataCache {
+    pub fn new(opts: Options) -> MetadataCache {
+        MetadataCache {
+            opts,
+        }
+    }
+
+    /// Get the metadata cached at `rel_path`, or call `fetch` and cache the result.
+    pub(crate) async fn get_or_fetch<T, F, Fut>(&self, rel_path: &str, fetch: F) -> Result<T>
+    where T: DeserializeOwned + Serialize,
+          F: FnOnce() -> Fut,
+          Fut: Future<Output = Result<T>>,
+    {
+        let path = self.opts.path.join(format!("{rel_path}.json"));
+
+        let cached = if self.opts.refresh {
+            None
+        } else {
+            read_entry::<T>(&path)?
+        };
+
+        let cached = match cached {
+            Some(entry) => {
+                let age = Utc::now().signed_duration_since(entry.fetched)
+                                    .to_std()
+                                    .unwrap_or(StdDuration::ZERO);
+                if age <= self.opts.max_age {
+                    tracing::debug!(path = %path.display(), age = ?age,
+                                    "Using cached dump metadata");
+                    return Ok(entry.data);
+                }
+                Some((entry, age))
+            },
+            None => None,
+        };
+
+        match fetch().await {
+            Ok(data) => {
+                let entry = Entry {
+                    fetched: Utc::now(),
+                    data,
+                };
+                write_entry(&path, &entry)?;
+                Ok(entry.data)
+            },
+            Err(e) => match cached {
+                Some((entry, age)) => {
+                    tracing::warn!(err = format!("{e:#}"), path = %path.display(), age = ?age,
+                                   "Error fetching dump metadata, using stale cached metadata");
+                    Ok(entry.data)
+                },
+                None => Err(e),
+            },
+        }
+    }
+}
+
+fn read_entry<T: DeserializeOwned>(path: &Path) -> Result<Option<Entry<T>>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(
+            || format!("reading metadata cache file path='{}'", path.display())),
+    };
+
+    match serde_json::from_slice::<Entry<T>>(&bytes) {
+        Ok(entry) => Ok(Some(entry)),
+        Err(e) => {
+            // Probably written by an older version with a different format, so fetch again.
+            tracing::warn!(err = %e, path = %path.display(),
+                           "Ignoring metadata cache file that couldn't be parsed");
+            Ok(None)
+        }
+    }
+}
+
+fn write_entry<T: Serialize>(path: &Path, entry: &Entry<T>) -> Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+
+    // Closure to add context to errors.
+    (|| {
+        fs::create_dir_all(path.parent().expect("parent of metadata cache file path"))?;
+        fs::write(&temp_path, serde_json::to_vec_pretty(entry)?)?;
+        fs::rename(&temp_path, path)?;
+        anyhow::Ok(())
+    })().with_context(|| format!("writing metadata cache file path='{}'", path.display()))
+}
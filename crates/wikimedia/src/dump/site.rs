@@ -0,0 +1,188 @@
+use crate::{dump::DumpName, slug};
+use serde::{Deserialize, Serialize};
+
+/// Where a dump's pages are published on the web.
+///
+/// Used to link to the live version of a page and to resolve interwiki links.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SiteInfo {
+    /// The site's URL without a trailing slash, e.g. `https://en.wiktionary.org`.
+    pub site_url: String,
+
+    /// The site's language code, e.g. `en`. `None` for multilingual sites such as Wikidata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// The path of an article under `site_url`, with `$1` in place of the page slug,
+    /// e.g. `/wiki/$1`. This is the same format as MediaWiki's `$wgArticlePath`.
+    pub article_path: String,
+}
+
+/// Wikimedia projects named `{language}{suffix}` in dump names, e.g. `frwiktionary`.
+/// Ordered so that longer suffixes are tried before `wiki`.
+const LANGUAGE_PROJECTS: &[(&str, &str)] = &[
+    ("wikibooks", "wikibooks.org"),
+    ("wikinews", "wikinews.org"),
+    ("wikiquote", "wikiquote.org"),
+    ("wikisource", "wikisource.org"),
+    ("wikiversity", "wikiversity.org"),
+    ("wikivoyage", "wikivoyage.org"),
+    ("wiktionary", "wiktionary.org"),
+    ("wiki", "wikipedia.org"),
+];
+
+/// Wikimedia projects without a language in their dump name.
+const SINGLE_PROJECTS: &[(&str, &str)] = &[
+    ("commonswiki", "commons.wikimedia.org"),
+    ("mediawikiwiki", "www.mediawiki.org"),
+    ("metawiki", "meta.wikimedia.org"),
+    ("specieswiki", "species.wikimedia.org"),
+    ("wikidatawiki", "www.wikidata.org"),
+];
+
+/// Interwiki prefixes that link to a Wikimedia project, with the project's suffix in
+/// [`LANGUAGE_PROJECTS`] or dump name in [`SINGLE_PROJECTS`].
+const INTERWIKI_PREFIXES: &[(&str, &str)] = &[
+    ("b", "wikibooks"),
+    ("commons", "commonswiki"),
+    ("d", "wikidatawiki"),
+    ("m", "metawiki"),
+    ("meta", "metawiki"),
+    ("mw", "mediawikiwiki"),
+    ("n", "wikinews"),
+    ("q", "wikiquote"),
+    ("s", "wikisource"),
+    ("species", "specieswiki"),
+    ("v", "wikiversity"),
+    ("voy", "wikivoyage"),
+    ("w", "wiki"),
+    ("wikibooks", "wikibooks"),
+    ("wikidata", "wikidatawiki"),
+    ("wikinews", "wikinews"),
+    ("wikipedia", "wiki"),
+    ("wikiquote", "wikiquote"),
+    ("wikisource", "wikisource"),
+    ("wikiversity", "wikiversity"),
+    ("wikivoyage", "wikivoyage"),
+    ("wikt", "wiktionary"),
+    ("wiktionary", "wiktionary"),
+];
+
+const DEFAULT_ARTICLE_PATH: &str = "/wiki/$1";
+
+impl SiteInfo {
+    /// The site for a Wikimedia dump name, e.g. `enwiki` or `frwiktionary`.
+    ///
+    /// Returns `None` if the dump name is not a recognised Wikimedia project.
+    pub fn default_for_dump(dump: &DumpName) -> Option<SiteInfo> {
+        let name = &*dump.0;
+
+        if let Some((_, host)) = SINGLE_PROJECTS.iter().find(|(dump, _)| *dump == name) {
+            return Some(SiteInfo::new(host, None));
+        }
+
+        let (language, domain) = LANGUAGE_PROJECTS.iter().find_map(|(suffix, domain)| {
+            let language = name.strip_suffix(suffix)?;
+            Some((language, domain))
+        })?;
+
+        if language.is_empty()
+            || !language.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+        {
+            return None;
+        }
+
+        // Dump names use '_' where language codes use '-', e.g. `zh_min_nanwiki`.
+        let language = language.replace('_', "-");
+        Some(SiteInfo::new(&format!("{language}.{domain}"), Some(language)))
+    }
+
+    fn new(host: &str, language: Option<String>) -> SiteInfo {
+        SiteInfo {
+            site_url: format!("https://{host}"),
+            language,
+            article_path: DEFAULT_ARTICLE_PATH.to_string(),
+        }
+    }
+
+    /// The URL of the page with `title` on this site.
+    pub fn page_url(&self, title: &str) -> String {
+        format!("{site_url}{path}",
+                site_url = self.site_url,
+                path = self.article_path.replace("$1", &slug::title_to_slug(title)))
+    }
+
+    /// Interwiki link prefixes (e.g. `wikt`) mapped to a URL prefix to append a slug to.
+    ///
+    /// Projects with a language use this site's language, so there are none for a site
+    /// without a language.
+    pub fn interwiki_url_prefixes(&self) -> Vec<(&'static str, String)> {
+        INTERWIKI_PREFIXES.iter().filter_map(|(prefix, project)| {
+            let site = match SINGLE_PROJECTS.iter().find(|(dump, _)| dump == project) {
+                Some((_, host)) => SiteInfo::new(host, None),
+                None => {
+                    let language = self.language.as_ref()?;
+                    let dump = DumpName(format!("{}{project}", language.replace('-', "_")));
+                    SiteInfo::default_for_dump(&dump)?
+                }
+            };
+            let url_prefix = site.page_url("$1").replace("$1", "");
+            Some((*prefix, url_prefix))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(dump: &str) -> Option<SiteInfo> {
+        SiteInfo::default_for_dump(&DumpName(dump.to_string()))
+    }
+
+    #[test]
+    fn default_for_dump() {
+        let en = site("enwiki").unwrap();
+        assert_eq!(en.site_url, "https://en.wikipedia.org");
+        assert_eq!(en.language.as_deref(), Some("en"));
+
+        assert_eq!(site("simplewiki").unwrap().site_url, "https://simple.wikipedia.org");
+        assert_eq!(site("frwiktionary").unwrap().site_url, "https://fr.wiktionary.org");
+        assert_eq!(site("enwikivoyage").unwrap().site_url, "https://en.wikivoyage.org");
+        assert_eq!(site("zh_min_nanwiki").unwrap().site_url,
+                   "https://zh-min-nan.wikipedia.org");
+
+        let wikidata = site("wikidatawiki").unwrap();
+        assert_eq!(wikidata.site_url, "https://www.wikidata.org");
+        assert_eq!(wikidata.language, None);
+
+        assert_eq!(site("wiki"), None);
+        assert_eq!(site("not-a-dump"), None);
+    }
+
+    #[test]
+    fn page_url() {
+        assert_eq!(site("enwiki").unwrap().page_url("Category:Living people"),
+                   "https://en.wikipedia.org/wiki/Category:Living_people");
+
+        let custom = SiteInfo {
+            site_url: "https://example.com".to_string(),
+            language: None,
+            article_path: "/index.php?title=$1".to_string(),
+        };
+        assert_eq!(custom.page_url("Main Page"), "https://example.com/index.php?title=Main_Page");
+    }
+
+    #[test]
+    fn interwiki_url_prefixes() {
+        let prefixes = site("dewikivoyage").unwrap().interwiki_url_prefixes();
+        let get = |prefix: &str| prefixes.iter().find(|(p, _)| *p == prefix)
+                                         .map(|(_, url)| &**url);
+        assert_eq!(get("wikt"), Some("https://de.wiktionary.org/wiki/"));
+        assert_eq!(get("d"), Some("https://www.wikidata.org/wiki/"));
+
+        let wikidata_prefixes = site("wikidatawiki").unwrap().interwiki_url_prefixes();
+        assert!(wikidata_prefixes.iter().all(|(p, _)| *p != "w"));
+        assert!(wikidata_prefixes.iter().any(|(p, _)| *p == "commons"));
+    }
+}
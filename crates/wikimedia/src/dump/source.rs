@@ -0,0 +1,140 @@
+//! Where dump job files are downloaded from.
+//!
+//! [`WikimediaSource`] reads job metadata from the Wikimedia dumps server and downloads files
+//! from it or a mirror. [`UrlTemplateSource`] downloads files from any other server, e.g. a
+//! self-hosted MediaWiki's XML exports, given a URL layout and the file names.
+
+use anyhow::{bail, format_err};
+use chrono::Utc;
+use crate::{
+    dump::{
+        download::{self, FileInfos, MetadataClient},
+        DumpName, FileMetadata, JobName, Status, Version, VersionSpec,
+    },
+    Result,
+    UserRegex,
+};
+use futures::future::{BoxFuture, FutureExt};
+use std::fmt::Debug;
+
+/// A server to download dump job files from.
+pub trait DumpSource: Debug + Send + Sync {
+    /// Get the version and files of a job.
+    ///
+    /// Returns an error if the job is not done, unless `allow_incomplete_job` is true.
+    fn get_file_infos<'a>(
+        &'a self,
+        client: &'a MetadataClient,
+        dump_name: &'a DumpName,
+        version_spec: &'a VersionSpec,
+        job_name: &'a JobName,
+        file_name_regex: Option<&'a UserRegex>,
+        allow_incomplete_job: bool,
+    ) -> BoxFuture<'a, Result<FileInfos>>;
+
+    /// The absolute URL to download a file returned by `get_file_infos` from.
+    fn file_url(&self, file_meta: &FileMetadata) -> Result<String>;
+}
+
+/// The Wikimedia dumps server, with job files optionally downloaded from a mirror.
+#[derive(Clone, Debug)]
+pub struct WikimediaSource {
+    /// The URL to download job files from, e.g. `https://dumps.wikimedia.org`.
+    ///
+    /// Metadata is always fetched from the Wikimedia dumps server to get the freshest data.
+    pub mirror_url: String,
+}
+
+/// A server with a fixed URL layout and no job metadata.
+///
+/// Nothing is known about files before they are downloaded except the server's HTTP headers,
+/// so they can't be checked against an expected hash.
+#[derive(Clone, Debug)]
+pub struct UrlTemplateSource {
+    /// The URL of a job file, with placeholders `{dump}`, `{version}`, `{job}` and `{file}`,
+    /// e.g. `https://wiki.example.com/dumps/{file}`.
+    pub url_template: String,
+
+    /// The names of the job's files.
+    pub file_names: Vec<String>,
+}
+
+impl Default for WikimediaSource {
+    fn default() -> WikimediaSource {
+        WikimediaSource {
+            mirror_url: download::DUMPS_WIKIMEDIA_SERVER.to_string(),
+        }
+    }
+}
+
+impl DumpSource for WikimediaSource {
+    fn get_file_infos<'a>(
+        &'a self,
+        client: &'a MetadataClient,
+        dump_name: &'a DumpName,
+        version_spec: &'a VersionSpec,
+        job_name: &'a JobName,
+        file_name_regex: Option<&'a UserRegex>,
+        allow_incomplete_job: bool,
+    ) -> BoxFuture<'a, Result<FileInfos>> {
+        download::get_file_infos(client, dump_name, version_spec, job_name, file_name_regex,
+                                 allow_incomplete_job).boxed()
+    }
+
+    fn file_url(&self, file_meta: &FileMetadata) -> Result<String> {
+        let rel_url = file_meta.url.as_deref().ok_or_else(|| format_err!(
+            "File URL missing. Usually this is because the parent job has status 'waiting'."))?;
+        download::validate_file_relative_url(rel_url)?;
+        Ok(format!("{mirror_url}{rel_url}", mirror_url = self.mirror_url))
+    }
+}
+
+impl DumpSource for UrlTemplateSource {
+    fn get_file_infos<'a>(
+        &'a self,
+        _client: &'a MetadataClient,
+        dump_name: &'a DumpName,
+        version_spec: &'a VersionSpec,
+        job_name: &'a JobName,
+        file_name_regex: Option<&'a UserRegex>,
+        _allow_incomplete_job: bool,
+    ) -> BoxFuture<'a, Result<FileInfos>> {
+        let version = match version_spec {
+            VersionSpec::Version(version) => version.clone(),
+            // There's no list of versions, so each day's download is its own version.
+            VersionSpec::Latest => Version(Utc::now().format("%Y%m%d").to_string()),
+        };
+
+        let files = self.file_names.iter()
+            .filter(|name| file_name_regex.is_none_or(|UserRegex(re)| re.is_match(name)))
+            .map(|name| {
+                let url = self.url_template.replace("{dump}", &dump_name.0)
+                                           .replace("{version}", &version.0)
+                                           .replace("{job}", &job_name.0)
+                                           .replace("{file}", name);
+                (name.clone(), FileMetadata {
+                    size: None,
+                    url: Some(url),
+                    sha1: None,
+                    md5: None,
+                })
+            })
+            .collect::<Vec<(String, FileMetadata)>>();
+
+        futures::future::ok(FileInfos {
+            version,
+            job_status: Status::Done,
+            files,
+        }).boxed()
+    }
+
+    fn file_url(&self, file_meta: &FileMetadata) -> Result<String> {
+        let Some(url) = file_meta.url.as_ref() else {
+            bail!("File URL missing.");
+        };
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            bail!("File URL must be http: or https: url='{url}'");
+        }
+        Ok(url.clone())
+    }
+}
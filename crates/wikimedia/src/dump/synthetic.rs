@@ -0,0 +1,164 @@
+//! Generate synthetic dump files, for benchmarks and tests that need dumps of a chosen size.
+//!
+//! The pages are generated from a seeded random number generator, so the same options always
+//! generate the same dump. Their wikitext has paragraphs of random words with links to other
+//! pages and category links, and some pages are redirects, so importing them exercises the
+//! same parser, chunk, and index code as a real dump.
+
+use crate::Result;
+use rand::{
+    Rng,
+    rngs::StdRng,
+    SeedableRng,
+};
+use std::io::Write;
+use valuable::Valuable;
+
+/// What to generate, see [`write_dump`].
+#[derive(Clone, Debug, Valuable)]
+pub struct Options {
+    /// The number of pages to generate.
+    pub pages: u64,
+
+    /// The approximate length in bytes of each article's wikitext.
+    pub text_len: usize,
+
+    /// The number of distinct categories that pages are linked to.
+    pub categories: u64,
+
+    /// One in this many pages is a redirect to another page, or none if 0.
+    pub redirect_interval: u64,
+
+    /// The random number generator seed.
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            pages: 10_000,
+            text_len: 4_000,
+            categories: 100,
+            redirect_interval: 20,
+            seed: 0,
+        }
+    }
+}
+
+const WORDS: &[&str] = &[
+    "the", "of", "and", "in", "was", "is", "for", "as", "on", "with", "by", "he", "she", "at",
+    "from", "his", "her", "an", "were", "are", "which", "this", "also", "be", "has", "had",
+    "first", "one", "their", "its", "new", "after", "who", "they", "two", "city", "river",
+    "album", "season", "school", "team", "station", "village", "county", "species", "film",
+    "music", "history", "war", "church", "island", "league", "party", "railway", "district",
+];
+
+/// Write a synthetic uncompressed dump to `out`.
+pub fn write_dump(out: &mut impl Write, options: &Options) -> Result<()> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    writeln!(out, r#"<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.10/" version="0.10" xml:lang="en">
+  <siteinfo>
+    <sitename>Synthetic</sitename>
+    <dbname>syntheticwiki</dbname>
+    <namespaces>
+      <namespace key="0" case="first-letter" />
+      <namespace key="14" case="first-letter">Category</namespace>
+    </namespaces>
+  </siteinfo>"#)?;
+
+    let mut text = String::with_capacity(options.text_len + 200);
+    for page_idx in 0..options.pages {
+        text.clear();
+        let is_redirect = options.redirect_interval > 0
+                          && page_idx % options.redirect_interval == options.redirect_interval - 1;
+        if is_redirect {
+            text.push_str(&format!("#REDIRECT [[Page {}]]", rng.gen_range(0..options.pages)));
+        } else {
+            write_article_text(&mut text, &mut rng, options);
+        }
+
+        writeln!(out, r#"  <page>
+    <title>Page {page_idx}</title>
+    <ns>0</ns>
+    <id>{id}</id>
+    <revision>
+      <id>{revision_id}</id>
+      <timestamp>2023-01-01T00:00:00Z</timestamp>
+      <contributor><username>Synthetic</username><id>1</id></contributor>
+      <model>wikitext</model>
+      <format>text/x-wiki</format>
+      <text bytes="{text_len}" xml:space="preserve">{text}</text>
+    </revision>
+  </page>"#,
+                 id = page_idx + 1,
+                 revision_id = page_idx + 1_000_000,
+                 text_len = text.len(),
+                 text = html_escape::encode_text(&text))?;
+    }
+
+    writeln!(out, "</mediawiki>")?;
+    Ok(())
+}
+
+fn write_article_text(text: &mut String, rng: &mut StdRng, options: &Options) {
+    let mut words_in_paragraph = 0;
+    while text.len() < options.text_len {
+        if words_in_paragraph > 0 {
+            text.push(' ');
+        }
+        if options.pages > 0 && rng.gen_ratio(1, 20) {
+            text.push_str(&format!("[[Page {}]]", rng.gen_range(0..options.pages)));
+        } else {
+            text.push_str(WORDS[rng.gen_range(0..WORDS.len())]);
+        }
+        words_in_paragraph += 1;
+
+        if words_in_paragraph >= 80 {
+            text.push_str(".\n\n");
+            words_in_paragraph = 0;
+        }
+    }
+
+    for _ in 0..rng.gen_range(1..=3) {
+        if options.categories == 0 {
+            break;
+        }
+        text.push_str(&format!("\n[[Category:Group {}]]", rng.gen_range(0..options.categories)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::local::{parse_pages_from_reader, ParseLimits};
+
+    #[test]
+    fn parses() {
+        let options = Options {
+            pages: 40,
+            text_len: 500,
+            ..Options::default()
+        };
+        let mut xml = Vec::new();
+        write_dump(&mut xml, &options).unwrap();
+
+        let pages = parse_pages_from_reader(&*xml, ParseLimits::default())
+                        .collect::<Result<Vec<_>>>()
+                        .unwrap();
+        assert_eq!(pages.len(), 40);
+        assert_eq!(pages[0].title, "Page 0");
+        assert_eq!(pages[39].id, 40);
+
+        let revision = pages[0].revision.as_ref().unwrap();
+        assert!(revision.text.as_ref().unwrap().len() >= 500);
+        assert!(!revision.categories.is_empty());
+
+        let redirect_text = pages[19].revision.as_ref().unwrap().text.as_ref().unwrap();
+        assert!(redirect_text.starts_with("#REDIRECT [[Page "));
+
+        let mut again = Vec::new();
+        write_dump(&mut again, &options).unwrap();
+        assert_eq!(xml, again);
+    }
+}
@@ -0,0 +1,121 @@
+//! Progress events from long running operations, such as downloading a job's files and
+//! importing them into a store, e.g. for a live dashboard.
+//!
+//! Events are sent on a broadcast channel that never blocks the sender, so a slow subscriber
+//! can't hold up a download or an import. Instead a subscriber that falls more than
+//! [`CAPACITY`] events behind misses the oldest ones, and its next receive returns how many
+//! it missed.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+pub use broadcast::{error::RecvError, Receiver};
+
+/// The count of events buffered for each subscriber before the oldest are dropped.
+pub const CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event {
+    /// Downloading a job's files started.
+    DownloadStarted {
+        dump: String,
+        version: String,
+        job: String,
+        files_len: usize,
+        expected_bytes: u64,
+    },
+
+    /// A job file was downloaded and verified, or an existing copy was found to be OK.
+    FileVerified {
+        path: String,
+        bytes: u64,
+
+        /// False if an existing copy was used.
+        downloaded: bool,
+    },
+
+    /// Importing into a store started.
+    ImportStarted {
+        dump: String,
+        source_files_len: usize,
+        source_bytes: u64,
+    },
+
+    /// A chunk of pages was committed to a store during an import.
+    ChunkCommitted {
+        dump: String,
+        chunk_id: u64,
+        pages_len: u64,
+
+        /// Pages imported so far, including this chunk's.
+        pages_total: u64,
+
+        source_bytes_read: u64,
+        source_bytes: u64,
+    },
+
+    /// An import finished, successfully or not.
+    ImportFinished {
+        dump: String,
+        pages_total: u64,
+        duration_secs: f64,
+
+        /// The error that stopped the import, if any.
+        error: Option<String>,
+    },
+}
+
+/// Sends [`Event`]s to every current subscriber. Clones send to the same subscribers.
+#[derive(Clone, Debug)]
+pub struct Sender(broadcast::Sender<Event>);
+
+impl Sender {
+    pub fn new() -> Sender {
+        let (sender, _receiver) = broadcast::channel(CAPACITY);
+        Sender(sender)
+    }
+
+    /// Send an event to the current subscribers without blocking. The event is dropped if
+    /// there are none.
+    pub fn send(&self, event: Event) {
+        let _ = self.0.send(event);
+    }
+
+    /// Receive the events sent from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for Sender {
+    fn default() -> Sender {
+        Sender::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lagging_subscriber_misses_oldest() {
+        let sender = Sender::new();
+        let mut receiver = sender.subscribe();
+        for chunk_id in 0..(CAPACITY as u64 + 2) {
+            sender.send(Event::ChunkCommitted {
+                dump: "simplewiki".to_string(),
+                chunk_id,
+                pages_len: 1,
+                pages_total: chunk_id + 1,
+                source_bytes_read: 0,
+                source_bytes: 0,
+            });
+        }
+
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(2))));
+        let event = receiver.recv().await.unwrap();
+        assert!(matches!(event, Event::ChunkCommitted { chunk_id: 2, .. }));
+        assert_eq!(serde_json::to_value(&event).unwrap()["type"], "chunk-committed");
+    }
+}
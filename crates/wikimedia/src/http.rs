@@ -1,9 +1,13 @@
 //! Shared code for making HTTP requests
 
-use anyhow::{bail, Context, format_err};
+use anyhow::{Context, format_err};
 use crate::{
+    bail_kind,
     Result,
-    util::fmt::{self, Bytes, Duration, Sha1Hash, TransferStats},
+    util::{
+        fmt::{self, Bytes, Sha1Hash, TransferStats},
+        progress,
+    },
 };
 use derive_builder::Builder;
 use encoding_rs::{Encoding, UTF_8};
@@ -42,6 +46,21 @@ pub struct FetchTextResult {
     pub stats: TransferStats,
 }
 
+/// The response to an HTTP HEAD request, from [`fetch_head`].
+#[derive(Clone, Debug)]
+pub struct HeadResult {
+    pub response_code: StatusCode,
+
+    /// The `Content-Length` header, if present.
+    pub content_len: Option<Bytes>,
+
+    /// The `ETag` header, if present.
+    pub etag: Option<String>,
+
+    /// The `Last-Modified` header, if present.
+    pub last_modified: Option<String>,
+}
+
 #[derive(Clone, Copy)]
 pub struct StatusCode(pub reqwest::StatusCode);
 
@@ -194,14 +213,16 @@ pub async fn download_file(
                         "http::download_file() response HTTP status");
 
         if !download_res_code.0.is_success() {
-            bail!("HTTP response error code \
-                   response_code={download_res_code:?}");
+            bail_kind!(Network, "HTTP response error code \
+                                 response_code={download_res_code:?}");
         }
 
         let mut bytes_stream = download_res.bytes_stream();
         let mut sha1_hasher = Sha1::new();
         let mut bytes_written: u64 = 0;
         let mut last_progress_update = chrono::Utc::now();
+        let mut progress_tracker = progress::Tracker::new_at(start_time,
+                                                             expected_len.map(|len| len.0));
 
         let progress_interval = chrono::Duration::seconds(2);
 
@@ -218,7 +239,7 @@ pub async fn download_file(
             let now = chrono::Utc::now();
             let next_update_due = last_progress_update + progress_interval;
             if next_update_due < now {
-                print_download_progress(bytes_written, start_time, expected_len);
+                print_download_progress(progress_tracker.update(bytes_written));
                 last_progress_update = now;
             }
         } // end of while bytes_chunk = next().
@@ -253,41 +274,17 @@ pub async fn download_file(
                                        file_path = file_path.display()))
 }
 
-fn print_download_progress(
-    bytes_written: u64,
-    start_time: Instant,
-    expected_len: Option<Bytes>,
-) {
+fn print_download_progress(progress: progress::Progress) {
     let now = chrono::Local::now();
 
     let now_str = now.to_rfc3339_opts(chrono::SecondsFormat::Secs,
                                       true /* use_z */);
 
-    let bytes_written_str = Bytes(bytes_written).to_string();
-    let duration_so_far = start_time.elapsed();
-
-    let estimate_str = match expected_len {
-        Some(len) if len.0 > 0 && bytes_written > 0 => {
-            let bytes_remaining = Bytes(len.0 - bytes_written);
-
-            let percent_complete =
-                ((bytes_written as f64) / (len.0 as f64)) * 100.0;
-            let percent_complete_str = format!("{percent_complete:3.1}%");
+    let bytes_written_str = Bytes(progress.done).to_string();
 
-            let remaining_secs: f64 =
-                (duration_so_far.as_secs_f64() / (bytes_written as f64))
-                * (bytes_remaining.0 as f64);
-            let remaining_nanos = remaining_secs * 1_000_000_000.0;
-            let remaining =
-                StdDuration::from_nanos(remaining_nanos as u64);
-            let remaining_str = Duration(remaining).to_string();
-
-            let eta = chrono::Duration::from_std(remaining)
-                          .ok().map(|dur| now + dur);
-            let eta_str = match eta {
-                Some(eta) => fmt::chrono_time(eta),
-                None => "".to_string(),
-            };
+    let estimate_str = match (progress.percent_str(), progress.remaining_str()) {
+        (Some(percent_complete_str), Some(remaining_str)) => {
+            let eta_str = progress.eta(now).map(fmt::chrono_time).unwrap_or_default();
 
             format!("{percent_complete_str:>6}   \
                      remaining: {remaining_str:>16}   \
@@ -364,8 +361,8 @@ pub async fn fetch_text(
         }
 
         if !res_code.0.is_success() {
-            bail!("HTTP response code error \
-                   response_code={res_code:?}");
+            bail_kind!(Network, "HTTP response code error \
+                                 response_code={res_code:?}");
         }
 
         let duration = start_time.elapsed();
@@ -391,3 +388,123 @@ pub async fn fetch_text(
                                         url='{url}' \
                                         method={method}"))
 }
+
+/// Fetch the headers of a resource with an HTTP HEAD request.
+///
+/// Returns `Ok(None)` if the server doesn't support HEAD requests, i.e. responds with 405
+/// Method Not Allowed or 501 Not Implemented. Other error response codes return an error.
+#[tracing::instrument(level = "trace", skip(client))]
+pub async fn fetch_head(
+    client: &Client,
+    url: &str,
+) -> Result<Option<HeadResult>> {
+    // Async block to add context to errors.
+    async {
+        let request = client.head(url).build()?;
+        let response = client.execute(request).await?;
+
+        let response_code = StatusCode(response.status());
+        tracing::debug!(url,
+                        response_code = response_code.as_value(),
+                        "http::fetch_head() response HTTP status");
+
+        if matches!(response_code.0, reqwest::StatusCode::METHOD_NOT_ALLOWED
+                                     | reqwest::StatusCode::NOT_IMPLEMENTED) {
+            return Ok(None);
+        }
+
+        if !response_code.0.is_success() {
+            bail_kind!(Network, "HTTP response error code \
+                                 response_code={response_code:?}");
+        }
+
+        let headers = response.headers();
+        let header_str = |name: reqwest::header::HeaderName| -> Option<String> {
+            headers.get(name)
+                   .and_then(|value| value.to_str().ok())
+                   .map(|value| value.to_string())
+        };
+
+        // Read the header rather than `response.content_length()`, which is the length of the
+        // (empty) body of a HEAD response.
+        let content_len = match header_str(reqwest::header::CONTENT_LENGTH) {
+            None => None,
+            Some(len) => Some(Bytes(len.trim().parse::<u64>().with_context(
+                || format!("parsing Content-Length header value='{len}'"))?)),
+        };
+
+        Ok(Some(HeadResult {
+            response_code,
+            content_len,
+            etag: header_str(reqwest::header::ETAG),
+            last_modified: header_str(reqwest::header::LAST_MODIFIED),
+        }))
+    }.await.with_context(|| format!("while fetching HTTP headers with a HEAD request \
+                                     url='{url}'"))
+}
+
+/// Fetch the byte range `start .. start + len` of a resource with an HTTP range request.
+///
+/// Returns `Ok(None)` if the server responds with 404 Not Found. The server must support
+/// range requests, i.e. respond with 206 Partial Content, otherwise an error is returned.
+#[tracing::instrument(
+    level = "trace",
+    skip(client),
+    fields(len = %Bytes(len)))]
+pub async fn fetch_range(
+    client: &Client,
+    url: &str,
+    start: u64,
+    len: u64,
+) -> Result<Option<Vec<u8>>> {
+    let start_time = Instant::now();
+
+    // Async block to add context to errors.
+    async {
+        if len == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let request = client.get(url)
+                            .header(reqwest::header::RANGE,
+                                    format!("bytes={start}-{end}", end = start + len - 1))
+                            .build()?;
+        let response = client.execute(request).await?;
+
+        let res_code = StatusCode(response.status());
+        tracing::debug!(url,
+                        response_code = res_code.as_value(),
+                        "HTTP response headers");
+
+        if res_code.0 == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if res_code.0 != reqwest::StatusCode::PARTIAL_CONTENT {
+            bail_kind!(Network,
+                       "HTTP response code was not 206 Partial Content, \
+                        the server may not support range requests. \
+                        response_code={res_code:?}");
+        }
+
+        let body = response.bytes().await?;
+        if u64::try_from(body.len()).expect("usize into u64") != len {
+            bail_kind!(Network,
+                       "HTTP range response body was the wrong length \
+                        expected_len={expected_len} actual_len={actual_len}",
+                       expected_len = Bytes(len),
+                       actual_len = Bytes(body.len().try_into().expect("usize into u64")));
+        }
+
+        let stats = TransferStats::new(Bytes(len), start_time.elapsed());
+        tracing::debug!(url,
+                        start,
+                        stats = stats.as_value(),
+                        "http::fetch_range() complete");
+
+        Ok(Some(body.to_vec()))
+    }.await.with_context(|| format!("while fetching HTTP byte range \
+                                     url='{url}' \
+                                     start={start} \
+                                     len={len}"))
+}
@@ -4,24 +4,53 @@
     iterator_try_reduce,
 )]
 
-// These sub-modules are imported first to import their macros.
+// Import the `lazy_regex!` macro, and the `bail_kind!` and `format_err_kind!` macros
+// re-exported below.
 #[macro_use]
-mod lazy_regex;
+extern crate wikimedia_types;
+
+// This sub-module is imported first to import its macros.
 #[macro_use]
 pub mod util;
 
 // The rest of these sub-modules are in alphabetical order.
 mod progress_reader;
+pub mod cancel;
 pub mod dump;
+pub mod events;
+#[cfg(feature = "download")]
 pub mod http;
-pub mod slug;
+#[cfg(feature = "download")]
+pub mod live;
 mod temp_dir;
 mod user_regex;
+pub mod web_api;
 pub mod wikitext;
 
+pub use wikimedia_types::{
+    bail_kind,
+    ErrorKind,
+    format_err_kind,
+    KindError,
+    lazy_regex,
+    slug,
+};
 pub use progress_reader::ProgressReader;
 pub use temp_dir::TempDir;
 pub use user_regex::UserRegex;
 
 pub type Error = anyhow::Error;
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// This crate's version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The names of this crate's enabled cargo features.
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "download")]
+    "download",
+    #[cfg(feature = "fuzzing")]
+    "fuzzing",
+    #[cfg(feature = "wikitext-render")]
+    "wikitext-render",
+];
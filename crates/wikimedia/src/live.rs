@@ -0,0 +1,103 @@
+//! Read pages from a live MediaWiki site with its REST API, e.g. pages missing from a store.
+//!
+//! See <https://www.mediawiki.org/wiki/API:REST_API/Reference#Get_page_source>.
+
+use anyhow::{Context, format_err};
+use chrono::DateTime;
+use crate::{
+    bail_kind,
+    dump::{Namespace, Page, Revision, SiteInfo},
+    http,
+    Result,
+    slug,
+    wikitext,
+};
+use serde::Deserialize;
+
+/// A client for one site's REST API.
+#[derive(Clone)]
+pub struct Client {
+    api_url: reqwest::Url,
+    http: http::Client,
+}
+
+/// The response to `GET /v1/page/{title}`.
+#[derive(Deserialize)]
+struct RestPage {
+    id: u64,
+    title: String,
+    latest: RestRevision,
+    source: String,
+}
+
+#[derive(Deserialize)]
+struct RestRevision {
+    id: u64,
+    timestamp: String,
+}
+
+impl Client {
+    /// A client for the REST API at `api_url`, e.g. `https://en.wikipedia.org/w/rest.php`.
+    pub fn new(api_url: &str, http_options: &http::Options) -> Result<Client> {
+        let api_url = reqwest::Url::parse(api_url)
+            .with_context(|| format!("parsing REST API URL url='{api_url}'"))?;
+        if api_url.cannot_be_a_base() {
+            bail_kind!(User, "The REST API URL must be an http: or https: URL url='{api_url}'");
+        }
+
+        Ok(Client {
+            api_url,
+            http: http::metadata_client(http_options)?,
+        })
+    }
+
+    /// A client for the REST API of `site`, at the path Wikimedia sites serve it from.
+    pub fn for_site(site: &SiteInfo, http_options: &http::Options) -> Result<Client> {
+        Client::new(&format!("{site_url}/w/rest.php", site_url = site.site_url), http_options)
+    }
+
+    /// Get the latest revision of the page titled `title`, or `None` if the site doesn't
+    /// have it.
+    ///
+    /// The page's namespace is found from its title's prefix, and its categories are parsed
+    /// from its wikitext, as for a page in a dump.
+    #[tracing::instrument(level = "debug", skip(self), fields(api_url = %self.api_url))]
+    pub async fn get_page(&self, title: &str) -> Result<Option<Page>> {
+        let mut url = self.api_url.clone();
+        url.path_segments_mut()
+           .map_err(|()| format_err!("REST API URL cannot be a base url='{}'", self.api_url))?
+           .pop_if_empty()
+           .extend(["v1", "page", &*slug::title_to_slug(title)]);
+
+        let response = self.http.get(url.clone()).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            bail_kind!(Network, "HTTP response code error from REST API url='{url}' \
+                                 response_code={code}",
+                       code = response.status());
+        }
+
+        let body = response.text().await?;
+        let rest_page = serde_json::from_str::<RestPage>(&body)
+            .with_context(|| format!("parsing REST API page response url='{url}'"))?;
+        let timestamp = DateTime::parse_from_rfc3339(&rest_page.latest.timestamp)
+            .with_context(|| format!("parsing REST API revision timestamp url='{url}'"))?;
+
+        Ok(Some(Page {
+            ns_id: Namespace::from_page_title(&rest_page.title).key(),
+            id: rest_page.id,
+            revision: Some(Revision {
+                id: rest_page.latest.id,
+                parent_id: None,
+                timestamp: Some(timestamp),
+                categories: wikitext::parse_categories(&rest_page.source, &[]),
+                text: Some(rest_page.source),
+                sha1: None,
+            }),
+            title: rest_page.title,
+            restrictions: None,
+        }))
+    }
+}
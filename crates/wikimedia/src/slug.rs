@@ -1,3 +0,0 @@
-pub fn title_to_slug(title: &str) -> String {
-    title.replace(' ', "_")
-}
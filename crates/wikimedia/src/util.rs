@@ -1,7 +1,9 @@
 mod collections;
 pub use collections::{IteratorExt, IteratorExtLocal, IteratorExtSend};
 
-pub mod fmt;
+pub use wikimedia_types::fmt;
+
+pub mod progress;
 
 pub mod rand;
 
@@ -0,0 +1,260 @@
+//! Track the progress of a long-running task, such as a download or an import, to estimate
+//! how long it has left.
+//!
+//! A [`Tracker`] is updated with the units done so far (usually bytes) and returns a
+//! [`Progress`] snapshot with the percentage complete, throughput, time remaining and ETA.
+//! The rate used for estimates is smoothed with an exponentially weighted moving average,
+//! so estimates follow changes in speed without jumping around on every update.
+
+use crate::util::fmt;
+use std::time::{Duration as StdDuration, Instant};
+
+/// The weight of the newest rate sample in the smoothed rate, see [`Tracker::smoothing`].
+pub const SMOOTHING_DEFAULT: f64 = 0.3;
+
+/// Tracks progress towards an optional total. See the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    start: Instant,
+    total: Option<u64>,
+    smoothing: f64,
+
+    done: u64,
+    last_sample: Instant,
+    last_sample_done: u64,
+
+    /// Units per second.
+    smoothed_rate: Option<f64>,
+}
+
+/// A snapshot of a [`Tracker`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+    pub done: u64,
+    pub total: Option<u64>,
+
+    /// Time since the tracker started, at the last update.
+    pub elapsed: StdDuration,
+
+    /// The fraction of the total done, from 0.0 to 1.0. `None` if the total is unknown.
+    pub fraction: Option<f64>,
+
+    /// Units per second over the whole task so far. `None` before any time has passed.
+    pub average_rate: Option<f64>,
+
+    /// Units per second, smoothed over recent updates. `None` before the first update.
+    pub rate: Option<f64>,
+
+    /// Estimated time until the total is done. `None` if the total is unknown or nothing
+    /// has been done recently.
+    pub remaining: Option<StdDuration>,
+}
+
+impl Tracker {
+    /// Start tracking a task now, with `total` units to do if known.
+    pub fn new(total: Option<u64>) -> Tracker {
+        Tracker::new_at(Instant::now(), total)
+    }
+
+    /// Start tracking a task that started at `start`.
+    pub fn new_at(start: Instant, total: Option<u64>) -> Tracker {
+        Tracker {
+            start,
+            total,
+            smoothing: SMOOTHING_DEFAULT,
+
+            done: 0,
+            last_sample: start,
+            last_sample_done: 0,
+            smoothed_rate: None,
+        }
+    }
+
+    /// Set the weight of the newest rate sample in the smoothed rate, from 0.0 (exclusive)
+    /// to 1.0, where 1.0 uses only the rate since the last update. Default
+    /// [`SMOOTHING_DEFAULT`].
+    pub fn smoothing(&mut self, smoothing: f64) -> &mut Self {
+        assert!(smoothing > 0.0 && smoothing <= 1.0,
+                "Tracker smoothing must be in (0.0, 1.0], was {smoothing}");
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Set the total, e.g. when it becomes known after the task starts.
+    pub fn set_total(&mut self, total: Option<u64>) {
+        self.total = total;
+    }
+
+    /// Record that `done` units are done in total now.
+    pub fn update(&mut self, done: u64) -> Progress {
+        self.update_at(done, Instant::now())
+    }
+
+    /// Record that `done` units were done in total at `now`.
+    ///
+    /// Updates with no time since the last update don't change the rate. If `done` is less
+    /// than before it is recorded, but counted as no progress for the rate.
+    pub fn update_at(&mut self, done: u64, now: Instant) -> Progress {
+        self.done = done;
+
+        let sample_secs = now.saturating_duration_since(self.last_sample).as_secs_f64();
+        if sample_secs > 0.0 {
+            let sample_rate = done.saturating_sub(self.last_sample_done) as f64 / sample_secs;
+            self.smoothed_rate = Some(match self.smoothed_rate {
+                None => sample_rate,
+                Some(prev) => self.smoothing * sample_rate + (1.0 - self.smoothing) * prev,
+            });
+            self.last_sample = now;
+            self.last_sample_done = done;
+        }
+
+        self.progress()
+    }
+
+    /// The progress as of the last update.
+    pub fn progress(&self) -> Progress {
+        let elapsed = self.last_sample.saturating_duration_since(self.start);
+        let elapsed_secs = elapsed.as_secs_f64();
+
+        let fraction = self.total.map(|total| match total {
+            0 => 1.0,
+            total => (self.done as f64 / total as f64).min(1.0),
+        });
+
+        let remaining = match (self.total, self.smoothed_rate) {
+            (Some(total), _) if self.done >= total => Some(StdDuration::ZERO),
+            (Some(total), Some(rate)) if rate > 0.0 =>
+                StdDuration::try_from_secs_f64((total - self.done) as f64 / rate).ok(),
+            _ => None,
+        };
+
+        Progress {
+            done: self.done,
+            total: self.total,
+            elapsed,
+            fraction,
+            average_rate: (elapsed_secs > 0.0).then(|| self.done as f64 / elapsed_secs),
+            rate: self.smoothed_rate,
+            remaining,
+        }
+    }
+}
+
+impl Progress {
+    /// The percentage complete formatted like "12.3%", or `None` if the total is unknown.
+    pub fn percent_str(&self) -> Option<String> {
+        self.fraction.map(|fraction| format!("{percent:3.1}%", percent = fraction * 100.0))
+    }
+
+    /// The estimated time the task will be done, if `now` is the time of the last update.
+    pub fn eta<Tz: chrono::TimeZone>(&self, now: chrono::DateTime<Tz>
+    ) -> Option<chrono::DateTime<Tz>>
+    {
+        let remaining = chrono::Duration::from_std(self.remaining?).ok()?;
+        now.checked_add_signed(remaining)
+    }
+
+    /// The time remaining formatted like "1h 2m 1s 10ms", or `None` if not known.
+    pub fn remaining_str(&self) -> Option<String> {
+        self.remaining.map(|remaining| fmt::Duration(remaining).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: StdDuration = StdDuration::from_secs(1);
+
+    #[test]
+    fn zero_done() {
+        let start = Instant::now();
+        let mut tracker = Tracker::new_at(start, Some(1000));
+
+        let progress = tracker.progress();
+        assert_eq!(progress.fraction, Some(0.0));
+        assert_eq!(progress.rate, None);
+        assert_eq!(progress.average_rate, None);
+        assert_eq!(progress.remaining, None);
+
+        let progress = tracker.update_at(0, start + SECOND);
+        assert_eq!(progress.rate, Some(0.0));
+        assert_eq!(progress.average_rate, Some(0.0));
+        assert_eq!(progress.remaining, None);
+        assert_eq!(progress.percent_str().unwrap(), "0.0%");
+    }
+
+    #[test]
+    fn rate_and_remaining() {
+        let start = Instant::now();
+        let mut tracker = Tracker::new_at(start, Some(1000));
+
+        let progress = tracker.update_at(100, start + SECOND);
+        assert_eq!(progress.fraction, Some(0.1));
+        assert_eq!(progress.rate, Some(100.0));
+        assert_eq!(progress.average_rate, Some(100.0));
+        assert_eq!(progress.remaining, Some(9 * SECOND));
+        assert_eq!(progress.percent_str().unwrap(), "10.0%");
+
+        // Twice as fast in the last second: the smoothed rate moves part way towards it.
+        let progress = tracker.update_at(300, start + 2 * SECOND);
+        let rate = progress.rate.unwrap();
+        assert!((rate - 130.0).abs() < 1e-9, "rate = {rate}");
+        assert_eq!(progress.average_rate, Some(150.0));
+
+        // No time passed, so the rate is unchanged.
+        let progress = tracker.update_at(350, start + 2 * SECOND);
+        assert_eq!(progress.rate, Some(rate));
+        assert_eq!(progress.done, 350);
+    }
+
+    #[test]
+    fn smoothing_one_uses_latest_rate() {
+        let start = Instant::now();
+        let mut tracker = Tracker::new_at(start, None);
+        tracker.smoothing(1.0);
+
+        tracker.update_at(100, start + SECOND);
+        let progress = tracker.update_at(400, start + 2 * SECOND);
+        assert_eq!(progress.rate, Some(300.0));
+        assert_eq!(progress.fraction, None);
+        assert_eq!(progress.remaining, None);
+        assert_eq!(progress.percent_str(), None);
+    }
+
+    #[test]
+    fn done_past_total() {
+        let start = Instant::now();
+        let mut tracker = Tracker::new_at(start, Some(100));
+
+        let progress = tracker.update_at(150, start + SECOND);
+        assert_eq!(progress.fraction, Some(1.0));
+        assert_eq!(progress.remaining, Some(StdDuration::ZERO));
+
+        // Going backwards counts as no progress rather than underflowing.
+        let progress = tracker.update_at(50, start + 2 * SECOND);
+        assert_eq!(progress.done, 50);
+        assert!(progress.rate.unwrap() < 150.0);
+    }
+
+    #[test]
+    fn zero_total() {
+        let start = Instant::now();
+        let mut tracker = Tracker::new_at(start, Some(0));
+        let progress = tracker.update_at(0, start + SECOND);
+        assert_eq!(progress.fraction, Some(1.0));
+        assert_eq!(progress.remaining, Some(StdDuration::ZERO));
+    }
+
+    #[test]
+    fn eta() {
+        let start = Instant::now();
+        let mut tracker = Tracker::new_at(start, Some(200));
+        let progress = tracker.update_at(100, start + SECOND);
+
+        let now = chrono::DateTime::parse_from_rfc3339("2023-04-01T12:00:00Z").unwrap();
+        assert_eq!(progress.eta(now).unwrap(),
+                   chrono::DateTime::parse_from_rfc3339("2023-04-01T12:00:01Z").unwrap());
+        assert_eq!(progress.remaining_str().unwrap(), "1s");
+    }
+}
@@ -0,0 +1,113 @@
+//! The request and response types of the `wikimedia-web` JSON API, shared by the server and
+//! the `wikimedia-client` crate so both sides serialize the same structures.
+//!
+//! The API is served under `/api/v1/` for the server's default store, and under
+//! `/{dump_name}/api/v1/` for each store.
+
+use chrono::{DateTime, Utc};
+use crate::wikitext::SectionNode;
+use serde::{Deserialize, Serialize};
+
+/// The path of the API's version 1 routes for `dump_name`, or the default store if `None`,
+/// without a trailing slash, e.g. `/simplewiki/api/v1`.
+pub fn v1_path(dump_name: Option<&str>) -> String {
+    match dump_name {
+        Some(dump_name) => format!("/{dump_name}/api/v1"),
+        None => "/api/v1".to_string(),
+    }
+}
+
+/// The query string of `GET /api/v1/changes`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChangesQuery {
+    /// Only pages whose latest revision was saved after this time, in RFC 3339 format.
+    pub since: DateTime<Utc>,
+
+    /// A `next_cursor` from a previous response, to continue after it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+/// The response of `GET /api/v1/changes`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Changes {
+    pub pages: Vec<PageChange>,
+
+    /// Pass this as `cursor` to get the following pages. `None` when there are no more.
+    pub next_cursor: Option<String>,
+}
+
+/// A page in [`Changes`], ordered by revision timestamp then MediaWiki ID.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PageChange {
+    pub mediawiki_id: u64,
+    pub slug: String,
+    pub ns_id: i64,
+    pub revision_timestamp: Option<DateTime<Utc>>,
+
+    /// The path of the page on the server.
+    pub url: String,
+}
+
+/// The response of `GET /api/v1/page/{mediawiki_id}/sections`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PageSections {
+    pub mediawiki_id: u64,
+
+    /// The sections of the page's wikitext, nested by heading level. Byte ranges are into
+    /// the text of the page's revision.
+    pub sections: Vec<SectionNode>,
+}
+
+/// The response of `GET /api/v1/saved-searches`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedSearches {
+    /// Ordered by name.
+    pub searches: Vec<SavedSearch>,
+}
+
+/// A page search saved by name in the web interface.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedSearch {
+    pub name: String,
+
+    /// The search terms, including flag filters such as `-is:disambiguation`.
+    pub query: String,
+    pub min_words: Option<u64>,
+    pub max_words: Option<u64>,
+
+    /// Only pages in the category with this slug.
+    pub category: Option<String>,
+
+    /// Only pages in the namespace with this ID.
+    pub namespace: Option<i64>,
+
+    /// `relevance`, `longest`, or `shortest`.
+    pub order: String,
+    pub saved: DateTime<Utc>,
+
+    /// The path of the search's results on the server.
+    pub url: String,
+}
+
+/// The response of `GET /api/v1/saved-searches/{name}/results`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedSearchResults {
+    pub search: SavedSearch,
+    pub pages: Vec<SearchResultPage>,
+}
+
+/// A page in [`SavedSearchResults`], in the search's order.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SearchResultPage {
+    pub mediawiki_id: u64,
+    pub slug: String,
+    pub ns_id: i64,
+    pub revision_timestamp: Option<DateTime<Utc>>,
+
+    /// The path of the page on the server.
+    pub url: String,
+}
@@ -1,187 +1,731 @@
-use anyhow::{bail, Context, format_err};
-use crate::{
-    dump::{self, CategoryName},
-    Result,
-    TempDir,
+#[cfg(feature = "wikitext-render")]
+mod render;
+#[cfg(feature = "wikitext-render")]
+pub use render::{
+    convert_page_to_html,
+    highlighting_css,
+    HighlightStyle,
+    RenderedHtml,
+    RenderLimitExceeded,
+    RenderLimits,
 };
+
+use crate::dump::{CategoryName, PageCategory};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{
-    fs,
-    path::Path,
-    time::{Duration, Instant},
+    collections::HashMap,
+    ops::{BitOr, Range},
+    result::Result as StdResult,
 };
-use tokio::io::AsyncWriteExt;
-
-pub async fn convert_page_to_html(
-    page: &dump::Page,
-    dump_name: &dump::DumpName,
-    out_dir: &Path,
-) -> Result<String> {
-
-    let pandoc_start = Instant::now();
-
-    let temp_dir = TempDir::create(out_dir, /* keep: */ false)?;
-
-    // Write Lua filter
-
-    // TODO: Escape these as a Lua string literal.
-    let dump_name = &*dump_name.0;
-    let page_by_title = format!("/{dump_name}/page/by-title/");
-    let category_by_name = format!("/{dump_name}/category/by-name/");
-
-    let lua_filter = format!(
-        r##"
-            function Link(el)
-                local target = el.target
-                if string.find(target, "^http") ~= nil then
-                    -- nothing to do for http(s) links.
-                elseif string.find(target, "^Category:") ~= nil then
-                    -- internal link for category page
-                    local name = string.gsub(target, "Category:", "", 1)
-                    target = "{category_by_name}" .. name
-                else
-                    -- internal link for regular page
-                    target = "{page_by_title}" .. el.target
-                end
-                return pandoc.Link(el.content, target)
-            end
-        "##);
-    let lua_filter_path = temp_dir.path()?.join("filter.lua");
-    fs::write(&*lua_filter_path, lua_filter.as_bytes())?;
-
-    // Write template
-    let template_path = temp_dir.path()?.join("template.html");
-    const TEMPLATE: &'static str =
-        r#"
-$if(toc)$
-  <nav id="$idprefix$TOC" role="doc-toc">
-
-    <h2 id="$idprefix$toc-title">Table of contents</h2>
-
-    $table-of-contents$
-  </nav>
-$endif$
-
-$body$
-        "#;
-    fs::write(&*template_path, TEMPLATE.as_bytes())?;
-
-    let wikitext = page.revision_text().unwrap_or("");
-
-    let wikitext = escape_templates(wikitext);
-
-    let mut child =
-        tokio::process::Command::new("pandoc")
-            .args(&[
-                "--from", "mediawiki",
-                "--to", "html",
-                "--sandbox",
-                "--standalone",
-                "--template", &*template_path.to_string_lossy(),
-                "--id-prefix", "wikitext-",
-                "--toc",
-                "--number-sections",
-                "--number-offset", "1",
-                "--lua-filter", &*lua_filter_path.to_string_lossy(),
-            ])
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()
-            .context("While starting pandoc. Is it installed and on your path?")?;
-
-    let mut child_stdin =
-        child.stdin.take().ok_or(format_err!("Failed to open stdin"))?;
-
-    child_stdin.write_all(wikitext.as_bytes()).await?;
-    drop(child_stdin); // Closes child's stdin so it will read EOF.
-
-    // TODO: Collect stderr manually to print on timeout.
-
-    let child_out = child.wait_with_output();
-    let child_out = tokio::time::timeout(Duration::from_secs(5), child_out);
-    let child_out = child_out.await??;
-    let pandoc_duration = pandoc_start.elapsed();
-    if !child_out.status.success() {
-        bail!("Error exit code running pandoc code={code} stdout='{stdout}' \
-               stderr='{stderr}'",
-              code = child_out.status,
-              stdout = String::from_utf8_lossy(&child_out.stdout),
-              stderr = String::from_utf8_lossy(&child_out.stderr));
-    }
-
-    tracing::debug!(duration = ?pandoc_duration, "Pandoc completed");
-
-    let html = String::from_utf8_lossy(&*child_out.stdout);
-
-    tracing::trace!(pandoc_output_html = &*html, "Pandoc output HTML");
-
-    let sanitised =
-        ammonia::Builder::default()
-            .url_schemes(maplit::hashset![
-                "http", "https", "mailto"
-            ])
-            .link_rel(Some("noopener noreferrer nofollow"))
-            .add_tag_attributes("a" , &["id"])
-            .add_tag_attributes("h1", &["id"])
-            .add_tag_attributes("h2", &["id"])
-            .add_tag_attributes("h3", &["id"])
-            .add_tag_attributes("h4", &["id"])
-            .add_tag_attributes("h5", &["id"])
-            .add_tag_attributes("h6", &["id"])
-            .add_tag_attributes("li", &["id"])
-            .clean(&*html)
-            .to_string();
-
-    tracing::trace!(ammonia_output_html = sanitised, "ammonia output HTML");
-
-    Ok(sanitised)
+
+/// Cheap statistics about a page's wikitext, computed during import.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct TextStats {
+    /// Length of the wikitext in bytes.
+    pub bytes: u64,
+
+    /// Approximate count of words a reader sees. Templates, HTML tags, and comments are
+    /// skipped, but other markup such as link targets is counted.
+    pub words: u64,
+}
+
+/// Page properties set by magic words in a page's wikitext, from [`parse_page_props`].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct PageProps {
+    /// The title to show for the page, from `{{DISPLAYTITLE:...}}` with its markup removed,
+    /// e.g. `iPhone` for the page `IPhone`.
+    pub display_title: Option<String>,
+
+    /// The key the page is sorted by in categories, from `{{DEFAULTSORT:...}}`.
+    pub default_sort: Option<String>,
+}
+
+/// A section of a page's wikitext, started by a heading such as `== History ==`. See
+/// [`parse_sections`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Section {
+    /// The heading level, from 1 for `= Title =` to 6 for `====== Title ======`.
+    pub level: u8,
+
+    /// The heading text with links, bold and italic quotes, and HTML tags removed.
+    pub title: String,
+
+    /// The id of the section's heading in HTML from [`convert_page_to_html`]. As on
+    /// MediaWiki, this is the title with spaces replaced by `_`, and with `_2`, `_3`, and
+    /// so on appended to repeated titles.
+    pub anchor: String,
+
+    /// The byte range in the wikitext of the heading and the section's text, up to the
+    /// next heading of the same or a higher level.
+    pub range: Range<usize>,
 }
 
+/// A [`Section`] with the sections nested in it, from [`section_tree`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SectionNode {
+    #[serde(flatten)]
+    pub section: Section,
+
+    /// The subsections directly in this section, e.g. the `=== Early history ===` sections
+    /// of a `== History ==` section, in the order they appear.
+    pub children: Vec<SectionNode>,
+}
+
+/// Average adult reading speed used to estimate reading time.
+const WORDS_PER_MINUTE: u64 = 200;
+
+/// Kinds of article detected from a page's title and wikitext by [`classify_page`].
+///
+/// A set of bit flags, serialized as a list of names, e.g. `["stub"]`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PageFlags(pub u32);
+
+/// Articles with fewer words than this are classified as stubs, even without a stub
+/// template.
+const STUB_MAX_WORDS: u64 = 100;
+
+/// Names of templates that mark disambiguation pages, other than those ending in
+/// "disambiguation", lower case.
+const DISAMBIGUATION_TEMPLATES: &[&str] = &[
+    "dab", "disamb", "disambig", "geodis", "hndis", "mathdab", "numberdis",
+];
+
+/// Parse the categories a page belongs to from its category links, e.g.
+/// `[[Category:Living people|Smith, John]]`.
+///
+/// `namespace_names` are the wiki's own names for the category namespace, which are
+/// matched as well as `Category`, e.g. `Kategorie` on the German Wikipedia. Dumps list these
+/// in their `<siteinfo>`.
+///
+/// Returns categories sorted by name. If a page links to a category more than once, the
+/// first link's sort key is kept.
 pub fn parse_categories(
-    wikitext: &str
-) -> Vec<CategoryName> {
-    let mut vec = lazy_regex!(r#"\[\[Category:([^\]]+)\]\]"#).captures_iter(wikitext)
+    wikitext: &str,
+    namespace_names: &[&str],
+) -> Vec<PageCategory> {
+    let is_category_namespace = |prefix: &str| {
+        let prefix = crate::slug::normalize_title(prefix).to_lowercase();
+        prefix == "category"
+            || namespace_names.iter().any(|name| name.to_lowercase() == prefix)
+    };
+
+    let default_sort_key = parse_default_sort(wikitext);
+
+    let mut vec = lazy_regex!(r#"\[\[\s*([^:\[\]|{}]+?)\s*:([^\]]+)\]\]"#)
+        .captures_iter(wikitext)
+        .filter(|captures| {
+            is_category_namespace(captures.get(1).expect("capture group 1").as_str())
+        })
+        .map(|captures| {
+            let link = captures.get(2).expect("capture group 2").as_str();
+            let (name, sort_key) = match link.split_once('|') {
+                Some((name, sort_key)) => (name, Some(sort_key).filter(|key| !key.is_empty())),
+                None => (link, None),
+            };
+            PageCategory {
+                name: CategoryName::new(name),
+                sort_key: sort_key.or(default_sort_key).map(|key| key.to_string()),
+            }
+        })
+        .filter(|category| !category.name.0.is_empty())
+        .collect::<Vec<PageCategory>>();
+    // The sort is stable, so the first link to each category is kept.
+    vec.sort_by(|a, b| a.name.cmp(&b.name));
+    vec.dedup_by(|a, b| a.name == b.name);
+    vec
+}
+
+/// Parse the titles of the articles `wikitext` links to, e.g. `Rust (programming language)`
+/// from `[[rust (programming language)#History|Rust]]`.
+///
+/// Links with a namespace or interwiki prefix, such as categories and files, are skipped, as
+/// are links to sections of the same page. Titles have their first letter upper cased, as
+/// MediaWiki does by default. Returns titles sorted and without duplicates.
+pub fn parse_links(wikitext: &str) -> Vec<String> {
+    let mut vec = lazy_regex!(r#"\[\[([^\[\]|{}#:]*)(?:#[^\[\]|]*)?(?:\|[^\[\]]*)?\]\]"#)
+        .captures_iter(wikitext)
         .map(|captures| {
-            let name = captures.get(1).expect("capture group 1").as_str().to_string();
-            CategoryName(name)
+            upper_case_first(&crate::slug::normalize_title(
+                captures.get(1).expect("capture group 1").as_str()))
         })
-        .collect::<Vec<CategoryName>>();
+        .filter(|title| !title.is_empty())
+        .collect::<Vec<String>>();
     vec.sort();
     vec.dedup();
     vec
 }
 
-fn escape_templates(wikitext: &str) -> String {
-    fn replacer<'t>(caps: &regex::Captures<'t>) -> String {
-        let inner = caps.get(0).expect("regex capture 0").as_str();
-        let inner = html_escape::encode_text(inner);
-        format!("<pre>{inner}</pre>")
+/// Variables that look like template transclusions, e.g. `{{PAGENAME}}`.
+const MAGIC_VARIABLES: &[&str] = &[
+    "!", "BASEPAGENAME", "CURRENTDAY", "CURRENTDAYNAME", "CURRENTMONTH", "CURRENTMONTHNAME",
+    "CURRENTTIME", "CURRENTTIMESTAMP", "CURRENTYEAR", "FULLPAGENAME", "LOCALYEAR", "NAMESPACE",
+    "NAMESPACENUMBER", "NUMBEROFARTICLES", "PAGENAME", "PAGENAMEE", "REVISIONID",
+    "REVISIONYEAR", "ROOTPAGENAME", "SERVER", "SERVERNAME", "SITENAME", "SUBPAGENAME",
+    "TALKPAGENAME",
+];
+
+/// Parse the titles of the templates and modules `wikitext` transcludes, e.g.
+/// `Template:Infobox person` from `{{infobox_person|name=...}}` and `Module:Citation/CS1`
+/// from `{{#invoke:Citation/CS1|citation}}`.
+///
+/// Titles use the canonical namespace names `Template` and `Module`. Pages transcluded from
+/// the main namespace, e.g. `{{:Main Page}}`, are kept without a namespace. Template
+/// parameters such as `{{{1}}}`, parser functions, variables, and names with any other
+/// prefix, such as `{{DEFAULTSORT:Smith}}`, are skipped. Returns titles sorted and without
+/// duplicates.
+pub fn parse_templates(wikitext: &str) -> Vec<String> {
+    let mut vec = lazy_regex!(r#"\{\{([^{}|\[\]<>]+?)(?:\||\}\})"#)
+        .captures_iter(wikitext)
+        // `{{{1}}}` is a template parameter, matched from its second brace.
+        .filter(|captures| {
+            !wikitext[..captures.get(0).expect("capture group 0").start()].ends_with('{')
+        })
+        .filter_map(|captures| template_title(captures.get(1).expect("capture group 1").as_str()))
+        .collect::<Vec<String>>();
+    vec.sort();
+    vec.dedup();
+    vec
+}
+
+/// The title transcluded by a template name written in wikitext, e.g.
+/// `Template:Infobox person` from `infobox_person`, see [`parse_templates`]. Returns `None`
+/// for names that don't transclude a page.
+pub fn template_title(name: &str) -> Option<String> {
+    fn strip_prefix<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+        s.get(..prefix.len())
+         .filter(|start| start.eq_ignore_ascii_case(prefix))
+         .map(|_| &s[prefix.len()..])
+    }
+
+    let name = name.trim();
+    // `subst:` is replaced when a page is saved, but `safesubst:` in templates is not.
+    let name = strip_prefix(name, "safesubst:").unwrap_or(name).trim_start();
+
+    if let Some(module) = strip_prefix(name, "#invoke:") {
+        let module = upper_case_first(&crate::slug::normalize_title(module));
+        return (!module.is_empty()).then(|| format!("Module:{module}"));
+    }
+    if name.starts_with('#') || MAGIC_VARIABLES.contains(&name) {
+        return None;
+    }
+
+    let (namespace, title) = match name.split_once(':') {
+        None => (Some("Template"), name),
+        Some((prefix, title)) => match &*crate::slug::normalize_title(prefix).to_lowercase() {
+            "" => (None, title),
+            "template" => (Some("Template"), title),
+            "module" => (Some("Module"), title),
+            _ => return None,
+        },
+    };
+    let title = upper_case_first(&crate::slug::normalize_title(title));
+    if title.is_empty() {
+        return None;
+    }
+    Some(match namespace {
+        Some(namespace) => format!("{namespace}:{title}"),
+        None => title,
+    })
+}
+
+/// Where a redirect page points, see [`parse_redirect_target`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RedirectTarget {
+    /// The normalised title of the target page, see [`crate::slug::normalize_title`].
+    pub title: String,
+
+    /// The anchor of the section of the target page to show, e.g. `Early_history` from
+    /// `#REDIRECT [[Rust#Early history]]`, or `None` to show the top of the page.
+    pub fragment: Option<String>,
+}
+
+/// Parse the sections of `wikitext` from its headings, in the order they appear.
+///
+/// As on MediaWiki, a heading's level is the lower of its counts of leading and trailing
+/// `=`, and any extra `=` are part of its title. Text before the first heading is not in
+/// any section.
+pub fn parse_sections(wikitext: &str) -> Vec<Section> {
+    let mut sections = Vec::<Section>::new();
+    let mut anchor_counts = HashMap::<String, u32>::new();
+
+    for captures in lazy_regex!(r#"(?m)^(=+)(.+?)(=+)[ \t]*\r?$"#).captures_iter(wikitext) {
+        let start = captures.get(1).expect("capture group 1");
+        let end = captures.get(3).expect("capture group 3");
+        let level = start.len().min(end.len()).min(6);
+        let text = format!("{extra_start}{text}{extra_end}",
+                           extra_start = &start.as_str()[level..],
+                           text = captures.get(2).expect("capture group 2").as_str(),
+                           extra_end = &end.as_str()[level..]);
+        let title = strip_title_markup(
+            &lazy_regex!(r#"\[\[(?:[^\[\]|]*\|)?([^\[\]]*)\]\]"#).replace_all(&text, "$1"));
+        if title.is_empty() {
+            continue;
+        }
+
+        let mut anchor = title.split_whitespace().collect::<Vec<&str>>().join("_");
+        let count = anchor_counts.entry(anchor.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            anchor = format!("{anchor}_{count}");
+        }
+
+        sections.push(Section {
+            level: u8::try_from(level).expect("u8 from level <= 6"),
+            title,
+            anchor,
+            range: start.start()..wikitext.len(),
+        });
+    }
+
+    // End each section at the next heading of the same or a higher level.
+    for idx in 0..sections.len() {
+        let level = sections[idx].level;
+        if let Some(next) = sections[idx + 1..].iter().find(|next| next.level <= level) {
+            sections[idx].range.end = next.range.start;
+        }
+    }
+
+    sections
+}
+
+/// Nest `sections` from [`parse_sections`] into a tree, with each section's subsections as
+/// its children.
+///
+/// A section's children are the following sections with a greater level, up to the next
+/// section of the same or a lower level. As on MediaWiki, a level can be skipped, e.g. a
+/// level 4 section directly in a level 2 section.
+pub fn section_tree(sections: Vec<Section>) -> Vec<SectionNode> {
+    let mut roots = Vec::<SectionNode>::new();
+
+    // The path from a root to the last section seen, which later sections can nest in.
+    let mut open = Vec::<SectionNode>::new();
+    let close = |open: &mut Vec<SectionNode>, roots: &mut Vec<SectionNode>| {
+        let node = open.pop().expect("open is not empty");
+        match open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    };
+
+    for section in sections {
+        while open.last().is_some_and(|node| node.section.level >= section.level) {
+            close(&mut open, &mut roots);
+        }
+        open.push(SectionNode {
+            section,
+            children: Vec::new(),
+        });
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+
+    roots
+}
+
+/// Parse the page properties set by magic words in `wikitext`, for the page `title`.
+///
+/// As MediaWiki does by default, a `{{DISPLAYTITLE:...}}` is ignored unless it is the page's
+/// title apart from formatting and the case of its first letter.
+pub fn parse_page_props(title: &str, wikitext: &str) -> PageProps {
+    let display_title = lazy_regex!(r#"\{\{\s*DISPLAYTITLE\s*:([^}|]+)(?:\|[^}]*)?\}\}"#)
+        .captures(wikitext)
+        .map(|captures| strip_title_markup(captures.get(1).expect("capture group 1").as_str()))
+        .filter(|display_title| {
+            let normalize = |title: &str| upper_case_first(&crate::slug::normalize_title(title));
+            !display_title.is_empty() && normalize(display_title) == normalize(title)
+        });
+
+    PageProps {
+        display_title,
+        default_sort: parse_default_sort(wikitext).map(|key| key.to_string()),
+    }
+}
+
+/// The key from a `{{DEFAULTSORT:...}}` in `wikitext`, if any.
+fn parse_default_sort(wikitext: &str) -> Option<&str> {
+    lazy_regex!(r#"\{\{\s*(?:DEFAULTSORT|DEFAULTSORTKEY|DEFAULTCATEGORYSORT)\s*:([^}|]+)\}\}"#)
+        .captures(wikitext)
+        .map(|captures| captures.get(1).expect("capture group 1").as_str().trim())
+        .filter(|key| !key.is_empty())
+}
+
+/// Remove bold and italic quotes and HTML tags from a display title, and decode HTML
+/// entities, e.g. `''iPhone''` or `<i>iPhone</i>` to `iPhone`.
+fn strip_title_markup(display_title: &str) -> String {
+    let without_tags = lazy_regex!(r#"<[^<>]*>|'{2,}"#).replace_all(display_title, "");
+    html_escape::decode_html_entities(&without_tags).trim().to_string()
+}
+
+fn upper_case_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Parse the title a redirect page points to, e.g. `Rust (programming language)` from
+/// `#REDIRECT [[Rust (programming language)#History]]`.
+///
+/// Returns `None` if the page is not a redirect. The section after `#` is dropped, see
+/// [`parse_redirect_target`] to keep it.
+pub fn parse_redirect(wikitext: &str) -> Option<String> {
+    parse_redirect_target(wikitext).map(|target| target.title)
+}
+
+/// Parse the title and section a redirect page points to, e.g. title
+/// `Rust (programming language)` and fragment `History` from
+/// `#REDIRECT [[Rust (programming language)#History]]`.
+///
+/// Returns `None` if the page is not a redirect.
+pub fn parse_redirect_target(wikitext: &str) -> Option<RedirectTarget> {
+    let captures = lazy_regex!(r#"^\s*(?i:#redirect)\s*:?\s*\[\[:?([^\]|#]*)(?:#([^\]|]*))?"#)
+        .captures(wikitext)?;
+    let title = crate::slug::normalize_title(
+        captures.get(1).expect("capture group 1").as_str());
+    // A redirect to a section of the same page, e.g. `#REDIRECT [[#History]]`, has no title.
+    if title.is_empty() {
+        return None;
+    }
+    // As on MediaWiki, the fragment is a section's anchor, see `Section::anchor`.
+    let fragment = captures.get(2)
+        .map(|fragment| fragment.as_str().split_whitespace().collect::<Vec<&str>>().join("_"))
+        .filter(|fragment| !fragment.is_empty());
+    Some(RedirectTarget { title, fragment })
+}
+
+impl TextStats {
+    pub fn from_wikitext(wikitext: &str) -> TextStats {
+        TextStats {
+            bytes: wikitext.len().try_into().expect("u64 from usize"),
+            words: count_words(wikitext),
+        }
+    }
+
+    /// Approximate reading time in whole minutes, rounded up.
+    pub fn reading_minutes(&self) -> u64 {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+}
+
+impl PageFlags {
+    pub const STUB: PageFlags = PageFlags(1);
+    pub const DISAMBIGUATION: PageFlags = PageFlags(1 << 1);
+    pub const LIST: PageFlags = PageFlags(1 << 2);
+
+    /// Each flag with its name, as used in search filters such as `is:stub`.
+    pub const NAMED: &'static [(PageFlags, &'static str)] = &[
+        (PageFlags::STUB, "stub"),
+        (PageFlags::DISAMBIGUATION, "disambiguation"),
+        (PageFlags::LIST, "list"),
+    ];
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all flags in `other` are set in `self`.
+    pub fn contains(self, other: PageFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn from_name(name: &str) -> Option<PageFlags> {
+        PageFlags::NAMED.iter()
+            .find(|(_, flag_name)| flag_name.eq_ignore_ascii_case(name))
+            .map(|(flag, _)| *flag)
+    }
+
+    /// The names of the flags that are set.
+    pub fn names(self) -> Vec<&'static str> {
+        PageFlags::NAMED.iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl BitOr for PageFlags {
+    type Output = PageFlags;
+
+    fn bitor(self, rhs: PageFlags) -> PageFlags {
+        PageFlags(self.0 | rhs.0)
+    }
+}
+
+impl Serialize for PageFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+/// Classify an article as a stub, disambiguation page, or list with cheap heuristics.
+///
+/// Checks for templates such as `{{Disambiguation}}` and `{{Physics-stub}}`, title prefixes
+/// and suffixes such as `List of ` and ` (disambiguation)`, and short text. Redirects are
+/// never classified.
+pub fn classify_page(title: &str, wikitext: &str, stats: &TextStats) -> PageFlags {
+    if wikitext.trim_start().get(..9).is_some_and(|s| s.eq_ignore_ascii_case("#redirect")) {
+        return PageFlags::default();
+    }
+
+    let mut flags = PageFlags::default();
+    let mut has_stub_template = false;
+
+    for captures in lazy_regex!(r#"\{\{\s*([^{}|\n]+?)\s*(?:\||\}\})"#).captures_iter(wikitext) {
+        let name = captures.get(1).expect("capture group 1").as_str()
+                           .replace('_', " ")
+                           .to_lowercase();
+        if name.ends_with("disambiguation") || DISAMBIGUATION_TEMPLATES.contains(&&*name) {
+            flags = flags | PageFlags::DISAMBIGUATION;
+        }
+        if name.ends_with("stub") {
+            has_stub_template = true;
+        }
+    }
+
+    if title.ends_with(" (disambiguation)") || title.ends_with("_(disambiguation)") {
+        flags = flags | PageFlags::DISAMBIGUATION;
+    }
+    if ["List of ", "List_of_", "Lists of ", "Lists_of_"].iter().any(|p| title.starts_with(p)) {
+        flags = flags | PageFlags::LIST;
+    }
+
+    // Disambiguation pages and lists are often short, but aren't stubs.
+    if has_stub_template
+        || (flags.is_empty() && stats.words < STUB_MAX_WORDS)
+    {
+        flags = flags | PageFlags::STUB;
+    }
+
+    flags
+}
+
+fn count_words(wikitext: &str) -> u64 {
+    let mut words = 0;
+    let mut in_word = false;
+    let mut template_depth: u64 = 0;
+    let mut rest = wikitext;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.split_once("-->").map_or("", |(_, after)| after);
+            in_word = false;
+        } else if let Some(after) = rest.strip_prefix("{{") {
+            template_depth += 1;
+            rest = after;
+            in_word = false;
+        } else if template_depth > 0 {
+            match rest.strip_prefix("}}") {
+                Some(after) => {
+                    template_depth -= 1;
+                    rest = after;
+                },
+                None => rest = &rest[c.len_utf8()..],
+            }
+        } else if c == '<' {
+            rest = rest.split_once('>').map_or("", |(_, after)| after);
+            in_word = false;
+        } else {
+            if c.is_whitespace() || c == '|' {
+                in_word = false;
+            } else if !in_word && c.is_alphanumeric() {
+                in_word = true;
+                words += 1;
+            }
+            rest = &rest[c.len_utf8()..];
+        }
     }
 
-    // TODO: This doesn't handle nested template invocations.
-    lazy_regex!(r#"\{\{[^}]+\}\}"#).replace_all(wikitext, replacer).to_string()
+    words
 }
 
 #[cfg(test)]
 mod tests {
-    use super::escape_templates;
+    use super::{CategoryName, classify_page, PageCategory, PageFlags, PageProps,
+                parse_categories, parse_links, parse_page_props, parse_redirect,
+                parse_redirect_target, parse_sections, parse_templates, RedirectTarget,
+                section_tree, SectionNode, TextStats};
+
+    #[test]
+    fn parse_redirect_targets() {
+        assert_eq!(parse_redirect("#REDIRECT [[Rust (programming language)#History]]"),
+                   Some("Rust (programming language)".to_string()));
+        assert_eq!(parse_redirect("  #redirect:[[:Category:Machine_learning|ML]] {{R from move}}"),
+                   Some("Category:Machine learning".to_string()));
+        assert_eq!(parse_redirect("#REDIRECT [[#History]]"), None);
+        assert_eq!(parse_redirect("Text then #REDIRECT [[Rust]]"), None);
+        assert_eq!(parse_redirect("#REDIRECTION [[Rust]]"), None);
+    }
+
+    #[test]
+    fn parse_redirect_target_fragments() {
+        let target = |title: &str, fragment: Option<&str>| Some(RedirectTarget {
+            title: title.to_string(),
+            fragment: fragment.map(|fragment| fragment.to_string()),
+        });
+        assert_eq!(parse_redirect_target("#REDIRECT [[Rust (programming language)#Early history]]"),
+                   target("Rust (programming language)", Some("Early_history")));
+        assert_eq!(parse_redirect_target("#REDIRECT [[Rust#History|the history]]"),
+                   target("Rust", Some("History")));
+        assert_eq!(parse_redirect_target("#REDIRECT [[Rust#]]"), target("Rust", None));
+        assert_eq!(parse_redirect_target("#REDIRECT [[Rust]]"), target("Rust", None));
+        assert_eq!(parse_redirect_target("#REDIRECT [[#History]]"), None);
+    }
+
+    #[test]
+    fn parse_categories_names() {
+        let category = |name: &str, sort_key: Option<&str>| PageCategory {
+            name: CategoryName(name.to_string()),
+            sort_key: sort_key.map(|key| key.to_string()),
+        };
+
+        let categories = parse_categories(
+            "[[Category:Living people|Smith, John]] [[Category:Machine_learning]] \
+             [[Category:Living people]] [[Category: |x]] [[:Category:Linked]] \
+             [[Kategorie:Informatik| ]] [[File:Diagram.png|thumb]]",
+            &[]);
+        assert_eq!(categories, vec![category("Living people", Some("Smith, John")),
+                                    category("Machine learning", None)]);
+
+        let categories = parse_categories(
+            "{{DEFAULTSORT:Turing, Alan}} [[kategorie:Informatik]] [[Category:Logik|Alan]]",
+            &["Kategorie"]);
+        assert_eq!(categories, vec![category("Informatik", Some("Turing, Alan")),
+                                    category("Logik", Some("Alan"))]);
+    }
 
     #[test]
-    fn escape_templates_cases() {
-        let cases: &[(&str, &str)] = [
-            ("", ""),
-            ("asdf", "asdf"),
-            ("{{a}}", "<pre>{{a}}</pre>"),
-            ("{{<pre>a</pre>}}", "<pre>{{&lt;pre&gt;a&lt;/pre&gt;}}</pre>"),
-        ].as_slice();
-
-        for (input, expected) in cases.into_iter() {
-            let out = escape_templates(input);
-            println!("\nCase:\n\
-                      |   in:       '{input}'\n\
-                      |   out:      '{out}'\n\
-                      |   expected: '{expected}'\n");
-            assert_eq!(out, *expected);
+    fn parse_templates_titles() {
+        assert_eq!(
+            parse_templates(
+                "{{infobox_person\n| name = {{PAGENAME}} | born = {{birth date|1912|6|23}}}} \
+                 {{#invoke:citation/CS1|citation}} {{#if:{{{1|}}}|{{{1}}}|x}} \
+                 {{Template:Infobox person}} {{:Main Page}} {{safesubst:Cite web|url=x}} \
+                 {{DEFAULTSORT:Turing, Alan}} {{!}}"),
+            vec!["Main Page", "Module:Citation/CS1", "Template:Birth date",
+                 "Template:Cite web", "Template:Infobox person"]);
+        assert_eq!(parse_templates("{{{title}}} {{ }} [[Page]]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn parse_sections_headings() {
+        let wikitext = "Lead\n\
+                        == History ==\n\
+                        Old.\n\
+                        === ''Early'' [[Rust (language)|Rust]] ===\n\
+                        Older.\n\
+                        == History ==\n\
+                        ==Uneven===\n\
+                        Not a heading == here ==\n";
+        let sections = parse_sections(wikitext);
+        let summary = sections.iter()
+            .map(|s| (s.level, &*s.title, &*s.anchor, &wikitext[s.range.clone()]))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (2, "History", "History",
+             "== History ==\nOld.\n=== ''Early'' [[Rust (language)|Rust]] ===\nOlder.\n"),
+            (3, "Early Rust", "Early_Rust", "=== ''Early'' [[Rust (language)|Rust]] ===\nOlder.\n"),
+            (2, "History", "History_2", "== History ==\n"),
+            (2, "Uneven=", "Uneven=", "==Uneven===\nNot a heading == here ==\n"),
+        ]);
+        assert!(parse_sections("No headings\n").is_empty());
+    }
+
+    #[test]
+    fn section_tree_nests_subsections() {
+        let wikitext = "== History ==\n\
+                        === Early ===\n\
+                        ===== Skipped a level =====\n\
+                        === Late ===\n\
+                        == References ==\n";
+        let tree = section_tree(parse_sections(wikitext));
+        fn titles(nodes: &[SectionNode]) -> Vec<String> {
+            nodes.iter()
+                 .map(|node| match node.children.is_empty() {
+                     true => node.section.title.clone(),
+                     false => format!("{title} {children:?}",
+                                      title = node.section.title,
+                                      children = titles(&node.children)),
+                 })
+                 .collect()
         }
+        assert_eq!(titles(&tree), [
+            r#"History ["Early [\"Skipped a level\"]", "Late"]"#,
+            "References",
+        ]);
+        assert!(section_tree(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn parse_links_titles() {
+        assert_eq!(
+            parse_links("[[rust (programming language)#History|Rust]] is [[fast]], see \
+                         [[Rust_(programming language)]] [[#Safety]] [[Category:Languages]] \
+                         [[:File:Logo.png]] [[fr:Rust]] [[Memory  safety|safe]]."),
+            vec!["Fast", "Memory safety", "Rust (programming language)"]);
+        assert_eq!(parse_links("No links, [[ ]], {{[[Template]]}}."), vec!["Template"]);
+    }
+
+    #[test]
+    fn parse_page_props_magic_words() {
+        let props = parse_page_props(
+            "IPhone", "{{DISPLAYTITLE:''iPhone''}} {{DEFAULTSORT: Iphone }} text");
+        assert_eq!(props, PageProps {
+            display_title: Some("iPhone".to_string()),
+            default_sort: Some("Iphone".to_string()),
+        });
+
+        assert_eq!(parse_page_props("Gene_name", "{{DISPLAYTITLE:<i>gene</i> name|noerror}}")
+                       .display_title,
+                   Some("gene name".to_string()));
+        assert_eq!(parse_page_props("AT&T", "{{DISPLAYTITLE:AT&amp;T}}").display_title,
+                   Some("AT&T".to_string()));
+
+        // A display title for a different page is ignored.
+        assert_eq!(parse_page_props("Rust", "{{DISPLAYTITLE:Go}}"), PageProps::default());
+        assert_eq!(parse_page_props("Rust", "No magic words."), PageProps::default());
+    }
+
+    #[test]
+    fn text_stats() {
+        let stats = TextStats::from_wikitext(
+            "{{Infobox|name={{nested}} x}}'''Rust''' is a [[programming language|language]]\
+             <br />.<!-- Not counted -->\n\n== History ==\nIt's fast.");
+        // Link targets are counted: "programming language language".
+        assert_eq!(stats.words, 9);
+        assert_eq!(stats.reading_minutes(), 1);
+
+        assert_eq!(TextStats::from_wikitext(""), TextStats { bytes: 0, words: 0 });
+        assert_eq!(TextStats::from_wikitext("é é").bytes, 5);
+        assert_eq!(TextStats::from_wikitext(&"word ".repeat(401)).reading_minutes(), 3);
+        // Unclosed markup doesn't panic.
+        assert_eq!(TextStats::from_wikitext("a {{b").words, 1);
+        assert_eq!(TextStats::from_wikitext("a <b").words, 1);
+    }
+
+    #[test]
+    fn classify_page_flags() {
+        let classify = |title: &str, wikitext: &str| {
+            classify_page(title, wikitext, &TextStats::from_wikitext(wikitext)).names()
+        };
+        let long = "word ".repeat(200);
+
+        assert_eq!(classify("Rust", &long), Vec::<&str>::new());
+        assert_eq!(classify("Rust", "Short."), vec!["stub"]);
+        assert_eq!(classify("Rust", &format!("{long}{{{{Programming-language-stub}}}}")),
+                   vec!["stub"]);
+        assert_eq!(classify("Mercury", "'''Mercury''' may refer to: {{Disambiguation|geo}}"),
+                   vec!["disambiguation"]);
+        assert_eq!(classify("Smith", "{{hndis}}"), vec!["disambiguation"]);
+        assert_eq!(classify("Mercury (disambiguation)", "Mercury may be:"),
+                   vec!["disambiguation"]);
+        assert_eq!(classify("List of rivers", "Rivers:"), vec!["list"]);
+        assert_eq!(classify("Rust lang", "#REDIRECT [[Rust]]"), Vec::<&str>::new());
+
+        assert_eq!(PageFlags::from_name("Stub"), Some(PageFlags::STUB));
+        assert_eq!(PageFlags::from_name("redirect"), None);
+        assert_eq!(serde_json::to_string(&(PageFlags::STUB | PageFlags::LIST)).unwrap(),
+                   r#"["stub","list"]"#);
     }
 }
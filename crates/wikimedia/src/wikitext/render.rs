@@ -0,0 +1,528 @@
+//! Rendering wikitext to HTML with pandoc, enabled by the `wikitext-render` feature.
+
+use anyhow::{bail, Context, format_err};
+use crate::{
+    dump::{self, SiteInfo},
+    Result,
+    TempDir,
+};
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncWriteExt;
+
+/// MathML elements kept in HTML from [`convert_page_to_html`]. These are the presentation
+/// elements pandoc writes for `<math>` tags, which browsers render natively.
+const MATHML_TAGS: &[&str] = &[
+    "annotation", "math", "menclose", "merror", "mfrac", "mi", "mmultiscripts", "mn", "mo",
+    "mover", "mpadded", "mphantom", "mprescripts", "mroot", "mrow", "ms", "mspace", "msqrt",
+    "mstyle", "msub", "msubsup", "msup", "mtable", "mtd", "mtext", "mtr", "munder",
+    "munderover", "none", "semantics",
+];
+
+/// Attributes kept on [`MATHML_TAGS`].
+const MATHML_ATTRIBUTES: &[&str] = &[
+    "accent", "accentunder", "columnalign", "columnlines", "columnspacing", "depth",
+    "display", "displaystyle", "encoding", "fence", "form", "height", "largeop",
+    "linethickness", "lspace", "mathvariant", "maxsize", "minsize", "movablelimits",
+    "notation", "rowalign", "rowlines", "rowspacing", "rspace", "scriptlevel", "separator",
+    "stretchy", "symmetric", "width",
+];
+
+/// Themes for highlighted code, see [`highlighting_css`]. These are pandoc's built in
+/// highlight styles.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum HighlightStyle {
+    Breezedark,
+    Espresso,
+    Haddock,
+    Kate,
+    Monochrome,
+    #[default]
+    Pygments,
+    Tango,
+    Zenburn,
+}
+
+/// Classes pandoc gives the tokens of highlighted code, styled by [`highlighting_css`].
+const HIGHLIGHT_TOKEN_CLASSES: &[&str] = &[
+    "al", "an", "at", "bn", "bu", "cf", "ch", "cn", "co", "cv", "do", "dt", "dv", "er", "ex",
+    "fl", "fu", "im", "in", "kw", "op", "ot", "pp", "sc", "ss", "st", "va", "vs", "wa",
+];
+
+/// Limits on rendering one page with [`convert_page_to_html`], so a pathological page
+/// can't hold a CPU or lots of memory. A page over a limit is rendered truncated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RenderLimits {
+    /// The most wikitext to render, in bytes. Longer wikitext is cut at the last line break
+    /// before this.
+    pub max_input_bytes: usize,
+
+    /// The most HTML to return, in bytes. Longer HTML is cut before the last tag that
+    /// starts before this.
+    pub max_output_bytes: usize,
+
+    /// How long pandoc may run before it is killed.
+    pub timeout: Duration,
+}
+
+impl Default for RenderLimits {
+    fn default() -> RenderLimits {
+        RenderLimits {
+            max_input_bytes: 4_000_000,
+            max_output_bytes: 16_000_000,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Which of the [`RenderLimits`] a page exceeded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RenderLimitExceeded {
+    /// The wikitext was longer than [`RenderLimits::max_input_bytes`], so only its start
+    /// was rendered.
+    InputBytes { len: usize, limit: usize },
+
+    /// The HTML was longer than [`RenderLimits::max_output_bytes`], so only its start was
+    /// kept.
+    OutputBytes { len: usize, limit: usize },
+
+    /// Pandoc ran for longer than [`RenderLimits::timeout`], so the start of the wikitext
+    /// is shown as plain text instead.
+    Timeout { limit: Duration },
+}
+
+impl Display for RenderLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderLimitExceeded::InputBytes { len, limit } =>
+                write!(f, "The page's wikitext is {len} bytes, only the first {limit} bytes \
+                           were rendered."),
+            RenderLimitExceeded::OutputBytes { len, limit } =>
+                write!(f, "The page's HTML is {len} bytes, only the first {limit} bytes \
+                           are shown."),
+            RenderLimitExceeded::Timeout { limit } =>
+                write!(f, "Rendering the page took longer than {limit:?}, its wikitext is \
+                           shown instead."),
+        }
+    }
+}
+
+/// HTML returned by [`convert_page_to_html`].
+#[derive(Clone, Debug)]
+pub struct RenderedHtml {
+    pub html: String,
+
+    /// Set if the page exceeded one of the [`RenderLimits`] and `html` is truncated.
+    pub limit_exceeded: Option<RenderLimitExceeded>,
+}
+
+/// How much wikitext to show as plain text when pandoc times out.
+const TIMEOUT_WIKITEXT_BYTES: usize = 64 * 1024;
+
+/// Convert a page's wikitext to HTML using pandoc.
+///
+/// Internal links point to the `wmd web` routes for `dump_name`. Interwiki links (e.g.
+/// `[[wikt:word]]`) point to the sister projects of `site`, when it is known. `<math>` tags
+/// are converted to MathML, so formulas need no scripts or fonts to display. Code in
+/// `<syntaxhighlight>` and `<source>` tags is highlighted, see [`highlighting_css`].
+///
+/// Pages that exceed `limits` are truncated rather than returning an error, see
+/// [`RenderedHtml::limit_exceeded`].
+pub async fn convert_page_to_html(
+    page: &dump::Page,
+    dump_name: &dump::DumpName,
+    site: Option<&SiteInfo>,
+    out_dir: &Path,
+    limits: &RenderLimits,
+) -> Result<RenderedHtml> {
+
+    let temp_dir = TempDir::create(out_dir, /* keep: */ false)?;
+
+    // Write Lua filter
+
+    let dump_name = &*dump_name.0;
+    let page_by_title = lua_string_literal(&format!("/{dump_name}/page/by-title/"));
+    let category_by_name = lua_string_literal(&format!("/{dump_name}/category/by-name/"));
+    let interwiki_entries =
+        site.map(|site| site.interwiki_url_prefixes()).unwrap_or_default()
+            .iter()
+            .map(|(prefix, url)| format!("[{prefix}] = {url},",
+                                         prefix = lua_string_literal(prefix),
+                                         url = lua_string_literal(url)))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+    let lua_filter = format!(
+        r##"
+            local interwiki = {{
+                {interwiki_entries}
+            }}
+
+            -- Give headings the same ids as MediaWiki, which `wikitext::parse_sections`
+            -- also calculates.
+            local anchor_counts = {{}}
+
+            function Header(el)
+                local text = pandoc.utils.stringify(el.content)
+                local anchor = string.gsub(string.gsub(text, "^%s+", ""), "%s+$", "")
+                anchor = string.gsub(anchor, "%s+", "_")
+                local count = (anchor_counts[anchor] or 0) + 1
+                anchor_counts[anchor] = count
+                if count > 1 then
+                    anchor = anchor .. "_" .. count
+                end
+                el.identifier = anchor
+                return el
+            end
+
+            function Link(el)
+                local target = el.target
+                local prefix, rest = string.match(target, "^:?([%a]+):(.*)$")
+                if string.find(target, "^http") ~= nil then
+                    -- nothing to do for http(s) links.
+                elseif string.find(target, "^#") ~= nil then
+                    -- link to a section of this page
+                    target = string.gsub(target, " ", "_")
+                elseif string.find(target, "^Category:") ~= nil then
+                    -- internal link for category page
+                    local name = string.gsub(target, "Category:", "", 1)
+                    target = {category_by_name} .. name
+                elseif prefix ~= nil and interwiki[string.lower(prefix)] ~= nil then
+                    -- interwiki link to a sister project
+                    local slug = string.gsub(rest, " ", "_")
+                    target = interwiki[string.lower(prefix)] .. slug
+                else
+                    -- internal link for regular page
+                    target = {page_by_title} .. el.target
+                end
+                return pandoc.Link(el.content, target)
+            end
+        "##);
+    let lua_filter_path = temp_dir.path()?.join("filter.lua");
+    fs::write(&*lua_filter_path, lua_filter.as_bytes())?;
+
+    // Write template. The table of contents is rendered by `wmd web` from
+    // `parse_sections`, so it can link to each section on its own.
+    let template_path = temp_dir.path()?.join("template.html");
+    const TEMPLATE: &'static str =
+        r#"
+$body$
+        "#;
+    fs::write(&*template_path, TEMPLATE.as_bytes())?;
+
+    let wikitext = page.revision_text().unwrap_or("");
+
+    let mut limit_exceeded = None;
+    let input_len = truncate_at_line(wikitext, limits.max_input_bytes).len();
+    if input_len < wikitext.len() {
+        limit_exceeded = Some(RenderLimitExceeded::InputBytes {
+            len: wikitext.len(),
+            limit: limits.max_input_bytes,
+        });
+    }
+    let wikitext = &wikitext[..input_len];
+
+    let escaped = escape_templates(wikitext);
+
+    let html = run_pandoc(&[
+                              "--from", "mediawiki",
+                              "--to", "html",
+                              "--sandbox",
+                              "--standalone",
+                              "--template", &*template_path.to_string_lossy(),
+                              "--lua-filter", &*lua_filter_path.to_string_lossy(),
+                              "--mathml",
+                          ],
+                          &escaped, limits.timeout).await?;
+
+    let Some(mut html) = html else {
+        let shown = truncate_at_line(wikitext, TIMEOUT_WIKITEXT_BYTES);
+        return Ok(RenderedHtml {
+            html: format!("<pre>{}</pre>", ammonia::clean_text(shown)),
+            limit_exceeded: Some(RenderLimitExceeded::Timeout { limit: limits.timeout }),
+        });
+    };
+
+    tracing::trace!(pandoc_output_html = &*html, "Pandoc output HTML");
+
+    let html_len = html.len();
+    html.truncate(truncate_before_tag(&html, limits.max_output_bytes).len());
+    if html.len() < html_len {
+        limit_exceeded = Some(RenderLimitExceeded::OutputBytes {
+            len: html_len,
+            limit: limits.max_output_bytes,
+        });
+    }
+
+    // Sanitising also closes any tags left open by truncation.
+    let sanitised = sanitise_html(&html);
+
+    tracing::trace!(ammonia_output_html = sanitised, "ammonia output HTML");
+
+    Ok(RenderedHtml {
+        html: sanitised,
+        limit_exceeded,
+    })
+}
+
+/// The longest prefix of `s` of at most `max_bytes` that ends at a char boundary.
+fn truncate_at_char(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// The longest prefix of `s` of at most `max_bytes` that ends after a line break, or at a
+/// char boundary if the first line is longer than `max_bytes`.
+fn truncate_at_line(s: &str, max_bytes: usize) -> &str {
+    let prefix = truncate_at_char(s, max_bytes);
+    if prefix.len() == s.len() {
+        return s;
+    }
+    match prefix.rfind('\n') {
+        Some(idx) => &prefix[..=idx],
+        None => prefix,
+    }
+}
+
+/// The longest prefix of HTML `s` of at most `max_bytes` that doesn't end inside a tag.
+fn truncate_before_tag(s: &str, max_bytes: usize) -> &str {
+    let prefix = truncate_at_char(s, max_bytes);
+    if prefix.len() == s.len() {
+        return s;
+    }
+    match (prefix.rfind('<'), prefix.rfind('>')) {
+        (Some(open), Some(close)) if close > open => prefix,
+        (Some(open), _) => &prefix[..open],
+        (None, _) => prefix,
+    }
+}
+
+/// The CSS that styles code blocks highlighted by [`convert_page_to_html`] with `style`.
+///
+/// Runs pandoc, so callers should cache the result.
+pub async fn highlighting_css(style: HighlightStyle, out_dir: &Path) -> Result<String> {
+    let temp_dir = TempDir::create(out_dir, /* keep: */ false)?;
+    let template_path = temp_dir.path()?.join("template.css");
+    fs::write(&*template_path, b"$highlighting-css$\n")?;
+
+    // Pandoc only sets `highlighting-css` for documents with highlighted code.
+    run_pandoc(&[
+                   "--from", "mediawiki",
+                   "--to", "html",
+                   "--sandbox",
+                   "--standalone",
+                   "--template", &*template_path.to_string_lossy(),
+                   "--highlight-style", style.pandoc_name(),
+               ],
+               r#"<syntaxhighlight lang="c">int x;</syntaxhighlight>"#,
+               RenderLimits::default().timeout).await?
+        .ok_or_else(|| format_err!("Timed out running pandoc for highlighting CSS"))
+}
+
+/// Run pandoc with `args`, writing `input` to its stdin, and return its stdout.
+///
+/// Returns `Ok(None)` if pandoc runs for longer than `timeout`, after killing it.
+async fn run_pandoc(args: &[&str], input: &str, timeout: Duration) -> Result<Option<String>> {
+    let pandoc_start = Instant::now();
+
+    let mut child =
+        tokio::process::Command::new("pandoc")
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("While starting pandoc. Is it installed and on your path?")?;
+
+    let mut child_stdin =
+        child.stdin.take().ok_or(format_err!("Failed to open stdin"))?;
+
+    // TODO: Collect stderr manually to print on timeout.
+
+    // Dropping this future on timeout drops `child`, which kills pandoc.
+    let child_out = async move {
+        child_stdin.write_all(input.as_bytes()).await?;
+        drop(child_stdin); // Closes child's stdin so it will read EOF.
+        anyhow::Ok(child.wait_with_output().await?)
+    };
+    let child_out = match tokio::time::timeout(timeout, child_out).await {
+        Ok(child_out) => child_out?,
+        Err(_elapsed) => {
+            tracing::warn!(?timeout, input_len = input.len(), "Pandoc timed out");
+            return Ok(None);
+        },
+    };
+    let pandoc_duration = pandoc_start.elapsed();
+    if !child_out.status.success() {
+        bail!("Error exit code running pandoc code={code} stdout='{stdout}' \
+               stderr='{stderr}'",
+              code = child_out.status,
+              stdout = String::from_utf8_lossy(&child_out.stdout),
+              stderr = String::from_utf8_lossy(&child_out.stderr));
+    }
+
+    tracing::debug!(duration = ?pandoc_duration, "Pandoc completed");
+
+    Ok(Some(String::from_utf8_lossy(&child_out.stdout).into_owned()))
+}
+
+/// Remove scripts and other unsafe markup from HTML output by pandoc, keeping heading ids,
+/// MathML, and the classes of highlighted code.
+fn sanitise_html(html: &str) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .url_schemes(maplit::hashset![
+            "http", "https", "mailto"
+        ])
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .add_tag_attributes("a" , &["id"])
+        .add_tag_attributes("h1", &["id"])
+        .add_tag_attributes("h2", &["id"])
+        .add_tag_attributes("h3", &["id"])
+        .add_tag_attributes("h4", &["id"])
+        .add_tag_attributes("h5", &["id"])
+        .add_tag_attributes("h6", &["id"])
+        .add_tag_attributes("li", &["id"])
+        .add_tags(MATHML_TAGS)
+        .add_allowed_classes("div", &["sourceCode"])
+        .add_allowed_classes("pre", &["numberLines", "numberSource", "sourceCode"])
+        .add_allowed_classes("code", &["sourceCode"])
+        .add_allowed_classes("span", HIGHLIGHT_TOKEN_CLASSES);
+    for tag in MATHML_TAGS {
+        builder.add_tag_attributes(tag, MATHML_ATTRIBUTES);
+    }
+    builder.clean(html).to_string()
+}
+
+/// Format `s` as a double quoted Lua string literal.
+fn lua_string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl HighlightStyle {
+    /// The style's name as a pandoc `--highlight-style` argument.
+    fn pandoc_name(self) -> &'static str {
+        match self {
+            HighlightStyle::Breezedark => "breezedark",
+            HighlightStyle::Espresso => "espresso",
+            HighlightStyle::Haddock => "haddock",
+            HighlightStyle::Kate => "kate",
+            HighlightStyle::Monochrome => "monochrome",
+            HighlightStyle::Pygments => "pygments",
+            HighlightStyle::Tango => "tango",
+            HighlightStyle::Zenburn => "zenburn",
+        }
+    }
+}
+
+fn escape_templates(wikitext: &str) -> String {
+    fn replacer<'t>(caps: &regex::Captures<'t>) -> String {
+        let inner = caps.get(0).expect("regex capture 0").as_str();
+        let inner = html_escape::encode_text(inner);
+        format!("<pre>{inner}</pre>")
+    }
+
+    // Code blocks are matched too so they are left as they are, e.g. braces in C code.
+    // TODO: This doesn't handle nested template invocations.
+    lazy_regex!(r#"(?si)<(?:syntaxhighlight|source)\b[^>]*>.*?</(?:syntaxhighlight|source)\s*>"#,
+                r#"|\{\{[^}]+\}\}"#)
+        .replace_all(wikitext, |caps: &regex::Captures<'_>| {
+            let matched = caps.get(0).expect("regex capture 0").as_str();
+            if matched.starts_with('<') { matched.to_string() } else { replacer(caps) }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_templates, lua_string_literal, sanitise_html, truncate_at_line,
+                truncate_before_tag};
+
+    #[test]
+    fn truncate_input_and_output() {
+        assert_eq!(truncate_at_line("one\ntwo\nthree", 100), "one\ntwo\nthree");
+        assert_eq!(truncate_at_line("one\ntwo\nthree", 9), "one\ntwo\n");
+        assert_eq!(truncate_at_line("one\ntwo\nthree", 7), "one\n");
+        assert_eq!(truncate_at_line("é\n", 1), "");
+
+        let html = "<p>one</p><p>two</p>";
+        assert_eq!(truncate_before_tag(html, 100), html);
+        assert_eq!(truncate_before_tag(html, 12), "<p>one</p>");
+        assert_eq!(truncate_before_tag(html, 15), "<p>one</p><p>tw");
+        assert_eq!(truncate_before_tag(html, 17), "<p>one</p><p>two");
+        assert_eq!(sanitise_html(truncate_before_tag(html, 15)), "<p>one</p><p>tw</p>");
+    }
+
+    #[test]
+    fn lua_string_literal_escapes() {
+        assert_eq!(lua_string_literal("/enwiki/page/"), r#""/enwiki/page/""#);
+        assert_eq!(lua_string_literal("a\"b\\c\nd"), r#""a\"b\\c\nd""#);
+    }
+
+    #[test]
+    fn sanitise_html_keeps_mathml() {
+        let math = concat!(r#"<math display="block"><semantics><mrow><msup><mi>x</mi>"#,
+                           r#"<mn>2</mn></msup></mrow>"#,
+                           r#"<annotation encoding="application/x-tex">x^2</annotation>"#,
+                           r#"</semantics></math>"#);
+        assert_eq!(sanitise_html(math), math);
+
+        assert_eq!(sanitise_html(r#"<h2 id="History" onclick="x()">History</h2>"#),
+                   r#"<h2 id="History">History</h2>"#);
+        assert_eq!(sanitise_html(r#"<math><mi onclick="x()">x</mi><script>x()</script></math>"#),
+                   "<math><mi>x</mi></math>");
+
+        assert_eq!(sanitise_html(concat!(r#"<div class="sourceCode" id="cb1">"#,
+                                         r#"<pre class="sourceCode c"><code class="sourceCode c">"#,
+                                         r#"<span class="kw">int</span> "#,
+                                         r#"<span class="evil">x</span></code></pre></div>"#)),
+                   concat!(r#"<div class="sourceCode"><pre class="sourceCode">"#,
+                           r#"<code class="sourceCode"><span class="kw">int</span> "#,
+                           r#"<span class="">x</span></code></pre></div>"#));
+    }
+
+    #[test]
+    fn escape_templates_cases() {
+        let cases: &[(&str, &str)] = [
+            ("", ""),
+            ("asdf", "asdf"),
+            ("{{a}}", "<pre>{{a}}</pre>"),
+            ("{{<pre>a</pre>}}", "<pre>{{&lt;pre&gt;a&lt;/pre&gt;}}</pre>"),
+            ("<syntaxhighlight lang=\"cpp\">int a[] = {{1}};</syntaxhighlight> {{b}}",
+             "<syntaxhighlight lang=\"cpp\">int a[] = {{1}};</syntaxhighlight> <pre>{{b}}</pre>"),
+            ("<source>{{a}}</source>", "<source>{{a}}</source>"),
+        ].as_slice();
+
+        for (input, expected) in cases.iter() {
+            let out = escape_templates(input);
+            println!("\nCase:\n\
+                      |   in:       '{input}'\n\
+                      |   out:      '{out}'\n\
+                      |   expected: '{expected}'\n");
+            assert_eq!(out, *expected);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wikimedia::dump::local::{parse_pages_from_reader, ParseLimits};
+
+fuzz_target!(|data: &[u8]| {
+    // Errors are expected for malformed XML, but the iterator must not panic or loop forever.
+    for page in parse_pages_from_reader(data, ParseLimits::default()).take(10_000) {
+        let _ = page;
+    }
+});